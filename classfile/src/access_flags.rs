@@ -1,84 +1,510 @@
-#[derive(Debug, Clone)]
-pub struct AccessFlags(u16);
+//! Access flags, one type per JVMS table since the same bit means different
+//! things depending on where it appears, e.g. `0x0020` is `ACC_SUPER` on a class
+//! and `ACC_SYNCHRONIZED` on a method.
+//!
+//! Bits not defined for a table are reserved for future use and are dropped by
+//! `from_bits_truncate`, which is what the JVM does when it ignores them.
 
-impl AccessFlags {
-    pub fn is_public(&self) -> bool {
-        self.0 & AccessFlag::ACC_PUBLIC.bits != 0
-    }
+use crate::error::Error;
+use crate::version::{JAVA_10, JAVA_8};
+use std::fmt::{self, Display, Formatter};
+
+macro_rules! flag_accessors {
+    ($flags:ident { $($name:ident => $flag:ident,)* }) => {
+        impl $flags {
+            $(
+                pub fn $name(&self) -> bool {
+                    self.contains($flags::$flag)
+                }
+            )*
+        }
+    };
+}
 
-    pub fn is_private(&self) -> bool {
-        self.0 & AccessFlag::ACC_PRIVATE.bits != 0
+/// Writes the Java source modifiers in the order of `java.lang.reflect.Modifier`
+fn write_modifiers(f: &mut Formatter<'_>, modifiers: &[(bool, &str)]) -> fmt::Result {
+    let mut first = true;
+    for (_, modifier) in modifiers.iter().filter(|(set, _)| *set) {
+        if !first {
+            f.write_str(" ")?;
+        }
+        f.write_str(modifier)?;
+        first = false;
     }
+    Ok(())
+}
 
-    pub fn is_protected(&self) -> bool {
-        self.0 & AccessFlag::ACC_PROTECTED.bits != 0
+fn check(legal: bool, bits: u16) -> Result<(), Error> {
+    if legal {
+        Ok(())
+    } else {
+        Err(Error::InvalidAccessFlags(bits))
     }
+}
 
-    pub fn is_static(&self) -> bool {
-        self.0 & AccessFlag::ACC_STATIC.bits != 0
+bitflags! {
+    /// Table 4.1-B, `ClassFile.access_flags`
+    pub struct ClassAccessFlags: u16 {
+        const ACC_PUBLIC = 0x0001;
+        const ACC_FINAL = 0x0010;
+        const ACC_SUPER = 0x0020;
+        const ACC_INTERFACE = 0x0200;
+        const ACC_ABSTRACT = 0x0400;
+        const ACC_SYNTHETIC = 0x1000;
+        const ACC_ANNOTATION = 0x2000;
+        const ACC_ENUM = 0x4000;
+        const ACC_MODULE = 0x8000;
     }
+}
 
-    pub fn is_final(&self) -> bool {
-        self.0 & AccessFlag::ACC_FINAL.bits != 0
+flag_accessors!(ClassAccessFlags {
+    is_public => ACC_PUBLIC,
+    is_final => ACC_FINAL,
+    is_super => ACC_SUPER,
+    is_interface => ACC_INTERFACE,
+    is_abstract => ACC_ABSTRACT,
+    is_synthetic => ACC_SYNTHETIC,
+    is_annotation => ACC_ANNOTATION,
+    is_enum => ACC_ENUM,
+    is_module => ACC_MODULE,
+});
+
+impl ClassAccessFlags {
+    /// JVMS 4.1, an interface is abstract and neither final, super, enum nor
+    /// module; a class is not both final and abstract; a module has no other flag
+    pub fn check(&self) -> Result<(), Error> {
+        let legal = if self.is_module() {
+            *self == ClassAccessFlags::ACC_MODULE
+        } else if self.is_interface() {
+            self.is_abstract()
+                && !self.intersects(
+                    ClassAccessFlags::ACC_FINAL
+                        | ClassAccessFlags::ACC_SUPER
+                        | ClassAccessFlags::ACC_ENUM,
+                )
+        } else {
+            !(self.is_annotation() || self.is_final() && self.is_abstract())
+        };
+        check(legal, self.bits())
     }
 
-    pub fn is_synchronized(&self) -> bool {
-        self.0 & AccessFlag::ACC_SYNCHRONIZED.bits != 0
+    /// Keyword introducing the declaration
+    pub fn kind(&self) -> &'static str {
+        if self.is_module() {
+            "module"
+        } else if self.is_annotation() {
+            "@interface"
+        } else if self.is_interface() {
+            "interface"
+        } else if self.is_enum() {
+            "enum"
+        } else {
+            "class"
+        }
     }
+}
 
-    pub fn is_super(&self) -> bool {
-        self.0 & AccessFlag::ACC_SUPER.bits != 0
+impl Display for ClassAccessFlags {
+    /// Source modifiers, `abstract` is implied for interfaces and `final` for enums
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_modifiers(
+            f,
+            &[
+                (self.is_public(), "public"),
+                (self.is_abstract() && !self.is_interface(), "abstract"),
+                (self.is_final() && !self.is_enum(), "final"),
+            ],
+        )
     }
+}
 
-    pub fn is_volatile(&self) -> bool {
-        self.0 & AccessFlag::ACC_VOLATILE.bits != 0
+bitflags! {
+    /// Table 4.5-A, `field_info.access_flags`
+    pub struct FieldAccessFlags: u16 {
+        const ACC_PUBLIC = 0x0001;
+        const ACC_PRIVATE = 0x0002;
+        const ACC_PROTECTED = 0x0004;
+        const ACC_STATIC = 0x0008;
+        const ACC_FINAL = 0x0010;
+        const ACC_VOLATILE = 0x0040;
+        const ACC_TRANSIENT = 0x0080;
+        const ACC_SYNTHETIC = 0x1000;
+        const ACC_ENUM = 0x4000;
     }
+}
 
-    pub fn is_transient(&self) -> bool {
-        self.0 & AccessFlag::ACC_TRANSIENT.bits != 0
+flag_accessors!(FieldAccessFlags {
+    is_public => ACC_PUBLIC,
+    is_private => ACC_PRIVATE,
+    is_protected => ACC_PROTECTED,
+    is_static => ACC_STATIC,
+    is_final => ACC_FINAL,
+    is_volatile => ACC_VOLATILE,
+    is_transient => ACC_TRANSIENT,
+    is_synthetic => ACC_SYNTHETIC,
+    is_enum => ACC_ENUM,
+});
+
+impl FieldAccessFlags {
+    /// JVMS 4.5, interface fields are public static final
+    pub fn check(&self, in_interface: bool) -> Result<(), Error> {
+        let legal = if in_interface {
+            let required = FieldAccessFlags::ACC_PUBLIC
+                | FieldAccessFlags::ACC_STATIC
+                | FieldAccessFlags::ACC_FINAL;
+            self.contains(required)
+                && (*self - required - FieldAccessFlags::ACC_SYNTHETIC).is_empty()
+        } else {
+            at_most_one(&[self.is_public(), self.is_private(), self.is_protected()])
+                && !(self.is_final() && self.is_volatile())
+        };
+        check(legal, self.bits())
     }
+}
 
-    pub fn is_native(&self) -> bool {
-        self.0 & AccessFlag::ACC_NATIVE.bits != 0
+impl Display for FieldAccessFlags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_modifiers(
+            f,
+            &[
+                (self.is_public(), "public"),
+                (self.is_protected(), "protected"),
+                (self.is_private(), "private"),
+                (self.is_static(), "static"),
+                (self.is_final(), "final"),
+                (self.is_transient(), "transient"),
+                (self.is_volatile(), "volatile"),
+            ],
+        )
     }
+}
 
-    pub fn is_interface(&self) -> bool {
-        self.0 & AccessFlag::ACC_INTERFACE.bits != 0
+bitflags! {
+    /// Table 4.6-A, `method_info.access_flags`
+    pub struct MethodAccessFlags: u16 {
+        const ACC_PUBLIC = 0x0001;
+        const ACC_PRIVATE = 0x0002;
+        const ACC_PROTECTED = 0x0004;
+        const ACC_STATIC = 0x0008;
+        const ACC_FINAL = 0x0010;
+        const ACC_SYNCHRONIZED = 0x0020;
+        const ACC_BRIDGE = 0x0040;
+        const ACC_VARARGS = 0x0080;
+        const ACC_NATIVE = 0x0100;
+        const ACC_ABSTRACT = 0x0400;
+        const ACC_STRICT = 0x0800;
+        const ACC_SYNTHETIC = 0x1000;
     }
+}
 
-    pub fn is_abstract(&self) -> bool {
-        self.0 & AccessFlag::ACC_ABSTRACT.bits != 0
+flag_accessors!(MethodAccessFlags {
+    is_public => ACC_PUBLIC,
+    is_private => ACC_PRIVATE,
+    is_protected => ACC_PROTECTED,
+    is_static => ACC_STATIC,
+    is_final => ACC_FINAL,
+    is_synchronized => ACC_SYNCHRONIZED,
+    is_bridge => ACC_BRIDGE,
+    is_varargs => ACC_VARARGS,
+    is_native => ACC_NATIVE,
+    is_abstract => ACC_ABSTRACT,
+    is_strict => ACC_STRICT,
+    is_synthetic => ACC_SYNTHETIC,
+});
+
+impl MethodAccessFlags {
+    /// JVMS 4.6, `name` is needed because `<init>` and `<clinit>` have rules
+    /// of their own, and the major version because interfaces may declare
+    /// non-abstract methods from Java 8 on
+    pub fn check(&self, in_interface: bool, major_version: u16, name: &[u8]) -> Result<(), Error> {
+        if name == b"<clinit>" {
+            // only ACC_STATIC matters, and only from Java 7 on
+            return Ok(());
+        }
+        let visibility = at_most_one(&[self.is_public(), self.is_private(), self.is_protected()]);
+        let legal = if name == b"<init>" {
+            let allowed = MethodAccessFlags::ACC_PUBLIC
+                | MethodAccessFlags::ACC_PRIVATE
+                | MethodAccessFlags::ACC_PROTECTED
+                | MethodAccessFlags::ACC_VARARGS
+                | MethodAccessFlags::ACC_STRICT
+                | MethodAccessFlags::ACC_SYNTHETIC;
+            visibility && !in_interface && (*self - allowed).is_empty()
+        } else if in_interface {
+            let forbidden = MethodAccessFlags::ACC_PROTECTED
+                | MethodAccessFlags::ACC_FINAL
+                | MethodAccessFlags::ACC_SYNCHRONIZED
+                | MethodAccessFlags::ACC_NATIVE;
+            let required = if major_version < JAVA_8 {
+                self.is_public() && self.is_abstract()
+            } else {
+                self.is_public() != self.is_private()
+            };
+            required && !self.intersects(forbidden) && self.abstract_legal()
+        } else {
+            visibility && self.abstract_legal()
+        };
+        check(legal, self.bits())
     }
 
-    pub fn is_strict(&self) -> bool {
-        self.0 & AccessFlag::ACC_STRICT.bits != 0
+    fn abstract_legal(&self) -> bool {
+        !self.is_abstract()
+            || !self.intersects(
+                MethodAccessFlags::ACC_PRIVATE
+                    | MethodAccessFlags::ACC_STATIC
+                    | MethodAccessFlags::ACC_FINAL
+                    | MethodAccessFlags::ACC_SYNCHRONIZED
+                    | MethodAccessFlags::ACC_NATIVE
+                    | MethodAccessFlags::ACC_STRICT,
+            )
     }
+}
 
-    // Attribute flags
-    pub fn is_synthetic(&self) -> bool {
-        self.0 & AccessFlag::ACC_SYNTHETIC.bits != 0
+impl Display for MethodAccessFlags {
+    /// `ACC_BRIDGE`, `ACC_VARARGS` and `ACC_SYNTHETIC` have no modifier
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_modifiers(
+            f,
+            &[
+                (self.is_public(), "public"),
+                (self.is_protected(), "protected"),
+                (self.is_private(), "private"),
+                (self.is_abstract(), "abstract"),
+                (self.is_static(), "static"),
+                (self.is_final(), "final"),
+                (self.is_synchronized(), "synchronized"),
+                (self.is_native(), "native"),
+                (self.is_strict(), "strictfp"),
+            ],
+        )
     }
 }
 
 bitflags! {
-    struct AccessFlag: u16 {
+    /// Table 4.7.6-A, `inner_class_access_flags` of the `InnerClasses` attribute
+    pub struct NestedClassAccessFlags: u16 {
         const ACC_PUBLIC = 0x0001;
         const ACC_PRIVATE = 0x0002;
         const ACC_PROTECTED = 0x0004;
         const ACC_STATIC = 0x0008;
         const ACC_FINAL = 0x0010;
-        const ACC_SUPER = 0x0020;
-        const ACC_SYNCHRONIZED = 0x0020;
-        const ACC_BRIDGE = 0x0040;
-        const ACC_VOLATILE = 0x0040;
-        const ACC_VARARGS = 0x0080;
-        const ACC_TRANSIENT = 0x0080;
-        const ACC_NATIVE = 0x0100;
         const ACC_INTERFACE = 0x0200;
         const ACC_ABSTRACT = 0x0400;
-        const ACC_STRICT = 0x0800;
         const ACC_SYNTHETIC = 0x1000;
         const ACC_ANNOTATION = 0x2000;
         const ACC_ENUM = 0x4000;
     }
 }
+
+flag_accessors!(NestedClassAccessFlags {
+    is_public => ACC_PUBLIC,
+    is_private => ACC_PRIVATE,
+    is_protected => ACC_PROTECTED,
+    is_static => ACC_STATIC,
+    is_final => ACC_FINAL,
+    is_interface => ACC_INTERFACE,
+    is_abstract => ACC_ABSTRACT,
+    is_synthetic => ACC_SYNTHETIC,
+    is_annotation => ACC_ANNOTATION,
+    is_enum => ACC_ENUM,
+});
+
+impl NestedClassAccessFlags {
+    pub fn check(&self) -> Result<(), Error> {
+        let legal = at_most_one(&[self.is_public(), self.is_private(), self.is_protected()])
+            && if self.is_interface() {
+                self.is_abstract() && !self.is_final()
+            } else {
+                !(self.is_annotation() || self.is_final() && self.is_abstract())
+            };
+        check(legal, self.bits())
+    }
+}
+
+impl Display for NestedClassAccessFlags {
+    /// Member interfaces, enums and annotations are implicitly static
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let implicitly_static = self.is_interface() || self.is_enum();
+        write_modifiers(
+            f,
+            &[
+                (self.is_public(), "public"),
+                (self.is_protected(), "protected"),
+                (self.is_private(), "private"),
+                (self.is_abstract() && !self.is_interface(), "abstract"),
+                (self.is_static() && !implicitly_static, "static"),
+                (self.is_final() && !self.is_enum(), "final"),
+            ],
+        )
+    }
+}
+
+bitflags! {
+    /// `module_flags` of the `Module` attribute
+    pub struct ModuleFlags: u16 {
+        const ACC_OPEN = 0x0020;
+        const ACC_SYNTHETIC = 0x1000;
+        const ACC_MANDATED = 0x8000;
+    }
+}
+
+flag_accessors!(ModuleFlags {
+    is_open => ACC_OPEN,
+    is_synthetic => ACC_SYNTHETIC,
+    is_mandated => ACC_MANDATED,
+});
+
+impl Display for ModuleFlags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_modifiers(f, &[(self.is_open(), "open")])
+    }
+}
+
+bitflags! {
+    /// `requires_flags` of the `Module` attribute
+    pub struct ModuleRequiresFlags: u16 {
+        const ACC_TRANSITIVE = 0x0020;
+        const ACC_STATIC_PHASE = 0x0040;
+        const ACC_SYNTHETIC = 0x1000;
+        const ACC_MANDATED = 0x8000;
+    }
+}
+
+flag_accessors!(ModuleRequiresFlags {
+    is_transitive => ACC_TRANSITIVE,
+    is_static_phase => ACC_STATIC_PHASE,
+    is_synthetic => ACC_SYNTHETIC,
+    is_mandated => ACC_MANDATED,
+});
+
+impl ModuleRequiresFlags {
+    /// JVMS 4.7.25, from Java 10 on `requires java.base` may be neither
+    /// transitive nor static, unless the module is `java.base` itself
+    pub fn check(&self, major_version: u16, requires_java_base: bool) -> Result<(), Error> {
+        let legal = major_version < JAVA_10
+            || !requires_java_base
+            || !self.intersects(
+                ModuleRequiresFlags::ACC_TRANSITIVE | ModuleRequiresFlags::ACC_STATIC_PHASE,
+            );
+        check(legal, self.bits())
+    }
+}
+
+impl Display for ModuleRequiresFlags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_modifiers(
+            f,
+            &[
+                (self.is_static_phase(), "static"),
+                (self.is_transitive(), "transitive"),
+            ],
+        )
+    }
+}
+
+bitflags! {
+    /// `exports_flags` of the `Module` attribute
+    pub struct ModuleExportsFlags: u16 {
+        const ACC_SYNTHETIC = 0x1000;
+        const ACC_MANDATED = 0x8000;
+    }
+}
+
+flag_accessors!(ModuleExportsFlags {
+    is_synthetic => ACC_SYNTHETIC,
+    is_mandated => ACC_MANDATED,
+});
+
+bitflags! {
+    /// `opens_flags` of the `Module` attribute
+    pub struct ModuleOpensFlags: u16 {
+        const ACC_SYNTHETIC = 0x1000;
+        const ACC_MANDATED = 0x8000;
+    }
+}
+
+flag_accessors!(ModuleOpensFlags {
+    is_synthetic => ACC_SYNTHETIC,
+    is_mandated => ACC_MANDATED,
+});
+
+bitflags! {
+    /// `access_flags` of the `MethodParameters` attribute
+    pub struct MethodParameterFlags: u16 {
+        const ACC_FINAL = 0x0010;
+        const ACC_SYNTHETIC = 0x1000;
+        const ACC_MANDATED = 0x8000;
+    }
+}
+
+flag_accessors!(MethodParameterFlags {
+    is_final => ACC_FINAL,
+    is_synthetic => ACC_SYNTHETIC,
+    is_mandated => ACC_MANDATED,
+});
+
+impl Display for MethodParameterFlags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_modifiers(f, &[(self.is_final(), "final")])
+    }
+}
+
+fn at_most_one(flags: &[bool]) -> bool {
+    flags.iter().filter(|set| **set).count() <= 1
+}
+
+#[cfg(test)]
+mod test {
+    use crate::access_flags::{
+        ClassAccessFlags, FieldAccessFlags, MethodAccessFlags, NestedClassAccessFlags,
+    };
+    use crate::version::{JAVA_7, JAVA_8};
+
+    #[test]
+    fn flags_depend_on_context() {
+        // 0x0020 is ACC_SUPER on a class but ACC_SYNCHRONIZED on a method
+        let class = ClassAccessFlags::from_bits_truncate(0x0021);
+        assert!(class.is_super());
+        assert_eq!(class.to_string(), "public");
+        assert_eq!(class.kind(), "class");
+        let method = MethodAccessFlags::from_bits_truncate(0x0029);
+        assert!(method.is_synchronized());
+        assert_eq!(method.to_string(), "public static synchronized");
+        // 0x0040 is ACC_VOLATILE on a field but ACC_BRIDGE on a method
+        assert!(FieldAccessFlags::from_bits_truncate(0x0040).is_volatile());
+        assert!(MethodAccessFlags::from_bits_truncate(0x0040).is_bridge());
+
+        assert!(ClassAccessFlags::from_bits_truncate(0x0411)
+            .check()
+            .is_err());
+        assert!(ClassAccessFlags::from_bits_truncate(0x0601).check().is_ok());
+        assert!(ClassAccessFlags::from_bits_truncate(0x0201)
+            .check()
+            .is_err());
+        assert!(FieldAccessFlags::from_bits_truncate(0x0003)
+            .check(false)
+            .is_err());
+        assert!(FieldAccessFlags::from_bits_truncate(0x0050)
+            .check(false)
+            .is_err());
+        assert!(FieldAccessFlags::from_bits_truncate(0x0019)
+            .check(true)
+            .is_ok());
+        assert!(FieldAccessFlags::from_bits_truncate(0x0009)
+            .check(true)
+            .is_err());
+
+        let private_method = MethodAccessFlags::ACC_PRIVATE;
+        assert!(private_method.check(true, JAVA_8, b"helper").is_ok());
+        assert!(private_method.check(true, JAVA_7, b"helper").is_err());
+        let abstract_final = MethodAccessFlags::ACC_ABSTRACT | MethodAccessFlags::ACC_FINAL;
+        assert!(abstract_final.check(false, JAVA_8, b"run").is_err());
+        assert!(MethodAccessFlags::ACC_STATIC
+            .check(false, JAVA_8, b"<init>")
+            .is_err());
+
+        let nested = NestedClassAccessFlags::from_bits_truncate(0x0609);
+        assert_eq!(nested.to_string(), "public");
+        assert!(nested.check().is_ok());
+    }
+}
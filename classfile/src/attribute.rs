@@ -1,5 +1,9 @@
 #![allow(dead_code)]
 
+use crate::access_flags::{
+    MethodParameterFlags, ModuleExportsFlags, ModuleFlags, ModuleOpensFlags, ModuleRequiresFlags,
+    NestedClassAccessFlags,
+};
 use crate::constant::get_utf8;
 use crate::error::Error;
 use crate::{BytesRef, ConstantPoolRef, TryFromCp, TryInto};
@@ -14,6 +18,22 @@ pub struct Attribute {
     pub attr_type: AttributeType,
 }
 
+impl Attribute {
+    /// Wraps `attr_type`, computing `attribute_length` from its encoding
+    pub fn new(attribute_name_index: u16, attr_type: AttributeType) -> Result<Attribute, Error> {
+        let mut attribute = Attribute {
+            attribute_name_index,
+            attribute_length: 0,
+            attr_type,
+        };
+        let mut buf: Vec<u8> = vec![];
+        let len = attribute.try_into(&mut buf)?;
+        // attribute_name_index and attribute_length aren't counted
+        attribute.attribute_length = (len - 6) as u32;
+        Ok(attribute)
+    }
+}
+
 impl TryFromCp<&mut BytesMut> for Attribute {
     type Error = Error;
 
@@ -52,7 +72,7 @@ impl TryFromCp<&mut BytesMut> for Attribute {
                     exception_index_table,
                 })
             }
-            b"InnerClass" => {
+            b"InnerClasses" => {
                 let number_of_classes = buf.get_u16();
                 let mut classes: Vec<InnerClass> = vec![];
                 for _ in 0..number_of_classes {
@@ -150,7 +170,7 @@ impl TryFromCp<&mut BytesMut> for Attribute {
                 })
             }
             b"RuntimeVisibleTypeAnnotations" => {
-                let num_annotations = buf.get_u8();
+                let num_annotations = buf.get_u16();
                 let mut type_annotations: Vec<TypeAnnotation> = vec![];
                 for _ in 0..num_annotations {
                     type_annotations.push(TypeAnnotation::try_from(&mut *buf)?);
@@ -160,7 +180,7 @@ impl TryFromCp<&mut BytesMut> for Attribute {
                 })
             }
             b"RuntimeInvisibleTypeAnnotations" => {
-                let num_annotations = buf.get_u8();
+                let num_annotations = buf.get_u16();
                 let mut type_annotations: Vec<TypeAnnotation> = vec![];
                 for _ in 0..num_annotations {
                     type_annotations.push(TypeAnnotation::try_from(&mut *buf)?);
@@ -188,6 +208,34 @@ impl TryFromCp<&mut BytesMut> for Attribute {
                 }
                 Ok(AttributeType::MethodParameters { parameters })
             }
+            b"Module" => Ok(AttributeType::Module {
+                module: ModuleAttribute::try_from(&mut *buf)?,
+            }),
+            b"ModulePackages" => Ok(AttributeType::ModulePackages {
+                package_index: read_u16_table(buf),
+            }),
+            b"ModuleMainClass" => {
+                let main_class_index = buf.get_u16();
+                Ok(AttributeType::ModuleMainClass { main_class_index })
+            }
+            b"NestHost" => {
+                let host_class_index = buf.get_u16();
+                Ok(AttributeType::NestHost { host_class_index })
+            }
+            b"NestMembers" => Ok(AttributeType::NestMembers {
+                classes: read_u16_table(buf),
+            }),
+            b"Record" => {
+                let components_count = buf.get_u16();
+                let mut components: Vec<RecordComponent> = vec![];
+                for _ in 0..components_count {
+                    components.push(RecordComponent::try_from_cp(buf, constant_pool)?);
+                }
+                Ok(AttributeType::Record { components })
+            }
+            b"PermittedSubclasses" => Ok(AttributeType::PermittedSubclasses {
+                classes: read_u16_table(buf),
+            }),
             _ => Err(Error::InvalidAttributeName(
                 String::from_utf8(attribute_name.clone()).unwrap(),
             )),
@@ -222,6 +270,8 @@ where
                 len += code.try_into(buf)?;
             }
             AttributeType::StackMapTable { entries } => {
+                buf.put_u16(entries.len() as u16);
+                len += 2;
                 for stack_map in entries {
                     len += stack_map.try_into(buf)?;
                 }
@@ -229,12 +279,11 @@ where
             AttributeType::Exceptions {
                 exception_index_table,
             } => {
-                for exception_index in exception_index_table {
-                    buf.put_u16(*exception_index);
-                    len += 2;
-                }
+                len += write_u16_table(exception_index_table, buf);
             }
             AttributeType::InnerClasses { classes } => {
+                buf.put_u16(classes.len() as u16);
+                len += 2;
                 for class in classes {
                     len += class.try_into(buf)?;
                 }
@@ -263,6 +312,8 @@ where
                 }
             }
             AttributeType::LineNumberTable { line_number_table } => {
+                buf.put_u16(line_number_table.len() as u16);
+                len += 2;
                 for line_number in line_number_table {
                     len += line_number.try_into(buf)?;
                 }
@@ -270,6 +321,8 @@ where
             AttributeType::LocalVariableTable {
                 local_variable_table,
             } => {
+                buf.put_u16(local_variable_table.len() as u16);
+                len += 2;
                 for local_variable in local_variable_table {
                     len += local_variable.try_into(buf)?;
                 }
@@ -277,17 +330,23 @@ where
             AttributeType::LocalVariableTypeTable {
                 local_variable_type_table,
             } => {
+                buf.put_u16(local_variable_type_table.len() as u16);
+                len += 2;
                 for local_variable_type in local_variable_type_table {
                     len += local_variable_type.try_into(buf)?;
                 }
             }
             AttributeType::Deprecated => {}
             AttributeType::RuntimeVisibleAnnotations { annotations } => {
+                buf.put_u16(annotations.len() as u16);
+                len += 2;
                 for annotation in annotations {
                     len += annotation.try_into(buf)?;
                 }
             }
             AttributeType::RuntimeInvisibleAnnotations { annotations } => {
+                buf.put_u16(annotations.len() as u16);
+                len += 2;
                 for annotation in annotations {
                     len += annotation.try_into(buf)?;
                 }
@@ -295,6 +354,8 @@ where
             AttributeType::RuntimeVisibleParameterAnnotations {
                 parameter_annotations,
             } => {
+                buf.put_u8(parameter_annotations.len() as u8);
+                len += 1;
                 for parameter_annotation in parameter_annotations {
                     len += parameter_annotation.try_into(buf)?;
                 }
@@ -302,16 +363,22 @@ where
             AttributeType::RuntimeInvisibleParameterAnnotations {
                 parameter_annotations,
             } => {
+                buf.put_u8(parameter_annotations.len() as u8);
+                len += 1;
                 for parameter_annotation in parameter_annotations {
                     len += parameter_annotation.try_into(buf)?;
                 }
             }
             AttributeType::RuntimeVisibleTypeAnnotations { annotations } => {
+                buf.put_u16(annotations.len() as u16);
+                len += 2;
                 for annotation in annotations {
                     len += annotation.try_into(buf)?;
                 }
             }
             AttributeType::RuntimeInvisibleTypeAnnotations { annotations } => {
+                buf.put_u16(annotations.len() as u16);
+                len += 2;
                 for annotation in annotations {
                     len += annotation.try_into(buf)?;
                 }
@@ -320,15 +387,46 @@ where
                 len += default_value.try_into(buf)?;
             }
             AttributeType::BootstrapMethods { bootstrap_methods } => {
+                buf.put_u16(bootstrap_methods.len() as u16);
+                len += 2;
                 for bootstrap_method in bootstrap_methods {
                     len += bootstrap_method.try_into(buf)?;
                 }
             }
             AttributeType::MethodParameters { parameters } => {
+                buf.put_u8(parameters.len() as u8);
+                len += 1;
                 for parameter in parameters {
                     len += parameter.try_into(buf)?;
                 }
             }
+            AttributeType::Module { module } => {
+                len += module.try_into(buf)?;
+            }
+            AttributeType::ModulePackages { package_index } => {
+                len += write_u16_table(package_index, buf);
+            }
+            AttributeType::ModuleMainClass { main_class_index } => {
+                buf.put_u16(*main_class_index);
+                len += 2;
+            }
+            AttributeType::NestHost { host_class_index } => {
+                buf.put_u16(*host_class_index);
+                len += 2;
+            }
+            AttributeType::NestMembers { classes } => {
+                len += write_u16_table(classes, buf);
+            }
+            AttributeType::Record { components } => {
+                buf.put_u16(components.len() as u16);
+                len += 2;
+                for component in components {
+                    len += component.try_into(buf)?;
+                }
+            }
+            AttributeType::PermittedSubclasses { classes } => {
+                len += write_u16_table(classes, buf);
+            }
         }
         Ok(len)
     }
@@ -402,6 +500,27 @@ pub enum AttributeType {
     MethodParameters {
         parameters: Vec<MethodParameter>,
     },
+    Module {
+        module: ModuleAttribute,
+    },
+    ModulePackages {
+        package_index: Vec<u16>,
+    },
+    ModuleMainClass {
+        main_class_index: u16,
+    },
+    NestHost {
+        host_class_index: u16,
+    },
+    NestMembers {
+        classes: Vec<u16>,
+    },
+    Record {
+        components: Vec<RecordComponent>,
+    },
+    PermittedSubclasses {
+        classes: Vec<u16>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -413,6 +532,25 @@ pub struct CodeAttribute {
     pub attributes: Vec<Attribute>,
 }
 
+impl CodeAttribute {
+    /// Source line of the instruction at `pc`, taken from the `LineNumberTable`s
+    pub fn line_number(&self, pc: u16) -> Option<u16> {
+        let mut line: Option<(u16, u16)> = None;
+        for attribute in &self.attributes {
+            if let AttributeType::LineNumberTable { line_number_table } = &attribute.attr_type {
+                for entry in line_number_table {
+                    if entry.start_pc <= pc
+                        && line.is_none_or(|(start_pc, _)| entry.start_pc >= start_pc)
+                    {
+                        line = Some((entry.start_pc, entry.line_number));
+                    }
+                }
+            }
+        }
+        line.map(|(_, line_number)| line_number)
+    }
+}
+
 impl TryFromCp<&mut BytesMut> for CodeAttribute {
     type Error = Error;
 
@@ -464,6 +602,8 @@ where
             buf.put_u8(*byte);
             len += 1;
         }
+        buf.put_u16(self.exception_table.len() as u16);
+        len += 2;
         for exception in &self.exception_table {
             len += exception.try_into(buf)?;
         }
@@ -552,6 +692,34 @@ pub struct StackMap {
     frame: StackMapFrame,
 }
 
+impl StackMap {
+    pub fn new(frame_type: u8, frame: StackMapFrame) -> StackMap {
+        StackMap { frame_type, frame }
+    }
+
+    pub fn frame_type(&self) -> u8 {
+        self.frame_type
+    }
+
+    pub fn frame(&self) -> &StackMapFrame {
+        &self.frame
+    }
+
+    /// Distance to the previous frame, the offset of the first frame is
+    /// `offset_delta` and of each following one `offset_delta + 1` more
+    pub fn offset_delta(&self) -> u16 {
+        match &self.frame {
+            StackMapFrame::SameFrame => self.frame_type as u16,
+            StackMapFrame::SameLocals1StackItemFrame { .. } => self.frame_type as u16 - 64,
+            StackMapFrame::SameLocals1StackItemFrameExtended { offset_delta, .. }
+            | StackMapFrame::ChopFrame { offset_delta }
+            | StackMapFrame::SameFrameExtended { offset_delta }
+            | StackMapFrame::AppendFrame { offset_delta, .. }
+            | StackMapFrame::FullFrame { offset_delta, .. } => *offset_delta,
+        }
+    }
+}
+
 impl TryFrom<&mut BytesMut> for StackMap {
     type Error = Error;
 
@@ -710,9 +878,13 @@ where
                 } = &self.frame
                 {
                     buf.put_u16(*offset_delta);
+                    buf.put_u16(locals.len() as u16);
+                    len += 4;
                     for verification_type_info in locals {
                         len += verification_type_info.try_into(buf)?;
                     }
+                    buf.put_u16(stack.len() as u16);
+                    len += 2;
                     for verification_type_info in stack {
                         len += verification_type_info.try_into(buf)?;
                     }
@@ -823,6 +995,12 @@ pub struct InnerClass {
     pub inner_class_access_flags: u16,
 }
 
+impl InnerClass {
+    pub fn flags(&self) -> NestedClassAccessFlags {
+        NestedClassAccessFlags::from_bits_truncate(self.inner_class_access_flags)
+    }
+}
+
 impl TryFrom<&mut BytesMut> for InnerClass {
     type Error = Error;
 
@@ -1029,7 +1207,8 @@ where
     fn try_into(&self, buf: &mut T) -> Result<usize, Self::Error> {
         let mut len: usize = 0;
         buf.put_u16(self.type_index);
-        len += 2;
+        buf.put_u16(self.element_value_pairs.len() as u16);
+        len += 4;
         for element_value_pair in &self.element_value_pairs {
             buf.put_u16(element_value_pair.0);
             len += 2;
@@ -1135,7 +1314,8 @@ where
     type Error = Error;
 
     fn try_into(&self, buf: &mut T) -> Result<usize, Self::Error> {
-        let mut len: usize = 0;
+        buf.put_u8(self.tag);
+        let mut len: usize = 1;
         let tag = self.tag as char;
         match tag {
             'B' | 'C' | 'D' | 'F' | 'I' | 'J' | 'S' | 'Z' | 's' => {
@@ -1217,6 +1397,7 @@ where
     fn try_into(&self, buf: &mut T) -> Result<usize, Self::Error> {
         let mut len: usize = 0;
         buf.put_u16(self.annotations.len() as u16);
+        len += 2;
         for annotation in &self.annotations {
             len += annotation.try_into(buf)?;
         }
@@ -1254,8 +1435,34 @@ where
 /// | Value | Kind of target | target_info Item |
 /// |---|---|---|
 /// |0x00|type parameter declaration of generic class or interface|type_parameter_target|
-/// |0x01|
-/// TODO
+/// |0x01|type parameter declaration of generic method or constructor|type_parameter_target|
+/// |0x10|type in extends or implements clause of class declaration, or in extends clause of interface declaration|supertype_target|
+/// |0x11|type in bound of type parameter declaration of generic class or interface|type_parameter_bound_target|
+/// |0x12|type in bound of type parameter declaration of generic method or constructor|type_parameter_bound_target|
+/// |0x13|type in field or record component declaration|empty_target|
+/// |0x14|return type of method, or type of newly constructed object|empty_target|
+/// |0x15|receiver type of method or constructor|empty_target|
+/// |0x16|type in formal parameter declaration of method, constructor, or lambda expression|formal_parameter_target|
+/// |0x17|type in throws clause of method or constructor|throws_target|
+///
+/// ### Interpretation of target_type values (Part 2)
+///
+/// | Value | Kind of target | target_info Item |
+/// |---|---|---|
+/// |0x40|type in local variable declaration|localvar_target|
+/// |0x41|type in resource variable declaration|localvar_target|
+/// |0x42|type in exception parameter declaration|catch_target|
+/// |0x43|type in instanceof expression|offset_target|
+/// |0x44|type in new expression|offset_target|
+/// |0x45|type in method reference expression using ::new|offset_target|
+/// |0x46|type in method reference expression using ::Identifier|offset_target|
+/// |0x47|type in cast expression|type_argument_target|
+/// |0x48|type argument for generic constructor in new expression or explicit constructor invocation statement|type_argument_target|
+/// |0x49|type argument for generic method in method invocation expression|type_argument_target|
+/// |0x4A|type argument for generic constructor in method reference expression using ::new|type_argument_target|
+/// |0x4B|type argument for generic method in method reference expression using ::Identifier|type_argument_target|
+///
+/// See `crate::type_annotation` for resolving targets and paths against the class file.
 #[derive(Debug, Clone)]
 pub struct TypeAnnotation {
     pub target_type: u8,
@@ -1489,7 +1696,7 @@ where
         len += 4;
         for element_value_pair in &self.element_value_pairs {
             buf.put_u16(element_value_pair.0);
-            len += 1;
+            len += 2;
             len += element_value_pair.1.try_into(buf)?;
         }
         Ok(len)
@@ -1635,6 +1842,12 @@ pub struct MethodParameter {
     pub access_flags: u16,
 }
 
+impl MethodParameter {
+    pub fn flags(&self) -> MethodParameterFlags {
+        MethodParameterFlags::from_bits_truncate(self.access_flags)
+    }
+}
+
 impl TryFrom<&mut BytesMut> for MethodParameter {
     type Error = Error;
 
@@ -1662,3 +1875,287 @@ where
         Ok(len)
     }
 }
+
+///```jvm
+/// Module_attribute {
+///     u2 attribute_name_index;
+///     u4 attribute_length;
+///
+///     u2 module_name_index;
+///     u2 module_flags;
+///     u2 module_version_index;
+///
+///     u2 requires_count;
+///     {   u2 requires_index;
+///         u2 requires_flags;
+///         u2 requires_version_index;
+///     } requires[requires_count];
+///
+///     u2 exports_count;
+///     {   u2 exports_index;
+///         u2 exports_flags;
+///         u2 exports_to_count;
+///         u2 exports_to_index[exports_to_count];
+///     } exports[exports_count];
+///
+///     u2 opens_count;
+///     {   u2 opens_index;
+///         u2 opens_flags;
+///         u2 opens_to_count;
+///         u2 opens_to_index[opens_to_count];
+///     } opens[opens_count];
+///
+///     u2 uses_count;
+///     u2 uses_index[uses_count];
+///
+///     u2 provides_count;
+///     {   u2 provides_index;
+///         u2 provides_with_count;
+///         u2 provides_with_index[provides_with_count];
+///     } provides[provides_count];
+/// }
+///```
+#[derive(Debug, Clone)]
+pub struct ModuleAttribute {
+    pub module_name_index: u16,
+    pub module_flags: u16,
+    pub module_version_index: u16,
+    pub requires: Vec<ModuleRequires>,
+    pub exports: Vec<ModuleExports>,
+    pub opens: Vec<ModuleOpens>,
+    pub uses_index: Vec<u16>,
+    pub provides: Vec<ModuleProvides>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleRequires {
+    pub requires_index: u16,
+    pub requires_flags: u16,
+    pub requires_version_index: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleExports {
+    pub exports_index: u16,
+    pub exports_flags: u16,
+    pub exports_to_index: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleOpens {
+    pub opens_index: u16,
+    pub opens_flags: u16,
+    pub opens_to_index: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleProvides {
+    pub provides_index: u16,
+    pub provides_with_index: Vec<u16>,
+}
+
+impl ModuleAttribute {
+    pub fn flags(&self) -> ModuleFlags {
+        ModuleFlags::from_bits_truncate(self.module_flags)
+    }
+}
+
+impl ModuleRequires {
+    pub fn flags(&self) -> ModuleRequiresFlags {
+        ModuleRequiresFlags::from_bits_truncate(self.requires_flags)
+    }
+}
+
+impl ModuleExports {
+    pub fn flags(&self) -> ModuleExportsFlags {
+        ModuleExportsFlags::from_bits_truncate(self.exports_flags)
+    }
+}
+
+impl ModuleOpens {
+    pub fn flags(&self) -> ModuleOpensFlags {
+        ModuleOpensFlags::from_bits_truncate(self.opens_flags)
+    }
+}
+
+impl TryFrom<&mut BytesMut> for ModuleAttribute {
+    type Error = Error;
+
+    fn try_from(buf: &mut BytesMut) -> Result<Self, Self::Error> {
+        let module_name_index = buf.get_u16();
+        let module_flags = buf.get_u16();
+        let module_version_index = buf.get_u16();
+        let requires_count = buf.get_u16();
+        let mut requires: Vec<ModuleRequires> = vec![];
+        for _ in 0..requires_count {
+            let requires_index = buf.get_u16();
+            let requires_flags = buf.get_u16();
+            let requires_version_index = buf.get_u16();
+            requires.push(ModuleRequires {
+                requires_index,
+                requires_flags,
+                requires_version_index,
+            });
+        }
+        let exports_count = buf.get_u16();
+        let mut exports: Vec<ModuleExports> = vec![];
+        for _ in 0..exports_count {
+            let exports_index = buf.get_u16();
+            let exports_flags = buf.get_u16();
+            exports.push(ModuleExports {
+                exports_index,
+                exports_flags,
+                exports_to_index: read_u16_table(buf),
+            });
+        }
+        let opens_count = buf.get_u16();
+        let mut opens: Vec<ModuleOpens> = vec![];
+        for _ in 0..opens_count {
+            let opens_index = buf.get_u16();
+            let opens_flags = buf.get_u16();
+            opens.push(ModuleOpens {
+                opens_index,
+                opens_flags,
+                opens_to_index: read_u16_table(buf),
+            });
+        }
+        let uses_index = read_u16_table(buf);
+        let provides_count = buf.get_u16();
+        let mut provides: Vec<ModuleProvides> = vec![];
+        for _ in 0..provides_count {
+            let provides_index = buf.get_u16();
+            provides.push(ModuleProvides {
+                provides_index,
+                provides_with_index: read_u16_table(buf),
+            });
+        }
+        Ok(ModuleAttribute {
+            module_name_index,
+            module_flags,
+            module_version_index,
+            requires,
+            exports,
+            opens,
+            uses_index,
+            provides,
+        })
+    }
+}
+
+impl<T> TryInto<&mut T, usize> for ModuleAttribute
+where
+    T: BufMut,
+{
+    type Error = Error;
+
+    fn try_into(&self, buf: &mut T) -> Result<usize, Self::Error> {
+        let mut len: usize = 0;
+        buf.put_u16(self.module_name_index);
+        buf.put_u16(self.module_flags);
+        buf.put_u16(self.module_version_index);
+        buf.put_u16(self.requires.len() as u16);
+        len += 8;
+        for requires in &self.requires {
+            buf.put_u16(requires.requires_index);
+            buf.put_u16(requires.requires_flags);
+            buf.put_u16(requires.requires_version_index);
+            len += 6;
+        }
+        buf.put_u16(self.exports.len() as u16);
+        len += 2;
+        for exports in &self.exports {
+            buf.put_u16(exports.exports_index);
+            buf.put_u16(exports.exports_flags);
+            len += 4 + write_u16_table(&exports.exports_to_index, buf);
+        }
+        buf.put_u16(self.opens.len() as u16);
+        len += 2;
+        for opens in &self.opens {
+            buf.put_u16(opens.opens_index);
+            buf.put_u16(opens.opens_flags);
+            len += 4 + write_u16_table(&opens.opens_to_index, buf);
+        }
+        len += write_u16_table(&self.uses_index, buf);
+        buf.put_u16(self.provides.len() as u16);
+        len += 2;
+        for provides in &self.provides {
+            buf.put_u16(provides.provides_index);
+            len += 2 + write_u16_table(&provides.provides_with_index, buf);
+        }
+        Ok(len)
+    }
+}
+
+///```jvm
+/// record_component_info {
+///     u2             name_index;
+///     u2             descriptor_index;
+///     u2             attributes_count;
+///     attribute_info attributes[attributes_count];
+/// }
+///```
+#[derive(Debug, Clone)]
+pub struct RecordComponent {
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<Attribute>,
+}
+
+impl TryFromCp<&mut BytesMut> for RecordComponent {
+    type Error = Error;
+
+    fn try_from_cp(
+        buf: &mut BytesMut,
+        constant_pool: &ConstantPoolRef,
+    ) -> Result<Self, Self::Error> {
+        let name_index = buf.get_u16();
+        let descriptor_index = buf.get_u16();
+        let attributes_count = buf.get_u16();
+        let mut attributes: Vec<Attribute> = vec![];
+        for _ in 0..attributes_count {
+            attributes.push(Attribute::try_from_cp(buf, constant_pool)?);
+        }
+        Ok(RecordComponent {
+            name_index,
+            descriptor_index,
+            attributes,
+        })
+    }
+}
+
+impl<T> TryInto<&mut T, usize> for RecordComponent
+where
+    T: BufMut,
+{
+    type Error = Error;
+
+    fn try_into(&self, buf: &mut T) -> Result<usize, Self::Error> {
+        let mut len: usize = 0;
+        buf.put_u16(self.name_index);
+        buf.put_u16(self.descriptor_index);
+        buf.put_u16(self.attributes.len() as u16);
+        len += 6;
+        for attribute in &self.attributes {
+            len += attribute.try_into(buf)?;
+        }
+        Ok(len)
+    }
+}
+
+/// Reads a `u2` count followed by that many `u2` entries
+fn read_u16_table(buf: &mut BytesMut) -> Vec<u16> {
+    let count = buf.get_u16();
+    let mut table: Vec<u16> = vec![];
+    for _ in 0..count {
+        table.push(buf.get_u16());
+    }
+    table
+}
+
+fn write_u16_table(table: &[u16], buf: &mut impl BufMut) -> usize {
+    buf.put_u16(table.len() as u16);
+    for entry in table {
+        buf.put_u16(*entry);
+    }
+    2 + 2 * table.len()
+}
@@ -1,11 +1,16 @@
-use crate::attribute::Attribute;
-use crate::constant::Constant;
+use crate::access_flags::ClassAccessFlags;
+use crate::attribute::{Attribute, AttributeType};
+use crate::class_reader::read_class_bytes;
+use crate::constant::{get_utf8, Constant};
 use crate::error::Error;
 use crate::field::FieldInfo;
 use crate::method::MethodInfo;
+use crate::smap::Smap;
+use crate::version::{ClassFileVersion, VersionPolicy};
 use crate::{ConstantPoolRef, TryFromCp, TryInto, MAGIC};
 use bytes::{Buf, BufMut, BytesMut};
 use std::convert::TryFrom;
+use std::io::{Read, Write};
 use std::sync::Arc;
 
 pub type ClassFileRef = Arc<ClassFile>;
@@ -19,35 +24,59 @@ pub struct ClassFile {
     pub access_flags: u16,
     pub this_class: u16,
     pub super_class: u16,
-    pub interfaces: Vec<Constant>,
+    /// constant pool indexes of `CONSTANT_Class_info` entries
+    pub interfaces: Vec<u16>,
     pub fields: Vec<FieldInfo>,
     pub methods: Vec<MethodInfo>,
     pub attributes: Vec<Attribute>,
 }
 
-impl TryFrom<&mut BytesMut> for ClassFile {
-    type Error = Error;
+impl ClassFile {
+    /// Parses a class file, rejecting it unless `policy` accepts its version and
+    /// the attributes and constants it uses, and its access flags are legal.
+    /// `ClassFile::try_from` parses without these checks.
+    pub fn parse(buf: &mut BytesMut, policy: &VersionPolicy) -> Result<ClassFile, Error> {
+        read_class_bytes(&buf[..])?;
+        ClassFile::read(buf, Some(policy))
+    }
 
-    fn try_from(buf: &mut BytesMut) -> Result<Self, Self::Error> {
+    /// Parses a class file whose structure `read_class_bytes` walked, so it
+    /// isn't truncated, checking it against `policy` if there is one
+    fn read(buf: &mut BytesMut, policy: Option<&VersionPolicy>) -> Result<ClassFile, Error> {
         let magic = buf.get_u32();
-        assert_eq!(magic, MAGIC);
+        if magic != MAGIC {
+            return Err(Error::InvalidMagic(magic));
+        }
         let minor_version = buf.get_u16();
         let major_version = buf.get_u16();
+        // check before the rest is parsed, newer class files may use structures we don't know
+        if let Some(policy) = policy {
+            policy.check_version(ClassFileVersion::new(major_version, minor_version))?;
+        }
         let constant_pool_count = buf.get_u16();
-        let mut constant_pool: Vec<Constant> = Vec::with_capacity(constant_pool_count as usize - 1);
-        for _ in 0..constant_pool_count - 1 {
-            constant_pool.push(Constant::try_from(&mut *buf)?);
+        let constant_pool_len = (constant_pool_count as usize).saturating_sub(1);
+        let mut constant_pool: Vec<Constant> = Vec::with_capacity(constant_pool_len);
+        while constant_pool.len() < constant_pool_len {
+            let constant = Constant::try_from(&mut *buf)?;
+            let is_wide = constant.is_wide();
+            constant_pool.push(constant);
+            if is_wide {
+                constant_pool.push(Constant::Unusable);
+            }
         }
         let constant_pool = Arc::new(constant_pool);
         let access_flags = buf.get_u16();
         let this_class = buf.get_u16();
         let super_class = buf.get_u16();
         let interface_count = buf.get_u16();
-        let mut interfaces: Vec<Constant> = vec![];
+        let mut interfaces: Vec<u16> = vec![];
         for _ in 0..interface_count {
-            let constant = Constant::try_from(&mut *buf)?;
-            if let Constant::Class { .. } = constant {
-                interfaces.push(constant);
+            let interface_index = buf.get_u16();
+            let constant = (interface_index as usize)
+                .checked_sub(1)
+                .and_then(|index| constant_pool.get(index));
+            if let Some(Constant::Class { .. }) = constant {
+                interfaces.push(interface_index);
             } else {
                 return Err(Error::MismatchConstantType);
             }
@@ -67,7 +96,7 @@ impl TryFrom<&mut BytesMut> for ClassFile {
         for _ in 0..attributes_count {
             attributes.push(Attribute::try_from_cp(buf, &constant_pool)?);
         }
-        Ok(ClassFile {
+        let class_file = ClassFile {
             magic,
             minor_version,
             major_version,
@@ -79,7 +108,110 @@ impl TryFrom<&mut BytesMut> for ClassFile {
             fields,
             methods,
             attributes,
-        })
+        };
+        if let Some(policy) = policy {
+            policy.validate(&class_file)?;
+            class_file.check_access_flags()?;
+        }
+        Ok(class_file)
+    }
+
+    /// Reads one class file from a file, a zip entry or a socket, consuming no
+    /// bytes past its end. Wrap unbuffered sources in a `BufReader`, see
+    /// `read_class_bytes`. Like `ClassFile::try_from` it doesn't check the
+    /// version, attributes or access flags, see `ClassFile::parse` for that.
+    pub fn read_from(reader: impl Read) -> Result<ClassFile, Error> {
+        ClassFile::read(&mut read_class_bytes(reader)?, None)
+    }
+
+    /// Writes the class file, returns the number of bytes written
+    pub fn write_to(&self, mut writer: impl Write) -> Result<usize, Error> {
+        let mut buf = BytesMut::with_capacity(4096);
+        let len = self.try_into(&mut buf)?;
+        writer
+            .write_all(&buf)
+            .map_err(|e| Error::Io(e.to_string()))?;
+        Ok(len)
+    }
+
+    pub fn version(&self) -> ClassFileVersion {
+        ClassFileVersion::new(self.major_version, self.minor_version)
+    }
+
+    pub fn flags(&self) -> ClassAccessFlags {
+        ClassAccessFlags::from_bits_truncate(self.access_flags)
+    }
+
+    /// Checks the class, field, method, nested class and module requires flags
+    /// against the combinations allowed by the specification
+    pub fn check_access_flags(&self) -> Result<(), Error> {
+        let flags = self.flags();
+        flags.check()?;
+        let in_interface = flags.is_interface();
+        for field in &self.fields {
+            field.flags().check(in_interface)?;
+        }
+        for method in &self.methods {
+            let name = get_utf8(&self.constant_pool, method.name_index as usize);
+            method
+                .flags()
+                .check(in_interface, self.major_version, name)?;
+        }
+        for attribute in &self.attributes {
+            match &attribute.attr_type {
+                AttributeType::InnerClasses { classes } => {
+                    for class in classes {
+                        class.flags().check()?;
+                    }
+                }
+                AttributeType::Module { module } => {
+                    for requires in &module.requires {
+                        let requires_java_base = match self
+                            .constant_pool
+                            .get((requires.requires_index as usize).wrapping_sub(1))
+                        {
+                            Some(Constant::Module { name_index }) => {
+                                get_utf8(&self.constant_pool, *name_index as usize).as_slice()
+                                    == b"java.base"
+                            }
+                            _ => false,
+                        };
+                        requires
+                            .flags()
+                            .check(self.major_version, requires_java_base)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn source_debug_extension(&self) -> Option<&Vec<u8>> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match &attribute.attr_type {
+                AttributeType::SourceDebugExtension { debug_extension } => Some(debug_extension),
+                _ => None,
+            })
+    }
+
+    /// Parses the JSR-45 source map, if the class carries one
+    pub fn source_map(&self) -> Result<Option<Smap>, Error> {
+        match self.source_debug_extension() {
+            Some(debug_extension) => Ok(Some(Smap::try_from(debug_extension.as_slice())?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl TryFrom<&mut BytesMut> for ClassFile {
+    type Error = Error;
+
+    /// Parses a class file of any version, truncated input is an error
+    fn try_from(buf: &mut BytesMut) -> Result<Self, Self::Error> {
+        read_class_bytes(&buf[..])?;
+        ClassFile::read(buf, None)
     }
 }
 
@@ -94,7 +226,7 @@ where
         buf.put_u32(self.magic);
         buf.put_u16(self.minor_version);
         buf.put_u16(self.major_version);
-        buf.put_u16(self.constant_pool.len() as u16);
+        buf.put_u16(self.constant_pool.len() as u16 + 1);
         len += 10;
         for constant in &*self.constant_pool {
             len += constant.to_buf(buf)?;
@@ -103,8 +235,11 @@ where
         buf.put_u16(self.this_class);
         buf.put_u16(self.super_class);
         buf.put_u16(self.interfaces.len() as u16);
+        for interface_index in &self.interfaces {
+            buf.put_u16(*interface_index);
+        }
         buf.put_u16(self.fields.len() as u16);
-        len += 10;
+        len += 10 + 2 * self.interfaces.len();
         for field in &self.fields {
             len += field.try_into(buf)?;
         }
@@ -125,9 +260,11 @@ where
 #[cfg(test)]
 mod test {
     use crate::class_file::ClassFile;
+    use crate::error::Error;
+    use crate::version::VersionPolicy;
     use bytes::{BufMut, BytesMut};
     use std::convert::TryFrom;
-    use std::io::Read;
+    use std::io::{BufReader, Read};
 
     #[test]
     fn read_class_file() {
@@ -138,4 +275,84 @@ mod test {
         let class_file = ClassFile::try_from(&mut buf).unwrap();
         println!("{:?}", class_file);
     }
+
+    #[test]
+    fn read_and_write_round_trip() {
+        for path in &[
+            "tests/HelloWorld.class",
+            "tests/TypeAnnotations.class",
+            "tests/RoundTrip.class",
+        ] {
+            let bytes = std::fs::read(path).unwrap();
+            // trailing bytes belong to whoever reads next
+            let mut stream = bytes.clone();
+            stream.extend_from_slice(b"next");
+            let mut reader = stream.as_slice();
+            let class_file = ClassFile::read_from(&mut reader).unwrap();
+            assert_eq!(reader, b"next");
+
+            let mut written: Vec<u8> = vec![];
+            let len = class_file.write_to(&mut written).unwrap();
+            assert_eq!(len, bytes.len());
+            assert_eq!(written, bytes);
+        }
+        assert!(ClassFile::read_from(&b"\xca\xfe\xba\xbe\x00"[..]).is_err());
+    }
+
+    #[test]
+    fn read_class_files_through_a_buffer() {
+        let bytes = std::fs::read("tests/HelloWorld.class").unwrap();
+        let stream = [bytes.as_slice(), bytes.as_slice(), b"next"].concat();
+        let mut reader = BufReader::new(stream.as_slice());
+        for _ in 0..2 {
+            let class_file = ClassFile::read_from(&mut reader).unwrap();
+            assert_eq!(class_file.major_version, 52);
+        }
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"next");
+    }
+
+    #[test]
+    fn only_parse_checks_the_version_policy() {
+        let mut bytes = std::fs::read("tests/HelloWorld.class").unwrap();
+        // major_version 99, newer than any release the default policy knows
+        bytes[6..8].copy_from_slice(&99u16.to_be_bytes());
+        let mut buf = BytesMut::from(bytes.as_slice());
+        assert!(ClassFile::try_from(&mut buf).is_ok());
+        assert!(ClassFile::read_from(bytes.as_slice()).is_ok());
+        let mut buf = BytesMut::from(bytes.as_slice());
+        assert!(matches!(
+            ClassFile::parse(&mut buf, &VersionPolicy::default()),
+            Err(Error::UnsupportedClassVersion(99, _))
+        ));
+    }
+
+    #[test]
+    fn only_parse_checks_access_flags() {
+        let bytes = std::fs::read("tests/HelloWorld.class").unwrap();
+        let mut class_file = ClassFile::read_from(bytes.as_slice()).unwrap();
+        // An interface can't be final
+        class_file.access_flags = 0x0200 | 0x0400 | 0x0010;
+        let mut written = vec![];
+        class_file.write_to(&mut written).unwrap();
+        let mut buf = BytesMut::from(written.as_slice());
+        assert!(ClassFile::try_from(&mut buf).is_ok());
+        let mut buf = BytesMut::from(written.as_slice());
+        assert!(matches!(
+            ClassFile::parse(&mut buf, &VersionPolicy::default()),
+            Err(Error::InvalidAccessFlags(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_class_files_are_errors() {
+        let bytes = std::fs::read("tests/HelloWorld.class").unwrap();
+        for len in [0, 3, 9, 40, bytes.len() - 1] {
+            let mut buf = BytesMut::from(&bytes[..len]);
+            assert!(ClassFile::try_from(&mut buf).is_err());
+            let mut buf = BytesMut::from(&bytes[..len]);
+            assert!(ClassFile::parse(&mut buf, &VersionPolicy::default()).is_err());
+        }
+    }
 }
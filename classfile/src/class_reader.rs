@@ -1 +1,126 @@
+use crate::error::Error;
+use crate::MAGIC;
+use bytes::{BufMut, BytesMut};
+use std::io::Read;
 
+/// Reads the bytes of exactly one class file from `reader`.
+///
+/// A class file carries no overall length, so its structure is walked just far
+/// enough to know how many bytes follow: constant pool entries by tag, then
+/// every `attribute_info` by its `attribute_length`. Nothing past the end of the
+/// class file is consumed, which keeps the reader usable for whatever comes next,
+/// e.g. on a socket.
+///
+/// That takes a `read` call per structure, so wrap a file or socket in a
+/// `BufReader` and pass it as `&mut`: what it buffers past the class file stays
+/// there for the next read.
+pub fn read_class_bytes(reader: impl Read) -> Result<BytesMut, Error> {
+    let mut class_reader = ClassReader {
+        reader,
+        buf: BytesMut::with_capacity(4096),
+    };
+    class_reader.read_class()?;
+    Ok(class_reader.buf)
+}
+
+struct ClassReader<R> {
+    reader: R,
+    buf: BytesMut,
+}
+
+impl<R: Read> ClassReader<R> {
+    fn read_class(&mut self) -> Result<(), Error> {
+        let magic = self.u32()?;
+        if magic != MAGIC {
+            return Err(Error::InvalidMagic(magic));
+        }
+        // minor_version, major_version
+        self.take(4)?;
+        let constant_pool_count = self.u16()?;
+        let mut index = 1;
+        while index < constant_pool_count {
+            let tag = self.u8()?;
+            match tag {
+                // CONSTANT_Utf8
+                1 => {
+                    let length = self.u16()?;
+                    self.take(length as usize)?;
+                }
+                // CONSTANT_Integer, CONSTANT_Float
+                3 | 4 => self.take(4)?,
+                // CONSTANT_Long, CONSTANT_Double
+                5 | 6 => {
+                    self.take(8)?;
+                    index += 1;
+                }
+                // CONSTANT_Class, CONSTANT_String, CONSTANT_MethodType, CONSTANT_Module, CONSTANT_Package
+                7 | 8 | 16 | 19 | 20 => self.take(2)?,
+                // CONSTANT_MethodHandle
+                15 => self.take(3)?,
+                // member refs, CONSTANT_NameAndType, CONSTANT_Dynamic, CONSTANT_InvokeDynamic
+                9 | 10 | 11 | 12 | 17 | 18 => self.take(4)?,
+                _ => return Err(Error::InvalidConstantTag(tag)),
+            }
+            index += 1;
+        }
+        // access_flags, this_class, super_class
+        self.take(6)?;
+        let interfaces_count = self.u16()?;
+        self.take(2 * interfaces_count as usize)?;
+        // fields, then methods
+        for _ in 0..2 {
+            let count = self.u16()?;
+            for _ in 0..count {
+                // access_flags, name_index, descriptor_index
+                self.take(6)?;
+                self.attributes()?;
+            }
+        }
+        self.attributes()
+    }
+
+    fn attributes(&mut self) -> Result<(), Error> {
+        let attributes_count = self.u16()?;
+        for _ in 0..attributes_count {
+            // attribute_name_index
+            self.take(2)?;
+            let attribute_length = self.u32()?;
+            self.take(attribute_length as usize)?;
+        }
+        Ok(())
+    }
+
+    /// Appends the next `len` bytes to the class file, growing the buffer only as
+    /// data arrives so a bogus length can't allocate gigabytes up front
+    fn read(&mut self, len: usize) -> Result<&[u8], Error> {
+        let start = self.buf.len();
+        let mut bytes: Vec<u8> = vec![];
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut bytes)
+            .map_err(|e| Error::Io(e.to_string()))?;
+        if bytes.len() != len {
+            return Err(Error::Io("unexpected end of class file".to_string()));
+        }
+        self.buf.put_slice(&bytes);
+        Ok(&self.buf[start..])
+    }
+
+    fn take(&mut self, len: usize) -> Result<(), Error> {
+        self.read(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.read(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.read(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
@@ -118,10 +118,22 @@ pub enum Constant {
     MethodType {
         descriptor_index: u16,
     },
+    Dynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
     InvokeDynamic {
         bootstrap_method_attr_index: u16,
         name_and_type_index: u16,
     },
+    Module {
+        name_index: u16,
+    },
+    Package {
+        name_index: u16,
+    },
+    /// The slot following a `Long` or `Double`, which take two constant pool entries
+    Unusable,
 }
 
 impl TryFrom<&mut BytesMut> for Constant {
@@ -129,7 +141,7 @@ impl TryFrom<&mut BytesMut> for Constant {
 
     fn try_from(buf: &mut BytesMut) -> Result<Self, Self::Error> {
         let tag = buf.get_u8();
-        let tag = Tag::from(tag);
+        let tag = Tag::try_from(tag)?;
         match tag {
             Tag::Class => {
                 let name_index = buf.get_u16();
@@ -200,6 +212,14 @@ impl TryFrom<&mut BytesMut> for Constant {
                 let descriptor_index = buf.get_u16();
                 Ok(Constant::MethodType { descriptor_index })
             }
+            Tag::Dynamic => {
+                let bootstrap_method_attr_index = buf.get_u16();
+                let name_and_type_index = buf.get_u16();
+                Ok(Constant::Dynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                })
+            }
             Tag::InvokeDynamic => {
                 let bootstrap_method_attr_index = buf.get_u16();
                 let name_and_type_index = buf.get_u16();
@@ -208,7 +228,14 @@ impl TryFrom<&mut BytesMut> for Constant {
                     name_and_type_index,
                 })
             }
-            _ => Err(Error::InvalidConstantTag(tag.into())),
+            Tag::Module => {
+                let name_index = buf.get_u16();
+                Ok(Constant::Module { name_index })
+            }
+            Tag::Package => {
+                let name_index = buf.get_u16();
+                Ok(Constant::Package { name_index })
+            }
         }
     }
 }
@@ -306,6 +333,15 @@ where
                 buf.put_u16(*descriptor_index);
                 len += 2;
             }
+            Constant::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => {
+                buf.put_u8(Tag::Dynamic.into());
+                buf.put_u16(*bootstrap_method_attr_index);
+                buf.put_u16(*name_and_type_index);
+                len += 4;
+            }
             Constant::InvokeDynamic {
                 bootstrap_method_attr_index,
                 name_and_type_index,
@@ -315,12 +351,53 @@ where
                 buf.put_u16(*name_and_type_index);
                 len += 4;
             }
+            Constant::Module { name_index } => {
+                buf.put_u8(Tag::Module.into());
+                buf.put_u16(*name_index);
+                len += 2;
+            }
+            Constant::Package { name_index } => {
+                buf.put_u8(Tag::Package.into());
+                buf.put_u16(*name_index);
+                len += 2;
+            }
+            Constant::Unusable => len = 0,
         }
         Ok(len)
     }
 }
 
 impl Constant {
+    /// `None` for the unusable slot after a `Long` or `Double`
+    pub fn tag(&self) -> Option<Tag> {
+        let tag = match self {
+            Constant::Class { .. } => Tag::Class,
+            Constant::FieldRef { .. } => Tag::FieldRef,
+            Constant::MethodRef { .. } => Tag::MethodRef,
+            Constant::InterfaceMethodRef { .. } => Tag::InterfaceMethodRef,
+            Constant::String { .. } => Tag::String,
+            Constant::Integer(..) => Tag::Integer,
+            Constant::Float(..) => Tag::Float,
+            Constant::Long(..) => Tag::Long,
+            Constant::Double(..) => Tag::Double,
+            Constant::NameAndType { .. } => Tag::NameAndType,
+            Constant::Utf8(..) => Tag::Utf8,
+            Constant::MethodHandle { .. } => Tag::MethodHandle,
+            Constant::MethodType { .. } => Tag::MethodType,
+            Constant::Dynamic { .. } => Tag::Dynamic,
+            Constant::InvokeDynamic { .. } => Tag::InvokeDynamic,
+            Constant::Module { .. } => Tag::Module,
+            Constant::Package { .. } => Tag::Package,
+            Constant::Unusable => return None,
+        };
+        Some(tag)
+    }
+
+    /// Long and double constants take two constant pool entries
+    pub fn is_wide(&self) -> bool {
+        matches!(self, Constant::Long(..) | Constant::Double(..))
+    }
+
     pub fn to_buf(&self, buf: &mut impl BufMut) -> Result<usize, Error> {
         let mut len: usize = 1;
         match self {
@@ -408,6 +485,15 @@ impl Constant {
                 buf.put_u16(*descriptor_index);
                 len += 2;
             }
+            Constant::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => {
+                buf.put_u8(Tag::Dynamic.into());
+                buf.put_u16(*bootstrap_method_attr_index);
+                buf.put_u16(*name_and_type_index);
+                len += 4;
+            }
             Constant::InvokeDynamic {
                 bootstrap_method_attr_index,
                 name_and_type_index,
@@ -417,6 +503,17 @@ impl Constant {
                 buf.put_u16(*name_and_type_index);
                 len += 4;
             }
+            Constant::Module { name_index } => {
+                buf.put_u8(Tag::Module.into());
+                buf.put_u16(*name_index);
+                len += 2;
+            }
+            Constant::Package { name_index } => {
+                buf.put_u8(Tag::Package.into());
+                buf.put_u16(*name_index);
+                len += 2;
+            }
+            Constant::Unusable => len = 0,
         }
         Ok(len)
     }
@@ -438,13 +535,17 @@ impl fmt::Display for Constant {
             Constant::Utf8(..) => "Constant::Utf8".fmt(fmt),
             Constant::MethodHandle { .. } => "Constant::MethodHandle".fmt(fmt),
             Constant::MethodType { .. } => "Constant::MethodType".fmt(fmt),
+            Constant::Dynamic { .. } => "Constant::Dynamic".fmt(fmt),
             Constant::InvokeDynamic { .. } => "Constant::InvokeDynamic".fmt(fmt),
+            Constant::Module { .. } => "Constant::Module".fmt(fmt),
+            Constant::Package { .. } => "Constant::Package".fmt(fmt),
+            Constant::Unusable => "Constant::Unusable".fmt(fmt),
         }
     }
 }
 
 /// Tag values for the constant pool entries
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Tag {
     Class,
     FieldRef,
@@ -489,9 +590,11 @@ impl Into<u8> for Tag {
     }
 }
 
-impl From<u8> for Tag {
-    fn from(tag: u8) -> Self {
-        match tag {
+impl TryFrom<u8> for Tag {
+    type Error = Error;
+
+    fn try_from(tag: u8) -> Result<Self, Self::Error> {
+        let tag = match tag {
             7 => Tag::Class,
             9 => Tag::FieldRef,
             10 => Tag::MethodRef,
@@ -509,7 +612,8 @@ impl From<u8> for Tag {
             18 => Tag::InvokeDynamic,
             19 => Tag::Module,
             20 => Tag::Package,
-            _ => unreachable!(),
-        }
+            _ => return Err(Error::InvalidConstantTag(tag)),
+        };
+        Ok(tag)
     }
 }
@@ -0,0 +1,131 @@
+use crate::constant::Constant;
+use crate::error::Error;
+use crate::ConstantPoolRef;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Builds a constant pool for a class file generated or rewritten in memory.
+///
+/// Adding a constant equal to one already in the pool returns the existing
+/// index, entries are compared by their encoding.
+#[derive(Debug, Clone, Default)]
+pub struct ConstantPoolBuilder {
+    constants: Vec<Constant>,
+    indexes: HashMap<Vec<u8>, u16>,
+}
+
+impl ConstantPoolBuilder {
+    pub fn new() -> ConstantPoolBuilder {
+        ConstantPoolBuilder::default()
+    }
+
+    /// Starts from an existing constant pool, keeping every index valid
+    pub fn from_constant_pool(constant_pool: &ConstantPoolRef) -> Result<Self, Error> {
+        let mut builder = ConstantPoolBuilder::new();
+        for constant in constant_pool.iter() {
+            match constant {
+                Constant::Unusable => builder.constants.push(Constant::Unusable),
+                constant => {
+                    let index = builder.constants.len() as u16 + 1;
+                    builder.constants.push(constant.clone());
+                    builder.indexes.entry(key(constant)?).or_insert(index);
+                }
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Index of `constant`, added to the pool if it isn't there yet
+    pub fn add(&mut self, constant: Constant) -> Result<u16, Error> {
+        let key = key(&constant)?;
+        if let Some(index) = self.indexes.get(&key) {
+            return Ok(*index);
+        }
+        let slots = if constant.is_wide() { 2 } else { 1 };
+        // constant_pool_count is a u2 and one more than the number of entries
+        if self.constants.len() + slots >= u16::MAX as usize {
+            return Err(Error::InvalidLength);
+        }
+        let index = self.constants.len() as u16 + 1;
+        let is_wide = constant.is_wide();
+        self.constants.push(constant);
+        if is_wide {
+            self.constants.push(Constant::Unusable);
+        }
+        self.indexes.insert(key, index);
+        Ok(index)
+    }
+
+    pub fn utf8(&mut self, string: &str) -> Result<u16, Error> {
+        self.add(Constant::Utf8(Arc::new(string.as_bytes().to_vec())))
+    }
+
+    pub fn class(&mut self, name: &str) -> Result<u16, Error> {
+        let name_index = self.utf8(name)?;
+        self.add(Constant::Class { name_index })
+    }
+
+    pub fn string(&mut self, string: &str) -> Result<u16, Error> {
+        let string_index = self.utf8(string)?;
+        self.add(Constant::String { string_index })
+    }
+
+    pub fn name_and_type(&mut self, name: &str, descriptor: &str) -> Result<u16, Error> {
+        let name_index = self.utf8(name)?;
+        let descriptor_index = self.utf8(descriptor)?;
+        self.add(Constant::NameAndType {
+            name_index,
+            descriptor_index,
+        })
+    }
+
+    pub fn field_ref(&mut self, class: &str, name: &str, descriptor: &str) -> Result<u16, Error> {
+        let class_index = self.class(class)?;
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.add(Constant::FieldRef {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> Result<u16, Error> {
+        let class_index = self.class(class)?;
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.add(Constant::MethodRef {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn interface_method_ref(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, Error> {
+        let class_index = self.class(class)?;
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.add(Constant::InterfaceMethodRef {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.constants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+
+    pub fn build(self) -> ConstantPoolRef {
+        Arc::new(self.constants)
+    }
+}
+
+fn key(constant: &Constant) -> Result<Vec<u8>, Error> {
+    let mut key: Vec<u8> = vec![];
+    constant.to_buf(&mut key)?;
+    Ok(key)
+}
@@ -0,0 +1,268 @@
+use crate::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// ```jvm
+/// BaseType:
+///     (one of)
+///     B C D F I J S Z
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BaseType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+}
+
+impl BaseType {
+    pub fn from_tag(tag: u8) -> Option<BaseType> {
+        match tag {
+            b'B' => Some(BaseType::Byte),
+            b'C' => Some(BaseType::Char),
+            b'D' => Some(BaseType::Double),
+            b'F' => Some(BaseType::Float),
+            b'I' => Some(BaseType::Int),
+            b'J' => Some(BaseType::Long),
+            b'S' => Some(BaseType::Short),
+            b'Z' => Some(BaseType::Boolean),
+            _ => None,
+        }
+    }
+
+    pub fn tag(&self) -> u8 {
+        match self {
+            BaseType::Byte => b'B',
+            BaseType::Char => b'C',
+            BaseType::Double => b'D',
+            BaseType::Float => b'F',
+            BaseType::Int => b'I',
+            BaseType::Long => b'J',
+            BaseType::Short => b'S',
+            BaseType::Boolean => b'Z',
+        }
+    }
+
+    /// long and double take two local variable slots and two operand stack entries
+    pub fn is_wide(&self) -> bool {
+        matches!(self, BaseType::Long | BaseType::Double)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BaseType::Byte => "byte",
+            BaseType::Char => "char",
+            BaseType::Double => "double",
+            BaseType::Float => "float",
+            BaseType::Int => "int",
+            BaseType::Long => "long",
+            BaseType::Short => "short",
+            BaseType::Boolean => "boolean",
+        }
+    }
+}
+
+/// ```jvm
+/// FieldDescriptor:
+///     FieldType
+/// FieldType:
+///     BaseType
+///     ObjectType
+///     ArrayType
+/// ObjectType:
+///     L ClassName ;
+/// ArrayType:
+///     [ ComponentType
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    Base(BaseType),
+    /// binary name in internal form, e.g. `java/lang/String`
+    Object(String),
+    Array(Box<FieldType>),
+}
+
+impl FieldType {
+    pub fn parse(descriptor: &[u8]) -> Result<FieldType, Error> {
+        let mut pos = 0;
+        let field_type = FieldType::parse_at(descriptor, &mut pos)?;
+        if pos != descriptor.len() {
+            return Err(invalid_descriptor(descriptor));
+        }
+        Ok(field_type)
+    }
+
+    pub(crate) fn parse_at(descriptor: &[u8], pos: &mut usize) -> Result<FieldType, Error> {
+        let tag = *descriptor
+            .get(*pos)
+            .ok_or_else(|| invalid_descriptor(descriptor))?;
+        *pos += 1;
+        if let Some(base_type) = BaseType::from_tag(tag) {
+            return Ok(FieldType::Base(base_type));
+        }
+        match tag {
+            b'L' => {
+                let start = *pos;
+                let len = descriptor[start..]
+                    .iter()
+                    .position(|b| *b == b';')
+                    .ok_or_else(|| invalid_descriptor(descriptor))?;
+                if len == 0 {
+                    return Err(invalid_descriptor(descriptor));
+                }
+                *pos += len + 1;
+                let name = String::from_utf8_lossy(&descriptor[start..start + len]).into_owned();
+                Ok(FieldType::Object(name))
+            }
+            b'[' => Ok(FieldType::Array(Box::new(FieldType::parse_at(
+                descriptor, pos,
+            )?))),
+            _ => Err(invalid_descriptor(descriptor)),
+        }
+    }
+
+    /// Number of local variable slots a value of this type occupies
+    pub fn slots(&self) -> usize {
+        match self {
+            FieldType::Base(base_type) if base_type.is_wide() => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn is_reference(&self) -> bool {
+        !matches!(self, FieldType::Base(_))
+    }
+
+    pub fn dimensions(&self) -> usize {
+        match self {
+            FieldType::Array(component) => 1 + component.dimensions(),
+            _ => 0,
+        }
+    }
+
+    pub fn descriptor(&self) -> String {
+        match self {
+            FieldType::Base(base_type) => (base_type.tag() as char).to_string(),
+            FieldType::Object(name) => format!("L{};", name),
+            FieldType::Array(component) => format!("[{}", component.descriptor()),
+        }
+    }
+}
+
+impl Display for FieldType {
+    /// Java source form, e.g. `java.lang.String[]`
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Base(base_type) => write!(f, "{}", base_type.name()),
+            FieldType::Object(name) => write!(f, "{}", name.replace('/', ".")),
+            FieldType::Array(component) => write!(f, "{}[]", component),
+        }
+    }
+}
+
+/// ```jvm
+/// MethodDescriptor:
+///     ( {ParameterDescriptor} ) ReturnDescriptor
+/// ReturnDescriptor:
+///     FieldType
+///     VoidDescriptor
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MethodDescriptor {
+    pub parameters: Vec<FieldType>,
+    /// None for `V`
+    pub return_type: Option<FieldType>,
+}
+
+impl MethodDescriptor {
+    pub fn parse(descriptor: &[u8]) -> Result<MethodDescriptor, Error> {
+        if descriptor.first() != Some(&b'(') {
+            return Err(invalid_descriptor(descriptor));
+        }
+        let mut pos = 1;
+        let mut parameters: Vec<FieldType> = vec![];
+        loop {
+            match descriptor.get(pos) {
+                Some(b')') => {
+                    pos += 1;
+                    break;
+                }
+                Some(_) => parameters.push(FieldType::parse_at(descriptor, &mut pos)?),
+                None => return Err(invalid_descriptor(descriptor)),
+            }
+        }
+        let return_type = if descriptor.get(pos) == Some(&b'V') {
+            pos += 1;
+            None
+        } else {
+            Some(FieldType::parse_at(descriptor, &mut pos)?)
+        };
+        if pos != descriptor.len() {
+            return Err(invalid_descriptor(descriptor));
+        }
+        Ok(MethodDescriptor {
+            parameters,
+            return_type,
+        })
+    }
+
+    /// Local variable slots taken by the parameters, not counting `this`
+    pub fn parameter_slots(&self) -> usize {
+        self.parameters.iter().map(|p| p.slots()).sum()
+    }
+
+    /// Operand stack entries pushed by an invocation returning this type
+    pub fn return_slots(&self) -> usize {
+        self.return_type.as_ref().map_or(0, |r| r.slots())
+    }
+
+    pub fn descriptor(&self) -> String {
+        let mut descriptor = String::from("(");
+        for parameter in &self.parameters {
+            descriptor.push_str(&parameter.descriptor());
+        }
+        descriptor.push(')');
+        match &self.return_type {
+            Some(return_type) => descriptor.push_str(&return_type.descriptor()),
+            None => descriptor.push('V'),
+        }
+        descriptor
+    }
+}
+
+fn invalid_descriptor(descriptor: &[u8]) -> Error {
+    Error::InvalidDescriptor(String::from_utf8_lossy(descriptor).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::descriptor::{BaseType, FieldType, MethodDescriptor};
+
+    #[test]
+    fn parse_method_descriptor() {
+        let descriptor =
+            MethodDescriptor::parse(b"(IDLjava/lang/Thread;[[J)Ljava/lang/Object;").unwrap();
+        assert_eq!(descriptor.parameters.len(), 4);
+        assert_eq!(descriptor.parameter_slots(), 5);
+        assert_eq!(descriptor.parameters[3].dimensions(), 2);
+        assert_eq!(descriptor.parameters[3].to_string(), "long[][]");
+        assert_eq!(
+            descriptor.return_type,
+            Some(FieldType::Object("java/lang/Object".to_string()))
+        );
+        assert_eq!(
+            descriptor.descriptor(),
+            "(IDLjava/lang/Thread;[[J)Ljava/lang/Object;"
+        );
+        assert_eq!(MethodDescriptor::parse(b"()V").unwrap().return_type, None);
+        assert_eq!(
+            FieldType::parse(b"Z").unwrap(),
+            FieldType::Base(BaseType::Boolean)
+        );
+        assert!(FieldType::parse(b"Ljava/lang/String").is_err());
+        assert!(MethodDescriptor::parse(b"(I)VV").is_err());
+    }
+}
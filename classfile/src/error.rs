@@ -2,6 +2,10 @@ use crate::attribute::StackMapFrame;
 
 #[derive(Debug, Clone)]
 pub enum Error {
+    Io(String),
+
+    InvalidMagic(u32),
+
     InvalidLength,
 
     InvalidString(String),
@@ -12,6 +16,20 @@ pub enum Error {
 
     InvalidTargetInfo,
 
+    InvalidTypePath,
+
+    InvalidDescriptor(String),
+
+    InvalidSignature(String),
+
+    InvalidAccessFlags(u16),
+
+    // Class file version (major, minor)
+    UnsupportedClassVersion(u16, u16),
+
+    // Constant tag not defined for the class file major version
+    UnsupportedConstant(u8, u16),
+
     // Constant
     MismatchConstantType,
 
@@ -30,4 +48,12 @@ pub enum Error {
 
     // Attribute
     InvalidAttributeName(String),
+
+    // Attribute not defined for the class file major version
+    UnsupportedAttribute(String, u16),
+
+    MisplacedAttribute(String),
+
+    // SourceDebugExtension
+    InvalidSmap(String),
 }
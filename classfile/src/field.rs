@@ -1,3 +1,4 @@
+use crate::access_flags::FieldAccessFlags;
 use crate::attribute::Attribute;
 use crate::error::Error;
 use crate::{ConstantPoolRef, TryFromCp, TryInto};
@@ -11,6 +12,12 @@ pub struct FieldInfo {
     pub attributes: Vec<Attribute>,
 }
 
+impl FieldInfo {
+    pub fn flags(&self) -> FieldAccessFlags {
+        FieldAccessFlags::from_bits_truncate(self.access_flags)
+    }
+}
+
 impl TryFromCp<&mut BytesMut> for FieldInfo {
     type Error = Error;
 
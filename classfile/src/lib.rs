@@ -14,9 +14,15 @@ pub mod attribute;
 pub mod class_file;
 pub mod class_reader;
 pub mod constant;
+pub mod constant_pool;
+pub mod descriptor;
 pub mod error;
 pub mod field;
 pub mod method;
+pub mod signature;
+pub mod smap;
+pub mod type_annotation;
+pub mod version;
 
 pub const MAGIC: u32 = 0xCAFEBABE;
 
@@ -1,4 +1,5 @@
 #[allow(dead_code)]
+use crate::access_flags::MethodAccessFlags;
 use crate::attribute::Attribute;
 use crate::attribute::{AttributeType, CodeAttribute};
 use crate::error::Error;
@@ -15,6 +16,28 @@ pub struct MethodInfo {
 }
 
 impl MethodInfo {
+    pub fn new(
+        access_flags: u16,
+        name_index: u16,
+        descriptor_index: u16,
+        attributes: Vec<Attribute>,
+    ) -> MethodInfo {
+        let code_attr_index = attributes
+            .iter()
+            .position(|attribute| matches!(attribute.attr_type, AttributeType::Code { .. }));
+        MethodInfo {
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes,
+            code_attr_index,
+        }
+    }
+
+    pub fn flags(&self) -> MethodAccessFlags {
+        MethodAccessFlags::from_bits_truncate(self.access_flags)
+    }
+
     pub fn get_code_attr(&self) -> Option<&CodeAttribute> {
         return match self.code_attr_index {
             Some(code_index) => {
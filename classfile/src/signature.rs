@@ -0,0 +1,473 @@
+use crate::descriptor::{BaseType, FieldType};
+use crate::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// ```jvm
+/// JavaTypeSignature:
+///     ReferenceTypeSignature
+///     BaseType
+/// ReferenceTypeSignature:
+///     ClassTypeSignature
+///     TypeVariableSignature
+///     ArrayTypeSignature
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeSignature {
+    Base(BaseType),
+    Class(ClassTypeSignature),
+    TypeVariable(String),
+    Array(Box<TypeSignature>),
+}
+
+/// ```jvm
+/// ClassTypeSignature:
+///     L [PackageSpecifier] SimpleClassTypeSignature {ClassTypeSignatureSuffix} ;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassTypeSignature {
+    /// `java/util`, empty for the unnamed package
+    pub package: String,
+    /// Outermost class first, one entry per `.` suffix
+    pub classes: Vec<SimpleClassTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleClassTypeSignature {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+/// ```jvm
+/// TypeArgument:
+///     [WildcardIndicator] ReferenceTypeSignature
+///     *
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeArgument {
+    /// `*`, an unbounded wildcard
+    Any,
+    /// `+`
+    Extends(TypeSignature),
+    /// `-`
+    Super(TypeSignature),
+    Exact(TypeSignature),
+}
+
+/// ```jvm
+/// TypeParameter:
+///     Identifier ClassBound {InterfaceBound}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeParameter {
+    pub name: String,
+    pub class_bound: Option<TypeSignature>,
+    pub interface_bounds: Vec<TypeSignature>,
+}
+
+/// ```jvm
+/// ClassSignature:
+///     [TypeParameters] SuperclassSignature {SuperinterfaceSignature}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub super_class: ClassTypeSignature,
+    pub interfaces: Vec<ClassTypeSignature>,
+}
+
+/// ```jvm
+/// MethodSignature:
+///     [TypeParameters] ( {JavaTypeSignature} ) Result {ThrowsSignature}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<TypeSignature>,
+    /// None for `V`
+    pub result: Option<TypeSignature>,
+    pub throws: Vec<TypeSignature>,
+}
+
+impl ClassTypeSignature {
+    /// Binary name in internal form, e.g. `java/util/Map$Entry`
+    pub fn internal_name(&self) -> String {
+        let mut name = self.package.clone();
+        for (i, class) in self.classes.iter().enumerate() {
+            if i == 0 {
+                if !name.is_empty() {
+                    name.push('/');
+                }
+            } else {
+                name.push('$');
+            }
+            name.push_str(&class.name);
+        }
+        name
+    }
+
+    pub fn from_internal_name(name: &str) -> ClassTypeSignature {
+        let (package, simple_name) = match name.rfind('/') {
+            Some(i) => (&name[..i], &name[i + 1..]),
+            None => ("", name),
+        };
+        ClassTypeSignature {
+            package: package.to_string(),
+            classes: vec![SimpleClassTypeSignature {
+                name: simple_name.to_string(),
+                type_arguments: vec![],
+            }],
+        }
+    }
+}
+
+impl TypeSignature {
+    pub fn parse(signature: &[u8]) -> Result<TypeSignature, Error> {
+        let mut parser = Parser::new(signature);
+        let type_signature = parser.java_type()?;
+        parser.finish()?;
+        Ok(type_signature)
+    }
+}
+
+impl ClassSignature {
+    pub fn parse(signature: &[u8]) -> Result<ClassSignature, Error> {
+        let mut parser = Parser::new(signature);
+        let type_parameters = parser.type_parameters()?;
+        let super_class = parser.class_type()?;
+        let mut interfaces: Vec<ClassTypeSignature> = vec![];
+        while !parser.at_end() {
+            interfaces.push(parser.class_type()?);
+        }
+        Ok(ClassSignature {
+            type_parameters,
+            super_class,
+            interfaces,
+        })
+    }
+}
+
+impl MethodSignature {
+    pub fn parse(signature: &[u8]) -> Result<MethodSignature, Error> {
+        let mut parser = Parser::new(signature);
+        let type_parameters = parser.type_parameters()?;
+        parser.expect(b'(')?;
+        let mut parameters: Vec<TypeSignature> = vec![];
+        while parser.peek() != Some(b')') {
+            parameters.push(parser.java_type()?);
+        }
+        parser.expect(b')')?;
+        let result = if parser.peek() == Some(b'V') {
+            parser.pos += 1;
+            None
+        } else {
+            Some(parser.java_type()?)
+        };
+        let mut throws: Vec<TypeSignature> = vec![];
+        while !parser.at_end() {
+            parser.expect(b'^')?;
+            throws.push(parser.reference_type()?);
+        }
+        Ok(MethodSignature {
+            type_parameters,
+            parameters,
+            result,
+            throws,
+        })
+    }
+}
+
+impl From<&FieldType> for TypeSignature {
+    fn from(field_type: &FieldType) -> Self {
+        match field_type {
+            FieldType::Base(base_type) => TypeSignature::Base(*base_type),
+            FieldType::Object(name) => {
+                TypeSignature::Class(ClassTypeSignature::from_internal_name(name))
+            }
+            FieldType::Array(component) => {
+                TypeSignature::Array(Box::new(TypeSignature::from(&**component)))
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    signature: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(signature: &'a [u8]) -> Self {
+        Parser { signature, pos: 0 }
+    }
+
+    fn error(&self) -> Error {
+        Error::InvalidSignature(String::from_utf8_lossy(self.signature).into_owned())
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.signature.get(self.pos).cloned()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.signature.len()
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), Error> {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        while let Some(b) = self.peek() {
+            if matches!(b, b'.' | b';' | b'[' | b'/' | b'<' | b'>' | b':') {
+                break;
+            }
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.error());
+        }
+        Ok(String::from_utf8_lossy(&self.signature[start..self.pos]).into_owned())
+    }
+
+    fn type_parameters(&mut self) -> Result<Vec<TypeParameter>, Error> {
+        let mut type_parameters: Vec<TypeParameter> = vec![];
+        if self.peek() != Some(b'<') {
+            return Ok(type_parameters);
+        }
+        self.pos += 1;
+        while self.peek() != Some(b'>') {
+            let name = self.identifier()?;
+            self.expect(b':')?;
+            let class_bound = match self.peek() {
+                Some(b'L') | Some(b'T') | Some(b'[') => Some(self.reference_type()?),
+                _ => None,
+            };
+            let mut interface_bounds: Vec<TypeSignature> = vec![];
+            while self.peek() == Some(b':') {
+                self.pos += 1;
+                interface_bounds.push(self.reference_type()?);
+            }
+            type_parameters.push(TypeParameter {
+                name,
+                class_bound,
+                interface_bounds,
+            });
+        }
+        self.expect(b'>')?;
+        if type_parameters.is_empty() {
+            return Err(self.error());
+        }
+        Ok(type_parameters)
+    }
+
+    fn java_type(&mut self) -> Result<TypeSignature, Error> {
+        match self.peek().and_then(BaseType::from_tag) {
+            Some(base_type) => {
+                self.pos += 1;
+                Ok(TypeSignature::Base(base_type))
+            }
+            None => self.reference_type(),
+        }
+    }
+
+    fn reference_type(&mut self) -> Result<TypeSignature, Error> {
+        match self.peek() {
+            Some(b'L') => Ok(TypeSignature::Class(self.class_type()?)),
+            Some(b'T') => {
+                self.pos += 1;
+                let name = self.identifier()?;
+                self.expect(b';')?;
+                Ok(TypeSignature::TypeVariable(name))
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                Ok(TypeSignature::Array(Box::new(self.java_type()?)))
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn class_type(&mut self) -> Result<ClassTypeSignature, Error> {
+        self.expect(b'L')?;
+        let mut package = String::new();
+        let mut name = self.identifier()?;
+        while self.peek() == Some(b'/') {
+            self.pos += 1;
+            if !package.is_empty() {
+                package.push('/');
+            }
+            package.push_str(&name);
+            name = self.identifier()?;
+        }
+        let mut classes = vec![SimpleClassTypeSignature {
+            name,
+            type_arguments: self.type_arguments()?,
+        }];
+        while self.peek() == Some(b'.') {
+            self.pos += 1;
+            let name = self.identifier()?;
+            classes.push(SimpleClassTypeSignature {
+                name,
+                type_arguments: self.type_arguments()?,
+            });
+        }
+        self.expect(b';')?;
+        Ok(ClassTypeSignature { package, classes })
+    }
+
+    fn type_arguments(&mut self) -> Result<Vec<TypeArgument>, Error> {
+        let mut type_arguments: Vec<TypeArgument> = vec![];
+        if self.peek() != Some(b'<') {
+            return Ok(type_arguments);
+        }
+        self.pos += 1;
+        while self.peek() != Some(b'>') {
+            let type_argument = match self.peek() {
+                Some(b'*') => {
+                    self.pos += 1;
+                    TypeArgument::Any
+                }
+                Some(b'+') => {
+                    self.pos += 1;
+                    TypeArgument::Extends(self.reference_type()?)
+                }
+                Some(b'-') => {
+                    self.pos += 1;
+                    TypeArgument::Super(self.reference_type()?)
+                }
+                _ => TypeArgument::Exact(self.reference_type()?),
+            };
+            type_arguments.push(type_argument);
+        }
+        self.expect(b'>')?;
+        if type_arguments.is_empty() {
+            return Err(self.error());
+        }
+        Ok(type_arguments)
+    }
+}
+
+impl Display for TypeSignature {
+    /// Java source form, e.g. `java.util.List<? extends T>[]`
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeSignature::Base(base_type) => write!(f, "{}", base_type.name()),
+            TypeSignature::Class(class_type) => write!(f, "{}", class_type),
+            TypeSignature::TypeVariable(name) => write!(f, "{}", name),
+            TypeSignature::Array(component) => write!(f, "{}[]", component),
+        }
+    }
+}
+
+impl Display for ClassTypeSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if !self.package.is_empty() {
+            write!(f, "{}.", self.package.replace('/', "."))?;
+        }
+        for (i, class) in self.classes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", class.name)?;
+            if !class.type_arguments.is_empty() {
+                write!(f, "<")?;
+                for (j, type_argument) in class.type_arguments.iter().enumerate() {
+                    if j > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", type_argument)?;
+                }
+                write!(f, ">")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for TypeArgument {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeArgument::Any => write!(f, "?"),
+            TypeArgument::Extends(bound) => write!(f, "? extends {}", bound),
+            TypeArgument::Super(bound) => write!(f, "? super {}", bound),
+            TypeArgument::Exact(type_signature) => write!(f, "{}", type_signature),
+        }
+    }
+}
+
+impl Display for TypeParameter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        let bounds: Vec<&TypeSignature> = self
+            .class_bound
+            .iter()
+            .chain(self.interface_bounds.iter())
+            .collect();
+        for (i, bound) in bounds.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " extends " } else { " & " }, bound)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::signature::{ClassSignature, MethodSignature, TypeArgument, TypeSignature};
+
+    #[test]
+    fn parse_generic_signatures() {
+        let class = ClassSignature::parse(
+            b"<K:Ljava/lang/Object;V::Ljava/lang/Comparable<-TV;>;>Ljava/util/AbstractMap<TK;TV;>;Ljava/io/Serializable;",
+        )
+        .unwrap();
+        assert_eq!(class.type_parameters.len(), 2);
+        assert!(class.type_parameters[1].class_bound.is_none());
+        assert_eq!(
+            class.type_parameters[1].to_string(),
+            "V extends java.lang.Comparable<? super V>"
+        );
+        assert_eq!(class.super_class.internal_name(), "java/util/AbstractMap");
+        assert_eq!(class.interfaces.len(), 1);
+
+        let method =
+            MethodSignature::parse(b"<T:Ljava/lang/Object;>(Ljava/util/List<+TT;>;[I)TT;^TE;")
+                .unwrap();
+        assert_eq!(
+            method.parameters[0].to_string(),
+            "java.util.List<? extends T>"
+        );
+        assert_eq!(method.parameters[1].to_string(), "int[]");
+        assert_eq!(
+            method.throws,
+            vec![TypeSignature::TypeVariable("E".to_string())]
+        );
+
+        let nested = TypeSignature::parse(b"Lp/Outer<TT;>.Inner<*>;").unwrap();
+        match &nested {
+            TypeSignature::Class(class_type) => {
+                assert_eq!(class_type.internal_name(), "p/Outer$Inner");
+                assert_eq!(
+                    class_type.classes[1].type_arguments,
+                    vec![TypeArgument::Any]
+                );
+            }
+            _ => panic!("expected a class type"),
+        }
+        assert_eq!(nested.to_string(), "p.Outer<T>.Inner<?>");
+        assert!(TypeSignature::parse(b"Ljava/util/List<>;").is_err());
+    }
+}
@@ -0,0 +1,397 @@
+use crate::error::Error;
+use std::convert::TryFrom;
+
+/// Source map (SMAP) stored in the `SourceDebugExtension` attribute, see JSR-45.
+///
+///```jvm
+/// SMAP
+/// <output file name>
+/// <default stratum id>
+/// *S <stratum id>
+/// *F
+/// + 1 <file name>
+/// <absolute file name>
+/// 2 <file name>
+/// *L
+/// <input start line>[#<line file id>][,<repeat count>]:<output start line>[,<output line increment>]
+/// *E
+///```
+///
+/// Kotlin emits one `*E` per stratum, so an end section followed by another
+/// stratum section is accepted.
+#[derive(Debug, Clone)]
+pub struct Smap {
+    pub output_file_name: String,
+    pub default_stratum: String,
+    pub strata: Vec<Stratum>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stratum {
+    pub id: String,
+    pub files: Vec<FileInfo>,
+    pub lines: Vec<LineInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    pub id: u32,
+    pub name: String,
+    /// Only present when the entry was introduced with `+`
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineInfo {
+    pub input_start_line: u32,
+    /// Already resolved against the previous line info when omitted
+    pub line_file_id: u32,
+    pub repeat_count: u32,
+    pub output_start_line: u32,
+    pub output_line_increment: u32,
+}
+
+/// Source file and line an output line maps to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub file_name: String,
+    pub path: Option<String>,
+    pub line: u32,
+}
+
+impl LineInfo {
+    /// Returns the input line for `output_line` if this entry covers it
+    pub fn input_line(&self, output_line: u32) -> Option<u32> {
+        if output_line < self.output_start_line {
+            return None;
+        }
+        let delta = output_line - self.output_start_line;
+        if self.output_line_increment == 0 {
+            // every input line collapses onto the same output line
+            return if delta == 0 {
+                Some(self.input_start_line)
+            } else {
+                None
+            };
+        }
+        let index = delta / self.output_line_increment;
+        if index < self.repeat_count {
+            self.input_start_line.checked_add(index)
+        } else {
+            None
+        }
+    }
+
+    /// Output line range `[start, end)` of the `n`-th repeated input line, none
+    /// when it lies past the last `u32` line
+    pub fn output_range(&self, n: u32) -> Option<(u32, u32)> {
+        let start = n
+            .checked_mul(self.output_line_increment)?
+            .checked_add(self.output_start_line)?;
+        Some((start, start.checked_add(self.output_line_increment.max(1))?))
+    }
+}
+
+impl Stratum {
+    pub fn file(&self, id: u32) -> Option<&FileInfo> {
+        self.files.iter().find(|file| file.id == id)
+    }
+
+    /// Maps a line of the generated class file to a line of an input source.
+    /// The first matching line info wins, as required by JSR-45.
+    pub fn map_line(&self, output_line: u32) -> Option<SourcePosition> {
+        self.lines.iter().find_map(|line_info| {
+            let line = line_info.input_line(output_line)?;
+            let file = self.file(line_info.line_file_id)?;
+            Some(SourcePosition {
+                file_name: file.name.clone(),
+                path: file.path.clone(),
+                line,
+            })
+        })
+    }
+
+    /// Reverse mapping, returns every output line range generated from `line` of `file_name`
+    pub fn output_lines(&self, file_name: &str, line: u32) -> Vec<(u32, u32)> {
+        let ids: Vec<u32> = self
+            .files
+            .iter()
+            .filter(|file| file.name == file_name)
+            .map(|file| file.id)
+            .collect();
+        self.lines
+            .iter()
+            .filter(|line_info| ids.contains(&line_info.line_file_id))
+            .filter_map(|line_info| {
+                let n = line.checked_sub(line_info.input_start_line)?;
+                if n >= line_info.repeat_count {
+                    return None;
+                }
+                line_info.output_range(n)
+            })
+            .collect()
+    }
+}
+
+impl Smap {
+    pub fn parse(source: &str) -> Result<Smap, Error> {
+        let mut lines = source.lines().map(|line| line.trim_end_matches('\r'));
+        if lines.next() != Some("SMAP") {
+            return Err(Error::InvalidSmap("missing SMAP header".to_string()));
+        }
+        let output_file_name = lines
+            .next()
+            .ok_or_else(|| Error::InvalidSmap("missing output file name".to_string()))?
+            .to_string();
+        let default_stratum = lines
+            .next()
+            .ok_or_else(|| Error::InvalidSmap("missing default stratum".to_string()))?
+            .trim()
+            .to_string();
+
+        let mut strata: Vec<Stratum> = vec![];
+        let mut section = Section::None;
+        let mut line_file_id: u32 = 0;
+        while let Some(line) = lines.next() {
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('*') {
+                let mut chars = header.chars();
+                let kind = chars.next();
+                let rest = chars.as_str();
+                section = match kind {
+                    Some('S') => {
+                        strata.push(Stratum {
+                            id: rest.trim().to_string(),
+                            files: vec![],
+                            lines: vec![],
+                        });
+                        line_file_id = 0;
+                        Section::None
+                    }
+                    Some('F') => Section::File,
+                    Some('L') => Section::Line,
+                    Some('V') => Section::Vendor,
+                    Some('E') => Section::None,
+                    Some('O') | Some('C') => {
+                        return Err(Error::InvalidSmap("unresolved embedded SMAP".to_string()))
+                    }
+                    // unknown sections must be ignored
+                    _ => Section::Vendor,
+                };
+                continue;
+            }
+            match section {
+                Section::File => {
+                    let stratum = current_stratum(&mut strata)?;
+                    let (with_path, entry) = match line.strip_prefix('+') {
+                        Some(entry) => (true, entry),
+                        None => (false, line),
+                    };
+                    let entry = entry.trim_start();
+                    let (id, name) = entry.split_at(entry.find(' ').unwrap_or(entry.len()));
+                    let id = parse_number(id)?;
+                    let path = if with_path {
+                        let path = lines.next().ok_or_else(|| {
+                            Error::InvalidSmap(format!("missing path of file {}", id))
+                        })?;
+                        Some(path.trim().to_string())
+                    } else {
+                        None
+                    };
+                    stratum.files.push(FileInfo {
+                        id,
+                        name: name.trim().to_string(),
+                        path,
+                    });
+                }
+                Section::Line => {
+                    let stratum = current_stratum(&mut strata)?;
+                    let line_info = parse_line_info(line, line_file_id)?;
+                    line_file_id = line_info.line_file_id;
+                    stratum.lines.push(line_info);
+                }
+                Section::Vendor => {}
+                Section::None => {
+                    return Err(Error::InvalidSmap(format!("unexpected line: {}", line)));
+                }
+            }
+        }
+        if strata.is_empty() {
+            return Err(Error::InvalidSmap("no stratum section".to_string()));
+        }
+        Ok(Smap {
+            output_file_name,
+            default_stratum,
+            strata,
+        })
+    }
+
+    pub fn stratum(&self, id: &str) -> Option<&Stratum> {
+        self.strata.iter().find(|stratum| stratum.id == id)
+    }
+
+    pub fn default_stratum(&self) -> Option<&Stratum> {
+        self.stratum(&self.default_stratum)
+    }
+
+    /// Maps `output_line` using the default stratum. The `Java` stratum is the
+    /// identity mapping and never has a section of its own.
+    pub fn map_line(&self, output_line: u32) -> Option<SourcePosition> {
+        self.map_line_in(&self.default_stratum, output_line)
+    }
+
+    pub fn map_line_in(&self, stratum: &str, output_line: u32) -> Option<SourcePosition> {
+        if stratum == "Java" {
+            return Some(SourcePosition {
+                file_name: self.output_file_name.clone(),
+                path: None,
+                line: output_line,
+            });
+        }
+        self.stratum(stratum)?.map_line(output_line)
+    }
+}
+
+impl TryFrom<&[u8]> for Smap {
+    type Error = Error;
+
+    fn try_from(debug_extension: &[u8]) -> Result<Self, Self::Error> {
+        let source = std::str::from_utf8(debug_extension)
+            .map_err(|e| Error::InvalidString(e.to_string()))?;
+        Smap::parse(source)
+    }
+}
+
+enum Section {
+    None,
+    File,
+    Line,
+    Vendor,
+}
+
+fn current_stratum(strata: &mut [Stratum]) -> Result<&mut Stratum, Error> {
+    strata
+        .last_mut()
+        .ok_or_else(|| Error::InvalidSmap("section outside of a stratum".to_string()))
+}
+
+fn parse_number(s: &str) -> Result<u32, Error> {
+    s.trim()
+        .parse::<u32>()
+        .map_err(|_| Error::InvalidSmap(format!("invalid number: {}", s)))
+}
+
+/// `InputStartLine[#LineFileID][,RepeatCount]:OutputStartLine[,OutputLineIncrement]`
+fn parse_line_info(line: &str, last_file_id: u32) -> Result<LineInfo, Error> {
+    let colon = line
+        .find(':')
+        .ok_or_else(|| Error::InvalidSmap(format!("invalid line info: {}", line)))?;
+    let (input, output) = (&line[..colon], &line[colon + 1..]);
+
+    let (input, repeat_count) = match input.find(',') {
+        Some(comma) => (&input[..comma], parse_number(&input[comma + 1..])?),
+        None => (input, 1),
+    };
+    let (input_start_line, line_file_id) = match input.find('#') {
+        Some(hash) => (
+            parse_number(&input[..hash])?,
+            parse_number(&input[hash + 1..])?,
+        ),
+        None => (parse_number(input)?, last_file_id),
+    };
+    let (output_start_line, output_line_increment) = match output.find(',') {
+        Some(comma) => (
+            parse_number(&output[..comma])?,
+            parse_number(&output[comma + 1..])?,
+        ),
+        None => (parse_number(output)?, 1),
+    };
+    Ok(LineInfo {
+        input_start_line,
+        line_file_id,
+        repeat_count,
+        output_start_line,
+        output_line_increment,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::smap::{Smap, SourcePosition};
+
+    const KOTLIN_SMAP: &str = "SMAP
+Foo.kt
+Kotlin
+*S Kotlin
+*F
++ 1 Foo.kt
+com/example/FooKt
++ 2 Inline.kt
+com/example/InlineKt
+*L
+1#1,20:1
+7#2,3:21,2
+*E
+*S KotlinDebug
+*F
++ 1 Foo.kt
+com/example/FooKt
+*L
+12#1:21,6
+*E
+";
+
+    #[test]
+    fn map_kotlin_inline_lines() {
+        let smap = Smap::parse(KOTLIN_SMAP).unwrap();
+        assert_eq!(smap.output_file_name, "Foo.kt");
+        assert_eq!(smap.strata.len(), 2);
+        assert_eq!(
+            smap.map_line(5),
+            Some(SourcePosition {
+                file_name: "Foo.kt".to_string(),
+                path: Some("com/example/FooKt".to_string()),
+                line: 5,
+            })
+        );
+        let inlined = smap.map_line(24).unwrap();
+        assert_eq!(inlined.file_name, "Inline.kt");
+        assert_eq!(inlined.line, 8);
+        assert_eq!(smap.map_line(27), None);
+        assert_eq!(smap.map_line_in("KotlinDebug", 24).unwrap().line, 12);
+        assert_eq!(smap.map_line_in("Java", 24).unwrap().line, 24);
+
+        let stratum = smap.default_stratum().unwrap();
+        assert_eq!(stratum.output_lines("Inline.kt", 9), vec![(25, 27)]);
+    }
+
+    #[test]
+    fn overflowing_line_numbers_have_no_mapping() {
+        let smap = Smap::parse(
+            "SMAP\nA.java\nJSP\n*S JSP\n*F\n0 a.jsp\n*L\n4294967290,100:1\n1,10:4294967290,5\n*E\n",
+        )
+        .unwrap();
+        assert_eq!(smap.map_line(1).unwrap().line, 4294967290);
+        assert_eq!(smap.map_line(10), None);
+        let stratum = smap.default_stratum().unwrap();
+        assert_eq!(stratum.output_lines("a.jsp", 4294967290), vec![(1, 2)]);
+        assert_eq!(
+            stratum.output_lines("a.jsp", 1),
+            vec![(4294967290, 4294967295)]
+        );
+        assert_eq!(stratum.output_lines("a.jsp", 3), vec![]);
+    }
+
+    #[test]
+    fn line_file_id_is_inherited() {
+        let smap = Smap::parse(
+            "SMAP\nindex_jsp.java\nJSP\n*S JSP\n*F\n0 index.jsp\n1 header.jsp\n*L\n1#1,2:10\n3:12\n5#0:20\n*E\n",
+        )
+        .unwrap();
+        assert_eq!(smap.map_line(12).unwrap().file_name, "header.jsp");
+        assert_eq!(smap.map_line(20).unwrap().file_name, "index.jsp");
+        assert!(Smap::parse("SMAP\nA.java\nJSP\n*O JSP\n").is_err());
+    }
+}
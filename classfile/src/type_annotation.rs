@@ -0,0 +1,838 @@
+use crate::attribute::{
+    Attribute, AttributeType, CodeAttribute, TargetInfo, TypeAnnotation, TypePath,
+};
+use crate::class_file::ClassFile;
+use crate::constant::{get_class_name, get_utf8};
+use crate::descriptor::{FieldType, MethodDescriptor};
+use crate::error::Error;
+use crate::field::FieldInfo;
+use crate::method::MethodInfo;
+use crate::signature::{
+    ClassSignature, ClassTypeSignature, MethodSignature, SimpleClassTypeSignature, TypeArgument,
+    TypeParameter, TypeSignature,
+};
+use std::collections::HashMap;
+
+const ACC_STATIC: u16 = 0x0008;
+
+/// Kind of target denoted by `target_type`, see the tables on [`TypeAnnotation`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    ClassTypeParameter,
+    MethodTypeParameter,
+    Supertype,
+    ClassTypeParameterBound,
+    MethodTypeParameterBound,
+    /// field or record component
+    Field,
+    /// return type of a method, or the type of the object built by a constructor
+    MethodReturn,
+    MethodReceiver,
+    MethodFormalParameter,
+    Throws,
+    LocalVariable,
+    ResourceVariable,
+    ExceptionParameter,
+    InstanceOf,
+    New,
+    ConstructorReference,
+    MethodReference,
+    Cast,
+    ConstructorInvocationTypeArgument,
+    MethodInvocationTypeArgument,
+    ConstructorReferenceTypeArgument,
+    MethodReferenceTypeArgument,
+}
+
+/// Structure whose attributes may hold a type annotation of a given kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetLocation {
+    ClassFile,
+    FieldInfo,
+    MethodInfo,
+    Code,
+}
+
+impl TargetKind {
+    pub fn from_target_type(target_type: u8) -> Result<TargetKind, Error> {
+        let kind = match target_type {
+            0x00 => TargetKind::ClassTypeParameter,
+            0x01 => TargetKind::MethodTypeParameter,
+            0x10 => TargetKind::Supertype,
+            0x11 => TargetKind::ClassTypeParameterBound,
+            0x12 => TargetKind::MethodTypeParameterBound,
+            0x13 => TargetKind::Field,
+            0x14 => TargetKind::MethodReturn,
+            0x15 => TargetKind::MethodReceiver,
+            0x16 => TargetKind::MethodFormalParameter,
+            0x17 => TargetKind::Throws,
+            0x40 => TargetKind::LocalVariable,
+            0x41 => TargetKind::ResourceVariable,
+            0x42 => TargetKind::ExceptionParameter,
+            0x43 => TargetKind::InstanceOf,
+            0x44 => TargetKind::New,
+            0x45 => TargetKind::ConstructorReference,
+            0x46 => TargetKind::MethodReference,
+            0x47 => TargetKind::Cast,
+            0x48 => TargetKind::ConstructorInvocationTypeArgument,
+            0x49 => TargetKind::MethodInvocationTypeArgument,
+            0x4A => TargetKind::ConstructorReferenceTypeArgument,
+            0x4B => TargetKind::MethodReferenceTypeArgument,
+            _ => return Err(Error::InvalidTargetType(target_type)),
+        };
+        Ok(kind)
+    }
+
+    pub fn location(&self) -> TargetLocation {
+        match self {
+            TargetKind::ClassTypeParameter
+            | TargetKind::Supertype
+            | TargetKind::ClassTypeParameterBound => TargetLocation::ClassFile,
+            TargetKind::Field => TargetLocation::FieldInfo,
+            TargetKind::MethodTypeParameter
+            | TargetKind::MethodTypeParameterBound
+            | TargetKind::MethodReturn
+            | TargetKind::MethodReceiver
+            | TargetKind::MethodFormalParameter
+            | TargetKind::Throws => TargetLocation::MethodInfo,
+            _ => TargetLocation::Code,
+        }
+    }
+}
+
+/// One step of a `type_path`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypePathStep {
+    /// 0: deeper in an array type
+    Array,
+    /// 1: deeper in a nested type
+    Nested,
+    /// 2: on the bound of a wildcard type argument
+    WildcardBound,
+    /// 3: on the type argument with the given index
+    TypeArgument(u8),
+}
+
+impl TypePath {
+    pub fn steps(&self) -> Result<Vec<TypePathStep>, Error> {
+        self.path
+            .iter()
+            .map(|(type_path_kind, type_argument_index)| {
+                match (*type_path_kind, *type_argument_index) {
+                    (0, 0) => Ok(TypePathStep::Array),
+                    (1, 0) => Ok(TypePathStep::Nested),
+                    (2, 0) => Ok(TypePathStep::WildcardBound),
+                    (3, index) => Ok(TypePathStep::TypeArgument(index)),
+                    _ => Err(Error::InvalidTypePath),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The part of a type selected by a `type_path`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnnotatedType {
+    Type(TypeSignature),
+    /// A wildcard type argument, `type_path_kind` 2 moves on to its bound
+    Wildcard(TypeArgument),
+    /// Declaration of a type parameter, targets 0x00 and 0x01
+    TypeParameter(TypeParameter),
+}
+
+/// Live range of a local variable from a `localvar_target`, completed
+/// with `LocalVariableTable` and `LocalVariableTypeTable` where present
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalVariableRange {
+    pub start_pc: u16,
+    pub length: u16,
+    pub index: u16,
+    pub name: Option<String>,
+    pub variable_type: Option<TypeSignature>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedTypeAnnotation<'a> {
+    pub annotation: &'a TypeAnnotation,
+    /// Comes from a `RuntimeVisibleTypeAnnotations` attribute
+    pub visible: bool,
+    pub kind: TargetKind,
+    /// Type denoted by the target before the `type_path` is applied. None when
+    /// the class file does not record it, e.g. explicit type arguments of calls.
+    pub target_type: Option<TypeSignature>,
+    /// Part of `target_type` the annotation applies to
+    pub annotated_type: Option<AnnotatedType>,
+    /// Ranges of the annotated variable for local and resource variable targets
+    pub local_variables: Vec<LocalVariableRange>,
+}
+
+/// Interprets `target_type`, `target_info` and `type_path` of the type annotations
+/// of a class file against its descriptors, `Signature`s and local variable tables.
+///
+/// Nested types are told apart using the `InnerClasses` attribute: only inner
+/// (non-static) classes are a separate step of `type_path_kind` 1, as in
+/// `Outer.@A Inner` versus `java.util.Map.@A Entry`.
+pub struct TypeAnnotationResolver<'a> {
+    class_file: &'a ClassFile,
+    class_signature: Option<ClassSignature>,
+    /// inner class -> immediately enclosing class, for non-static member classes
+    enclosing_classes: HashMap<String, String>,
+}
+
+enum Position {
+    Type(TypeSignature),
+    /// class type split into nesting levels, and the selected level
+    Class(ClassTypeSignature, usize),
+    Wildcard(TypeArgument),
+}
+
+enum Context<'a> {
+    Class,
+    Field(&'a FieldInfo),
+    Method(&'a MethodInfo, Option<MethodSignature>),
+    Code(&'a CodeAttribute),
+}
+
+impl<'a> TypeAnnotationResolver<'a> {
+    pub fn new(class_file: &'a ClassFile) -> Result<Self, Error> {
+        let class_signature = match signature_index(&class_file.attributes) {
+            Some(index) => Some(ClassSignature::parse(get_utf8(
+                &class_file.constant_pool,
+                index as usize,
+            ))?),
+            None => None,
+        };
+        let mut enclosing_classes = HashMap::new();
+        for attribute in &class_file.attributes {
+            if let AttributeType::InnerClasses { classes } = &attribute.attr_type {
+                for class in classes {
+                    if class.outer_class_info_index == 0
+                        || class.inner_class_access_flags & ACC_STATIC != 0
+                    {
+                        continue;
+                    }
+                    let cp = &class_file.constant_pool;
+                    enclosing_classes.insert(
+                        utf8_string(get_class_name(cp, class.inner_class_info_index as usize)),
+                        utf8_string(get_class_name(cp, class.outer_class_info_index as usize)),
+                    );
+                }
+            }
+        }
+        Ok(TypeAnnotationResolver {
+            class_file,
+            class_signature,
+            enclosing_classes,
+        })
+    }
+
+    /// Type annotations on the class declaration
+    pub fn class_annotations(&self) -> Result<Vec<ResolvedTypeAnnotation<'a>>, Error> {
+        self.resolve_all(&self.class_file.attributes, &Context::Class)
+    }
+
+    pub fn field_annotations(
+        &self,
+        field: &'a FieldInfo,
+    ) -> Result<Vec<ResolvedTypeAnnotation<'a>>, Error> {
+        self.resolve_all(&field.attributes, &Context::Field(field))
+    }
+
+    /// Type annotations on the method declaration followed by those of its body
+    pub fn method_annotations(
+        &self,
+        method: &'a MethodInfo,
+    ) -> Result<Vec<ResolvedTypeAnnotation<'a>>, Error> {
+        let method_signature = match signature_index(&method.attributes) {
+            Some(index) => Some(MethodSignature::parse(self.utf8(index))?),
+            None => None,
+        };
+        let mut resolved = self.resolve_all(
+            &method.attributes,
+            &Context::Method(method, method_signature),
+        )?;
+        if let Some(code) = method.get_code_attr() {
+            resolved.extend(self.resolve_all(&code.attributes, &Context::Code(code))?);
+        }
+        Ok(resolved)
+    }
+
+    /// Follows `type_path` from `root`, see JVMS 4.7.20.2
+    pub fn apply_type_path(
+        &self,
+        root: &TypeSignature,
+        type_path: &TypePath,
+    ) -> Result<AnnotatedType, Error> {
+        let mut position = self.position(root.clone());
+        for step in type_path.steps()? {
+            position = match (step, position) {
+                (TypePathStep::Array, Position::Type(TypeSignature::Array(component))) => {
+                    self.position(*component)
+                }
+                (TypePathStep::Nested, Position::Class(class_type, level))
+                    if level + 1 < class_type.classes.len() =>
+                {
+                    Position::Class(class_type, level + 1)
+                }
+                (TypePathStep::TypeArgument(index), Position::Class(class_type, level)) => {
+                    match class_type.classes[level].type_arguments.get(index as usize) {
+                        Some(TypeArgument::Exact(type_signature)) => {
+                            self.position(type_signature.clone())
+                        }
+                        Some(wildcard) => Position::Wildcard(wildcard.clone()),
+                        None => return Err(Error::InvalidTypePath),
+                    }
+                }
+                (TypePathStep::WildcardBound, Position::Wildcard(TypeArgument::Extends(bound)))
+                | (TypePathStep::WildcardBound, Position::Wildcard(TypeArgument::Super(bound))) => {
+                    self.position(bound)
+                }
+                _ => return Err(Error::InvalidTypePath),
+            };
+        }
+        Ok(match position {
+            Position::Type(type_signature) => AnnotatedType::Type(type_signature),
+            Position::Class(class_type, level) => {
+                AnnotatedType::Type(TypeSignature::Class(ClassTypeSignature {
+                    package: class_type.package,
+                    classes: class_type.classes[..=level].to_vec(),
+                }))
+            }
+            Position::Wildcard(wildcard) => AnnotatedType::Wildcard(wildcard),
+        })
+    }
+
+    /// Class types start at their outermost level of nesting
+    fn position(&self, type_signature: TypeSignature) -> Position {
+        match type_signature {
+            TypeSignature::Class(class_type) => {
+                Position::Class(self.nesting_levels(&class_type), 0)
+            }
+            type_signature => Position::Type(type_signature),
+        }
+    }
+
+    /// Splits a class type into one part per level of inner class nesting.
+    /// Static member classes stay joined with `$` to their enclosing class.
+    fn nesting_levels(&self, class_type: &ClassTypeSignature) -> ClassTypeSignature {
+        let first = &class_type.classes[0];
+        let first_name = if class_type.package.is_empty() {
+            first.name.clone()
+        } else {
+            format!("{}/{}", class_type.package, first.name)
+        };
+        let mut chain = vec![first_name];
+        while let Some(enclosing) = self.enclosing_classes.get(&chain[0]) {
+            if chain.contains(enclosing) {
+                break;
+            }
+            chain.insert(0, enclosing.clone());
+        }
+        let mut classes: Vec<SimpleClassTypeSignature> = vec![];
+        let mut prefix = class_type.package.clone();
+        for (i, name) in chain.iter().enumerate() {
+            let simple_name = if prefix.is_empty() {
+                name.as_str()
+            } else {
+                name.strip_prefix(prefix.as_str())
+                    .map(|rest| rest.trim_start_matches(['/', '$']))
+                    .unwrap_or(name.as_str())
+            };
+            classes.push(SimpleClassTypeSignature {
+                name: simple_name.to_string(),
+                type_arguments: if i + 1 == chain.len() {
+                    first.type_arguments.clone()
+                } else {
+                    vec![]
+                },
+            });
+            prefix = name.clone();
+        }
+        classes.extend(class_type.classes.iter().skip(1).cloned());
+        ClassTypeSignature {
+            package: class_type.package.clone(),
+            classes,
+        }
+    }
+
+    fn resolve_all(
+        &self,
+        attributes: &'a [Attribute],
+        context: &Context<'a>,
+    ) -> Result<Vec<ResolvedTypeAnnotation<'a>>, Error> {
+        let mut resolved: Vec<ResolvedTypeAnnotation<'a>> = vec![];
+        for attribute in attributes {
+            let (annotations, visible) = match &attribute.attr_type {
+                AttributeType::RuntimeVisibleTypeAnnotations { annotations } => (annotations, true),
+                AttributeType::RuntimeInvisibleTypeAnnotations { annotations } => {
+                    (annotations, false)
+                }
+                _ => continue,
+            };
+            for annotation in annotations {
+                resolved.push(self.resolve(annotation, visible, context)?);
+            }
+        }
+        Ok(resolved)
+    }
+
+    fn resolve(
+        &self,
+        annotation: &'a TypeAnnotation,
+        visible: bool,
+        context: &Context<'a>,
+    ) -> Result<ResolvedTypeAnnotation<'a>, Error> {
+        let kind = TargetKind::from_target_type(annotation.target_type)?;
+        let location = match context {
+            Context::Class => TargetLocation::ClassFile,
+            Context::Field(_) => TargetLocation::FieldInfo,
+            Context::Method(..) => TargetLocation::MethodInfo,
+            Context::Code(_) => TargetLocation::Code,
+        };
+        if kind.location() != location {
+            return Err(Error::InvalidTargetType(annotation.target_type));
+        }
+        let mut local_variables: Vec<LocalVariableRange> = vec![];
+        let mut type_parameter: Option<TypeParameter> = None;
+        let target_type = match (&annotation.target_info, context) {
+            (TargetInfo::TypeParameterTarget(index), Context::Class) => {
+                type_parameter = self.class_type_parameters().get(*index as usize).cloned();
+                None
+            }
+            (TargetInfo::TypeParameterTarget(index), Context::Method(_, signature)) => {
+                type_parameter = signature
+                    .as_ref()
+                    .and_then(|signature| signature.type_parameters.get(*index as usize))
+                    .cloned();
+                None
+            }
+            (TargetInfo::SupertypeTarget(index), Context::Class) => self.supertype(*index)?,
+            (
+                TargetInfo::TypeParameterBoundTarget {
+                    type_parameter_index,
+                    bound_index,
+                },
+                _,
+            ) => {
+                let type_parameters = match context {
+                    Context::Method(_, Some(signature)) => signature.type_parameters.as_slice(),
+                    Context::Method(_, None) => &[],
+                    _ => self.class_type_parameters(),
+                };
+                let type_parameter = type_parameters
+                    .get(*type_parameter_index as usize)
+                    .ok_or(Error::InvalidTargetInfo)?;
+                let bound = match *bound_index {
+                    0 => type_parameter.class_bound.as_ref(),
+                    i => type_parameter.interface_bounds.get(i as usize - 1),
+                };
+                Some(bound.ok_or(Error::InvalidTargetInfo)?.clone())
+            }
+            (TargetInfo::EmptyTarget, Context::Field(field)) => Some(self.field_type(field)?),
+            (TargetInfo::EmptyTarget, Context::Method(method, signature)) => match kind {
+                TargetKind::MethodReceiver => Some(self.this_type()),
+                _ if self.utf8(method.name_index) == b"<init>" => Some(self.this_type()),
+                _ => match signature {
+                    Some(signature) => signature.result.clone(),
+                    None => self
+                        .method_descriptor(method)?
+                        .return_type
+                        .as_ref()
+                        .map(TypeSignature::from),
+                },
+            },
+            (TargetInfo::FormalParameterTarget(index), Context::Method(method, signature)) => {
+                let index = *index as usize;
+                match signature {
+                    Some(signature) => signature.parameters.get(index).cloned(),
+                    None => self
+                        .method_descriptor(method)?
+                        .parameters
+                        .get(index)
+                        .map(TypeSignature::from),
+                }
+            }
+            (TargetInfo::ThrowTarget(index), Context::Method(method, signature)) => {
+                let index = *index as usize;
+                match signature {
+                    Some(signature) if !signature.throws.is_empty() => {
+                        signature.throws.get(index).cloned()
+                    }
+                    _ => exception_index(method, index)
+                        .map(|class_index| self.class_type(class_index)),
+                }
+            }
+            (TargetInfo::LocalVarTarget(table), Context::Code(code)) => {
+                local_variables = table
+                    .iter()
+                    .map(|local_var| {
+                        self.local_variable(
+                            code,
+                            local_var.start_pc,
+                            local_var.length,
+                            local_var.index,
+                        )
+                    })
+                    .collect();
+                local_variables
+                    .iter()
+                    .find_map(|local_variable| local_variable.variable_type.clone())
+            }
+            (TargetInfo::CatchTarget(index), Context::Code(code)) => {
+                let exception = code
+                    .exception_table
+                    .get(*index as usize)
+                    .ok_or(Error::InvalidTargetInfo)?;
+                match exception.catch_type {
+                    0 => None,
+                    catch_type => Some(self.class_type(catch_type)),
+                }
+            }
+            (TargetInfo::OffsetTarget(offset), Context::Code(code)) => {
+                self.instruction_type(code, *offset)
+            }
+            (TargetInfo::TypeArgumentTarget { offset, .. }, Context::Code(code)) => {
+                match kind {
+                    TargetKind::Cast => self.instruction_type(code, *offset),
+                    // explicit type arguments of calls are erased
+                    _ => None,
+                }
+            }
+            _ => return Err(Error::InvalidTargetInfo),
+        };
+
+        let annotated_type = match type_parameter {
+            Some(type_parameter) => {
+                if !annotation.type_path.path.is_empty() {
+                    return Err(Error::InvalidTypePath);
+                }
+                Some(AnnotatedType::TypeParameter(type_parameter))
+            }
+            None => match &target_type {
+                Some(target_type) => {
+                    Some(self.apply_type_path(target_type, &annotation.type_path)?)
+                }
+                None => None,
+            },
+        };
+        Ok(ResolvedTypeAnnotation {
+            annotation,
+            visible,
+            kind,
+            target_type,
+            annotated_type,
+            local_variables,
+        })
+    }
+
+    fn utf8(&self, index: u16) -> &[u8] {
+        get_utf8(&self.class_file.constant_pool, index as usize)
+    }
+
+    fn class_type_parameters(&self) -> &[TypeParameter] {
+        match &self.class_signature {
+            Some(signature) => &signature.type_parameters,
+            None => &[],
+        }
+    }
+
+    /// Type of a `CONSTANT_Class_info`, which names an array type by its descriptor
+    fn class_type(&self, class_index: u16) -> TypeSignature {
+        let name = get_class_name(&self.class_file.constant_pool, class_index as usize);
+        if name.first() == Some(&b'[') {
+            if let Ok(field_type) = FieldType::parse(name) {
+                return TypeSignature::from(&field_type);
+            }
+        }
+        TypeSignature::Class(ClassTypeSignature::from_internal_name(&utf8_string(name)))
+    }
+
+    /// This class with its own type parameters as arguments, the receiver type
+    fn this_type(&self) -> TypeSignature {
+        let mut this_type = match self.class_type(self.class_file.this_class) {
+            TypeSignature::Class(class_type) => class_type,
+            _ => unreachable!(),
+        };
+        if let Some(class) = this_type.classes.last_mut() {
+            class.type_arguments = self
+                .class_type_parameters()
+                .iter()
+                .map(|type_parameter| {
+                    TypeArgument::Exact(TypeSignature::TypeVariable(type_parameter.name.clone()))
+                })
+                .collect();
+        }
+        TypeSignature::Class(this_type)
+    }
+
+    fn supertype(&self, index: u16) -> Result<Option<TypeSignature>, Error> {
+        if let Some(signature) = &self.class_signature {
+            let supertype = match index {
+                0xFFFF => Some(&signature.super_class),
+                i => signature.interfaces.get(i as usize),
+            };
+            return Ok(supertype.map(|class_type| TypeSignature::Class(class_type.clone())));
+        }
+        let class_index = match index {
+            0xFFFF => self.class_file.super_class,
+            i => *self
+                .class_file
+                .interfaces
+                .get(i as usize)
+                .ok_or(Error::InvalidTargetInfo)?,
+        };
+        Ok(Some(self.class_type(class_index)))
+    }
+
+    fn field_type(&self, field: &FieldInfo) -> Result<TypeSignature, Error> {
+        match signature_index(&field.attributes) {
+            Some(index) => TypeSignature::parse(self.utf8(index)),
+            None => Ok(TypeSignature::from(&FieldType::parse(
+                self.utf8(field.descriptor_index),
+            )?)),
+        }
+    }
+
+    fn method_descriptor(&self, method: &MethodInfo) -> Result<MethodDescriptor, Error> {
+        MethodDescriptor::parse(self.utf8(method.descriptor_index))
+    }
+
+    fn local_variable(
+        &self,
+        code: &CodeAttribute,
+        start_pc: u16,
+        length: u16,
+        index: u16,
+    ) -> LocalVariableRange {
+        let mut local_variable = LocalVariableRange {
+            start_pc,
+            length,
+            index,
+            name: None,
+            variable_type: None,
+        };
+        let covers = |entry_start: u16, entry_length: u16, entry_index: u16| {
+            entry_index == index
+                && entry_start <= start_pc
+                && start_pc as u32 + length as u32 <= entry_start as u32 + entry_length as u32
+        };
+        for attribute in &code.attributes {
+            match &attribute.attr_type {
+                AttributeType::LocalVariableTypeTable {
+                    local_variable_type_table,
+                } => {
+                    if let Some(entry) = local_variable_type_table
+                        .iter()
+                        .find(|entry| covers(entry.start_pc, entry.length, entry.index))
+                    {
+                        local_variable.name = Some(utf8_string(self.utf8(entry.name_index)));
+                        if let Ok(signature) =
+                            TypeSignature::parse(self.utf8(entry.signature_index))
+                        {
+                            local_variable.variable_type = Some(signature);
+                        }
+                    }
+                }
+                AttributeType::LocalVariableTable {
+                    local_variable_table,
+                } => {
+                    if let Some(entry) = local_variable_table
+                        .iter()
+                        .find(|entry| covers(entry.start_pc, entry.length, entry.index))
+                    {
+                        if local_variable.name.is_none() {
+                            local_variable.name = Some(utf8_string(self.utf8(entry.name_index)));
+                        }
+                        if local_variable.variable_type.is_none() {
+                            if let Ok(field_type) =
+                                FieldType::parse(self.utf8(entry.descriptor_index))
+                            {
+                                local_variable.variable_type =
+                                    Some(TypeSignature::from(&field_type));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        local_variable
+    }
+
+    /// Type operand of the instruction an offset_target points at
+    fn instruction_type(&self, code: &CodeAttribute, offset: u16) -> Option<TypeSignature> {
+        let offset = offset as usize;
+        let opcode = *code.code.get(offset)?;
+        let operand = || -> Option<u16> {
+            Some(u16::from_be_bytes([
+                *code.code.get(offset + 1)?,
+                *code.code.get(offset + 2)?,
+            ]))
+        };
+        match opcode {
+            // new, checkcast, instanceof, multianewarray
+            0xbb | 0xc0 | 0xc1 | 0xc5 => Some(self.class_type(operand()?)),
+            // anewarray
+            0xbd => Some(TypeSignature::Array(Box::new(self.class_type(operand()?)))),
+            // newarray
+            0xbc => {
+                let tag = match code.code.get(offset + 1)? {
+                    4 => b'Z',
+                    5 => b'C',
+                    6 => b'F',
+                    7 => b'D',
+                    8 => b'B',
+                    9 => b'S',
+                    10 => b'I',
+                    11 => b'J',
+                    _ => return None,
+                };
+                let field_type = FieldType::parse(&[b'[', tag]).ok()?;
+                Some(TypeSignature::from(&field_type))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn signature_index(attributes: &[Attribute]) -> Option<u16> {
+    attributes
+        .iter()
+        .find_map(|attribute| match attribute.attr_type {
+            AttributeType::Signature { signature_index } => Some(signature_index),
+            _ => None,
+        })
+}
+
+fn exception_index(method: &MethodInfo, index: usize) -> Option<u16> {
+    method
+        .attributes
+        .iter()
+        .find_map(|attribute| match &attribute.attr_type {
+            AttributeType::Exceptions {
+                exception_index_table,
+            } => exception_index_table.get(index).cloned(),
+            _ => None,
+        })
+}
+
+fn utf8_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::class_file::ClassFile;
+    use crate::constant::get_utf8;
+    use crate::type_annotation::{AnnotatedType, TargetKind, TypeAnnotationResolver};
+    use bytes::{BufMut, BytesMut};
+    use std::convert::TryFrom;
+
+    fn annotated(resolved: &AnnotatedType) -> String {
+        match resolved {
+            AnnotatedType::Type(type_signature) => type_signature.to_string(),
+            AnnotatedType::Wildcard(wildcard) => wildcard.to_string(),
+            AnnotatedType::TypeParameter(type_parameter) => type_parameter.to_string(),
+        }
+    }
+
+    #[test]
+    fn resolve_type_annotations() {
+        let bytes = std::fs::read("tests/TypeAnnotations.class").unwrap();
+        let mut buf = BytesMut::with_capacity(bytes.len());
+        buf.put_slice(bytes.as_slice());
+        let class_file = ClassFile::try_from(&mut buf).unwrap();
+        let resolver = TypeAnnotationResolver::new(&class_file).unwrap();
+
+        let class_annotations = resolver.class_annotations().unwrap();
+        let found: Vec<(TargetKind, String)> = class_annotations
+            .iter()
+            .map(|r| (r.kind, annotated(r.annotated_type.as_ref().unwrap())))
+            .collect();
+        assert!(found.contains(&(TargetKind::Supertype, "java.lang.String".to_string())));
+        assert!(found.contains(&(
+            TargetKind::ClassTypeParameter,
+            "T extends java.lang.Comparable<T>".to_string()
+        )));
+        assert!(found.contains(&(
+            TargetKind::ClassTypeParameterBound,
+            "java.lang.Comparable<T>".to_string()
+        )));
+
+        let field_annotations = |name: &[u8]| -> Vec<String> {
+            let field = class_file
+                .fields
+                .iter()
+                .find(|field| {
+                    get_utf8(&class_file.constant_pool, field.name_index as usize).as_slice()
+                        == name
+                })
+                .unwrap();
+            resolver
+                .field_annotations(field)
+                .unwrap()
+                .iter()
+                .map(|r| annotated(r.annotated_type.as_ref().unwrap()))
+                .collect()
+        };
+        assert_eq!(
+            field_annotations(b"entry"),
+            vec!["java.util.Map$Entry<java.lang.String, T>", "T"]
+        );
+        assert_eq!(
+            field_annotations(b"lists"),
+            vec![
+                "java.util.List<? extends java.lang.Number>[]",
+                "java.lang.Number"
+            ]
+        );
+        assert_eq!(
+            field_annotations(b"inner"),
+            vec!["io.github.iamazy.jvm.TypeAnnotations<T>.Inner"]
+        );
+
+        let join = class_file
+            .methods
+            .iter()
+            .find(|method| {
+                get_utf8(&class_file.constant_pool, method.name_index as usize).as_slice()
+                    == b"join"
+            })
+            .unwrap();
+        let method_annotations = resolver.method_annotations(join).unwrap();
+        let by_kind = |kind: TargetKind| {
+            method_annotations
+                .iter()
+                .find(|r| r.kind == kind)
+                .and_then(|r| r.annotated_type.as_ref())
+                .map(annotated)
+        };
+        assert_eq!(
+            by_kind(TargetKind::MethodReturn).unwrap(),
+            "java.lang.String"
+        );
+        assert_eq!(
+            by_kind(TargetKind::MethodFormalParameter).unwrap(),
+            "java.lang.String"
+        );
+        assert_eq!(
+            by_kind(TargetKind::Throws).unwrap(),
+            "java.lang.IllegalStateException"
+        );
+        assert_eq!(by_kind(TargetKind::New).unwrap(), "java.lang.StringBuilder");
+        assert_eq!(by_kind(TargetKind::InstanceOf).unwrap(), "java.lang.String");
+        assert_eq!(
+            by_kind(TargetKind::ExceptionParameter).unwrap(),
+            "java.lang.RuntimeException"
+        );
+        let local = method_annotations
+            .iter()
+            .find(|r| r.kind == TargetKind::LocalVariable)
+            .unwrap();
+        assert_eq!(local.local_variables[0].name.as_deref(), Some("builder"));
+        assert_eq!(
+            annotated(local.annotated_type.as_ref().unwrap()),
+            "java.lang.StringBuilder"
+        );
+    }
+}
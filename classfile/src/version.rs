@@ -0,0 +1,320 @@
+use crate::attribute::{Attribute, AttributeType};
+use crate::class_file::ClassFile;
+use crate::constant::{get_utf8, Constant, Tag};
+use crate::error::Error;
+use crate::ConstantPoolRef;
+use std::fmt::{self, Display, Formatter};
+
+pub const JAVA_1_1: u16 = 45;
+pub const JAVA_1_2: u16 = 46;
+pub const JAVA_1_3: u16 = 47;
+pub const JAVA_1_4: u16 = 48;
+pub const JAVA_5: u16 = 49;
+pub const JAVA_6: u16 = 50;
+pub const JAVA_7: u16 = 51;
+pub const JAVA_8: u16 = 52;
+pub const JAVA_9: u16 = 53;
+pub const JAVA_10: u16 = 54;
+pub const JAVA_11: u16 = 55;
+pub const JAVA_12: u16 = 56;
+pub const JAVA_13: u16 = 57;
+pub const JAVA_14: u16 = 58;
+pub const JAVA_15: u16 = 59;
+pub const JAVA_16: u16 = 60;
+pub const JAVA_17: u16 = 61;
+
+/// Minor version of a class file that depends on the preview features of its
+/// Java SE release, only meaningful from Java 12 on
+pub const PREVIEW_MINOR_VERSION: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClassFileVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl ClassFileVersion {
+    pub fn new(major: u16, minor: u16) -> ClassFileVersion {
+        ClassFileVersion { major, minor }
+    }
+
+    pub fn is_preview(&self) -> bool {
+        self.major >= JAVA_12 && self.minor == PREVIEW_MINOR_VERSION
+    }
+}
+
+impl Display for ClassFileVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Where an attribute appears, see JVMS table 4.7-C
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeLocation {
+    ClassFile,
+    FieldInfo,
+    MethodInfo,
+    Code,
+    RecordComponent,
+}
+
+/// Which class file versions are accepted, see JVMS 4.1.
+///
+/// A class file is rejected with `UnsupportedClassVersion` when its major version
+/// is below 45 or above `max_major_version`. From Java 12 on the minor version
+/// must be 0, or `0xFFFF` for a class file using preview features, which is only
+/// loaded when `enable_preview` is set and the class file targets exactly
+/// `max_major_version`, like `--enable-preview` on the reference implementation.
+#[derive(Debug, Clone)]
+pub struct VersionPolicy {
+    pub max_major_version: u16,
+    pub enable_preview: bool,
+    /// Reject attributes and constants introduced after the class file version
+    /// or appearing at a location the specification does not allow
+    pub check_attributes: bool,
+}
+
+impl Default for VersionPolicy {
+    fn default() -> Self {
+        VersionPolicy {
+            max_major_version: JAVA_17,
+            enable_preview: false,
+            check_attributes: true,
+        }
+    }
+}
+
+impl VersionPolicy {
+    pub fn new(max_major_version: u16) -> VersionPolicy {
+        VersionPolicy {
+            max_major_version,
+            ..VersionPolicy::default()
+        }
+    }
+
+    pub fn check_version(&self, version: ClassFileVersion) -> Result<(), Error> {
+        let unsupported = Err(Error::UnsupportedClassVersion(version.major, version.minor));
+        if version.major < JAVA_1_1 || version.major > self.max_major_version {
+            return unsupported;
+        }
+        let preview_enabled = version.minor == PREVIEW_MINOR_VERSION
+            && self.enable_preview
+            && version.major == self.max_major_version;
+        if version.major >= JAVA_12 && version.minor != 0 && !preview_enabled {
+            return unsupported;
+        }
+        Ok(())
+    }
+
+    /// Checks the version of `class_file`, and when `check_attributes` is set,
+    /// that every constant and attribute is defined for that version
+    pub fn validate(&self, class_file: &ClassFile) -> Result<(), Error> {
+        self.check_version(class_file.version())?;
+        if !self.check_attributes {
+            return Ok(());
+        }
+        let major = class_file.major_version;
+        let is_module = class_file.flags().is_module();
+        for constant in class_file.constant_pool.iter() {
+            check_constant(constant, major, is_module)?;
+        }
+
+        let constant_pool = &class_file.constant_pool;
+        check_attributes(
+            &class_file.attributes,
+            AttributeLocation::ClassFile,
+            major,
+            constant_pool,
+        )?;
+        for field in &class_file.fields {
+            check_attributes(
+                &field.attributes,
+                AttributeLocation::FieldInfo,
+                major,
+                constant_pool,
+            )?;
+        }
+        for method in &class_file.methods {
+            check_attributes(
+                &method.attributes,
+                AttributeLocation::MethodInfo,
+                major,
+                constant_pool,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// First major version defining the constant, see JVMS table 4.4-B
+pub fn constant_since(tag: Tag) -> u16 {
+    match tag {
+        Tag::MethodHandle | Tag::MethodType | Tag::InvokeDynamic => JAVA_7,
+        Tag::Module | Tag::Package => JAVA_9,
+        Tag::Dynamic => JAVA_11,
+        _ => JAVA_1_1,
+    }
+}
+
+/// First major version defining the attribute and the locations it may appear
+/// at, see JVMS tables 4.7-B and 4.7-C
+pub fn attribute_since(attr_type: &AttributeType) -> (u16, &'static [AttributeLocation]) {
+    use AttributeLocation::*;
+    match attr_type {
+        AttributeType::ConstantValue { .. } => (JAVA_1_1, &[FieldInfo]),
+        AttributeType::Code { .. } => (JAVA_1_1, &[MethodInfo]),
+        AttributeType::StackMapTable { .. } => (JAVA_6, &[Code]),
+        AttributeType::Exceptions { .. } => (JAVA_1_1, &[MethodInfo]),
+        AttributeType::InnerClasses { .. } => (JAVA_1_1, &[ClassFile]),
+        AttributeType::EnclosingMethod { .. } => (JAVA_5, &[ClassFile]),
+        AttributeType::Synthetic => (JAVA_1_1, &[ClassFile, FieldInfo, MethodInfo]),
+        AttributeType::Signature { .. } => {
+            (JAVA_5, &[ClassFile, FieldInfo, MethodInfo, RecordComponent])
+        }
+        AttributeType::SourceFile { .. } => (JAVA_1_1, &[ClassFile]),
+        AttributeType::SourceDebugExtension { .. } => (JAVA_5, &[ClassFile]),
+        AttributeType::LineNumberTable { .. } => (JAVA_1_1, &[Code]),
+        AttributeType::LocalVariableTable { .. } => (JAVA_1_1, &[Code]),
+        AttributeType::LocalVariableTypeTable { .. } => (JAVA_5, &[Code]),
+        AttributeType::Deprecated => (JAVA_1_1, &[ClassFile, FieldInfo, MethodInfo]),
+        AttributeType::RuntimeVisibleAnnotations { .. }
+        | AttributeType::RuntimeInvisibleAnnotations { .. } => {
+            (JAVA_5, &[ClassFile, FieldInfo, MethodInfo, RecordComponent])
+        }
+        AttributeType::RuntimeVisibleParameterAnnotations { .. }
+        | AttributeType::RuntimeInvisibleParameterAnnotations { .. } => (JAVA_5, &[MethodInfo]),
+        AttributeType::RuntimeVisibleTypeAnnotations { .. }
+        | AttributeType::RuntimeInvisibleTypeAnnotations { .. } => (
+            JAVA_8,
+            &[ClassFile, FieldInfo, MethodInfo, Code, RecordComponent],
+        ),
+        AttributeType::AnnotationDefault { .. } => (JAVA_5, &[MethodInfo]),
+        AttributeType::BootstrapMethods { .. } => (JAVA_7, &[ClassFile]),
+        AttributeType::MethodParameters { .. } => (JAVA_8, &[MethodInfo]),
+        AttributeType::Module { .. }
+        | AttributeType::ModulePackages { .. }
+        | AttributeType::ModuleMainClass { .. } => (JAVA_9, &[ClassFile]),
+        AttributeType::NestHost { .. } | AttributeType::NestMembers { .. } => {
+            (JAVA_11, &[ClassFile])
+        }
+        AttributeType::Record { .. } => (JAVA_16, &[ClassFile]),
+        AttributeType::PermittedSubclasses { .. } => (JAVA_17, &[ClassFile]),
+    }
+}
+
+fn check_constant(constant: &Constant, major: u16, is_module: bool) -> Result<(), Error> {
+    let tag = match constant.tag() {
+        Some(tag) => tag,
+        None => return Ok(()),
+    };
+    // CONSTANT_Module_info and CONSTANT_Package_info only live in module-info
+    let module_only = matches!(tag, Tag::Module | Tag::Package);
+    if major < constant_since(tag) || module_only && !is_module {
+        return Err(Error::UnsupportedConstant(tag.into(), major));
+    }
+    Ok(())
+}
+
+fn check_attributes(
+    attributes: &[Attribute],
+    location: AttributeLocation,
+    major: u16,
+    constant_pool: &ConstantPoolRef,
+) -> Result<(), Error> {
+    for attribute in attributes {
+        let name = || {
+            let name = get_utf8(constant_pool, attribute.attribute_name_index as usize);
+            String::from_utf8_lossy(name).into_owned()
+        };
+        let (since, locations) = attribute_since(&attribute.attr_type);
+        if major < since {
+            return Err(Error::UnsupportedAttribute(name(), major));
+        }
+        if !locations.contains(&location) {
+            return Err(Error::MisplacedAttribute(name()));
+        }
+        match &attribute.attr_type {
+            AttributeType::Code { code } => check_attributes(
+                &code.attributes,
+                AttributeLocation::Code,
+                major,
+                constant_pool,
+            )?,
+            AttributeType::Record { components } => {
+                for component in components {
+                    check_attributes(
+                        &component.attributes,
+                        AttributeLocation::RecordComponent,
+                        major,
+                        constant_pool,
+                    )?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::class_file::ClassFile;
+    use crate::error::Error;
+    use crate::version::{ClassFileVersion, VersionPolicy, JAVA_17, PREVIEW_MINOR_VERSION};
+    use bytes::BytesMut;
+
+    fn read(path: &str, minor: u16, major: u16) -> Vec<u8> {
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[4..6].copy_from_slice(&minor.to_be_bytes());
+        bytes[6..8].copy_from_slice(&major.to_be_bytes());
+        bytes
+    }
+
+    fn parse(bytes: &[u8], policy: &VersionPolicy) -> Result<ClassFile, Error> {
+        ClassFile::parse(&mut BytesMut::from(bytes), policy)
+    }
+
+    #[test]
+    fn version_policy() {
+        let policy = VersionPolicy::default();
+        assert!(parse(&read("tests/HelloWorld.class", 0, JAVA_17), &policy).is_ok());
+        assert!(matches!(
+            parse(&read("tests/HelloWorld.class", 0, JAVA_17 + 1), &policy),
+            Err(Error::UnsupportedClassVersion(62, 0))
+        ));
+
+        let preview = read("tests/HelloWorld.class", PREVIEW_MINOR_VERSION, JAVA_17);
+        assert!(parse(&preview, &policy).is_err());
+        let preview_policy = VersionPolicy {
+            enable_preview: true,
+            ..VersionPolicy::default()
+        };
+        let class_file = parse(&preview, &preview_policy).unwrap();
+        assert!(class_file.version().is_preview());
+        // preview features of an older release are never enabled
+        assert!(parse(
+            &preview,
+            &VersionPolicy {
+                max_major_version: JAVA_17 + 1,
+                ..preview_policy
+            }
+        )
+        .is_err());
+        assert!(VersionPolicy::default()
+            .check_version(ClassFileVersion::new(45, 3))
+            .is_ok());
+
+        // TypeAnnotations.class carries a StackMapTable and type annotations
+        let bytes = read("tests/TypeAnnotations.class", 0, 49);
+        assert!(matches!(
+            parse(&bytes, &policy),
+            Err(Error::UnsupportedAttribute(_, 49))
+        ));
+        let lenient = VersionPolicy {
+            check_attributes: false,
+            ..VersionPolicy::default()
+        };
+        assert!(parse(&bytes, &lenient).is_ok());
+    }
+}
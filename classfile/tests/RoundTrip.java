@@ -0,0 +1,28 @@
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.util.function.LongSupplier;
+
+public class RoundTrip {
+    @Retention(RetentionPolicy.RUNTIME)
+    @interface Tag {
+        String value();
+        int[] weights() default {1, 2};
+        RetentionPolicy policy() default RetentionPolicy.CLASS;
+    }
+
+    static final long BIG = 1L << 40;
+    static final double RATIO = 0.75d;
+
+    @Tag(value = "sum", weights = {3})
+    public static long sum(@Deprecated final long a, double b) throws Exception {
+        LongSupplier supplier = () -> a + BIG;
+        switch ((int) b) {
+            case 1:
+                return supplier.getAsLong();
+            case 7:
+                return (long) (b * RATIO);
+            default:
+                return a;
+        }
+    }
+}
@@ -0,0 +1,42 @@
+package io.github.iamazy.jvm;
+
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+import java.util.ArrayList;
+import java.util.List;
+import java.util.Map;
+
+// javac -g --release 8 TypeAnnotations.java
+@Target({ElementType.TYPE_USE, ElementType.TYPE_PARAMETER})
+@Retention(RetentionPolicy.RUNTIME)
+@interface NonNull {
+}
+
+public class TypeAnnotations<@NonNull T extends @NonNull Comparable<T>> extends ArrayList<@NonNull String> {
+
+    public class Inner {
+    }
+
+    public Map.@NonNull Entry<String, @NonNull T> entry;
+
+    public List<? extends @NonNull Number> @NonNull [] lists;
+
+    public TypeAnnotations<T>.@NonNull Inner inner;
+
+    public @NonNull String join(List<@NonNull String> parts) throws @NonNull IllegalStateException {
+        @NonNull StringBuilder builder = new @NonNull StringBuilder();
+        for (String part : parts) {
+            if (part instanceof @NonNull String) {
+                builder.append(part);
+            }
+        }
+        try {
+            builder.append((@NonNull Object) builder.length());
+        } catch (@NonNull RuntimeException e) {
+            return null;
+        }
+        return builder.toString();
+    }
+}
@@ -0,0 +1,86 @@
+import java.io.IOException;
+import java.util.ArrayList;
+import java.util.List;
+
+public class Verified implements Runnable {
+    private long total;
+    private final String[] names;
+
+    public Verified(int size) {
+        this(size > 0 ? new String[size] : null);
+    }
+
+    Verified(String[] names) {
+        super();
+        this.names = names;
+    }
+
+    public void run() {
+        total++;
+    }
+
+    static int loop(int n) {
+        int sum = 0;
+        for (int i = 0; i < n; i++) {
+            sum += i * 2;
+        }
+        return sum;
+    }
+
+    static double mix(long a, float b, double c) {
+        return a * b + c;
+    }
+
+    static String pick(Object o) {
+        if (o instanceof String) {
+            return (String) o;
+        }
+        if (o instanceof Number) {
+            return String.valueOf(((Number) o).intValue());
+        }
+        return null;
+    }
+
+    int guarded(String s) {
+        try {
+            return Integer.parseInt(s);
+        } catch (NumberFormatException | IllegalStateException e) {
+            return -1;
+        } finally {
+            total--;
+        }
+    }
+
+    static int select(int k) {
+        switch (k) {
+            case 1:
+                return 10;
+            case 2:
+                return 20;
+            case 100:
+                return 30;
+            default:
+                return 0;
+        }
+    }
+
+    static Object arrays(int n) {
+        int[][] grid = new int[n][n];
+        Object[] objects = new String[n];
+        grid[0][0] = objects.length;
+        long[] longs = {1L, 2L};
+        return n > 1 ? grid : longs;
+    }
+
+    synchronized List<String> list() {
+        List<String> list = new ArrayList<>();
+        for (String name : names) {
+            list.add(name);
+        }
+        return list;
+    }
+
+    static void fail() throws IOException {
+        throw new IOException("fail");
+    }
+}
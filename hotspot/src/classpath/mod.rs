@@ -1,5 +1,6 @@
 #[allow(dead_code)]
 use crate::sys::{FILE_SEP, PATH_SEP};
+use crate::runtime::instrument;
 use log::{error, trace};
 use once_cell::sync::Lazy;
 use std::fs::File;
@@ -12,8 +13,9 @@ static CLASS_PATH_MANAGER: Lazy<RwLock<ClassPathManager>> =
     Lazy::new(|| RwLock::new(ClassPathManager::new()));
 
 pub fn find_class(name: &str) -> Result<ClassPathResult, Error> {
-    let cpm = CLASS_PATH_MANAGER.read().unwrap();
-    cpm.search_class(name)
+    let mut result = CLASS_PATH_MANAGER.read().unwrap().search_class(name)?;
+    result.data = instrument::transform(name, result.data);
+    Ok(result)
 }
 
 pub fn add_path(path: &str) {
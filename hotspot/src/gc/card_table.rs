@@ -0,0 +1,46 @@
+use std::ops::Range;
+
+/// Old generation slots a card covers
+pub const CARD_SLOTS: usize = 64;
+
+const CLEAN_CARD: u8 = 0xff;
+const DIRTY_CARD: u8 = 0;
+
+/// One byte per `CARD_SLOTS` slots of the old generation. The write barrier
+/// dirties the card of an object a reference is stored in, so a young
+/// collection only scans the old objects in dirty cards for references into
+/// the young generation, instead of the whole old generation.
+#[derive(Default)]
+pub struct CardTable {
+    cards: Vec<u8>,
+}
+
+impl CardTable {
+    /// Dirties the card covering old generation slot `index`
+    pub fn dirty(&mut self, index: usize) {
+        let card = index / CARD_SLOTS;
+        if card >= self.cards.len() {
+            self.cards.resize(card + 1, CLEAN_CARD);
+        }
+        self.cards[card] = DIRTY_CARD;
+    }
+
+    pub fn clean(&mut self, card: usize) {
+        self.cards[card] = CLEAN_CARD;
+    }
+
+    pub fn is_dirty(&self, card: usize) -> bool {
+        self.cards.get(card) == Some(&DIRTY_CARD)
+    }
+
+    pub fn dirty_cards(&self) -> Vec<usize> {
+        (0..self.cards.len())
+            .filter(|card| self.is_dirty(*card))
+            .collect()
+    }
+
+    /// Old generation slots `card` covers
+    pub fn slots(card: usize) -> Range<usize> {
+        card * CARD_SLOTS..(card + 1) * CARD_SLOTS
+    }
+}
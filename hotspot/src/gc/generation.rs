@@ -0,0 +1,139 @@
+use crate::gc::card_table::CardTable;
+use crate::gc::heap::HeapObject;
+
+/// Set in the addresses of young objects, with the epoch they were allocated
+/// or copied in above the index. Old objects are addressed by their slot alone.
+const YOUNG_BIT: u64 = 1 << 63;
+const EPOCH_SHIFT: u32 = 32;
+const INDEX_MASK: u64 = (1 << EPOCH_SHIFT) - 1;
+/// Epochs wrap around past this, a reference kept by Rust across that many
+/// young collections goes undetected
+const EPOCH_MASK: usize = (1 << 31) - 1;
+
+/// Where an address points: an old generation slot, or the epoch of a young
+/// object and its index in the semispace of that epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Old(usize),
+    Young(usize, usize),
+}
+
+impl Location {
+    pub fn of(address: u64) -> Location {
+        if address & YOUNG_BIT == 0 {
+            return Location::Old(address as usize);
+        }
+        let epoch = (address & !YOUNG_BIT) >> EPOCH_SHIFT;
+        Location::Young(epoch as usize, (address & INDEX_MASK) as usize)
+    }
+
+    pub fn address(self) -> u64 {
+        match self {
+            Location::Old(index) => index as u64,
+            Location::Young(epoch, index) => {
+                YOUNG_BIT | ((epoch & EPOCH_MASK) as u64) << EPOCH_SHIFT | index as u64
+            }
+        }
+    }
+}
+
+pub fn is_young(address: u64) -> bool {
+    address & YOUNG_BIT != 0
+}
+
+/// Objects that survived a few young collections, and the ones too large to
+/// copy around. They live in slots reused once a full collection freed them.
+#[derive(Default)]
+pub struct OldGen {
+    pub(crate) objects: Vec<Option<HeapObject>>,
+    pub(crate) free: Vec<usize>,
+    pub(crate) cards: CardTable,
+    pub(crate) capacity: usize,
+    pub(crate) used: usize,
+}
+
+impl OldGen {
+    pub fn fits(&self, size: usize) -> bool {
+        self.used + size <= self.capacity
+    }
+
+    /// Moves `object` into a free slot, returns its address
+    pub fn push(&mut self, object: HeapObject) -> u64 {
+        self.used += object.size();
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                index
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.objects.len() - 1
+            }
+        };
+        Location::Old(index).address()
+    }
+}
+
+/// Newly allocated objects, bumped into the current of two semispaces. A young
+/// collection copies the live ones to the other semispace, or promotes them,
+/// and makes it current.
+#[derive(Default)]
+pub struct YoungGen {
+    pub(crate) spaces: [Vec<HeapObject>; 2],
+    /// Young collections so far, wrapping at `EPOCH_MASK`. The current
+    /// semispace is the one of the epoch, and young addresses carry it so the
+    /// ones a collection left behind are told apart from the objects now at
+    /// their index.
+    pub(crate) epoch: usize,
+    /// Bytes each semispace holds
+    pub(crate) capacity: usize,
+    /// Bytes the current semispace holds
+    pub(crate) used: usize,
+}
+
+impl YoungGen {
+    pub fn fits(&self, size: usize) -> bool {
+        self.used + size <= self.capacity
+    }
+
+    /// Appends `object` to the current semispace, returns its address
+    pub fn push(&mut self, object: HeapObject) -> u64 {
+        self.used += object.size();
+        self.push_to(self.epoch, object)
+    }
+
+    /// Appends `object` to the semispace of `epoch`, returns its address
+    pub fn push_to(&mut self, epoch: usize, object: HeapObject) -> u64 {
+        let space = &mut self.spaces[semispace(epoch)];
+        space.push(object);
+        Location::Young(epoch, space.len() - 1).address()
+    }
+
+    /// The semispace objects of `epoch` live in, `None` for the epochs before
+    /// the current one. The next epoch is the to-space of a young collection
+    /// in progress, and empty otherwise.
+    pub fn space(&self, epoch: usize) -> Option<&Vec<HeapObject>> {
+        if epoch == self.epoch || epoch == next_epoch(self.epoch) {
+            Some(&self.spaces[semispace(epoch)])
+        } else {
+            None
+        }
+    }
+
+    pub fn space_mut(&mut self, epoch: usize) -> Option<&mut Vec<HeapObject>> {
+        if epoch == self.epoch || epoch == next_epoch(self.epoch) {
+            Some(&mut self.spaces[semispace(epoch)])
+        } else {
+            None
+        }
+    }
+}
+
+/// The index of the semispace of `epoch`
+pub fn semispace(epoch: usize) -> usize {
+    epoch % 2
+}
+
+pub fn next_epoch(epoch: usize) -> usize {
+    (epoch + 1) & EPOCH_MASK
+}
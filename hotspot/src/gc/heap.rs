@@ -0,0 +1,573 @@
+use crate::basic_type::BasicType;
+use crate::gc::generation::{Location, OldGen, YoungGen};
+use crate::gc::{mark_sweep, scavenge};
+use crate::oops::field_layout::{self, FieldLayout};
+use crate::oops::mark_word::MarkWord;
+use crate::oops::{ArrayOop, InstanceOop, MirrorOop, ObjectArrayOop, Oop, OopRef, TypeArrayOop};
+use crate::types::ClassRef;
+use crate::Error;
+use instructions::instruction::ArrayType;
+use log::info;
+use once_cell::sync::Lazy;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Bytes the heap holds unless configured otherwise
+pub const DEFAULT_CAPACITY: usize = 64 * 1024 * 1024;
+/// Young collections an object survives before it is promoted, unless
+/// configured otherwise
+pub const DEFAULT_TENURING_THRESHOLD: u8 = 7;
+
+/// Bytes of the mark word and class pointer every object starts with
+pub const HEADER_SIZE: usize = 16;
+/// Bytes of the length arrays have after the header, padded to a word
+pub const ARRAY_LENGTH_SIZE: usize = 8;
+/// Bytes of an element of a reference array
+pub const REFERENCE_SIZE: usize = 8;
+
+static HEAP: Lazy<Mutex<Heap>> = Lazy::new(|| Mutex::new(Heap::new(DEFAULT_CAPACITY)));
+
+/// The Java heap, locked for the caller. Don't hold on to it while calling into
+/// anything that may allocate, or read or write objects.
+///
+/// Allocating may collect, which frees every object not reachable from the
+/// roots, see `gc::roots`, and moves the young ones that are.
+pub fn heap() -> MutexGuard<'static, Heap> {
+    HEAP.lock().unwrap()
+}
+
+/// An object in the heap: its header, then the body
+#[derive(Debug, Clone)]
+pub struct HeapObject {
+    pub mark: MarkWord,
+    pub body: Body,
+}
+
+#[derive(Debug, Clone)]
+pub enum Body {
+    Instance(InstanceOop),
+    Mirror(MirrorOop),
+    Array(ArrayOop),
+}
+
+impl HeapObject {
+    /// Bytes the object takes, padded to a word
+    pub fn size(&self) -> usize {
+        let body = match &self.body {
+            Body::Instance(InstanceOop { fields, .. }) | Body::Mirror(MirrorOop { fields, .. }) => {
+                fields.len()
+            }
+            Body::Array(ArrayOop::Object(array)) => {
+                ARRAY_LENGTH_SIZE + array.elements.len() * REFERENCE_SIZE
+            }
+            Body::Array(ArrayOop::Type(array)) => {
+                ARRAY_LENGTH_SIZE + array.length() * array.element_size()
+            }
+        };
+        align(HEADER_SIZE + body)
+    }
+
+    /// Addresses of the objects the fields, static fields of a mirror or
+    /// elements refer to
+    pub(crate) fn references(&self) -> Result<Vec<u64>, Error> {
+        let slots = self.reference_slots()?;
+        Ok(slots.into_iter().map(|(_, address)| address).collect())
+    }
+
+    /// The non-null references held, with the field offset or element index
+    /// each is held at
+    pub(crate) fn reference_slots(&self) -> Result<Vec<(usize, u64)>, Error> {
+        let (fields, oops) = match &self.body {
+            Body::Instance(InstanceOop { class, fields }) => {
+                (fields, &field_layout(class)?.instance_oops)
+            }
+            Body::Mirror(MirrorOop { class, fields }) => {
+                (fields, &field_layout(class)?.static_oops)
+            }
+            Body::Array(ArrayOop::Object(array)) => {
+                return Ok(array
+                    .elements
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, element)| match element {
+                        Oop::Reference(reference) => Some((index, reference.address())),
+                        _ => None,
+                    })
+                    .collect());
+            }
+            Body::Array(ArrayOop::Type(_)) => return Ok(vec![]),
+        };
+        let mut slots = vec![];
+        for offset in oops {
+            if let Oop::Reference(reference) =
+                field_layout::load(fields, *offset, &BasicType::OBJECT)?
+            {
+                slots.push((*offset, reference.address()));
+            }
+        }
+        Ok(slots)
+    }
+
+    /// Points the reference at `slot`, see `reference_slots`, to `address`
+    pub(crate) fn set_reference(&mut self, slot: usize, address: u64) -> Result<(), Error> {
+        let value = reference(address);
+        match &mut self.body {
+            Body::Instance(InstanceOop { fields, .. }) | Body::Mirror(MirrorOop { fields, .. }) => {
+                field_layout::store(fields, slot, &BasicType::OBJECT, &value)
+            }
+            Body::Array(ArrayOop::Object(array)) => match array.elements.get_mut(slot) {
+                Some(element) => {
+                    *element = value;
+                    Ok(())
+                }
+                None => Err(Error::Internal(format!("Invalid element: {}", slot))),
+            },
+            Body::Array(ArrayOop::Type(_)) => Err(Error::Internal(
+                "References in a primitive array".to_string(),
+            )),
+        }
+    }
+}
+
+fn align(size: usize) -> usize {
+    (size + 7) & !7
+}
+
+/// Objects are allocated in the young generation and promoted to the old one
+/// once they survived `tenuring_threshold` young collections. The young
+/// generation takes a third of the capacity, split into two semispaces.
+pub struct Heap {
+    pub(crate) young: YoungGen,
+    pub(crate) old: OldGen,
+    capacity: usize,
+    pub(crate) tenuring_threshold: u8,
+    collections: usize,
+    hash_seed: u32,
+}
+
+impl Heap {
+    pub fn new(capacity: usize) -> Self {
+        let mut heap = Heap {
+            young: YoungGen::default(),
+            old: OldGen::default(),
+            capacity: 0,
+            tenuring_threshold: DEFAULT_TENURING_THRESHOLD,
+            collections: 0,
+            hash_seed: 0x9e37_79b9,
+        };
+        heap.set_capacity(capacity);
+        heap
+    }
+
+    /// Bytes the objects may take in total, both semispaces included
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Limits the heap to `capacity` bytes, allocations that don't fit after a
+    /// collection fail with `OutOfMemoryError`
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.young.capacity = (capacity / 6) & !7;
+        self.old.capacity = capacity - 2 * self.young.capacity;
+    }
+
+    /// Young collections an object survives before it is promoted, at most
+    /// `MarkWord::MAX_AGE`
+    pub fn set_tenuring_threshold(&mut self, threshold: u8) {
+        self.tenuring_threshold = threshold.min(MarkWord::MAX_AGE);
+    }
+
+    /// Bytes the live and not yet collected objects take
+    pub fn used(&self) -> usize {
+        self.young.used + self.old.used
+    }
+
+    /// Allocates an instance of `class` with its fields at their default
+    /// values, the class must be linked
+    pub fn allocate_instance(&mut self, class: &ClassRef) -> Result<Oop, Error> {
+        let size = field_layout(class)?.instance_size;
+        self.allocate(Body::Instance(InstanceOop {
+            class: class.clone(),
+            fields: vec![0; size],
+        }))
+    }
+
+    /// Allocates the `java.lang.Class` object of `class`, with its static
+    /// fields at their default values. Mirrors live as long as their class, so
+    /// they go to the old generation right away.
+    pub fn allocate_mirror(&mut self, class: &ClassRef) -> Result<Oop, Error> {
+        let size = field_layout(class)?.static_size;
+        let address = self.allocate_old(new_object(Body::Mirror(MirrorOop {
+            class: class.clone(),
+            fields: vec![0; size],
+        })))?;
+        Ok(reference(address))
+    }
+
+    /// Allocates a primitive array of `length` zeros, as `newarray` does
+    pub fn allocate_type_array(
+        &mut self,
+        array_type: ArrayType,
+        length: i32,
+    ) -> Result<Oop, Error> {
+        let length = check_length(length)?;
+        let array = TypeArrayOop::new(array_type, length);
+        self.allocate(Body::Array(ArrayOop::Type(array)))
+    }
+
+    /// Allocates an array of `length` nulls, as `anewarray` does
+    pub fn allocate_object_array(
+        &mut self,
+        element_class: &ClassRef,
+        length: i32,
+    ) -> Result<Oop, Error> {
+        let length = check_length(length)?;
+        let array = ObjectArrayOop {
+            element_class: element_class.clone(),
+            elements: vec![Oop::Null; length],
+        };
+        self.allocate(Body::Array(ArrayOop::Object(array)))
+    }
+
+    fn allocate(&mut self, body: Body) -> Result<Oop, Error> {
+        let object = new_object(body);
+        let size = object.size();
+        // Objects taking more than half a semispace would be copied at a loss
+        if size <= self.young.capacity / 2 {
+            if !self.young.fits(size) {
+                // Everything young may survive, a full collection first makes
+                // room in the old generation to promote it to
+                if self.old.used + self.young.used > self.old.capacity {
+                    self.collect()?;
+                } else {
+                    self.collect_young()?;
+                }
+            }
+            if self.young.fits(size) {
+                return Ok(reference(self.young.push(object)));
+            }
+        }
+        Ok(reference(self.allocate_old(object)?))
+    }
+
+    fn allocate_old(&mut self, object: HeapObject) -> Result<u64, Error> {
+        let size = object.size();
+        if !self.old.fits(size) {
+            self.collect()?;
+            if !self.old.fits(size) {
+                return Err(Error::Exception("java/lang/OutOfMemoryError"));
+            }
+        }
+        Ok(self.old.push(object))
+    }
+
+    /// Copies the young objects the roots and the old generation reach to the
+    /// other semispace, promoting the old enough ones. Returns the bytes the
+    /// young generation shrank by.
+    pub fn collect_young(&mut self) -> Result<usize, Error> {
+        let start = Instant::now();
+        let (young, used) = (self.young.used, self.used());
+        let promoted = scavenge::collect(self)?;
+        info!(
+            "GC({}) Pause Young {}K->{}K({}K) promoted {}K {:.3}ms",
+            self.collections,
+            used / 1024,
+            self.used() / 1024,
+            self.capacity / 1024,
+            promoted / 1024,
+            elapsed_millis(start)
+        );
+        self.collections += 1;
+        Ok(young - self.young.used)
+    }
+
+    /// Collects the objects no root reaches in both generations, returns the
+    /// bytes freed. The objects held only by Rust locals are collected too,
+    /// and young ones move, hold them through `GlobalHandle`s across
+    /// allocations.
+    pub fn collect(&mut self) -> Result<usize, Error> {
+        let start = Instant::now();
+        let used = self.used();
+        mark_sweep::collect(self)?;
+        // Only now, as dead old objects in dirty cards would keep the young
+        // objects they refer to alive
+        let promoted = scavenge::collect(self)?;
+        info!(
+            "GC({}) Pause Full {}K->{}K({}K) promoted {}K {:.3}ms",
+            self.collections,
+            used / 1024,
+            self.used() / 1024,
+            self.capacity / 1024,
+            promoted / 1024,
+            elapsed_millis(start)
+        );
+        self.collections += 1;
+        Ok(used - self.used())
+    }
+
+    /// The write barrier of `putfield`, `putstatic` and `aastore`: dirties the
+    /// card of the object a reference was stored in when it is old, so the
+    /// next young collection finds the young objects it may now refer to
+    pub fn write_barrier(&mut self, reference: &OopRef) {
+        if let Location::Old(index) = Location::of(reference.address()) {
+            self.old.cards.dirty(index);
+        }
+    }
+
+    /// The object at `reference`. References kept by Rust across an
+    /// allocation dangle, as the collection it may start frees or moves
+    /// their objects, and fail here as long as the heap can tell: a young
+    /// object moved away is, the old object of a slot reused isn't. Keep
+    /// them in a `GlobalHandle` instead.
+    pub(crate) fn get(&self, reference: &OopRef) -> Result<&HeapObject, Error> {
+        self.object(reference.address())
+    }
+
+    pub(crate) fn get_mut(&mut self, reference: &OopRef) -> Result<&mut HeapObject, Error> {
+        self.object_mut(reference.address())
+    }
+
+    pub(crate) fn object(&self, address: u64) -> Result<&HeapObject, Error> {
+        let object = match Location::of(address) {
+            Location::Old(index) => self.old.objects.get(index).and_then(Option::as_ref),
+            Location::Young(epoch, index) => {
+                self.young.space(epoch).and_then(|space| space.get(index))
+            }
+        };
+        object.ok_or_else(|| dangling(address))
+    }
+
+    pub(crate) fn object_mut(&mut self, address: u64) -> Result<&mut HeapObject, Error> {
+        let object = match Location::of(address) {
+            Location::Old(index) => self.old.objects.get_mut(index).and_then(Option::as_mut),
+            Location::Young(epoch, index) => self
+                .young
+                .space_mut(epoch)
+                .and_then(|space| space.get_mut(index)),
+        };
+        object.ok_or_else(|| dangling(address))
+    }
+
+    /// `System.identityHashCode`, picked on first use and kept in the mark word
+    pub fn identity_hash(&mut self, reference: &OopRef) -> Result<i32, Error> {
+        if let Some(hash) = self.get(reference)?.mark.hash() {
+            return Ok(hash);
+        }
+        // Marsaglia's xorshift, skipping the 0 that stands for no hash
+        let mut hash = 0;
+        while hash & 0x7fff_ffff == 0 {
+            self.hash_seed ^= self.hash_seed << 13;
+            self.hash_seed ^= self.hash_seed >> 17;
+            self.hash_seed ^= self.hash_seed << 5;
+            hash = self.hash_seed;
+        }
+        let object = self.get_mut(reference)?;
+        object.mark = object.mark.with_hash(hash);
+        Ok((hash & 0x7fff_ffff) as i32)
+    }
+}
+
+fn field_layout(class: &ClassRef) -> Result<&FieldLayout, Error> {
+    class.get_class().field_layout().ok_or_else(|| {
+        Error::Internal(format!(
+            "Class not prepared: {}",
+            String::from_utf8_lossy(class.name())
+        ))
+    })
+}
+
+fn dangling(address: u64) -> Error {
+    Error::Internal(format!("Dangling reference: {:#x}", address))
+}
+
+fn elapsed_millis(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+fn new_object(body: Body) -> HeapObject {
+    HeapObject {
+        mark: MarkWord::new(),
+        body,
+    }
+}
+
+fn reference(address: u64) -> Oop {
+    Oop::Reference(Arc::new(OopRef::new(address)))
+}
+
+fn check_length(length: i32) -> Result<usize, Error> {
+    if length < 0 {
+        return Err(Error::Exception("java/lang/NegativeArraySizeException"));
+    }
+    Ok(length as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::class_loader::test::{define, lock};
+    use crate::runtime::handles::GlobalHandle;
+    use crate::sys::dic;
+
+    fn field(class: &ClassRef, name: &str, descriptor: &str) -> (usize, BasicType) {
+        let field = class
+            .get_class()
+            .find_field(name.as_bytes(), descriptor.as_bytes())
+            .unwrap();
+        (field.offset, field.field.field.field_type.clone())
+    }
+
+    fn is_exception(result: Result<impl std::fmt::Debug, Error>, name: &str) -> bool {
+        matches!(result, Err(Error::Exception(exception)) if exception == name)
+    }
+
+    #[test]
+    fn instance_fields_hold_their_values() {
+        let _lock = lock();
+        let class = define(
+            "
+.class public HeapFields
+.super java/lang/Object
+.field public b B
+.field public i I
+.field public j J
+.field public d D
+.field public next LHeapFields;
+",
+        )
+        .unwrap();
+        let object = GlobalHandle::new(heap().allocate_instance(&class).unwrap());
+        let other = heap().allocate_instance(&class).unwrap();
+        let object = object.get();
+
+        let (offset, field_type) = field(&class, "b", "B");
+        assert!(matches!(
+            object.get_field(offset, &field_type),
+            Ok(Oop::Int(0))
+        ));
+        object
+            .put_field(offset, &field_type, Oop::Int(300))
+            .unwrap();
+        assert!(matches!(
+            object.get_field(offset, &field_type),
+            Ok(Oop::Int(44))
+        ));
+        let (offset, field_type) = field(&class, "i", "I");
+        object.put_field(offset, &field_type, Oop::Int(-7)).unwrap();
+        assert!(matches!(
+            object.get_field(offset, &field_type),
+            Ok(Oop::Int(-7))
+        ));
+        let (offset, field_type) = field(&class, "j", "J");
+        object
+            .put_field(offset, &field_type, Oop::Long(1 << 40))
+            .unwrap();
+        assert!(matches!(
+            object.get_field(offset, &field_type),
+            Ok(Oop::Long(value)) if value == 1 << 40
+        ));
+        let (offset, field_type) = field(&class, "d", "D");
+        object
+            .put_field(offset, &field_type, Oop::Double(0.5))
+            .unwrap();
+        assert!(matches!(
+            object.get_field(offset, &field_type),
+            Ok(Oop::Double(value)) if value == 0.5
+        ));
+
+        let (offset, field_type) = field(&class, "next", "LHeapFields;");
+        assert!(matches!(
+            object.get_field(offset, &field_type),
+            Ok(Oop::Null)
+        ));
+        object
+            .put_field(offset, &field_type, other.clone())
+            .unwrap();
+        match (object.get_field(offset, &field_type), &other) {
+            (Ok(Oop::Reference(next)), Oop::Reference(other)) => assert_eq!(next, *other),
+            (next, _) => panic!("Not the other instance: {:?}", next),
+        }
+        assert!(is_exception(
+            Oop::Null.get_field(offset, &field_type),
+            "java/lang/NullPointerException"
+        ));
+        assert!(is_exception(
+            object.array_length(),
+            "java/lang/IncompatibleClassChangeError"
+        ));
+        assert!(is_exception(
+            object.get_element(0),
+            "java/lang/IncompatibleClassChangeError"
+        ));
+    }
+
+    #[test]
+    fn array_elements_hold_their_values() {
+        let _lock = lock();
+        let bytes = heap().allocate_type_array(ArrayType::Byte, 3).unwrap();
+        assert!(matches!(bytes.array_length(), Ok(3)));
+        assert!(matches!(bytes.get_element(2), Ok(Oop::Int(0))));
+        bytes.put_element(2, Oop::Int(200)).unwrap();
+        assert!(matches!(bytes.get_element(2), Ok(Oop::Int(-56))));
+        assert!(is_exception(
+            bytes.get_element(3),
+            "java/lang/ArrayIndexOutOfBoundsException"
+        ));
+        assert!(is_exception(
+            bytes.put_element(-1, Oop::Int(1)),
+            "java/lang/ArrayIndexOutOfBoundsException"
+        ));
+        assert!(is_exception(
+            bytes.put_element(0, Oop::Float(1.0)),
+            "java/lang/ArrayStoreException"
+        ));
+        assert!(is_exception(
+            bytes.get_field(0, &BasicType::INT),
+            "java/lang/IncompatibleClassChangeError"
+        ));
+        assert!(is_exception(
+            heap().allocate_type_array(ArrayType::Int, -1),
+            "java/lang/NegativeArraySizeException"
+        ));
+
+        let object = dic::find(b"java/lang/Object").unwrap();
+        let objects = GlobalHandle::new(heap().allocate_object_array(&object, 2).unwrap());
+        let element = heap().allocate_type_array(ArrayType::Long, 1).unwrap();
+        let objects = objects.get();
+        assert!(matches!(objects.get_element(1), Ok(Oop::Null)));
+        objects.put_element(1, element.clone()).unwrap();
+        match (objects.get_element(1), &element) {
+            (Ok(Oop::Reference(stored)), Oop::Reference(element)) => {
+                assert_eq!(stored, *element)
+            }
+            (stored, _) => panic!("Not the element: {:?}", stored),
+        }
+        assert!(is_exception(
+            objects.put_element(0, Oop::Int(1)),
+            "java/lang/ArrayStoreException"
+        ));
+        let mut primitive = new_object(Body::Array(ArrayOop::Type(TypeArrayOop::new(
+            ArrayType::Int,
+            1,
+        ))));
+        assert!(matches!(
+            primitive.set_reference(0, 0),
+            Err(Error::Internal(_))
+        ));
+    }
+
+    #[test]
+    fn references_kept_across_a_collection_are_errors() {
+        let _lock = lock();
+        let array = GlobalHandle::new(heap().allocate_type_array(ArrayType::Int, 2).unwrap());
+        let kept = array.get();
+        heap().collect_young().unwrap();
+        assert!(matches!(kept.array_length(), Err(Error::Internal(_))));
+        assert!(matches!(array.get().array_length(), Ok(2)));
+        // Back in the same semispace, the index may now hold another object
+        heap().collect_young().unwrap();
+        assert!(matches!(kept.array_length(), Err(Error::Internal(_))));
+        assert!(matches!(array.get().array_length(), Ok(2)));
+    }
+}
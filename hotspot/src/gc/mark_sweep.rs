@@ -0,0 +1,215 @@
+use crate::gc::generation;
+use crate::gc::heap::Heap;
+use crate::gc::roots;
+use crate::oops::Oop;
+use crate::Error;
+
+/// Stop-the-world mark-sweep of the old generation: marks what the roots reach
+/// through instance fields, static fields and reference arrays, young objects
+/// included, then frees the old objects left unmarked. Returns the bytes freed.
+pub(crate) fn collect(heap: &mut Heap) -> Result<usize, Error> {
+    mark(heap)?;
+    // Marked young objects would look forwarded to the next young collection
+    let current = generation::semispace(heap.young.epoch);
+    for object in heap.young.spaces[current].iter_mut() {
+        object.mark = object.mark.unmarked();
+    }
+    Ok(sweep(heap))
+}
+
+fn mark(heap: &mut Heap) -> Result<(), Error> {
+    let mut pending = vec![];
+    roots::for_each_root(|oop| {
+        if let Oop::Reference(reference) = oop {
+            pending.push(reference.address());
+        }
+    });
+    while let Some(address) = pending.pop() {
+        let object = heap.object_mut(address)?;
+        if object.mark.is_marked() {
+            continue;
+        }
+        object.mark = object.mark.marked();
+        pending.extend(object.references()?);
+    }
+    Ok(())
+}
+
+fn sweep(heap: &mut Heap) -> usize {
+    let old = &mut heap.old;
+    let mut freed = 0;
+    for (index, slot) in old.objects.iter_mut().enumerate() {
+        if let Some(object) = slot {
+            if object.mark.is_marked() {
+                object.mark = object.mark.unmarked();
+            } else {
+                freed += object.size();
+                *slot = None;
+                old.free.push(index);
+            }
+        }
+    }
+    old.used -= freed;
+    freed
+}
+
+#[cfg(test)]
+mod test {
+    use crate::basic_type::BasicType;
+    use crate::gc::generation::Location;
+    use crate::gc::heap::{heap, DEFAULT_TENURING_THRESHOLD};
+    use crate::oops::Oop;
+    use crate::runtime::class_loader::test::{define, lock};
+    use crate::runtime::frame::Frame;
+    use crate::runtime::handles::GlobalHandle;
+    use crate::runtime::{string_table, Slot};
+    use instructions::instruction::ArrayType;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+
+    /// Promotes every young object the roots reach
+    fn promote() {
+        let mut heap = heap();
+        heap.set_tenuring_threshold(1);
+        heap.collect_young().unwrap();
+        heap.set_tenuring_threshold(DEFAULT_TENURING_THRESHOLD);
+    }
+
+    /// The old generation slot of `oop`
+    fn slot(oop: &Oop) -> usize {
+        match oop {
+            Oop::Reference(reference) => match Location::of(reference.address()) {
+                Location::Old(index) => index,
+                location => panic!("Not old: {:?}", location),
+            },
+            oop => panic!("Not a reference: {}", oop),
+        }
+    }
+
+    fn is_live(index: usize) -> bool {
+        heap().old.objects[index].is_some()
+    }
+
+    #[test]
+    fn frees_what_no_root_reaches() {
+        let _lock = lock();
+        let class = define(
+            "
+.class public MarkSweepStatics
+.super java/lang/Object
+.field public static kept [J
+",
+        )
+        .unwrap();
+        let field = class.get_class().find_field(b"kept", b"[J").unwrap();
+        let mirror = class.get_class().mirror().unwrap().clone();
+        let array = heap().allocate_type_array(ArrayType::Long, 2).unwrap();
+        mirror
+            .put_field(field.offset, &BasicType::ARRAY, array)
+            .unwrap();
+        string_table::intern("MarkSweepInterned", || {
+            heap().allocate_type_array(ArrayType::Char, 4)
+        })
+        .unwrap();
+        let kept = GlobalHandle::new(heap().allocate_type_array(ArrayType::Int, 4).unwrap());
+        let dropped = GlobalHandle::new(heap().allocate_type_array(ArrayType::Int, 4).unwrap());
+        promote();
+
+        let dropped_slot = slot(&dropped.get());
+        drop(dropped);
+        let kept_slot = slot(&kept.get());
+        let interned_slot = slot(&string_table::lookup("MarkSweepInterned").unwrap());
+        let static_slot = slot(&mirror.get_field(field.offset, &BasicType::ARRAY).unwrap());
+        assert!(is_live(dropped_slot));
+
+        let freed = heap().collect().unwrap();
+        assert!(freed > 0);
+        assert!(!is_live(dropped_slot));
+        assert!(is_live(kept_slot));
+        assert!(is_live(interned_slot));
+        assert!(is_live(static_slot));
+        assert!(is_live(slot(&mirror)));
+        // Survivors are left unmarked for the next collection
+        let heap = heap();
+        assert!(!heap.old.objects[kept_slot]
+            .as_ref()
+            .unwrap()
+            .mark
+            .is_marked());
+    }
+
+    #[test]
+    fn frames_are_roots_while_executed() {
+        let _lock = lock();
+        let class = define(
+            "
+.class public MarkSweepFrame
+.super java/lang/Object
+.method public static run()V
+    .limit stack 1
+    .limit locals 1
+    return
+.end method
+",
+        )
+        .unwrap();
+        let frame = Arc::new(Frame::new(class.get_instance().methods[0].clone(), 0));
+        let array = heap().allocate_type_array(ArrayType::Int, 4).unwrap();
+        frame.push(array);
+        let activation = Frame::activate(&frame);
+        promote();
+        let array_slot = match frame.data_area.stack.lock().peek() {
+            Some(Slot::Oop(array)) => slot(array),
+            slot => panic!("Not the array: {:?}", slot),
+        };
+        heap().collect().unwrap();
+        assert!(is_live(array_slot));
+        // Off the frame stack the frame is just a Rust local
+        drop(activation);
+        heap().collect().unwrap();
+        assert!(!is_live(array_slot));
+    }
+
+    #[test]
+    fn frames_of_other_threads_are_roots() {
+        let _lock = lock();
+        let class = define(
+            "
+.class public MarkSweepThread
+.super java/lang/Object
+.method public static run()V
+    .limit stack 1
+    .limit locals 1
+    return
+.end method
+",
+        )
+        .unwrap();
+        let frame = Arc::new(Frame::new(class.get_instance().methods[0].clone(), 0));
+        let array = heap().allocate_type_array(ArrayType::Int, 4).unwrap();
+        frame.push(array);
+        let (activated, on_stack) = mpsc::channel();
+        let (done, finished) = mpsc::channel::<()>();
+        let thread = {
+            let frame = frame.clone();
+            thread::spawn(move || {
+                let _activation = Frame::activate(&frame);
+                activated.send(()).unwrap();
+                finished.recv().unwrap();
+            })
+        };
+        on_stack.recv().unwrap();
+        // The young array moves, the other thread's frame follows it
+        promote();
+        let array_slot = match frame.data_area.stack.lock().peek() {
+            Some(Slot::Oop(array)) => slot(array),
+            slot => panic!("Not the array: {:?}", slot),
+        };
+        heap().collect().unwrap();
+        assert!(is_live(array_slot));
+        done.send(()).unwrap();
+        thread.join().unwrap();
+        heap().collect().unwrap();
+        assert!(!is_live(array_slot));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod card_table;
+pub mod generation;
+pub mod heap;
+pub mod mark_sweep;
+pub mod roots;
+pub mod scavenge;
@@ -0,0 +1,27 @@
+use crate::oops::Oop;
+use crate::runtime::frame;
+use crate::runtime::{handles, string_table, Slot};
+use crate::sys::dic;
+
+/// Calls `f` with every root: the locals and operand stacks of the active
+/// frames, the class mirrors holding the static fields, the interned strings
+/// and the global handles. A young collection moves objects, so `f` may
+/// update the roots in place.
+pub(crate) fn for_each_root(mut f: impl FnMut(&mut Oop)) {
+    frame::for_each_active(|frame| {
+        let mut locals = frame.data_area.local.lock();
+        let mut stack = frame.data_area.stack.lock();
+        for slot in locals.iter_mut().chain(stack.iter_mut()) {
+            if let Slot::Oop(oop) = slot {
+                f(oop);
+            }
+        }
+    });
+    for class in dic::classes() {
+        if let Some(mirror) = class.get_mut_class().mirror_mut() {
+            f(mirror);
+        }
+    }
+    string_table::for_each(&mut f);
+    handles::for_each(&mut f);
+}
@@ -0,0 +1,250 @@
+use crate::gc::card_table::CardTable;
+use crate::gc::generation::{self, Location};
+use crate::gc::heap::Heap;
+use crate::gc::roots;
+use crate::oops::mark_word::MarkWord;
+use crate::oops::{Oop, OopRef};
+use crate::Error;
+use std::sync::Arc;
+
+/// Stop-the-world young collection: copies the young objects reachable from
+/// the roots or the old objects in dirty cards out of the current semispace,
+/// then the ones those reach, and so on. A copy goes to the other semispace
+/// one collection older, or to the old generation once it reaches the tenuring
+/// threshold and the old generation has room. Returns the bytes promoted.
+pub(crate) fn collect(heap: &mut Heap) -> Result<usize, Error> {
+    let from = heap.young.epoch;
+    let mut scavenger = Scavenger {
+        from,
+        to_used: 0,
+        promoted: 0,
+        pending: vec![],
+    };
+    let mut forwarded = Ok(());
+    roots::for_each_root(|oop| {
+        if forwarded.is_ok() {
+            forwarded = scavenger.forward_oop(heap, oop);
+        }
+    });
+    forwarded?;
+    for card in heap.old.cards.dirty_cards() {
+        heap.old.cards.clean(card);
+        for index in CardTable::slots(card) {
+            if let Some(Some(_)) = heap.old.objects.get(index) {
+                scavenger.scan(heap, Location::Old(index).address())?;
+            }
+        }
+    }
+    while let Some(address) = scavenger.pending.pop() {
+        scavenger.scan(heap, address)?;
+    }
+    heap.young.spaces[generation::semispace(from)].clear();
+    heap.young.epoch = generation::next_epoch(from);
+    heap.young.used = scavenger.to_used;
+    Ok(scavenger.promoted)
+}
+
+struct Scavenger {
+    /// The epoch of the objects copied out
+    from: usize,
+    to_used: usize,
+    promoted: usize,
+    /// Addresses of the copies whose references aren't forwarded yet
+    pending: Vec<u64>,
+}
+
+impl Scavenger {
+    /// Where the object at `address` lives after the collection, copying it
+    /// on the first visit when it is in from-space
+    fn forward(&mut self, heap: &mut Heap, address: u64) -> Result<u64, Error> {
+        let index = match Location::of(address) {
+            Location::Young(epoch, index) if epoch == self.from => index,
+            _ => return Ok(address),
+        };
+        let object = heap.object(address)?;
+        if let Some(forwardee) = object.mark.forwardee() {
+            return Ok(forwardee);
+        }
+        let mut copy = object.clone();
+        let age = copy.mark.age() + 1;
+        copy.mark = copy.mark.with_age(age);
+        let size = copy.size();
+        // Survivors that don't fit in to-space are promoted early. Once the
+        // old generation is full too they overflow to-space, the allocation
+        // that collected then falls back to a full collection and fails with
+        // `OutOfMemoryError` unless that makes room.
+        let overflow = self.to_used + size > heap.young.capacity;
+        let forwardee = if (age >= heap.tenuring_threshold || overflow) && heap.old.fits(size) {
+            self.promoted += size;
+            heap.old.push(copy)
+        } else {
+            self.to_used += size;
+            heap.young.push_to(generation::next_epoch(self.from), copy)
+        };
+        let from = generation::semispace(self.from);
+        heap.young.spaces[from][index].mark = MarkWord::forwarded(forwardee);
+        self.pending.push(forwardee);
+        Ok(forwardee)
+    }
+
+    fn forward_oop(&mut self, heap: &mut Heap, oop: &mut Oop) -> Result<(), Error> {
+        if let Oop::Reference(reference) = oop {
+            let forwardee = self.forward(heap, reference.address())?;
+            if forwardee != reference.address() {
+                *oop = Oop::Reference(Arc::new(OopRef::new(forwardee)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Forwards the references the object at `address` holds. Old objects left
+    /// referring to young ones get their card dirtied again.
+    fn scan(&mut self, heap: &mut Heap, address: u64) -> Result<(), Error> {
+        let mut young = false;
+        for (slot, target) in heap.object(address)?.reference_slots()? {
+            let forwardee = self.forward(heap, target)?;
+            if forwardee != target {
+                heap.object_mut(address)?.set_reference(slot, forwardee)?;
+            }
+            young |= generation::is_young(forwardee);
+        }
+        if let (true, Location::Old(index)) = (young, Location::of(address)) {
+            heap.old.cards.dirty(index);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::basic_type::BasicType;
+    use crate::gc::card_table::CARD_SLOTS;
+    use crate::gc::generation::{self, Location};
+    use crate::gc::heap::{heap, DEFAULT_CAPACITY, DEFAULT_TENURING_THRESHOLD};
+    use crate::oops::Oop;
+    use crate::runtime::class_loader::test::{define, lock};
+    use crate::runtime::handles::GlobalHandle;
+    use crate::Error;
+    use instructions::instruction::ArrayType;
+
+    fn address(oop: &Oop) -> u64 {
+        match oop {
+            Oop::Reference(reference) => reference.address(),
+            oop => panic!("Not a reference: {}", oop),
+        }
+    }
+
+    fn age(oop: &Oop) -> u8 {
+        heap().object(address(oop)).unwrap().mark.age()
+    }
+
+    #[test]
+    fn survivors_age_until_promoted() {
+        let _lock = lock();
+        heap().set_tenuring_threshold(3);
+        let array = GlobalHandle::new(heap().allocate_type_array(ArrayType::Int, 4).unwrap());
+        array.get().put_element(0, Oop::Int(42)).unwrap();
+        for collections in 1..3 {
+            heap().collect_young().unwrap();
+            assert!(generation::is_young(address(&array.get())));
+            assert_eq!(age(&array.get()), collections);
+        }
+        heap().collect_young().unwrap();
+        heap().set_tenuring_threshold(DEFAULT_TENURING_THRESHOLD);
+        assert!(!generation::is_young(address(&array.get())));
+        assert!(matches!(array.get().get_element(0), Ok(Oop::Int(42))));
+    }
+
+    #[test]
+    fn dirty_cards_keep_young_objects_alive() {
+        let _lock = lock();
+        let class = define(
+            "
+.class public ScavengeHolder
+.super java/lang/Object
+.field public held [I
+",
+        )
+        .unwrap();
+        let field = class.get_class().find_field(b"held", b"[I").unwrap();
+        let holder = GlobalHandle::new(heap().allocate_instance(&class).unwrap());
+        heap().set_tenuring_threshold(1);
+        heap().collect_young().unwrap();
+        heap().set_tenuring_threshold(DEFAULT_TENURING_THRESHOLD);
+        let holder = holder.get();
+        let card = match Location::of(address(&holder)) {
+            Location::Old(index) => index / CARD_SLOTS,
+            location => panic!("Not promoted: {:?}", location),
+        };
+
+        // Only the old holder refers to the array, through its dirty card
+        let array = heap().allocate_type_array(ArrayType::Int, 4).unwrap();
+        array.put_element(3, Oop::Int(7)).unwrap();
+        holder
+            .put_field(field.offset, &BasicType::ARRAY, array)
+            .unwrap();
+        assert!(heap().old.cards.is_dirty(card));
+        heap().collect_young().unwrap();
+
+        let array = holder.get_field(field.offset, &BasicType::ARRAY).unwrap();
+        assert!(generation::is_young(address(&array)));
+        assert_eq!(age(&array), 1);
+        assert!(matches!(array.get_element(3), Ok(Oop::Int(7))));
+        // Still referring to a young object, the card stays dirty
+        assert!(heap().old.cards.is_dirty(card));
+    }
+
+    #[test]
+    fn running_out_of_heap_is_an_out_of_memory_error() {
+        let _lock = lock();
+        heap().collect().unwrap();
+        let capacity = heap().used() * 3 / 2 + 96 * 1024;
+        heap().set_capacity(capacity);
+        let mut arrays = vec![];
+        let result = loop {
+            match heap().allocate_type_array(ArrayType::Int, 1000) {
+                Ok(array) => arrays.push(GlobalHandle::new(array)),
+                Err(e) => break e,
+            }
+            assert!(arrays.len() < 1000);
+        };
+        assert!(matches!(
+            result,
+            Error::Exception("java/lang/OutOfMemoryError")
+        ));
+        // Every array allocated is still there
+        for array in &arrays {
+            assert!(matches!(array.get().array_length(), Ok(1000)));
+        }
+        drop(arrays);
+        let mut heap = heap();
+        heap.set_capacity(DEFAULT_CAPACITY);
+        heap.collect().unwrap();
+    }
+
+    #[test]
+    fn survivors_overflow_to_space_when_old_is_full() {
+        let _lock = lock();
+        heap().collect().unwrap();
+        let arrays: Vec<_> = (0..4)
+            .map(|_| GlobalHandle::new(heap().allocate_type_array(ArrayType::Int, 1000).unwrap()))
+            .collect();
+        {
+            // Neither to-space nor the old generation can take them all
+            let mut heap = heap();
+            heap.young.capacity = 8 * 1024;
+            heap.old.capacity = heap.old.used;
+            heap.collect_young().unwrap();
+            assert!(heap.old.used <= heap.old.capacity);
+            assert!(heap.young.used > heap.young.capacity);
+        }
+        for array in &arrays {
+            assert!(generation::is_young(address(&array.get())));
+            assert!(matches!(array.get().array_length(), Ok(1000)));
+        }
+        drop(arrays);
+        let mut heap = heap();
+        heap.set_capacity(DEFAULT_CAPACITY);
+        heap.collect().unwrap();
+    }
+}
@@ -0,0 +1,944 @@
+use crate::gc::heap::heap;
+use crate::oops::field_layout::LaidOutField;
+use crate::oops::Oop;
+use crate::runtime::frame::Frame;
+use crate::runtime::{class_loader, string_table};
+use crate::sys::dic;
+use crate::types::ClassRef;
+use crate::Error;
+use classfile::constant::Constant;
+use classfile::ConstantPoolRef;
+use instructions::decoder::decode_at;
+use instructions::instruction::{Instruction, Operand};
+use instructions::opcode::OpCode;
+use std::cmp::Ordering;
+use std::sync::{atomic, Arc};
+
+/// Where execution goes after an instruction
+enum Flow {
+    Next,
+    Jump(u32),
+    Return(Option<Oop>),
+}
+
+macro_rules! binary {
+    ($frame: expr, $pop: ident, $push: ident, |$a: ident, $b: ident| $result: expr) => {{
+        let $b = $frame.$pop()?;
+        let $a = $frame.$pop()?;
+        $frame.$push($result);
+    }};
+}
+
+macro_rules! unary {
+    ($frame: expr, $pop: ident, $push: ident, |$a: ident| $result: expr) => {{
+        let $a = $frame.$pop()?;
+        $frame.$push($result);
+    }};
+}
+
+/// Runs the code of `frame` from its pc until it returns, with the returned
+/// value unless the method is `void`. The frame is on the thread's frame stack
+/// meanwhile, where the collector finds its locals and operands.
+pub fn execute(frame: Arc<Frame>) -> Result<Option<Oop>, Error> {
+    let code = frame.code.clone();
+    let _activation = Frame::activate(&frame);
+    loop {
+        let pc = frame.pc.load(atomic::Ordering::Relaxed) as u32;
+        let (instruction, length) = decode_at(&code, pc).map_err(Error::InvalidCode)?;
+        let next = match step(&frame, instruction)? {
+            Flow::Next => pc + length,
+            Flow::Jump(target) => target,
+            Flow::Return(value) => return Ok(value),
+        };
+        frame.pc.store(next as usize, atomic::Ordering::Relaxed);
+    }
+}
+
+fn step(frame: &Frame, instruction: Instruction) -> Result<Flow, Error> {
+    use OpCode::*;
+    let Instruction { opcode, operand } = instruction;
+    match opcode {
+        nop => {}
+        // Constants
+        aconst_null => frame.push_null(),
+        iconst_m1 | iconst_0 | iconst_1 | iconst_2 | iconst_3 | iconst_4 | iconst_5 => {
+            frame.push_int(opcode as i32 - iconst_0 as i32)
+        }
+        lconst_0 | lconst_1 => frame.push_long((opcode as u8 - lconst_0 as u8) as i64),
+        fconst_0 | fconst_1 | fconst_2 => frame.push_float((opcode as u8 - fconst_0 as u8) as f32),
+        dconst_0 | dconst_1 => frame.push_double((opcode as u8 - dconst_0 as u8) as f64),
+        bipush | sipush => frame.push_int(match operand {
+            Operand::Byte(value) => value as i32,
+            Operand::Short(value) => value as i32,
+            _ => return Err(incorrect_operand(opcode)),
+        }),
+        ldc | ldc_w | ldc2_w => {
+            let index = constant_index(opcode, &operand)?;
+            match constant(&frame.constant_pool, index) {
+                Some(Constant::Integer(value)) => frame.push_int(*value),
+                Some(Constant::Float(value)) => frame.push_float(*value),
+                Some(Constant::Long(value)) => frame.push_long(*value),
+                Some(Constant::Double(value)) => frame.push_double(*value),
+                Some(Constant::String { string_index }) => {
+                    let value = utf8(&frame.constant_pool, *string_index)
+                        .ok_or(Error::UnsupportedConstant(index))?;
+                    let value = String::from_utf8_lossy(value);
+                    frame.push(string_table::intern(&value, || {
+                        string_table::new_string(&value)
+                    })?);
+                }
+                Some(Constant::Class { .. }) => {
+                    let name = class_name(&frame.constant_pool, index)
+                        .ok_or(Error::UnsupportedConstant(index))?;
+                    let class = class_loader::load_class(name)?;
+                    let mirror = class.get_class().mirror().cloned();
+                    frame.push(mirror.ok_or_else(|| not_prepared(&class))?);
+                }
+                _ => return Err(Error::UnsupportedConstant(index)),
+            }
+        }
+        // Loads
+        iload | lload | fload | dload | aload => load(frame, opcode, local(opcode, &operand)?)?,
+        iload_0 | iload_1 | iload_2 | iload_3 => {
+            load(frame, iload, (opcode as u8 - iload_0 as u8) as usize)?
+        }
+        lload_0 | lload_1 | lload_2 | lload_3 => {
+            load(frame, lload, (opcode as u8 - lload_0 as u8) as usize)?
+        }
+        fload_0 | fload_1 | fload_2 | fload_3 => {
+            load(frame, fload, (opcode as u8 - fload_0 as u8) as usize)?
+        }
+        dload_0 | dload_1 | dload_2 | dload_3 => {
+            load(frame, dload, (opcode as u8 - dload_0 as u8) as usize)?
+        }
+        aload_0 | aload_1 | aload_2 | aload_3 => {
+            load(frame, aload, (opcode as u8 - aload_0 as u8) as usize)?
+        }
+        // Stores
+        istore | lstore | fstore | dstore | astore => {
+            store(frame, opcode, local(opcode, &operand)?)?
+        }
+        istore_0 | istore_1 | istore_2 | istore_3 => {
+            store(frame, istore, (opcode as u8 - istore_0 as u8) as usize)?
+        }
+        lstore_0 | lstore_1 | lstore_2 | lstore_3 => {
+            store(frame, lstore, (opcode as u8 - lstore_0 as u8) as usize)?
+        }
+        fstore_0 | fstore_1 | fstore_2 | fstore_3 => {
+            store(frame, fstore, (opcode as u8 - fstore_0 as u8) as usize)?
+        }
+        dstore_0 | dstore_1 | dstore_2 | dstore_3 => {
+            store(frame, dstore, (opcode as u8 - dstore_0 as u8) as usize)?
+        }
+        astore_0 | astore_1 | astore_2 | astore_3 => {
+            store(frame, astore, (opcode as u8 - astore_0 as u8) as usize)?
+        }
+        // Arrays
+        iaload | laload | faload | daload | aaload | baload | caload | saload => {
+            let index = frame.pop_int()?;
+            let array = frame.pop()?;
+            frame.push(array.get_element(index)?);
+        }
+        iastore | lastore | fastore | dastore | aastore | bastore | castore | sastore => {
+            let value = frame.pop()?;
+            let index = frame.pop_int()?;
+            let array = frame.pop()?;
+            array.put_element(index, value)?;
+        }
+        newarray => {
+            let array_type = match operand {
+                Operand::NewArray(array_type) => array_type,
+                _ => return Err(incorrect_operand(opcode)),
+            };
+            let length = frame.pop_int()?;
+            let array = heap().allocate_type_array(array_type, length)?;
+            frame.push(array);
+        }
+        anewarray => {
+            let index = constant_index(opcode, &operand)?;
+            let element_class = class_name(&frame.constant_pool, index)
+                .and_then(dic::find)
+                .ok_or(Error::UnsupportedConstant(index))?;
+            let length = frame.pop_int()?;
+            let array = heap().allocate_object_array(&element_class, length)?;
+            frame.push(array);
+        }
+        arraylength => {
+            let array = frame.pop()?;
+            frame.push_int(array.array_length()?);
+        }
+        // Fields
+        getfield | putfield | getstatic | putstatic => {
+            let index = constant_index(opcode, &operand)?;
+            let field = resolve_field(&frame.constant_pool, index)?;
+            let field_type = &field.field.field.field_type;
+            match opcode {
+                getfield => {
+                    let object = frame.pop()?;
+                    frame.push(object.get_field(field.offset, field_type)?);
+                }
+                putfield => {
+                    let value = frame.pop()?;
+                    let object = frame.pop()?;
+                    object.put_field(field.offset, field_type, value)?;
+                }
+                _ => {
+                    let class = field.field.field.class.clone();
+                    let mirror = class.get_class().mirror().cloned();
+                    let mirror = mirror.ok_or_else(|| not_prepared(&class))?;
+                    if opcode == getstatic {
+                        frame.push(mirror.get_field(field.offset, field_type)?);
+                    } else {
+                        let value = frame.pop()?;
+                        mirror.put_field(field.offset, field_type, value)?;
+                    }
+                }
+            }
+        }
+        // Stack
+        pop => {
+            frame.pop()?;
+        }
+        pop2 => {
+            pop_words(frame, 2)?;
+        }
+        dup => duplicate(frame, 1, 0)?,
+        dup_x1 => duplicate(frame, 1, 1)?,
+        dup_x2 => duplicate(frame, 1, 2)?,
+        dup2 => duplicate(frame, 2, 0)?,
+        dup2_x1 => duplicate(frame, 2, 1)?,
+        dup2_x2 => duplicate(frame, 2, 2)?,
+        swap => {
+            let value1 = frame.pop()?;
+            let value2 = frame.pop()?;
+            frame.push(value1);
+            frame.push(value2);
+        }
+        // Math
+        iadd => binary!(frame, pop_int, push_int, |a, b| a.wrapping_add(b)),
+        ladd => binary!(frame, pop_long, push_long, |a, b| a.wrapping_add(b)),
+        fadd => binary!(frame, pop_float, push_float, |a, b| a + b),
+        dadd => binary!(frame, pop_double, push_double, |a, b| a + b),
+        isub => binary!(frame, pop_int, push_int, |a, b| a.wrapping_sub(b)),
+        lsub => binary!(frame, pop_long, push_long, |a, b| a.wrapping_sub(b)),
+        fsub => binary!(frame, pop_float, push_float, |a, b| a - b),
+        dsub => binary!(frame, pop_double, push_double, |a, b| a - b),
+        imul => binary!(frame, pop_int, push_int, |a, b| a.wrapping_mul(b)),
+        lmul => binary!(frame, pop_long, push_long, |a, b| a.wrapping_mul(b)),
+        fmul => binary!(frame, pop_float, push_float, |a, b| a * b),
+        dmul => binary!(frame, pop_double, push_double, |a, b| a * b),
+        idiv | irem => {
+            let b = frame.pop_int()?;
+            let a = frame.pop_int()?;
+            if b == 0 {
+                return Err(Error::Exception("java/lang/ArithmeticException"));
+            }
+            // Integer.MIN_VALUE / -1 overflows to Integer.MIN_VALUE, with remainder 0
+            frame.push_int(if opcode == idiv {
+                a.wrapping_div(b)
+            } else {
+                a.wrapping_rem(b)
+            });
+        }
+        ldiv | lrem => {
+            let b = frame.pop_long()?;
+            let a = frame.pop_long()?;
+            if b == 0 {
+                return Err(Error::Exception("java/lang/ArithmeticException"));
+            }
+            frame.push_long(if opcode == ldiv {
+                a.wrapping_div(b)
+            } else {
+                a.wrapping_rem(b)
+            });
+        }
+        fdiv => binary!(frame, pop_float, push_float, |a, b| a / b),
+        ddiv => binary!(frame, pop_double, push_double, |a, b| a / b),
+        // `%` truncates like fmod, which is what the JVM's frem and drem do
+        frem => binary!(frame, pop_float, push_float, |a, b| a % b),
+        drem => binary!(frame, pop_double, push_double, |a, b| a % b),
+        ineg => unary!(frame, pop_int, push_int, |a| a.wrapping_neg()),
+        lneg => unary!(frame, pop_long, push_long, |a| a.wrapping_neg()),
+        fneg => unary!(frame, pop_float, push_float, |a| -a),
+        dneg => unary!(frame, pop_double, push_double, |a| -a),
+        // Only the low 5 bits of an int shift distance count, 6 for a long
+        ishl => binary!(frame, pop_int, push_int, |a, b| a << (b & 0x1f)),
+        ishr => binary!(frame, pop_int, push_int, |a, b| a >> (b & 0x1f)),
+        iushr => binary!(frame, pop_int, push_int, |a, b| ((a as u32) >> (b & 0x1f))
+            as i32),
+        lshl => {
+            let b = frame.pop_int()?;
+            let a = frame.pop_long()?;
+            frame.push_long(a << (b & 0x3f));
+        }
+        lshr => {
+            let b = frame.pop_int()?;
+            let a = frame.pop_long()?;
+            frame.push_long(a >> (b & 0x3f));
+        }
+        lushr => {
+            let b = frame.pop_int()?;
+            let a = frame.pop_long()?;
+            frame.push_long(((a as u64) >> (b & 0x3f)) as i64);
+        }
+        iand => binary!(frame, pop_int, push_int, |a, b| a & b),
+        land => binary!(frame, pop_long, push_long, |a, b| a & b),
+        ior => binary!(frame, pop_int, push_int, |a, b| a | b),
+        lor => binary!(frame, pop_long, push_long, |a, b| a | b),
+        ixor => binary!(frame, pop_int, push_int, |a, b| a ^ b),
+        lxor => binary!(frame, pop_long, push_long, |a, b| a ^ b),
+        iinc => {
+            let (index, constant) = match operand {
+                Operand::Iinc {
+                    index, constant, ..
+                } => (index as usize, constant as i32),
+                _ => return Err(incorrect_operand(opcode)),
+            };
+            let mut locals = frame.data_area.local.lock();
+            let value = locals.get_int(index)?;
+            locals.set_int(index, value.wrapping_add(constant))?;
+        }
+        // Conversions. Rust's `as` rounds to nearest, and saturates float to
+        // integer casts with NaN going to 0, as the JVMS asks for.
+        i2l => unary!(frame, pop_int, push_long, |a| a as i64),
+        i2f => unary!(frame, pop_int, push_float, |a| a as f32),
+        i2d => unary!(frame, pop_int, push_double, |a| a as f64),
+        l2i => unary!(frame, pop_long, push_int, |a| a as i32),
+        l2f => unary!(frame, pop_long, push_float, |a| a as f32),
+        l2d => unary!(frame, pop_long, push_double, |a| a as f64),
+        f2i => unary!(frame, pop_float, push_int, |a| a as i32),
+        f2l => unary!(frame, pop_float, push_long, |a| a as i64),
+        f2d => unary!(frame, pop_float, push_double, |a| a as f64),
+        d2i => unary!(frame, pop_double, push_int, |a| a as i32),
+        d2l => unary!(frame, pop_double, push_long, |a| a as i64),
+        d2f => unary!(frame, pop_double, push_float, |a| a as f32),
+        i2b => unary!(frame, pop_int, push_int, |a| a as i8 as i32),
+        i2c => unary!(frame, pop_int, push_int, |a| a as u16 as i32),
+        i2s => unary!(frame, pop_int, push_int, |a| a as i16 as i32),
+        // Comparisons
+        lcmp => binary!(frame, pop_long, push_int, |a, b| a.cmp(&b) as i32),
+        // The two forms only differ in what a NaN compares as
+        fcmpl => binary!(frame, pop_float, push_int, |a, b| compare(
+            a.partial_cmp(&b),
+            -1
+        )),
+        fcmpg => binary!(frame, pop_float, push_int, |a, b| compare(
+            a.partial_cmp(&b),
+            1
+        )),
+        dcmpl => binary!(frame, pop_double, push_int, |a, b| compare(
+            a.partial_cmp(&b),
+            -1
+        )),
+        dcmpg => binary!(frame, pop_double, push_int, |a, b| compare(
+            a.partial_cmp(&b),
+            1
+        )),
+        ifeq | ifne | iflt | ifge | ifgt | ifle => {
+            let value = frame.pop_int()?;
+            return Ok(branch(
+                &operand,
+                holds(opcode as u8 - ifeq as u8, value.cmp(&0)),
+            ));
+        }
+        if_icmpeq | if_icmpne | if_icmplt | if_icmpge | if_icmpgt | if_icmple => {
+            let b = frame.pop_int()?;
+            let a = frame.pop_int()?;
+            return Ok(branch(
+                &operand,
+                holds(opcode as u8 - if_icmpeq as u8, a.cmp(&b)),
+            ));
+        }
+        if_acmpeq | if_acmpne | ifnull | ifnonnull => {
+            let b = match opcode {
+                ifnull | ifnonnull => Oop::Null,
+                _ => frame.pop()?,
+            };
+            let a = frame.pop()?;
+            let same = match (&a, &b) {
+                (Oop::Null, Oop::Null) => true,
+                (Oop::Reference(a), Oop::Reference(b)) => a == b,
+                _ => false,
+            };
+            return Ok(branch(
+                &operand,
+                same == matches!(opcode, if_acmpeq | ifnull),
+            ));
+        }
+        // Control
+        goto | goto_w => return Ok(branch(&operand, true)),
+        tableswitch => {
+            let (default, low, high, targets) = match operand {
+                Operand::TableSwitch {
+                    default,
+                    low,
+                    high,
+                    targets,
+                } => (default, low, high, targets),
+                _ => return Err(incorrect_operand(opcode)),
+            };
+            let key = frame.pop_int()?;
+            let target = if key < low || key > high {
+                default
+            } else {
+                targets
+                    .get((key as i64 - low as i64) as usize)
+                    .copied()
+                    .ok_or_else(|| incorrect_operand(opcode))?
+            };
+            return Ok(Flow::Jump(target));
+        }
+        lookupswitch => {
+            let (default, pairs) = match operand {
+                Operand::LookupSwitch { default, pairs } => (default, pairs),
+                _ => return Err(incorrect_operand(opcode)),
+            };
+            let key = frame.pop_int()?;
+            let target = match pairs.binary_search_by_key(&key, |(key, _)| *key) {
+                Ok(found) => pairs[found].1,
+                Err(_) => default,
+            };
+            return Ok(Flow::Jump(target));
+        }
+        ireturn | lreturn | freturn | dreturn | areturn => {
+            return Ok(Flow::Return(Some(frame.pop()?)))
+        }
+        vreturn => return Ok(Flow::Return(None)),
+        _ => return Err(Error::UnsupportedOpCode(opcode)),
+    }
+    Ok(Flow::Next)
+}
+
+fn constant(constant_pool: &ConstantPoolRef, index: u16) -> Option<&Constant> {
+    constant_pool.get((index as usize).checked_sub(1)?)
+}
+
+fn utf8(constant_pool: &ConstantPoolRef, index: u16) -> Option<&[u8]> {
+    match constant(constant_pool, index)? {
+        Constant::Utf8(bytes) => Some(bytes),
+        _ => None,
+    }
+}
+
+/// Name of the `Class` constant at `index`
+fn class_name(constant_pool: &ConstantPoolRef, index: u16) -> Option<&[u8]> {
+    match constant(constant_pool, index)? {
+        Constant::Class { name_index } => utf8(constant_pool, *name_index),
+        _ => None,
+    }
+}
+
+/// The field a `Fieldref` constant refers to
+fn resolve_field(constant_pool: &ConstantPoolRef, index: u16) -> Result<LaidOutField, Error> {
+    let (class_index, name_and_type_index) = match constant(constant_pool, index) {
+        Some(Constant::FieldRef {
+            class_index,
+            name_and_type_index,
+        }) => (*class_index, *name_and_type_index),
+        _ => return Err(Error::UnsupportedConstant(index)),
+    };
+    let (name, descriptor) = match constant(constant_pool, name_and_type_index) {
+        Some(Constant::NameAndType {
+            name_index,
+            descriptor_index,
+        }) => (
+            utf8(constant_pool, *name_index),
+            utf8(constant_pool, *descriptor_index),
+        ),
+        _ => (None, None),
+    };
+    let class = class_name(constant_pool, class_index)
+        .and_then(dic::find)
+        .ok_or(Error::UnsupportedConstant(index))?;
+    class
+        .get_class()
+        .find_field(name.unwrap_or_default(), descriptor.unwrap_or_default())
+        .ok_or(Error::Exception("java/lang/NoSuchFieldError"))
+}
+
+/// An operand `opcode` never has, which decoding rules out
+fn incorrect_operand(opcode: OpCode) -> Error {
+    Error::Internal(format!("Incorrect operand for {}", opcode.name()))
+}
+
+/// A class whose mirror is asked for before it was prepared, which linking
+/// rules out for the classes in the system dictionary
+fn not_prepared(class: &ClassRef) -> Error {
+    Error::Internal(format!(
+        "Class not prepared: {}",
+        String::from_utf8_lossy(class.name())
+    ))
+}
+
+fn constant_index(opcode: OpCode, operand: &Operand) -> Result<u16, Error> {
+    match operand {
+        Operand::Constant(index) => Ok(*index),
+        _ => Err(incorrect_operand(opcode)),
+    }
+}
+
+fn local(opcode: OpCode, operand: &Operand) -> Result<usize, Error> {
+    match operand {
+        Operand::Local { index, .. } => Ok(*index as usize),
+        _ => Err(incorrect_operand(opcode)),
+    }
+}
+
+/// Pushes local `index` by the type of the `<t>load` opcode
+fn load(frame: &Frame, opcode: OpCode, index: usize) -> Result<(), Error> {
+    let value = {
+        let locals = frame.data_area.local.lock();
+        match opcode {
+            OpCode::iload => Oop::Int(locals.get_int(index)?),
+            OpCode::lload => Oop::Long(locals.get_long(index)?),
+            OpCode::fload => Oop::Float(locals.get_float(index)?),
+            OpCode::dload => Oop::Double(locals.get_double(index)?),
+            _ => locals.get(index)?,
+        }
+    };
+    frame.push(value);
+    Ok(())
+}
+
+/// Pops into local `index` by the type of the `<t>store` opcode
+fn store(frame: &Frame, opcode: OpCode, index: usize) -> Result<(), Error> {
+    let value = frame.pop()?;
+    let mut locals = frame.data_area.local.lock();
+    match (opcode, value) {
+        (OpCode::istore, Oop::Int(value)) => locals.set_int(index, value)?,
+        (OpCode::lstore, Oop::Long(value)) => locals.set_long(index, value)?,
+        (OpCode::fstore, Oop::Float(value)) => locals.set_float(index, value)?,
+        (OpCode::dstore, Oop::Double(value)) => locals.set_double(index, value)?,
+        (OpCode::astore, value @ Oop::Reference(_)) | (OpCode::astore, value @ Oop::Null) => {
+            locals.set(index, value)?
+        }
+        (opcode, value) => {
+            return Err(Error::Internal(format!(
+                "Incorrect Oop for {}: {}",
+                opcode.name(),
+                value
+            )))
+        }
+    }
+    Ok(())
+}
+
+/// Stack words a value takes: 2 for the category 2 `long` and `double`, 1 for
+/// everything else
+fn words(value: &Oop) -> usize {
+    match value {
+        Oop::Long(_) | Oop::Double(_) => 2,
+        _ => 1,
+    }
+}
+
+/// Pops the values making up the top `count` words, top of the stack first
+fn pop_words(frame: &Frame, count: usize) -> Result<Vec<Oop>, Error> {
+    let mut values = vec![];
+    let mut popped = 0;
+    while popped < count {
+        let value = frame.pop()?;
+        popped += words(&value);
+        values.push(value);
+    }
+    if popped != count {
+        return Err(Error::Internal(
+            "Category 2 value split by a stack instruction".to_string(),
+        ));
+    }
+    Ok(values)
+}
+
+/// The `dup` family: copies the top `top` words below the `below` words under
+/// them, which takes care of the forms picked by value category
+fn duplicate(frame: &Frame, top: usize, below: usize) -> Result<(), Error> {
+    let copied = pop_words(frame, top)?;
+    let skipped = pop_words(frame, below)?;
+    for value in copied.iter().rev() {
+        frame.push(value.clone());
+    }
+    for value in skipped.into_iter().rev().chain(copied.into_iter().rev()) {
+        frame.push(value);
+    }
+    Ok(())
+}
+
+/// Result of `<t>cmp<op>`, `nan` if either operand is NaN
+fn compare(ordering: Option<Ordering>, nan: i32) -> i32 {
+    ordering.map_or(nan, |ordering| ordering as i32)
+}
+
+/// Whether the condition of `if<cond>` or `if_icmp<cond>`, by its distance from
+/// the `eq` form, holds for `ordering`
+fn holds(condition: u8, ordering: Ordering) -> bool {
+    match condition {
+        0 => ordering == Ordering::Equal,
+        1 => ordering != Ordering::Equal,
+        2 => ordering == Ordering::Less,
+        3 => ordering != Ordering::Less,
+        4 => ordering == Ordering::Greater,
+        _ => ordering != Ordering::Greater,
+    }
+}
+
+fn branch(operand: &Operand, taken: bool) -> Flow {
+    match operand {
+        Operand::Branch(target) if taken => Flow::Jump(*target),
+        _ => Flow::Next,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::basic_type::BasicType;
+    use crate::runtime::class_loader::define_class;
+    use crate::runtime::class_loader::test::{bytes, define, lock};
+    use crate::runtime::handles::GlobalHandle;
+    use instructions::jasmin::parse;
+    use std::sync::Arc;
+
+    /// A frame for the `run` method of class `name`, which returns
+    /// `return_type` and executes `body`
+    fn frame(name: &str, return_type: &str, body: &str) -> Frame {
+        let class = define(&format!(
+            "
+.class public {}
+.super java/lang/Object
+.method public static run(){}
+    .limit stack 8
+    .limit locals 301
+{}
+.end method
+",
+            name, return_type, body
+        ))
+        .unwrap();
+        let method = class.get_instance().methods[0].clone();
+        Frame::new(method, 0)
+    }
+
+    fn run(name: &str, return_type: &str, body: &str) -> Oop {
+        let _lock = lock();
+        execute(Arc::new(frame(name, return_type, body)))
+            .unwrap()
+            .unwrap()
+    }
+
+    fn int(name: &str, body: &str) -> i32 {
+        match run(name, "I", body) {
+            Oop::Int(value) => value,
+            oop => panic!("Not an int: {}", oop),
+        }
+    }
+
+    fn long(name: &str, body: &str) -> i64 {
+        match run(name, "J", body) {
+            Oop::Long(value) => value,
+            oop => panic!("Not a long: {}", oop),
+        }
+    }
+
+    #[test]
+    fn stack_instructions_keep_category_2_values_whole() {
+        // Form 4: a long duplicated below a long
+        let body = "
+    ldc2_w 2
+    ldc2_w 5
+    dup2_x2
+    lsub
+    lsub
+    lreturn
+";
+        assert_eq!(long("InterpreterDup2X2Longs", body), 8);
+        // Form 2: a double duplicated below two ints, then popped whole
+        let body = "
+    iconst_3
+    iconst_1
+    dconst_1
+    dup2_x2
+    pop2
+    isub
+    i2d
+    dadd
+    dreturn
+";
+        assert!(matches!(
+            run("InterpreterDup2X2Double", "D", body),
+            Oop::Double(value) if value == 3.0
+        ));
+        let body = "
+    iconst_1
+    iconst_2
+    iconst_3
+    pop2
+    ireturn
+";
+        assert_eq!(int("InterpreterPop2Ints", body), 1);
+    }
+
+    #[test]
+    fn float_comparisons_order_nan_by_form() {
+        let nan = "
+    fconst_0
+    fconst_0
+    fdiv
+    fconst_1
+";
+        assert_eq!(
+            int("InterpreterFcmpl", &format!("{}fcmpl\nireturn", nan)),
+            -1
+        );
+        assert_eq!(
+            int("InterpreterFcmpg", &format!("{}fcmpg\nireturn", nan)),
+            1
+        );
+    }
+
+    #[test]
+    fn float_conversions_saturate() {
+        assert_eq!(int("InterpreterF2iMax", "ldc 1e20\nf2i\nireturn"), i32::MAX);
+        assert_eq!(
+            int("InterpreterF2iMin", "ldc -1e20\nf2i\nireturn"),
+            i32::MIN
+        );
+        let nan = "fconst_0\nfconst_0\nfdiv\nf2i\nireturn";
+        assert_eq!(int("InterpreterF2iNaN", nan), 0);
+        assert_eq!(
+            long("InterpreterD2lMax", "ldc2_w 1e300\nd2l\nlreturn"),
+            i64::MAX
+        );
+        assert_eq!(
+            long("InterpreterD2lMin", "ldc2_w -1e300\nd2l\nlreturn"),
+            i64::MIN
+        );
+    }
+
+    #[test]
+    fn shifts_mask_their_distance() {
+        assert_eq!(
+            int("InterpreterIshl", "iconst_1\nbipush 33\nishl\nireturn"),
+            2
+        );
+        assert_eq!(
+            int("InterpreterIushr", "iconst_m1\nbipush 60\niushr\nireturn"),
+            15
+        );
+        assert_eq!(
+            int("InterpreterIshr", "iconst_m1\nbipush 63\nishr\nireturn"),
+            -1
+        );
+        assert_eq!(
+            long("InterpreterLshl", "lconst_1\nbipush 65\nlshl\nlreturn"),
+            2
+        );
+        let lushr = "ldc2_w -1\nbipush 124\nlushr\nlreturn";
+        assert_eq!(long("InterpreterLushr", lushr), 15);
+    }
+
+    #[test]
+    fn switches_jump_by_key() {
+        let switch = |name: &str, key: i32, kind: &str| {
+            let body = format!(
+                "
+    ldc {}
+    {}
+L1:
+    iconst_1
+    ireturn
+L2:
+    iconst_2
+    ireturn
+L3:
+    iconst_3
+    ireturn
+",
+                key, kind
+            );
+            int(&format!("{}{}", name, key.abs()), &body)
+        };
+        let table = "tableswitch 5 6\n        L1\n        L2\n        default : L3";
+        assert_eq!(switch("InterpreterTableswitch", 5, table), 1);
+        assert_eq!(switch("InterpreterTableswitch", 6, table), 2);
+        assert_eq!(switch("InterpreterTableswitch", 7, table), 3);
+        assert_eq!(switch("InterpreterTableswitch", 4, table), 3);
+        let lookup = "lookupswitch\n        -9 : L1\n        100 : L2\n        default : L3";
+        assert_eq!(switch("InterpreterLookupswitch", -9, lookup), 1);
+        assert_eq!(switch("InterpreterLookupswitch", 100, lookup), 2);
+        assert_eq!(switch("InterpreterLookupswitch", 0, lookup), 3);
+    }
+
+    #[test]
+    fn wide_iinc_reaches_high_locals_and_large_constants() {
+        let body = "
+    bipush 7
+    istore 300
+    iinc 300 1000
+    iinc 300 -3
+    iload 300
+    ireturn
+";
+        assert_eq!(int("InterpreterWideIinc", body), 1004);
+    }
+
+    #[test]
+    fn mistyped_operands_are_errors() {
+        let _lock = lock();
+        let frame = frame("InterpreterMistyped", "V", "return");
+        frame.push_float(1.0);
+        let istore = Instruction {
+            opcode: OpCode::istore_0,
+            operand: Operand::None,
+        };
+        assert!(matches!(step(&frame, istore), Err(Error::Internal(_))));
+        frame.push_int(1);
+        let pop2 = Instruction {
+            opcode: OpCode::pop2,
+            operand: Operand::None,
+        };
+        frame.push_long(2);
+        frame.push_int(3);
+        assert!(matches!(step(&frame, pop2), Err(Error::Internal(_))));
+        let iadd = Instruction {
+            opcode: OpCode::iadd,
+            operand: Operand::None,
+        };
+        assert!(matches!(step(&frame, iadd), Err(Error::Internal(_))));
+        // Operands decoding never gives an instruction
+        let bipush = Instruction {
+            opcode: OpCode::bipush,
+            operand: Operand::None,
+        };
+        assert!(matches!(step(&frame, bipush), Err(Error::Internal(_))));
+        let getstatic = Instruction {
+            opcode: OpCode::getstatic,
+            operand: Operand::Byte(1),
+        };
+        assert!(matches!(step(&frame, getstatic), Err(Error::Internal(_))));
+        // A local past max_locals
+        frame.push_long(4);
+        let lstore = Instruction {
+            opcode: OpCode::lstore,
+            operand: Operand::Local {
+                index: 300,
+                wide: true,
+            },
+        };
+        assert!(matches!(step(&frame, lstore), Err(Error::Internal(_))));
+    }
+
+    #[test]
+    fn string_constants_are_interned() {
+        let body = "
+    ldc \"InterpreterInterned\"
+    ldc \"InterpreterInterned\"
+    if_acmpne Different
+    ldc \"InterpreterInterned\"
+    areturn
+Different:
+    aconst_null
+    areturn
+";
+        let string = run("InterpreterLdcString", "Ljava/lang/String;", body);
+        assert!(matches!(string, Oop::Reference(_)));
+        let _lock = lock();
+        let interned = string_table::lookup("InterpreterInterned").unwrap();
+        assert!(matches!(
+            (&string, &interned),
+            (Oop::Reference(a), Oop::Reference(b)) if a == b
+        ));
+        let class = dic::find(b"java/lang/String").unwrap();
+        let value = class.get_class().find_field(b"value", b"[C").unwrap();
+        let chars = string.get_field(value.offset, &BasicType::ARRAY).unwrap();
+        assert_eq!(chars.array_length().unwrap(), 19);
+        assert!(matches!(chars.get_element(0), Ok(Oop::Int(0x49))));
+    }
+
+    #[test]
+    fn class_constants_push_the_mirror() {
+        let _lock = lock();
+        // Jasmin has no class constant for ldc, the string constant naming
+        // the class becomes one
+        let mut class_file = parse(
+            "
+.class public InterpreterLdcClass
+.super java/lang/Object
+.method public static run()Ljava/lang/Class;
+    .limit stack 1
+    .limit locals 0
+    ldc \"InterpreterLdcClass\"
+    areturn
+.end method
+",
+        )
+        .unwrap();
+        let constant_pool = Arc::make_mut(&mut class_file.constant_pool);
+        for constant in constant_pool.iter_mut() {
+            if let Constant::String { string_index } = *constant {
+                *constant = Constant::Class {
+                    name_index: string_index,
+                };
+            }
+        }
+        let class = define_class(&bytes(&class_file)).unwrap();
+        let method = class.get_instance().methods[0].clone();
+        let mirror = execute(Arc::new(Frame::new(method, 0))).unwrap().unwrap();
+        assert!(matches!(
+            (&mirror, class.get_class().mirror().unwrap()),
+            (Oop::Reference(a), Oop::Reference(b)) if a == b
+        ));
+    }
+
+    #[test]
+    fn array_stores_check_the_element_class() {
+        let body = "
+    iconst_1
+    anewarray InterpreterElement
+    iconst_0
+    iconst_1
+    newarray int
+    aastore
+    return
+";
+        {
+            let _lock = lock();
+            let element =
+                define(".class public InterpreterElement\n.super java/lang/Object\n").unwrap();
+            let sub_element =
+                define(".class public InterpreterSubElement\n.super InterpreterElement\n").unwrap();
+            // Allocating the instances may move the array
+            let array = GlobalHandle::new(heap().allocate_object_array(&element, 1).unwrap());
+            let instance = heap().allocate_instance(&sub_element).unwrap();
+            array.get().put_element(0, instance).unwrap();
+            let instance = heap().allocate_instance(&dic::find(b"java/lang/String").unwrap());
+            assert!(matches!(
+                array.get().put_element(0, instance.unwrap()),
+                Err(Error::Exception("java/lang/ArrayStoreException"))
+            ));
+            let frame = Arc::new(frame("InterpreterArrayStore", "V", body));
+            assert!(matches!(
+                execute(frame),
+                Err(Error::Exception("java/lang/ArrayStoreException"))
+            ));
+        }
+        // Any array is an Object
+        let body = "
+    iconst_1
+    anewarray java/lang/Object
+    dup
+    iconst_0
+    iconst_1
+    newarray int
+    aastore
+    iconst_0
+    aaload
+    ifnull Empty
+    iconst_1
+    ireturn
+Empty:
+    iconst_0
+    ireturn
+";
+        assert_eq!(int("InterpreterObjectArrayStore", body), 1);
+    }
+}
@@ -1,8 +1,12 @@
 extern crate log;
 extern crate simplelog;
 
+use instructions::opcode::OpCode;
+
 pub mod basic_type;
 pub mod classpath;
+pub mod gc;
+pub mod interpreter;
 #[macro_use]
 pub mod macros;
 pub mod oops;
@@ -11,6 +15,24 @@ pub mod stack;
 pub mod sys;
 pub mod types;
 
+#[derive(Debug)]
 pub enum Error {
     StackOverflow,
+    // Class loading
+    /// The class file doesn't parse
+    ClassFormat(classfile::error::Error),
+    /// The class file failed verification
+    Verify(instructions::verifier::VerifyError),
+    // Interpreter
+    /// The code at the pc doesn't decode to an instruction
+    InvalidCode(instructions::error::Error),
+    /// An instruction the interpreter doesn't execute yet
+    UnsupportedOpCode(OpCode),
+    /// A constant `ldc` can't push yet, by constant pool index
+    UnsupportedConstant(u16),
+    /// An exception thrown by an instruction, by class name
+    Exception(&'static str),
+    /// A mistyped local or operand, or a split category 2 value, which
+    /// verified code never has
+    Internal(String),
 }
@@ -1,10 +1,20 @@
 use crate::basic_type::BasicType;
+use crate::gc::heap::heap;
+use crate::oops::field::{Field, FieldId};
+use crate::oops::field_layout::{FieldLayout, LaidOutField};
+use crate::oops::method::{Method, MethodId};
 use crate::oops::Oop;
-use crate::runtime::class_loader::ClassLoader;
+use crate::runtime::class_loader::{self, ClassLoader};
+use crate::runtime::string_table;
 use crate::types::{ClassRef, FieldIdRef, MethodIdRef};
-use classfile::access_flags::AccessFlags;
+use crate::Error;
+use classfile::access_flags::ClassAccessFlags;
+use classfile::attribute::AttributeType;
 use classfile::class_file::ClassFileRef;
+use classfile::constant::{get_class_name, get_utf8, Constant};
+use classfile::field::FieldInfo;
 use classfile::{BytesRef, ConstantPoolRef};
+use instructions::verifier::{self, ClassHierarchy};
 use parking_lot::ReentrantMutex;
 use std::fmt::{self, Display, Formatter};
 use std::sync::{Arc, Mutex};
@@ -67,7 +77,7 @@ pub struct Class {
     mutex: ReentrantMutex<()>,
     clint_mutex: Arc<Mutex<()>>,
     class_state: ClassState,
-    pub access_flags: AccessFlags,
+    pub access_flags: ClassAccessFlags,
     pub constant_pool: ConstantPoolRef,
     // java/lang/String, etc
     pub name: BytesRef,
@@ -78,9 +88,89 @@ pub struct Class {
     // None for the bootstrap loader
     pub class_loader: Option<ClassLoader>,
     pub instance: Instance,
+    field_layout: Option<FieldLayout>,
 }
 
 impl Class {
+    /// A `Loaded` class for `class_file`, with its fields and methods
+    pub fn allocate_instance_class(
+        class_file: ClassFileRef,
+        super_class: Option<ClassRef>,
+        interfaces: Vec<ClassRef>,
+    ) -> ClassRef {
+        let constant_pool = class_file.constant_pool.clone();
+        let class = Class {
+            mutex: ReentrantMutex::new(()),
+            clint_mutex: Arc::new(Mutex::new(())),
+            class_state: ClassState::Loaded,
+            access_flags: class_file.flags(),
+            name: get_class_name(&constant_pool, class_file.this_class as usize).clone(),
+            constant_pool,
+            super_class,
+            sub_classes: None,
+            class_file: class_file.clone(),
+            class_loader: None,
+            // Replaced below, the fields and methods refer back to the class
+            instance: Instance::TypeArray(TypeArrayInstance {
+                dimension: 0,
+                element_type: BasicType::VOID,
+                max_length: 0,
+                mirror: None,
+            }),
+            field_layout: None,
+        };
+        let class = Arc::new(ClassPtr(Box::into_raw(Box::new(class)) as u64));
+        let constant_pool = &class_file.constant_pool;
+        let fields = class_file
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(index, field_info)| {
+                let descriptor = get_utf8(constant_pool, field_info.descriptor_index as usize);
+                let access_flags = field_info.flags();
+                let constant_value = match access_flags.is_static() {
+                    true => constant_value(constant_pool, field_info),
+                    false => None,
+                };
+                Arc::new(FieldId {
+                    index,
+                    field: Field {
+                        access_flags,
+                        class: class.clone(),
+                        name: get_utf8(constant_pool, field_info.name_index as usize).clone(),
+                        field_type: BasicType::from(descriptor[0]),
+                        field_info: field_info.clone(),
+                        constant_value,
+                    },
+                })
+            })
+            .collect();
+        let methods = class_file
+            .methods
+            .iter()
+            .enumerate()
+            .map(|(index, method_info)| {
+                Arc::new(MethodId {
+                    index,
+                    method: Method {
+                        access_flags: method_info.flags(),
+                        class: class.clone(),
+                        offset: index,
+                        method_info: method_info.clone(),
+                    },
+                })
+            })
+            .collect();
+        class.get_mut_class().instance = Instance::Instance(ObjectInstance {
+            class: class.clone(),
+            interfaces,
+            methods,
+            fields,
+            mirror: None,
+        });
+        class
+    }
+
     pub fn get_name(&self) -> BytesRef {
         self.name.clone()
     }
@@ -92,7 +182,23 @@ impl Class {
         }
     }
 
-    pub fn access_flags(&self) -> &AccessFlags {
+    /// Whether an instance of the class is an instance of `class`: the class
+    /// is `class`, extends or implements it
+    pub fn is_assignable_to(&self, class: &Class) -> bool {
+        if self.name == class.name {
+            return true;
+        }
+        let interfaces = match &self.instance {
+            Instance::Instance(instance) => &instance.interfaces[..],
+            _ => &[],
+        };
+        self.super_class
+            .iter()
+            .chain(interfaces)
+            .any(|super_type| super_type.get_class().is_assignable_to(class))
+    }
+
+    pub fn access_flags(&self) -> &ClassAccessFlags {
         &self.access_flags
     }
 
@@ -149,6 +255,100 @@ impl Class {
         self.sub_classes.clone()
     }
 
+    /// Where the fields live, once the class is prepared
+    pub fn field_layout(&self) -> Option<&FieldLayout> {
+        self.field_layout.as_ref()
+    }
+
+    /// The `java.lang.Class` object, holding the static fields, once the class
+    /// is prepared
+    pub fn mirror(&self) -> Option<&Oop> {
+        match &self.instance {
+            Instance::Instance(instance) => instance.mirror.as_ref(),
+            Instance::ObjectArray(array) => array.mirror.as_ref(),
+            Instance::TypeArray(array) => array.mirror.as_ref(),
+        }
+    }
+
+    pub(crate) fn mirror_mut(&mut self) -> Option<&mut Oop> {
+        match &mut self.instance {
+            Instance::Instance(instance) => instance.mirror.as_mut(),
+            Instance::ObjectArray(array) => array.mirror.as_mut(),
+            Instance::TypeArray(array) => array.mirror.as_mut(),
+        }
+    }
+
+    /// The field `name` with `descriptor`, declared by the class or inherited,
+    /// see "The Java Virtual Machine Specification" section 5.4.3.2
+    pub fn find_field(&self, name: &[u8], descriptor: &[u8]) -> Option<LaidOutField> {
+        if let Some(field) = self.field_layout()?.find(name, descriptor) {
+            return Some(field.clone());
+        }
+        if let Instance::Instance(instance) = &self.instance {
+            for interface in &instance.interfaces {
+                if let Some(field) = interface.get_class().find_field(name, descriptor) {
+                    return Some(field);
+                }
+            }
+        }
+        self.super_class
+            .as_ref()?
+            .get_class()
+            .find_field(name, descriptor)
+    }
+
+    pub fn state(&self) -> &ClassState {
+        &self.class_state
+    }
+
+    /// Links `class`: verifies its class file, then prepares it, see "The Java
+    /// Virtual Machine Specification" section 5.4. The class is only `Linked`
+    /// once both passed, its super class and interfaces must be linked.
+    pub fn link(class: &ClassRef) -> Result<(), Error> {
+        if let ClassState::Allocated | ClassState::Loaded = class.get_class().class_state {
+            verifier::verify(&class.get_class().class_file, &LoadedClasses)
+                .map_err(Error::Verify)?;
+            Class::prepare(class)?;
+            class.get_mut_class().class_state = ClassState::Linked;
+        }
+        Ok(())
+    }
+
+    /// Lays out the fields of `class` and allocates its mirror, with the static
+    /// fields set to their `ConstantValue` or default values. String constants
+    /// are interned, as `ldc` of the same string pushes the same object.
+    fn prepare(class: &ClassRef) -> Result<(), Error> {
+        let layout = FieldLayout::compute(class.get_class())?;
+        let fields = layout.fields.clone();
+        class.get_mut_class().field_layout = Some(layout);
+        let mirror = heap().allocate_mirror(class)?;
+        // Mirrors don't move, and from here the class keeps this one alive
+        // through the collections interning may start
+        match &mut class.get_mut_class().instance {
+            Instance::Instance(instance) => instance.mirror = Some(mirror.clone()),
+            Instance::ObjectArray(array) => array.mirror = Some(mirror.clone()),
+            Instance::TypeArray(array) => array.mirror = Some(mirror.clone()),
+        }
+        let constant_pool = class.get_class().constant_pool.clone();
+        for field in fields {
+            let declared = &field.field.field;
+            if !declared.is_static() {
+                continue;
+            }
+            let value = match &declared.constant_value {
+                Some(value) => value.clone(),
+                None => match string_constant(&constant_pool, &declared.field_info) {
+                    Some(value) => {
+                        string_table::intern(&value, || string_table::new_string(&value))?
+                    }
+                    None => continue,
+                },
+            };
+            mirror.put_field(field.offset, &declared.field_type, value)?;
+        }
+        Ok(())
+    }
+
     pub fn initialize(&self) {
         unimplemented!()
     }
@@ -162,6 +362,68 @@ impl Class {
     }
 }
 
+/// The value of the `ConstantValue` attribute of a static field. String
+/// constants need the heap, see `string_constant`.
+fn constant_value(constant_pool: &ConstantPoolRef, field_info: &FieldInfo) -> Option<Oop> {
+    match constant_value_attribute(constant_pool, field_info)? {
+        Constant::Integer(value) => Some(Oop::Int(*value)),
+        Constant::Float(value) => Some(Oop::Float(*value)),
+        Constant::Long(value) => Some(Oop::Long(*value)),
+        Constant::Double(value) => Some(Oop::Double(*value)),
+        _ => None,
+    }
+}
+
+/// The string the `ConstantValue` attribute of a static field holds, interned
+/// once the class is prepared
+fn string_constant(constant_pool: &ConstantPoolRef, field_info: &FieldInfo) -> Option<String> {
+    match constant_value_attribute(constant_pool, field_info)? {
+        Constant::String { string_index } => {
+            match constant_pool.get((*string_index as usize).checked_sub(1)?)? {
+                Constant::Utf8(value) => Some(String::from_utf8_lossy(value).into_owned()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn constant_value_attribute<'a>(
+    constant_pool: &'a ConstantPoolRef,
+    field_info: &FieldInfo,
+) -> Option<&'a Constant> {
+    let index = field_info
+        .attributes
+        .iter()
+        .find_map(|attribute| match attribute.attr_type {
+            AttributeType::ConstantValue {
+                constant_value_index,
+            } => Some(constant_value_index),
+            _ => None,
+        })?;
+    constant_pool.get((index as usize).checked_sub(1)?)
+}
+
+/// Hierarchy of the classes the bootstrap loader can load, loading those not
+/// in the system dictionary yet. A class that fails to load, including one
+/// whose loading runs into a class still being defined on this thread (the
+/// `ClassCircularityError` of `load_class`), has no super class and is not
+/// an interface, so the verifier rejects assignments to or from it.
+pub(crate) struct LoadedClasses;
+
+impl ClassHierarchy for LoadedClasses {
+    fn super_class(&self, class: &str) -> Option<String> {
+        let class = class_loader::load_class(class.as_bytes()).ok()?;
+        let super_class = class.get_class().super_class()?;
+        Some(String::from_utf8_lossy(super_class.name()).into_owned())
+    }
+
+    fn is_interface(&self, class: &str) -> bool {
+        class_loader::load_class(class.as_bytes())
+            .is_ok_and(|class| class.get_class().is_interface())
+    }
+}
+
 impl Display for Class {
     // print class code
     fn fmt(&self, _f: &mut Formatter<'_>) -> fmt::Result {
@@ -177,7 +439,7 @@ pub enum ClassState {
     Allocated,
     // loaded and inserted in class hierarchy (but not linked yet)
     Loaded,
-    // successfully linked/verified (but not initialized yet)
+    // successfully verified and prepared (but not initialized yet)
     Linked,
     // currently running class initializer
     BeingInitialized,
@@ -204,8 +466,8 @@ pub struct ObjectInstance {
     pub class: ClassRef,
     pub interfaces: Vec<ClassRef>,
     pub methods: Vec<MethodIdRef>,
-    // field, value
-    pub fields: Vec<(FieldIdRef, Oop)>,
+    // declared fields, the values live in instances and the mirror
+    pub fields: Vec<FieldIdRef>,
     pub mirror: Option<Oop>,
 }
 
@@ -225,15 +487,10 @@ pub struct TypeArrayInstance {
 }
 
 impl ObjectInstance {
-    #[inline]
-    pub fn is_gc_marked(&self) -> bool {
-        unimplemented!()
-    }
-
     pub fn static_field_size(&self) -> usize {
         let mut size: usize = 0;
         for field in &self.fields {
-            if !field.0.field.is_static() {
+            if field.field.is_static() {
                 size += 1;
             }
         }
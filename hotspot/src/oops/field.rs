@@ -1,7 +1,7 @@
 use crate::basic_type::BasicType;
 use crate::oops::Oop;
 use crate::types::ClassRef;
-use classfile::access_flags::AccessFlags;
+use classfile::access_flags::FieldAccessFlags;
 use classfile::field::FieldInfo;
 use classfile::BytesRef;
 
@@ -11,7 +11,7 @@ pub struct FieldId {
 }
 
 pub struct Field {
-    pub access_flags: AccessFlags,
+    pub access_flags: FieldAccessFlags,
     pub class: ClassRef,
     pub name: BytesRef,
     pub field_type: BasicType,
@@ -40,7 +40,7 @@ impl Field {
     }
 
     // Access flags
-    pub fn access_flags(&self) -> &AccessFlags {
+    pub fn access_flags(&self) -> &FieldAccessFlags {
         &self.access_flags
     }
 
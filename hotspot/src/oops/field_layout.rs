@@ -0,0 +1,309 @@
+use crate::basic_type::BasicType;
+use crate::oops::class::{Class, Instance};
+use crate::oops::{Oop, OopRef};
+use crate::types::FieldIdRef;
+use crate::Error;
+use classfile::constant::get_utf8;
+use classfile::BytesRef;
+use std::cmp::Reverse;
+use std::convert::TryInto;
+use std::sync::Arc;
+
+/// Where the fields of a class live: instance fields in its instances, after
+/// the inherited ones, and static fields in its mirror. Offsets count from the
+/// end of the object header.
+#[derive(Clone)]
+pub struct FieldLayout {
+    /// The fields the class declares, instance and static
+    pub fields: Vec<LaidOutField>,
+    /// Bytes the instance fields take, inherited ones included
+    pub instance_size: usize,
+    pub static_size: usize,
+    /// Offsets of the instance fields holding references, inherited ones
+    /// included
+    pub instance_oops: Vec<usize>,
+    pub static_oops: Vec<usize>,
+}
+
+#[derive(Clone)]
+pub struct LaidOutField {
+    pub field: FieldIdRef,
+    pub descriptor: BytesRef,
+    pub offset: usize,
+}
+
+impl FieldLayout {
+    /// Lays out the fields `class` declares. Fields are placed largest first so
+    /// each is aligned to its size, smaller ones fill the padding left behind,
+    /// and references come first within their size so the collector finds them
+    /// together.
+    pub fn compute(class: &Class) -> Result<FieldLayout, Error> {
+        let super_layout = class
+            .super_class
+            .as_ref()
+            .and_then(|super_class| super_class.get_class().field_layout());
+        let mut instance = Packer {
+            end: super_layout.map_or(0, |layout| layout.instance_size),
+            holes: vec![],
+        };
+        let mut statics = Packer {
+            end: 0,
+            holes: vec![],
+        };
+        let mut instance_oops = super_layout.map_or(vec![], |layout| layout.instance_oops.clone());
+        let mut static_oops = vec![];
+
+        let mut declared: Vec<(usize, &FieldIdRef)> = vec![];
+        if let Instance::Instance(instance) = &class.instance {
+            for field in &instance.fields {
+                declared.push((size_of(&field.field.field_type)?, field));
+            }
+        }
+        declared
+            .sort_by_key(|(size, field)| (Reverse(*size), !is_reference(&field.field.field_type)));
+
+        let mut fields = vec![];
+        for (size, field) in declared {
+            let field_type = &field.field.field_type;
+            let (packer, oops) = if field.field.is_static() {
+                (&mut statics, &mut static_oops)
+            } else {
+                (&mut instance, &mut instance_oops)
+            };
+            let offset = packer.place(size);
+            if is_reference(field_type) {
+                oops.push(offset);
+            }
+            let descriptor = get_utf8(
+                &class.constant_pool,
+                field.field.field_info.descriptor_index as usize,
+            );
+            fields.push(LaidOutField {
+                field: field.clone(),
+                descriptor: descriptor.clone(),
+                offset,
+            });
+        }
+        instance_oops.sort_unstable();
+        static_oops.sort_unstable();
+
+        Ok(FieldLayout {
+            fields,
+            instance_size: instance.end,
+            static_size: statics.end,
+            instance_oops,
+            static_oops,
+        })
+    }
+
+    /// The field `name` with `descriptor` the class declares
+    pub fn find(&self, name: &[u8], descriptor: &[u8]) -> Option<&LaidOutField> {
+        self.fields.iter().find(|field| {
+            field.field.field.name.as_slice() == name && field.descriptor.as_slice() == descriptor
+        })
+    }
+}
+
+/// Places fields at the end of what was laid out so far, or in the padding
+/// alignment left before
+struct Packer {
+    end: usize,
+    /// Unused `(offset, size)` ranges before `end`
+    holes: Vec<(usize, usize)>,
+}
+
+impl Packer {
+    fn place(&mut self, size: usize) -> usize {
+        for i in 0..self.holes.len() {
+            let (start, length) = self.holes[i];
+            let offset = align(start, size);
+            if offset + size <= start + length {
+                self.holes.remove(i);
+                if offset > start {
+                    self.holes.push((start, offset - start));
+                }
+                if offset + size < start + length {
+                    self.holes
+                        .push((offset + size, start + length - offset - size));
+                }
+                self.holes.sort_unstable();
+                return offset;
+            }
+        }
+        let offset = align(self.end, size);
+        if offset > self.end {
+            self.holes.push((self.end, offset - self.end));
+        }
+        self.end = offset + size;
+        offset
+    }
+}
+
+fn align(offset: usize, size: usize) -> usize {
+    (offset + size - 1) & !(size - 1)
+}
+
+/// Bytes a field of `field_type` takes
+pub fn size_of(field_type: &BasicType) -> Result<usize, Error> {
+    match field_type {
+        BasicType::BOOLEAN | BasicType::BYTE => Ok(1),
+        BasicType::CHAR | BasicType::SHORT => Ok(2),
+        BasicType::INT | BasicType::FLOAT => Ok(4),
+        BasicType::LONG | BasicType::DOUBLE | BasicType::OBJECT | BasicType::ARRAY => Ok(8),
+        BasicType::VOID => Err(Error::Internal("Field of type void".to_string())),
+    }
+}
+
+fn is_reference(field_type: &BasicType) -> bool {
+    matches!(field_type, BasicType::OBJECT | BasicType::ARRAY)
+}
+
+/// The bytes of a field of `field_type` at `offset` in `data`
+fn field(
+    data: &[u8],
+    offset: usize,
+    field_type: &BasicType,
+) -> Result<std::ops::Range<usize>, Error> {
+    let end = offset + size_of(field_type)?;
+    if end > data.len() {
+        return Err(Error::Internal(format!("Invalid field offset: {}", offset)));
+    }
+    Ok(offset..end)
+}
+
+/// The value of a field of `field_type` at `offset` in `data`, as `getfield`
+/// pushes it
+pub fn load(data: &[u8], offset: usize, field_type: &BasicType) -> Result<Oop, Error> {
+    let bytes = &data[field(data, offset, field_type)?];
+    Ok(match field_type {
+        BasicType::BOOLEAN | BasicType::BYTE => Oop::Int(bytes[0] as i8 as i32),
+        BasicType::CHAR => Oop::Int(u16::from_le_bytes(bytes.try_into().unwrap()) as i32),
+        BasicType::SHORT => Oop::Int(i16::from_le_bytes(bytes.try_into().unwrap()) as i32),
+        BasicType::INT => Oop::Int(i32::from_le_bytes(bytes.try_into().unwrap())),
+        BasicType::FLOAT => Oop::Float(f32::from_le_bytes(bytes.try_into().unwrap())),
+        BasicType::LONG => Oop::Long(i64::from_le_bytes(bytes.try_into().unwrap())),
+        BasicType::DOUBLE => Oop::Double(f64::from_le_bytes(bytes.try_into().unwrap())),
+        BasicType::OBJECT | BasicType::ARRAY => {
+            match u64::from_le_bytes(bytes.try_into().unwrap()) {
+                0 => Oop::Null,
+                address => Oop::Reference(Arc::new(OopRef::new(address - 1))),
+            }
+        }
+        BasicType::VOID => return Err(Error::Internal("Field of type void".to_string())),
+    })
+}
+
+/// Stores `value` in a field of `field_type` at `offset` in `data`, narrowing
+/// ints as `putfield` does. References are kept as their address plus one, so
+/// `null` is 0 like in a freshly allocated object.
+pub fn store(
+    data: &mut [u8],
+    offset: usize,
+    field_type: &BasicType,
+    value: &Oop,
+) -> Result<(), Error> {
+    let range = field(data, offset, field_type)?;
+    let bytes = &mut data[range];
+    match (field_type, value) {
+        (BasicType::BOOLEAN, Oop::Int(value)) => bytes[0] = (value & 1) as u8,
+        (BasicType::BYTE, Oop::Int(value)) => bytes[0] = *value as u8,
+        (BasicType::CHAR, Oop::Int(value)) => bytes.copy_from_slice(&(*value as u16).to_le_bytes()),
+        (BasicType::SHORT, Oop::Int(value)) => {
+            bytes.copy_from_slice(&(*value as i16).to_le_bytes())
+        }
+        (BasicType::INT, Oop::Int(value)) => bytes.copy_from_slice(&value.to_le_bytes()),
+        (BasicType::FLOAT, Oop::Float(value)) => bytes.copy_from_slice(&value.to_le_bytes()),
+        (BasicType::LONG, Oop::Long(value)) => bytes.copy_from_slice(&value.to_le_bytes()),
+        (BasicType::DOUBLE, Oop::Double(value)) => bytes.copy_from_slice(&value.to_le_bytes()),
+        (BasicType::OBJECT, Oop::Null) | (BasicType::ARRAY, Oop::Null) => bytes.fill(0),
+        (BasicType::OBJECT, Oop::Reference(reference))
+        | (BasicType::ARRAY, Oop::Reference(reference)) => {
+            bytes.copy_from_slice(&(reference.address() + 1).to_le_bytes())
+        }
+        (_, value) => return Err(Error::Internal(format!("Incorrect Oop: {}", value))),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime::class_loader::test::{define, lock};
+    use crate::types::ClassRef;
+
+    fn offset(class: &ClassRef, name: &str, descriptor: &str) -> usize {
+        let field = class
+            .get_class()
+            .find_field(name.as_bytes(), descriptor.as_bytes())
+            .unwrap();
+        field.offset
+    }
+
+    #[test]
+    fn fields_follow_the_super_class_and_fill_holes() {
+        let _lock = lock();
+        let base = define(
+            "
+.class public LayoutBase
+.super java/lang/Object
+.field public b B
+.field public a J
+",
+        )
+        .unwrap();
+        let layout = base.get_class().field_layout().unwrap();
+        assert_eq!(offset(&base, "a", "J"), 0);
+        assert_eq!(offset(&base, "b", "B"), 8);
+        assert_eq!(layout.instance_size, 9);
+
+        let derived = define(
+            "
+.class public LayoutDerived
+.super LayoutBase
+.field public z Z
+.field public c I
+.field public l J
+.field public s S
+.field public r Ljava/lang/Object;
+.field public static count I
+.field public static self LLayoutDerived;
+",
+        )
+        .unwrap();
+        // The references come first among the 8 byte fields, aligned past the
+        // inherited ones, and the smaller fields fill the hole left before
+        assert_eq!(offset(&derived, "r", "Ljava/lang/Object;"), 16);
+        assert_eq!(offset(&derived, "l", "J"), 24);
+        assert_eq!(offset(&derived, "c", "I"), 12);
+        assert_eq!(offset(&derived, "s", "S"), 10);
+        assert_eq!(offset(&derived, "z", "Z"), 9);
+        assert_eq!(offset(&derived, "b", "B"), 8);
+        let layout = derived.get_class().field_layout().unwrap();
+        assert_eq!(layout.instance_size, 32);
+        assert_eq!(layout.instance_oops, vec![16]);
+
+        assert_eq!(offset(&derived, "self", "LLayoutDerived;"), 0);
+        assert_eq!(offset(&derived, "count", "I"), 8);
+        assert_eq!(layout.static_size, 12);
+        assert_eq!(layout.static_oops, vec![0]);
+    }
+
+    #[test]
+    fn malformed_accesses_are_errors() {
+        let mut data = vec![0; 8];
+        store(&mut data, 4, &BasicType::INT, &Oop::Int(7)).unwrap();
+        assert!(matches!(load(&data, 4, &BasicType::INT), Ok(Oop::Int(7))));
+        assert!(matches!(
+            load(&data, 4, &BasicType::LONG),
+            Err(Error::Internal(_))
+        ));
+        assert!(matches!(
+            store(&mut data, 0, &BasicType::INT, &Oop::Float(1.0)),
+            Err(Error::Internal(_))
+        ));
+        assert!(matches!(
+            load(&data, 0, &BasicType::VOID),
+            Err(Error::Internal(_))
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+/// The first word of every object header. The bit layout follows 64 bit HotSpot:
+///
+///  unused:25 hash:31 -->| unused_gap:1 age:4 biased_lock:1 lock:2
+///
+/// A hash of 0 means the identity hash wasn't asked for yet. Once a young
+/// collection copied the object away, the marked lock bits come with the new
+/// address in the rest of the word instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkWord(u64);
+
+impl MarkWord {
+    const LOCK_MASK: u64 = 0b11;
+    const AGE_SHIFT: u64 = 3;
+    const AGE_MASK: u64 = 0b1111;
+    const HASH_SHIFT: u64 = 8;
+    const HASH_MASK: u64 = 0x7fff_ffff;
+    const FORWARDEE_SHIFT: u64 = 2;
+
+    const UNLOCKED_VALUE: u64 = 0b01;
+    const MARKED_VALUE: u64 = 0b11;
+
+    /// Oldest age an object reaches, the age field saturates there
+    pub const MAX_AGE: u8 = 15;
+
+    /// Header of a newly allocated object: unlocked, age 0 and no hash
+    pub fn new() -> Self {
+        MarkWord(Self::UNLOCKED_VALUE)
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    pub fn hash(&self) -> Option<i32> {
+        match (self.0 >> Self::HASH_SHIFT) & Self::HASH_MASK {
+            0 => None,
+            hash => Some(hash as i32),
+        }
+    }
+
+    /// With the identity hash set to the low 31 bits of `hash`, which must not
+    /// be 0
+    pub fn with_hash(self, hash: u32) -> Self {
+        let hash = hash as u64 & Self::HASH_MASK;
+        debug_assert_ne!(hash, 0);
+        MarkWord(self.0 & !(Self::HASH_MASK << Self::HASH_SHIFT) | hash << Self::HASH_SHIFT)
+    }
+
+    /// Number of collections the object survived
+    pub fn age(&self) -> u8 {
+        ((self.0 >> Self::AGE_SHIFT) & Self::AGE_MASK) as u8
+    }
+
+    pub fn with_age(self, age: u8) -> Self {
+        let age = age.min(Self::MAX_AGE) as u64;
+        MarkWord(self.0 & !(Self::AGE_MASK << Self::AGE_SHIFT) | age << Self::AGE_SHIFT)
+    }
+
+    /// Whether the collector marked the object as reachable
+    pub fn is_marked(&self) -> bool {
+        self.0 & Self::LOCK_MASK == Self::MARKED_VALUE
+    }
+
+    pub fn marked(self) -> Self {
+        MarkWord(self.0 & !Self::LOCK_MASK | Self::MARKED_VALUE)
+    }
+
+    pub fn unmarked(self) -> Self {
+        MarkWord(self.0 & !Self::LOCK_MASK | Self::UNLOCKED_VALUE)
+    }
+
+    /// Header left behind in from-space by an object copied to `address`
+    pub fn forwarded(address: u64) -> Self {
+        MarkWord(address << Self::FORWARDEE_SHIFT | Self::MARKED_VALUE)
+    }
+
+    /// Where the object was copied to, only meaningful during a young
+    /// collection as marked objects look forwarded too
+    pub fn forwardee(&self) -> Option<u64> {
+        match self.is_marked() {
+            true => Some(self.0 >> Self::FORWARDEE_SHIFT),
+            false => None,
+        }
+    }
+}
+
+impl Default for MarkWord {
+    fn default() -> Self {
+        MarkWord::new()
+    }
+}
@@ -1,8 +1,13 @@
 use crate::basic_type::BasicType;
 use crate::types::ClassRef;
-use classfile::access_flags::AccessFlags;
+use classfile::access_flags::MethodAccessFlags;
+use classfile::attribute::AttributeType;
+use classfile::constant::get_utf8;
 use classfile::method::MethodInfo;
+use classfile::smap::SourcePosition;
 use classfile::BytesRef;
+use instructions::error::Error;
+use instructions::ssa::{self, Function};
 use std::fmt::{self, Display, Formatter};
 use std::sync::Arc;
 
@@ -12,7 +17,7 @@ pub struct MethodId {
 }
 
 pub struct Method {
-    pub access_flags: AccessFlags,
+    pub access_flags: MethodAccessFlags,
     // method holder
     pub class: ClassRef,
     // offset in class method list
@@ -30,7 +35,7 @@ impl Method {
     }
 
     // Access flags
-    pub fn access_flags(&self) -> &AccessFlags {
+    pub fn access_flags(&self) -> &MethodAccessFlags {
         &self.access_flags
     }
 
@@ -185,6 +190,44 @@ impl Method {
     pub fn size_of_parameters(&self) -> usize {
         unimplemented!()
     }
+
+    pub fn line_number(&self, pc: usize) -> Option<u16> {
+        self.method_info.get_code_attr()?.line_number(pc as u16)
+    }
+
+    /// Source file and line of `pc` for stack traces. Classes compiled from other
+    /// languages (Kotlin inline functions, JSP) are remapped through their SMAP.
+    pub fn source_position(&self, pc: usize) -> Option<SourcePosition> {
+        let line = self.line_number(pc)? as u32;
+        let class_file = &self.class.get_class().class_file;
+        if let Ok(Some(smap)) = class_file.source_map() {
+            if let Some(position) = smap.map_line(line) {
+                return Some(position);
+            }
+        }
+        let file_name = class_file
+            .attributes
+            .iter()
+            .find_map(|attribute| match attribute.attr_type {
+                AttributeType::SourceFile { sourcefile_index } => Some(sourcefile_index),
+                _ => None,
+            })
+            .map(|index| {
+                let name = get_utf8(&class_file.constant_pool, index as usize);
+                String::from_utf8_lossy(name).into_owned()
+            })?;
+        Some(SourcePosition {
+            file_name,
+            path: None,
+            line,
+        })
+    }
+
+    /// The code lifted into SSA form, fails with `MissingCode` for abstract and
+    /// native methods
+    pub fn ssa(&self) -> Result<Function, Error> {
+        ssa::build(&self.method_info, &self.class.get_class().constant_pool)
+    }
 }
 
 impl Display for Method {
@@ -1,13 +1,21 @@
 use std::fmt::{self, Display, Formatter};
 use std::sync::Arc;
 
+use crate::basic_type::BasicType;
+use crate::gc::heap::{heap, Body};
+use crate::oops::class::Class;
+use crate::sys::dic;
 use crate::types::{
     BoolArrayPtr, ByteArrayPtr, CharArrayPtr, ClassRef, DoubleArrayPtr, FloatArrayPtr, IntArrayPtr,
     LongArrayPtr, ShortArrayPtr,
 };
+use crate::Error;
+use instructions::instruction::ArrayType;
 
 pub mod class;
 pub mod field;
+pub mod field_layout;
+pub mod mark_word;
 pub mod method;
 pub mod module;
 pub mod package;
@@ -36,14 +44,157 @@ impl Display for Oop {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Address of an object in the heap, see `gc::heap`. Only good until the next
+/// allocation, which may move or free the object, objects held across one go
+/// in a `GlobalHandle`. The heap fails on the moved ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct OopRef(u64);
 
+impl OopRef {
+    pub(crate) fn new(address: u64) -> Self {
+        OopRef(address)
+    }
+
+    pub(crate) fn address(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Oop {
+    /// The object referenced, fails with `NullPointerException` on `null`
+    fn reference(&self) -> Result<&OopRef, Error> {
+        match self {
+            Oop::Reference(reference) => Ok(reference),
+            Oop::Null => Err(Error::Exception("java/lang/NullPointerException")),
+            oop => Err(Error::Internal(format!("Incorrect Oop: {}", oop))),
+        }
+    }
+
+    /// Value of the field of `field_type` at `offset`, see `FieldLayout`. The
+    /// fields of a mirror are the static fields of its class. Fails with
+    /// `IncompatibleClassChangeError` on an array.
+    pub fn get_field(&self, offset: usize, field_type: &BasicType) -> Result<Oop, Error> {
+        let reference = self.reference()?;
+        match &heap().get(reference)?.body {
+            Body::Instance(InstanceOop { fields, .. }) | Body::Mirror(MirrorOop { fields, .. }) => {
+                field_layout::load(fields, offset, field_type)
+            }
+            _ => Err(Error::Exception("java/lang/IncompatibleClassChangeError")),
+        }
+    }
+
+    pub fn put_field(
+        &self,
+        offset: usize,
+        field_type: &BasicType,
+        value: Oop,
+    ) -> Result<(), Error> {
+        let reference = self.reference()?;
+        let mut heap = heap();
+        match &mut heap.get_mut(reference)?.body {
+            Body::Instance(InstanceOop { fields, .. }) | Body::Mirror(MirrorOop { fields, .. }) => {
+                field_layout::store(fields, offset, field_type, &value)?
+            }
+            _ => return Err(Error::Exception("java/lang/IncompatibleClassChangeError")),
+        }
+        if let Oop::Reference(_) = value {
+            heap.write_barrier(reference);
+        }
+        Ok(())
+    }
+
+    /// Length of the array, fails with `IncompatibleClassChangeError` on
+    /// anything else like the other array accessors
+    pub fn array_length(&self) -> Result<i32, Error> {
+        let reference = self.reference()?;
+        match &heap().get(reference)?.body {
+            Body::Array(array) => Ok(array.length() as i32),
+            _ => Err(Error::Exception("java/lang/IncompatibleClassChangeError")),
+        }
+    }
+
+    /// Element `index` as `<t>aload` pushes it, fails with
+    /// `ArrayIndexOutOfBoundsException` outside the array
+    pub fn get_element(&self, index: i32) -> Result<Oop, Error> {
+        let reference = self.reference()?;
+        match &heap().get(reference)?.body {
+            Body::Array(array) => {
+                let index = array.check_index(index)?;
+                Ok(match array {
+                    ArrayOop::Object(array) => array.elements[index].clone(),
+                    ArrayOop::Type(array) => array.get(index),
+                })
+            }
+            _ => Err(Error::Exception("java/lang/IncompatibleClassChangeError")),
+        }
+    }
+
+    /// Sets element `index` as `<t>astore` does, narrowing ints to the
+    /// element type. Fails with `ArrayStoreException` on a value of another
+    /// type, or an object that isn't an instance of the element class.
+    pub fn put_element(&self, index: i32, value: Oop) -> Result<(), Error> {
+        let reference = self.reference()?;
+        let mut heap = heap();
+        let stored_reference = matches!(value, Oop::Reference(_));
+        let storable = match (&value, &heap.get(reference)?.body) {
+            (Oop::Reference(stored), Body::Array(ArrayOop::Object(array))) => {
+                is_instance_of(&heap.get(stored)?.body, array.element_class.get_class())
+            }
+            _ => true,
+        };
+        match &mut heap.get_mut(reference)?.body {
+            Body::Array(array) => {
+                let index = array.check_index(index)?;
+                match array {
+                    ArrayOop::Object(array) => match value {
+                        Oop::Reference(_) if storable => array.elements[index] = value,
+                        Oop::Null => array.elements[index] = value,
+                        _ => return Err(Error::Exception("java/lang/ArrayStoreException")),
+                    },
+                    ArrayOop::Type(array) => array.set(index, value)?,
+                }
+            }
+            _ => return Err(Error::Exception("java/lang/IncompatibleClassChangeError")),
+        }
+        if stored_reference {
+            heap.write_barrier(reference);
+        }
+        Ok(())
+    }
+}
+
+/// Whether the object of `body` is an instance of `class`. A mirror is one of
+/// `java.lang.Class`, which is only known once loaded, and an array one of
+/// `Object`, `Cloneable` and `Serializable`.
+fn is_instance_of(body: &Body, class: &Class) -> bool {
+    let is_object = class.name.as_slice() == b"java/lang/Object";
+    match body {
+        Body::Instance(InstanceOop { class: own, .. }) => own.get_class().is_assignable_to(class),
+        Body::Mirror(_) => dic::find(b"java/lang/Class")
+            .map_or(is_object, |own| own.get_class().is_assignable_to(class)),
+        Body::Array(_) => {
+            is_object
+                || matches!(
+                    class.name.as_slice(),
+                    b"java/lang/Cloneable" | b"java/io/Serializable"
+                )
+        }
+    }
+}
+
+/// An instance of a class, its fields laid out by the class' `FieldLayout`
 #[derive(Debug, Clone)]
-pub struct InstanceOop {}
+pub struct InstanceOop {
+    pub class: ClassRef,
+    pub fields: Vec<u8>,
+}
 
+/// The `java.lang.Class` object of a class, holding its static fields
 #[derive(Debug, Clone)]
-pub struct MirrorOop {}
+pub struct MirrorOop {
+    pub class: ClassRef,
+    pub fields: Vec<u8>,
+}
 
 /// The layout of array Oops is:
 ///
@@ -51,9 +202,25 @@ pub struct MirrorOop {}
 ///  Klass*    // 32 bits if compressed but declared 64 in LP64.
 ///  length    // shares klass memory or allocated after declared fields.
 #[derive(Debug, Clone)]
-pub struct ArrayOop {
-    pub class: ClassRef,
-    pub elements: Vec<Oop>,
+pub enum ArrayOop {
+    Object(ObjectArrayOop),
+    Type(TypeArrayOop),
+}
+
+impl ArrayOop {
+    pub fn length(&self) -> usize {
+        match self {
+            ArrayOop::Object(array) => array.elements.len(),
+            ArrayOop::Type(array) => array.length(),
+        }
+    }
+
+    fn check_index(&self, index: i32) -> Result<usize, Error> {
+        if index < 0 || index as usize >= self.length() {
+            return Err(Error::Exception("java/lang/ArrayIndexOutOfBoundsException"));
+        }
+        Ok(index as usize)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -68,5 +235,79 @@ pub enum TypeArrayOop {
     Double(DoubleArrayPtr),
 }
 
+impl TypeArrayOop {
+    /// An array of `length` zeros
+    pub fn new(array_type: ArrayType, length: usize) -> Self {
+        match array_type {
+            ArrayType::Boolean => TypeArrayOop::Boolean(Box::new(vec![false; length])),
+            ArrayType::Char => TypeArrayOop::Char(Box::new(vec![0; length])),
+            ArrayType::Float => TypeArrayOop::Float(Box::new(vec![0.0; length])),
+            ArrayType::Double => TypeArrayOop::Double(Box::new(vec![0.0; length])),
+            ArrayType::Byte => TypeArrayOop::Byte(Box::new(vec![0; length])),
+            ArrayType::Short => TypeArrayOop::Short(Box::new(vec![0; length])),
+            ArrayType::Int => TypeArrayOop::Int(Box::new(vec![0; length])),
+            ArrayType::Long => TypeArrayOop::Long(Box::new(vec![0; length])),
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        match self {
+            TypeArrayOop::Char(array) => array.len(),
+            TypeArrayOop::Boolean(array) => array.len(),
+            TypeArrayOop::Byte(array) => array.len(),
+            TypeArrayOop::Int(array) => array.len(),
+            TypeArrayOop::Long(array) => array.len(),
+            TypeArrayOop::Short(array) => array.len(),
+            TypeArrayOop::Float(array) => array.len(),
+            TypeArrayOop::Double(array) => array.len(),
+        }
+    }
+
+    /// Bytes an element takes
+    pub fn element_size(&self) -> usize {
+        match self {
+            TypeArrayOop::Boolean(_) | TypeArrayOop::Byte(_) => 1,
+            TypeArrayOop::Char(_) | TypeArrayOop::Short(_) => 2,
+            TypeArrayOop::Int(_) | TypeArrayOop::Float(_) => 4,
+            TypeArrayOop::Long(_) | TypeArrayOop::Double(_) => 8,
+        }
+    }
+
+    /// Element `index`, widened to an int for the types smaller than that
+    pub fn get(&self, index: usize) -> Oop {
+        match self {
+            TypeArrayOop::Char(array) => Oop::Int(array[index] as i32),
+            TypeArrayOop::Boolean(array) => Oop::Int(array[index] as i32),
+            TypeArrayOop::Byte(array) => Oop::Int(array[index] as i8 as i32),
+            TypeArrayOop::Int(array) => Oop::Int(array[index]),
+            TypeArrayOop::Long(array) => Oop::Long(array[index]),
+            TypeArrayOop::Short(array) => Oop::Int(array[index] as i32),
+            TypeArrayOop::Float(array) => Oop::Float(array[index]),
+            TypeArrayOop::Double(array) => Oop::Double(array[index]),
+        }
+    }
+
+    /// Sets element `index`, an int is truncated to the element type, to its
+    /// lowest bit for `boolean`
+    pub fn set(&mut self, index: usize, value: Oop) -> Result<(), Error> {
+        match (self, value) {
+            (TypeArrayOop::Char(array), Oop::Int(value)) => array[index] = value as u16,
+            (TypeArrayOop::Boolean(array), Oop::Int(value)) => array[index] = value & 1 != 0,
+            (TypeArrayOop::Byte(array), Oop::Int(value)) => array[index] = value as u8,
+            (TypeArrayOop::Int(array), Oop::Int(value)) => array[index] = value,
+            (TypeArrayOop::Long(array), Oop::Long(value)) => array[index] = value,
+            (TypeArrayOop::Short(array), Oop::Int(value)) => array[index] = value as i16,
+            (TypeArrayOop::Float(array), Oop::Float(value)) => array[index] = value,
+            (TypeArrayOop::Double(array), Oop::Double(value)) => array[index] = value,
+            _ => return Err(Error::Exception("java/lang/ArrayStoreException")),
+        }
+        Ok(())
+    }
+}
+
+/// An array of references
 #[derive(Debug, Clone)]
-pub struct ObjectArrayOop {}
+pub struct ObjectArrayOop {
+    pub element_class: ClassRef,
+    pub elements: Vec<Oop>,
+}
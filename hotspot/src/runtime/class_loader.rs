@@ -1,2 +1,270 @@
+use crate::classpath;
+use crate::oops::class::Class;
+use crate::sys::dic;
+use crate::types::ClassRef;
+use crate::Error;
+use classfile::class_file::ClassFile;
+use classfile::constant::get_class_name;
+use std::cell::RefCell;
+use std::sync::Arc;
+
 #[derive(Debug, Clone, Copy)]
 pub enum ClassLoader {}
+
+thread_local! {
+    /// Names of the classes this thread is defining, to catch a class that is
+    /// its own super class or super interface
+    static DEFINING: RefCell<Vec<Vec<u8>>> = const { RefCell::new(vec![]) };
+}
+
+/// The class `name` from the system dictionary, else loaded from the class
+/// path by the bootstrap loader, see "The Java Virtual Machine Specification"
+/// section 5.3.1
+pub fn load_class(name: &[u8]) -> Result<ClassRef, Error> {
+    if let Some(class) = dic::find(name) {
+        return Ok(class);
+    }
+    let result = classpath::find_class(&String::from_utf8_lossy(name))
+        .map_err(|_| Error::Exception("java/lang/NoClassDefFoundError"))?;
+    define_class(&result.data)
+}
+
+/// Defines the class in `data`: loads its super class and interfaces, enters
+/// it in the system dictionary and links it. A class failing to link is taken
+/// out of the dictionary again.
+pub fn define_class(data: &[u8]) -> Result<ClassRef, Error> {
+    let class_file = ClassFile::read_from(data).map_err(Error::ClassFormat)?;
+    let constant_pool = &class_file.constant_pool;
+    let name = get_class_name(constant_pool, class_file.this_class as usize).clone();
+    if dic::find(&name).is_some() {
+        return Err(Error::Exception("java/lang/LinkageError"));
+    }
+    let _defining = Defining::enter(&name)?;
+
+    let super_class = match class_file.super_class {
+        0 => None,
+        index => Some(load_class(get_class_name(constant_pool, index as usize))?),
+    };
+    if super_class
+        .as_ref()
+        .is_some_and(|class| class.get_class().is_interface())
+    {
+        return Err(Error::Exception("java/lang/IncompatibleClassChangeError"));
+    }
+    let interfaces = class_file
+        .interfaces
+        .iter()
+        .map(|index| load_class(get_class_name(constant_pool, *index as usize)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let class = Class::allocate_instance_class(Arc::new(class_file), super_class, interfaces);
+    dic::put(&name, class.clone());
+    if let Err(e) = Class::link(&class) {
+        dic::remove(&name);
+        return Err(e);
+    }
+    Ok(class)
+}
+
+/// Lists a class in `DEFINING` until dropped
+struct Defining;
+
+impl Defining {
+    fn enter(name: &[u8]) -> Result<Defining, Error> {
+        DEFINING.with(|defining| {
+            let mut defining = defining.borrow_mut();
+            if defining.iter().any(|defined| defined.as_slice() == name) {
+                return Err(Error::Exception("java/lang/ClassCircularityError"));
+            }
+            defining.push(name.to_vec());
+            Ok(Defining)
+        })
+    }
+}
+
+impl Drop for Defining {
+    fn drop(&mut self) {
+        DEFINING.with(|defining| defining.borrow_mut().pop());
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::basic_type::BasicType;
+    use crate::oops::class::ClassState;
+    use crate::oops::Oop;
+    use crate::runtime::string_table;
+    use instructions::jasmin::parse;
+    use once_cell::sync::Lazy;
+    use std::sync::{Mutex, MutexGuard};
+
+    static LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    /// Serializes the tests sharing the heap and the system dictionary, and
+    /// defines a bare `java/lang/Object` for their classes to extend and a
+    /// `java/lang/String` holding its chars in `value`
+    pub(crate) fn lock() -> MutexGuard<'static, ()> {
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        if dic::find(b"java/lang/Object").is_none() {
+            let mut object =
+                parse(".bytecode 52.0\n.class public java/lang/Object\n.super java/lang/Object\n")
+                    .unwrap();
+            object.super_class = 0;
+            define_class(&bytes(&object)).unwrap();
+            define(
+                ".class public final java/lang/String\n.super java/lang/Object\n.field private final value [C\n",
+            )
+            .unwrap();
+        }
+        guard
+    }
+
+    /// Defines the class in Jasmin `source`
+    pub(crate) fn define(source: &str) -> Result<ClassRef, Error> {
+        define_class(&bytes(&parse(source).unwrap()))
+    }
+
+    pub(crate) fn bytes(class_file: &ClassFile) -> Vec<u8> {
+        let mut data = vec![];
+        class_file.write_to(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn defines_and_links() {
+        let _lock = lock();
+        let class = define(
+            "
+.class public LoaderPoint
+.super java/lang/Object
+.field public x I
+.field public static final ORIGIN I = 7
+",
+        )
+        .unwrap();
+        assert!(matches!(class.get_class().state(), ClassState::Linked));
+        // Linking prepared the class, its mirror holds the static fields
+        let origin = class.get_class().find_field(b"ORIGIN", b"I").unwrap();
+        let mirror = class.get_class().mirror().unwrap().clone();
+        assert!(matches!(
+            mirror.get_field(origin.offset, &BasicType::INT),
+            Ok(Oop::Int(7))
+        ));
+        assert!(Arc::ptr_eq(&dic::find(b"LoaderPoint").unwrap(), &class));
+        let super_class = class.get_class().super_class().unwrap();
+        assert_eq!(super_class.name().as_slice(), b"java/lang/Object");
+        assert!(matches!(
+            define(".class public LoaderPoint\n.super java/lang/Object\n"),
+            Err(Error::Exception("java/lang/LinkageError"))
+        ));
+    }
+
+    #[test]
+    fn string_constants_are_interned() {
+        let _lock = lock();
+        let class = define(
+            "
+.class public LoaderGreeter
+.super java/lang/Object
+.field public static final GREETING Ljava/lang/String; = \"LoaderGreeting\"
+",
+        )
+        .unwrap();
+        let greeting = class
+            .get_class()
+            .find_field(b"GREETING", b"Ljava/lang/String;")
+            .unwrap();
+        let mirror = class.get_class().mirror().unwrap().clone();
+        let value = mirror
+            .get_field(greeting.offset, &BasicType::OBJECT)
+            .unwrap();
+        match (value, string_table::lookup("LoaderGreeting")) {
+            (Oop::Reference(value), Some(Oop::Reference(interned))) => {
+                assert_eq!(value, interned)
+            }
+            (value, _) => panic!("Not the interned string: {}", value),
+        }
+    }
+
+    #[test]
+    fn rejects_unverifiable_classes() {
+        let _lock = lock();
+        let result = define(
+            "
+.bytecode 49.0
+.class public LoaderBroken
+.super java/lang/Object
+.method public static broken()I
+    .limit stack 1
+    .limit locals 0
+    fconst_1
+    ireturn
+.end method
+",
+        );
+        assert!(matches!(result, Err(Error::Verify(_))));
+        assert!(dic::find(b"LoaderBroken").is_none());
+    }
+
+    #[test]
+    fn reports_missing_super_classes() {
+        let _lock = lock();
+        let result = define(".class public LoaderOrphan\n.super LoaderMissing\n");
+        assert!(matches!(
+            result,
+            Err(Error::Exception("java/lang/NoClassDefFoundError"))
+        ));
+    }
+
+    #[test]
+    fn verifies_against_classes_it_loads() {
+        let _lock = lock();
+        let dir = std::env::temp_dir().join(format!("loader-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for source in &[
+            ".class public LoaderLazyBase\n.super java/lang/Object\n",
+            ".class public LoaderLazyImpl\n.super LoaderLazyBase\n",
+        ] {
+            let class_file = parse(source).unwrap();
+            let name = get_class_name(&class_file.constant_pool, class_file.this_class as usize);
+            let path = dir.join(format!("{}.class", String::from_utf8_lossy(name)));
+            std::fs::write(path, bytes(&class_file)).unwrap();
+        }
+        classpath::add_path(dir.to_str().unwrap());
+
+        // Neither class is loaded until the verifier asks for their hierarchy
+        assert!(dic::find(b"LoaderLazyImpl").is_none());
+        define(
+            "
+.class public LoaderLazyUser
+.super java/lang/Object
+.method public static widen(LLoaderLazyImpl;)LLoaderLazyBase;
+    .limit stack 1
+    .limit locals 1
+    aload_0
+    areturn
+.end method
+",
+        )
+        .unwrap();
+        assert!(dic::find(b"LoaderLazyImpl").is_some());
+        assert!(dic::find(b"LoaderLazyBase").is_some());
+
+        // A class that can't be loaded is only rejected once loading failed
+        let result = define(
+            "
+.class public LoaderLazyStranger
+.super java/lang/Object
+.method public static widen(LLoaderNowhere;)LLoaderLazyBase;
+    .limit stack 1
+    .limit locals 1
+    aload_0
+    areturn
+.end method
+",
+        );
+        assert!(matches!(result, Err(Error::Verify(_))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
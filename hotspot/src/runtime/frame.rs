@@ -2,9 +2,44 @@ use crate::oops;
 use crate::oops::Oop;
 use crate::runtime::{DataArea, Slot};
 use crate::types::MethodIdRef;
+use crate::Error;
 use classfile::{BytesRef, ConstantPoolRef};
+use hashbrown::HashMap;
+use once_cell::sync::Lazy;
 use std::sync::atomic::AtomicUsize;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+
+/// The frames each thread is executing, innermost last. The collector finds
+/// roots in the frames of every thread.
+static FRAMES: Lazy<Mutex<HashMap<ThreadId, Vec<Arc<Frame>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::default()));
+
+/// Keeps a frame on its thread's frame stack until dropped
+pub(crate) struct Activation(ThreadId);
+
+impl Drop for Activation {
+    fn drop(&mut self) {
+        let mut frames = FRAMES.lock().unwrap();
+        if let Some(stack) = frames.get_mut(&self.0) {
+            stack.pop();
+            if stack.is_empty() {
+                frames.remove(&self.0);
+            }
+        }
+    }
+}
+
+/// Calls `f` with every frame any thread is executing. The other threads
+/// aren't stopped at a safepoint yet, so only what their frames hold is
+/// seen: a value popped and not pushed back is a Rust local of that thread,
+/// which dangles once the collection moved its object.
+pub(crate) fn for_each_active(mut f: impl FnMut(&Frame)) {
+    let frames = FRAMES.lock().unwrap();
+    for frame in frames.values().flatten() {
+        f(frame);
+    }
+}
 
 pub struct Frame {
     pub id: usize,
@@ -35,67 +70,111 @@ impl Frame {
         }
     }
 
+    /// Pushes `frame` on the thread's frame stack while it's executed
+    pub(crate) fn activate(frame: &Arc<Frame>) -> Activation {
+        let thread = thread::current().id();
+        let mut frames = FRAMES.lock().unwrap();
+        frames.entry(thread).or_default().push(frame.clone());
+        Activation(thread)
+    }
+
     #[inline]
-    pub fn push_int(&mut self, v: i32) {
+    pub fn push_int(&self, v: i32) {
         self.data_area
             .stack
-            .borrow_mut()
+            .lock()
             .push(Slot::Oop(oops::Oop::Int(v)));
     }
 
     #[inline]
-    pub fn push_float(&mut self, v: f32) {
+    pub fn push_float(&self, v: f32) {
         self.data_area
             .stack
-            .borrow_mut()
+            .lock()
             .push(Slot::Oop(oops::Oop::Float(v)));
     }
 
     #[inline]
-    pub fn push_double(&mut self, v: f64) {
+    pub fn push_double(&self, v: f64) {
         self.data_area
             .stack
-            .borrow_mut()
+            .lock()
             .push(Slot::Oop(oops::Oop::Double(v)));
     }
 
     #[inline]
-    pub fn push_long(&mut self, v: i64) {
+    pub fn push_long(&self, v: i64) {
         self.data_area
             .stack
-            .borrow_mut()
+            .lock()
             .push(Slot::Oop(oops::Oop::Long(v)));
     }
 
     #[inline]
-    pub fn push_null(&mut self) {
-        self.data_area
-            .stack
-            .borrow_mut()
-            .push(Slot::Oop(oops::Oop::Null));
+    pub fn push_null(&self) {
+        self.data_area.stack.lock().push(Slot::Oop(oops::Oop::Null));
     }
 
     #[inline]
-    pub fn push_nop(&mut self) {
-        self.data_area.stack.borrow_mut().push(Slot::Nop)
+    pub fn push_nop(&self) {
+        self.data_area.stack.lock().push(Slot::Nop)
     }
 
     #[inline]
-    pub fn push_ref(&mut self, v: oops::Oop) {
+    pub fn push_ref(&self, v: oops::Oop) {
         if let oops::Oop::Reference(..) = &v {
-            self.data_area.stack.borrow_mut().push(Slot::Oop(v));
+            self.data_area.stack.lock().push(Slot::Oop(v));
         } else {
             panic!("Incorrect Oop")
         }
     }
 
     #[inline]
-    pub fn pop(&mut self) -> Oop {
-        let mut stack = self.data_area.stack.borrow_mut();
-        assert!(stack.size() > 0);
-        match stack.pop().unwrap() {
-            Slot::Oop(oop) => oop,
-            _ => unreachable!(),
+    pub fn push(&self, v: oops::Oop) {
+        self.data_area.stack.lock().push(Slot::Oop(v));
+    }
+
+    #[inline]
+    pub fn pop_int(&self) -> Result<i32, Error> {
+        match self.pop()? {
+            Oop::Int(v) => Ok(v),
+            oop => Err(Error::Internal(format!("Incorrect Oop: {}", oop))),
+        }
+    }
+
+    #[inline]
+    pub fn pop_long(&self) -> Result<i64, Error> {
+        match self.pop()? {
+            Oop::Long(v) => Ok(v),
+            oop => Err(Error::Internal(format!("Incorrect Oop: {}", oop))),
+        }
+    }
+
+    #[inline]
+    pub fn pop_float(&self) -> Result<f32, Error> {
+        match self.pop()? {
+            Oop::Float(v) => Ok(v),
+            oop => Err(Error::Internal(format!("Incorrect Oop: {}", oop))),
+        }
+    }
+
+    #[inline]
+    pub fn pop_double(&self) -> Result<f64, Error> {
+        match self.pop()? {
+            Oop::Double(v) => Ok(v),
+            oop => Err(Error::Internal(format!("Incorrect Oop: {}", oop))),
+        }
+    }
+
+    #[inline]
+    pub fn pop(&self) -> Result<Oop, Error> {
+        let mut stack = self.data_area.stack.lock();
+        if stack.size() == 0 {
+            return Err(Error::Internal("Operand stack underflow".to_string()));
+        }
+        match stack.pop() {
+            Some(Slot::Oop(oop)) => Ok(oop),
+            slot => Err(Error::Internal(format!("Invalid slot: {:?}", slot))),
         }
     }
 }
@@ -0,0 +1,62 @@
+use crate::oops::Oop;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+/// Slots of the live handles, `None` once released
+struct Handles {
+    slots: Vec<Option<Oop>>,
+    free: Vec<usize>,
+}
+
+static HANDLES: Lazy<Mutex<Handles>> = Lazy::new(|| {
+    Mutex::new(Handles {
+        slots: vec![],
+        free: vec![],
+    })
+});
+
+/// A global handle, as JNI `NewGlobalRef` makes: the object stays alive until
+/// the handle is dropped. Native code and the VM hold objects across
+/// allocations through these, since the collector doesn't see Rust locals.
+pub struct GlobalHandle(usize);
+
+impl GlobalHandle {
+    pub fn new(oop: Oop) -> Self {
+        let mut handles = HANDLES.lock().unwrap();
+        match handles.free.pop() {
+            Some(index) => {
+                handles.slots[index] = Some(oop);
+                GlobalHandle(index)
+            }
+            None => {
+                handles.slots.push(Some(oop));
+                GlobalHandle(handles.slots.len() - 1)
+            }
+        }
+    }
+
+    pub fn get(&self) -> Oop {
+        let handles = HANDLES.lock().unwrap();
+        handles.slots[self.0].clone().unwrap()
+    }
+
+    pub fn set(&self, oop: Oop) {
+        let mut handles = HANDLES.lock().unwrap();
+        handles.slots[self.0] = Some(oop);
+    }
+}
+
+impl Drop for GlobalHandle {
+    fn drop(&mut self) {
+        let mut handles = HANDLES.lock().unwrap();
+        handles.slots[self.0] = None;
+        handles.free.push(self.0);
+    }
+}
+
+/// Calls `f` with the object of every live handle, which a moving collection
+/// updates in place
+pub(crate) fn for_each(f: impl FnMut(&mut Oop)) {
+    let mut handles = HANDLES.lock().unwrap();
+    handles.slots.iter_mut().flatten().for_each(f);
+}
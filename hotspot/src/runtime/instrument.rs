@@ -0,0 +1,233 @@
+//! Load-time instrumentation: once `enable` was called, the classes read from
+//! the class path get calls to probes on method entry and exit, see
+//! `instructions::instrument`.
+
+use crate::runtime::class_loader;
+use crate::sys::dic;
+use crate::types::ClassRef;
+use classfile::class_file::ClassFile;
+use instructions::error::Error;
+use instructions::instrument::{instrument_class, MethodIds, Probes};
+use instructions::verifier::ClassHierarchy;
+use log::{error, trace};
+use once_cell::sync::Lazy;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+struct Instrumentation {
+    probes: Option<Probes>,
+    /// Kept across `disable`, so an id is never given to two methods
+    ids: MethodIds,
+}
+
+thread_local! {
+    /// Names of the classes this thread is instrumenting
+    static TRANSFORMING: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+}
+
+static INSTRUMENTATION: Lazy<Mutex<Instrumentation>> = Lazy::new(|| {
+    Mutex::new(Instrumentation {
+        probes: None,
+        ids: MethodIds::new(),
+    })
+});
+
+/// Instruments the classes read from now on with calls to `probes`
+pub fn enable(probes: Probes) {
+    INSTRUMENTATION.lock().unwrap().probes = Some(probes);
+}
+
+/// Leaves the classes read from now on as they are
+pub fn disable() {
+    INSTRUMENTATION.lock().unwrap().probes = None;
+}
+
+/// Class, name and descriptor of the method whose probes are called with `id`
+pub fn method(id: i32) -> Option<(String, String, String)> {
+    let instrumentation = INSTRUMENTATION.lock().unwrap();
+    let (class, name, descriptor) = instrumentation.ids.get(id)?;
+    Some((class.to_string(), name.to_string(), descriptor.to_string()))
+}
+
+/// The class file `data` read for class `name`, instrumented when enabled. A
+/// class that can't be instrumented is loaded as it is.
+pub(crate) fn transform(name: &str, data: Vec<u8>) -> Vec<u8> {
+    if INSTRUMENTATION.lock().unwrap().probes.is_none() {
+        return data;
+    }
+    let class_file = match ClassFile::read_from(&data[..]) {
+        Ok(class_file) => class_file,
+        Err(e) => {
+            error!("instrument class error, name = {}, e = {:?}", name, e);
+            return data;
+        }
+    };
+    TRANSFORMING.with(|transforming| transforming.borrow_mut().push(name.to_string()));
+    let instrumented = instrument(&class_file);
+    TRANSFORMING.with(|transforming| transforming.borrow_mut().pop());
+    let mut transformed = vec![];
+    match instrumented
+        .map(|class_file| class_file.map(|class_file| class_file.write_to(&mut transformed)))
+    {
+        None => data,
+        Some(Ok(Ok(_))) => {
+            trace!("instrumented class: {}", name);
+            transformed
+        }
+        Some(Ok(Err(e))) => {
+            error!(
+                "write instrumented class error, name = {}, e = {:?}",
+                name, e
+            );
+            data
+        }
+        Some(Err(e)) => {
+            error!("instrument class error, name = {}, e = {:?}", name, e);
+            data
+        }
+    }
+}
+
+/// Instruments `class_file` once the classes its stack maps ask about were
+/// loaded, None when instrumentation was disabled meanwhile. The classes are
+/// loaded without holding the lock, as they are transformed in turn, and the
+/// class is instrumented again with them: the ids are only taken by the
+/// attempt that needed no more classes.
+fn instrument(class_file: &ClassFile) -> Option<Result<ClassFile, Error>> {
+    let mut tried = BTreeSet::new();
+    loop {
+        let hierarchy = Loading::default();
+        let mut instrumentation = INSTRUMENTATION.lock().unwrap();
+        let Instrumentation { probes, ids } = &mut *instrumentation;
+        let probes = probes.as_ref()?;
+        let mut attempt = ids.clone();
+        let instrumented = instrument_class(class_file, probes, &mut attempt, &hierarchy);
+        let missing: Vec<String> = hierarchy
+            .missing
+            .into_inner()
+            .into_iter()
+            .filter(|class| tried.insert(class.clone()))
+            .collect();
+        if missing.is_empty() {
+            *ids = attempt;
+            return Some(instrumented);
+        }
+        drop(instrumentation);
+        for class in missing {
+            // a class failing to load is not a subclass of any other one
+            let _ = class_loader::load_class(class.as_bytes());
+        }
+    }
+}
+
+/// Hierarchy of the classes in the system dictionary, noting those asked
+/// about that aren't there yet, for `instrument` to load. The classes this
+/// thread is transforming are never loaded, that would transform them again.
+#[derive(Default)]
+struct Loading {
+    missing: RefCell<BTreeSet<String>>,
+}
+
+impl Loading {
+    fn find(&self, class: &str) -> Option<ClassRef> {
+        let found = dic::find(class.as_bytes());
+        let transforming = TRANSFORMING
+            .with(|transforming| transforming.borrow().iter().any(|name| name == class));
+        if found.is_none() && !transforming {
+            self.missing.borrow_mut().insert(class.to_string());
+        }
+        found
+    }
+}
+
+impl ClassHierarchy for Loading {
+    fn super_class(&self, class: &str) -> Option<String> {
+        let class = self.find(class)?;
+        let super_class = class.get_class().super_class()?;
+        Some(String::from_utf8_lossy(super_class.name()).into_owned())
+    }
+
+    fn is_interface(&self, class: &str) -> bool {
+        self.find(class)
+            .is_some_and(|class| class.get_class().is_interface())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::classpath;
+    use crate::oops::class::ClassState;
+    use crate::runtime::class_loader::test::lock;
+    use classfile::attribute::AttributeType;
+    use classfile::constant::get_class_name;
+    use instructions::jasmin::parse;
+
+    const USER: &str = "
+.bytecode 51.0
+.class public ProbedUser
+.super java/lang/Object
+.method public static pick(Z)LProbedBase;
+    .limit stack 2
+    .limit locals 1
+    iload_0
+    ifeq Other
+    new ProbedLeft
+    dup
+    invokespecial ProbedLeft/<init>()V
+    goto Done
+Other:
+    new ProbedRight
+    dup
+    invokespecial ProbedRight/<init>()V
+Done:
+    areturn
+.end method
+";
+
+    #[test]
+    fn stack_maps_load_the_classes_they_merge() {
+        let _lock = lock();
+        let dir = std::env::temp_dir().join(format!("probed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for source in &[
+            ".class public ProbedBase\n.super java/lang/Object\n",
+            ".class public ProbedLeft\n.super ProbedBase\n",
+            ".class public ProbedRight\n.super ProbedBase\n",
+            USER,
+        ] {
+            let class_file = parse(source).unwrap();
+            let name = get_class_name(&class_file.constant_pool, class_file.this_class as usize);
+            let path = dir.join(format!("{}.class", String::from_utf8_lossy(name)));
+            let mut data = vec![];
+            class_file.write_to(&mut data).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+        classpath::add_path(dir.to_str().unwrap());
+
+        enable(Probes {
+            class: "Probes".to_string(),
+            enter: "enter".to_string(),
+            exit: "exit".to_string(),
+            throw: "throw".to_string(),
+        });
+        let loaded = class_loader::load_class(b"ProbedUser");
+        disable();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        // The class file has no stack maps, it only passes the type checker
+        // with those instrumenting it added, merging both classes into their
+        // super class
+        let class = loaded.unwrap();
+        assert!(matches!(class.get_class().state(), ClassState::Linked));
+        let class_file = &class.get_class().class_file;
+        let code = class_file.methods[0].get_code_attr().unwrap();
+        assert!(code
+            .attributes
+            .iter()
+            .any(|attribute| matches!(attribute.attr_type, AttributeType::StackMapTable { .. })));
+        assert!(dic::find(b"ProbedLeft").is_some());
+        assert!(dic::find(b"ProbedRight").is_some());
+    }
+}
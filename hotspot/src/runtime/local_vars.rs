@@ -1,5 +1,6 @@
 use crate::oops::{Oop, OopRef};
 use crate::runtime::Slot;
+use crate::Error;
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
@@ -13,60 +14,101 @@ impl LocalVars {
         LocalVars { slots }
     }
 
-    pub fn set_int(&mut self, pos: usize, value: i32) {
-        self.slots[pos - 1] = Slot::Oop(Oop::Int(value));
+    pub fn iter(&self) -> impl Iterator<Item = &Slot> {
+        self.slots.iter()
     }
 
-    pub fn get_int(&self, pos: usize) -> i32 {
-        match self.slots.get(pos - 1).unwrap() {
-            Slot::Oop(Oop::Int(value)) => *value,
-            slot => panic!("Invalid slot: {:?}", slot),
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Slot> {
+        self.slots.iter_mut()
+    }
+
+    pub fn set_int(&mut self, pos: usize, value: i32) -> Result<(), Error> {
+        self.put(pos, Oop::Int(value))
+    }
+
+    pub fn get_int(&self, pos: usize) -> Result<i32, Error> {
+        match self.slots.get(pos) {
+            Some(Slot::Oop(Oop::Int(value))) => Ok(*value),
+            slot => Err(Error::Internal(format!("Invalid slot: {:?}", slot))),
         }
     }
 
-    pub fn set_long(&mut self, pos: usize, value: i64) {
-        self.slots[pos - 1] = Slot::Oop(Oop::Long(value));
-        self.slots[pos] = Slot::Nop;
+    pub fn set_long(&mut self, pos: usize, value: i64) -> Result<(), Error> {
+        self.put(pos, Oop::Long(value))
     }
 
-    pub fn get_long(&self, pos: usize) -> i64 {
-        match self.slots.get(pos - 1).unwrap() {
-            Slot::Oop(Oop::Long(value)) => *value,
-            slot => panic!("Invalid slot: {:?}", slot),
+    pub fn get_long(&self, pos: usize) -> Result<i64, Error> {
+        match self.slots.get(pos) {
+            Some(Slot::Oop(Oop::Long(value))) => Ok(*value),
+            slot => Err(Error::Internal(format!("Invalid slot: {:?}", slot))),
         }
     }
 
-    pub fn set_float(&mut self, pos: usize, value: f32) {
-        self.slots[pos - 1] = Slot::Oop(Oop::Float(value));
+    pub fn set_float(&mut self, pos: usize, value: f32) -> Result<(), Error> {
+        self.put(pos, Oop::Float(value))
     }
 
-    pub fn get_float(&mut self, pos: usize) -> f32 {
-        match self.slots.get(pos - 1).unwrap() {
-            Slot::Oop(Oop::Float(value)) => *value,
-            slot => panic!("Invalid slot: {:?}", slot),
+    pub fn get_float(&self, pos: usize) -> Result<f32, Error> {
+        match self.slots.get(pos) {
+            Some(Slot::Oop(Oop::Float(value))) => Ok(*value),
+            slot => Err(Error::Internal(format!("Invalid slot: {:?}", slot))),
         }
     }
 
-    pub fn set_double(&mut self, pos: usize, value: f64) {
-        self.slots[pos - 1] = Slot::Oop(Oop::Double(value));
-        self.slots[pos] = Slot::Nop;
+    pub fn set_double(&mut self, pos: usize, value: f64) -> Result<(), Error> {
+        self.put(pos, Oop::Double(value))
+    }
+
+    pub fn get_double(&self, pos: usize) -> Result<f64, Error> {
+        match self.slots.get(pos) {
+            Some(Slot::Oop(Oop::Double(value))) => Ok(*value),
+            slot => Err(Error::Internal(format!("Invalid slot: {:?}", slot))),
+        }
     }
 
-    pub fn get_double(&self, pos: usize) -> f64 {
-        match self.slots.get(pos - 1).unwrap() {
-            Slot::Oop(Oop::Double(value)) => *value,
-            slot => panic!("Invalid slot: {:?}", slot),
+    /// A reference, `null` included, for `aload` and `astore`
+    pub fn get(&self, pos: usize) -> Result<Oop, Error> {
+        match self.slots.get(pos) {
+            Some(Slot::Oop(value @ Oop::Reference(_))) | Some(Slot::Oop(value @ Oop::Null)) => {
+                Ok(value.clone())
+            }
+            slot => Err(Error::Internal(format!("Invalid slot: {:?}", slot))),
         }
     }
 
-    pub fn set_ref(&mut self, pos: usize, value: Arc<OopRef>) {
-        self.slots[pos - 1] = Slot::Oop(Oop::Reference(value));
+    /// Stores a reference, `null` included, for `astore`
+    pub fn set(&mut self, pos: usize, value: Oop) -> Result<(), Error> {
+        match value {
+            Oop::Reference(_) | Oop::Null => self.put(pos, value),
+            value => Err(Error::Internal(format!("Incorrect Oop: {}", value))),
+        }
+    }
+
+    pub fn set_ref(&mut self, pos: usize, value: Arc<OopRef>) -> Result<(), Error> {
+        self.put(pos, Oop::Reference(value))
+    }
+
+    pub fn get_ref(&self, pos: usize) -> Result<Arc<OopRef>, Error> {
+        match self.slots.get(pos) {
+            Some(Slot::Oop(Oop::Reference(value))) => Ok(value.clone()),
+            slot => Err(Error::Internal(format!("Invalid slot: {:?}", slot))),
+        }
     }
 
-    pub fn get_ref(&self, pos: usize) -> Arc<OopRef> {
-        match self.slots.get(pos - 1).unwrap() {
-            Slot::Oop(Oop::Reference(value)) => value.clone(),
-            slot => panic!("Invalid slot: {:?}", slot),
+    /// Stores `value` at `pos`, a long or double taking `pos + 1` too
+    fn put(&mut self, pos: usize, value: Oop) -> Result<(), Error> {
+        let words = match value {
+            Oop::Long(_) | Oop::Double(_) => 2,
+            _ => 1,
+        };
+        let slots = pos
+            .checked_add(words)
+            .and_then(|end| self.slots.get_mut(pos..end))
+            .ok_or_else(|| Error::Internal(format!("Invalid local: {}", pos)))?;
+        slots[0] = Slot::Oop(value);
+        if let Some(second) = slots.get_mut(1) {
+            *second = Slot::Nop;
         }
+        Ok(())
     }
 }
@@ -1,13 +1,16 @@
 use crate::oops::Oop;
 use crate::runtime::local_vars::LocalVars;
 use crate::stack::Stack;
-use std::cell::RefCell;
+use parking_lot::Mutex;
 
 pub mod class_loader;
 pub mod constant_pool;
 pub mod frame;
+pub mod handles;
+pub mod instrument;
 pub mod local_vars;
 pub mod park;
+pub mod string_table;
 pub mod thread;
 
 #[derive(Debug, Clone)]
@@ -16,23 +19,23 @@ pub enum Slot {
     Nop,
 }
 
+/// The locals and operand stack of a frame, locked apart as the collector of
+/// another thread may update the references in them
 pub struct DataArea {
-    pub local: RefCell<LocalVars>,
-    pub stack: RefCell<Stack<Slot>>,
-    pub value: RefCell<Option<Oop>>,
+    pub local: Mutex<LocalVars>,
+    pub stack: Mutex<Stack<Slot>>,
+    pub value: Mutex<Option<Oop>>,
 }
 
-unsafe impl Sync for DataArea {}
-
 impl DataArea {
     pub fn new(max_locals: usize, max_stack: usize) -> Self {
-        let local = RefCell::new(LocalVars::new(max_locals));
-        let stack = RefCell::new(Stack::new(max_stack));
+        let local = Mutex::new(LocalVars::new(max_locals));
+        let stack = Mutex::new(Stack::new(max_stack));
 
         Self {
             local,
             stack,
-            value: RefCell::new(None),
+            value: Mutex::new(None),
         }
     }
 }
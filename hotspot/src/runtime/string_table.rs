@@ -0,0 +1,57 @@
+use crate::basic_type::BasicType;
+use crate::gc::heap::heap;
+use crate::oops::Oop;
+use crate::runtime::class_loader;
+use crate::runtime::handles::GlobalHandle;
+use crate::Error;
+use hashbrown::HashMap;
+use instructions::instruction::ArrayType;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+/// `java.lang.String` objects by value, as `String.intern` and `ldc` of a
+/// string constant share them
+static STRING_TABLE: Lazy<Mutex<HashMap<String, Oop>>> =
+    Lazy::new(|| Mutex::new(HashMap::default()));
+
+/// The interned string `value`, made by `create` unless already interned
+pub fn intern(value: &str, create: impl FnOnce() -> Result<Oop, Error>) -> Result<Oop, Error> {
+    if let Some(oop) = lookup(value) {
+        return Ok(oop);
+    }
+    // Not holding the table while `create` allocates, which may collect
+    let oop = create()?;
+    let mut table = STRING_TABLE.lock().unwrap();
+    Ok(table.entry(value.to_string()).or_insert(oop).clone())
+}
+
+/// A new `java.lang.String` of `value`, its UTF-16 code units in the `char[]`
+/// field `value` as the class library lays it out up to JDK 8
+pub fn new_string(value: &str) -> Result<Oop, Error> {
+    let class = class_loader::load_class(b"java/lang/String")?;
+    let field = class
+        .get_class()
+        .find_field(b"value", b"[C")
+        .ok_or(Error::Exception("java/lang/NoSuchFieldError"))?;
+    let units: Vec<u16> = value.encode_utf16().collect();
+    let chars = heap().allocate_type_array(ArrayType::Char, units.len() as i32)?;
+    for (index, unit) in units.iter().enumerate() {
+        chars.put_element(index as i32, Oop::Int(*unit as i32))?;
+    }
+    // Allocating the string may move the array
+    let chars = GlobalHandle::new(chars);
+    let string = heap().allocate_instance(&class)?;
+    string.put_field(field.offset, &BasicType::ARRAY, chars.get())?;
+    Ok(string)
+}
+
+pub fn lookup(value: &str) -> Option<Oop> {
+    let table = STRING_TABLE.lock().unwrap();
+    table.get(value).cloned()
+}
+
+/// Calls `f` with every interned string
+pub(crate) fn for_each(f: impl FnMut(&mut Oop)) {
+    let mut table = STRING_TABLE.lock().unwrap();
+    table.values_mut().for_each(f);
+}
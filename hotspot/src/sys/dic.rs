@@ -21,3 +21,16 @@ pub fn find(key: &[u8]) -> Option<ClassRef> {
     let dict = SYS_DIC.lock().unwrap();
     dict.get(key).cloned()
 }
+
+pub fn remove(key: &[u8]) -> Option<ClassRef> {
+    debug_assert!(!key.contains(&b'.'));
+    let key = unsafe { std::str::from_utf8_unchecked(key) };
+    let mut dict = SYS_DIC.lock().unwrap();
+    dict.remove(key)
+}
+
+/// Every class in the dictionary
+pub fn classes() -> Vec<ClassRef> {
+    let dict = SYS_DIC.lock().unwrap();
+    dict.values().cloned().collect()
+}
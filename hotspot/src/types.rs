@@ -7,7 +7,7 @@ pub type FieldIdRef = Arc<FieldId>;
 pub type MethodIdRef = Arc<MethodId>;
 pub type ClassRef = Arc<ClassPtr>;
 
-def_ptr!(CharArrayPtr, Vec<char>);
+def_ptr!(CharArrayPtr, Vec<u16>);
 def_ptr!(BoolArrayPtr, Vec<bool>);
 def_ptr!(ByteArrayPtr, Vec<u8>);
 def_ptr!(IntArrayPtr, Vec<i32>);
@@ -0,0 +1,517 @@
+use crate::error::Error;
+use crate::instruction::{Instruction, Operand};
+use crate::opcode::OpCode;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+
+/// Symbolic branch target, bound to an offset by `Item::Label`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Label(pub u32);
+
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Label(Label),
+    Instruction(Instruction<Label>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Assembled {
+    pub code: Vec<u8>,
+    /// Offset of every label, for exception tables, line numbers and local
+    /// variable ranges
+    pub labels: HashMap<Label, u32>,
+}
+
+/// Collects instructions and labels, then lays them out with `assemble`
+#[derive(Debug, Clone, Default)]
+pub struct Assembler {
+    items: Vec<Item>,
+    next_label: u32,
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler::default()
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        let label = Label(self.next_label);
+        self.next_label += 1;
+        label
+    }
+
+    /// Binds `label` to the offset of the next instruction
+    pub fn bind(&mut self, label: Label) {
+        self.items.push(Item::Label(label));
+    }
+
+    pub fn push(&mut self, instruction: Instruction<Label>) {
+        self.items.push(Item::Instruction(instruction));
+    }
+
+    pub fn emit(&mut self, opcode: OpCode, operand: Operand<Label>) {
+        self.push(Instruction::new(opcode, operand));
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub fn assemble(&self) -> Result<Assembled, Error> {
+        assemble(&self.items)
+    }
+}
+
+/// How a branch ended up being encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Form {
+    Short,
+    /// `goto_w` or `jsr_w`
+    Wide,
+    /// The negated condition jumping over a `goto_w` to the target
+    Inverted,
+}
+
+/// Encodes `items`, choosing the shortest form of every instruction.
+///
+/// Loads and stores get their `<t>load_<n>` form or the `wide` prefix from the
+/// local index, `ldc` and `ldc_w` are picked from the constant pool index and
+/// `iinc` is widened when needed, whatever form the instruction came in. Branches
+/// start short and are widened until every offset fits: `goto` and `jsr`
+/// become `goto_w` and `jsr_w`, a conditional branch is inverted to skip over a
+/// `goto_w`. Widening only grows the code, so this terminates.
+pub fn assemble(items: &[Item]) -> Result<Assembled, Error> {
+    let items: Vec<Item> = items
+        .iter()
+        .map(|item| match item {
+            Item::Instruction(instruction) => Item::Instruction(shortest(instruction)),
+            label => label.clone(),
+        })
+        .collect();
+    let mut forms: Vec<Form> = items
+        .iter()
+        .map(|item| match item {
+            Item::Instruction(Instruction {
+                opcode: OpCode::goto_w,
+                ..
+            })
+            | Item::Instruction(Instruction {
+                opcode: OpCode::jsr_w,
+                ..
+            }) => Form::Wide,
+            _ => Form::Short,
+        })
+        .collect();
+
+    loop {
+        let (offsets, labels, _) = layout(&items, &forms)?;
+        let mut changed = false;
+        for (i, item) in items.iter().enumerate() {
+            if let Item::Instruction(Instruction {
+                opcode,
+                operand: Operand::Branch(target),
+            }) = item
+            {
+                if forms[i] != Form::Short {
+                    continue;
+                }
+                let offset = resolve(&labels, target)? as i64 - offsets[i] as i64;
+                if offset < i16::MIN as i64 || offset > i16::MAX as i64 {
+                    forms[i] = match opcode {
+                        OpCode::goto | OpCode::jsr => Form::Wide,
+                        _ => Form::Inverted,
+                    };
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let (offsets, labels, length) = layout(&items, &forms)?;
+    if length > u16::MAX as u32 {
+        return Err(Error::CodeTooLarge(length));
+    }
+    let mut code: Vec<u8> = Vec::with_capacity(length as usize);
+    for (i, item) in items.iter().enumerate() {
+        if let Item::Instruction(instruction) = item {
+            let pc = offsets[i];
+            let target = |label: &Label| -> Result<i64, Error> {
+                Ok(resolve(&labels, label)? as i64 - pc as i64)
+            };
+            match &instruction.operand {
+                Operand::Branch(label) => {
+                    let offset = target(label)?;
+                    match forms[i] {
+                        Form::Short => {
+                            code.push(instruction.opcode as u8);
+                            code.extend_from_slice(&(offset as i16).to_be_bytes());
+                        }
+                        Form::Wide => {
+                            let opcode = match instruction.opcode {
+                                OpCode::jsr | OpCode::jsr_w => OpCode::jsr_w,
+                                _ => OpCode::goto_w,
+                            };
+                            code.push(opcode as u8);
+                            code.extend_from_slice(&(offset as i32).to_be_bytes());
+                        }
+                        Form::Inverted => {
+                            let negated = instruction
+                                .opcode
+                                .negated()
+                                .ok_or(Error::InvalidOperand(instruction.opcode))?;
+                            code.push(negated as u8);
+                            code.extend_from_slice(&8i16.to_be_bytes());
+                            code.push(OpCode::goto_w as u8);
+                            code.extend_from_slice(&((offset - 3) as i32).to_be_bytes());
+                        }
+                    }
+                }
+                Operand::TableSwitch {
+                    default,
+                    low,
+                    high,
+                    targets,
+                } => {
+                    code.push(instruction.opcode as u8);
+                    pad(&mut code);
+                    code.extend_from_slice(&(target(default)? as i32).to_be_bytes());
+                    code.extend_from_slice(&low.to_be_bytes());
+                    code.extend_from_slice(&high.to_be_bytes());
+                    for label in targets {
+                        code.extend_from_slice(&(target(label)? as i32).to_be_bytes());
+                    }
+                }
+                Operand::LookupSwitch { default, pairs } => {
+                    code.push(instruction.opcode as u8);
+                    pad(&mut code);
+                    code.extend_from_slice(&(target(default)? as i32).to_be_bytes());
+                    code.extend_from_slice(&(pairs.len() as i32).to_be_bytes());
+                    let mut pairs: Vec<&(i32, Label)> = pairs.iter().collect();
+                    pairs.sort_by_key(|(key, _)| *key);
+                    for (key, label) in pairs {
+                        code.extend_from_slice(&key.to_be_bytes());
+                        code.extend_from_slice(&(target(label)? as i32).to_be_bytes());
+                    }
+                }
+                _ => encode(instruction, &mut code)?,
+            }
+            debug_assert_eq!(code.len() as u32, offsets[i] + size(item, pc, forms[i])?);
+        }
+    }
+    Ok(Assembled { code, labels })
+}
+
+/// Offsets of every item, the label map and the code length
+type Layout = (Vec<u32>, HashMap<Label, u32>, u32);
+
+/// Lays out the items with the given forms
+fn layout(items: &[Item], forms: &[Form]) -> Result<Layout, Error> {
+    let mut offsets: Vec<u32> = Vec::with_capacity(items.len());
+    let mut labels: HashMap<Label, u32> = HashMap::new();
+    let mut pc: u32 = 0;
+    for (item, form) in items.iter().zip(forms) {
+        offsets.push(pc);
+        if let Item::Label(label) = item {
+            if labels.insert(*label, pc).is_some() {
+                return Err(Error::DuplicateLabel(label.0));
+            }
+        }
+        pc += size(item, pc, *form)?;
+    }
+    Ok((offsets, labels, pc))
+}
+
+fn resolve(labels: &HashMap<Label, u32>, label: &Label) -> Result<u32, Error> {
+    labels
+        .get(label)
+        .copied()
+        .ok_or(Error::UndefinedLabel(label.0))
+}
+
+fn size(item: &Item, pc: u32, form: Form) -> Result<u32, Error> {
+    let instruction = match item {
+        Item::Label(_) => return Ok(0),
+        Item::Instruction(instruction) => instruction,
+    };
+    let padding = (4 - (pc + 1) % 4) % 4;
+    let size = match &instruction.operand {
+        Operand::Branch(_) => match form {
+            Form::Short => 3,
+            Form::Wide => 5,
+            Form::Inverted => 8,
+        },
+        Operand::TableSwitch {
+            low, high, targets, ..
+        } => {
+            if *low > *high || targets.len() as i64 != *high as i64 - *low as i64 + 1 {
+                return Err(Error::InvalidOperand(instruction.opcode));
+            }
+            1 + padding + 12 + 4 * targets.len() as u32
+        }
+        Operand::LookupSwitch { pairs, .. } => 1 + padding + 8 + 8 * pairs.len() as u32,
+        _ => {
+            let mut code: Vec<u8> = vec![];
+            encode(instruction, &mut code)?;
+            code.len() as u32
+        }
+    };
+    Ok(size)
+}
+
+fn pad(code: &mut Vec<u8>) {
+    let padding = (4 - code.len() % 4) % 4;
+    code.resize(code.len() + padding, 0);
+}
+
+/// Rewrites loads, stores, `ldc` and `iinc` to their shortest form
+fn shortest(instruction: &Instruction<Label>) -> Instruction<Label> {
+    let opcode = instruction.opcode as u8;
+    match (opcode, &instruction.operand) {
+        // iload to aload and istore to astore have one opcode per index up to 3
+        (0x15..=0x19, Operand::Local { index, .. }) if *index <= 3 => {
+            numbered(0x1a + 4 * (opcode - 0x15) + *index as u8)
+        }
+        (0x36..=0x3a, Operand::Local { index, .. }) if *index <= 3 => {
+            numbered(0x3b + 4 * (opcode - 0x36) + *index as u8)
+        }
+        (0x15..=0x19, Operand::Local { index, .. })
+        | (0x36..=0x3a, Operand::Local { index, .. })
+        | (0xa9, Operand::Local { index, .. }) => Instruction::new(
+            instruction.opcode,
+            Operand::Local {
+                index: *index,
+                wide: *index > u8::MAX as u16,
+            },
+        ),
+        (
+            0x84,
+            Operand::Iinc {
+                index, constant, ..
+            },
+        ) => Instruction::new(
+            OpCode::iinc,
+            Operand::Iinc {
+                index: *index,
+                constant: *constant,
+                wide: *index > u8::MAX as u16
+                    || *constant < i8::MIN as i16
+                    || *constant > i8::MAX as i16,
+            },
+        ),
+        (0x12, Operand::Constant(index)) | (0x13, Operand::Constant(index)) => {
+            let opcode = if *index <= u8::MAX as u16 {
+                OpCode::ldc
+            } else {
+                OpCode::ldc_w
+            };
+            Instruction::new(opcode, Operand::Constant(*index))
+        }
+        _ => instruction.clone(),
+    }
+}
+
+fn numbered(opcode: u8) -> Instruction<Label> {
+    // only called with iload_0 to aload_3 and istore_0 to astore_3
+    Instruction::new(OpCode::try_from(opcode).unwrap(), Operand::None)
+}
+
+/// Encodes an instruction without branch targets
+fn encode<T>(instruction: &Instruction<T>, code: &mut Vec<u8>) -> Result<(), Error> {
+    let opcode = instruction.opcode;
+    let invalid = Err(Error::InvalidOperand(opcode));
+    match (opcode as u8, &instruction.operand) {
+        (0x10, Operand::Byte(value)) => {
+            code.push(opcode as u8);
+            code.push(*value as u8);
+        }
+        (0x11, Operand::Short(value)) => {
+            code.push(opcode as u8);
+            code.extend_from_slice(&value.to_be_bytes());
+        }
+        (0x12, Operand::Constant(index)) => {
+            if *index > u8::MAX as u16 {
+                return invalid;
+            }
+            code.push(opcode as u8);
+            code.push(*index as u8);
+        }
+        (0x13 | 0x14 | 0xb2..=0xb8 | 0xbb | 0xbd | 0xc0 | 0xc1, Operand::Constant(index)) => {
+            code.push(opcode as u8);
+            code.extend_from_slice(&index.to_be_bytes());
+        }
+        (0xba, Operand::Constant(index)) => {
+            code.push(opcode as u8);
+            code.extend_from_slice(&index.to_be_bytes());
+            code.extend_from_slice(&[0, 0]);
+        }
+        (0x15..=0x19 | 0x36..=0x3a | 0xa9, Operand::Local { index, wide }) => {
+            if *wide {
+                code.push(OpCode::wide as u8);
+                code.push(opcode as u8);
+                code.extend_from_slice(&index.to_be_bytes());
+            } else {
+                if *index > u8::MAX as u16 {
+                    return invalid;
+                }
+                code.push(opcode as u8);
+                code.push(*index as u8);
+            }
+        }
+        (
+            0x84,
+            Operand::Iinc {
+                index,
+                constant,
+                wide,
+            },
+        ) => {
+            if *wide {
+                code.push(OpCode::wide as u8);
+                code.push(opcode as u8);
+                code.extend_from_slice(&index.to_be_bytes());
+                code.extend_from_slice(&constant.to_be_bytes());
+            } else {
+                if *index > u8::MAX as u16
+                    || *constant < i8::MIN as i16
+                    || *constant > i8::MAX as i16
+                {
+                    return invalid;
+                }
+                code.push(opcode as u8);
+                code.push(*index as u8);
+                code.push(*constant as i8 as u8);
+            }
+        }
+        (0xb9, Operand::InvokeInterface { index, count }) => {
+            code.push(opcode as u8);
+            code.extend_from_slice(&index.to_be_bytes());
+            code.push(*count);
+            code.push(0);
+        }
+        (0xbc, Operand::NewArray(array_type)) => {
+            code.push(opcode as u8);
+            code.push(*array_type as u8);
+        }
+        (0xc5, Operand::MultiANewArray { index, dimensions }) => {
+            code.push(opcode as u8);
+            code.extend_from_slice(&index.to_be_bytes());
+            code.push(*dimensions);
+        }
+        // every other opcode takes no operand, except wide which only prefixes
+        (0x10..=0x19 | 0x36..=0x3a | 0x84 | 0x99..=0xab | 0xb2..=0xbd | 0xc0 | 0xc1, _)
+        | (0xc4..=0xc9, _) => return invalid,
+        (_, Operand::None) => code.push(opcode as u8),
+        _ => return invalid,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assembler::{Assembler, Label};
+    use crate::decoder::decode;
+    use crate::error::Error;
+    use crate::instruction::{Instruction, Operand};
+    use crate::opcode::OpCode;
+
+    #[test]
+    fn assemble_with_labels() {
+        let mut asm = Assembler::new();
+        let (start, far, done) = (asm.new_label(), asm.new_label(), asm.new_label());
+        asm.bind(start);
+        asm.emit(
+            OpCode::iload,
+            Operand::Local {
+                index: 2,
+                wide: true,
+            },
+        );
+        asm.emit(
+            OpCode::istore,
+            Operand::Local {
+                index: 300,
+                wide: false,
+            },
+        );
+        asm.emit(OpCode::ldc_w, Operand::Constant(7));
+        asm.emit(OpCode::ifeq, Operand::Branch(far));
+        asm.emit(
+            OpCode::lookupswitch,
+            Operand::LookupSwitch {
+                default: done,
+                pairs: vec![(10, far), (-1, start)],
+            },
+        );
+        for _ in 0..40000 {
+            asm.emit(OpCode::nop, Operand::None);
+        }
+        asm.bind(far);
+        asm.emit(OpCode::goto, Operand::Branch(start));
+        asm.bind(done);
+        asm.emit(OpCode::vreturn, Operand::None);
+        let assembled = asm.assemble().unwrap();
+
+        let instructions = decode(&assembled.code).unwrap();
+        let far_offset = assembled.labels[&far];
+        let done_offset = assembled.labels[&done];
+        assert_eq!(
+            instructions[0],
+            (0, Instruction::new(OpCode::iload_2, Operand::None))
+        );
+        assert_eq!(
+            instructions[1].1.operand,
+            Operand::Local {
+                index: 300,
+                wide: true,
+            }
+        );
+        assert_eq!(
+            instructions[2],
+            (5, Instruction::new(OpCode::ldc, Operand::Constant(7)))
+        );
+        // ifeq too far away, inverted around a goto_w
+        assert_eq!(
+            instructions[3],
+            (7, Instruction::new(OpCode::ifne, Operand::Branch(15)))
+        );
+        assert_eq!(
+            instructions[4],
+            (
+                10,
+                Instruction::new(OpCode::goto_w, Operand::Branch(far_offset))
+            )
+        );
+        // 1 byte of padding after the opcode at 15, pairs sorted by key
+        assert_eq!(
+            instructions[5],
+            (
+                15,
+                Instruction::new(
+                    OpCode::lookupswitch,
+                    Operand::LookupSwitch {
+                        default: done_offset,
+                        pairs: vec![(-1, 0), (10, far_offset)],
+                    }
+                )
+            )
+        );
+        assert_eq!(far_offset, 40 + 40000);
+        let (_, goto) = &instructions[instructions.len() - 2];
+        assert_eq!(goto, &Instruction::new(OpCode::goto_w, Operand::Branch(0)));
+        assert_eq!(done_offset, far_offset + 5);
+
+        let mut asm = Assembler::new();
+        asm.emit(OpCode::goto, Operand::Branch(Label(9)));
+        assert_eq!(asm.assemble().unwrap_err(), Error::UndefinedLabel(9));
+    }
+}
@@ -0,0 +1,96 @@
+//! Searches the classes of jars, directories and class files for an
+//! instruction sequence, see `instructions::search` for the query syntax.
+//!
+//! ```text
+//! search 'invokevirtual java/lang/Thread.stop' lib/ build/classes
+//! ```
+//!
+//! prints one line per match, prefixed with the file the class was read from.
+//! Classes that can't be read or searched are reported on stderr and skipped.
+
+use classfile::class_file::ClassFile;
+use instructions::search::Query;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::process;
+use zip::ZipArchive;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("usage: search <query> <jar, directory or class file>...");
+        process::exit(2);
+    }
+    let query = match Query::parse(&args[0]) {
+        Ok(query) => query,
+        Err(error) => {
+            eprintln!("invalid query: {:?}", error);
+            process::exit(2);
+        }
+    };
+    for path in &args[1..] {
+        if let Err(error) = search_path(&query, Path::new(path)) {
+            eprintln!("{}: {}", path, error);
+        }
+    }
+}
+
+fn search_path(query: &Query, path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() || is_class(&entry) || is_jar(&entry) {
+                if let Err(error) = search_path(query, &entry) {
+                    eprintln!("{}: {}", entry.display(), error);
+                }
+            }
+        }
+    } else if is_jar(path) {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            if !file.name().ends_with(".class") {
+                continue;
+            }
+            let name = format!("{}!{}", path.display(), file.name());
+            let mut data = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut data)?;
+            search_class(query, &name, &data);
+        }
+    } else {
+        search_class(query, &path.display().to_string(), &fs::read(path)?);
+    }
+    Ok(())
+}
+
+fn search_class(query: &Query, name: &str, data: &[u8]) {
+    let class_file = match ClassFile::read_from(data) {
+        Ok(class_file) => class_file,
+        Err(error) => {
+            eprintln!("{}: {:?}", name, error);
+            return;
+        }
+    };
+    match query.search(&class_file) {
+        Ok(matches) => {
+            for found in matches {
+                println!("{}: {}", name, found);
+            }
+        }
+        Err(error) => eprintln!("{}: {:?}", name, error),
+    }
+}
+
+fn is_class(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "class")
+}
+
+fn is_jar(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "jar" || extension == "zip")
+}
@@ -0,0 +1,387 @@
+use crate::decoder::decode;
+use crate::error::Error;
+use crate::instruction::Instruction;
+use crate::metadata::Flow;
+use classfile::attribute::CodeAttribute;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// To the next block, also after a conditional branch isn't taken
+    FallThrough,
+    /// Taken conditional branch
+    Branch,
+    /// `goto` and `goto_w`
+    Jump,
+    /// A switch target or its default
+    Switch,
+    /// From a block covered by an exception handler to the handler,
+    /// `catch_type` is 0 for `finally`
+    Exception { catch_type: u16 },
+    /// From `jsr` to the subroutine
+    Subroutine,
+    /// From `ret` to the instruction after each `jsr` calling the subroutine
+    Ret,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Edge {
+    /// Index of the successor in `ControlFlowGraph::blocks`
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    /// Offset of the first instruction
+    pub start: u32,
+    /// Offset just past the last instruction
+    pub end: u32,
+    pub instructions: Vec<(u32, Instruction)>,
+    pub successors: Vec<Edge>,
+}
+
+impl BasicBlock {
+    pub fn last(&self) -> &(u32, Instruction) {
+        // blocks are never empty
+        self.instructions.last().unwrap()
+    }
+}
+
+/// Basic blocks of a method body in code order, the entry block first
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    block_index: HashMap<u32, usize>,
+}
+
+impl ControlFlowGraph {
+    /// Splits `code` into basic blocks and connects them.
+    ///
+    /// A block starts at the method entry, at branch targets and handlers, after
+    /// an instruction that doesn't only fall through and at both ends of every
+    /// range protected by an exception handler, so a block is either covered by
+    /// a handler as a whole or not at all. Every covered block gets an edge to
+    /// the handler, whether or not its instructions can throw.
+    pub fn build(code: &CodeAttribute) -> Result<ControlFlowGraph, Error> {
+        let instructions = decode(&code.code)?;
+        let length = code.code.len() as u32;
+        let boundaries: BTreeSet<u32> = instructions.iter().map(|(pc, _)| *pc).collect();
+        let is_boundary = |pc: u32| boundaries.contains(&pc) || pc == length;
+
+        let mut leaders: BTreeSet<u32> = BTreeSet::new();
+        leaders.insert(0);
+        for (i, (pc, instruction)) in instructions.iter().enumerate() {
+            for target in instruction.targets() {
+                if !boundaries.contains(target) {
+                    return Err(Error::InvalidBranchTarget(*pc, *target as i64));
+                }
+                leaders.insert(*target);
+            }
+            if instruction.opcode.info().flow != Flow::Next {
+                let next = instructions.get(i + 1).map_or(length, |(next, _)| *next);
+                leaders.insert(next);
+            }
+        }
+        for (i, exception) in code.exception_table.iter().enumerate() {
+            let (start, end, handler) = (
+                exception.start_pc as u32,
+                exception.end_pc as u32,
+                exception.handler_pc as u32,
+            );
+            if start >= end
+                || !is_boundary(start)
+                || !is_boundary(end)
+                || !boundaries.contains(&handler)
+            {
+                return Err(Error::InvalidExceptionTable(i as u16));
+            }
+            leaders.insert(start);
+            leaders.insert(end);
+            leaders.insert(handler);
+        }
+        leaders.remove(&length);
+
+        let mut blocks: Vec<BasicBlock> = vec![];
+        let mut block_index: HashMap<u32, usize> = HashMap::new();
+        for (pc, instruction) in instructions {
+            if leaders.contains(&pc) {
+                block_index.insert(pc, blocks.len());
+                blocks.push(BasicBlock {
+                    start: pc,
+                    end: pc,
+                    instructions: vec![],
+                    successors: vec![],
+                });
+            }
+            let block = blocks.last_mut().unwrap();
+            block.instructions.push((pc, instruction));
+        }
+        let ends: Vec<u32> = blocks
+            .iter()
+            .skip(1)
+            .map(|block| block.start)
+            .chain(std::iter::once(length))
+            .collect();
+        for (block, end) in blocks.iter_mut().zip(ends) {
+            block.end = end;
+        }
+
+        let mut cfg = ControlFlowGraph {
+            blocks,
+            block_index,
+        };
+        cfg.connect(code)?;
+        Ok(cfg)
+    }
+
+    fn connect(&mut self, code: &CodeAttribute) -> Result<(), Error> {
+        let length = code.code.len() as u32;
+        // return site of every jsr, by subroutine entry
+        let mut return_sites: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in 0..self.blocks.len() {
+            let (pc, instruction) = self.blocks[i].last().clone();
+            let end = self.blocks[i].end;
+            let next = self.block_index.get(&end).copied();
+            let mut edges: Vec<Edge> = vec![];
+            let target = |pc: &u32| self.block_index[pc];
+            match instruction.opcode.info().flow {
+                Flow::Next => edges.extend(next.map(|next| Edge {
+                    target: next,
+                    kind: EdgeKind::FallThrough,
+                })),
+                Flow::Branch => {
+                    edges.extend(next.map(|next| Edge {
+                        target: next,
+                        kind: EdgeKind::FallThrough,
+                    }));
+                    edges.push(Edge {
+                        target: target(instruction.targets()[0]),
+                        kind: EdgeKind::Branch,
+                    });
+                }
+                Flow::Jump => edges.push(Edge {
+                    target: target(instruction.targets()[0]),
+                    kind: EdgeKind::Jump,
+                }),
+                Flow::Switch => {
+                    for switch_target in instruction.targets() {
+                        edges.push(Edge {
+                            target: target(switch_target),
+                            kind: EdgeKind::Switch,
+                        });
+                    }
+                }
+                Flow::Subroutine => {
+                    let subroutine = target(instruction.targets()[0]);
+                    edges.push(Edge {
+                        target: subroutine,
+                        kind: EdgeKind::Subroutine,
+                    });
+                    // jsr as the last instruction could never be returned from
+                    let next = next.ok_or(Error::InvalidBranchTarget(pc, length as i64))?;
+                    return_sites.entry(subroutine).or_default().push(next);
+                }
+                Flow::Ret | Flow::Return | Flow::Throw => {}
+            }
+            let start = self.blocks[i].start;
+            for exception in &code.exception_table {
+                if exception.start_pc as u32 <= start && start < exception.end_pc as u32 {
+                    edges.push(Edge {
+                        target: target(&(exception.handler_pc as u32)),
+                        kind: EdgeKind::Exception {
+                            catch_type: exception.catch_type,
+                        },
+                    });
+                }
+            }
+            for edge in edges {
+                if !self.blocks[i].successors.contains(&edge) {
+                    self.blocks[i].successors.push(edge);
+                }
+            }
+        }
+
+        let mut subroutines: Vec<(usize, Vec<usize>)> = return_sites.into_iter().collect();
+        subroutines.sort();
+        for (entry, sites) in subroutines {
+            for block in self.subroutine_blocks(entry) {
+                if self.blocks[block].last().1.opcode.info().flow == Flow::Ret {
+                    for site in &sites {
+                        let edge = Edge {
+                            target: *site,
+                            kind: EdgeKind::Ret,
+                        };
+                        if !self.blocks[block].successors.contains(&edge) {
+                            self.blocks[block].successors.push(edge);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Blocks reachable from a subroutine entry without entering nested
+    /// subroutines, whose `jsr`s continue at their return sites instead
+    fn subroutine_blocks(&self, entry: usize) -> BTreeSet<usize> {
+        let mut reached: BTreeSet<usize> = BTreeSet::new();
+        let mut pending = vec![entry];
+        while let Some(block) = pending.pop() {
+            if !reached.insert(block) {
+                continue;
+            }
+            let jsr = self.blocks[block].last().1.opcode.info().flow == Flow::Subroutine;
+            for edge in &self.blocks[block].successors {
+                match edge.kind {
+                    EdgeKind::Subroutine | EdgeKind::Ret => {}
+                    _ => pending.push(edge.target),
+                }
+            }
+            if jsr {
+                pending.extend(self.block_index.get(&self.blocks[block].end));
+            }
+        }
+        reached
+    }
+
+    /// Index of the block starting at `pc`
+    pub fn block_index(&self, pc: u32) -> Option<usize> {
+        self.block_index.get(&pc).copied()
+    }
+
+    /// The block containing the instruction at `pc`
+    pub fn block_containing(&self, pc: u32) -> Option<&BasicBlock> {
+        let i = self.blocks.partition_point(|block| block.start <= pc);
+        self.blocks
+            .get(i.checked_sub(1)?)
+            .filter(|block| pc < block.end)
+    }
+
+    pub fn predecessors(&self, block: usize) -> Vec<usize> {
+        let mut predecessors: Vec<usize> = vec![];
+        for (i, other) in self.blocks.iter().enumerate() {
+            if other.successors.iter().any(|edge| edge.target == block) {
+                predecessors.push(i);
+            }
+        }
+        predecessors
+    }
+
+    /// Graphviz rendering with the disassembled instructions of each block
+    pub fn to_dot(&self) -> String {
+        let mut dot =
+            String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for (i, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for (pc, instruction) in &block.instructions {
+                write!(label, "{}: {}\\l", pc, escape(&instruction.to_string())).unwrap();
+            }
+            writeln!(dot, "    b{} [label=\"{}\"];", i, label).unwrap();
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            for edge in &block.successors {
+                let attributes = match edge.kind {
+                    EdgeKind::FallThrough | EdgeKind::Jump => String::new(),
+                    EdgeKind::Branch => " [label=\"taken\"]".to_string(),
+                    EdgeKind::Switch => " [label=\"switch\"]".to_string(),
+                    EdgeKind::Exception { catch_type: 0 } => {
+                        " [style=dashed, label=\"any\"]".to_string()
+                    }
+                    EdgeKind::Exception { catch_type } => {
+                        format!(" [style=dashed, label=\"#{}\"]", catch_type)
+                    }
+                    EdgeKind::Subroutine => " [style=bold, label=\"jsr\"]".to_string(),
+                    EdgeKind::Ret => " [style=dotted, label=\"ret\"]".to_string(),
+                };
+                writeln!(dot, "    b{} -> b{}{};", i, edge.target, attributes).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cfg::{ControlFlowGraph, Edge, EdgeKind};
+    use crate::jasmin::parse;
+
+    #[test]
+    fn build_control_flow_graph() {
+        let class_file = parse(
+            r#".class A
+.super java/lang/Object
+.method static m(I)I
+    .limit stack 2
+    .limit locals 3
+    .catch java/lang/Exception from Try to TryEnd using Handler
+Try:
+    iload_0
+    tableswitch 0 1
+        One
+        Two
+        default : Two
+One:
+    iinc 0 1
+    jsr Sub
+TryEnd:
+    iload_0
+    ireturn
+Two:
+    iload_0
+    ifeq One
+    goto TryEnd
+Handler:
+    astore_1
+    iconst_0
+    ireturn
+Sub:
+    astore_2
+    ret 2
+.end method
+"#,
+        )
+        .unwrap();
+        let code = class_file.methods[0].get_code_attr().unwrap();
+        let catch_type = code.exception_table[0].catch_type;
+        let cfg = ControlFlowGraph::build(code).unwrap();
+
+        let starts: Vec<u32> = cfg.blocks.iter().map(|block| block.start).collect();
+        assert_eq!(starts, vec![0, 24, 30, 32, 36, 39, 42]);
+        let successors = |block: usize| -> Vec<(usize, EdgeKind)> {
+            cfg.blocks[block]
+                .successors
+                .iter()
+                .map(|Edge { target, kind }| (*target, *kind))
+                .collect()
+        };
+        let exception = EdgeKind::Exception { catch_type };
+        assert_eq!(
+            successors(0),
+            vec![(3, EdgeKind::Switch), (1, EdgeKind::Switch), (5, exception)]
+        );
+        assert_eq!(
+            successors(1),
+            vec![(6, EdgeKind::Subroutine), (5, exception)]
+        );
+        assert_eq!(successors(2), vec![]);
+        assert_eq!(
+            successors(3),
+            vec![(4, EdgeKind::FallThrough), (1, EdgeKind::Branch)]
+        );
+        assert_eq!(successors(4), vec![(2, EdgeKind::Jump)]);
+        assert_eq!(successors(6), vec![(2, EdgeKind::Ret)]);
+        assert_eq!(cfg.predecessors(1), vec![0, 3]);
+        assert_eq!(cfg.block_containing(26).unwrap().start, 24);
+
+        let dot = cfg.to_dot();
+        assert!(dot.contains("    b1 [label=\"24: iinc 0, 1\\l27: jsr 42\\l\"];\n"));
+        assert!(dot.contains("    b6 -> b2 [style=dotted, label=\"ret\"];\n"));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::Error;
+use classfile::constant::Constant;
+use classfile::ConstantPoolRef;
+
+/// Lookups in the constant pool, failing with `InvalidConstant` on entries of
+/// the wrong kind
+#[derive(Clone, Copy)]
+pub(crate) struct Pool<'a>(pub(crate) &'a ConstantPoolRef);
+
+impl<'a> Pool<'a> {
+    pub(crate) fn constant(&self, index: u16) -> Result<&'a Constant, Error> {
+        (index as usize)
+            .checked_sub(1)
+            .and_then(|i| self.0.get(i))
+            .ok_or(Error::InvalidConstant(index))
+    }
+
+    pub(crate) fn utf8(&self, index: u16) -> Result<String, Error> {
+        match self.constant(index)? {
+            Constant::Utf8(bytes) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            _ => Err(Error::InvalidConstant(index)),
+        }
+    }
+
+    /// Internal name of a `Class` entry
+    pub(crate) fn class_name(&self, index: u16) -> Result<String, Error> {
+        match self.constant(index)? {
+            Constant::Class { name_index } => self.utf8(*name_index),
+            _ => Err(Error::InvalidConstant(index)),
+        }
+    }
+
+    pub(crate) fn name_and_type(&self, index: u16) -> Result<(String, String), Error> {
+        match self.constant(index)? {
+            Constant::NameAndType {
+                name_index,
+                descriptor_index,
+            } => Ok((self.utf8(*name_index)?, self.utf8(*descriptor_index)?)),
+            _ => Err(Error::InvalidConstant(index)),
+        }
+    }
+
+    /// Class, name and descriptor of a field or method reference
+    pub(crate) fn member(&self, index: u16) -> Result<(String, String, String), Error> {
+        match self.constant(index)? {
+            Constant::FieldRef {
+                class_index,
+                name_and_type_index,
+            }
+            | Constant::MethodRef {
+                class_index,
+                name_and_type_index,
+            }
+            | Constant::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => {
+                let (name, descriptor) = self.name_and_type(*name_and_type_index)?;
+                Ok((self.class_name(*class_index)?, name, descriptor))
+            }
+            _ => Err(Error::InvalidConstant(index)),
+        }
+    }
+}
@@ -0,0 +1,514 @@
+use crate::cfg::{Edge, EdgeKind};
+use crate::dataflow::{local_access, Access, Analysis, Direction};
+use crate::instruction::{Instruction, Operand};
+use crate::metadata::stack_effect;
+use classfile::constant::Constant;
+use classfile::ConstantPoolRef;
+use std::cmp::Ordering;
+
+/// Contents of a local variable slot or an operand stack entry.
+///
+/// A `long` or `double` takes two entries, the value followed by `NotConstant`.
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// Not known, or not the same on every path
+    NotConstant,
+}
+
+impl PartialEq for Value {
+    /// Floating point values are compared by their bits, so NaN equals itself
+    /// and the analysis reaches a fixed point
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Long(a), Value::Long(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Double(a), Value::Double(b)) => a.to_bits() == b.to_bits(),
+            (Value::NotConstant, Value::NotConstant) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    fn is_wide(&self) -> bool {
+        matches!(self, Value::Long(_) | Value::Double(_))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub locals: Vec<Value>,
+    pub stack: Vec<Value>,
+}
+
+impl Frame {
+    fn push(&mut self, value: Value) {
+        let is_wide = value.is_wide();
+        self.stack.push(value);
+        if is_wide {
+            self.stack.push(Value::NotConstant);
+        }
+    }
+
+    /// Pops one entry, or two for `long` and `double`
+    fn pop(&mut self, wide: bool) -> Value {
+        if wide {
+            self.stack.pop();
+        }
+        self.stack.pop().unwrap_or(Value::NotConstant)
+    }
+
+    fn pop_slots(&mut self, slots: usize) {
+        let length = self.stack.len().saturating_sub(slots);
+        self.stack.truncate(length);
+    }
+
+    #[allow(clippy::manual_repeat_n)]
+    fn push_unknown(&mut self, slots: usize) {
+        self.stack
+            .extend(std::iter::repeat(Value::NotConstant).take(slots));
+    }
+
+    /// Copies the top `count` entries below the `skip` entries under them, as
+    /// the `dup` family does
+    fn dup(&mut self, count: usize, skip: usize) {
+        if self.stack.len() < count + skip {
+            self.stack.clear();
+            self.push_unknown(count * 2 + skip);
+            return;
+        }
+        let top = self.stack.split_off(self.stack.len() - count);
+        let below = self.stack.split_off(self.stack.len() - skip);
+        self.stack.extend_from_slice(&top);
+        self.stack.extend(below);
+        self.stack.extend(top);
+    }
+}
+
+/// Values of local variables and operand stack entries that are the same
+/// constant on every path to an instruction.
+///
+/// Integer and floating point arithmetic, conversions, comparisons and `iinc`
+/// are folded with Java semantics; an integer division by a constant zero is
+/// not folded since it throws. The state is None where no path reaches.
+#[derive(Debug, Clone)]
+pub struct ConstantPropagation {
+    constant_pool: ConstantPoolRef,
+    max_locals: u16,
+}
+
+impl ConstantPropagation {
+    pub fn new(constant_pool: ConstantPoolRef, max_locals: u16) -> ConstantPropagation {
+        ConstantPropagation {
+            constant_pool,
+            max_locals,
+        }
+    }
+
+    fn constant(&self, index: u16) -> Option<&Constant> {
+        (index as usize)
+            .checked_sub(1)
+            .and_then(|i| self.constant_pool.get(i))
+    }
+
+    fn ldc(&self, frame: &mut Frame, index: u16) {
+        match self.constant(index) {
+            Some(Constant::Integer(value)) => frame.push(Value::Int(*value)),
+            Some(Constant::Float(value)) => frame.push(Value::Float(*value)),
+            Some(Constant::Long(value)) => frame.push(Value::Long(*value)),
+            Some(Constant::Double(value)) => frame.push(Value::Double(*value)),
+            Some(Constant::Dynamic {
+                name_and_type_index,
+                ..
+            }) => {
+                // a dynamically computed long or double takes two slots
+                let wide = match self.constant(*name_and_type_index) {
+                    Some(Constant::NameAndType {
+                        descriptor_index, ..
+                    }) => matches!(
+                        self.constant(*descriptor_index),
+                        Some(Constant::Utf8(d)) if d.as_slice() == b"J" || d.as_slice() == b"D"
+                    ),
+                    _ => false,
+                };
+                frame.push_unknown(if wide { 2 } else { 1 });
+            }
+            _ => frame.push_unknown(1),
+        }
+    }
+}
+
+impl Analysis for ConstantPropagation {
+    type Domain = Option<Frame>;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn bottom(&self) -> Self::Domain {
+        None
+    }
+
+    fn boundary(&self) -> Self::Domain {
+        Some(Frame {
+            locals: vec![Value::NotConstant; self.max_locals as usize],
+            stack: vec![],
+        })
+    }
+
+    fn join(&self, into: &mut Self::Domain, other: &Self::Domain) -> bool {
+        let other = match other {
+            Some(other) => other,
+            None => return false,
+        };
+        let into = match into {
+            Some(into) => into,
+            None => {
+                *into = Some(other.clone());
+                return true;
+            }
+        };
+        let mut changed = false;
+        let mut merge = |values: &mut Vec<Value>, others: &[Value]| {
+            if values.len() != others.len() {
+                // only in invalid code
+                values.resize(values.len().max(others.len()), Value::NotConstant);
+            }
+            for (value, other) in values.iter_mut().zip(others) {
+                if *value != *other && *value != Value::NotConstant {
+                    *value = Value::NotConstant;
+                    changed = true;
+                }
+            }
+        };
+        merge(&mut into.locals, &other.locals);
+        merge(&mut into.stack, &other.stack);
+        changed
+    }
+
+    fn transfer(&self, state: &mut Self::Domain, _pc: u32, instruction: &Instruction) {
+        let frame = match state {
+            Some(frame) => frame,
+            None => return,
+        };
+        let opcode = instruction.opcode as u8;
+        match opcode {
+            // iconst_m1 to iconst_5
+            0x02..=0x08 => frame.push(Value::Int(opcode as i32 - 0x03)),
+            0x09 | 0x0a => frame.push(Value::Long((opcode - 0x09) as i64)),
+            0x0b..=0x0d => frame.push(Value::Float((opcode - 0x0b) as f32)),
+            0x0e | 0x0f => frame.push(Value::Double((opcode - 0x0e) as f64)),
+            0x10 | 0x11 => match instruction.operand {
+                Operand::Byte(value) => frame.push(Value::Int(value as i32)),
+                Operand::Short(value) => frame.push(Value::Int(value as i32)),
+                _ => frame.push_unknown(1),
+            },
+            0x12..=0x14 => match instruction.operand {
+                Operand::Constant(index) => self.ldc(frame, index),
+                _ => frame.push_unknown(1),
+            },
+            // loads, stores and iinc
+            0x15..=0x2d | 0x36..=0x4e | 0x84 => {
+                let (index, width, access) = match local_access(instruction) {
+                    Some(access) => access,
+                    None => return,
+                };
+                let (index, width) = (index as usize, width as usize);
+                if frame.locals.len() < index + width {
+                    frame.locals.resize(index + width, Value::NotConstant);
+                }
+                match access {
+                    Access::Read => {
+                        let values = frame.locals[index..index + width].to_vec();
+                        frame.stack.extend(values);
+                    }
+                    Access::Write => {
+                        let length = frame.stack.len().saturating_sub(width);
+                        let mut values = frame.stack.split_off(length);
+                        values.resize(width, Value::NotConstant);
+                        // a store into the second slot of a long or double
+                        // breaks the value
+                        if index > 0 && frame.locals[index - 1].is_wide() {
+                            frame.locals[index - 1] = Value::NotConstant;
+                        }
+                        frame.locals.splice(index..index + width, values);
+                    }
+                    Access::ReadWrite => {
+                        frame.locals[index] = match (frame.locals[index], &instruction.operand) {
+                            (Value::Int(value), Operand::Iinc { constant, .. }) => {
+                                Value::Int(value.wrapping_add(*constant as i32))
+                            }
+                            _ => Value::NotConstant,
+                        };
+                    }
+                }
+            }
+            // pop, pop2
+            0x57 => frame.pop_slots(1),
+            0x58 => frame.pop_slots(2),
+            // dup, dup_x1, dup_x2, dup2, dup2_x1, dup2_x2
+            0x59 => frame.dup(1, 0),
+            0x5a => frame.dup(1, 1),
+            0x5b => frame.dup(1, 2),
+            0x5c => frame.dup(2, 0),
+            0x5d => frame.dup(2, 1),
+            0x5e => frame.dup(2, 2),
+            // swap
+            0x5f => {
+                let length = frame.stack.len();
+                if length >= 2 {
+                    frame.stack.swap(length - 1, length - 2);
+                }
+            }
+            // add, sub, mul, div, rem and neg for each of i, l, f, d
+            0x60..=0x77 => {
+                let kind = (opcode - 0x60) % 4;
+                let operation = (opcode - 0x60) / 4;
+                let wide = kind == 1 || kind == 3;
+                let right = frame.pop(wide);
+                let left = if operation == 5 {
+                    right
+                } else {
+                    frame.pop(wide)
+                };
+                let result = arithmetic(operation, left, right);
+                push_result(frame, result, wide);
+            }
+            // shl, shr, ushr for i and l, the distance is always an int
+            0x78..=0x7d => {
+                let wide = (opcode - 0x78) % 2 == 1;
+                let distance = frame.pop(false);
+                let value = frame.pop(wide);
+                let result = match (value, distance) {
+                    (Value::Int(value), Value::Int(distance)) => {
+                        let distance = distance as u32 & 0x1f;
+                        Some(Value::Int(match (opcode - 0x78) / 2 {
+                            0 => value.wrapping_shl(distance),
+                            1 => value.wrapping_shr(distance),
+                            _ => ((value as u32) >> distance) as i32,
+                        }))
+                    }
+                    (Value::Long(value), Value::Int(distance)) => {
+                        let distance = distance as u32 & 0x3f;
+                        Some(Value::Long(match (opcode - 0x78) / 2 {
+                            0 => value.wrapping_shl(distance),
+                            1 => value.wrapping_shr(distance),
+                            _ => ((value as u64) >> distance) as i64,
+                        }))
+                    }
+                    _ => None,
+                };
+                push_result(frame, result, wide);
+            }
+            // and, or, xor for i and l
+            0x7e..=0x83 => {
+                let wide = (opcode - 0x7e) % 2 == 1;
+                let right = frame.pop(wide);
+                let left = frame.pop(wide);
+                let operation = (opcode - 0x7e) / 2;
+                let result = match (left, right) {
+                    (Value::Int(a), Value::Int(b)) => Some(Value::Int(match operation {
+                        0 => a & b,
+                        1 => a | b,
+                        _ => a ^ b,
+                    })),
+                    (Value::Long(a), Value::Long(b)) => Some(Value::Long(match operation {
+                        0 => a & b,
+                        1 => a | b,
+                        _ => a ^ b,
+                    })),
+                    _ => None,
+                };
+                push_result(frame, result, wide);
+            }
+            // i2l to i2s
+            0x85..=0x93 => {
+                let wide_source = matches!(opcode, 0x88..=0x8a | 0x8e..=0x90);
+                let value = frame.pop(wide_source);
+                // Rust's `as` saturates and maps NaN to zero like the JVM
+                let result = match (opcode, value) {
+                    (0x85, Value::Int(v)) => Some(Value::Long(v as i64)),
+                    (0x86, Value::Int(v)) => Some(Value::Float(v as f32)),
+                    (0x87, Value::Int(v)) => Some(Value::Double(v as f64)),
+                    (0x88, Value::Long(v)) => Some(Value::Int(v as i32)),
+                    (0x89, Value::Long(v)) => Some(Value::Float(v as f32)),
+                    (0x8a, Value::Long(v)) => Some(Value::Double(v as f64)),
+                    (0x8b, Value::Float(v)) => Some(Value::Int(v as i32)),
+                    (0x8c, Value::Float(v)) => Some(Value::Long(v as i64)),
+                    (0x8d, Value::Float(v)) => Some(Value::Double(v as f64)),
+                    (0x8e, Value::Double(v)) => Some(Value::Int(v as i32)),
+                    (0x8f, Value::Double(v)) => Some(Value::Long(v as i64)),
+                    (0x90, Value::Double(v)) => Some(Value::Float(v as f32)),
+                    (0x91, Value::Int(v)) => Some(Value::Int(v as i8 as i32)),
+                    (0x92, Value::Int(v)) => Some(Value::Int(v as u16 as i32)),
+                    (0x93, Value::Int(v)) => Some(Value::Int(v as i16 as i32)),
+                    _ => None,
+                };
+                let wide_result = matches!(opcode, 0x85 | 0x87 | 0x8a | 0x8c | 0x8d | 0x8f);
+                push_result(frame, result, wide_result);
+            }
+            // lcmp, fcmpl, fcmpg, dcmpl, dcmpg
+            0x94..=0x98 => {
+                let wide = matches!(opcode, 0x94 | 0x97 | 0x98);
+                let right = frame.pop(wide);
+                let left = frame.pop(wide);
+                // fcmpl and dcmpl push -1 for NaN, fcmpg and dcmpg 1
+                let nan = if opcode == 0x95 || opcode == 0x97 {
+                    -1
+                } else {
+                    1
+                };
+                let ordering = match (left, right) {
+                    (Value::Long(a), Value::Long(b)) => Some(a.cmp(&b)),
+                    (Value::Float(a), Value::Float(b)) => a.partial_cmp(&b),
+                    (Value::Double(a), Value::Double(b)) => a.partial_cmp(&b),
+                    _ => {
+                        frame.push_unknown(1);
+                        return;
+                    }
+                };
+                frame.push(Value::Int(match ordering {
+                    Some(Ordering::Less) => -1,
+                    Some(Ordering::Equal) => 0,
+                    Some(Ordering::Greater) => 1,
+                    None => nan,
+                }));
+            }
+            _ => match stack_effect(instruction, &self.constant_pool) {
+                Ok((pops, pushes)) => {
+                    frame.pop_slots(pops as usize);
+                    frame.push_unknown(pushes as usize);
+                }
+                // an unresolvable instruction never completes normally
+                Err(_) => *state = None,
+            },
+        }
+    }
+
+    /// A handler starts with only the exception on the stack
+    fn edge(&self, state: &Self::Domain, edge: &Edge) -> Self::Domain {
+        match (state, edge.kind) {
+            (Some(frame), EdgeKind::Exception { .. }) => Some(Frame {
+                locals: frame.locals.clone(),
+                stack: vec![Value::NotConstant],
+            }),
+            _ => state.clone(),
+        }
+    }
+}
+
+fn push_result(frame: &mut Frame, result: Option<Value>, wide: bool) {
+    match result {
+        Some(value) => frame.push(value),
+        None => frame.push_unknown(if wide { 2 } else { 1 }),
+    }
+}
+
+/// Folds add, sub, mul, div, rem (0 to 4) or neg (5, `right` is ignored)
+fn arithmetic(operation: u8, left: Value, right: Value) -> Option<Value> {
+    match (left, right) {
+        (Value::Int(a), Value::Int(b)) => Some(Value::Int(match operation {
+            0 => a.wrapping_add(b),
+            1 => a.wrapping_sub(b),
+            2 => a.wrapping_mul(b),
+            3 if b != 0 => a.wrapping_div(b),
+            4 if b != 0 => a.wrapping_rem(b),
+            5 => a.wrapping_neg(),
+            _ => return None,
+        })),
+        (Value::Long(a), Value::Long(b)) => Some(Value::Long(match operation {
+            0 => a.wrapping_add(b),
+            1 => a.wrapping_sub(b),
+            2 => a.wrapping_mul(b),
+            3 if b != 0 => a.wrapping_div(b),
+            4 if b != 0 => a.wrapping_rem(b),
+            5 => a.wrapping_neg(),
+            _ => return None,
+        })),
+        // `%` on floating point values truncates like Java's drem
+        (Value::Float(a), Value::Float(b)) => Some(Value::Float(match operation {
+            0 => a + b,
+            1 => a - b,
+            2 => a * b,
+            3 => a / b,
+            4 => a % b,
+            _ => -a,
+        })),
+        (Value::Double(a), Value::Double(b)) => Some(Value::Double(match operation {
+            0 => a + b,
+            1 => a - b,
+            2 => a * b,
+            3 => a / b,
+            4 => a % b,
+            _ => -a,
+        })),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cfg::ControlFlowGraph;
+    use crate::dataflow::constant_propagation::{ConstantPropagation, Value};
+    use crate::dataflow::solve;
+    use crate::jasmin::parse;
+
+    #[test]
+    fn propagate_constants() {
+        let class_file = parse(
+            r#".class A
+.super java/lang/Object
+.method static m(I)J
+    .limit stack 4
+    .limit locals 4
+    bipush 6
+    iconst_4
+    imul
+    istore_1
+    iload_0
+    ifeq Else
+    iload_1
+    iconst_3
+    iadd
+    istore_2
+    goto Join
+Else:
+    iload_1
+    bipush 3
+    iadd
+    istore_2
+    iconst_0
+    istore_1
+Join:
+    iload_2
+    i2l
+    ldc2_w 5
+    lmul
+    iload_1
+    pop
+    lreturn
+.end method
+"#,
+        )
+        .unwrap();
+        let code = class_file.methods[0].get_code_attr().unwrap();
+        let cfg = ControlFlowGraph::build(code).unwrap();
+        let analysis = ConstantPropagation::new(class_file.constant_pool.clone(), code.max_locals);
+        let results = solve(&analysis, &cfg);
+
+        let join = cfg.blocks.len() - 1;
+        let frame = results.entry[join].clone().unwrap();
+        assert_eq!(frame.locals[0], Value::NotConstant);
+        // the two branches disagree on slot 1 and agree on slot 2
+        assert_eq!(frame.locals[1], Value::NotConstant);
+        assert_eq!(frame.locals[2], Value::Int(27));
+        assert!(frame.stack.is_empty());
+
+        let lreturn = cfg.blocks[join].last().0;
+        let frame = results.before(&analysis, &cfg, lreturn).unwrap().unwrap();
+        assert_eq!(frame.stack, vec![Value::Long(135), Value::NotConstant]);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::dataflow::{local_access, Access, Analysis, Direction};
+use crate::instruction::Instruction;
+use std::collections::BTreeSet;
+
+/// Local variable slots whose current value may still be read.
+///
+/// A slot is live before an instruction if some path from it reads the slot
+/// before writing it; `long` and `double` values keep both of their slots live.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Liveness;
+
+impl Analysis for Liveness {
+    type Domain = BTreeSet<u16>;
+
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn bottom(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn boundary(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn join(&self, into: &mut Self::Domain, other: &Self::Domain) -> bool {
+        let before = into.len();
+        into.extend(other.iter().copied());
+        into.len() != before
+    }
+
+    fn transfer(&self, state: &mut Self::Domain, _pc: u32, instruction: &Instruction) {
+        if let Some((index, width, access)) = local_access(instruction) {
+            let slots = index..index.saturating_add(width);
+            match access {
+                Access::Write => slots.for_each(|slot| {
+                    state.remove(&slot);
+                }),
+                Access::Read | Access::ReadWrite => state.extend(slots),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cfg::ControlFlowGraph;
+    use crate::dataflow::liveness::Liveness;
+    use crate::dataflow::solve;
+    use crate::jasmin::parse;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn liveness_of_locals() {
+        let source = "\
+.class public Live
+.super java/lang/Object
+
+.method public static f(IJ)I
+    .limit stack 4
+    .limit locals 5
+    lload_1
+    l2i
+    istore 3
+    iconst_0
+    istore 4
+Loop:
+    iload 4
+    iload_0
+    if_icmpge Done
+    iinc 4 1
+    goto Loop
+Done:
+    iload_3
+    ireturn
+.end method
+";
+        let class_file = parse(source).unwrap();
+        let code = class_file.methods[0].get_code_attr().unwrap();
+        let cfg = ControlFlowGraph::build(code).unwrap();
+        let results = solve(&Liveness, &cfg);
+
+        let live = |slots: Option<BTreeSet<u16>>| slots.unwrap().into_iter().collect::<Vec<_>>();
+        // lload_1 reads both slots of the long
+        assert_eq!(live(results.before(&Liveness, &cfg, 0)), vec![0, 1, 2]);
+        // the long is dead once stored into istore_3, slot 3 is live after it
+        assert_eq!(live(results.before(&Liveness, &cfg, 2)), vec![0]);
+        assert_eq!(live(results.after(&Liveness, &cfg, 2)), vec![0, 3]);
+        // the loop keeps the bound, the counter and the result live
+        let header = cfg.block_index(6).unwrap();
+        assert_eq!(live(Some(results.entry[header].clone())), vec![0, 3, 4]);
+        assert!(results.exit[cfg.blocks.len() - 1].is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+//! Worklist based dataflow analysis over a control-flow graph.
+//!
+//! An analysis describes a lattice through `bottom` and `join` and how each
+//! instruction transforms a state; `solve` iterates to a fixed point and keeps
+//! the state at both ends of every basic block, the state at any instruction is
+//! recomputed from those on demand.
+
+use crate::cfg::{BasicBlock, ControlFlowGraph, Edge, EdgeKind};
+use crate::instruction::Instruction;
+use std::collections::VecDeque;
+
+pub mod constant_propagation;
+pub mod liveness;
+pub mod reaching_definitions;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// States flow from the method entry along the edges
+    Forward,
+    /// States flow from the method exits against the edges
+    Backward,
+}
+
+pub trait Analysis {
+    type Domain: Clone + PartialEq;
+
+    const DIRECTION: Direction;
+
+    /// Initial state of every block, the identity of `join`
+    fn bottom(&self) -> Self::Domain;
+
+    /// State at the method entry for a forward analysis, after each
+    /// instruction leaving the method for a backward one
+    fn boundary(&self) -> Self::Domain;
+
+    /// Merges `other` into `into`, returning whether `into` changed
+    fn join(&self, into: &mut Self::Domain, other: &Self::Domain) -> bool;
+
+    /// Applies the effect of the instruction at `pc`, in the direction of the
+    /// analysis
+    fn transfer(&self, state: &mut Self::Domain, pc: u32, instruction: &Instruction);
+
+    /// State passed along `edge`, the default passes it unchanged
+    fn edge(&self, state: &Self::Domain, _edge: &Edge) -> Self::Domain {
+        state.clone()
+    }
+}
+
+/// Fixed point of an analysis, indexed like `ControlFlowGraph::blocks`
+#[derive(Debug, Clone)]
+pub struct Results<D> {
+    /// State before the first instruction of each block
+    pub entry: Vec<D>,
+    /// State after the last instruction of each block
+    pub exit: Vec<D>,
+}
+
+impl<D: Clone + PartialEq> Results<D> {
+    /// State just before the instruction at `pc` executes
+    pub fn before<A>(&self, analysis: &A, cfg: &ControlFlowGraph, pc: u32) -> Option<D>
+    where
+        A: Analysis<Domain = D>,
+    {
+        self.states(analysis, cfg, pc).map(|(before, _)| before)
+    }
+
+    /// State just after the instruction at `pc` completes normally
+    pub fn after<A>(&self, analysis: &A, cfg: &ControlFlowGraph, pc: u32) -> Option<D>
+    where
+        A: Analysis<Domain = D>,
+    {
+        self.states(analysis, cfg, pc).map(|(_, after)| after)
+    }
+
+    fn states<A>(&self, analysis: &A, cfg: &ControlFlowGraph, pc: u32) -> Option<(D, D)>
+    where
+        A: Analysis<Domain = D>,
+    {
+        let block = cfg.block_index(cfg.block_containing(pc)?.start)?;
+        let instructions = &cfg.blocks[block].instructions;
+        let position = instructions.iter().position(|(at, _)| *at == pc)?;
+        match A::DIRECTION {
+            Direction::Forward => {
+                let mut state = self.entry[block].clone();
+                for (at, instruction) in &instructions[..position] {
+                    analysis.transfer(&mut state, *at, instruction);
+                }
+                let before = state.clone();
+                let (at, instruction) = &instructions[position];
+                analysis.transfer(&mut state, *at, instruction);
+                Some((before, state))
+            }
+            Direction::Backward => {
+                let handlers = handler_state(analysis, cfg, &self.entry, block);
+                let mut state = self.exit[block].clone();
+                for (at, instruction) in instructions[position + 1..].iter().rev() {
+                    analysis.transfer(&mut state, *at, instruction);
+                    analysis.join(&mut state, &handlers);
+                }
+                let after = state.clone();
+                let (at, instruction) = &instructions[position];
+                analysis.transfer(&mut state, *at, instruction);
+                analysis.join(&mut state, &handlers);
+                Some((state, after))
+            }
+        }
+    }
+}
+
+/// Runs `analysis` over `cfg` to a fixed point.
+///
+/// An instruction covered by an exception handler may transfer control to it
+/// before it completes: a forward analysis passes the join of the states before
+/// each instruction of a covered block to its handlers, a backward analysis
+/// joins the entry states of the handlers into the state before each of them.
+pub fn solve<A: Analysis>(analysis: &A, cfg: &ControlFlowGraph) -> Results<A::Domain> {
+    let count = cfg.blocks.len();
+    let mut results = Results {
+        entry: vec![analysis.bottom(); count],
+        exit: vec![analysis.bottom(); count],
+    };
+    if count == 0 {
+        return results;
+    }
+    let predecessors: Vec<Vec<usize>> = (0..count).map(|b| cfg.predecessors(b)).collect();
+    match A::DIRECTION {
+        Direction::Forward => results.entry[0] = analysis.boundary(),
+        Direction::Backward => {
+            for (index, block) in cfg.blocks.iter().enumerate() {
+                if block.successors.iter().all(is_exception) {
+                    results.exit[index] = analysis.boundary();
+                }
+            }
+        }
+    }
+
+    let mut worklist: VecDeque<usize> = match A::DIRECTION {
+        Direction::Forward => (0..count).collect(),
+        Direction::Backward => (0..count).rev().collect(),
+    };
+    let mut queued = vec![true; count];
+    while let Some(index) = worklist.pop_front() {
+        queued[index] = false;
+        let block = &cfg.blocks[index];
+        let mut enqueue = |target: usize, worklist: &mut VecDeque<usize>| {
+            if !queued[target] {
+                queued[target] = true;
+                worklist.push_back(target);
+            }
+        };
+        match A::DIRECTION {
+            Direction::Forward => {
+                let (exit, thrown) = forward(analysis, block, results.entry[index].clone());
+                results.exit[index] = exit;
+                for edge in &block.successors {
+                    let state = if is_exception(edge) {
+                        &thrown
+                    } else {
+                        &results.exit[index]
+                    };
+                    let state = analysis.edge(state, edge);
+                    if analysis.join(&mut results.entry[edge.target], &state) {
+                        enqueue(edge.target, &mut worklist);
+                    }
+                }
+            }
+            Direction::Backward => {
+                let handlers = handler_state(analysis, cfg, &results.entry, index);
+                let mut state = results.exit[index].clone();
+                for (pc, instruction) in block.instructions.iter().rev() {
+                    analysis.transfer(&mut state, *pc, instruction);
+                    analysis.join(&mut state, &handlers);
+                }
+                if state == results.entry[index] {
+                    continue;
+                }
+                results.entry[index] = state;
+                for &predecessor in &predecessors[index] {
+                    for edge in &cfg.blocks[predecessor].successors {
+                        if edge.target != index || is_exception(edge) {
+                            continue;
+                        }
+                        let state = analysis.edge(&results.entry[index], edge);
+                        analysis.join(&mut results.exit[predecessor], &state);
+                    }
+                    // the predecessor is revisited even if only its handler state changed
+                    enqueue(predecessor, &mut worklist);
+                }
+            }
+        }
+    }
+    results
+}
+
+/// Applies a block forward, returning its exit state and the join of the
+/// states before each instruction
+fn forward<A: Analysis>(
+    analysis: &A,
+    block: &BasicBlock,
+    mut state: A::Domain,
+) -> (A::Domain, A::Domain) {
+    let mut thrown = analysis.bottom();
+    for (pc, instruction) in &block.instructions {
+        analysis.join(&mut thrown, &state);
+        analysis.transfer(&mut state, *pc, instruction);
+    }
+    (state, thrown)
+}
+
+/// Join of the entry states of the handlers covering `block`
+fn handler_state<A: Analysis>(
+    analysis: &A,
+    cfg: &ControlFlowGraph,
+    entry: &[A::Domain],
+    block: usize,
+) -> A::Domain {
+    let mut state = analysis.bottom();
+    for edge in cfg.blocks[block]
+        .successors
+        .iter()
+        .filter(|e| is_exception(e))
+    {
+        analysis.join(&mut state, &analysis.edge(&entry[edge.target], edge));
+    }
+    state
+}
+
+fn is_exception(edge: &Edge) -> bool {
+    matches!(edge.kind, EdgeKind::Exception { .. })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// `iinc`
+    ReadWrite,
+}
+
+/// Local variable slots accessed by an instruction: the first slot, the number
+/// of slots (2 for `long` and `double`) and how they are accessed
+pub fn local_access(instruction: &Instruction) -> Option<(u16, u16, Access)> {
+    let index = instruction.local_index();
+    let opcode = instruction.opcode as u8;
+    // i, l, f, d, a
+    let (kind, access) = match opcode {
+        0x15..=0x19 => (opcode - 0x15, Access::Read),
+        0x1a..=0x2d => ((opcode - 0x1a) / 4, Access::Read),
+        0x36..=0x3a => (opcode - 0x36, Access::Write),
+        0x3b..=0x4e => ((opcode - 0x3b) / 4, Access::Write),
+        // iinc
+        0x84 => (0, Access::ReadWrite),
+        // ret
+        0xa9 => (4, Access::Read),
+        _ => return None,
+    };
+    let width = if kind == 1 || kind == 3 { 2 } else { 1 };
+    index.map(|index| (index, width, access))
+}
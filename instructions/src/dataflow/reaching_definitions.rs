@@ -0,0 +1,110 @@
+use crate::dataflow::{local_access, Access, Analysis, Direction};
+use crate::instruction::Instruction;
+use std::collections::BTreeSet;
+
+/// A write to a local variable slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Definition {
+    pub slot: u16,
+    /// Offset of the store or `iinc`, None for the value a parameter is
+    /// passed in
+    pub pc: Option<u32>,
+}
+
+/// Definitions of local variables that may reach an instruction without being
+/// overwritten on the way.
+///
+/// Both slots of a `long` or `double` get a definition.
+#[derive(Debug, Clone, Copy)]
+pub struct ReachingDefinitions {
+    parameter_slots: u16,
+}
+
+impl ReachingDefinitions {
+    /// `parameter_slots` counts the slots of the arguments, including `this`
+    /// for an instance method
+    pub fn new(parameter_slots: u16) -> ReachingDefinitions {
+        ReachingDefinitions { parameter_slots }
+    }
+}
+
+impl Analysis for ReachingDefinitions {
+    type Domain = BTreeSet<Definition>;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn bottom(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn boundary(&self) -> Self::Domain {
+        (0..self.parameter_slots)
+            .map(|slot| Definition { slot, pc: None })
+            .collect()
+    }
+
+    fn join(&self, into: &mut Self::Domain, other: &Self::Domain) -> bool {
+        let before = into.len();
+        into.extend(other.iter().copied());
+        into.len() != before
+    }
+
+    fn transfer(&self, state: &mut Self::Domain, pc: u32, instruction: &Instruction) {
+        if let Some((index, width, access)) = local_access(instruction) {
+            if access == Access::Read {
+                return;
+            }
+            let slots = index..index.saturating_add(width);
+            state.retain(|definition| !slots.contains(&definition.slot));
+            state.extend(slots.map(|slot| Definition { slot, pc: Some(pc) }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cfg::ControlFlowGraph;
+    use crate::dataflow::reaching_definitions::{Definition, ReachingDefinitions};
+    use crate::dataflow::solve;
+    use crate::jasmin::parse;
+
+    #[test]
+    fn reaching_definitions() {
+        let class_file = parse(
+            r#".class A
+.super java/lang/Object
+.method static m(ZD)D
+    .limit stack 4
+    .limit locals 3
+    iload_0
+    ifeq Else
+    dconst_1
+    dstore_1
+Else:
+    dload_1
+    dreturn
+.end method
+"#,
+        )
+        .unwrap();
+        let code = class_file.methods[0].get_code_attr().unwrap();
+        let cfg = ControlFlowGraph::build(code).unwrap();
+        let analysis = ReachingDefinitions::new(3);
+        let results = solve(&analysis, &cfg);
+
+        let definition = |slot, pc| Definition { slot, pc };
+        let reaching = results.before(&analysis, &cfg, 6).unwrap();
+        assert_eq!(
+            reaching.into_iter().collect::<Vec<_>>(),
+            vec![
+                definition(0, None),
+                definition(1, None),
+                definition(1, Some(5)),
+                definition(2, None),
+                definition(2, Some(5)),
+            ]
+        );
+        let after_store = results.after(&analysis, &cfg, 5).unwrap();
+        assert!(!after_store.contains(&definition(1, None)));
+    }
+}
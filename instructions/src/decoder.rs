@@ -0,0 +1,270 @@
+use crate::error::Error;
+use crate::instruction::{ArrayType, Instruction, Operand};
+use crate::opcode::OpCode;
+use std::convert::TryFrom;
+
+/// Decodes a whole `Code` attribute code array into `(offset, instruction)` pairs
+pub fn decode(code: &[u8]) -> Result<Vec<(u32, Instruction)>, Error> {
+    let mut instructions: Vec<(u32, Instruction)> = vec![];
+    let mut pc: u32 = 0;
+    while (pc as usize) < code.len() {
+        let (instruction, length) = decode_at(code, pc)?;
+        instructions.push((pc, instruction));
+        pc += length;
+    }
+    Ok(instructions)
+}
+
+/// Decodes the instruction starting at `pc`, returns it with its encoded length
+pub fn decode_at(code: &[u8], pc: u32) -> Result<(Instruction, u32), Error> {
+    let mut reader = CodeReader {
+        code,
+        pc,
+        pos: pc as usize,
+    };
+    let byte = reader.u1()?;
+    let opcode = OpCode::try_from(byte).map_err(|_| Error::InvalidOpCode(pc, byte))?;
+    let operand = match byte {
+        // bipush
+        0x10 => Operand::Byte(reader.u1()? as i8),
+        // sipush
+        0x11 => Operand::Short(reader.u2()? as i16),
+        // ldc
+        0x12 => Operand::Constant(reader.u1()? as u16),
+        // ldc_w, ldc2_w
+        0x13 | 0x14 => Operand::Constant(reader.u2()?),
+        // iload to aload, istore to astore, ret
+        0x15..=0x19 | 0x36..=0x3a | 0xa9 => Operand::Local {
+            index: reader.u1()? as u16,
+            wide: false,
+        },
+        // iinc
+        0x84 => Operand::Iinc {
+            index: reader.u1()? as u16,
+            constant: reader.u1()? as i8 as i16,
+            wide: false,
+        },
+        // if<cond>, if_<t>cmp<cond>, goto, jsr, ifnull, ifnonnull
+        0x99..=0xa8 | 0xc6 | 0xc7 => {
+            let offset = reader.u2()? as i16;
+            Operand::Branch(reader.target(offset as i64)?)
+        }
+        // goto_w, jsr_w
+        0xc8 | 0xc9 => {
+            let offset = reader.u4()? as i32;
+            Operand::Branch(reader.target(offset as i64)?)
+        }
+        0xaa => {
+            reader.align()?;
+            let default = reader.u4()? as i32;
+            let default = reader.target(default as i64)?;
+            let low = reader.u4()? as i32;
+            let high = reader.u4()? as i32;
+            if low > high {
+                return Err(Error::InvalidSwitchRange(pc, low, high));
+            }
+            let mut targets: Vec<u32> = vec![];
+            for _ in low..=high {
+                let offset = reader.u4()? as i32;
+                targets.push(reader.target(offset as i64)?);
+            }
+            Operand::TableSwitch {
+                default,
+                low,
+                high,
+                targets,
+            }
+        }
+        0xab => {
+            reader.align()?;
+            let default = reader.u4()? as i32;
+            let default = reader.target(default as i64)?;
+            let npairs = reader.u4()? as i32;
+            if npairs < 0 {
+                return Err(Error::InvalidSwitchRange(pc, 0, npairs));
+            }
+            let mut pairs: Vec<(i32, u32)> = vec![];
+            for _ in 0..npairs {
+                let key = reader.u4()? as i32;
+                let offset = reader.u4()? as i32;
+                pairs.push((key, reader.target(offset as i64)?));
+            }
+            Operand::LookupSwitch { default, pairs }
+        }
+        // getstatic to invokestatic, new, anewarray, checkcast, instanceof
+        0xb2..=0xb8 | 0xbb | 0xbd | 0xc0 | 0xc1 => Operand::Constant(reader.u2()?),
+        0xb9 => {
+            let index = reader.u2()?;
+            let count = reader.u1()?;
+            // always zero
+            reader.u1()?;
+            Operand::InvokeInterface { index, count }
+        }
+        0xba => {
+            let index = reader.u2()?;
+            // always zero
+            reader.u2()?;
+            Operand::Constant(index)
+        }
+        0xbc => {
+            let atype = reader.u1()?;
+            let array_type =
+                ArrayType::from_atype(atype).ok_or(Error::InvalidArrayType(pc, atype))?;
+            Operand::NewArray(array_type)
+        }
+        0xc5 => Operand::MultiANewArray {
+            index: reader.u2()?,
+            dimensions: reader.u1()?,
+        },
+        0xc4 => {
+            let modified = reader.u1()?;
+            let instruction = match modified {
+                0x15..=0x19 | 0x36..=0x3a | 0xa9 => Instruction::new(
+                    OpCode::try_from(modified).map_err(|_| Error::InvalidOpCode(pc, modified))?,
+                    Operand::Local {
+                        index: reader.u2()?,
+                        wide: true,
+                    },
+                ),
+                0x84 => Instruction::new(
+                    OpCode::iinc,
+                    Operand::Iinc {
+                        index: reader.u2()?,
+                        constant: reader.u2()? as i16,
+                        wide: true,
+                    },
+                ),
+                _ => return Err(Error::InvalidWideOpCode(pc, modified)),
+            };
+            return Ok((instruction, reader.length()));
+        }
+        _ => Operand::None,
+    };
+    Ok((Instruction::new(opcode, operand), reader.length()))
+}
+
+struct CodeReader<'a> {
+    code: &'a [u8],
+    /// offset of the instruction being decoded
+    pc: u32,
+    pos: usize,
+}
+
+impl<'a> CodeReader<'a> {
+    fn u1(&mut self) -> Result<u8, Error> {
+        let byte = *self
+            .code
+            .get(self.pos)
+            .ok_or(Error::UnexpectedEnd(self.pc))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn u2(&mut self) -> Result<u16, Error> {
+        Ok(((self.u1()? as u16) << 8) | self.u1()? as u16)
+    }
+
+    fn u4(&mut self) -> Result<u32, Error> {
+        Ok(((self.u2()? as u32) << 16) | self.u2()? as u32)
+    }
+
+    /// Skips the 0-3 bytes of padding after `tableswitch` and `lookupswitch`,
+    /// so the operands start at a multiple of four from the start of the code
+    fn align(&mut self) -> Result<(), Error> {
+        for _ in 0..(4 - self.pos % 4) % 4 {
+            self.u1()?;
+        }
+        Ok(())
+    }
+
+    /// Branch offsets are relative to the opcode of the branching instruction
+    fn target(&self, offset: i64) -> Result<u32, Error> {
+        let target = self.pc as i64 + offset;
+        if target < 0 || target >= self.code.len() as i64 {
+            return Err(Error::InvalidBranchTarget(self.pc, target));
+        }
+        Ok(target as u32)
+    }
+
+    fn length(&self) -> u32 {
+        self.pos as u32 - self.pc
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::decoder::decode;
+    use crate::error::Error;
+    use crate::instruction::{Instruction, Operand};
+    use crate::opcode::OpCode;
+
+    #[test]
+    fn decode_operands() {
+        #[rustfmt::skip]
+        let code: Vec<u8> = vec![
+            // 0: iload_1
+            0x1b,
+            // 1: tableswitch, 2 bytes of padding, default 1+31, keys 0..1
+            0xaa, 0, 0,
+            0, 0, 0, 31,
+            0, 0, 0, 0,
+            0, 0, 0, 1,
+            0, 0, 0, 27,
+            0, 0, 0, 29,
+            // 24: wide iinc 300, -2
+            0xc4, 0x84, 0x01, 0x2c, 0xff, 0xfe,
+            // 30: goto 0
+            0xa7, 0xff, 0xe2,
+            // 33: invokeinterface #7, 2
+            0xb9, 0x00, 0x07, 0x02, 0x00,
+            // 38: multianewarray #9, 3
+            0xc5, 0x00, 0x09, 0x03,
+            // 42: return
+            0xb1,
+        ];
+        let instructions = decode(&code).unwrap();
+        let offsets: Vec<u32> = instructions.iter().map(|(pc, _)| *pc).collect();
+        assert_eq!(offsets, vec![0, 1, 24, 30, 33, 38, 42]);
+        assert_eq!(instructions[0].1.local_index(), Some(1));
+        assert_eq!(
+            instructions[1].1.operand,
+            Operand::TableSwitch {
+                default: 32,
+                low: 0,
+                high: 1,
+                targets: vec![28, 30],
+            }
+        );
+        assert_eq!(
+            instructions[2].1,
+            Instruction::new(
+                OpCode::iinc,
+                Operand::Iinc {
+                    index: 300,
+                    constant: -2,
+                    wide: true,
+                }
+            )
+        );
+        assert_eq!(instructions[3].1.operand, Operand::Branch(0));
+        assert_eq!(instructions[4].1.to_string(), "invokeinterface #7, 2");
+        assert_eq!(
+            instructions[5].1.operand,
+            Operand::MultiANewArray {
+                index: 9,
+                dimensions: 3,
+            }
+        );
+
+        assert_eq!(
+            decode(&[0xa7, 0x00, 0x10]),
+            Err(Error::InvalidBranchTarget(0, 16))
+        );
+        assert_eq!(decode(&[0x00, 0xcb]), Err(Error::InvalidOpCode(1, 0xcb)));
+        assert_eq!(decode(&[0x11, 0x01]), Err(Error::UnexpectedEnd(0)));
+        assert_eq!(
+            decode(&[0xc4, 0x60]),
+            Err(Error::InvalidWideOpCode(0, 0x60))
+        );
+    }
+}
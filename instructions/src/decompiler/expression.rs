@@ -0,0 +1,1212 @@
+use crate::cfg::{BasicBlock, ControlFlowGraph, EdgeKind};
+use crate::decompiler::{class_name, quote, Context};
+use crate::error::Error;
+use crate::instruction::{Instruction, Operand};
+use crate::opcode::OpCode;
+use classfile::attribute::CodeAttribute;
+use classfile::constant::Constant;
+use classfile::descriptor::{BaseType, FieldType, MethodDescriptor};
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+
+// Precedence of Java operators, higher binds tighter
+const PRIMARY: u8 = 15;
+const UNARY: u8 = 14;
+const MULTIPLICATIVE: u8 = 12;
+const ADDITIVE: u8 = 11;
+const SHIFT: u8 = 10;
+const RELATIONAL: u8 = 9;
+const EQUALITY: u8 = 8;
+const AND: u8 = 7;
+const XOR: u8 = 6;
+const OR: u8 = 5;
+const CONDITIONAL_AND: u8 = 4;
+const CONDITIONAL_OR: u8 = 3;
+
+/// Statements of a basic block and how it ends
+#[derive(Debug, Clone)]
+pub(crate) struct BlockCode {
+    pub statements: Vec<Statement>,
+    pub exit: Exit,
+    /// Name of the exception caught, for a block starting an exception handler
+    pub catch: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Statement {
+    /// Assignment to a local variable, declared with `ty` where it is first
+    /// assigned. Variables holding values left on the stack have no type.
+    Assign {
+        name: String,
+        ty: Option<String>,
+        value: String,
+    },
+    /// Any other statement, without the semicolon
+    Other(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Exit {
+    /// Falls through or jumps to the only successor
+    Next,
+    /// To the branch target when the condition holds, to the next block
+    /// otherwise
+    Branch(Condition),
+    /// Targets by key, keys going to the default left out
+    Switch {
+        value: String,
+        cases: Vec<(i32, u32)>,
+        default: u32,
+    },
+    Return(Option<String>),
+    Throw(String),
+}
+
+/// Condition of a branch, with its negation
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Condition {
+    text: String,
+    negated: String,
+    /// Precedence of the text and of the negation
+    precedence: (u8, u8),
+}
+
+impl Condition {
+    pub fn negate(&self) -> Condition {
+        Condition {
+            text: self.negated.clone(),
+            negated: self.text.clone(),
+            precedence: (self.precedence.1, self.precedence.0),
+        }
+    }
+
+    /// `self && other`, negated as `!self || !other`
+    pub fn and(&self, other: &Condition) -> Condition {
+        let (negated, negated_other) = (self.negate(), other.negate());
+        Condition {
+            text: format!(
+                "{} && {}",
+                self.operand(CONDITIONAL_AND),
+                other.operand(CONDITIONAL_AND)
+            ),
+            negated: format!(
+                "{} || {}",
+                negated.operand(CONDITIONAL_OR),
+                negated_other.operand(CONDITIONAL_OR)
+            ),
+            precedence: (CONDITIONAL_AND, CONDITIONAL_OR),
+        }
+    }
+
+    /// `self || other`
+    pub fn or(&self, other: &Condition) -> Condition {
+        self.negate().and(&other.negate()).negate()
+    }
+
+    fn operand(&self, precedence: u8) -> String {
+        if self.precedence.0 < precedence {
+            format!("({})", self.text)
+        } else {
+            self.text.clone()
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Turns each block reached from the method entry into statements, the
+/// blocks never reached are left out
+pub(crate) fn translate(
+    context: &Context,
+    code: &CodeAttribute,
+    cfg: &ControlFlowGraph,
+) -> Result<Vec<Option<BlockCode>>, Error> {
+    let handlers: HashSet<u32> = code
+        .exception_table
+        .iter()
+        .map(|exception| exception.handler_pc as u32)
+        .collect();
+    let mut entries: Vec<Option<Vec<Entry>>> = vec![None; cfg.blocks.len()];
+    let mut blocks: Vec<Option<BlockCode>> = vec![None; cfg.blocks.len()];
+    entries[0] = Some(vec![]);
+    let mut pending = vec![0];
+    while let Some(index) = pending.pop() {
+        let block = &cfg.blocks[index];
+        let entry = entries[index].as_ref().unwrap();
+        let mut translator = Translator {
+            context,
+            stack: entry
+                .iter()
+                .enumerate()
+                .map(|(depth, entry)| entry.expression(depth))
+                .collect(),
+            statements: vec![],
+            uninitialized_this: context.is_constructor
+                && (index == 0 || entry.contains(&Entry::This)),
+        };
+        let (code, exit) = translator.block(block, handlers.contains(&block.start))?;
+        blocks[index] = Some(code);
+        for edge in &block.successors {
+            let entry = match edge.kind {
+                EdgeKind::Exception { .. } => vec![Entry::Value { wide: false }],
+                _ => exit.clone(),
+            };
+            match &entries[edge.target] {
+                None => {
+                    entries[edge.target] = Some(entry);
+                    pending.push(edge.target);
+                }
+                Some(reached) if reached.len() == entry.len() => {}
+                Some(_) => {
+                    return Err(Error::InconsistentStackHeight(
+                        cfg.blocks[edge.target].start,
+                    ))
+                }
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+/// Value on the stack between blocks
+#[derive(Debug, Clone, PartialEq)]
+enum Entry {
+    /// Held by the `stack<n>` variable of its depth
+    Value { wide: bool },
+    /// Object created by the `new` at `pc`, not yet initialized
+    New { pc: u32, text: String },
+    /// `this` in a constructor, before it calls another constructor
+    This,
+}
+
+impl Entry {
+    fn expression(&self, depth: usize) -> Expr {
+        match self {
+            Entry::Value { wide } => Expr::stable(format!("stack{}", depth)).wide(*wide),
+            Entry::New { pc, text } => Expr::new(text.clone(), PRIMARY, Kind::Uninitialized(*pc)),
+            Entry::This => Expr::stable("this".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Literal, `this` or stack variable, whose value the statements that
+    /// follow don't change
+    Stable,
+    /// Reads variables, fields or array elements
+    Read,
+    /// Invokes a method, to be evaluated exactly once
+    Call,
+    /// Object created by the `new` at the offset, not yet initialized
+    Uninitialized(u32),
+}
+
+/// Expression on the stack
+#[derive(Debug, Clone)]
+struct Expr {
+    text: String,
+    /// Precedence of the outermost operator
+    precedence: u8,
+    kind: Kind,
+    /// long or double, taking two stack slots
+    wide: bool,
+    /// Of type boolean as far as known, a branch tests it without comparing
+    /// it to 0
+    boolean: bool,
+    /// Operands of `lcmp`, `fcmp<op>` or `dcmp<op>`, for the branch comparing
+    /// the result to 0
+    compared: Option<Box<(Expr, Expr)>>,
+}
+
+impl Expr {
+    fn new(text: String, precedence: u8, kind: Kind) -> Expr {
+        Expr {
+            text,
+            precedence,
+            kind,
+            wide: false,
+            boolean: false,
+            compared: None,
+        }
+    }
+
+    fn stable(text: String) -> Expr {
+        Expr::new(text, PRIMARY, Kind::Stable)
+    }
+
+    fn literal(text: String) -> Expr {
+        let precedence = if text.starts_with('-') {
+            UNARY
+        } else {
+            PRIMARY
+        };
+        Expr::new(text, precedence, Kind::Stable)
+    }
+
+    /// Result of an operator applied to `operands`
+    fn operation(text: String, precedence: u8, operands: &[&Expr]) -> Expr {
+        let kind = if operands
+            .iter()
+            .any(|operand| matches!(operand.kind, Kind::Call | Kind::Uninitialized(_)))
+        {
+            Kind::Call
+        } else if operands.iter().all(|operand| operand.kind == Kind::Stable) {
+            Kind::Stable
+        } else {
+            Kind::Read
+        };
+        Expr::new(text, precedence, kind)
+    }
+
+    fn wide(mut self, wide: bool) -> Expr {
+        self.wide = wide;
+        self
+    }
+
+    fn boolean(mut self, boolean: bool) -> Expr {
+        self.boolean = boolean;
+        self
+    }
+
+    fn slots(&self) -> usize {
+        if self.wide {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The text as an operand of an operator with `precedence`, parenthesized
+    /// if it binds less tightly, or as tightly on the right of a left
+    /// associative operator
+    fn operand(&self, precedence: u8, right: bool) -> String {
+        if self.precedence < precedence || right && self.precedence == precedence {
+            format!("({})", self.text)
+        } else {
+            self.text.clone()
+        }
+    }
+
+    /// The text assigned to or passed as a boolean, `0` and `1` become
+    /// `false` and `true`
+    fn as_boolean(&self, boolean: bool) -> String {
+        match self.text.as_str() {
+            "0" if boolean => "false".to_string(),
+            "1" if boolean => "true".to_string(),
+            _ => self.text.clone(),
+        }
+    }
+}
+
+struct Translator<'a> {
+    context: &'a Context<'a>,
+    stack: Vec<Expr>,
+    statements: Vec<Statement>,
+    /// Whether the constructor has yet to call another one
+    uninitialized_this: bool,
+}
+
+impl<'a> Translator<'a> {
+    /// Statements of `block` and the values it leaves on the stack. A handler
+    /// storing the exception caught right away names it after the variable.
+    fn block(
+        &mut self,
+        block: &BasicBlock,
+        handler: bool,
+    ) -> Result<(BlockCode, Vec<Entry>), Error> {
+        let mut instructions = &block.instructions[..];
+        let mut catch = None;
+        if handler {
+            self.stack.clear();
+            let name = match instructions {
+                [(_, first), (next, _), ..] if is_astore(first) => {
+                    instructions = &instructions[1..];
+                    self.context
+                        .local_name(first.local_index().unwrap(), *next)?
+                }
+                [(_, first)] if is_astore(first) => {
+                    instructions = &[];
+                    self.context
+                        .local_name(first.local_index().unwrap(), block.end)?
+                }
+                _ => {
+                    self.stack.push(Expr::stable("exception".to_string()));
+                    "exception".to_string()
+                }
+            };
+            catch = Some(name);
+        }
+
+        let mut exit = Exit::Next;
+        for (i, (pc, instruction)) in instructions.iter().enumerate() {
+            let next = instructions.get(i + 1).map_or(block.end, |(next, _)| *next);
+            if let Some(end) = self.instruction(*pc, next, instruction)? {
+                exit = end;
+                break;
+            }
+        }
+        let entries = match exit {
+            Exit::Return(_) | Exit::Throw(_) => vec![],
+            _ => self.leave(),
+        };
+        let code = BlockCode {
+            statements: std::mem::take(&mut self.statements),
+            exit,
+            catch,
+        };
+        Ok((code, entries))
+    }
+
+    /// Evaluates the instruction at `pc`, `next` being the offset of the
+    /// instruction after it. Returns how the block ends for branches,
+    /// switches, returns and `athrow`.
+    fn instruction(
+        &mut self,
+        pc: u32,
+        next: u32,
+        instruction: &Instruction,
+    ) -> Result<Option<Exit>, Error> {
+        use OpCode::*;
+        let opcode = instruction.opcode;
+        match opcode {
+            nop => {}
+            aconst_null => self.push(Expr::stable("null".to_string())),
+            iconst_m1 | iconst_0 | iconst_1 | iconst_2 | iconst_3 | iconst_4 | iconst_5 => {
+                let value = opcode as i32 - iconst_0 as i32;
+                self.push(Expr::literal(value.to_string()))
+            }
+            lconst_0 | lconst_1 => {
+                let value = opcode as i32 - lconst_0 as i32;
+                self.push(Expr::literal(format!("{}L", value)).wide(true))
+            }
+            fconst_0 | fconst_1 | fconst_2 => {
+                let value = opcode as i32 - fconst_0 as i32;
+                self.push(Expr::literal(format!("{}.0F", value)))
+            }
+            dconst_0 | dconst_1 => {
+                let value = opcode as i32 - dconst_0 as i32;
+                self.push(Expr::literal(format!("{}.0", value)).wide(true))
+            }
+            bipush | sipush => {
+                let value = match instruction.operand {
+                    Operand::Byte(value) => value as i32,
+                    Operand::Short(value) => value as i32,
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                self.push(Expr::literal(value.to_string()))
+            }
+            ldc | ldc_w | ldc2_w => {
+                let index = constant_index(instruction)?;
+                let text = self.context.pool.literal(index)?;
+                let long = matches!(
+                    self.context.pool.constant(index)?,
+                    Constant::Long(_) | Constant::Double(_)
+                );
+                let expr = if text.starts_with('/') {
+                    Expr::new(text, UNARY, Kind::Stable)
+                } else {
+                    Expr::literal(text)
+                };
+                self.push(expr.wide(long))
+            }
+
+            // Loads
+            iload | iload_0 | iload_1 | iload_2 | iload_3 | fload | fload_0 | fload_1 | fload_2
+            | fload_3 | aload | aload_0 | aload_1 | aload_2 | aload_3 => {
+                self.load(pc, instruction, false)?
+            }
+            lload | lload_0 | lload_1 | lload_2 | lload_3 | dload | dload_0 | dload_1 | dload_2
+            | dload_3 => self.load(pc, instruction, true)?,
+            iaload | faload | aaload | baload | caload | saload => self.array_load(false)?,
+            laload | daload => self.array_load(true)?,
+
+            // Stores
+            istore | istore_0 | istore_1 | istore_2 | istore_3 => {
+                self.store(next, instruction, "int")?
+            }
+            lstore | lstore_0 | lstore_1 | lstore_2 | lstore_3 => {
+                self.store(next, instruction, "long")?
+            }
+            fstore | fstore_0 | fstore_1 | fstore_2 | fstore_3 => {
+                self.store(next, instruction, "float")?
+            }
+            dstore | dstore_0 | dstore_1 | dstore_2 | dstore_3 => {
+                self.store(next, instruction, "double")?
+            }
+            astore | astore_0 | astore_1 | astore_2 | astore_3 => {
+                self.store(next, instruction, "Object")?
+            }
+            iastore | lastore | fastore | dastore | aastore | bastore | castore | sastore => {
+                let value = self.pop(pc)?;
+                let index = self.pop(pc)?;
+                let array = self.pop(pc)?;
+                self.emit(Statement::Other(format!(
+                    "{}[{}] = {}",
+                    array.operand(PRIMARY, false),
+                    index.text,
+                    value.text
+                )));
+            }
+
+            // Stack
+            pop => {
+                let value = self.pop(pc)?;
+                self.discard(value);
+            }
+            pop2 => {
+                let values = self.take_slots(pc, 2)?;
+                for value in values {
+                    self.discard(value);
+                }
+            }
+            dup => self.dup(pc, 1, 0)?,
+            dup_x1 => self.dup(pc, 1, 1)?,
+            dup_x2 => self.dup(pc, 1, 2)?,
+            dup2 => self.dup(pc, 2, 0)?,
+            dup2_x1 => self.dup(pc, 2, 1)?,
+            dup2_x2 => self.dup(pc, 2, 2)?,
+            swap => {
+                let first = self.pop(pc)?;
+                let second = self.pop(pc)?;
+                self.push(first);
+                self.push(second);
+            }
+
+            // Math
+            iadd | fadd => self.binary(pc, "+", ADDITIVE, false)?,
+            ladd | dadd => self.binary(pc, "+", ADDITIVE, true)?,
+            isub | fsub => self.binary(pc, "-", ADDITIVE, false)?,
+            lsub | dsub => self.binary(pc, "-", ADDITIVE, true)?,
+            imul | fmul => self.binary(pc, "*", MULTIPLICATIVE, false)?,
+            lmul | dmul => self.binary(pc, "*", MULTIPLICATIVE, true)?,
+            idiv | fdiv => self.binary(pc, "/", MULTIPLICATIVE, false)?,
+            ldiv | ddiv => self.binary(pc, "/", MULTIPLICATIVE, true)?,
+            irem | frem => self.binary(pc, "%", MULTIPLICATIVE, false)?,
+            lrem | drem => self.binary(pc, "%", MULTIPLICATIVE, true)?,
+            ishl => self.binary(pc, "<<", SHIFT, false)?,
+            lshl => self.binary(pc, "<<", SHIFT, true)?,
+            ishr => self.binary(pc, ">>", SHIFT, false)?,
+            lshr => self.binary(pc, ">>", SHIFT, true)?,
+            iushr => self.binary(pc, ">>>", SHIFT, false)?,
+            lushr => self.binary(pc, ">>>", SHIFT, true)?,
+            iand => self.binary(pc, "&", AND, false)?,
+            land => self.binary(pc, "&", AND, true)?,
+            ior => self.binary(pc, "|", OR, false)?,
+            lor => self.binary(pc, "|", OR, true)?,
+            ixor => self.binary(pc, "^", XOR, false)?,
+            lxor => self.binary(pc, "^", XOR, true)?,
+            ineg | lneg | fneg | dneg => {
+                let operand = self.pop(pc)?;
+                let text = if operand.text.starts_with('-') {
+                    format!("-({})", operand.text)
+                } else {
+                    format!("-{}", operand.operand(UNARY, false))
+                };
+                let long = operand.wide;
+                self.push(Expr::operation(text, UNARY, &[&operand]).wide(long))
+            }
+            iinc => {
+                let (index, constant) = match instruction.operand {
+                    Operand::Iinc {
+                        index, constant, ..
+                    } => (index, constant),
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                let name = self.context.local_name(index, pc)?;
+                let text = match constant {
+                    1 => format!("{}++", name),
+                    -1 => format!("{}--", name),
+                    constant if constant < 0 => format!("{} -= {}", name, -(constant as i32)),
+                    constant => format!("{} += {}", name, constant),
+                };
+                self.emit(Statement::Other(text));
+            }
+
+            // Conversions
+            i2l | f2l | d2l => self.cast(pc, "long", true)?,
+            i2f | l2f | d2f => self.cast(pc, "float", false)?,
+            i2d | l2d | f2d => self.cast(pc, "double", true)?,
+            l2i | f2i | d2i => self.cast(pc, "int", false)?,
+            i2b => self.cast(pc, "byte", false)?,
+            i2c => self.cast(pc, "char", false)?,
+            i2s => self.cast(pc, "short", false)?,
+
+            // Comparisons
+            lcmp | fcmpl | fcmpg | dcmpl | dcmpg => {
+                let right = self.pop(pc)?;
+                let left = self.pop(pc)?;
+                let class = match opcode {
+                    lcmp => "Long",
+                    fcmpl | fcmpg => "Float",
+                    _ => "Double",
+                };
+                let text = format!("{}.compare({}, {})", class, left.text, right.text);
+                let mut expr = Expr::operation(text, PRIMARY, &[&left, &right]);
+                expr.compared = Some(Box::new((left, right)));
+                self.push(expr)
+            }
+            ifeq | ifne | iflt | ifge | ifgt | ifle => {
+                let value = self.pop(pc)?;
+                let operator = operator(opcode as u8 - ifeq as u8);
+                let condition = match value.compared {
+                    Some(compared) => comparison(&compared.0, operator, &compared.1),
+                    None if value.boolean && matches!(opcode, ifeq | ifne) => {
+                        let negated = format!("!{}", value.operand(UNARY, false));
+                        let condition = Condition {
+                            text: value.text,
+                            negated,
+                            precedence: (value.precedence, UNARY),
+                        };
+                        if opcode == ifne {
+                            condition
+                        } else {
+                            condition.negate()
+                        }
+                    }
+                    None => comparison(&value, operator, &Expr::literal("0".to_string())),
+                };
+                return Ok(Some(Exit::Branch(condition)));
+            }
+            if_icmpeq | if_icmpne | if_icmplt | if_icmpge | if_icmpgt | if_icmple | if_acmpeq
+            | if_acmpne => {
+                let right = self.pop(pc)?;
+                let left = self.pop(pc)?;
+                let operator = match opcode {
+                    if_acmpeq => "==",
+                    if_acmpne => "!=",
+                    _ => operator(opcode as u8 - if_icmpeq as u8),
+                };
+                return Ok(Some(Exit::Branch(comparison(&left, operator, &right))));
+            }
+            ifnull | ifnonnull => {
+                let value = self.pop(pc)?;
+                let operator = if opcode == ifnull { "==" } else { "!=" };
+                let null = Expr::stable("null".to_string());
+                return Ok(Some(Exit::Branch(comparison(&value, operator, &null))));
+            }
+
+            // Control
+            goto | goto_w => return Ok(Some(Exit::Next)),
+            tableswitch | lookupswitch => {
+                let value = self.pop(pc)?;
+                let (default, cases) = match &instruction.operand {
+                    Operand::TableSwitch {
+                        default,
+                        low,
+                        targets,
+                        ..
+                    } => (
+                        *default,
+                        targets
+                            .iter()
+                            .enumerate()
+                            .map(|(i, target)| (low + i as i32, *target))
+                            .collect::<Vec<_>>(),
+                    ),
+                    Operand::LookupSwitch { default, pairs } => (*default, pairs.clone()),
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                return Ok(Some(Exit::Switch {
+                    value: value.text,
+                    cases: cases
+                        .into_iter()
+                        .filter(|(_, target)| *target != default)
+                        .collect(),
+                    default,
+                }));
+            }
+            ireturn | lreturn | freturn | dreturn | areturn => {
+                let value = self.pop(pc)?;
+                let text = value.as_boolean(self.context.returns_boolean);
+                return Ok(Some(Exit::Return(Some(text))));
+            }
+            vreturn => return Ok(Some(Exit::Return(None))),
+
+            // References
+            getstatic | getfield => {
+                let (class, name, descriptor) = self.member(instruction)?;
+                let field_type = field_type(&descriptor, instruction)?;
+                let text = if opcode == getstatic {
+                    format!("{}.{}", class_name(&class), name)
+                } else {
+                    let object = self.pop(pc)?;
+                    format!("{}.{}", object.operand(PRIMARY, false), name)
+                };
+                let boolean = field_type == FieldType::Base(BaseType::Boolean);
+                let expr = Expr::new(text, PRIMARY, Kind::Read)
+                    .wide(field_type.slots() == 2)
+                    .boolean(boolean);
+                self.push(expr)
+            }
+            putstatic | putfield => {
+                let (class, name, descriptor) = self.member(instruction)?;
+                let boolean =
+                    field_type(&descriptor, instruction)? == FieldType::Base(BaseType::Boolean);
+                let value = self.pop(pc)?.as_boolean(boolean);
+                let text = if opcode == putstatic {
+                    format!("{}.{} = {}", class_name(&class), name, value)
+                } else {
+                    let object = self.pop(pc)?;
+                    format!("{}.{} = {}", object.operand(PRIMARY, false), name, value)
+                };
+                self.emit(Statement::Other(text));
+            }
+            invokevirtual | invokespecial | invokestatic | invokeinterface | invokedynamic => {
+                self.invoke(pc, instruction)?
+            }
+            new => {
+                let class = self.context.pool.class_name(constant_index(instruction)?)?;
+                let text = format!("new {}", class_name(&class));
+                self.push(Expr::new(text, PRIMARY, Kind::Uninitialized(pc)))
+            }
+            newarray => {
+                let count = self.pop(pc)?;
+                let array_type = match instruction.operand {
+                    Operand::NewArray(array_type) => format!("{}[]", array_type.name()),
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                self.push(new_array(&array_type, &[count]))
+            }
+            anewarray => {
+                let count = self.pop(pc)?;
+                let class = self.context.pool.class_name(constant_index(instruction)?)?;
+                let array_type = format!("{}[]", class_name(&class));
+                self.push(new_array(&array_type, &[count]))
+            }
+            multianewarray => {
+                let (index, dimensions) = match instruction.operand {
+                    Operand::MultiANewArray { index, dimensions } => (index, dimensions),
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                let counts = self.pop_values(pc, dimensions as usize)?;
+                let class = self.context.pool.class_name(index)?;
+                self.push(new_array(&class_name(&class), &counts))
+            }
+            arraylength => {
+                let array = self.pop(pc)?;
+                let text = format!("{}.length", array.operand(PRIMARY, false));
+                self.push(Expr::operation(text, PRIMARY, &[&array]))
+            }
+            athrow => {
+                let exception = self.pop(pc)?;
+                return Ok(Some(Exit::Throw(exception.text)));
+            }
+            checkcast => {
+                let object = self.pop(pc)?;
+                let class = self.context.pool.class_name(constant_index(instruction)?)?;
+                let text = format!("({}) {}", class_name(&class), object.operand(UNARY, false));
+                self.push(Expr::operation(text, UNARY, &[&object]))
+            }
+            instanceof => {
+                let object = self.pop(pc)?;
+                let class = self.context.pool.class_name(constant_index(instruction)?)?;
+                let text = format!(
+                    "{} instanceof {}",
+                    object.operand(RELATIONAL, false),
+                    class_name(&class)
+                );
+                self.push(Expr::operation(text, RELATIONAL, &[&object]).boolean(true))
+            }
+            monitorenter | monitorexit => {
+                let object = self.pop(pc)?;
+                self.emit(Statement::Other(format!(
+                    "{}({})",
+                    opcode.name(),
+                    object.text
+                )));
+            }
+
+            // Subroutines are inlined before and `wide` is part of the operand
+            jsr | jsr_w | ret | wide | breakpoint | impdep1 | impdep2 => {
+                return Err(Error::InvalidOpCode(pc, opcode as u8))
+            }
+        }
+        Ok(None)
+    }
+
+    fn load(&mut self, pc: u32, instruction: &Instruction, wide: bool) -> Result<(), Error> {
+        let index = instruction.local_index().unwrap();
+        let name = self.context.local_name(index, pc)?;
+        let boolean = self.context.local_type(index, pc)?.as_deref() == Some("boolean");
+        let kind = if name == "this" {
+            Kind::Stable
+        } else {
+            Kind::Read
+        };
+        self.push(Expr::new(name, PRIMARY, kind).wide(wide).boolean(boolean));
+        Ok(())
+    }
+
+    /// Assigns the value on top of the stack to a local variable, typed `ty`
+    /// unless the `LocalVariableTable` has its type
+    fn store(&mut self, next: u32, instruction: &Instruction, ty: &str) -> Result<(), Error> {
+        let value = self.pop(next)?;
+        let index = instruction.local_index().unwrap();
+        let name = self.context.local_name(index, next)?;
+        let ty = self
+            .context
+            .local_type(index, next)?
+            .unwrap_or_else(|| ty.to_string());
+        let value = value.as_boolean(ty == "boolean");
+        self.emit(Statement::Assign {
+            name,
+            ty: Some(ty),
+            value,
+        });
+        Ok(())
+    }
+
+    fn array_load(&mut self, wide: bool) -> Result<(), Error> {
+        let index = self.pop(0)?;
+        let array = self.pop(0)?;
+        let text = format!("{}[{}]", array.operand(PRIMARY, false), index.text);
+        let mut expr = Expr::operation(text, PRIMARY, &[&array, &index]).wide(wide);
+        if expr.kind == Kind::Stable {
+            expr.kind = Kind::Read;
+        }
+        self.push(expr);
+        Ok(())
+    }
+
+    fn binary(&mut self, pc: u32, operator: &str, precedence: u8, wide: bool) -> Result<(), Error> {
+        let right = self.pop(pc)?;
+        let left = self.pop(pc)?;
+        let text = format!(
+            "{} {} {}",
+            left.operand(precedence, false),
+            operator,
+            right.operand(precedence, true)
+        );
+        self.push(Expr::operation(text, precedence, &[&left, &right]).wide(wide));
+        Ok(())
+    }
+
+    fn cast(&mut self, pc: u32, ty: &str, wide: bool) -> Result<(), Error> {
+        let operand = self.pop(pc)?;
+        let text = format!("({}) {}", ty, operand.operand(UNARY, false));
+        self.push(Expr::operation(text, UNARY, &[&operand]).wide(wide));
+        Ok(())
+    }
+
+    /// Copies the values taking the top `slots` stack slots below the values
+    /// taking the `under` slots beneath them. Invocations are assigned to a
+    /// variable first, so they are still made once.
+    fn dup(&mut self, pc: u32, slots: usize, under: usize) -> Result<(), Error> {
+        let mut copied = self.take_slots(pc, slots)?;
+        if copied.iter().any(|expr| expr.kind == Kind::Call) {
+            self.stack.extend(copied);
+            self.spill();
+            copied = self.take_slots(pc, slots)?;
+        }
+        let skipped = self.take_slots(pc, under)?;
+        self.stack.extend(copied.iter().cloned());
+        self.stack.extend(skipped);
+        self.stack.extend(copied);
+        Ok(())
+    }
+
+    fn invoke(&mut self, pc: u32, instruction: &Instruction) -> Result<(), Error> {
+        let opcode = instruction.opcode;
+        let index = constant_index(instruction)?;
+        let pool = &self.context.pool;
+        let (class, name, descriptor, bootstrap) = match pool.constant(index)? {
+            Constant::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => {
+                let (name, descriptor) = pool.name_and_type(*name_and_type_index)?;
+                (None, name, descriptor, Some(*bootstrap_method_attr_index))
+            }
+            _ => {
+                let (class, name, descriptor) = pool.member(index)?;
+                (Some(class), name, descriptor, None)
+            }
+        };
+        let descriptor = MethodDescriptor::parse(descriptor.as_bytes())
+            .map_err(|_| Error::InvalidConstant(index))?;
+        let arguments = self.pop_values(pc, descriptor.parameters.len())?;
+        let list = arguments
+            .iter()
+            .zip(&descriptor.parameters)
+            .map(|(argument, parameter)| {
+                argument.as_boolean(*parameter == FieldType::Base(BaseType::Boolean))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let receiver = match opcode {
+            OpCode::invokestatic | OpCode::invokedynamic => None,
+            _ => Some(self.pop(pc)?),
+        };
+
+        let (text, precedence) = match (class, receiver, bootstrap) {
+            (Some(class), Some(receiver), _) if name == "<init>" => {
+                if let Kind::Uninitialized(new) = receiver.kind {
+                    let text = format!("new {}({})", class_name(&class), list);
+                    let mut initialized = false;
+                    for expr in &mut self.stack {
+                        if expr.kind == Kind::Uninitialized(new) {
+                            *expr = Expr::new(text.clone(), PRIMARY, Kind::Call);
+                            initialized = true;
+                        }
+                    }
+                    if !initialized {
+                        self.emit(Statement::Other(text));
+                    }
+                } else if receiver.text == "this" {
+                    self.uninitialized_this = false;
+                    // the implicit call of the superclass constructor
+                    if class == self.context.this_class {
+                        self.emit(Statement::Other(format!("this({})", list)));
+                    } else if !list.is_empty() {
+                        self.emit(Statement::Other(format!("super({})", list)));
+                    }
+                } else {
+                    let text = format!("{}.<init>({})", receiver.operand(PRIMARY, false), list);
+                    self.emit(Statement::Other(text));
+                }
+                return Ok(());
+            }
+            (Some(class), Some(receiver), _) => {
+                let target = if opcode == OpCode::invokespecial
+                    && receiver.text == "this"
+                    && class != self.context.this_class
+                {
+                    "super".to_string()
+                } else {
+                    receiver.operand(PRIMARY, false)
+                };
+                (format!("{}.{}({})", target, name, list), PRIMARY)
+            }
+            (Some(class), None, _) => (
+                format!("{}.{}({})", class_name(&class), name, list),
+                PRIMARY,
+            ),
+            (None, _, bootstrap) => match self.concatenation(bootstrap, &arguments)? {
+                Some(text) => (text, ADDITIVE),
+                None => (format!("/* invokedynamic */ {}({})", name, list), UNARY),
+            },
+        };
+        match descriptor.return_type {
+            Some(return_type) => {
+                let boolean = return_type == FieldType::Base(BaseType::Boolean);
+                let expr = Expr::new(text, precedence, Kind::Call)
+                    .wide(return_type.slots() == 2)
+                    .boolean(boolean);
+                self.push(expr);
+            }
+            None => self.emit(Statement::Other(text)),
+        }
+        Ok(())
+    }
+
+    /// String concatenation made by `StringConcatFactory`, following the
+    /// recipe of `makeConcatWithConstants` where `\1` stands for an argument
+    /// and `\2` for a constant
+    fn concatenation(
+        &self,
+        bootstrap: Option<u16>,
+        arguments: &[Expr],
+    ) -> Result<Option<String>, Error> {
+        let pool = &self.context.pool;
+        let method = match bootstrap.and_then(|index| self.context.bootstrap_method(index)) {
+            Some(method) => method,
+            None => return Ok(None),
+        };
+        let handle = match pool.constant(method.bootstrap_method_ref)? {
+            Constant::MethodHandle {
+                reference_index, ..
+            } => pool.member(*reference_index)?,
+            _ => return Ok(None),
+        };
+        let (recipe, constants) = match (handle.0.as_str(), handle.1.as_str()) {
+            ("java/lang/invoke/StringConcatFactory", "makeConcatWithConstants") => {
+                match method.bootstrap_arguments.split_first() {
+                    Some((recipe, constants)) => match pool.constant(*recipe)? {
+                        Constant::String { string_index } => (pool.utf8(*string_index)?, constants),
+                        _ => return Ok(None),
+                    },
+                    None => return Ok(None),
+                }
+            }
+            ("java/lang/invoke/StringConcatFactory", "makeConcat") => {
+                ("\u{1}".repeat(arguments.len()), &[][..])
+            }
+            _ => return Ok(None),
+        };
+
+        // (text, whether it is a string literal)
+        let mut parts: Vec<(String, bool)> = vec![];
+        let mut literal = String::new();
+        let (mut arguments, mut constants) = (arguments.iter(), constants.iter());
+        for c in recipe.chars() {
+            if c != '\u{1}' && c != '\u{2}' {
+                literal.push(c);
+                continue;
+            }
+            if !literal.is_empty() {
+                parts.push((quote(&literal), true));
+                literal.clear();
+            }
+            let part = match c {
+                '\u{1}' => arguments
+                    .next()
+                    .map(|argument| argument.operand(ADDITIVE, true)),
+                _ => match constants.next() {
+                    Some(index) => Some(pool.literal(*index)?),
+                    None => None,
+                },
+            };
+            match part {
+                Some(part) => parts.push((part, false)),
+                None => return Ok(None),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push((quote(&literal), true));
+        }
+        // the first operand of + being a string makes it a concatenation
+        if !parts.first().is_some_and(|(_, literal)| *literal)
+            && !parts.get(1).is_some_and(|(_, literal)| *literal)
+        {
+            parts.insert(0, ("\"\"".to_string(), true));
+        }
+        let parts: Vec<String> = parts.into_iter().map(|(text, _)| text).collect();
+        Ok(Some(parts.join(" + ")))
+    }
+
+    fn member(&self, instruction: &Instruction) -> Result<(String, String, String), Error> {
+        self.context.pool.member(constant_index(instruction)?)
+    }
+
+    fn push(&mut self, expr: Expr) {
+        self.stack.push(expr);
+    }
+
+    fn pop(&mut self, pc: u32) -> Result<Expr, Error> {
+        self.stack.pop().ok_or(Error::StackUnderflow(pc))
+    }
+
+    /// Pops `count` values, in the order they were pushed
+    fn pop_values(&mut self, pc: u32, count: usize) -> Result<Vec<Expr>, Error> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(count)
+            .ok_or(Error::StackUnderflow(pc))?;
+        Ok(self.stack.split_off(start))
+    }
+
+    /// Pops the values taking the top `slots` stack slots, in the order they
+    /// were pushed
+    fn take_slots(&mut self, pc: u32, slots: usize) -> Result<Vec<Expr>, Error> {
+        let mut taken = 0;
+        let mut count = 0;
+        while taken < slots {
+            let expr = self
+                .stack
+                .iter()
+                .rev()
+                .nth(count)
+                .ok_or(Error::StackUnderflow(pc))?;
+            taken += expr.slots();
+            count += 1;
+        }
+        if taken != slots {
+            return Err(Error::StackTypeMismatch(pc));
+        }
+        self.pop_values(pc, count)
+    }
+
+    /// Drops a value popped, keeping the invocation it comes from
+    fn discard(&mut self, value: Expr) {
+        if value.kind == Kind::Call {
+            self.emit(Statement::Other(value.text));
+        }
+    }
+
+    /// Adds a statement after assigning the values on the stack it might
+    /// change, or that might change it, to variables
+    fn emit(&mut self, statement: Statement) {
+        self.spill();
+        self.statements.push(statement);
+    }
+
+    /// Assigns the values on the stack that read or invoke something to
+    /// fresh variables, in the order they were pushed
+    fn spill(&mut self) {
+        for depth in 0..self.stack.len() {
+            let expr = &self.stack[depth];
+            if matches!(expr.kind, Kind::Read | Kind::Call) {
+                let name = self.context.temporary();
+                self.statements.push(Statement::Assign {
+                    name: name.clone(),
+                    ty: None,
+                    value: expr.text.clone(),
+                });
+                let (wide, boolean) = (expr.wide, expr.boolean);
+                self.stack[depth] = Expr::stable(name).wide(wide).boolean(boolean);
+            }
+        }
+    }
+
+    /// Assigns the values left on the stack to the `stack<n>` variables of
+    /// their depth, read by the blocks that follow. Values not yet
+    /// initialized, `this` included, are passed on as they are. The assignments happen all at
+    /// once: a variable still read by another one is assigned after it, a
+    /// cycle is broken through a fresh variable.
+    fn leave(&mut self) -> Vec<Entry> {
+        let mut entries = vec![];
+        let mut pending: Vec<(String, String)> = vec![];
+        for (depth, expr) in self.stack.iter().enumerate() {
+            match expr.kind {
+                Kind::Uninitialized(pc) => entries.push(Entry::New {
+                    pc,
+                    text: expr.text.clone(),
+                }),
+                _ if self.uninitialized_this && expr.text == "this" => entries.push(Entry::This),
+                _ => {
+                    entries.push(Entry::Value { wide: expr.wide });
+                    let name = format!("stack{}", depth);
+                    if expr.text != name {
+                        pending.push((name, expr.text.clone()));
+                    }
+                }
+            }
+        }
+        while !pending.is_empty() {
+            let free = (0..pending.len()).find(|&i| {
+                pending
+                    .iter()
+                    .enumerate()
+                    .all(|(j, (_, value))| i == j || !mentions(value, &pending[i].0))
+            });
+            match free {
+                Some(i) => {
+                    let (name, value) = pending.remove(i);
+                    self.statements.push(Statement::Assign {
+                        name,
+                        ty: None,
+                        value,
+                    });
+                }
+                None => {
+                    let saved = self.context.temporary();
+                    let name = pending[0].0.clone();
+                    self.statements.push(Statement::Assign {
+                        name: saved.clone(),
+                        ty: None,
+                        value: name.clone(),
+                    });
+                    for (_, value) in &mut pending[1..] {
+                        *value = replace_name(value, &name, &saved);
+                    }
+                }
+            }
+        }
+        entries
+    }
+}
+
+/// Whether `text` mentions the variable `name`
+fn mentions(text: &str, name: &str) -> bool {
+    text.match_indices(name)
+        .any(|(i, _)| is_whole_word(text, i, name.len()))
+}
+
+fn replace_name(text: &str, name: &str, replacement: &str) -> String {
+    let mut replaced = String::new();
+    let mut last = 0;
+    for (i, _) in text.match_indices(name) {
+        if is_whole_word(text, i, name.len()) {
+            replaced.push_str(&text[last..i]);
+            replaced.push_str(replacement);
+            last = i + name.len();
+        }
+    }
+    replaced.push_str(&text[last..]);
+    replaced
+}
+
+fn is_whole_word(text: &str, start: usize, len: usize) -> bool {
+    let identifier = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    !text[..start].chars().next_back().is_some_and(identifier)
+        && !text[start + len..].chars().next().is_some_and(identifier)
+}
+
+fn is_astore(instruction: &Instruction) -> bool {
+    use OpCode::*;
+    matches!(
+        instruction.opcode,
+        astore | astore_0 | astore_1 | astore_2 | astore_3
+    )
+}
+
+/// Comparison operator of `if<cond>` and `if_icmp<cond>`, by offset from the
+/// `eq` form
+fn operator(condition: u8) -> &'static str {
+    ["==", "!=", "<", ">=", ">", "<="][condition as usize]
+}
+
+fn comparison(left: &Expr, operator: &str, right: &Expr) -> Condition {
+    let negated = match operator {
+        "==" => "!=",
+        "!=" => "==",
+        "<" => ">=",
+        ">=" => "<",
+        ">" => "<=",
+        _ => ">",
+    };
+    let precedence = if operator == "==" || operator == "!=" {
+        EQUALITY
+    } else {
+        RELATIONAL
+    };
+    let text = |operator: &str| {
+        format!(
+            "{} {} {}",
+            left.operand(precedence, false),
+            operator,
+            right.operand(precedence, true)
+        )
+    };
+    Condition {
+        text: text(operator),
+        negated: text(negated),
+        precedence: (precedence, precedence),
+    }
+}
+
+/// Array creation, `array_type` being the type of the array created and
+/// `counts` the lengths of its first dimensions
+fn new_array(array_type: &str, counts: &[Expr]) -> Expr {
+    let split = array_type.find("[]").unwrap_or(array_type.len());
+    let (component, brackets) = array_type.split_at(split);
+    let mut text = format!("new {}", component);
+    for count in counts {
+        text.push_str(&format!("[{}]", count.text));
+    }
+    for _ in counts.len()..brackets.len() / 2 {
+        text.push_str("[]");
+    }
+    let operands: Vec<&Expr> = counts.iter().collect();
+    let mut expr = Expr::operation(text, PRIMARY, &operands);
+    expr.kind = Kind::Call;
+    expr
+}
+
+fn constant_index(instruction: &Instruction) -> Result<u16, Error> {
+    match instruction.operand {
+        Operand::Constant(index) | Operand::InvokeInterface { index, .. } => Ok(index),
+        _ => Err(Error::InvalidOperand(instruction.opcode)),
+    }
+}
+
+fn field_type(descriptor: &str, instruction: &Instruction) -> Result<FieldType, Error> {
+    FieldType::parse(descriptor.as_bytes())
+        .map_err(|_| Error::InvalidConstant(constant_index(instruction).unwrap_or(0)))
+}
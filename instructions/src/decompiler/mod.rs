@@ -0,0 +1,676 @@
+//! Java-like pseudo-source of class files, to read method bodies of classes
+//! whose sources aren't at hand.
+//!
+//! The instructions of each basic block are evaluated on a stack of
+//! expressions, turning them into statements. Values left on the stack at the
+//! end of a block are assigned to `stack<n>` variables, `n` being their depth,
+//! and read back by the blocks that follow. The blocks are then nested into
+//! `if`, `while`, `switch` and `try` statements following the dominator tree;
+//! control flow that doesn't fit becomes a `goto` to a labelled block. Local
+//! variables are named after the `LocalVariableTable` when the code has one,
+//! `local<n>` for slot `n` otherwise.
+
+use crate::cfg::ControlFlowGraph;
+use crate::constant_pool::Pool;
+use crate::error::Error;
+use crate::subroutine::inline_subroutines;
+use classfile::access_flags::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+use classfile::attribute::{AttributeType, BootstrapMethod, LocalVariable};
+use classfile::class_file::ClassFile;
+use classfile::constant::Constant;
+use classfile::descriptor::{BaseType, FieldType, MethodDescriptor};
+use classfile::method::MethodInfo;
+use std::cell::Cell;
+use std::fmt::Write;
+
+mod expression;
+mod structure;
+
+/// Pseudo-source of the class, its fields and methods. A method whose code
+/// can't be decompiled is left with a comment saying why.
+pub fn decompile(class_file: &ClassFile) -> Result<String, Error> {
+    let pool = Pool(&class_file.constant_pool);
+    let flags = ClassAccessFlags::from_bits_truncate(class_file.access_flags);
+    let mut source = String::new();
+    write!(
+        source,
+        "{}{} {}",
+        modifiers(&flags.to_string()),
+        flags.kind(),
+        class_name(&pool.class_name(class_file.this_class)?)
+    )
+    .unwrap();
+    let mut interfaces = vec![];
+    for index in &class_file.interfaces {
+        interfaces.push(class_name(&pool.class_name(*index)?));
+    }
+    if flags.is_interface() {
+        if !interfaces.is_empty() {
+            write!(source, " extends {}", interfaces.join(", ")).unwrap();
+        }
+    } else {
+        if class_file.super_class != 0 && !flags.is_enum() {
+            let super_class = pool.class_name(class_file.super_class)?;
+            if super_class != "java/lang/Object" {
+                write!(source, " extends {}", class_name(&super_class)).unwrap();
+            }
+        }
+        if !interfaces.is_empty() {
+            write!(source, " implements {}", interfaces.join(", ")).unwrap();
+        }
+    }
+    source.push_str(" {\n");
+
+    for field in &class_file.fields {
+        let flags = FieldAccessFlags::from_bits_truncate(field.access_flags);
+        let field_type = FieldType::parse(pool.utf8(field.descriptor_index)?.as_bytes())
+            .map_err(|_| Error::InvalidConstant(field.descriptor_index))?;
+        write!(
+            source,
+            "    {}{} {}",
+            modifiers(&flags.to_string()),
+            type_name(&field_type),
+            pool.utf8(field.name_index)?
+        )
+        .unwrap();
+        for attribute in &field.attributes {
+            if let AttributeType::ConstantValue {
+                constant_value_index,
+            } = attribute.attr_type
+            {
+                write!(source, " = {}", pool.literal(constant_value_index)?).unwrap();
+            }
+        }
+        source.push_str(";\n");
+    }
+
+    for method in &class_file.methods {
+        if !source.ends_with("{\n") {
+            source.push('\n');
+        }
+        let text = match decompile_method(class_file, method) {
+            Ok(text) => text,
+            Err(error) => format!(
+                "// {}{}: {:?}\n",
+                pool.utf8(method.name_index)?,
+                pool.utf8(method.descriptor_index)?,
+                error
+            ),
+        };
+        for line in text.lines() {
+            if line.is_empty() {
+                source.push('\n');
+            } else {
+                writeln!(source, "    {}", line).unwrap();
+            }
+        }
+    }
+    source.push_str("}\n");
+    Ok(source)
+}
+
+/// Pseudo-source of a method of `class_file`, its declaration followed by the
+/// body
+pub fn decompile_method(class_file: &ClassFile, method: &MethodInfo) -> Result<String, Error> {
+    let pool = Pool(&class_file.constant_pool);
+    let this_class = pool.class_name(class_file.this_class)?;
+    let name = pool.utf8(method.name_index)?;
+    let descriptor = MethodDescriptor::parse(pool.utf8(method.descriptor_index)?.as_bytes())
+        .map_err(|_| Error::InvalidConstant(method.descriptor_index))?;
+    let flags = MethodAccessFlags::from_bits_truncate(method.access_flags);
+    let code = match method.get_code_attr() {
+        Some(code) => Some(inline_subroutines(code)?),
+        None => None,
+    };
+    let context = Context {
+        class_file,
+        pool,
+        this_class,
+        is_static: flags.is_static(),
+        is_constructor: name == "<init>",
+        returns_boolean: descriptor.return_type == Some(FieldType::Base(BaseType::Boolean)),
+        variables: code
+            .iter()
+            .flat_map(|code| &code.attributes)
+            .filter_map(|attribute| match &attribute.attr_type {
+                AttributeType::LocalVariableTable {
+                    local_variable_table,
+                } => Some(local_variable_table.iter()),
+                _ => None,
+            })
+            .flatten()
+            .cloned()
+            .collect(),
+        temporaries: Cell::new(0),
+    };
+
+    let mut source = modifiers(&flags.to_string());
+    let mut parameters = vec![];
+    let mut slot = if flags.is_static() { 0 } else { 1 };
+    for parameter in &descriptor.parameters {
+        let declared = (context.local_name(slot, 0)?, type_name(parameter));
+        parameters.push(declared);
+        slot += parameter.slots() as u16;
+    }
+    let declarations: Vec<String> = parameters
+        .iter()
+        .map(|(name, ty)| format!("{} {}", ty, name))
+        .collect();
+    match name.as_str() {
+        "<clinit>" => source.push_str("static"),
+        "<init>" => {
+            let simple_name = context.this_class.rsplit('/').next().unwrap();
+            write!(source, "{}({})", simple_name, declarations.join(", ")).unwrap();
+        }
+        _ => {
+            let return_type = descriptor
+                .return_type
+                .as_ref()
+                .map_or("void".to_string(), type_name);
+            write!(
+                source,
+                "{} {}({})",
+                return_type,
+                name,
+                declarations.join(", ")
+            )
+            .unwrap();
+        }
+    }
+    for attribute in &method.attributes {
+        if let AttributeType::Exceptions {
+            exception_index_table,
+        } = &attribute.attr_type
+        {
+            let mut exceptions = vec![];
+            for index in exception_index_table {
+                exceptions.push(class_name(&context.pool.class_name(*index)?));
+            }
+            write!(source, " throws {}", exceptions.join(", ")).unwrap();
+        }
+    }
+    let code = match code {
+        Some(code) => code,
+        None => {
+            source.push_str(";\n");
+            return Ok(source);
+        }
+    };
+
+    source.push_str(" {\n");
+    let cfg = ControlFlowGraph::build(&code)?;
+    let blocks = expression::translate(&context, &code, &cfg)?;
+    if !context.is_static {
+        parameters.push(("this".to_string(), class_name(&context.this_class)));
+    }
+    let mut lines = structure::structure(&context, &code, &cfg, &blocks, parameters)?;
+    // the return ending a void method goes without saying
+    if lines.last().map(String::as_str) == Some("    return;")
+        && !lines
+            .iter()
+            .rev()
+            .nth(1)
+            .is_some_and(|line| line.ends_with(':'))
+    {
+        lines.pop();
+    }
+    for line in lines {
+        writeln!(source, "{}", line).unwrap();
+    }
+    source.push_str("}\n");
+    Ok(source)
+}
+
+/// What the translation of a method body needs to know about the method and
+/// its class
+pub(crate) struct Context<'a> {
+    class_file: &'a ClassFile,
+    pool: Pool<'a>,
+    /// Internal name of the class declaring the method
+    this_class: String,
+    is_static: bool,
+    is_constructor: bool,
+    returns_boolean: bool,
+    /// Entries of the `LocalVariableTable`
+    variables: Vec<LocalVariable>,
+    /// Variables holding intermediate values so far
+    temporaries: Cell<u32>,
+}
+
+impl<'a> Context<'a> {
+    /// Fresh variable for an intermediate value
+    fn temporary(&self) -> String {
+        let count = self.temporaries.get() + 1;
+        self.temporaries.set(count);
+        format!("temp{}", count)
+    }
+
+    /// Entry of the `LocalVariableTable` for `slot` at `pc`
+    fn variable(&self, slot: u16, pc: u32) -> Option<&LocalVariable> {
+        self.variables.iter().find(|variable| {
+            variable.index == slot
+                && variable.start_pc as u32 <= pc
+                && pc < variable.start_pc as u32 + variable.length as u32
+        })
+    }
+
+    /// Name of the local variable in `slot` at `pc`
+    fn local_name(&self, slot: u16, pc: u32) -> Result<String, Error> {
+        match self.variable(slot, pc) {
+            Some(variable) => self.pool.utf8(variable.name_index),
+            None if slot == 0 && !self.is_static => Ok("this".to_string()),
+            None => Ok(format!("local{}", slot)),
+        }
+    }
+
+    /// Declared type of the local variable in `slot` at `pc`, if the
+    /// `LocalVariableTable` has it
+    fn local_type(&self, slot: u16, pc: u32) -> Result<Option<String>, Error> {
+        match self.variable(slot, pc) {
+            Some(variable) => {
+                let descriptor = self.pool.utf8(variable.descriptor_index)?;
+                let field_type = FieldType::parse(descriptor.as_bytes())
+                    .map_err(|_| Error::InvalidConstant(variable.descriptor_index))?;
+                Ok(Some(type_name(&field_type)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Entry `index` of the `BootstrapMethods` attribute
+    fn bootstrap_method(&self, index: u16) -> Option<&'a BootstrapMethod> {
+        self.class_file
+            .attributes
+            .iter()
+            .find_map(|attribute| match &attribute.attr_type {
+                AttributeType::BootstrapMethods { bootstrap_methods } => {
+                    bootstrap_methods.get(index as usize)
+                }
+                _ => None,
+            })
+    }
+}
+
+impl Pool<'_> {
+    /// Source form of a loadable constant, e.g. `1.5F` or `String.class`
+    fn literal(&self, index: u16) -> Result<String, Error> {
+        Ok(match self.constant(index)? {
+            Constant::Integer(value) => value.to_string(),
+            Constant::Float(value) => float_literal(*value as f64, "Float", "F"),
+            Constant::Long(value) => format!("{}L", value),
+            Constant::Double(value) => float_literal(*value, "Double", ""),
+            Constant::String { string_index } => quote(&self.utf8(*string_index)?),
+            Constant::Class { .. } => format!("{}.class", class_name(&self.class_name(index)?)),
+            Constant::MethodType { descriptor_index } => format!(
+                "/* method type */ {}",
+                quote(&self.utf8(*descriptor_index)?)
+            ),
+            Constant::MethodHandle {
+                reference_index, ..
+            } => {
+                let (class, name, _) = self.member(*reference_index)?;
+                format!("/* method handle */ {}::{}", class_name(&class), name)
+            }
+            Constant::Dynamic {
+                name_and_type_index,
+                ..
+            } => {
+                let (name, _) = self.name_and_type(*name_and_type_index)?;
+                format!("/* dynamic constant */ {}", name)
+            }
+            _ => return Err(Error::InvalidConstant(index)),
+        })
+    }
+}
+
+/// Source form of a class, `java.lang` left out, e.g. `java.util.List` or
+/// `String`. Array classes are given by their descriptor.
+fn class_name(internal: &str) -> String {
+    if internal.starts_with('[') {
+        if let Ok(field_type) = FieldType::parse(internal.as_bytes()) {
+            return type_name(&field_type);
+        }
+    }
+    let name = internal.replace('/', ".");
+    match name.strip_prefix("java.lang.") {
+        Some(simple_name) if !simple_name.contains('.') => simple_name.to_string(),
+        _ => name,
+    }
+}
+
+fn type_name(field_type: &FieldType) -> String {
+    match field_type {
+        FieldType::Base(base_type) => base_type.name().to_string(),
+        FieldType::Object(name) => class_name(name),
+        FieldType::Array(component) => format!("{}[]", type_name(component)),
+    }
+}
+
+/// Modifiers followed by a space, nothing without any
+fn modifiers(modifiers: &str) -> String {
+    if modifiers.is_empty() {
+        String::new()
+    } else {
+        format!("{} ", modifiers)
+    }
+}
+
+fn float_literal(value: f64, class: &str, suffix: &str) -> String {
+    if value.is_nan() {
+        format!("{}.NaN", class)
+    } else if value.is_infinite() {
+        let sign = if value > 0.0 { "POSITIVE" } else { "NEGATIVE" };
+        format!("{}.{}_INFINITY", class, sign)
+    } else if suffix.is_empty() {
+        format!("{:?}", value)
+    } else {
+        format!("{:?}{}", value as f32, suffix)
+    }
+}
+
+/// Java string literal
+fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jasmin::parse;
+    use std::fs::File;
+
+    const LOOP: &str = ".class A
+.super java/lang/Object
+.method static count([I)I
+    .limit stack 3
+    .limit locals 4
+    .var 0 is values [I from Start to End
+    .var 1 is sum I from Start to End
+    .var 2 is i I from Start to End
+    .var 3 is e Ljava/lang/RuntimeException; from Handler to End
+    .catch java/lang/RuntimeException from Divide to Handler using Handler
+Start:
+    iconst_0
+    istore_1
+    iconst_0
+    istore_2
+Loop:
+    iload_2
+    aload_0
+    arraylength
+    if_icmpge Divide
+    aload_0
+    iload_2
+    iaload
+    lookupswitch
+        0 : Ten
+        1 : One
+        7 : Stop
+        default : Next
+One:
+    iinc 1 1
+Ten:
+    iinc 1 10
+    goto Next
+Stop:
+    goto Divide
+Next:
+    iinc 2 1
+    goto Loop
+Divide:
+    iload_1
+    aload_0
+    arraylength
+    idiv
+    ireturn
+Handler:
+    astore_3
+    iconst_m1
+    ireturn
+End:
+.end method
+";
+
+    #[test]
+    fn structured() {
+        let class_file = parse(LOOP).unwrap();
+        let source = decompile_method(&class_file, &class_file.methods[0]).unwrap();
+        assert_eq!(
+            source,
+            "static int count(int[] values) {
+    int sum = 0;
+    int i = 0;
+    loop4: while (i < values.length) {
+        switch (values[i]) {
+            case 1:
+                sum++;
+            case 0:
+                sum += 10;
+                break;
+            case 7:
+                break loop4;
+        }
+        i++;
+    }
+    try {
+        return sum / values.length;
+    } catch (RuntimeException e) {
+        return -1;
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn verified() {
+        let class_file =
+            ClassFile::read_from(File::open("../classfile/tests/Verified.class").unwrap()).unwrap();
+        let source = decompile(&class_file).unwrap();
+        assert!(source.starts_with("public class Verified implements Runnable {\n"));
+        // every method is decompiled
+        assert!(!source.contains("//"));
+        assert!(source.contains(
+            "    static int loop(int local0) {
+        int local1 = 0;
+        int local2 = 0;
+        while (local2 < local0) {
+            local1 = local1 + local2 * 2;
+            local2++;
+        }
+        return local1;
+    }
+"
+        ));
+        assert!(source.contains("} catch (NumberFormatException | IllegalStateException local2) {"));
+    }
+
+    /// Pseudo-source of a static method `m(II)I` with `body`
+    fn method(body: &str) -> String {
+        let source = format!(
+            ".class A\n.super java/lang/Object\n.method static m(II)I\n    .limit stack 4\n    .limit locals 4\n{}\n.end method\n",
+            body
+        );
+        let class_file = parse(&source).unwrap();
+        decompile_method(&class_file, &class_file.methods[0]).unwrap()
+    }
+
+    #[test]
+    fn if_without_else() {
+        let source =
+            method("    iload_0\n    ifle Done\n    iinc 1 1\nDone:\n    iload_1\n    ireturn");
+        assert_eq!(
+            source,
+            "static int m(int local0, int local1) {
+    if (local0 > 0) {
+        local1++;
+    }
+    return local1;
+}
+"
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        let source = method(
+            "    iload_0\n    ifle Else\n    iconst_1\n    istore_1\n    goto Join\nElse:\n    iconst_2\n    istore_1\nJoin:\n    iload_1\n    ireturn",
+        );
+        assert_eq!(
+            source,
+            "static int m(int local0, int local1) {
+    if (local0 > 0) {
+        local1 = 1;
+    } else {
+        local1 = 2;
+    }
+    return local1;
+}
+"
+        );
+    }
+
+    #[test]
+    fn combined_conditions() {
+        // two branches to the same block become one condition
+        let source = method(
+            "    iload_0\n    ifle Done\n    iload_1\n    ifle Done\n    iinc 1 1\nDone:\n    iload_1\n    ireturn",
+        );
+        assert_eq!(
+            source,
+            "static int m(int local0, int local1) {
+    if (local0 > 0 && local1 > 0) {
+        local1++;
+    }
+    return local1;
+}
+"
+        );
+    }
+
+    #[test]
+    fn while_loop() {
+        let source = method(
+            "Loop:\n    iload_0\n    ifle Done\n    iinc 0 -1\n    iinc 1 2\n    goto Loop\nDone:\n    iload_1\n    ireturn",
+        );
+        assert_eq!(
+            source,
+            "static int m(int local0, int local1) {
+    while (local0 > 0) {
+        local0--;
+        local1 += 2;
+    }
+    return local1;
+}
+"
+        );
+    }
+
+    #[test]
+    fn continue_and_break() {
+        // the inner loop continues the outer one and breaks to the code after it
+        let source = method(
+            "Outer:\n    iload_0\n    ifle Done\n    iinc 0 -1\n    iconst_0\n    istore_2\nInner:\n    iinc 2 1\n    iload_2\n    iload_0\n    if_icmpgt Outer\n    iinc 1 1\n    iload_1\n    bipush 50\n    if_icmplt Inner\n    iinc 1 100\n    goto Outer\nDone:\n    iload_1\n    ireturn",
+        );
+        assert_eq!(
+            source,
+            "static int m(int local0, int local1) {
+    loop0: while (local0 > 0) {
+        local0--;
+        int local2 = 0;
+        while (true) {
+            local2++;
+            if (local2 > local0) {
+                continue loop0;
+            }
+            local1++;
+            if (local1 >= 50) {
+                break;
+            }
+        }
+        local1 += 100;
+    }
+    return local1;
+}
+"
+        );
+    }
+
+    #[test]
+    fn tableswitch() {
+        // case 1 falls through into the default
+        let source = method(
+            "    iload_0\n    tableswitch 0\n        Zero\n        One\n        default : Other\nZero:\n    iconst_1\n    ireturn\nOne:\n    iinc 1 1\nOther:\n    iload_1\n    ireturn",
+        );
+        assert_eq!(
+            source,
+            "static int m(int local0, int local1) {
+    switch (local0) {
+        case 0:
+            return 1;
+        case 1:
+            local1++;
+        default:
+            return local1;
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn try_catch() {
+        // the value left on the stack by the protected code is a variable
+        let source = method(
+            "    .catch java/lang/ArithmeticException from Start to End using Handler\nStart:\n    iload_0\n    iload_1\n    idiv\nEnd:\n    ireturn\nHandler:\n    pop\n    iconst_0\n    ireturn",
+        );
+        assert_eq!(
+            source,
+            "static int m(int local0, int local1) {
+    try {
+        stack0 = local0 / local1;
+    } catch (ArithmeticException exception) {
+        return 0;
+    }
+    return stack0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn irreducible_goto() {
+        // a loop entered in the middle has no header, it is left to a goto
+        let source = method(
+            "    iload_0\n    ifle Second\nFirst:\n    iinc 1 1\nSecond:\n    iinc 1 2\n    iload_1\n    bipush 100\n    if_icmplt First\n    iload_1\n    ireturn",
+        );
+        assert_eq!(
+            source,
+            "static int m(int local0, int local1) {
+    if (local0 > 0) {
+    L4:
+        local1++;
+    }
+    local1 += 2;
+    if (local1 < 100) {
+        goto L4;
+    }
+    return local1;
+}
+"
+        );
+    }
+}
@@ -0,0 +1,1194 @@
+use crate::cfg::{ControlFlowGraph, EdgeKind};
+use crate::decompiler::expression::{BlockCode, Condition, Exit, Statement};
+use crate::decompiler::{class_name, Context};
+use crate::error::Error;
+use crate::instruction::Instruction;
+use crate::metadata::Flow;
+use crate::opcode::OpCode;
+use classfile::attribute::CodeAttribute;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Nests the blocks of a method body into statements and returns the lines of
+/// the body, indented by one level. `declared` are the variables declared by
+/// the method itself, the parameters and `this`, by name and type.
+///
+/// A block is emitted in line, within the statement of the block that
+/// dominates it, unless it is where that statement continues: the immediate
+/// post-dominator of an `if` or `switch`, the exit of a loop or the block
+/// after a `try`. A natural loop becomes a `while` with the blocks of its body,
+/// and the blocks only leading to a return or `throw` from there. A jump to an
+/// enclosing loop or switch becomes a `continue` or `break`, any other jump
+/// that doesn't fall out of the statement becomes a `goto` to a label, the
+/// blocks not emitted by then being appended after the others.
+pub(crate) fn structure(
+    context: &Context,
+    code: &CodeAttribute,
+    cfg: &ControlFlowGraph,
+    blocks: &[Option<BlockCode>],
+    declared: Vec<(String, String)>,
+) -> Result<Vec<String>, Error> {
+    let mut blocks = blocks.to_vec();
+    let branches = combine_conditions(cfg, &mut blocks);
+    let blocks = &blocks[..];
+    let graph = Graph::new(cfg, blocks, branches);
+    // javac leaves the return or `goto` ending the protected code out of the
+    // range, splitting it in two when that's not at the end
+    let instructions: Vec<&(u32, Instruction)> = cfg
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instructions)
+        .collect();
+    let mut ranges: Vec<(u32, u32, u16, u16)> = vec![];
+    for exception in &code.exception_table {
+        let (start, end) = (exception.start_pc as u32, exception.end_pc as u32);
+        let (catch_type, handler_pc) = (exception.catch_type, exception.handler_pc);
+        let range = ranges.iter_mut().find(|other| {
+            other.2 == catch_type
+                && other.3 == handler_pc
+                && other.1 <= start
+                && instructions
+                    .iter()
+                    .filter(|(pc, _)| (other.1..start).contains(pc))
+                    .all(|(_, instruction)| is_quiet(instruction))
+        });
+        match range {
+            Some(range) => range.1 = end,
+            None => ranges.push((start, end, catch_type, handler_pc)),
+        }
+    }
+
+    let mut tries: Vec<Try> = vec![];
+    for (start, end, catch_type, handler_pc) in ranges {
+        let handler = match cfg.block_index(handler_pc as u32) {
+            Some(handler) if blocks[handler].is_some() => handler,
+            _ => continue,
+        };
+        let position = tries
+            .iter()
+            .position(|other| other.start == start && other.end == end);
+        let index = match position {
+            Some(index) => index,
+            None => {
+                tries.push(Try {
+                    start,
+                    end,
+                    handlers: vec![],
+                });
+                tries.len() - 1
+            }
+        };
+        let catch = (catch_type, handler);
+        if !tries[index].handlers.contains(&catch) {
+            tries[index].handlers.push(catch);
+        }
+    }
+    // a handler within the range it protects, like the one releasing the
+    // monitor of a synchronized statement, has no source form
+    tries.retain(|other| {
+        other.handlers.iter().all(|(_, handler)| {
+            let start = cfg.blocks[*handler].start;
+            start < other.start || start >= other.end
+        })
+    });
+
+    let mut emitter = Emitter {
+        context,
+        cfg,
+        blocks,
+        graph,
+        tries,
+        emitted: vec![false; blocks.len()],
+        scopes: vec![],
+        lines: vec![],
+        gotos: BTreeSet::new(),
+        declared: declared.into_iter().collect(),
+    };
+    emitter.sequence(0, 0, None, 1)?;
+    for (block, code) in blocks.iter().enumerate() {
+        if code.is_some() && !emitter.emitted[block] {
+            emitter.sequence(block, block, None, 1)?;
+        }
+    }
+
+    let mut lines = vec![];
+    for line in emitter.lines {
+        match line {
+            Line::Text(indent, text) => lines.push(format!("{}{}", "    ".repeat(indent), text)),
+            Line::Label(indent, block) if emitter.gotos.contains(&block) => lines.push(format!(
+                "{}L{}:",
+                "    ".repeat(indent - 1),
+                cfg.blocks[block].start
+            )),
+            Line::Label(..) => {}
+        }
+    }
+    Ok(lines)
+}
+
+/// Whether the instruction can't throw an exception
+fn is_quiet(instruction: &Instruction) -> bool {
+    use OpCode::*;
+    matches!(instruction.opcode.info().flow, Flow::Jump | Flow::Return)
+        || (nop as u8..=sipush as u8).contains(&(instruction.opcode as u8))
+        || (iload as u8..=aload_3 as u8).contains(&(instruction.opcode as u8))
+        || (istore as u8..=astore_3 as u8).contains(&(instruction.opcode as u8))
+}
+
+/// Branch target and next block of each block ending in a branch, the
+/// conditions of a block tested right after another one being folded into the
+/// condition of the other with `&&` or `||`, leaving the block out
+fn combine_conditions(
+    cfg: &ControlFlowGraph,
+    blocks: &mut [Option<BlockCode>],
+) -> Vec<Option<(usize, usize)>> {
+    let mut branches: Vec<Option<(usize, usize)>> = cfg
+        .blocks
+        .iter()
+        .map(|block| {
+            let target = |kind| {
+                block
+                    .successors
+                    .iter()
+                    .find(|edge| edge.kind == kind)
+                    .map(|edge| edge.target)
+            };
+            let taken = target(EdgeKind::Branch)?;
+            Some((taken, target(EdgeKind::FallThrough).unwrap_or(taken)))
+        })
+        .collect();
+    let handlers = |block: usize| {
+        cfg.blocks[block]
+            .successors
+            .iter()
+            .filter(|edge| matches!(edge.kind, EdgeKind::Exception { .. }))
+            .map(|edge| edge.target)
+            .collect::<Vec<usize>>()
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut predecessors = vec![0; blocks.len()];
+        for (block, code) in blocks.iter().enumerate() {
+            if let Some(code) = code {
+                let mut targets: Vec<usize> = match (&code.exit, branches[block]) {
+                    (Exit::Branch(_), Some((taken, next))) => vec![taken, next],
+                    _ => cfg.blocks[block]
+                        .successors
+                        .iter()
+                        .map(|edge| edge.target)
+                        .collect(),
+                };
+                targets.sort_unstable();
+                targets.dedup();
+                for target in targets {
+                    predecessors[target] += 1;
+                }
+            }
+        }
+        // later blocks first, so the conditions nest as they were written
+        for block in (0..blocks.len()).rev() {
+            let (first, (taken, next)) = match (&blocks[block], branches[block]) {
+                (
+                    Some(BlockCode {
+                        exit: Exit::Branch(condition),
+                        ..
+                    }),
+                    Some(targets),
+                ) => (condition.clone(), targets),
+                _ => continue,
+            };
+            for (other, other_taken) in [(next, false), (taken, true)] {
+                let second = match (&blocks[other], branches[other]) {
+                    (
+                        Some(BlockCode {
+                            statements,
+                            exit: Exit::Branch(condition),
+                            catch: None,
+                        }),
+                        Some(targets),
+                    ) if statements.is_empty()
+                        && other != block
+                        && other != 0
+                        && predecessors[other] == 1
+                        && handlers(other) == handlers(block) =>
+                    {
+                        (condition.clone(), targets)
+                    }
+                    _ => continue,
+                };
+                let (condition, targets) = match (other_taken, second) {
+                    (false, (second, (second_taken, second_next))) if second_taken == taken => {
+                        (first.or(&second), (taken, second_next))
+                    }
+                    (false, (second, (second_taken, second_next))) if second_next == taken => {
+                        (first.negate().and(&second), (second_taken, taken))
+                    }
+                    (true, (second, (second_taken, second_next))) if second_next == next => {
+                        (first.and(&second), (second_taken, next))
+                    }
+                    (true, (second, (second_taken, second_next))) if second_taken == next => {
+                        (first.negate().or(&second), (second_taken, second_next))
+                    }
+                    _ => continue,
+                };
+                if let Some(code) = &mut blocks[block] {
+                    code.exit = Exit::Branch(condition);
+                }
+                branches[block] = Some(targets);
+                blocks[other] = None;
+                changed = true;
+                break;
+            }
+            if changed {
+                break;
+            }
+        }
+    }
+    branches
+}
+
+/// Control flow between the blocks reached
+struct Graph {
+    /// Successors by normal control flow, without exceptions
+    successors: Vec<Vec<usize>>,
+    /// Branch target and next block of blocks ending in a branch
+    branches: Vec<Option<(usize, usize)>>,
+    /// Predecessors by normal control flow and exceptions
+    predecessors: Vec<Vec<usize>>,
+    /// Immediate dominators, following exceptions too, the entry block being
+    /// its own
+    dominators: Vec<Option<usize>>,
+    /// Immediate post-dominators by normal control flow, none for blocks only
+    /// post-dominated by the method exit
+    post_dominators: Vec<Option<usize>>,
+    /// Immediate post-dominators following exceptions too
+    exception_post_dominators: Vec<Option<usize>>,
+    /// Blocks of the natural loop of each loop header
+    loops: BTreeMap<usize, BTreeSet<usize>>,
+    /// Blocks from which control flow only reaches blocks they dominate,
+    /// ending in a return or `throw`
+    tails: Vec<bool>,
+}
+
+impl Graph {
+    fn new(
+        cfg: &ControlFlowGraph,
+        blocks: &[Option<BlockCode>],
+        branches: Vec<Option<(usize, usize)>>,
+    ) -> Graph {
+        let count = blocks.len();
+        let mut successors: Vec<Vec<usize>> = vec![vec![]; count];
+        let mut all_successors: Vec<Vec<usize>> = vec![vec![]; count];
+        for (index, block) in cfg.blocks.iter().enumerate() {
+            if blocks[index].is_none() {
+                continue;
+            }
+            let targets = match branches[index] {
+                Some((taken, next)) => vec![taken, next],
+                None => block
+                    .successors
+                    .iter()
+                    .filter(|edge| !matches!(edge.kind, EdgeKind::Exception { .. }))
+                    .map(|edge| edge.target)
+                    .collect(),
+            };
+            for target in targets {
+                if !successors[index].contains(&target) {
+                    successors[index].push(target);
+                }
+            }
+            all_successors[index] = successors[index].clone();
+            for edge in &block.successors {
+                if let EdgeKind::Exception { .. } = edge.kind {
+                    if !all_successors[index].contains(&edge.target) {
+                        all_successors[index].push(edge.target);
+                    }
+                }
+            }
+        }
+        let mut predecessors: Vec<Vec<usize>> = vec![vec![]; count];
+        for (index, targets) in all_successors.iter().enumerate() {
+            for target in targets {
+                predecessors[*target].push(index);
+            }
+        }
+
+        let dominators = dominators(&all_successors, 0);
+        let mut graph = Graph {
+            post_dominators: post_dominators(&successors, &successors, blocks),
+            exception_post_dominators: post_dominators(&all_successors, &successors, blocks),
+            successors,
+            branches,
+            predecessors,
+            dominators,
+            loops: BTreeMap::new(),
+            tails: vec![false; count],
+        };
+
+        for header in 0..count {
+            let latches: Vec<usize> = graph
+                .predecessors(header)
+                .filter(|latch| graph.dominates(header, *latch))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+            let mut body = BTreeSet::new();
+            body.insert(header);
+            let mut pending = latches;
+            while let Some(block) = pending.pop() {
+                if body.insert(block) {
+                    pending.extend(graph.predecessors[block].iter().copied());
+                }
+            }
+            graph.loops.insert(header, body);
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (block, code) in blocks.iter().enumerate() {
+                let tail = match code {
+                    Some(code) if !graph.tails[block] => {
+                        matches!(code.exit, Exit::Return(_) | Exit::Throw(_))
+                            || !graph.successors[block].is_empty()
+                                && graph.successors[block].iter().all(|successor| {
+                                    *successor != block
+                                        && graph.dominates(block, *successor)
+                                        && graph.tails[*successor]
+                                })
+                    }
+                    _ => false,
+                };
+                if tail {
+                    graph.tails[block] = true;
+                    changed = true;
+                }
+            }
+        }
+        graph
+    }
+
+    /// Predecessors of `block` by normal control flow
+    fn predecessors(&self, block: usize) -> impl Iterator<Item = usize> + '_ {
+        self.predecessors[block]
+            .iter()
+            .copied()
+            .filter(move |predecessor| self.successors[*predecessor].contains(&block))
+    }
+
+    fn dominates(&self, dominator: usize, block: usize) -> bool {
+        let mut block = block;
+        loop {
+            if block == dominator {
+                return true;
+            }
+            match self.dominators[block] {
+                Some(parent) if parent != block => block = parent,
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// Immediate dominator of each node reached from `entry`, following
+/// `successors`, the entry being its own
+fn dominators(successors: &[Vec<usize>], entry: usize) -> Vec<Option<usize>> {
+    let count = successors.len();
+    let mut postorder = vec![];
+    let mut visited = vec![false; count];
+    visited[entry] = true;
+    let mut stack = vec![(entry, 0)];
+    while let Some((node, next)) = stack.pop() {
+        match successors[node].get(next) {
+            Some(successor) => {
+                stack.push((node, next + 1));
+                if !visited[*successor] {
+                    visited[*successor] = true;
+                    stack.push((*successor, 0));
+                }
+            }
+            None => postorder.push(node),
+        }
+    }
+    let mut numbers = vec![0; count];
+    let mut predecessors: Vec<Vec<usize>> = vec![vec![]; count];
+    for (number, node) in postorder.iter().enumerate() {
+        numbers[*node] = number;
+        for successor in &successors[*node] {
+            predecessors[*successor].push(*node);
+        }
+    }
+
+    let mut dominators: Vec<Option<usize>> = vec![None; count];
+    dominators[entry] = Some(entry);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in postorder.iter().rev().copied() {
+            if node == entry {
+                continue;
+            }
+            let mut dominator: Option<usize> = None;
+            for predecessor in predecessors[node].iter().copied() {
+                if dominators[predecessor].is_none() {
+                    continue;
+                }
+                dominator = Some(match dominator {
+                    None => predecessor,
+                    Some(mut other) => {
+                        let mut predecessor = predecessor;
+                        while predecessor != other {
+                            while numbers[predecessor] < numbers[other] {
+                                predecessor = dominators[predecessor].unwrap();
+                            }
+                            while numbers[other] < numbers[predecessor] {
+                                other = dominators[other].unwrap();
+                            }
+                        }
+                        other
+                    }
+                });
+            }
+            if dominator != dominators[node] {
+                dominators[node] = dominator;
+                changed = true;
+            }
+        }
+    }
+    dominators
+}
+
+/// Immediate post-dominators of the blocks reached following `successors`,
+/// the blocks without `normal` successors leading to the method exit. A return
+/// or `throw` only branched to from one block, as an early exit, is left out,
+/// so that it doesn't keep the branch from merging with the other one.
+fn post_dominators(
+    successors: &[Vec<usize>],
+    normal: &[Vec<usize>],
+    blocks: &[Option<BlockCode>],
+) -> Vec<Option<usize>> {
+    let exit = successors.len();
+    let mut predecessors: Vec<Vec<usize>> = vec![vec![]; exit];
+    for (block, targets) in normal.iter().enumerate() {
+        for target in targets {
+            predecessors[*target].push(block);
+        }
+    }
+    let branch = |block: usize| {
+        matches!(
+            &blocks[block],
+            Some(BlockCode {
+                exit: Exit::Branch(_),
+                ..
+            })
+        )
+    };
+    let mut early: Vec<bool> = (0..exit)
+        .map(|block| match &blocks[block] {
+            Some(BlockCode {
+                exit: Exit::Return(_) | Exit::Throw(_),
+                ..
+            }) => matches!(predecessors[block][..], [predecessor] if branch(predecessor)),
+            _ => false,
+        })
+        .collect();
+
+    let mut post_dominators = loop {
+        let mut reversed: Vec<Vec<usize>> = vec![vec![]; exit + 1];
+        for (block, targets) in successors.iter().enumerate() {
+            if blocks[block].is_none() || early[block] {
+                continue;
+            }
+            if normal[block].iter().all(|target| early[*target]) {
+                reversed[exit].push(block);
+            }
+            for target in targets {
+                reversed[*target].push(block);
+            }
+        }
+        let post_dominators = dominators(&reversed, exit);
+        // the exits of blocks not leading to another one, as a loop ending
+        // in a return, are kept, the last one first
+        let kept = (0..exit)
+            .filter(|block| {
+                blocks[*block].is_some() && !early[*block] && post_dominators[*block].is_none()
+            })
+            .flat_map(|block| normal[block].iter().copied())
+            .filter(|target| early[*target])
+            .max();
+        match kept {
+            Some(kept) => early[kept] = false,
+            None => break post_dominators,
+        }
+    };
+    post_dominators.truncate(exit);
+    for post_dominator in &mut post_dominators {
+        if *post_dominator == Some(exit) {
+            *post_dominator = None;
+        }
+    }
+    post_dominators
+}
+
+/// Handlers of a range of the exception table, `catch_type` first
+struct Try {
+    start: u32,
+    end: u32,
+    handlers: Vec<(u16, usize)>,
+}
+
+enum Scope {
+    /// `line` is the index of the line opening the loop, prefixed with a label
+    /// when a `break` or `continue` from an inner statement needs it
+    Loop {
+        header: usize,
+        exit: Option<usize>,
+        line: usize,
+        labelled: bool,
+    },
+    Switch {
+        block: usize,
+        exit: Option<usize>,
+        line: usize,
+        labelled: bool,
+    },
+    Try {
+        start: u32,
+        end: u32,
+    },
+}
+
+enum Line {
+    /// Indentation level and text
+    Text(usize, String),
+    /// Start of a block, written only if a `goto` targets it
+    Label(usize, usize),
+}
+
+struct Emitter<'a> {
+    context: &'a Context<'a>,
+    cfg: &'a ControlFlowGraph,
+    blocks: &'a [Option<BlockCode>],
+    graph: Graph,
+    tries: Vec<Try>,
+    emitted: Vec<bool>,
+    /// Statements enclosing the lines being emitted, innermost last
+    scopes: Vec<Scope>,
+    lines: Vec<Line>,
+    /// Blocks targeted by a `goto`
+    gotos: BTreeSet<usize>,
+    /// Variables declared so far, by name and type
+    declared: HashSet<(String, String)>,
+}
+
+impl<'a> Emitter<'a> {
+    /// Emits `first` and the blocks the statements continue with, as long as
+    /// they are dominated by `entry`, up to `follow`, where control falls out
+    /// of the enclosing statement
+    fn sequence(
+        &mut self,
+        first: usize,
+        entry: usize,
+        follow: Option<usize>,
+        indent: usize,
+    ) -> Result<(), Error> {
+        let mut block = first;
+        loop {
+            let next = match self.try_at(block) {
+                Some(index) => self.emit_try(block, index, entry, follow, indent)?,
+                None if self.graph.loops.contains_key(&block) && !self.is_loop_open(block) => {
+                    self.emit_loop(block, indent)?
+                }
+                None => self.emit_block(block, entry, follow, indent)?,
+            };
+            match next {
+                Some(next) if Some(next) != follow && self.can_inline(next, entry) => block = next,
+                Some(next) => {
+                    self.jump(next, follow, indent);
+                    return Ok(());
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Emits the statements of `block` and the statement its branch or switch
+    /// starts. Returns where control continues after them.
+    fn emit_block(
+        &mut self,
+        block: usize,
+        entry: usize,
+        follow: Option<usize>,
+        indent: usize,
+    ) -> Result<Option<usize>, Error> {
+        self.emitted[block] = true;
+        self.lines.push(Line::Label(indent, block));
+        let code = self.blocks[block].as_ref().unwrap();
+        for statement in &code.statements {
+            let text = match statement {
+                Statement::Assign {
+                    name,
+                    ty: Some(ty),
+                    value,
+                } if self.declared.insert((name.clone(), ty.clone())) => {
+                    format!("{} {} = {};", ty, name, value)
+                }
+                Statement::Assign { name, value, .. } => format!("{} = {};", name, value),
+                Statement::Other(text) => format!("{};", text),
+            };
+            self.text(indent, text);
+        }
+        match &code.exit {
+            Exit::Return(None) => self.text(indent, "return;".to_string()),
+            Exit::Return(Some(value)) => self.text(indent, format!("return {};", value)),
+            Exit::Throw(exception) => self.text(indent, format!("throw {};", exception)),
+            Exit::Next => return Ok(self.graph.successors[block].first().copied()),
+            Exit::Branch(condition) => {
+                return self.emit_if(block, condition, entry, follow, indent)
+            }
+            Exit::Switch {
+                value,
+                cases,
+                default,
+            } => return self.emit_switch(block, value, cases, *default, entry, follow, indent),
+        }
+        Ok(None)
+    }
+
+    /// `if` statement for the branch ending `block`, the branch taken first
+    /// unless control falls through into a block emitted in line
+    fn emit_if(
+        &mut self,
+        block: usize,
+        condition: &Condition,
+        entry: usize,
+        follow: Option<usize>,
+        indent: usize,
+    ) -> Result<Option<usize>, Error> {
+        let (taken, next) = self.graph.branches[block].unwrap();
+        if taken == next {
+            return Ok(Some(taken));
+        }
+        let merge = self.merge(block, entry, follow);
+        let follow = merge.or(follow);
+        // an arm only going on to where the statement continues is empty
+        let mut arms = [taken, next];
+        for arm in &mut arms {
+            let forwarded = self.forward(*arm, follow);
+            if Some(forwarded) == follow {
+                self.skip(*arm, forwarded);
+                *arm = forwarded;
+            }
+        }
+        let [taken, next] = arms;
+        if taken == next {
+            self.text(indent, format!("if ({}) {{", condition));
+            self.text(indent, "}".to_string());
+            return Ok(merge);
+        }
+        if Some(taken) == follow {
+            self.arm(&condition.negate(), next, block, follow, indent)?;
+            return Ok(merge);
+        }
+        if Some(next) == follow {
+            self.arm(condition, taken, block, follow, indent)?;
+            return Ok(merge);
+        }
+        match (self.can_inline(taken, block), self.can_inline(next, block)) {
+            // no `else` after an arm that doesn't complete normally
+            (true, true) if merge.is_none() && self.graph.tails[next] => {
+                self.arm(&condition.negate(), next, block, follow, indent)?;
+                Ok(Some(taken))
+            }
+            (true, true) if merge.is_none() && self.graph.tails[taken] => {
+                self.arm(condition, taken, block, follow, indent)?;
+                Ok(Some(next))
+            }
+            (true, true) => {
+                self.text(indent, format!("if ({}) {{", condition.negate()));
+                self.sequence(next, next, follow, indent + 1)?;
+                self.text(indent, "} else {".to_string());
+                self.sequence(taken, taken, follow, indent + 1)?;
+                self.text(indent, "}".to_string());
+                Ok(merge)
+            }
+            (false, _) => {
+                self.arm(condition, taken, block, follow, indent)?;
+                Ok(Some(next))
+            }
+            (true, false) => {
+                self.arm(&condition.negate(), next, block, follow, indent)?;
+                Ok(Some(taken))
+            }
+        }
+    }
+
+    /// `if` statement without `else`, going to `target` when `condition`
+    /// holds
+    fn arm(
+        &mut self,
+        condition: &Condition,
+        target: usize,
+        block: usize,
+        follow: Option<usize>,
+        indent: usize,
+    ) -> Result<(), Error> {
+        self.text(indent, format!("if ({}) {{", condition));
+        if Some(target) != follow && self.can_inline(target, block) {
+            self.sequence(target, target, follow, indent + 1)?;
+        } else {
+            self.jump(target, follow, indent + 1);
+        }
+        self.text(indent, "}".to_string());
+        Ok(())
+    }
+
+    /// `switch` statement with a group of labels for each target, in code
+    /// order, so a case falls through into the next one as the code does
+    #[allow(clippy::too_many_arguments)]
+    fn emit_switch(
+        &mut self,
+        block: usize,
+        value: &str,
+        cases: &[(i32, u32)],
+        default: u32,
+        entry: usize,
+        follow: Option<usize>,
+        indent: usize,
+    ) -> Result<Option<usize>, Error> {
+        let cfg = self.cfg;
+        let block_of = |pc: u32| cfg.block_index(pc).unwrap();
+        let default = block_of(default);
+        let mut targets: Vec<usize> = cases
+            .iter()
+            .map(|(_, pc)| block_of(*pc))
+            .chain(Some(default))
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        let merge = self
+            .merge(block, entry, follow)
+            .or_else(|| self.switch_merge(block, &targets, entry, follow));
+        let exit = merge.or(follow);
+
+        let line = self.lines.len();
+        self.text(indent, format!("switch ({}) {{", value));
+        self.scopes.push(Scope::Switch {
+            block,
+            exit,
+            line,
+            labelled: false,
+        });
+        for (i, target) in targets.iter().copied().enumerate() {
+            let keys: Vec<i32> = cases
+                .iter()
+                .filter(|(_, pc)| block_of(*pc) == target)
+                .map(|(key, _)| *key)
+                .collect();
+            if Some(target) == exit && keys.is_empty() {
+                continue;
+            }
+            for key in keys {
+                self.text(indent + 1, format!("case {}:", key));
+            }
+            if target == default {
+                self.text(indent + 1, "default:".to_string());
+            }
+            let case_follow = targets.get(i + 1).copied().or(exit);
+            if Some(target) == exit {
+                self.text(indent + 2, "break;".to_string());
+            } else if self.can_inline(target, block) {
+                self.sequence(target, target, case_follow, indent + 2)?;
+            } else {
+                self.jump(target, case_follow, indent + 2);
+            }
+        }
+        self.close_scope(indent);
+        Ok(merge)
+    }
+
+    /// Where a switch continues when some case returns or leaves an
+    /// enclosing loop: the first block after the cases that one of them
+    /// jumps to, as the `break` of a case does
+    fn switch_merge(
+        &self,
+        block: usize,
+        targets: &[usize],
+        entry: usize,
+        follow: Option<usize>,
+    ) -> Option<usize> {
+        let last = *targets.last()?;
+        (0..self.blocks.len())
+            .filter(|case| *case != block && self.graph.dominates(block, *case))
+            .flat_map(|case| &self.cfg.blocks[case].successors)
+            .filter(|edge| edge.kind == EdgeKind::Jump && edge.target >= last)
+            .map(|edge| edge.target)
+            .filter(|target| Some(*target) == follow || self.can_inline(*target, entry))
+            .min()
+    }
+
+    /// `while` statement for the natural loop of `header`, testing the
+    /// condition of the header if it has nothing else to do and leaves the
+    /// loop. Returns the exit of the loop.
+    fn emit_loop(&mut self, header: usize, indent: usize) -> Result<Option<usize>, Error> {
+        let exit = self.loop_exit(header);
+        let body = &self.graph.loops[&header];
+        let code = self.blocks[header].as_ref().unwrap();
+        let mut condition = None;
+        if let (Exit::Branch(branch), true) = (&code.exit, code.statements.is_empty()) {
+            let (taken, next) = self.graph.branches[header].unwrap();
+            if Some(taken) == exit && body.contains(&next) {
+                condition = Some((branch.negate(), next));
+            } else if Some(next) == exit && body.contains(&taken) {
+                condition = Some((branch.clone(), taken));
+            }
+        }
+
+        if condition.is_some() {
+            self.emitted[header] = true;
+            self.lines.push(Line::Label(indent, header));
+        }
+        let line = self.lines.len();
+        self.scopes.push(Scope::Loop {
+            header,
+            exit,
+            line,
+            labelled: false,
+        });
+        match condition {
+            Some((condition, inside)) => {
+                self.text(indent, format!("while ({}) {{", condition));
+                if self.can_inline(inside, header) {
+                    self.sequence(inside, header, Some(header), indent + 1)?;
+                } else {
+                    self.jump(inside, Some(header), indent + 1);
+                }
+            }
+            None => {
+                self.text(indent, "while (true) {".to_string());
+                self.sequence(header, header, Some(header), indent + 1)?;
+            }
+        }
+        self.close_scope(indent);
+        Ok(exit)
+    }
+
+    /// Where control continues after the loop of `header`: the block the
+    /// header branches to out of the loop, else the post-dominator of the
+    /// header or the first block left to, not counting those emitted within
+    /// the loop nor the headers of enclosing loops, which are continued
+    fn loop_exit(&self, header: usize) -> Option<usize> {
+        let body = &self.graph.loops[&header];
+        if let Some(BlockCode {
+            exit: Exit::Branch(_),
+            ..
+        }) = &self.blocks[header]
+        {
+            let outside = self.graph.successors[header]
+                .iter()
+                .find(|successor| !body.contains(successor) && !self.is_loop_open(**successor));
+            if let Some(outside) = outside {
+                return Some(*outside);
+            }
+        }
+        let exits: BTreeSet<usize> = body
+            .iter()
+            .flat_map(|block| self.graph.successors[*block].iter().copied())
+            .filter(|successor| {
+                // blocks only leading to a return from the loop are part of it
+                let tail = self.graph.tails[*successor] && self.graph.dominates(header, *successor);
+                !body.contains(successor) && !tail && !self.is_loop_open(*successor)
+            })
+            .collect();
+        match self.graph.post_dominators[header] {
+            Some(post_dominator) if exits.contains(&post_dominator) => Some(post_dominator),
+            _ => exits.iter().next().copied(),
+        }
+    }
+
+    /// `try` statement for the range of the exception table starting at
+    /// `block`, with a `catch` clause for each handler. Returns the block
+    /// after it.
+    fn emit_try(
+        &mut self,
+        block: usize,
+        index: usize,
+        entry: usize,
+        follow: Option<usize>,
+        indent: usize,
+    ) -> Result<Option<usize>, Error> {
+        let (start, end) = (self.tries[index].start, self.tries[index].end);
+        let inside =
+            |cfg: &ControlFlowGraph, block: usize| (start..end).contains(&cfg.blocks[block].start);
+        // handlers shared by several exception classes become one clause
+        let mut clauses: Vec<(Vec<u16>, usize)> = vec![];
+        for (catch_type, handler) in &self.tries[index].handlers {
+            match clauses.iter_mut().find(|(_, other)| other == handler) {
+                Some((catch_types, _)) => catch_types.push(*catch_type),
+                None => clauses.push((vec![*catch_type], *handler)),
+            }
+        }
+        // the post-dominator of the protected blocks and handlers, else the
+        // only block the protected blocks leave to
+        let after = match self.graph.exception_post_dominators[block] {
+            Some(after)
+                if !inside(self.cfg, after)
+                    && !clauses.iter().any(|(_, handler)| *handler == after) =>
+            {
+                Some(after)
+            }
+            _ => {
+                let exits: BTreeSet<usize> = (0..self.blocks.len())
+                    .filter(|other| {
+                        self.blocks[*other].is_some()
+                            && inside(self.cfg, *other)
+                            && self.graph.dominates(block, *other)
+                    })
+                    .flat_map(|other| self.graph.successors[other].iter().copied())
+                    .filter(|successor| {
+                        !inside(self.cfg, *successor)
+                            && !clauses.iter().any(|(_, handler)| handler == successor)
+                    })
+                    .collect();
+                if exits.len() == 1 {
+                    exits.iter().next().copied()
+                } else {
+                    None
+                }
+            }
+        };
+        // past the `goto` javac puts after the protected code and each handler
+        let after = after
+            .map(|after| self.forward(after, follow))
+            .filter(|after| Some(*after) == follow || self.can_inline(*after, entry));
+        let follow = after.or(follow);
+
+        self.text(indent, "try {".to_string());
+        self.scopes.push(Scope::Try { start, end });
+        self.sequence(block, block, follow, indent + 1)?;
+        self.scopes.pop();
+        for (catch_types, handler) in clauses {
+            let mut classes = vec![];
+            for catch_type in catch_types {
+                classes.push(match catch_type {
+                    0 => "Throwable".to_string(),
+                    catch_type => class_name(&self.context.pool.class_name(catch_type)?),
+                });
+            }
+            let class = classes.join(" | ");
+            let name = self.blocks[handler]
+                .as_ref()
+                .and_then(|code| code.catch.clone())
+                .unwrap_or_else(|| "exception".to_string());
+            self.text(indent, format!("}} catch ({} {}) {{", class, name));
+            self.declared.insert((name, class));
+            if self.can_inline(handler, block) {
+                self.sequence(handler, handler, follow, indent + 1)?;
+            } else {
+                self.jump(handler, follow, indent + 1);
+            }
+        }
+        self.text(indent, "}".to_string());
+        Ok(after)
+    }
+
+    /// Range of the exception table to start a `try` statement at `block`,
+    /// the largest one not started yet. A loop starting at the same block
+    /// encloses the `try` unless it is entirely protected.
+    fn try_at(&self, block: usize) -> Option<usize> {
+        let start = self.cfg.blocks[block].start;
+        let index = self
+            .tries
+            .iter()
+            .enumerate()
+            .filter(|(_, other)| {
+                other.start == start
+                    && !self.scopes.iter().any(|scope| {
+                        matches!(scope, Scope::Try { start, end }
+                            if *start == other.start && *end == other.end)
+                    })
+            })
+            .max_by_key(|(index, other)| (other.end, std::cmp::Reverse(*index)))
+            .map(|(index, _)| index)?;
+        if let Some(body) = self.graph.loops.get(&block) {
+            let range = self.tries[index].start..self.tries[index].end;
+            if !self.is_loop_open(block)
+                && !body
+                    .iter()
+                    .all(|other| range.contains(&self.cfg.blocks[*other].start))
+            {
+                return None;
+            }
+        }
+        Some(index)
+    }
+
+    /// The immediate post-dominator of `block`, where the statement it
+    /// starts continues, if that can be emitted after it
+    fn merge(&self, block: usize, entry: usize, follow: Option<usize>) -> Option<usize> {
+        let merge = self.graph.post_dominators[block]?;
+        if Some(merge) == follow || self.can_inline(merge, entry) {
+            Some(merge)
+        } else {
+            None
+        }
+    }
+
+    /// Whether `block` can be emitted within the statement started at
+    /// `entry`: it wasn't emitted yet, `entry` dominates it and it belongs to
+    /// the enclosing loops and `try` statements without being where they
+    /// continue
+    fn can_inline(&self, block: usize, entry: usize) -> bool {
+        self.blocks[block].is_some()
+            && !self.emitted[block]
+            && self.graph.dominates(entry, block)
+            && self.scopes.iter().all(|scope| match scope {
+                Scope::Loop { header, exit, .. } => {
+                    *exit != Some(block)
+                        && (self.graph.loops[header].contains(&block)
+                            || self.graph.tails[block] && self.graph.dominates(*header, block))
+                }
+                Scope::Switch { exit, .. } => *exit != Some(block),
+                Scope::Try { start, end } => (*start..*end).contains(&self.cfg.blocks[block].start),
+            })
+    }
+
+    /// Control going to `target`, nothing when it falls out of the statement
+    /// to `follow`
+    fn jump(&mut self, target: usize, follow: Option<usize>, indent: usize) {
+        let forwarded = self.forward(target, follow);
+        self.skip(target, forwarded);
+        let target = forwarded;
+        if Some(target) == follow {
+            return;
+        }
+        let (mut loops, mut breakables) = (0, 0);
+        for scope in self.scopes.iter_mut().rev() {
+            let text = match scope {
+                Scope::Loop {
+                    header,
+                    exit,
+                    labelled,
+                    ..
+                } => {
+                    let label = format!("loop{}", self.cfg.blocks[*header].start);
+                    let text = if *header == target {
+                        Some(("continue", loops))
+                    } else if *exit == Some(target) {
+                        Some(("break", breakables))
+                    } else {
+                        None
+                    };
+                    loops += 1;
+                    breakables += 1;
+                    text.map(|(keyword, enclosing)| {
+                        if enclosing == 0 {
+                            format!("{};", keyword)
+                        } else {
+                            *labelled = true;
+                            format!("{} {};", keyword, label)
+                        }
+                    })
+                }
+                Scope::Switch {
+                    block,
+                    exit,
+                    labelled,
+                    ..
+                } => {
+                    let label = format!("switch{}", self.cfg.blocks[*block].start);
+                    let text = if *exit == Some(target) {
+                        Some(if breakables == 0 {
+                            "break;".to_string()
+                        } else {
+                            *labelled = true;
+                            format!("break {};", label)
+                        })
+                    } else {
+                        None
+                    };
+                    breakables += 1;
+                    text
+                }
+                Scope::Try { .. } => None,
+            };
+            if let Some(text) = text {
+                self.text(indent, text);
+                return;
+            }
+        }
+        self.gotos.insert(target);
+        self.text(indent, format!("goto L{};", self.cfg.blocks[target].start));
+    }
+
+    /// Where control goes on to from `target`, through the blocks doing
+    /// nothing else, as a `break` out of a loop does, up to `follow`
+    fn forward(&self, target: usize, follow: Option<usize>) -> usize {
+        let mut target = target;
+        while Some(target) != follow {
+            match (&self.blocks[target], &self.graph.successors[target][..]) {
+                (
+                    Some(BlockCode {
+                        statements,
+                        exit: Exit::Next,
+                        ..
+                    }),
+                    [next],
+                ) if statements.is_empty()
+                    && *next != target
+                    && !self.graph.loops.contains_key(&target) =>
+                {
+                    target = *next
+                }
+                _ => break,
+            }
+        }
+        target
+    }
+
+    /// Leaves out the blocks from `target` to where `forward` goes on to
+    fn skip(&mut self, target: usize, forwarded: usize) {
+        let mut target = target;
+        while target != forwarded {
+            self.emitted[target] = true;
+            target = self.graph.successors[target][0];
+        }
+    }
+
+    fn is_loop_open(&self, header: usize) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| matches!(scope, Scope::Loop { header: other, .. } if *other == header))
+    }
+
+    /// Ends the innermost loop or switch, labelling it if it needs to be
+    fn close_scope(&mut self, indent: usize) {
+        let label = match self.scopes.pop() {
+            Some(Scope::Loop {
+                header,
+                line,
+                labelled: true,
+                ..
+            }) => Some((line, format!("loop{}", self.cfg.blocks[header].start))),
+            Some(Scope::Switch {
+                block,
+                line,
+                labelled: true,
+                ..
+            }) => Some((line, format!("switch{}", self.cfg.blocks[block].start))),
+            _ => None,
+        };
+        if let Some((line, label)) = label {
+            if let Line::Text(_, text) = &mut self.lines[line] {
+                *text = format!("{}: {}", label, text);
+            }
+        }
+        self.text(indent, "}".to_string());
+    }
+
+    fn text(&mut self, indent: usize, text: String) {
+        self.lines.push(Line::Text(indent, text));
+    }
+}
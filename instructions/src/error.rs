@@ -0,0 +1,88 @@
+use crate::opcode::OpCode;
+use crate::verifier::VerifyError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // OpCode
+    UndefinedOpCode(u8),
+
+    // Decoder, the first field is the offset of the instruction
+    UnexpectedEnd(u32),
+
+    InvalidOpCode(u32, u8),
+
+    InvalidWideOpCode(u32, u8),
+
+    InvalidArrayType(u32, u8),
+
+    InvalidSwitchRange(u32, i32, i32),
+
+    InvalidBranchTarget(u32, i64),
+
+    // Assembler
+    InvalidOperand(OpCode),
+
+    UndefinedLabel(u32),
+
+    DuplicateLabel(u32),
+
+    CodeTooLarge(u32),
+
+    // Control flow graph, index of the exception table entry
+    InvalidExceptionTable(u16),
+
+    // Jasmin text, the first field is the line number
+    Syntax(usize, String),
+
+    InvalidConstant(u16),
+
+    // Constant the text format has no syntax for
+    UnsupportedConstant(u16),
+
+    ClassFile(String),
+
+    // Max stack computation, offset of the instruction
+    StackUnderflow(u32),
+
+    InconsistentStackHeight(u32),
+
+    // Subroutine inlining, offset of the jsr or ret
+    RecursiveSubroutine(u32),
+
+    UnmatchedRet(u32),
+
+    // Code being rewritten doesn't verify
+    Verify(Box<VerifyError>),
+
+    // SSA construction, offset of the instruction
+    InvalidLocal(u32),
+
+    StackTypeMismatch(u32),
+
+    // Abstract or native method
+    MissingCode,
+
+    // Search query, the offending pattern
+    InvalidPattern(String),
+
+    // Jar being instrumented
+    Jar(String),
+}
+
+impl From<VerifyError> for Error {
+    fn from(error: VerifyError) -> Self {
+        Error::Verify(Box::new(error))
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(error: zip::result::ZipError) -> Self {
+        Error::Jar(error.to_string())
+    }
+}
+
+impl From<classfile::error::Error> for Error {
+    fn from(error: classfile::error::Error) -> Self {
+        Error::ClassFile(format!("{:?}", error))
+    }
+}
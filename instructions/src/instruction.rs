@@ -0,0 +1,249 @@
+use crate::opcode::OpCode;
+use std::fmt::{self, Display, Formatter};
+
+/// An instruction with its operands. Branch targets are absolute offsets into
+/// the code array when decoded, so an instruction doesn't depend on where it
+/// sits, or labels when handed to the assembler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction<T = u32> {
+    pub opcode: OpCode,
+    pub operand: Operand<T>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand<T = u32> {
+    None,
+    /// `bipush`
+    Byte(i8),
+    /// `sipush`
+    Short(i16),
+    /// Constant pool index of `ldc`, `ldc_w`, `ldc2_w`, field and method
+    /// instructions, `invokedynamic`, `new`, `anewarray`, `checkcast` and `instanceof`
+    Constant(u16),
+    /// Local variable index of the `<t>load`, `<t>store` and `ret` forms taking
+    /// an operand, `wide` when encoded behind the `wide` prefix
+    Local {
+        index: u16,
+        wide: bool,
+    },
+    Iinc {
+        index: u16,
+        constant: i16,
+        wide: bool,
+    },
+    /// Target of `if<cond>`, `goto`, `jsr` and their wide forms
+    Branch(T),
+    TableSwitch {
+        default: T,
+        low: i32,
+        high: i32,
+        /// One target per key from `low` to `high`
+        targets: Vec<T>,
+    },
+    LookupSwitch {
+        default: T,
+        /// Sorted by key
+        pairs: Vec<(i32, T)>,
+    },
+    InvokeInterface {
+        index: u16,
+        count: u8,
+    },
+    NewArray(ArrayType),
+    MultiANewArray {
+        index: u16,
+        dimensions: u8,
+    },
+}
+
+/// `atype` operand of `newarray`
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArrayType {
+    Boolean = 4,
+    Char = 5,
+    Float = 6,
+    Double = 7,
+    Byte = 8,
+    Short = 9,
+    Int = 10,
+    Long = 11,
+}
+
+impl ArrayType {
+    pub fn from_atype(atype: u8) -> Option<ArrayType> {
+        match atype {
+            4 => Some(ArrayType::Boolean),
+            5 => Some(ArrayType::Char),
+            6 => Some(ArrayType::Float),
+            7 => Some(ArrayType::Double),
+            8 => Some(ArrayType::Byte),
+            9 => Some(ArrayType::Short),
+            10 => Some(ArrayType::Int),
+            11 => Some(ArrayType::Long),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArrayType::Boolean => "boolean",
+            ArrayType::Char => "char",
+            ArrayType::Float => "float",
+            ArrayType::Double => "double",
+            ArrayType::Byte => "byte",
+            ArrayType::Short => "short",
+            ArrayType::Int => "int",
+            ArrayType::Long => "long",
+        }
+    }
+}
+
+impl<T> Instruction<T> {
+    pub fn new(opcode: OpCode, operand: Operand<T>) -> Instruction<T> {
+        Instruction { opcode, operand }
+    }
+
+    /// Local variable read or written, including the implicit index of forms
+    /// like `iload_2`
+    pub fn local_index(&self) -> Option<u16> {
+        match self.operand {
+            Operand::Local { index, .. } | Operand::Iinc { index, .. } => Some(index),
+            _ => {
+                let opcode = self.opcode as u8;
+                match opcode {
+                    // iload_0 to aload_3
+                    0x1a..=0x2d => Some(((opcode - 0x1a) % 4) as u16),
+                    // istore_0 to astore_3
+                    0x3b..=0x4e => Some(((opcode - 0x3b) % 4) as u16),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Every branch target, the default of a switch first
+    pub fn targets(&self) -> Vec<&T> {
+        match &self.operand {
+            Operand::Branch(target) => vec![target],
+            Operand::TableSwitch {
+                default, targets, ..
+            } => {
+                let mut all = vec![default];
+                all.extend(targets);
+                all
+            }
+            Operand::LookupSwitch { default, pairs } => {
+                let mut all = vec![default];
+                all.extend(pairs.iter().map(|(_, target)| target));
+                all
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Rewrites every branch target, e.g. offsets into labels and back
+    pub fn map_targets<U, E>(
+        self,
+        mut f: impl FnMut(T) -> Result<U, E>,
+    ) -> Result<Instruction<U>, E> {
+        let operand = match self.operand {
+            Operand::None => Operand::None,
+            Operand::Byte(value) => Operand::Byte(value),
+            Operand::Short(value) => Operand::Short(value),
+            Operand::Constant(index) => Operand::Constant(index),
+            Operand::Local { index, wide } => Operand::Local { index, wide },
+            Operand::Iinc {
+                index,
+                constant,
+                wide,
+            } => Operand::Iinc {
+                index,
+                constant,
+                wide,
+            },
+            Operand::Branch(target) => Operand::Branch(f(target)?),
+            Operand::TableSwitch {
+                default,
+                low,
+                high,
+                targets,
+            } => Operand::TableSwitch {
+                default: f(default)?,
+                low,
+                high,
+                targets: targets.into_iter().map(&mut f).collect::<Result<_, _>>()?,
+            },
+            Operand::LookupSwitch { default, pairs } => Operand::LookupSwitch {
+                default: f(default)?,
+                pairs: pairs
+                    .into_iter()
+                    .map(|(key, target)| Ok((key, f(target)?)))
+                    .collect::<Result<_, _>>()?,
+            },
+            Operand::InvokeInterface { index, count } => Operand::InvokeInterface { index, count },
+            Operand::NewArray(array_type) => Operand::NewArray(array_type),
+            Operand::MultiANewArray { index, dimensions } => {
+                Operand::MultiANewArray { index, dimensions }
+            }
+        };
+        Ok(Instruction::new(self.opcode, operand))
+    }
+}
+
+impl<T: Display> Display for Instruction<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = self.opcode.name();
+        match &self.operand {
+            Operand::None => write!(f, "{}", name),
+            Operand::Byte(value) => write!(f, "{} {}", name, value),
+            Operand::Short(value) => write!(f, "{} {}", name, value),
+            Operand::Constant(index) => write!(f, "{} #{}", name, index),
+            Operand::Local { index, wide } => {
+                if *wide {
+                    write!(f, "wide {} {}", name, index)
+                } else {
+                    write!(f, "{} {}", name, index)
+                }
+            }
+            Operand::Iinc {
+                index,
+                constant,
+                wide,
+            } => {
+                if *wide {
+                    write!(f, "wide {} {}, {}", name, index, constant)
+                } else {
+                    write!(f, "{} {}, {}", name, index, constant)
+                }
+            }
+            Operand::Branch(target) => write!(f, "{} {}", name, target),
+            Operand::TableSwitch {
+                default,
+                low,
+                targets,
+                ..
+            } => {
+                write!(f, "{} {{ ", name)?;
+                for (key, target) in (*low..).zip(targets) {
+                    write!(f, "{}: {}, ", key, target)?;
+                }
+                write!(f, "default: {} }}", default)
+            }
+            Operand::LookupSwitch { default, pairs } => {
+                write!(f, "{} {{ ", name)?;
+                for (key, target) in pairs {
+                    write!(f, "{}: {}, ", key, target)?;
+                }
+                write!(f, "default: {} }}", default)
+            }
+            Operand::InvokeInterface { index, count } => {
+                write!(f, "{} #{}, {}", name, index, count)
+            }
+            Operand::NewArray(array_type) => write!(f, "{} {}", name, array_type.name()),
+            Operand::MultiANewArray { index, dimensions } => {
+                write!(f, "{} #{}, {}", name, index, dimensions)
+            }
+        }
+    }
+}
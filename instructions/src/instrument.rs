@@ -0,0 +1,535 @@
+//! Instrumentation of methods with calls to probes on entry, before every
+//! return and when an exception is thrown out of the method, as an agent
+//! tracing or profiling calls would add them while classes are loaded.
+
+use crate::assembler::{Item, Label};
+use crate::constant_pool::Pool;
+use crate::decoder::decode;
+use crate::error::Error;
+use crate::instruction::{Instruction, Operand};
+use crate::limits::compute_max_stack;
+use crate::metadata::Flow;
+use crate::opcode::OpCode;
+use crate::optimizer::{items, push_int, rebuild};
+use crate::verifier::stack_map::add_stack_maps;
+use crate::verifier::type_inference::infer_frames;
+use crate::verifier::{methods, ClassHierarchy, Frame, MethodContext, TYPE_CHECKING_VERSION};
+use classfile::attribute::{Attribute, AttributeType, CodeAttribute, Exception};
+use classfile::class_file::ClassFile;
+use classfile::constant::Constant;
+use classfile::constant_pool::ConstantPoolBuilder;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{Read, Seek, Write};
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// Descriptor of the entry and exit probes, given the id of the method
+pub const PROBE_DESCRIPTOR: &str = "(I)V";
+
+/// Descriptor of the exception probe, given the exception and the id of the
+/// method
+pub const THROW_PROBE_DESCRIPTOR: &str = "(Ljava/lang/Throwable;I)V";
+
+/// Static methods of `class` the instrumented code calls
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probes {
+    /// Internal name of the class declaring the probes, which is never
+    /// instrumented itself
+    pub class: String,
+    /// Called on entry, `PROBE_DESCRIPTOR`
+    pub enter: String,
+    /// Called before every return, `PROBE_DESCRIPTOR`
+    pub exit: String,
+    /// Called with the exception about to be thrown out of the method, which
+    /// is rethrown once it returns, `THROW_PROBE_DESCRIPTOR`
+    pub throw: String,
+}
+
+/// Methods given an id by `instrument_class`, counting from 0 in the order
+/// they were instrumented
+#[derive(Debug, Clone, Default)]
+pub struct MethodIds {
+    methods: Vec<(String, String, String)>,
+}
+
+impl MethodIds {
+    pub fn new() -> MethodIds {
+        MethodIds::default()
+    }
+
+    /// Class, name and descriptor of the method with `id`
+    pub fn get(&self, id: i32) -> Option<(&str, &str, &str)> {
+        let (class, name, descriptor) = self.methods.get(usize::try_from(id).ok()?)?;
+        Some((class, name, descriptor))
+    }
+
+    pub fn len(&self) -> usize {
+        self.methods.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.methods.is_empty()
+    }
+
+    fn add(&mut self, class: &str, name: &str, descriptor: &str) -> i32 {
+        self.methods
+            .push((class.to_string(), name.to_string(), descriptor.to_string()));
+        self.methods.len() as i32 - 1
+    }
+}
+
+/// Adds calls to `probes` to every method with code of `class_file`, each
+/// passing the id the method is given in `ids`.
+///
+/// The enter probe is called before the first instruction, so a branch back
+/// to it doesn't call it again, and the exit probe right before every return.
+/// A handler catching any exception thrown out of the original code calls the
+/// throw probe and rethrows the exception. In a constructor, the code running
+/// before `this` is initialized gets a handler of its own, as a handler can't
+/// be reached with `this` both initialized and not. The constructor call
+/// initializing it can't be covered by a handler HotSpot accepts, an exception
+/// thrown there only reaches the probes of the constructor called.
+///
+/// `max_stack` grows for the probe arguments, and a class file of version 50
+/// and above gets new stack maps, computed with `hierarchy`. Other attributes
+/// of the code are moved along as `optimize` does. The code must pass
+/// verification by type inference.
+pub fn instrument_class(
+    class_file: &ClassFile,
+    probes: &Probes,
+    ids: &mut MethodIds,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<ClassFile, Error> {
+    // the ids of a class that fails are given again
+    let count = ids.len();
+    let instrumented = instrument(class_file, probes, ids, hierarchy);
+    if instrumented.is_err() {
+        ids.methods.truncate(count);
+    }
+    instrumented
+}
+
+fn instrument(
+    class_file: &ClassFile,
+    probes: &Probes,
+    ids: &mut MethodIds,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<ClassFile, Error> {
+    let names = Pool(&class_file.constant_pool);
+    let class = names.class_name(class_file.this_class)?;
+    if class == probes.class {
+        return Ok(class_file.clone());
+    }
+
+    let mut pool = ConstantPoolBuilder::from_constant_pool(&class_file.constant_pool)?;
+    let calls = Calls {
+        enter: pool.method_ref(&probes.class, &probes.enter, PROBE_DESCRIPTOR)?,
+        exit: pool.method_ref(&probes.class, &probes.exit, PROBE_DESCRIPTOR)?,
+        throw: pool.method_ref(&probes.class, &probes.throw, THROW_PROBE_DESCRIPTOR)?,
+    };
+    let mut instrumented = class_file.clone();
+    // methods lists the methods with code in order
+    let mut contexts = methods(class_file, hierarchy)?.into_iter();
+    for method in &mut instrumented.methods {
+        let index = match method.get_code_attr() {
+            Some(_) => method.code_attr_index.unwrap(),
+            None => continue,
+        };
+        let context = contexts.next().unwrap();
+        let name = names.utf8(method.name_index)?;
+        let descriptor = names.utf8(method.descriptor_index)?;
+        let states = if name == "<init>" {
+            this_states(&context)?
+        } else {
+            BTreeMap::new()
+        };
+        let id = ids.add(&class, &name, &descriptor);
+        let code = probe(
+            method.get_code_attr().unwrap(),
+            id,
+            &calls,
+            &states,
+            &mut pool,
+        )?;
+        let name_index = method.attributes[index].attribute_name_index;
+        method.attributes[index] = Attribute::new(name_index, AttributeType::Code { code })?;
+    }
+    instrumented.constant_pool = pool.build();
+
+    // the probe arguments go on top of what the code leaves on the stack
+    let constant_pool = instrumented.constant_pool.clone();
+    for method in &mut instrumented.methods {
+        let index = match method.get_code_attr() {
+            Some(_) => method.code_attr_index.unwrap(),
+            None => continue,
+        };
+        let mut code = method.get_code_attr().unwrap().clone();
+        code.max_stack = compute_max_stack(&code, &constant_pool)?;
+        let name_index = method.attributes[index].attribute_name_index;
+        method.attributes[index] = Attribute::new(name_index, AttributeType::Code { code })?;
+    }
+    if instrumented.major_version < TYPE_CHECKING_VERSION {
+        return Ok(instrumented);
+    }
+    add_stack_maps(&instrumented, hierarchy)
+}
+
+/// Copies the jar read from `input` to `output` with every class instrumented
+/// by `instrument_class`. Other entries and `module-info.class` are copied as
+/// they are, as is a class that can't be read or instrumented, e.g. one of a
+/// version too recent, as a multi-release jar may hold in `META-INF/versions`,
+/// or one using classes `hierarchy` doesn't know. The signatures of a signed
+/// jar no longer match.
+///
+/// Returns `output` and the name of every class left as it was, with the
+/// error it failed with.
+pub fn instrument_jar<R: Read + Seek, W: Write + Seek>(
+    input: R,
+    output: W,
+    probes: &Probes,
+    ids: &mut MethodIds,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<(W, Vec<(String, Error)>), Error> {
+    let mut archive = ZipArchive::new(input)?;
+    let mut writer = ZipWriter::new(output);
+    let mut skipped = vec![];
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let name = file.name().to_string();
+        if !name.ends_with(".class") || name.ends_with("module-info.class") {
+            writer.raw_copy_file(file)?;
+            continue;
+        }
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data).map_err(ZipError::Io)?;
+        let instrumented = ClassFile::read_from(&data[..])
+            .map_err(Error::from)
+            .and_then(|class_file| instrument_class(&class_file, probes, ids, hierarchy));
+        match instrumented {
+            Ok(instrumented) => {
+                data.clear();
+                instrumented.write_to(&mut data)?;
+            }
+            Err(error) => skipped.push((name.clone(), error)),
+        }
+        let options = FileOptions::default()
+            .compression_method(file.compression())
+            .last_modified_time(file.last_modified());
+        writer.start_file(name, options)?;
+        writer.write_all(&data).map_err(ZipError::Io)?;
+    }
+    Ok((writer.finish()?, skipped))
+}
+
+/// Constant pool indices of the probes
+struct Calls {
+    enter: u16,
+    exit: u16,
+    throw: u16,
+}
+
+/// State of `this` at an instruction of a constructor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum This {
+    Initialized,
+    Uninitialized,
+    /// The call of the superclass constructor, or of another constructor of
+    /// the class, initializing it
+    Initializing,
+}
+
+/// State of `this` at each instruction of a constructor reached
+fn this_states(method: &MethodContext) -> Result<BTreeMap<u32, This>, Error> {
+    let frames = infer_frames(method)?;
+    let uninitialized = |pc| {
+        frames
+            .get(&pc)
+            .map(|frame: &Frame| frame.this_uninitialized())
+    };
+    let instructions = decode(&method.code.code)?;
+    let mut states = BTreeMap::new();
+    for (index, (pc, instruction)) in instructions.iter().enumerate() {
+        let state = match uninitialized(*pc) {
+            None => continue,
+            Some(false) => This::Initialized,
+            Some(true)
+                if instruction.opcode == OpCode::invokespecial
+                    && instructions
+                        .get(index + 1)
+                        .and_then(|(next, _)| uninitialized(*next))
+                        == Some(false) =>
+            {
+                This::Initializing
+            }
+            Some(true) => This::Uninitialized,
+        };
+        states.insert(*pc, state);
+    }
+    Ok(states)
+}
+
+/// `code` with the probes of the method `id` added, `states` holding the state
+/// of `this` at the instructions of a constructor
+fn probe(
+    code: &CodeAttribute,
+    id: i32,
+    calls: &Calls,
+    states: &BTreeMap<u32, This>,
+    pool: &mut ConstantPoolBuilder,
+) -> Result<CodeAttribute, Error> {
+    let push_id = match push_int(id) {
+        Some(instruction) => instruction,
+        None => Instruction::new(
+            OpCode::ldc,
+            Operand::Constant(pool.add(Constant::Integer(id))?),
+        ),
+    };
+    let call = |index| {
+        Item::Instruction(Instruction::new(
+            OpCode::invokestatic,
+            Operand::Constant(index),
+        ))
+    };
+
+    let mut items = vec![Item::Instruction(push_id.clone()), call(calls.enter)];
+    for item in self::items(code)? {
+        if let Item::Instruction(instruction) = &item {
+            if instruction.opcode.info().flow == Flow::Return {
+                items.push(Item::Instruction(push_id.clone()));
+                items.push(call(calls.exit));
+            }
+        }
+        items.push(item);
+    }
+
+    // Ranges of the original code by the state of `this`. The labels of the
+    // handlers follow the offsets of the code, which rebuild maps the
+    // exception table through.
+    let length = code.code.len() as u32;
+    let mut ranges: Vec<(u32, This)> = vec![];
+    for (pc, _) in decode(&code.code)? {
+        let state = states.get(&pc).copied().unwrap_or(This::Initialized);
+        if ranges.last().map(|(_, last)| *last) != Some(state) {
+            ranges.push((pc, state));
+        }
+    }
+    let mut code = code.clone();
+    for (handler, state) in [
+        (length + 1, This::Initialized),
+        (length + 2, This::Uninitialized),
+    ] {
+        if !ranges.iter().any(|(_, kind)| *kind == state) {
+            continue;
+        }
+        let handler_pc = u16::try_from(handler).map_err(|_| Error::CodeTooLarge(handler))?;
+        for (index, (start, kind)) in ranges.iter().enumerate() {
+            if *kind != state {
+                continue;
+            }
+            let end = ranges.get(index + 1).map_or(length, |(next, _)| *next);
+            code.exception_table.push(Exception {
+                start_pc: *start as u16,
+                end_pc: end as u16,
+                handler_pc,
+                catch_type: 0,
+            });
+        }
+        items.push(Item::Label(Label(handler)));
+        items.push(Item::Instruction(Instruction::new(
+            OpCode::dup,
+            Operand::None,
+        )));
+        items.push(Item::Instruction(push_id.clone()));
+        items.push(call(calls.throw));
+        items.push(Item::Instruction(Instruction::new(
+            OpCode::athrow,
+            Operand::None,
+        )));
+    }
+    rebuild(&code, &items)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jasmin::parse;
+    use crate::search::Query;
+    use crate::verifier::test::Hierarchy;
+    use crate::verifier::verify;
+    use std::io::Cursor;
+
+    const COUNTER: &str = "
+.bytecode 52.0
+.class public Counter
+.super java/lang/Object
+.field private count I
+
+.method public <init>(Z)V
+    .limit stack 2
+    .limit locals 2
+    aload_0
+    iload_1
+    ifeq Plain
+    invokespecial java/lang/Object/<init>()V
+    goto Done
+Plain:
+    invokespecial java/lang/Object/<init>()V
+Done:
+    return
+.end method
+
+.method public next(I)I
+    .limit stack 3
+    .limit locals 2
+    .catch java/lang/RuntimeException from Start to End using Caught
+Start:
+    .line 7
+    aload_0
+    dup
+    getfield Counter/count I
+    iload_1
+    idiv
+    putfield Counter/count I
+End:
+    aload_0
+    getfield Counter/count I
+    ifge Positive
+    iconst_m1
+    ireturn
+Positive:
+    .line 8
+    aload_0
+    getfield Counter/count I
+    ireturn
+Caught:
+    pop
+    iconst_0
+    ireturn
+.end method
+
+.method public abstract size()I
+.end method
+";
+
+    fn probes() -> Probes {
+        Probes {
+            class: "Probe".to_string(),
+            enter: "enter".to_string(),
+            exit: "exit".to_string(),
+            throw: "thrown".to_string(),
+        }
+    }
+
+    fn count(class_file: &ClassFile, query: &str) -> Vec<(String, u32)> {
+        Query::parse(query)
+            .unwrap()
+            .search(class_file)
+            .unwrap()
+            .into_iter()
+            .map(|found| (found.method, found.pc))
+            .collect()
+    }
+
+    #[test]
+    fn instrument() {
+        let class_file = parse(COUNTER).unwrap();
+        let mut ids = MethodIds::new();
+        let instrumented = instrument_class(&class_file, &probes(), &mut ids, &Hierarchy).unwrap();
+        verify(&instrumented, &Hierarchy).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids.get(1), Some(("Counter", "next", "(I)I")));
+        assert_eq!(ids.get(2), None);
+
+        assert_eq!(
+            count(&instrumented, "iconst_*; invokestatic Probe.enter:(I)V"),
+            [("<init>".to_string(), 0), ("next".to_string(), 0)]
+        );
+        assert_eq!(
+            count(&instrumented, "iconst_1; invokestatic Probe.exit; ireturn").len(),
+            3
+        );
+        assert_eq!(
+            count(
+                &instrumented,
+                "dup; iconst_*; invokestatic Probe.thrown; athrow"
+            )
+            .len(),
+            3
+        );
+
+        // the constructor has a handler for each state of `this` and none
+        // covering the calls initializing it, the original handler of next
+        // keeps precedence over the probe's
+        let methods = &instrumented.methods;
+        let table = &methods[0].get_code_attr().unwrap().exception_table;
+        assert_eq!(table.len(), 3);
+        assert_eq!(table[0].handler_pc, table[1].handler_pc);
+        assert_ne!(table[1].handler_pc, table[2].handler_pc);
+        let code = methods[1].get_code_attr().unwrap();
+        assert_eq!(code.exception_table.len(), 2);
+        assert_eq!(code.exception_table[1].catch_type, 0);
+        assert_eq!(code.exception_table[1].start_pc, 4);
+        assert_eq!(code.line_number(4), Some(7));
+
+        let again = instrument_class(&instrumented, &probes(), &mut ids, &Hierarchy).unwrap();
+        verify(&again, &Hierarchy).unwrap();
+        assert_eq!(ids.len(), 4);
+    }
+
+    #[test]
+    fn jar() {
+        let class_file = parse(COUNTER).unwrap();
+        let mut class = vec![];
+        class_file.write_to(&mut class).unwrap();
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        writer
+            .start_file("META-INF/MANIFEST.MF", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"Manifest-Version: 1.0\n").unwrap();
+        writer
+            .start_file("Counter.class", FileOptions::default())
+            .unwrap();
+        writer.write_all(&class).unwrap();
+        // the ids its first method was given are taken back when the second
+        // fails to verify
+        let broken = parse(
+            ".bytecode 52.0\n.class Broken\n.super java/lang/Object\n.method static ok()V\n    .limit stack 0\n    .limit locals 0\n    return\n.end method\n.method static bad()I\n    .limit stack 2\n    .limit locals 0\n    iconst_0\n    fconst_0\n    iadd\n    ireturn\n.end method\n",
+        )
+        .unwrap();
+        let mut class = vec![];
+        broken.write_to(&mut class).unwrap();
+        writer
+            .start_file("Broken.class", FileOptions::default())
+            .unwrap();
+        writer.write_all(&class).unwrap();
+        let jar = writer.finish().unwrap();
+
+        let mut ids = MethodIds::new();
+        let output = Cursor::new(vec![]);
+        let (output, skipped) =
+            instrument_jar(jar, output, &probes(), &mut ids, &Hierarchy).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, "Broken.class");
+        let mut archive = ZipArchive::new(output).unwrap();
+        let mut manifest = String::new();
+        archive
+            .by_name("META-INF/MANIFEST.MF")
+            .unwrap()
+            .read_to_string(&mut manifest)
+            .unwrap();
+        assert_eq!(manifest, "Manifest-Version: 1.0\n");
+        let mut copied = vec![];
+        archive
+            .by_name("Broken.class")
+            .unwrap()
+            .read_to_end(&mut copied)
+            .unwrap();
+        assert_eq!(copied, class);
+        let instrumented = ClassFile::read_from(archive.by_name("Counter.class").unwrap()).unwrap();
+        verify(&instrumented, &Hierarchy).unwrap();
+        assert_eq!(count(&instrumented, "invokestatic Probe.enter").len(), 2);
+    }
+}
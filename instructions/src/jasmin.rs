@@ -0,0 +1,1325 @@
+use crate::assembler::{Assembler, Label};
+use crate::decoder::decode;
+use crate::error::Error;
+use crate::instruction::{ArrayType, Instruction, Operand};
+use crate::opcode::OpCode;
+use classfile::access_flags::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
+use classfile::attribute::{
+    Attribute, AttributeType, CodeAttribute, Exception, LineNumber, LocalVariable,
+};
+use classfile::class_file::ClassFile;
+use classfile::constant::Constant;
+use classfile::constant_pool::ConstantPoolBuilder;
+use classfile::field::FieldInfo;
+use classfile::method::MethodInfo;
+use classfile::{ConstantPoolRef, MAGIC};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::ops::{BitOr, Range};
+use std::str::FromStr;
+use std::sync::Arc;
+
+const CLASS_FLAGS: &[(&str, ClassAccessFlags)] = &[
+    ("public", ClassAccessFlags::ACC_PUBLIC),
+    ("final", ClassAccessFlags::ACC_FINAL),
+    ("super", ClassAccessFlags::ACC_SUPER),
+    ("interface", ClassAccessFlags::ACC_INTERFACE),
+    ("abstract", ClassAccessFlags::ACC_ABSTRACT),
+    ("synthetic", ClassAccessFlags::ACC_SYNTHETIC),
+    ("annotation", ClassAccessFlags::ACC_ANNOTATION),
+    ("enum", ClassAccessFlags::ACC_ENUM),
+    ("module", ClassAccessFlags::ACC_MODULE),
+];
+
+const FIELD_FLAGS: &[(&str, FieldAccessFlags)] = &[
+    ("public", FieldAccessFlags::ACC_PUBLIC),
+    ("private", FieldAccessFlags::ACC_PRIVATE),
+    ("protected", FieldAccessFlags::ACC_PROTECTED),
+    ("static", FieldAccessFlags::ACC_STATIC),
+    ("final", FieldAccessFlags::ACC_FINAL),
+    ("volatile", FieldAccessFlags::ACC_VOLATILE),
+    ("transient", FieldAccessFlags::ACC_TRANSIENT),
+    ("synthetic", FieldAccessFlags::ACC_SYNTHETIC),
+    ("enum", FieldAccessFlags::ACC_ENUM),
+];
+
+const METHOD_FLAGS: &[(&str, MethodAccessFlags)] = &[
+    ("public", MethodAccessFlags::ACC_PUBLIC),
+    ("private", MethodAccessFlags::ACC_PRIVATE),
+    ("protected", MethodAccessFlags::ACC_PROTECTED),
+    ("static", MethodAccessFlags::ACC_STATIC),
+    ("final", MethodAccessFlags::ACC_FINAL),
+    ("synchronized", MethodAccessFlags::ACC_SYNCHRONIZED),
+    ("bridge", MethodAccessFlags::ACC_BRIDGE),
+    ("varargs", MethodAccessFlags::ACC_VARARGS),
+    ("native", MethodAccessFlags::ACC_NATIVE),
+    ("abstract", MethodAccessFlags::ACC_ABSTRACT),
+    ("strict", MethodAccessFlags::ACC_STRICT),
+    ("synthetic", MethodAccessFlags::ACC_SYNTHETIC),
+];
+
+/// Parses a class written in Jasmin syntax.
+///
+/// ```text
+/// .bytecode 49.0
+/// .source Hello.java
+/// .class public super Hello
+/// .super java/lang/Object
+///
+/// .method public static main([Ljava/lang/String;)V
+///     .limit stack 2
+///     .limit locals 1
+///     getstatic java/lang/System/out Ljava/io/PrintStream;
+///     ldc "Hello"
+///     invokevirtual java/io/PrintStream/println(Ljava/lang/String;)V
+///     return
+/// .end method
+/// ```
+///
+/// Besides the directives of Jasmin, `.bytecode` sets the class file version
+/// (45.3 by default), `.line` adds a `LineNumberTable` entry, `super` is a class
+/// modifier of its own and `invokestatic interface` calls a static interface
+/// method. Instructions go through `assembler::assemble`, so the shortest
+/// encoding is chosen whatever mnemonic is written and `wide` never is.
+pub fn parse(source: &str) -> Result<ClassFile, Error> {
+    let mut lines: Vec<Line> = vec![];
+    for (i, text) in source.lines().enumerate() {
+        let tokens = tokenize(i + 1, text)?;
+        if !tokens.is_empty() {
+            lines.push(Line {
+                number: i + 1,
+                tokens,
+            });
+        }
+    }
+    lines.reverse();
+    Parser {
+        lines,
+        pool: ConstantPoolBuilder::new(),
+    }
+    .class_file()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+}
+
+impl Token {
+    fn text(&self) -> &str {
+        match self {
+            Token::Word(word) => word,
+            Token::Quoted(string) => string,
+        }
+    }
+}
+
+/// Splits a line at whitespace, a `;` starting a token begins a comment
+fn tokenize(number: usize, text: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        match chars.peek() {
+            None | Some(';') => break,
+            Some('"') => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    let c = chars
+                        .next()
+                        .ok_or_else(|| Error::Syntax(number, "unterminated string".to_string()))?;
+                    let c = match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('r') => '\r',
+                            Some('b') => '\u{8}',
+                            Some('f') => '\u{c}',
+                            Some(c @ '"') | Some(c @ '\'') | Some(c @ '\\') => c,
+                            Some('u') => {
+                                let hex: String = chars.by_ref().take(4).collect();
+                                u32::from_str_radix(&hex, 16)
+                                    .ok()
+                                    .and_then(std::char::from_u32)
+                                    .ok_or_else(|| {
+                                        Error::Syntax(number, format!("invalid escape \\u{}", hex))
+                                    })?
+                            }
+                            c => {
+                                return Err(Error::Syntax(
+                                    number,
+                                    format!("invalid escape {:?}", c),
+                                ))
+                            }
+                        },
+                        c => c,
+                    };
+                    string.push(c);
+                }
+                tokens.push(Token::Quoted(string));
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    word.push(*c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Line {
+    number: usize,
+    tokens: Vec<Token>,
+}
+
+impl Line {
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::Syntax(self.number, message.into())
+    }
+
+    fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    fn word(&self, i: usize) -> Result<&str, Error> {
+        match self.tokens.get(i) {
+            Some(Token::Word(word)) => Ok(word),
+            Some(Token::Quoted(string)) => Err(self.error(format!("unexpected \"{}\"", string))),
+            None => Err(self.error("missing operand")),
+        }
+    }
+
+    /// A name or a quoted string
+    fn text(&self, i: usize) -> Result<&str, Error> {
+        self.tokens
+            .get(i)
+            .map(Token::text)
+            .ok_or_else(|| self.error("missing operand"))
+    }
+
+    fn number<T: FromStr>(&self, i: usize) -> Result<T, Error> {
+        let word = self.word(i)?;
+        word.parse()
+            .map_err(|_| self.error(format!("invalid number {}", word)))
+    }
+
+    /// Checks that nothing follows the first `len` tokens
+    fn expect_len(&self, len: usize) -> Result<(), Error> {
+        match self.tokens.get(len) {
+            Some(token) => Err(self.error(format!("unexpected {}", token.text()))),
+            None if self.len() < len => Err(self.error("missing operand")),
+            None => Ok(()),
+        }
+    }
+
+    fn flags<F: Copy + BitOr<Output = F>>(
+        &self,
+        range: Range<usize>,
+        table: &[(&str, F)],
+        mut flags: F,
+    ) -> Result<F, Error> {
+        for i in range {
+            let word = self.word(i)?;
+            let (_, flag) = table
+                .iter()
+                .find(|(name, _)| *name == word)
+                .ok_or_else(|| self.error(format!("unknown modifier {}", word)))?;
+            flags = flags | *flag;
+        }
+        Ok(flags)
+    }
+
+    /// `<key> : <label>` of a switch, the key is `None` for a bare label
+    fn case(&self) -> Result<(Option<String>, String), Error> {
+        let text: Vec<&str> = self.tokens.iter().map(Token::text).collect();
+        let text = text.join(" ");
+        match text.find(':') {
+            Some(colon) => {
+                let key = text[..colon].trim();
+                let label = text[colon + 1..].trim();
+                if key.is_empty() || label.is_empty() || label.contains(char::is_whitespace) {
+                    return Err(self.error("expected <key> : <label>"));
+                }
+                Ok((Some(key.to_string()), label.to_string()))
+            }
+            None => {
+                self.expect_len(1)?;
+                Ok((None, self.word(0)?.to_string()))
+            }
+        }
+    }
+}
+
+/// Everything collected from a method body before it can be assembled
+#[derive(Default)]
+struct MethodBody {
+    assembler: Assembler,
+    labels: HashMap<String, Label>,
+    bound: HashSet<String>,
+    has_code: bool,
+    max_stack: u16,
+    max_locals: u16,
+    /// catch type, start, end and handler
+    catches: Vec<(Option<String>, Label, Label, Label)>,
+    line_numbers: Vec<(Label, u16)>,
+    /// index, name, descriptor, start and end
+    variables: Vec<(u16, String, String, Label, Label)>,
+    throws: Vec<String>,
+}
+
+impl MethodBody {
+    fn label(&mut self, name: &str) -> Label {
+        if let Some(label) = self.labels.get(name) {
+            return *label;
+        }
+        let label = self.assembler.new_label();
+        self.labels.insert(name.to_string(), label);
+        label
+    }
+
+    fn emit(&mut self, opcode: OpCode, operand: Operand<Label>) {
+        self.assembler.emit(opcode, operand);
+        self.has_code = true;
+    }
+}
+
+struct Parser {
+    /// Remaining lines, last to first
+    lines: Vec<Line>,
+    pool: ConstantPoolBuilder,
+}
+
+impl Parser {
+    fn class_file(mut self) -> Result<ClassFile, Error> {
+        let (mut major_version, mut minor_version) = (45, 3);
+        let mut class: Option<(ClassAccessFlags, String)> = None;
+        let mut super_class: Option<String> = None;
+        let mut source_file: Option<String> = None;
+        let mut interfaces: Vec<u16> = vec![];
+        let mut fields: Vec<FieldInfo> = vec![];
+        let mut methods: Vec<MethodInfo> = vec![];
+        while let Some(line) = self.lines.pop() {
+            match line.word(0)? {
+                ".bytecode" => {
+                    line.expect_len(2)?;
+                    let version = line.word(1)?;
+                    let mut parts = version.splitn(2, '.');
+                    let major = parts.next().and_then(|major| major.parse().ok());
+                    let minor = parts.next().unwrap_or("0").parse().ok();
+                    match (major, minor) {
+                        (Some(major), Some(minor)) => {
+                            major_version = major;
+                            minor_version = minor;
+                        }
+                        _ => return Err(line.error(format!("invalid version {}", version))),
+                    }
+                }
+                ".source" => {
+                    line.expect_len(2)?;
+                    source_file = Some(line.text(1)?.to_string());
+                }
+                directive @ ".class" | directive @ ".interface" => {
+                    if class.is_some() {
+                        return Err(line.error("class declared twice"));
+                    }
+                    let name = line.word(line.len().max(2) - 1)?;
+                    let mut flags =
+                        line.flags(1..line.len() - 1, CLASS_FLAGS, ClassAccessFlags::empty())?;
+                    if directive == ".interface" {
+                        flags |= ClassAccessFlags::ACC_INTERFACE | ClassAccessFlags::ACC_ABSTRACT;
+                    }
+                    class = Some((flags, name.to_string()));
+                }
+                ".super" => {
+                    line.expect_len(2)?;
+                    super_class = Some(line.word(1)?.to_string());
+                }
+                ".implements" => {
+                    line.expect_len(2)?;
+                    interfaces.push(self.pool.class(line.word(1)?)?);
+                }
+                ".field" => fields.push(self.field(&line)?),
+                ".method" => methods.push(self.method(&line)?),
+                directive => return Err(line.error(format!("unexpected {}", directive))),
+            }
+        }
+        let (flags, name) = class.ok_or_else(|| Error::Syntax(0, "missing .class".to_string()))?;
+        let this_class = self.pool.class(&name)?;
+        let super_class = match super_class {
+            Some(super_class) => self.pool.class(&super_class)?,
+            None if name == "java/lang/Object" => 0,
+            None => return Err(Error::Syntax(0, "missing .super".to_string())),
+        };
+        let mut attributes: Vec<Attribute> = vec![];
+        if let Some(source_file) = source_file {
+            let sourcefile_index = self.pool.utf8(&source_file)?;
+            attributes.push(Attribute::new(
+                self.pool.utf8("SourceFile")?,
+                AttributeType::SourceFile { sourcefile_index },
+            )?);
+        }
+        Ok(ClassFile {
+            magic: MAGIC,
+            minor_version,
+            major_version,
+            constant_pool: self.pool.build(),
+            access_flags: flags.bits(),
+            this_class,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            attributes,
+        })
+    }
+
+    /// `.field <modifiers> <name> <descriptor> [= <value>]`
+    fn field(&mut self, line: &Line) -> Result<FieldInfo, Error> {
+        let end = line
+            .tokens
+            .iter()
+            .position(|token| *token == Token::Word("=".to_string()))
+            .unwrap_or_else(|| line.len());
+        if end < 3 {
+            return Err(line.error("expected .field <modifiers> <name> <descriptor>"));
+        }
+        let flags = line.flags(1..end - 2, FIELD_FLAGS, FieldAccessFlags::empty())?;
+        let name = line.word(end - 2)?;
+        let descriptor = line.word(end - 1)?;
+        let mut attributes: Vec<Attribute> = vec![];
+        if end < line.len() {
+            line.expect_len(end + 2)?;
+            let value = end + 1;
+            let constant = match (descriptor, &line.tokens[value]) {
+                ("Ljava/lang/String;", Token::Quoted(string)) => {
+                    let string_index = self.pool.utf8(string)?;
+                    Constant::String { string_index }
+                }
+                ("I", _) | ("S", _) | ("C", _) | ("B", _) | ("Z", _) => {
+                    Constant::Integer(line.number(value)?)
+                }
+                ("J", _) => Constant::Long(line.number(value)?),
+                ("F", _) => Constant::Float(parse_float(line, value)?),
+                ("D", _) => Constant::Double(parse_float(line, value)?),
+                _ => return Err(line.error(format!("no initial value for {}", descriptor))),
+            };
+            let constant_value_index = self.pool.add(constant)?;
+            attributes.push(Attribute::new(
+                self.pool.utf8("ConstantValue")?,
+                AttributeType::ConstantValue {
+                    constant_value_index,
+                },
+            )?);
+        }
+        Ok(FieldInfo {
+            access_flags: flags.bits(),
+            name_index: self.pool.utf8(name)?,
+            descriptor_index: self.pool.utf8(descriptor)?,
+            attributes,
+        })
+    }
+
+    /// `.method <modifiers> <name><descriptor>` up to `.end method`
+    fn method(&mut self, header: &Line) -> Result<MethodInfo, Error> {
+        if header.len() < 2 {
+            return Err(header.error("expected .method <modifiers> <name><descriptor>"));
+        }
+        let spec = header.word(header.len() - 1)?;
+        let paren = spec
+            .find('(')
+            .ok_or_else(|| header.error(format!("missing descriptor in {}", spec)))?;
+        let (name, descriptor) = spec.split_at(paren);
+        let flags = header.flags(
+            1..header.len() - 1,
+            METHOD_FLAGS,
+            MethodAccessFlags::empty(),
+        )?;
+        let mut body = MethodBody::default();
+        loop {
+            let line = self
+                .lines
+                .pop()
+                .ok_or_else(|| header.error("missing .end method"))?;
+            if line.word(0)? == ".end" {
+                line.expect_len(2)?;
+                if line.word(1)? != "method" {
+                    return Err(line.error("expected .end method"));
+                }
+                break;
+            }
+            self.method_line(&line, &mut body)?;
+        }
+
+        let mut attributes: Vec<Attribute> = vec![];
+        let throws = std::mem::take(&mut body.throws);
+        if body.has_code {
+            attributes.push(self.code(body)?);
+        }
+        if !throws.is_empty() {
+            let mut exception_index_table: Vec<u16> = vec![];
+            for class in &throws {
+                exception_index_table.push(self.pool.class(class)?);
+            }
+            attributes.push(Attribute::new(
+                self.pool.utf8("Exceptions")?,
+                AttributeType::Exceptions {
+                    exception_index_table,
+                },
+            )?);
+        }
+        Ok(MethodInfo::new(
+            flags.bits(),
+            self.pool.utf8(name)?,
+            self.pool.utf8(descriptor)?,
+            attributes,
+        ))
+    }
+
+    fn method_line(&mut self, line: &Line, body: &mut MethodBody) -> Result<(), Error> {
+        let first = line.word(0)?;
+        if first.len() > 1 && first.ends_with(':') {
+            let name = &first[..first.len() - 1];
+            if !body.bound.insert(name.to_string()) {
+                return Err(line.error(format!("label {} defined twice", name)));
+            }
+            let label = body.label(name);
+            body.assembler.bind(label);
+            body.has_code = true;
+            if line.len() > 1 {
+                self.instruction(line, 1, body)?;
+            }
+            return Ok(());
+        }
+        match first {
+            ".limit" => {
+                line.expect_len(3)?;
+                match line.word(1)? {
+                    "stack" => body.max_stack = line.number(2)?,
+                    "locals" => body.max_locals = line.number(2)?,
+                    limit => return Err(line.error(format!("unknown limit {}", limit))),
+                }
+                body.has_code = true;
+            }
+            ".throws" => {
+                line.expect_len(2)?;
+                body.throws.push(line.word(1)?.to_string());
+            }
+            ".catch" => {
+                line.expect_len(8)?;
+                if line.word(2)? != "from" || line.word(4)? != "to" || line.word(6)? != "using" {
+                    return Err(
+                        line.error("expected .catch <class> from <label> to <label> using <label>")
+                    );
+                }
+                let catch_type = match line.word(1)? {
+                    "all" => None,
+                    class => Some(class.to_string()),
+                };
+                let start = body.label(line.word(3)?);
+                let end = body.label(line.word(5)?);
+                let handler = body.label(line.word(7)?);
+                body.catches.push((catch_type, start, end, handler));
+            }
+            ".line" => {
+                line.expect_len(2)?;
+                let label = body.assembler.new_label();
+                body.assembler.bind(label);
+                body.line_numbers.push((label, line.number(1)?));
+            }
+            ".var" => {
+                line.expect_len(9)?;
+                if line.word(2)? != "is" || line.word(5)? != "from" || line.word(7)? != "to" {
+                    return Err(line.error(
+                        "expected .var <index> is <name> <descriptor> from <label> to <label>",
+                    ));
+                }
+                let index = line.number(1)?;
+                let start = body.label(line.word(6)?);
+                let end = body.label(line.word(8)?);
+                body.variables.push((
+                    index,
+                    line.word(3)?.to_string(),
+                    line.word(4)?.to_string(),
+                    start,
+                    end,
+                ));
+            }
+            directive if directive.starts_with('.') => {
+                return Err(line.error(format!("unexpected {}", directive)))
+            }
+            _ => self.instruction(line, 0, body)?,
+        }
+        Ok(())
+    }
+
+    /// The instruction whose mnemonic is token `at` of `line`
+    fn instruction(&mut self, line: &Line, at: usize, body: &mut MethodBody) -> Result<(), Error> {
+        let name = line.word(at)?;
+        let opcode = OpCode::from_name(name)
+            .ok_or_else(|| line.error(format!("unknown instruction {}", name)))?;
+        let operand = match opcode as u8 {
+            0x10 => {
+                line.expect_len(at + 2)?;
+                Operand::Byte(line.number(at + 1)?)
+            }
+            0x11 => {
+                line.expect_len(at + 2)?;
+                Operand::Short(line.number(at + 1)?)
+            }
+            // ldc, ldc_w, ldc2_w
+            0x12..=0x14 => {
+                line.expect_len(at + 2)?;
+                let wide = opcode == OpCode::ldc2_w;
+                let constant = match &line.tokens[at + 1] {
+                    Token::Quoted(string) if !wide => {
+                        let string_index = self.pool.utf8(string)?;
+                        Constant::String { string_index }
+                    }
+                    Token::Word(word) if is_float(word) => {
+                        if wide {
+                            Constant::Double(parse_float(line, at + 1)?)
+                        } else {
+                            Constant::Float(parse_float(line, at + 1)?)
+                        }
+                    }
+                    Token::Word(_) if wide => Constant::Long(line.number(at + 1)?),
+                    Token::Word(_) => Constant::Integer(line.number(at + 1)?),
+                    Token::Quoted(_) => return Err(line.error("ldc2_w takes a long or double")),
+                };
+                Operand::Constant(self.pool.add(constant)?)
+            }
+            // iload to aload, istore to astore, ret
+            0x15..=0x19 | 0x36..=0x3a | 0xa9 => {
+                line.expect_len(at + 2)?;
+                Operand::Local {
+                    index: line.number(at + 1)?,
+                    wide: false,
+                }
+            }
+            0x84 => {
+                line.expect_len(at + 3)?;
+                Operand::Iinc {
+                    index: line.number(at + 1)?,
+                    constant: line.number(at + 2)?,
+                    wide: false,
+                }
+            }
+            0x99..=0xa8 | 0xc6..=0xc9 => {
+                line.expect_len(at + 2)?;
+                Operand::Branch(body.label(line.word(at + 1)?))
+            }
+            0xaa => self.table_switch(line, at, body)?,
+            0xab => self.lookup_switch(line, at, body)?,
+            // getstatic to putfield
+            0xb2..=0xb5 => {
+                line.expect_len(at + 3)?;
+                let (class, name) = split_member(line, line.word(at + 1)?)?;
+                Operand::Constant(self.pool.field_ref(class, name, line.word(at + 2)?)?)
+            }
+            // invokevirtual, invokespecial, invokestatic
+            0xb6..=0xb8 => {
+                let interface = line.word(at + 1)? == "interface";
+                let spec = if interface { at + 2 } else { at + 1 };
+                line.expect_len(spec + 1)?;
+                let (class, name, descriptor) = split_method(line, line.word(spec)?)?;
+                let index = if interface {
+                    self.pool.interface_method_ref(class, name, descriptor)?
+                } else {
+                    self.pool.method_ref(class, name, descriptor)?
+                };
+                Operand::Constant(index)
+            }
+            0xb9 => {
+                line.expect_len(at + 3)?;
+                let (class, name, descriptor) = split_method(line, line.word(at + 1)?)?;
+                Operand::InvokeInterface {
+                    index: self.pool.interface_method_ref(class, name, descriptor)?,
+                    count: line.number(at + 2)?,
+                }
+            }
+            0xba => return Err(line.error("invokedynamic has no Jasmin syntax")),
+            // new, anewarray, checkcast, instanceof
+            0xbb | 0xbd | 0xc0 | 0xc1 => {
+                line.expect_len(at + 2)?;
+                Operand::Constant(self.pool.class(line.word(at + 1)?)?)
+            }
+            0xbc => {
+                line.expect_len(at + 2)?;
+                let atype = line.word(at + 1)?;
+                let array_type = (4..=11)
+                    .filter_map(ArrayType::from_atype)
+                    .find(|array_type| array_type.name() == atype)
+                    .ok_or_else(|| line.error(format!("invalid array type {}", atype)))?;
+                Operand::NewArray(array_type)
+            }
+            0xc4 => return Err(line.error("wide is chosen by the assembler")),
+            0xc5 => {
+                line.expect_len(at + 3)?;
+                Operand::MultiANewArray {
+                    index: self.pool.class(line.word(at + 1)?)?,
+                    dimensions: line.number(at + 2)?,
+                }
+            }
+            _ => {
+                line.expect_len(at + 1)?;
+                Operand::None
+            }
+        };
+        body.emit(opcode, operand);
+        Ok(())
+    }
+
+    /// `tableswitch <low> [<high>]`, then a label per key and `default : <label>`
+    fn table_switch(
+        &mut self,
+        line: &Line,
+        at: usize,
+        body: &mut MethodBody,
+    ) -> Result<Operand<Label>, Error> {
+        let low: i32 = line.number(at + 1)?;
+        let high: Option<i32> = match line.len() - at {
+            2 => None,
+            _ => {
+                line.expect_len(at + 3)?;
+                Some(line.number(at + 2)?)
+            }
+        };
+        let mut targets: Vec<Label> = vec![];
+        loop {
+            let case = self
+                .lines
+                .pop()
+                .ok_or_else(|| line.error("missing default of tableswitch"))?;
+            match case.case()? {
+                (None, label) => targets.push(body.label(&label)),
+                (Some(key), label) if key == "default" => {
+                    let high = high.unwrap_or(low + targets.len() as i32 - 1);
+                    if targets.is_empty() || high as i64 - low as i64 + 1 != targets.len() as i64 {
+                        return Err(case.error(format!(
+                            "{} targets for keys {} to {}",
+                            targets.len(),
+                            low,
+                            high
+                        )));
+                    }
+                    return Ok(Operand::TableSwitch {
+                        default: body.label(&label),
+                        low,
+                        high,
+                        targets,
+                    });
+                }
+                (Some(key), _) => {
+                    return Err(case.error(format!("unexpected {} in tableswitch", key)))
+                }
+            }
+        }
+    }
+
+    /// `lookupswitch`, then `<key> : <label>` per key and `default : <label>`
+    fn lookup_switch(
+        &mut self,
+        line: &Line,
+        at: usize,
+        body: &mut MethodBody,
+    ) -> Result<Operand<Label>, Error> {
+        line.expect_len(at + 1)?;
+        let mut pairs: Vec<(i32, Label)> = vec![];
+        loop {
+            let case = self
+                .lines
+                .pop()
+                .ok_or_else(|| line.error("missing default of lookupswitch"))?;
+            match case.case()? {
+                (Some(key), label) if key == "default" => {
+                    return Ok(Operand::LookupSwitch {
+                        default: body.label(&label),
+                        pairs,
+                    });
+                }
+                (Some(key), label) => {
+                    let key = key
+                        .parse()
+                        .map_err(|_| case.error(format!("invalid key {}", key)))?;
+                    if pairs.iter().any(|(other, _)| *other == key) {
+                        return Err(case.error(format!("duplicate key {}", key)));
+                    }
+                    pairs.push((key, body.label(&label)));
+                }
+                (None, label) => return Err(case.error(format!("missing key for {}", label))),
+            }
+        }
+    }
+
+    fn code(&mut self, body: MethodBody) -> Result<Attribute, Error> {
+        let assembled = body.assembler.assemble()?;
+        let offset = |label: &Label| assembled.labels[label] as u16;
+        let mut exception_table: Vec<Exception> = vec![];
+        for (catch_type, start, end, handler) in &body.catches {
+            exception_table.push(Exception {
+                start_pc: offset(start),
+                end_pc: offset(end),
+                handler_pc: offset(handler),
+                catch_type: match catch_type {
+                    Some(class) => self.pool.class(class)?,
+                    None => 0,
+                },
+            });
+        }
+        let mut attributes: Vec<Attribute> = vec![];
+        if !body.line_numbers.is_empty() {
+            let line_number_table = body
+                .line_numbers
+                .iter()
+                .map(|(label, line_number)| LineNumber {
+                    start_pc: offset(label),
+                    line_number: *line_number,
+                })
+                .collect();
+            attributes.push(Attribute::new(
+                self.pool.utf8("LineNumberTable")?,
+                AttributeType::LineNumberTable { line_number_table },
+            )?);
+        }
+        if !body.variables.is_empty() {
+            let mut local_variable_table: Vec<LocalVariable> = vec![];
+            for (index, name, descriptor, start, end) in &body.variables {
+                local_variable_table.push(LocalVariable {
+                    start_pc: offset(start),
+                    length: offset(end) - offset(start),
+                    name_index: self.pool.utf8(name)?,
+                    descriptor_index: self.pool.utf8(descriptor)?,
+                    index: *index,
+                });
+            }
+            attributes.push(Attribute::new(
+                self.pool.utf8("LocalVariableTable")?,
+                AttributeType::LocalVariableTable {
+                    local_variable_table,
+                },
+            )?);
+        }
+        let code = CodeAttribute {
+            max_stack: body.max_stack,
+            max_locals: body.max_locals,
+            code: Arc::new(assembled.code),
+            exception_table,
+            attributes,
+        };
+        Ok(Attribute::new(
+            self.pool.utf8("Code")?,
+            AttributeType::Code { code },
+        )?)
+    }
+}
+
+/// `java/lang/System/out` into class and member name
+fn split_member<'a>(line: &Line, spec: &'a str) -> Result<(&'a str, &'a str), Error> {
+    match spec.rfind('/') {
+        Some(slash) if slash > 0 && slash + 1 < spec.len() => {
+            Ok((&spec[..slash], &spec[slash + 1..]))
+        }
+        _ => Err(line.error(format!("expected <class>/<name>, found {}", spec))),
+    }
+}
+
+/// `java/io/PrintStream/println(I)V` into class, name and descriptor
+fn split_method<'a>(line: &Line, spec: &'a str) -> Result<(&'a str, &'a str, &'a str), Error> {
+    let paren = spec
+        .find('(')
+        .ok_or_else(|| line.error(format!("missing descriptor in {}", spec)))?;
+    let (class, name) = split_member(line, &spec[..paren])?;
+    Ok((class, name, &spec[paren..]))
+}
+
+fn is_float(word: &str) -> bool {
+    word.contains(['.', 'e', 'E']) || word.ends_with("NaN") || word.ends_with("Infinity")
+}
+
+fn parse_float<T: FromStr>(line: &Line, i: usize) -> Result<T, Error> {
+    let word = line.word(i)?;
+    let word = match word {
+        "NaN" => "NaN",
+        "Infinity" => "inf",
+        "-Infinity" => "-inf",
+        word => word,
+    };
+    word.parse()
+        .map_err(|_| line.error(format!("invalid number {}", word)))
+}
+
+/// Prints `class_file` in the syntax read by `parse`.
+///
+/// Labels are named after the offset they stand for. `LineNumberTable`,
+/// `LocalVariableTable`, `Exceptions`, `ConstantValue` and `SourceFile` are
+/// printed as directives, other attributes have no syntax and are left out, so
+/// a class using `StackMapTable` needs its frames computed again. Code using
+/// `invokedynamic` or loading method handles, method types and dynamic or class
+/// constants can't be printed.
+pub fn print(class_file: &ClassFile) -> Result<String, Error> {
+    let printer = Printer {
+        constant_pool: &class_file.constant_pool,
+    };
+    let mut out = String::new();
+    writeln!(
+        out,
+        ".bytecode {}.{}",
+        class_file.major_version, class_file.minor_version
+    )
+    .unwrap();
+    for attribute in &class_file.attributes {
+        if let AttributeType::SourceFile { sourcefile_index } = attribute.attr_type {
+            writeln!(out, ".source {}", printer.utf8(sourcefile_index)?).unwrap();
+        }
+    }
+    let flags = ClassAccessFlags::from_bits_truncate(class_file.access_flags);
+    writeln!(
+        out,
+        ".class {}{}",
+        modifiers(flags, CLASS_FLAGS),
+        printer.class_name(class_file.this_class)?
+    )
+    .unwrap();
+    if class_file.super_class != 0 {
+        writeln!(
+            out,
+            ".super {}",
+            printer.class_name(class_file.super_class)?
+        )
+        .unwrap();
+    }
+    for interface in &class_file.interfaces {
+        writeln!(out, ".implements {}", printer.class_name(*interface)?).unwrap();
+    }
+    if !class_file.fields.is_empty() {
+        out.push('\n');
+    }
+    for field in &class_file.fields {
+        printer.field(field, &mut out)?;
+    }
+    for method in &class_file.methods {
+        out.push('\n');
+        printer.method(method, &mut out)?;
+    }
+    Ok(out)
+}
+
+fn modifiers<F: Copy + PartialEq + BitOr<Output = F>>(flags: F, table: &[(&str, F)]) -> String {
+    let mut modifiers = String::new();
+    for (name, flag) in table {
+        if flags | *flag == flags {
+            modifiers.push_str(name);
+            modifiers.push(' ');
+        }
+    }
+    modifiers
+}
+
+struct Printer<'a> {
+    constant_pool: &'a ConstantPoolRef,
+}
+
+impl<'a> Printer<'a> {
+    fn constant(&self, index: u16) -> Result<&'a Constant, Error> {
+        (index as usize)
+            .checked_sub(1)
+            .and_then(|i| self.constant_pool.get(i))
+            .ok_or(Error::InvalidConstant(index))
+    }
+
+    fn utf8(&self, index: u16) -> Result<&'a str, Error> {
+        match self.constant(index)? {
+            Constant::Utf8(bytes) => {
+                std::str::from_utf8(bytes).map_err(|_| Error::InvalidConstant(index))
+            }
+            _ => Err(Error::InvalidConstant(index)),
+        }
+    }
+
+    fn class_name(&self, index: u16) -> Result<&'a str, Error> {
+        match self.constant(index)? {
+            Constant::Class { name_index } => self.utf8(*name_index),
+            _ => Err(Error::InvalidConstant(index)),
+        }
+    }
+
+    /// Class, name and descriptor of a field or method reference, and whether it
+    /// refers to an interface method
+    fn member(&self, index: u16) -> Result<(&'a str, &'a str, &'a str, bool), Error> {
+        let (class_index, name_and_type_index, interface) = match self.constant(index)? {
+            Constant::FieldRef {
+                class_index,
+                name_and_type_index,
+            }
+            | Constant::MethodRef {
+                class_index,
+                name_and_type_index,
+            } => (*class_index, *name_and_type_index, false),
+            Constant::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => (*class_index, *name_and_type_index, true),
+            _ => return Err(Error::InvalidConstant(index)),
+        };
+        match self.constant(name_and_type_index)? {
+            Constant::NameAndType {
+                name_index,
+                descriptor_index,
+            } => Ok((
+                self.class_name(class_index)?,
+                self.utf8(*name_index)?,
+                self.utf8(*descriptor_index)?,
+                interface,
+            )),
+            _ => Err(Error::InvalidConstant(name_and_type_index)),
+        }
+    }
+
+    /// Operand of `ldc` or a `ConstantValue`
+    fn value(&self, index: u16) -> Result<String, Error> {
+        let value = match self.constant(index)? {
+            Constant::Integer(value) => value.to_string(),
+            Constant::Long(value) => value.to_string(),
+            Constant::Float(value) => float(*value as f64, value.to_string()),
+            Constant::Double(value) => float(*value, value.to_string()),
+            Constant::String { string_index } => quote(self.utf8(*string_index)?),
+            _ => return Err(Error::UnsupportedConstant(index)),
+        };
+        Ok(value)
+    }
+
+    fn field(&self, field: &FieldInfo, out: &mut String) -> Result<(), Error> {
+        let flags = FieldAccessFlags::from_bits_truncate(field.access_flags);
+        write!(
+            out,
+            ".field {}{} {}",
+            modifiers(flags, FIELD_FLAGS),
+            self.utf8(field.name_index)?,
+            self.utf8(field.descriptor_index)?
+        )
+        .unwrap();
+        for attribute in &field.attributes {
+            if let AttributeType::ConstantValue {
+                constant_value_index,
+            } = attribute.attr_type
+            {
+                write!(out, " = {}", self.value(constant_value_index)?).unwrap();
+            }
+        }
+        out.push('\n');
+        Ok(())
+    }
+
+    fn method(&self, method: &MethodInfo, out: &mut String) -> Result<(), Error> {
+        let flags = MethodAccessFlags::from_bits_truncate(method.access_flags);
+        writeln!(
+            out,
+            ".method {}{}{}",
+            modifiers(flags, METHOD_FLAGS),
+            self.utf8(method.name_index)?,
+            self.utf8(method.descriptor_index)?
+        )
+        .unwrap();
+        for attribute in &method.attributes {
+            if let AttributeType::Exceptions {
+                exception_index_table,
+            } = &attribute.attr_type
+            {
+                for class in exception_index_table {
+                    writeln!(out, "    .throws {}", self.class_name(*class)?).unwrap();
+                }
+            }
+        }
+        if let Some(code) = method.get_code_attr() {
+            self.code(code, out)?;
+        }
+        out.push_str(".end method\n");
+        Ok(())
+    }
+
+    fn code(&self, code: &CodeAttribute, out: &mut String) -> Result<(), Error> {
+        let instructions = decode(&code.code)?;
+        let mut line_numbers: Vec<&LineNumber> = vec![];
+        let mut variables: Vec<&LocalVariable> = vec![];
+        for attribute in &code.attributes {
+            match &attribute.attr_type {
+                AttributeType::LineNumberTable { line_number_table } => {
+                    line_numbers.extend(line_number_table)
+                }
+                AttributeType::LocalVariableTable {
+                    local_variable_table,
+                } => variables.extend(local_variable_table),
+                _ => {}
+            }
+        }
+        let mut labels: BTreeSet<u32> = BTreeSet::new();
+        for (_, instruction) in &instructions {
+            labels.extend(instruction.targets().into_iter().copied());
+        }
+        for exception in &code.exception_table {
+            labels.insert(exception.start_pc as u32);
+            labels.insert(exception.end_pc as u32);
+            labels.insert(exception.handler_pc as u32);
+        }
+        for variable in &variables {
+            labels.insert(variable.start_pc as u32);
+            labels.insert(variable.start_pc as u32 + variable.length as u32);
+        }
+
+        writeln!(out, "    .limit stack {}", code.max_stack).unwrap();
+        writeln!(out, "    .limit locals {}", code.max_locals).unwrap();
+        for exception in &code.exception_table {
+            let catch_type = match exception.catch_type {
+                0 => "all",
+                catch_type => self.class_name(catch_type)?,
+            };
+            writeln!(
+                out,
+                "    .catch {} from L{} to L{} using L{}",
+                catch_type, exception.start_pc, exception.end_pc, exception.handler_pc
+            )
+            .unwrap();
+        }
+        for variable in &variables {
+            writeln!(
+                out,
+                "    .var {} is {} {} from L{} to L{}",
+                variable.index,
+                self.utf8(variable.name_index)?,
+                self.utf8(variable.descriptor_index)?,
+                variable.start_pc,
+                variable.start_pc as u32 + variable.length as u32
+            )
+            .unwrap();
+        }
+        for (pc, instruction) in &instructions {
+            if labels.contains(pc) {
+                writeln!(out, "L{}:", pc).unwrap();
+            }
+            for line_number in &line_numbers {
+                if line_number.start_pc as u32 == *pc {
+                    writeln!(out, "    .line {}", line_number.line_number).unwrap();
+                }
+            }
+            self.instruction(instruction, out)?;
+        }
+        let end = code.code.len() as u32;
+        if labels.contains(&end) {
+            writeln!(out, "L{}:", end).unwrap();
+        }
+        Ok(())
+    }
+
+    fn instruction(&self, instruction: &Instruction, out: &mut String) -> Result<(), Error> {
+        let opcode = instruction.opcode;
+        let name = opcode.name();
+        match &instruction.operand {
+            Operand::None => writeln!(out, "    {}", name),
+            Operand::Byte(value) => writeln!(out, "    {} {}", name, value),
+            Operand::Short(value) => writeln!(out, "    {} {}", name, value),
+            Operand::Constant(index) => {
+                let (name, operand) = match opcode as u8 {
+                    // the assembler picks ldc or ldc_w
+                    0x12 | 0x13 => ("ldc", self.value(*index)?),
+                    0x14 => (name, self.value(*index)?),
+                    0xb2..=0xb5 => {
+                        let (class, member, descriptor, _) = self.member(*index)?;
+                        (name, format!("{}/{} {}", class, member, descriptor))
+                    }
+                    0xb6..=0xb8 => {
+                        let (class, member, descriptor, interface) = self.member(*index)?;
+                        let interface = if interface { "interface " } else { "" };
+                        let operand = format!("{}{}/{}{}", interface, class, member, descriptor);
+                        (name, operand)
+                    }
+                    0xbb | 0xbd | 0xc0 | 0xc1 => (name, self.class_name(*index)?.to_string()),
+                    _ => return Err(Error::UnsupportedConstant(*index)),
+                };
+                writeln!(out, "    {} {}", name, operand)
+            }
+            Operand::Local { index, .. } => writeln!(out, "    {} {}", name, index),
+            Operand::Iinc {
+                index, constant, ..
+            } => writeln!(out, "    {} {} {}", name, index, constant),
+            Operand::Branch(target) => writeln!(out, "    {} L{}", name, target),
+            Operand::TableSwitch {
+                default,
+                low,
+                high,
+                targets,
+            } => {
+                writeln!(out, "    {} {} {}", name, low, high).unwrap();
+                for target in targets {
+                    writeln!(out, "        L{}", target).unwrap();
+                }
+                writeln!(out, "        default : L{}", default)
+            }
+            Operand::LookupSwitch { default, pairs } => {
+                writeln!(out, "    {}", name).unwrap();
+                for (key, target) in pairs {
+                    writeln!(out, "        {} : L{}", key, target).unwrap();
+                }
+                writeln!(out, "        default : L{}", default)
+            }
+            Operand::InvokeInterface { index, count } => {
+                let (class, member, descriptor, _) = self.member(*index)?;
+                writeln!(
+                    out,
+                    "    {} {}/{}{} {}",
+                    name, class, member, descriptor, count
+                )
+            }
+            Operand::NewArray(array_type) => writeln!(out, "    {} {}", name, array_type.name()),
+            Operand::MultiANewArray { index, dimensions } => writeln!(
+                out,
+                "    {} {} {}",
+                name,
+                self.class_name(*index)?,
+                dimensions
+            ),
+        }
+        .unwrap();
+        Ok(())
+    }
+}
+
+/// `display` is the shortest text reading back as the same value, which lacks
+/// a decimal point for integral values
+fn float(value: f64, display: String) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if display.contains('.') {
+        display
+    } else {
+        display + ".0"
+    }
+}
+
+fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::Error;
+    use crate::jasmin::{parse, print};
+    use classfile::class_file::ClassFile;
+
+    const COUNTER: &str = r#".bytecode 49.0
+.source Counter.j
+.class public super Counter
+.super java/lang/Object
+.implements java/lang/Runnable
+
+.field private count I
+.field public static final NAME Ljava/lang/String; = "counter \"one\"\n"
+.field static final BIG J = 12345678901
+.field static final HALF D = 0.5
+
+.method public <init>()V
+    .limit stack 1
+    .limit locals 1
+    .var 0 is this LCounter; from L0 to L5
+L0:
+    .line 3
+    aload_0
+    invokespecial java/lang/Object/<init>()V
+    return
+L5:
+.end method
+
+.method public run()V
+    .throws java/lang/IllegalStateException
+    .limit stack 3
+    .limit locals 300
+    .catch java/lang/RuntimeException from L0 to L44 using L47
+    .catch all from L0 to L44 using L56
+L0:
+    aload_0
+    dup
+    getfield Counter/count I
+    iconst_1
+    iadd
+    putfield Counter/count I
+    iinc 299 -200
+    iload 299
+    tableswitch 0 1
+        L44
+        L56
+        default : L58
+L44:
+    goto L58
+L47:
+    astore 280
+    aload 280
+    athrow
+L56:
+    pop
+    return
+L58:
+    iload_1
+    lookupswitch
+        -1 : L44
+        7 : L56
+        default : L0
+.end method
+
+.method public static native compute([IF)[[J
+.end method
+"#;
+
+    #[test]
+    fn jasmin_round_trip() {
+        let class_file = parse(COUNTER).unwrap();
+        let mut bytes: Vec<u8> = vec![];
+        class_file.write_to(&mut bytes).unwrap();
+        let class_file = ClassFile::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(print(&class_file).unwrap(), COUNTER);
+
+        let bytes = std::fs::read("../classfile/tests/HelloWorld.class").unwrap();
+        let class_file = ClassFile::read_from(bytes.as_slice()).unwrap();
+        let text = print(&class_file).unwrap();
+        assert!(text.contains("    ldc \"HelloWorld\"\n"));
+        assert_eq!(print(&parse(&text).unwrap()).unwrap(), text);
+
+        // lambdas use invokedynamic, which Jasmin can't express
+        let bytes = std::fs::read("../classfile/tests/RoundTrip.class").unwrap();
+        let class_file = ClassFile::read_from(bytes.as_slice()).unwrap();
+        assert!(matches!(
+            print(&class_file),
+            Err(Error::UnsupportedConstant(_))
+        ));
+
+        assert_eq!(
+            parse(".class A\n.super B\n.method m()V\n    ildc 1\n.end method\n").unwrap_err(),
+            Error::Syntax(4, "unknown instruction ildc".to_string())
+        );
+    }
+}
@@ -1,4 +1,22 @@
+pub mod assembler;
+pub mod cfg;
+mod constant_pool;
+pub mod dataflow;
+pub mod decoder;
+pub mod decompiler;
+pub mod error;
+pub mod instrument;
+pub mod instruction;
+pub mod jasmin;
+pub mod limits;
+pub mod metadata;
 pub mod opcode;
+pub mod optimizer;
+pub mod search;
+pub mod ssa;
+pub mod subroutine;
+pub mod verifier;
+pub mod writer;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,210 @@
+use crate::dataflow::local_access;
+use crate::decoder::decode;
+use crate::error::Error;
+use crate::metadata::stack_effect;
+use crate::opcode::OpCode;
+use classfile::attribute::{Attribute, AttributeType, CodeAttribute};
+use classfile::class_file::ClassFile;
+use classfile::constant::Constant;
+use classfile::descriptor::MethodDescriptor;
+use classfile::method::MethodInfo;
+use classfile::ConstantPoolRef;
+use std::collections::BTreeMap;
+
+/// Deepest operand stack the code reaches, in slots.
+///
+/// The depth is followed along every path from the method entry, an exception
+/// handler starts with the exception alone on the stack. The instruction after
+/// a `jsr` is reached with the depth before it, as when the subroutine returns
+/// without leaving anything on the stack. Every path must reach an instruction
+/// with the same depth.
+pub fn compute_max_stack(
+    code: &CodeAttribute,
+    constant_pool: &ConstantPoolRef,
+) -> Result<u16, Error> {
+    let instructions = decode(&code.code)?;
+    let mut depths = Depths {
+        indices: instructions
+            .iter()
+            .enumerate()
+            .map(|(index, (pc, _))| (*pc, index))
+            .collect(),
+        depths: vec![None; instructions.len()],
+        pending: vec![],
+    };
+
+    let mut max_stack = 0;
+    if let Some((pc, _)) = instructions.first() {
+        depths.reach(*pc, *pc, 0)?;
+    }
+    while let Some(index) = depths.pending.pop() {
+        let (pc, instruction) = &instructions[index];
+        let depth = depths.depths[index].unwrap();
+        for entry in &code.exception_table {
+            if *pc >= entry.start_pc as u32 && *pc < entry.end_pc as u32 {
+                depths.reach(*pc, entry.handler_pc as u32, 1)?;
+                max_stack = max_stack.max(1);
+            }
+        }
+        let (popped, pushed) = stack_effect(instruction, constant_pool)?;
+        let after = depth
+            .checked_sub(popped)
+            .ok_or(Error::StackUnderflow(*pc))?
+            + pushed;
+        max_stack = max_stack.max(after);
+        for target in instruction.targets() {
+            depths.reach(*pc, *target, after)?;
+        }
+        let next = match instruction.opcode {
+            OpCode::jsr | OpCode::jsr_w => Some(depth),
+            opcode if opcode.info().falls_through() => Some(after),
+            _ => None,
+        };
+        if let Some(next_depth) = next {
+            let (next, _) = instructions
+                .get(index + 1)
+                .ok_or(Error::UnexpectedEnd(*pc))?;
+            depths.reach(*pc, *next, next_depth)?;
+        }
+    }
+    Ok(max_stack)
+}
+
+/// Operand stack depth before each instruction reached
+struct Depths {
+    /// Index of the instruction at each offset
+    indices: BTreeMap<u32, usize>,
+    depths: Vec<Option<u16>>,
+    /// Instructions reached but not yet followed
+    pending: Vec<usize>,
+}
+
+impl Depths {
+    /// Reaches `target` from the instruction at `pc` with `depth` slots on the
+    /// stack
+    fn reach(&mut self, pc: u32, target: u32, depth: u16) -> Result<(), Error> {
+        let index = *self
+            .indices
+            .get(&target)
+            .ok_or(Error::InvalidBranchTarget(pc, target as i64))?;
+        match self.depths[index] {
+            None => {
+                self.depths[index] = Some(depth);
+                self.pending.push(index);
+                Ok(())
+            }
+            Some(current) if current == depth => Ok(()),
+            Some(_) => Err(Error::InconsistentStackHeight(target)),
+        }
+    }
+}
+
+/// Local variable slots the code uses, at least `parameter_slots`, the slots
+/// of the arguments and `this`
+pub fn compute_max_locals(code: &CodeAttribute, parameter_slots: u16) -> Result<u16, Error> {
+    let mut max_locals = parameter_slots;
+    for (_, instruction) in decode(&code.code)? {
+        if let Some((slot, width, _)) = local_access(&instruction) {
+            max_locals = max_locals.max(slot + width);
+        }
+    }
+    Ok(max_locals)
+}
+
+/// Sets `max_stack` and `max_locals` of every method of `class_file` to the
+/// values its code needs
+pub fn compute_maxs(class_file: &ClassFile) -> Result<ClassFile, Error> {
+    let mut class_file = class_file.clone();
+    let constant_pool = class_file.constant_pool.clone();
+    for method in &mut class_file.methods {
+        let index = match method.get_code_attr() {
+            Some(_) => method.code_attr_index.unwrap(),
+            None => continue,
+        };
+        let parameter_slots = parameter_slots(method, &constant_pool)?;
+        let mut code = method.get_code_attr().unwrap().clone();
+        code.max_stack = compute_max_stack(&code, &constant_pool)?;
+        code.max_locals = compute_max_locals(&code, parameter_slots)?;
+        let name_index = method.attributes[index].attribute_name_index;
+        method.attributes[index] = Attribute::new(name_index, AttributeType::Code { code })?;
+    }
+    Ok(class_file)
+}
+
+/// Local variable slots taken by the arguments of `method` and `this`
+fn parameter_slots(method: &MethodInfo, constant_pool: &ConstantPoolRef) -> Result<u16, Error> {
+    let index = method.descriptor_index;
+    let descriptor = match (index as usize)
+        .checked_sub(1)
+        .and_then(|i| constant_pool.get(i))
+    {
+        Some(Constant::Utf8(bytes)) => {
+            MethodDescriptor::parse(bytes).map_err(|_| Error::InvalidConstant(index))?
+        }
+        _ => return Err(Error::InvalidConstant(index)),
+    };
+    let this = if method.flags().is_static() { 0 } else { 1 };
+    Ok(descriptor.parameter_slots() as u16 + this)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::Error;
+    use crate::jasmin::parse;
+    use crate::limits::{compute_max_locals, compute_max_stack, compute_maxs};
+    use classfile::class_file::ClassFile;
+    use std::fs::File;
+
+    fn maxs(body: &str) -> Result<(u16, u16), Error> {
+        let source = format!(
+            ".class A\n.super java/lang/Object\n.method m(JI)J\n    .limit stack 100\n    .limit locals 100\n{}\n.end method\n",
+            body
+        );
+        let class_file = parse(&source).unwrap();
+        let code = class_file.methods[0].get_code_attr().unwrap();
+        Ok((
+            compute_max_stack(code, &class_file.constant_pool)?,
+            compute_max_locals(code, 4)?,
+        ))
+    }
+
+    #[test]
+    fn compute_limits() {
+        // long values take two slots, the call pops this and two longs
+        let (max_stack, max_locals) =
+            maxs("    aload_0\n    lload_1\n    lload_1\n    invokevirtual A/f(JJ)J\n    lreturn")
+                .unwrap();
+        assert_eq!((max_stack, max_locals), (5, 4));
+
+        let (max_stack, max_locals) = maxs(
+            "    .catch all from Start to End using Handler\nStart:\n    iload_3\n    istore 7\n    lload_1\nEnd:\n    lreturn\nHandler:\n    astore 9\n    lload_1\n    lreturn",
+        )
+        .unwrap();
+        assert_eq!((max_stack, max_locals), (2, 10));
+
+        assert_eq!(
+            maxs("    iload_3\n    ifeq Done\n    iconst_0\nDone:\n    lload_1\n    lreturn")
+                .unwrap_err(),
+            Error::InconsistentStackHeight(5)
+        );
+        assert_eq!(
+            maxs("    iadd\n    lload_1\n    lreturn").unwrap_err(),
+            Error::StackUnderflow(0)
+        );
+
+        // javac computes the same
+        let class_file =
+            ClassFile::read_from(File::open("../classfile/tests/Verified.class").unwrap()).unwrap();
+        let computed = compute_maxs(&class_file).unwrap();
+        for (method, computed) in class_file.methods.iter().zip(&computed.methods) {
+            let (code, computed) = (
+                method.get_code_attr().unwrap(),
+                computed.get_code_attr().unwrap(),
+            );
+            assert_eq!(
+                (code.max_stack, code.max_locals),
+                (computed.max_stack, computed.max_locals)
+            );
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use self::Category::*;
+use self::Flow::*;
+use self::Slots::*;
+use crate::error::Error;
+use crate::instruction::{Instruction, Operand};
+use crate::opcode::OpCode;
+use classfile::constant::Constant;
+use classfile::descriptor::{FieldType, MethodDescriptor};
+use classfile::ConstantPoolRef;
+
+/// Operands following an opcode in the code array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandFormat {
+    None,
+    /// Signed byte of `bipush`
+    Byte,
+    /// Signed short of `sipush`
+    Short,
+    /// One byte constant pool index of `ldc`
+    ConstantByte,
+    /// Two byte constant pool index
+    Constant,
+    /// One byte local variable index, two behind `wide`
+    Local,
+    /// Local variable index and signed constant, one byte each or two behind `wide`
+    Iinc,
+    /// Signed two byte branch offset
+    Branch,
+    /// Signed four byte branch offset of `goto_w` and `jsr_w`
+    BranchWide,
+    TableSwitch,
+    LookupSwitch,
+    /// Constant pool index, argument count and a zero byte
+    InvokeInterface,
+    /// Constant pool index and two zero bytes
+    InvokeDynamic,
+    /// `atype` byte
+    NewArray,
+    /// Constant pool index and dimension count
+    MultiANewArray,
+    /// Opcode of the modified instruction and its widened operands
+    Wide,
+}
+
+impl OperandFormat {
+    /// Number of operand bytes, `None` for switches padded to their offset and
+    /// `wide` whose length depends on the modified instruction
+    pub fn length(&self) -> Option<u32> {
+        let length = match self {
+            OperandFormat::None => 0,
+            OperandFormat::Byte | OperandFormat::ConstantByte | OperandFormat::Local => 1,
+            OperandFormat::NewArray => 1,
+            OperandFormat::Short | OperandFormat::Constant | OperandFormat::Iinc => 2,
+            OperandFormat::Branch => 2,
+            OperandFormat::MultiANewArray => 3,
+            OperandFormat::BranchWide
+            | OperandFormat::InvokeInterface
+            | OperandFormat::InvokeDynamic => 4,
+            OperandFormat::TableSwitch | OperandFormat::LookupSwitch | OperandFormat::Wide => {
+                return None
+            }
+        };
+        Some(length)
+    }
+}
+
+/// Operand stack slots popped or pushed, `long` and `double` take two
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slots {
+    Fixed(u8),
+    /// The given slots plus the size of the field type of a field instruction,
+    /// of the arguments (popped) or of the return type (pushed) of an invocation
+    Descriptor(u8),
+    /// One slot per dimension of `multianewarray`
+    Dimensions,
+}
+
+/// Opcode groups of JVMS chapter 7
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    Constants,
+    Loads,
+    Stores,
+    Stack,
+    Math,
+    Conversions,
+    Comparisons,
+    Control,
+    References,
+    Extended,
+    Reserved,
+}
+
+/// Where execution continues after an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Flow {
+    /// The next instruction
+    Next,
+    /// The branch target or the next instruction
+    Branch,
+    /// `goto` and `goto_w`
+    Jump,
+    /// `jsr` and `jsr_w`, the next instruction is only reached through `ret`
+    Subroutine,
+    Ret,
+    /// One of the switch targets
+    Switch,
+    Return,
+    /// `athrow`
+    Throw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpCodeInfo {
+    pub opcode: OpCode,
+    pub operands: OperandFormat,
+    pub pops: Slots,
+    pub pushes: Slots,
+    pub category: Category,
+    pub flow: Flow,
+    /// Whether the instruction may complete abruptly with an exception other
+    /// than a `VirtualMachineError`, e.g. by resolution, a null reference or a
+    /// bounds check
+    pub can_throw: bool,
+}
+
+impl OpCodeInfo {
+    /// Whether the instruction has branch targets
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self.flow,
+            Flow::Branch | Flow::Jump | Flow::Subroutine | Flow::Switch
+        )
+    }
+
+    /// Whether the next instruction can directly follow this one
+    pub fn falls_through(&self) -> bool {
+        matches!(self.flow, Flow::Next | Flow::Branch)
+    }
+
+    /// Whether the instruction ends a basic block without falling through
+    pub fn is_terminator(&self) -> bool {
+        !self.falls_through()
+    }
+}
+
+impl OpCode {
+    /// Static metadata of the opcode
+    pub fn info(&self) -> &'static OpCodeInfo {
+        let index = match *self as u8 {
+            opcode @ 0x00..=0xca => opcode as usize,
+            0xfe => OPCODES.len() - 2,
+            _ => OPCODES.len() - 1,
+        };
+        &OPCODES[index]
+    }
+}
+
+/// Operand stack slots popped and pushed by `instruction`, resolving the
+/// descriptor of field and method instructions through `constant_pool`
+pub fn stack_effect(
+    instruction: &Instruction,
+    constant_pool: &ConstantPoolRef,
+) -> Result<(u16, u16), Error> {
+    let info = instruction.opcode.info();
+    let slots = |slots: Slots, popped: bool| -> Result<u16, Error> {
+        match slots {
+            Fixed(n) => Ok(n as u16),
+            Descriptor(n) => {
+                let index = match instruction.operand {
+                    Operand::Constant(index) | Operand::InvokeInterface { index, .. } => index,
+                    _ => return Err(Error::InvalidOperand(instruction.opcode)),
+                };
+                let descriptor = member_descriptor(constant_pool, index)?;
+                // getstatic, putstatic, getfield and putfield
+                let size = if matches!(instruction.opcode as u8, 0xb2..=0xb5) {
+                    FieldType::parse(descriptor)
+                        .map_err(|_| Error::InvalidConstant(index))?
+                        .slots()
+                } else {
+                    let descriptor = MethodDescriptor::parse(descriptor)
+                        .map_err(|_| Error::InvalidConstant(index))?;
+                    if popped {
+                        descriptor.parameter_slots()
+                    } else {
+                        descriptor.return_slots()
+                    }
+                };
+                Ok(n as u16 + size as u16)
+            }
+            Dimensions => match instruction.operand {
+                Operand::MultiANewArray { dimensions, .. } => Ok(dimensions as u16),
+                _ => Err(Error::InvalidOperand(instruction.opcode)),
+            },
+        }
+    };
+    Ok((slots(info.pops, true)?, slots(info.pushes, false)?))
+}
+
+/// Descriptor of the field, method, call site or dynamic constant a constant
+/// pool entry refers to
+pub(crate) fn member_descriptor(
+    constant_pool: &ConstantPoolRef,
+    index: u16,
+) -> Result<&[u8], Error> {
+    let constant = |index: u16| {
+        (index as usize)
+            .checked_sub(1)
+            .and_then(|i| constant_pool.get(i))
+            .ok_or(Error::InvalidConstant(index))
+    };
+    let name_and_type_index = match constant(index)? {
+        Constant::FieldRef {
+            name_and_type_index,
+            ..
+        }
+        | Constant::MethodRef {
+            name_and_type_index,
+            ..
+        }
+        | Constant::InterfaceMethodRef {
+            name_and_type_index,
+            ..
+        }
+        | Constant::InvokeDynamic {
+            name_and_type_index,
+            ..
+        }
+        | Constant::Dynamic {
+            name_and_type_index,
+            ..
+        } => *name_and_type_index,
+        _ => return Err(Error::InvalidConstant(index)),
+    };
+    let descriptor_index = match constant(name_and_type_index)? {
+        Constant::NameAndType {
+            descriptor_index, ..
+        } => *descriptor_index,
+        _ => return Err(Error::InvalidConstant(name_and_type_index)),
+    };
+    match constant(descriptor_index)? {
+        Constant::Utf8(bytes) => Ok(bytes.as_slice()),
+        _ => Err(Error::InvalidConstant(descriptor_index)),
+    }
+}
+
+const fn info(
+    opcode: OpCode,
+    operands: OperandFormat,
+    pops: Slots,
+    pushes: Slots,
+    category: Category,
+    flow: Flow,
+    can_throw: bool,
+) -> OpCodeInfo {
+    OpCodeInfo {
+        opcode,
+        operands,
+        pops,
+        pushes,
+        category,
+        flow,
+        can_throw,
+    }
+}
+
+/// Indexed by opcode up to `breakpoint`, followed by `impdep1` and `impdep2`
+#[rustfmt::skip]
+static OPCODES: [OpCodeInfo; 205] = [
+    info(OpCode::nop, OperandFormat::None, Fixed(0), Fixed(0), Constants, Next, false),
+    info(OpCode::aconst_null, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::iconst_m1, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::iconst_0, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::iconst_1, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::iconst_2, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::iconst_3, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::iconst_4, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::iconst_5, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::lconst_0, OperandFormat::None, Fixed(0), Fixed(2), Constants, Next, false),
+    info(OpCode::lconst_1, OperandFormat::None, Fixed(0), Fixed(2), Constants, Next, false),
+    info(OpCode::fconst_0, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::fconst_1, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::fconst_2, OperandFormat::None, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::dconst_0, OperandFormat::None, Fixed(0), Fixed(2), Constants, Next, false),
+    info(OpCode::dconst_1, OperandFormat::None, Fixed(0), Fixed(2), Constants, Next, false),
+    info(OpCode::bipush, OperandFormat::Byte, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::sipush, OperandFormat::Short, Fixed(0), Fixed(1), Constants, Next, false),
+    info(OpCode::ldc, OperandFormat::ConstantByte, Fixed(0), Fixed(1), Constants, Next, true),
+    info(OpCode::ldc_w, OperandFormat::Constant, Fixed(0), Fixed(1), Constants, Next, true),
+    info(OpCode::ldc2_w, OperandFormat::Constant, Fixed(0), Fixed(2), Constants, Next, false),
+    info(OpCode::iload, OperandFormat::Local, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::lload, OperandFormat::Local, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::fload, OperandFormat::Local, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::dload, OperandFormat::Local, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::aload, OperandFormat::Local, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::iload_0, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::iload_1, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::iload_2, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::iload_3, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::lload_0, OperandFormat::None, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::lload_1, OperandFormat::None, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::lload_2, OperandFormat::None, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::lload_3, OperandFormat::None, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::fload_0, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::fload_1, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::fload_2, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::fload_3, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::dload_0, OperandFormat::None, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::dload_1, OperandFormat::None, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::dload_2, OperandFormat::None, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::dload_3, OperandFormat::None, Fixed(0), Fixed(2), Loads, Next, false),
+    info(OpCode::aload_0, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::aload_1, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::aload_2, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::aload_3, OperandFormat::None, Fixed(0), Fixed(1), Loads, Next, false),
+    info(OpCode::iaload, OperandFormat::None, Fixed(2), Fixed(1), Loads, Next, true),
+    info(OpCode::laload, OperandFormat::None, Fixed(2), Fixed(2), Loads, Next, true),
+    info(OpCode::faload, OperandFormat::None, Fixed(2), Fixed(1), Loads, Next, true),
+    info(OpCode::daload, OperandFormat::None, Fixed(2), Fixed(2), Loads, Next, true),
+    info(OpCode::aaload, OperandFormat::None, Fixed(2), Fixed(1), Loads, Next, true),
+    info(OpCode::baload, OperandFormat::None, Fixed(2), Fixed(1), Loads, Next, true),
+    info(OpCode::caload, OperandFormat::None, Fixed(2), Fixed(1), Loads, Next, true),
+    info(OpCode::saload, OperandFormat::None, Fixed(2), Fixed(1), Loads, Next, true),
+    info(OpCode::istore, OperandFormat::Local, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::lstore, OperandFormat::Local, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::fstore, OperandFormat::Local, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::dstore, OperandFormat::Local, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::astore, OperandFormat::Local, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::istore_0, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::istore_1, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::istore_2, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::istore_3, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::lstore_0, OperandFormat::None, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::lstore_1, OperandFormat::None, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::lstore_2, OperandFormat::None, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::lstore_3, OperandFormat::None, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::fstore_0, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::fstore_1, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::fstore_2, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::fstore_3, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::dstore_0, OperandFormat::None, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::dstore_1, OperandFormat::None, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::dstore_2, OperandFormat::None, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::dstore_3, OperandFormat::None, Fixed(2), Fixed(0), Stores, Next, false),
+    info(OpCode::astore_0, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::astore_1, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::astore_2, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::astore_3, OperandFormat::None, Fixed(1), Fixed(0), Stores, Next, false),
+    info(OpCode::iastore, OperandFormat::None, Fixed(3), Fixed(0), Stores, Next, true),
+    info(OpCode::lastore, OperandFormat::None, Fixed(4), Fixed(0), Stores, Next, true),
+    info(OpCode::fastore, OperandFormat::None, Fixed(3), Fixed(0), Stores, Next, true),
+    info(OpCode::dastore, OperandFormat::None, Fixed(4), Fixed(0), Stores, Next, true),
+    info(OpCode::aastore, OperandFormat::None, Fixed(3), Fixed(0), Stores, Next, true),
+    info(OpCode::bastore, OperandFormat::None, Fixed(3), Fixed(0), Stores, Next, true),
+    info(OpCode::castore, OperandFormat::None, Fixed(3), Fixed(0), Stores, Next, true),
+    info(OpCode::sastore, OperandFormat::None, Fixed(3), Fixed(0), Stores, Next, true),
+    info(OpCode::pop, OperandFormat::None, Fixed(1), Fixed(0), Stack, Next, false),
+    info(OpCode::pop2, OperandFormat::None, Fixed(2), Fixed(0), Stack, Next, false),
+    info(OpCode::dup, OperandFormat::None, Fixed(1), Fixed(2), Stack, Next, false),
+    info(OpCode::dup_x1, OperandFormat::None, Fixed(2), Fixed(3), Stack, Next, false),
+    info(OpCode::dup_x2, OperandFormat::None, Fixed(3), Fixed(4), Stack, Next, false),
+    info(OpCode::dup2, OperandFormat::None, Fixed(2), Fixed(4), Stack, Next, false),
+    info(OpCode::dup2_x1, OperandFormat::None, Fixed(3), Fixed(5), Stack, Next, false),
+    info(OpCode::dup2_x2, OperandFormat::None, Fixed(4), Fixed(6), Stack, Next, false),
+    info(OpCode::swap, OperandFormat::None, Fixed(2), Fixed(2), Stack, Next, false),
+    info(OpCode::iadd, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::ladd, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::fadd, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::dadd, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::isub, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::lsub, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::fsub, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::dsub, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::imul, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::lmul, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::fmul, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::dmul, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::idiv, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, true),
+    info(OpCode::ldiv, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, true),
+    info(OpCode::fdiv, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::ddiv, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::irem, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, true),
+    info(OpCode::lrem, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, true),
+    info(OpCode::frem, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::drem, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::ineg, OperandFormat::None, Fixed(1), Fixed(1), Math, Next, false),
+    info(OpCode::lneg, OperandFormat::None, Fixed(2), Fixed(2), Math, Next, false),
+    info(OpCode::fneg, OperandFormat::None, Fixed(1), Fixed(1), Math, Next, false),
+    info(OpCode::dneg, OperandFormat::None, Fixed(2), Fixed(2), Math, Next, false),
+    info(OpCode::ishl, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::lshl, OperandFormat::None, Fixed(3), Fixed(2), Math, Next, false),
+    info(OpCode::ishr, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::lshr, OperandFormat::None, Fixed(3), Fixed(2), Math, Next, false),
+    info(OpCode::iushr, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::lushr, OperandFormat::None, Fixed(3), Fixed(2), Math, Next, false),
+    info(OpCode::iand, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::land, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::ior, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::lor, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::ixor, OperandFormat::None, Fixed(2), Fixed(1), Math, Next, false),
+    info(OpCode::lxor, OperandFormat::None, Fixed(4), Fixed(2), Math, Next, false),
+    info(OpCode::iinc, OperandFormat::Iinc, Fixed(0), Fixed(0), Math, Next, false),
+    info(OpCode::i2l, OperandFormat::None, Fixed(1), Fixed(2), Conversions, Next, false),
+    info(OpCode::i2f, OperandFormat::None, Fixed(1), Fixed(1), Conversions, Next, false),
+    info(OpCode::i2d, OperandFormat::None, Fixed(1), Fixed(2), Conversions, Next, false),
+    info(OpCode::l2i, OperandFormat::None, Fixed(2), Fixed(1), Conversions, Next, false),
+    info(OpCode::l2f, OperandFormat::None, Fixed(2), Fixed(1), Conversions, Next, false),
+    info(OpCode::l2d, OperandFormat::None, Fixed(2), Fixed(2), Conversions, Next, false),
+    info(OpCode::f2i, OperandFormat::None, Fixed(1), Fixed(1), Conversions, Next, false),
+    info(OpCode::f2l, OperandFormat::None, Fixed(1), Fixed(2), Conversions, Next, false),
+    info(OpCode::f2d, OperandFormat::None, Fixed(1), Fixed(2), Conversions, Next, false),
+    info(OpCode::d2i, OperandFormat::None, Fixed(2), Fixed(1), Conversions, Next, false),
+    info(OpCode::d2l, OperandFormat::None, Fixed(2), Fixed(2), Conversions, Next, false),
+    info(OpCode::d2f, OperandFormat::None, Fixed(2), Fixed(1), Conversions, Next, false),
+    info(OpCode::i2b, OperandFormat::None, Fixed(1), Fixed(1), Conversions, Next, false),
+    info(OpCode::i2c, OperandFormat::None, Fixed(1), Fixed(1), Conversions, Next, false),
+    info(OpCode::i2s, OperandFormat::None, Fixed(1), Fixed(1), Conversions, Next, false),
+    info(OpCode::lcmp, OperandFormat::None, Fixed(4), Fixed(1), Comparisons, Next, false),
+    info(OpCode::fcmpl, OperandFormat::None, Fixed(2), Fixed(1), Comparisons, Next, false),
+    info(OpCode::fcmpg, OperandFormat::None, Fixed(2), Fixed(1), Comparisons, Next, false),
+    info(OpCode::dcmpl, OperandFormat::None, Fixed(4), Fixed(1), Comparisons, Next, false),
+    info(OpCode::dcmpg, OperandFormat::None, Fixed(4), Fixed(1), Comparisons, Next, false),
+    info(OpCode::ifeq, OperandFormat::Branch, Fixed(1), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::ifne, OperandFormat::Branch, Fixed(1), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::iflt, OperandFormat::Branch, Fixed(1), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::ifge, OperandFormat::Branch, Fixed(1), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::ifgt, OperandFormat::Branch, Fixed(1), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::ifle, OperandFormat::Branch, Fixed(1), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::if_icmpeq, OperandFormat::Branch, Fixed(2), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::if_icmpne, OperandFormat::Branch, Fixed(2), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::if_icmplt, OperandFormat::Branch, Fixed(2), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::if_icmpge, OperandFormat::Branch, Fixed(2), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::if_icmpgt, OperandFormat::Branch, Fixed(2), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::if_icmple, OperandFormat::Branch, Fixed(2), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::if_acmpeq, OperandFormat::Branch, Fixed(2), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::if_acmpne, OperandFormat::Branch, Fixed(2), Fixed(0), Comparisons, Branch, false),
+    info(OpCode::goto, OperandFormat::Branch, Fixed(0), Fixed(0), Control, Jump, false),
+    info(OpCode::jsr, OperandFormat::Branch, Fixed(0), Fixed(1), Control, Subroutine, false),
+    info(OpCode::ret, OperandFormat::Local, Fixed(0), Fixed(0), Control, Ret, false),
+    info(OpCode::tableswitch, OperandFormat::TableSwitch, Fixed(1), Fixed(0), Control, Switch, false),
+    info(OpCode::lookupswitch, OperandFormat::LookupSwitch, Fixed(1), Fixed(0), Control, Switch, false),
+    info(OpCode::ireturn, OperandFormat::None, Fixed(1), Fixed(0), Control, Return, true),
+    info(OpCode::lreturn, OperandFormat::None, Fixed(2), Fixed(0), Control, Return, true),
+    info(OpCode::freturn, OperandFormat::None, Fixed(1), Fixed(0), Control, Return, true),
+    info(OpCode::dreturn, OperandFormat::None, Fixed(2), Fixed(0), Control, Return, true),
+    info(OpCode::areturn, OperandFormat::None, Fixed(1), Fixed(0), Control, Return, true),
+    info(OpCode::vreturn, OperandFormat::None, Fixed(0), Fixed(0), Control, Return, true),
+    info(OpCode::getstatic, OperandFormat::Constant, Fixed(0), Descriptor(0), References, Next, true),
+    info(OpCode::putstatic, OperandFormat::Constant, Descriptor(0), Fixed(0), References, Next, true),
+    info(OpCode::getfield, OperandFormat::Constant, Fixed(1), Descriptor(0), References, Next, true),
+    info(OpCode::putfield, OperandFormat::Constant, Descriptor(1), Fixed(0), References, Next, true),
+    info(OpCode::invokevirtual, OperandFormat::Constant, Descriptor(1), Descriptor(0), References, Next, true),
+    info(OpCode::invokespecial, OperandFormat::Constant, Descriptor(1), Descriptor(0), References, Next, true),
+    info(OpCode::invokestatic, OperandFormat::Constant, Descriptor(0), Descriptor(0), References, Next, true),
+    info(OpCode::invokeinterface, OperandFormat::InvokeInterface, Descriptor(1), Descriptor(0), References, Next, true),
+    info(OpCode::invokedynamic, OperandFormat::InvokeDynamic, Descriptor(0), Descriptor(0), References, Next, true),
+    info(OpCode::new, OperandFormat::Constant, Fixed(0), Fixed(1), References, Next, true),
+    info(OpCode::newarray, OperandFormat::NewArray, Fixed(1), Fixed(1), References, Next, true),
+    info(OpCode::anewarray, OperandFormat::Constant, Fixed(1), Fixed(1), References, Next, true),
+    info(OpCode::arraylength, OperandFormat::None, Fixed(1), Fixed(1), References, Next, true),
+    info(OpCode::athrow, OperandFormat::None, Fixed(1), Fixed(0), References, Throw, true),
+    info(OpCode::checkcast, OperandFormat::Constant, Fixed(1), Fixed(1), References, Next, true),
+    info(OpCode::instanceof, OperandFormat::Constant, Fixed(1), Fixed(1), References, Next, true),
+    info(OpCode::monitorenter, OperandFormat::None, Fixed(1), Fixed(0), References, Next, true),
+    info(OpCode::monitorexit, OperandFormat::None, Fixed(1), Fixed(0), References, Next, true),
+    info(OpCode::wide, OperandFormat::Wide, Fixed(0), Fixed(0), Extended, Next, false),
+    info(OpCode::multianewarray, OperandFormat::MultiANewArray, Dimensions, Fixed(1), Extended, Next, true),
+    info(OpCode::ifnull, OperandFormat::Branch, Fixed(1), Fixed(0), Extended, Branch, false),
+    info(OpCode::ifnonnull, OperandFormat::Branch, Fixed(1), Fixed(0), Extended, Branch, false),
+    info(OpCode::goto_w, OperandFormat::BranchWide, Fixed(0), Fixed(0), Extended, Jump, false),
+    info(OpCode::jsr_w, OperandFormat::BranchWide, Fixed(0), Fixed(1), Extended, Subroutine, false),
+    info(OpCode::breakpoint, OperandFormat::None, Fixed(0), Fixed(0), Reserved, Next, false),
+    info(OpCode::impdep1, OperandFormat::None, Fixed(0), Fixed(0), Reserved, Next, false),
+    info(OpCode::impdep2, OperandFormat::None, Fixed(0), Fixed(0), Reserved, Next, false),
+];
+
+#[cfg(test)]
+mod test {
+    use crate::decoder::decode_at;
+    use crate::metadata::{Category, Flow, OperandFormat, Slots};
+    use crate::opcode::OpCode;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn metadata_matches_decoder() {
+        let mut defined = 0;
+        for byte in 0..=u8::MAX {
+            let opcode = match OpCode::try_from(byte) {
+                Ok(opcode) => opcode,
+                Err(_) => continue,
+            };
+            defined += 1;
+            let info = opcode.info();
+            assert_eq!(info.opcode, opcode);
+            if let Some(length) = info.operands.length() {
+                let mut code = vec![byte];
+                code.resize(1 + length as usize, 0);
+                if opcode == OpCode::newarray {
+                    code[1] = 4;
+                }
+                assert_eq!(
+                    decode_at(&code, 0).unwrap().1,
+                    1 + length,
+                    "{}",
+                    opcode.name()
+                );
+            }
+        }
+        assert_eq!(defined, 205);
+
+        let dup2_x1 = OpCode::dup2_x1.info();
+        assert_eq!(
+            (dup2_x1.pops, dup2_x1.pushes),
+            (Slots::Fixed(3), Slots::Fixed(5))
+        );
+        let invokevirtual = OpCode::invokevirtual.info();
+        assert_eq!(invokevirtual.pops, Slots::Descriptor(1));
+        assert_eq!(invokevirtual.category, Category::References);
+        assert_eq!(
+            OpCode::tableswitch.info().operands,
+            OperandFormat::TableSwitch
+        );
+        assert!(OpCode::ifnull.info().is_branch() && OpCode::ifnull.info().falls_through());
+        assert!(OpCode::goto_w.info().is_terminator());
+        assert_eq!(OpCode::athrow.info().flow, Flow::Throw);
+        assert!(OpCode::idiv.info().can_throw && !OpCode::iadd.info().can_throw);
+        assert_eq!(OpCode::impdep2.info().category, Category::Reserved);
+    }
+}
@@ -1,6 +1,9 @@
+use crate::error::Error;
+use std::convert::TryFrom;
+
 #[repr(u8)]
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum OpCode {
     // Constants
     nop = 0x00,
@@ -231,9 +234,37 @@ pub enum OpCode {
     impdep2 = 0xff,
 }
 
-impl From<u8> for OpCode {
-    fn from(value: u8) -> Self {
-        match value {
+impl OpCode {
+    pub fn name(&self) -> &'static str {
+        (*self).into()
+    }
+
+    /// Opcode with the mnemonic `name`, e.g. `return` for `OpCode::vreturn`
+    pub fn from_name(name: &str) -> Option<OpCode> {
+        (0..=u8::MAX)
+            .filter_map(|byte| OpCode::try_from(byte).ok())
+            .find(|opcode| opcode.name() == name)
+    }
+
+    /// Conditional branch taken exactly when `self` is not, e.g. `ifne` for `ifeq`
+    pub fn negated(&self) -> Option<OpCode> {
+        let negated = match *self as u8 {
+            // ifeq to if_acmpne come in pairs, the odd opcode first
+            opcode @ 0x99..=0xa6 if opcode % 2 == 1 => opcode + 1,
+            opcode @ 0x99..=0xa6 => opcode - 1,
+            0xc6 => 0xc7,
+            0xc7 => 0xc6,
+            _ => return None,
+        };
+        OpCode::try_from(negated).ok()
+    }
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let opcode = match value {
             0x00 => OpCode::nop,
             0x01 => OpCode::aconst_null,
             0x02 => OpCode::iconst_m1,
@@ -439,8 +470,9 @@ impl From<u8> for OpCode {
             0xca => OpCode::breakpoint,
             0xfe => OpCode::impdep1,
             0xff => OpCode::impdep2,
-            _ => unreachable!(),
-        }
+            _ => return Err(Error::UndefinedOpCode(value)),
+        };
+        Ok(opcode)
     }
 }
 
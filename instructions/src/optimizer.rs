@@ -0,0 +1,790 @@
+use crate::assembler::{assemble, Item, Label};
+use crate::cfg::ControlFlowGraph;
+use crate::dataflow::liveness::Liveness;
+use crate::dataflow::{solve, Access};
+use crate::decoder::decode;
+use crate::error::Error;
+use crate::instruction::{Instruction, Operand};
+use crate::metadata::Slots;
+use crate::opcode::OpCode;
+use crate::verifier::stack_map::add_stack_maps;
+use crate::verifier::{ClassHierarchy, TYPE_CHECKING_VERSION};
+use classfile::attribute::{
+    Attribute, AttributeType, CodeAttribute, Exception, LineNumber, LocalVariable,
+    LocalVariableType,
+};
+use classfile::class_file::ClassFile;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// A rewrite of the instructions of a method, returns whether it changed
+/// anything
+type Pass = fn(&CodeAttribute, &mut Vec<Item>) -> Result<bool, Error>;
+
+/// Optimizes every method of `class_file`. A class file of version 50 and
+/// above gets new stack maps, computed with `hierarchy`.
+pub fn optimize_class(
+    class_file: &ClassFile,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<ClassFile, Error> {
+    let mut class_file = class_file.clone();
+    for method in &mut class_file.methods {
+        let index = match method.get_code_attr() {
+            Some(_) => method.code_attr_index.unwrap(),
+            None => continue,
+        };
+        let name_index = method.attributes[index].attribute_name_index;
+        let code = optimize(method.get_code_attr().unwrap())?;
+        method.attributes[index] = Attribute::new(name_index, AttributeType::Code { code })?;
+    }
+    if class_file.major_version < TYPE_CHECKING_VERSION {
+        return Ok(class_file);
+    }
+    add_stack_maps(&class_file, hierarchy)
+}
+
+/// Cleans up the code of a method until nothing changes:
+///
+/// - code that can't be reached is removed
+/// - branches to a `goto` go to its target, a `goto` to a return or `athrow`
+///   is replaced by it and a branch to the next instruction is removed
+/// - `int` arithmetic on constants is folded
+/// - a store to a local variable that is never read again is dropped, as are
+///   a load right after a store of the same variable, a store of the value
+///   just loaded from it and a value pushed only to be popped
+/// - every instruction gets its shortest form
+///
+/// The exception table, `LineNumberTable`, `LocalVariableTable` and
+/// `LocalVariableTypeTable` follow the instructions they refer to, ranges left
+/// empty are dropped. Other attributes of the code, the `StackMapTable` among
+/// them, no longer match and are dropped. `max_stack` and `max_locals` are
+/// kept, the optimized code never needs more. Code with subroutines is
+/// returned unchanged.
+pub fn optimize(code: &CodeAttribute) -> Result<CodeAttribute, Error> {
+    let has_subroutines = decode(&code.code)?.iter().any(|(_, instruction)| {
+        matches!(
+            instruction.opcode,
+            OpCode::jsr | OpCode::jsr_w | OpCode::ret
+        )
+    });
+    if has_subroutines {
+        return Ok(code.clone());
+    }
+
+    let passes: [Pass; 4] = [
+        remove_dead_code,
+        thread_jumps,
+        fold_constants,
+        eliminate_stores,
+    ];
+    // the assembler keeps a goto_w wide, as a goto it is only widened when
+    // its offset doesn't fit
+    let narrowed: Vec<Item> = items(code)?
+        .into_iter()
+        .map(|item| match item {
+            Item::Instruction(instruction) if instruction.opcode == OpCode::goto_w => {
+                Item::Instruction(Instruction::new(OpCode::goto, instruction.operand))
+            }
+            item => item,
+        })
+        .collect();
+    let mut code = rebuild(code, &narrowed)?;
+    loop {
+        let mut changed = false;
+        for pass in passes.iter() {
+            let mut items = items(&code)?;
+            if pass(&code, &mut items)? {
+                code = rebuild(&code, &items)?;
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(code);
+        }
+    }
+}
+
+/// Instructions of `code`, each preceded by a label named after its offset,
+/// and a label for the end of the code
+pub(crate) fn items(code: &CodeAttribute) -> Result<Vec<Item>, Error> {
+    let mut items = vec![];
+    for (pc, instruction) in decode(&code.code)? {
+        items.push(Item::Label(Label(pc)));
+        items.push(Item::Instruction(
+            instruction.map_targets(|target| Ok::<_, Error>(Label(target)))?,
+        ));
+    }
+    items.push(Item::Label(Label(code.code.len() as u32)));
+    Ok(items)
+}
+
+/// Assembles `items` into a copy of `code`, moving the offsets its exception
+/// table and debugging attributes refer to along with the labels
+pub(crate) fn rebuild(code: &CodeAttribute, items: &[Item]) -> Result<CodeAttribute, Error> {
+    let assembled = assemble(items)?;
+    let length = assembled.code.len() as u16;
+    // every label of an offset of the old code is still there
+    let offset = |pc: u16| assembled.labels[&Label(pc as u32)] as u16;
+
+    let exception_table = code
+        .exception_table
+        .iter()
+        .map(|entry| Exception {
+            start_pc: offset(entry.start_pc),
+            end_pc: offset(entry.end_pc),
+            handler_pc: offset(entry.handler_pc),
+            catch_type: entry.catch_type,
+        })
+        .filter(|entry| entry.start_pc < entry.end_pc)
+        .collect();
+
+    let mut attributes = vec![];
+    for attribute in &code.attributes {
+        let attr_type = match &attribute.attr_type {
+            AttributeType::LineNumberTable { line_number_table } => {
+                let mut lines = line_number_table.clone();
+                lines.sort_by_key(|line| line.start_pc);
+                // the line of an instruction removed moves to the next one,
+                // which keeps its own
+                let mut moved: BTreeMap<u16, u16> = BTreeMap::new();
+                for line in lines {
+                    moved.insert(offset(line.start_pc), line.line_number);
+                }
+                AttributeType::LineNumberTable {
+                    line_number_table: moved
+                        .into_iter()
+                        .filter(|(start_pc, _)| *start_pc < length)
+                        .map(|(start_pc, line_number)| LineNumber {
+                            start_pc,
+                            line_number,
+                        })
+                        .collect(),
+                }
+            }
+            AttributeType::LocalVariableTable {
+                local_variable_table,
+            } => AttributeType::LocalVariableTable {
+                local_variable_table: local_variable_table
+                    .iter()
+                    .map(|variable| LocalVariable {
+                        start_pc: offset(variable.start_pc),
+                        length: offset(variable.start_pc + variable.length)
+                            - offset(variable.start_pc),
+                        ..variable.clone()
+                    })
+                    .filter(|variable| variable.length > 0)
+                    .collect(),
+            },
+            AttributeType::LocalVariableTypeTable {
+                local_variable_type_table,
+            } => AttributeType::LocalVariableTypeTable {
+                local_variable_type_table: local_variable_type_table
+                    .iter()
+                    .map(|variable| LocalVariableType {
+                        start_pc: offset(variable.start_pc),
+                        length: offset(variable.start_pc + variable.length)
+                            - offset(variable.start_pc),
+                        ..variable.clone()
+                    })
+                    .filter(|variable| variable.length > 0)
+                    .collect(),
+            },
+            // StackMapTable and type annotations
+            _ => continue,
+        };
+        attributes.push(Attribute::new(attribute.attribute_name_index, attr_type)?);
+    }
+
+    Ok(CodeAttribute {
+        max_stack: code.max_stack,
+        max_locals: code.max_locals,
+        code: Arc::new(assembled.code),
+        exception_table,
+        attributes,
+    })
+}
+
+/// Positions of the instructions among the items and of the instruction
+/// following each label
+struct Layout {
+    /// Item index of each instruction
+    instructions: Vec<usize>,
+    /// Instruction following each label, the number of instructions for a
+    /// label at the end
+    labels: BTreeMap<Label, usize>,
+    /// Labels control can enter the code at other than by falling through:
+    /// branch targets and exception handlers
+    entries: BTreeSet<Label>,
+}
+
+impl Layout {
+    fn new(code: &CodeAttribute, items: &[Item]) -> Layout {
+        let mut layout = Layout {
+            instructions: vec![],
+            labels: BTreeMap::new(),
+            entries: BTreeSet::new(),
+        };
+        for (index, item) in items.iter().enumerate() {
+            match item {
+                Item::Label(label) => {
+                    layout.labels.insert(*label, layout.instructions.len());
+                }
+                Item::Instruction(instruction) => {
+                    layout.instructions.push(index);
+                    layout.entries.extend(instruction.targets());
+                }
+            }
+        }
+        for entry in &code.exception_table {
+            layout.entries.insert(Label(entry.handler_pc as u32));
+        }
+        layout
+    }
+
+    fn instruction<'a>(&self, items: &'a [Item], number: usize) -> &'a Instruction<Label> {
+        match &items[self.instructions[number]] {
+            Item::Instruction(instruction) => instruction,
+            Item::Label(_) => unreachable!(),
+        }
+    }
+
+    /// Whether control can only reach instruction `number` by falling through
+    /// from the one before
+    fn falls_into(&self, items: &[Item], number: usize) -> bool {
+        let (from, to) = (self.instructions[number - 1], self.instructions[number]);
+        !items[from + 1..to]
+            .iter()
+            .any(|item| matches!(item, Item::Label(label) if self.entries.contains(label)))
+    }
+}
+
+/// Removes `removed` instructions, keeping every label
+fn remove(items: &mut Vec<Item>, layout: &Layout, removed: &BTreeSet<usize>) {
+    let removed: BTreeSet<usize> = removed
+        .iter()
+        .map(|number| layout.instructions[*number])
+        .collect();
+    let mut index = 0;
+    items.retain(|_| {
+        index += 1;
+        !removed.contains(&(index - 1))
+    });
+}
+
+fn remove_dead_code(code: &CodeAttribute, items: &mut Vec<Item>) -> Result<bool, Error> {
+    let layout = Layout::new(code, items);
+    let count = layout.instructions.len();
+    let mut reachable = vec![false; count];
+    let mut pending = vec![0];
+    while !pending.is_empty() {
+        while let Some(number) = pending.pop() {
+            if number >= count || reachable[number] {
+                continue;
+            }
+            reachable[number] = true;
+            let instruction = layout.instruction(items, number);
+            pending.extend(instruction.targets().iter().map(|l| layout.labels[l]));
+            if instruction.opcode.info().falls_through() {
+                pending.push(number + 1);
+            }
+        }
+        for entry in &code.exception_table {
+            let start = layout.labels[&Label(entry.start_pc as u32)];
+            let end = layout.labels[&Label(entry.end_pc as u32)];
+            let handler = layout.labels[&Label(entry.handler_pc as u32)];
+            if !reachable[handler] && reachable[start..end].contains(&true) {
+                pending.push(handler);
+            }
+        }
+    }
+    let dead: BTreeSet<usize> = (0..count).filter(|number| !reachable[*number]).collect();
+    remove(items, &layout, &dead);
+    Ok(!dead.is_empty())
+}
+
+fn thread_jumps(code: &CodeAttribute, items: &mut Vec<Item>) -> Result<bool, Error> {
+    let layout = Layout::new(code, items);
+    let count = layout.instructions.len();
+    // the first instruction that isn't a goto along a chain of them
+    let resolve = |label: Label| {
+        let mut label = label;
+        let mut seen = BTreeSet::new();
+        while let Some(&number) = layout.labels.get(&label) {
+            if number == count || !seen.insert(number) {
+                break;
+            }
+            let instruction = layout.instruction(items, number);
+            match (instruction.opcode, &instruction.operand) {
+                (OpCode::goto | OpCode::goto_w, Operand::Branch(target)) => label = *target,
+                _ => break,
+            }
+        }
+        label
+    };
+
+    let mut changed = false;
+    let mut rewritten = vec![];
+    let mut removed = BTreeSet::new();
+    for number in 0..count {
+        let instruction = layout.instruction(items, number);
+        if instruction.targets().is_empty() {
+            continue;
+        }
+        let threaded = instruction
+            .clone()
+            .map_targets(|target| Ok::<_, Error>(resolve(target)))?;
+        let target = match &threaded.operand {
+            Operand::Branch(target) => layout.labels[target],
+            _ => count,
+        };
+        let replacement = if target == number + 1 {
+            // a branch to the next instruction only pops its operands
+            match threaded.opcode.info().pops {
+                _ if matches!(threaded.opcode, OpCode::goto | OpCode::goto_w) => None,
+                Slots::Fixed(1) => Some(Instruction::new(OpCode::pop, Operand::None)),
+                _ => Some(Instruction::new(OpCode::pop2, Operand::None)),
+            }
+        } else if matches!(threaded.opcode, OpCode::goto | OpCode::goto_w) && target < count {
+            let target = layout.instruction(items, target);
+            let leaves = matches!(
+                target.opcode,
+                OpCode::ireturn
+                    | OpCode::lreturn
+                    | OpCode::freturn
+                    | OpCode::dreturn
+                    | OpCode::areturn
+                    | OpCode::vreturn
+                    | OpCode::athrow
+            );
+            Some(if leaves { target.clone() } else { threaded })
+        } else {
+            Some(threaded)
+        };
+        match replacement {
+            None => {
+                removed.insert(number);
+                changed = true;
+            }
+            Some(replacement) if &replacement != instruction => {
+                rewritten.push((layout.instructions[number], replacement));
+                changed = true;
+            }
+            Some(_) => {}
+        }
+    }
+    for (index, instruction) in rewritten {
+        items[index] = Item::Instruction(instruction);
+    }
+    remove(items, &layout, &removed);
+    Ok(changed)
+}
+
+fn fold_constants(code: &CodeAttribute, items: &mut Vec<Item>) -> Result<bool, Error> {
+    let mut changed = false;
+    loop {
+        let layout = Layout::new(code, items);
+        let folded = (0..layout.instructions.len()).find_map(|number| {
+            let instruction = |offset: usize| {
+                (number + offset < layout.instructions.len()
+                    && (offset == 0 || layout.falls_into(items, number + offset)))
+                .then(|| layout.instruction(items, number + offset))
+            };
+            let first = int_constant(instruction(0)?)?;
+            let second = instruction(1)?;
+            let (value, length) = match int_constant(second) {
+                Some(second) => (binary(first, second, instruction(2)?.opcode)?, 3),
+                None => (unary(first, second.opcode)?, 2),
+            };
+            Some((number, push_int(value)?, length))
+        });
+        let (number, constant, length) = match folded {
+            Some(folded) => folded,
+            None => return Ok(changed),
+        };
+        items[layout.instructions[number]] = Item::Instruction(constant);
+        remove(items, &layout, &(number + 1..number + length).collect());
+        changed = true;
+    }
+}
+
+/// Value pushed by `iconst_<i>`, `bipush` or `sipush`
+fn int_constant(instruction: &Instruction<Label>) -> Option<i32> {
+    match (instruction.opcode, &instruction.operand) {
+        (OpCode::bipush, Operand::Byte(value)) => Some(*value as i32),
+        (OpCode::sipush, Operand::Short(value)) => Some(*value as i32),
+        (opcode, _) if (0x02..=0x08).contains(&(opcode as u8)) => Some(opcode as i32 - 0x03),
+        _ => None,
+    }
+}
+
+/// The shortest instruction pushing `value` without a constant pool entry
+pub(crate) fn push_int(value: i32) -> Option<Instruction<Label>> {
+    use std::convert::TryFrom;
+    if (-1..=5).contains(&value) {
+        let opcode = OpCode::try_from((value + 0x03) as u8).ok()?;
+        Some(Instruction::new(opcode, Operand::None))
+    } else if let Ok(value) = i8::try_from(value) {
+        Some(Instruction::new(OpCode::bipush, Operand::Byte(value)))
+    } else {
+        i16::try_from(value)
+            .ok()
+            .map(|value| Instruction::new(OpCode::sipush, Operand::Short(value)))
+    }
+}
+
+fn binary(left: i32, right: i32, opcode: OpCode) -> Option<i32> {
+    Some(match opcode {
+        OpCode::iadd => left.wrapping_add(right),
+        OpCode::isub => left.wrapping_sub(right),
+        OpCode::imul => left.wrapping_mul(right),
+        // division by zero throws
+        OpCode::idiv if right != 0 => left.wrapping_div(right),
+        OpCode::irem if right != 0 => left.wrapping_rem(right),
+        OpCode::iand => left & right,
+        OpCode::ior => left | right,
+        OpCode::ixor => left ^ right,
+        OpCode::ishl => left.wrapping_shl(right as u32 & 0x1f),
+        OpCode::ishr => left.wrapping_shr(right as u32 & 0x1f),
+        OpCode::iushr => ((left as u32) >> (right as u32 & 0x1f)) as i32,
+        _ => return None,
+    })
+}
+
+fn unary(value: i32, opcode: OpCode) -> Option<i32> {
+    Some(match opcode {
+        OpCode::ineg => value.wrapping_neg(),
+        OpCode::i2b => value as i8 as i32,
+        OpCode::i2c => value as u16 as i32,
+        OpCode::i2s => value as i16 as i32,
+        _ => return None,
+    })
+}
+
+fn eliminate_stores(code: &CodeAttribute, items: &mut Vec<Item>) -> Result<bool, Error> {
+    let cfg = ControlFlowGraph::build(code)?;
+    let results = solve(&Liveness, &cfg);
+    let layout = Layout::new(code, items);
+    let count = layout.instructions.len();
+    // the items come straight from the code, each instruction after its label
+    let pc = |number: usize| match &items[layout.instructions[number] - 1] {
+        Item::Label(Label(pc)) => *pc,
+        Item::Instruction(_) => unreachable!(),
+    };
+    let dead_after = |number: usize, slot: u16, width: u16| {
+        results
+            .after(&Liveness, &cfg, pc(number))
+            .is_some_and(|live| (slot..slot + width).all(|s| !live.contains(&s)))
+    };
+
+    let mut removed = BTreeSet::new();
+    let mut popped = vec![];
+    let mut number = 0;
+    while number < count {
+        let instruction = layout.instruction(items, number);
+        let next = (number + 1 < count && layout.falls_into(items, number + 1))
+            .then(|| layout.instruction(items, number + 1));
+        let pair = next.and_then(|next| {
+            let (first, second) = (local_variable(instruction)?, local_variable(next)?);
+            let same = first.kind == second.kind && first.slot == second.slot;
+            match (first.access, second.access) {
+                // the value stays on the stack
+                (Access::Write, Access::Read)
+                    if same && dead_after(number + 1, first.slot, first.width) =>
+                {
+                    Some(())
+                }
+                (Access::Read, Access::Write) if same => Some(()),
+                _ => None,
+            }
+        });
+        let pushed_and_popped = next.is_some_and(|next| {
+            let width = match next.opcode {
+                OpCode::pop => 1,
+                OpCode::pop2 => 2,
+                _ => return false,
+            };
+            pushes_only(instruction) == Some(width)
+        });
+        if pair.is_some() || pushed_and_popped {
+            removed.insert(number);
+            removed.insert(number + 1);
+            number += 2;
+            continue;
+        }
+        if let Some(variable) = local_variable(instruction) {
+            if variable.access == Access::Write && dead_after(number, variable.slot, variable.width)
+            {
+                let opcode = if variable.width == 2 {
+                    OpCode::pop2
+                } else {
+                    OpCode::pop
+                };
+                popped.push((layout.instructions[number], opcode));
+            }
+        }
+        number += 1;
+    }
+    let changed = !removed.is_empty() || !popped.is_empty();
+    for (index, opcode) in popped {
+        items[index] = Item::Instruction(Instruction::new(opcode, Operand::None));
+    }
+    remove(items, &layout, &removed);
+    Ok(changed)
+}
+
+/// A load or store of a local variable
+struct LocalVariableAccess {
+    /// 0 to 4 for int, long, float, double and reference
+    kind: u8,
+    slot: u16,
+    width: u16,
+    access: Access,
+}
+
+fn local_variable(instruction: &Instruction<Label>) -> Option<LocalVariableAccess> {
+    let opcode = instruction.opcode as u8;
+    let (kind, access) = match opcode {
+        0x15..=0x19 => (opcode - 0x15, Access::Read),
+        0x1a..=0x2d => ((opcode - 0x1a) / 4, Access::Read),
+        0x36..=0x3a => (opcode - 0x36, Access::Write),
+        0x3b..=0x4e => ((opcode - 0x3b) / 4, Access::Write),
+        _ => return None,
+    };
+    Some(LocalVariableAccess {
+        kind,
+        slot: instruction.local_index()?,
+        width: if kind == 1 || kind == 3 { 2 } else { 1 },
+        access,
+    })
+}
+
+/// Slots pushed by an instruction that has no other effect
+fn pushes_only(instruction: &Instruction<Label>) -> Option<u16> {
+    let opcode = instruction.opcode as u8;
+    match opcode {
+        // aconst_null, iconst_<i>, fconst_<f>, bipush, sipush
+        0x01..=0x08 | 0x0b..=0x0d | 0x10 | 0x11 => Some(1),
+        // lconst_<l>, dconst_<d>
+        0x09 | 0x0a | 0x0e | 0x0f => Some(2),
+        // dup
+        0x59 => Some(1),
+        _ => local_variable(instruction)
+            .filter(|variable| variable.access == Access::Read)
+            .map(|variable| variable.width),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::decoder::decode;
+    use crate::jasmin::parse;
+    use crate::opcode::OpCode;
+    use crate::optimizer::{optimize, optimize_class};
+    use crate::verifier::test::Hierarchy;
+    use crate::verifier::{type_checker, type_inference};
+    use classfile::attribute::AttributeType;
+    use classfile::class_file::ClassFile;
+    use std::fs::File;
+
+    const UNOPTIMIZED: &str = ".class A
+.super java/lang/Object
+.method static m(I)I
+    .limit stack 2
+    .limit locals 3
+    .catch java/lang/RuntimeException from Start to End using Handler
+Start:
+    .line 1
+    iconst_2
+    iconst_3
+    imul
+    istore_1
+    iload_1
+    iload_0
+    iadd
+    istore_2
+    goto First
+    .line 2
+    iconst_0
+    pop
+First:
+    goto_w Second
+Second:
+    .line 3
+    iload 0
+End:
+    ireturn
+Handler:
+    pop
+    iconst_m1
+    ireturn
+.end method
+";
+
+    #[test]
+    fn optimize_method_code() {
+        let class_file = parse(UNOPTIMIZED).unwrap();
+        let code = class_file.methods[0].get_code_attr().unwrap();
+        let optimized = optimize(code).unwrap();
+        let opcodes: Vec<OpCode> = decode(&optimized.code)
+            .unwrap()
+            .into_iter()
+            .map(|(_, instruction)| instruction.opcode)
+            .collect();
+        // 2 * 3 folded, the store of the sum is popped, the store and load of
+        // the product, dead code and the gotos are gone
+        assert_eq!(
+            opcodes,
+            vec![
+                OpCode::bipush,
+                OpCode::iload_0,
+                OpCode::iadd,
+                OpCode::pop,
+                OpCode::iload_0,
+                OpCode::ireturn,
+                OpCode::pop,
+                OpCode::iconst_m1,
+                OpCode::ireturn,
+            ]
+        );
+        let entry = &optimized.exception_table[0];
+        assert_eq!((entry.start_pc, entry.end_pc, entry.handler_pc), (0, 6, 7));
+        let lines: Vec<(u16, u16)> = match &optimized.attributes[0].attr_type {
+            AttributeType::LineNumberTable { line_number_table } => line_number_table
+                .iter()
+                .map(|line| (line.start_pc, line.line_number))
+                .collect(),
+            _ => panic!("no LineNumberTable"),
+        };
+        assert_eq!(lines, vec![(0, 1), (5, 3)]);
+
+        let mut class_file = class_file.clone();
+        class_file.methods[0].attributes[0].attr_type = AttributeType::Code { code: optimized };
+        type_inference::verify_class(&class_file, &Hierarchy).unwrap();
+
+        let class_file =
+            ClassFile::read_from(File::open("../classfile/tests/Verified.class").unwrap()).unwrap();
+        let optimized = optimize_class(&class_file, &Hierarchy).unwrap();
+        type_checker::verify_class(&optimized, &Hierarchy).unwrap();
+    }
+
+    /// Optimized code of a static method `m(II)I` with `body`, one
+    /// instruction per line preceded by its offset
+    fn listing(body: &str) -> Vec<String> {
+        let source = format!(
+            ".class A\n.super java/lang/Object\n.method static m(II)I\n    .limit stack 4\n    .limit locals 4\n{}\n.end method\n",
+            body
+        );
+        let class_file = parse(&source).unwrap();
+        let optimized = optimize(class_file.methods[0].get_code_attr().unwrap()).unwrap();
+        decode(&optimized.code)
+            .unwrap()
+            .into_iter()
+            .map(|(pc, instruction)| format!("{}: {}", pc, instruction))
+            .collect()
+    }
+
+    #[test]
+    fn removes_unreachable_code() {
+        assert_eq!(
+            listing("    iload_0\n    ireturn\n    iconst_1\n    ireturn"),
+            vec!["0: iload_0", "1: ireturn"]
+        );
+    }
+
+    #[test]
+    fn threads_branches_to_gotos() {
+        // ifeq goes straight to the target of the goto, which is then dead
+        assert_eq!(
+            listing(
+                "    iload_0\n    ifeq Far\n    iconst_1\n    ireturn\nFar:\n    goto Target\n    iconst_2\n    ireturn\nTarget:\n    iconst_3\n    ireturn"
+            ),
+            vec!["0: iload_0", "1: ifeq 6", "4: iconst_1", "5: ireturn", "6: iconst_3", "7: ireturn"]
+        );
+    }
+
+    #[test]
+    fn replaces_gotos_to_returns() {
+        assert_eq!(
+            listing(
+                "    iload_0\n    ifeq Zero\n    iload_1\n    goto Exit\nZero:\n    iconst_2\nExit:\n    ireturn"
+            ),
+            vec!["0: iload_0", "1: ifeq 6", "4: iload_1", "5: ireturn", "6: iconst_2", "7: ireturn"]
+        );
+    }
+
+    #[test]
+    fn removes_branches_to_the_next_instruction() {
+        // the operands of if_icmpeq are popped, the operand of ifne never
+        // pushed
+        assert_eq!(
+            listing(
+                "    iload_0\n    iload_1\n    if_icmpeq Next\nNext:\n    iload_0\n    ifne Then\nThen:\n    iload_0\n    ireturn"
+            ),
+            vec!["0: iload_0", "1: iload_1", "2: pop2", "3: iload_0", "4: ireturn"]
+        );
+    }
+
+    #[test]
+    fn folds_int_constants() {
+        // 2 * 3 + -100
+        assert_eq!(
+            listing("    iconst_2\n    iconst_3\n    imul\n    bipush 100\n    ineg\n    iadd\n    ireturn"),
+            vec!["0: bipush -94", "2: ireturn"]
+        );
+        // division by zero throws, so it stays
+        assert_eq!(
+            listing("    iconst_1\n    iconst_0\n    idiv\n    ireturn"),
+            vec!["0: iconst_1", "1: iconst_0", "2: idiv", "3: ireturn"]
+        );
+    }
+
+    #[test]
+    fn eliminates_stores() {
+        // a store never read again
+        assert_eq!(
+            listing("    iload_0\n    iload_1\n    iadd\n    istore_2\n    iload_1\n    ireturn"),
+            vec![
+                "0: iload_0",
+                "1: iload_1",
+                "2: iadd",
+                "3: pop",
+                "4: iload_1",
+                "5: ireturn"
+            ]
+        );
+        // a load right after a store of the same variable
+        assert_eq!(
+            listing("    iload_0\n    istore_2\n    iload_2\n    ireturn"),
+            vec!["0: iload_0", "1: ireturn"]
+        );
+        // a store of the value just loaded from the variable
+        assert_eq!(
+            listing("    iload_1\n    istore_1\n    iload_1\n    ireturn"),
+            vec!["0: iload_1", "1: ireturn"]
+        );
+        // a value pushed only to be popped
+        assert_eq!(
+            listing("    iconst_4\n    pop\n    lconst_1\n    pop2\n    iload_0\n    ireturn"),
+            vec!["0: iload_0", "1: ireturn"]
+        );
+    }
+
+    #[test]
+    fn picks_the_shortest_forms() {
+        assert_eq!(
+            listing(
+                "    iload 1\n    ifeq Zero\n    iconst_1\n    goto_w Add\nZero:\n    iconst_2\nAdd:\n    iload_0\n    iadd\n    ireturn"
+            ),
+            vec![
+                "0: iload_1",
+                "1: ifeq 8",
+                "4: iconst_1",
+                "5: goto 9",
+                "8: iconst_2",
+                "9: iload_0",
+                "10: iadd",
+                "11: ireturn",
+            ]
+        );
+    }
+}
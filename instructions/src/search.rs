@@ -0,0 +1,400 @@
+//! Search of method code for instruction sequences, e.g. every call of
+//! `java/lang/Thread.stop` or every `new` of a deprecated class.
+//!
+//! A query is a list of patterns separated by `;` or newlines, matching
+//! consecutive instructions. A pattern is an opcode name followed by an
+//! optional operand, both of which may use the `*` (any run of characters)
+//! and `?` (any character) wildcards. A pattern without an operand matches any
+//! operand, and `...` matches any run of instructions, so
+//!
+//! ```text
+//! new java/io/File*; ...; invokespecial java/io/File*.<init>
+//! ```
+//!
+//! matches the construction of a `File` or of one of its `java/io` siblings.
+//!
+//! Operands are written the way they resolve in the constant pool:
+//!
+//! * field and method references as `owner.name:descriptor`, the descriptor
+//!   being optional in the pattern
+//! * classes by their internal name, `java/lang/String`
+//! * strings in double quotes, unescaped, other constants as numbers
+//! * `invokedynamic` call sites and dynamic constants as `name:descriptor`
+//! * locals by index, `iinc` as `index constant` and branches by target offset
+//! * `newarray` by element type and `multianewarray` as `class dimensions`
+
+use crate::constant_pool::Pool;
+use crate::decoder::decode;
+use crate::error::Error;
+use crate::instruction::{Instruction, Operand};
+use classfile::attribute::{AttributeType, CodeAttribute};
+use classfile::class_file::ClassFile;
+use classfile::constant::Constant;
+use std::fmt;
+
+/// A parsed query, see the module documentation for the syntax
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+    Instruction {
+        opcode: String,
+        operand: Option<String>,
+    },
+    /// `...`
+    Gap,
+}
+
+/// Instruction sequence found by `Query::search`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Internal name of the class
+    pub class: String,
+    pub method: String,
+    pub descriptor: String,
+    /// Offset of the first instruction of the sequence
+    pub pc: u32,
+    /// Source line of `pc`, if the code has a `LineNumberTable`
+    pub line: Option<u16>,
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}{} pc {}",
+            self.class, self.method, self.descriptor, self.pc
+        )?;
+        if let Some(line) = self.line {
+            write!(f, " line {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Query {
+    /// Fails with `InvalidPattern` on an empty query or one made of gaps only,
+    /// and on an operand given to `...`
+    pub fn parse(text: &str) -> Result<Query, Error> {
+        let mut patterns = vec![];
+        for pattern in text.split([';', '\n']) {
+            let mut words = pattern.split_whitespace();
+            let opcode = match words.next() {
+                Some(opcode) => opcode,
+                None => continue,
+            };
+            let operand = words.collect::<Vec<_>>().join(" ");
+            if opcode == "..." {
+                if !operand.is_empty() {
+                    return Err(Error::InvalidPattern(pattern.trim().to_string()));
+                }
+                // Consecutive gaps match the same as one
+                if patterns.last() != Some(&Pattern::Gap) {
+                    patterns.push(Pattern::Gap);
+                }
+            } else {
+                patterns.push(Pattern::Instruction {
+                    opcode: opcode.to_string(),
+                    operand: if operand.is_empty() {
+                        None
+                    } else {
+                        Some(operand)
+                    },
+                });
+            }
+        }
+        if patterns.iter().all(|pattern| *pattern == Pattern::Gap) {
+            return Err(Error::InvalidPattern(text.trim().to_string()));
+        }
+        // Leading and trailing gaps don't change where a sequence starts
+        while patterns.first() == Some(&Pattern::Gap) {
+            patterns.remove(0);
+        }
+        while patterns.last() == Some(&Pattern::Gap) {
+            patterns.pop();
+        }
+        Ok(Query { patterns })
+    }
+
+    /// Matches in the code of every method of `class_file`, in method order
+    /// then by offset. Sequences may overlap but start at distinct offsets.
+    pub fn search(&self, class_file: &ClassFile) -> Result<Vec<Match>, Error> {
+        let pool = Pool(&class_file.constant_pool);
+        let class = pool.class_name(class_file.this_class)?;
+        let mut found = vec![];
+        for method in &class_file.methods {
+            let code = match method.get_code_attr() {
+                Some(code) => code,
+                None => continue,
+            };
+            let instructions = decode(&code.code)?;
+            let operands = if self.has_operands() {
+                instructions
+                    .iter()
+                    .map(|(_, instruction)| operand(pool, instruction))
+                    .collect::<Result<Vec<_>, Error>>()?
+            } else {
+                vec![]
+            };
+            for start in 0..instructions.len() {
+                if !matches(&self.patterns, &instructions, &operands, start) {
+                    continue;
+                }
+                let pc = instructions[start].0;
+                found.push(Match {
+                    class: class.clone(),
+                    method: pool.utf8(method.name_index)?,
+                    descriptor: pool.utf8(method.descriptor_index)?,
+                    pc,
+                    line: line(code, pc),
+                });
+            }
+        }
+        Ok(found)
+    }
+
+    fn has_operands(&self) -> bool {
+        self.patterns.iter().any(|pattern| match pattern {
+            Pattern::Instruction { operand, .. } => operand.is_some(),
+            Pattern::Gap => false,
+        })
+    }
+}
+
+/// Whether `patterns` match the instructions from `start` on
+fn matches(
+    patterns: &[Pattern],
+    instructions: &[(u32, Instruction)],
+    operands: &[(String, bool)],
+    start: usize,
+) -> bool {
+    let (first, rest) = match patterns.split_first() {
+        Some(split) => split,
+        None => return true,
+    };
+    match first {
+        Pattern::Gap => {
+            (start..=instructions.len()).any(|next| matches(rest, instructions, operands, next))
+        }
+        Pattern::Instruction { opcode, operand } => {
+            let instruction = match instructions.get(start) {
+                Some((_, instruction)) => instruction,
+                None => return false,
+            };
+            if !glob(opcode, instruction.opcode.name()) {
+                return false;
+            }
+            if let Some(pattern) = operand {
+                let (text, reference) = &operands[start];
+                let matched = glob(pattern, text)
+                    || (*reference
+                        && !pattern.contains(':')
+                        && glob(pattern, text.split(':').next().unwrap()));
+                if !matched {
+                    return false;
+                }
+            }
+            matches(rest, instructions, operands, start + 1)
+        }
+    }
+}
+
+/// Text of the operand as described in the module documentation, and whether
+/// it names a member or call site whose descriptor a pattern may leave out
+fn operand(pool: Pool, instruction: &Instruction) -> Result<(String, bool), Error> {
+    Ok(match &instruction.operand {
+        Operand::None | Operand::TableSwitch { .. } | Operand::LookupSwitch { .. } => {
+            (String::new(), false)
+        }
+        Operand::Byte(value) => (value.to_string(), false),
+        Operand::Short(value) => (value.to_string(), false),
+        Operand::Constant(index) => constant(pool, *index)?,
+        Operand::Local { index, .. } => (index.to_string(), false),
+        Operand::Iinc {
+            index, constant, ..
+        } => (format!("{} {}", index, constant), false),
+        Operand::Branch(target) => (target.to_string(), false),
+        Operand::InvokeInterface { index, .. } => constant(pool, *index)?,
+        Operand::NewArray(array_type) => (array_type.name().to_string(), false),
+        Operand::MultiANewArray { index, dimensions } => (
+            format!("{} {}", pool.class_name(*index)?, dimensions),
+            false,
+        ),
+    })
+}
+
+fn constant(pool: Pool, index: u16) -> Result<(String, bool), Error> {
+    Ok(match pool.constant(index)? {
+        Constant::Class { .. } => (pool.class_name(index)?, false),
+        Constant::FieldRef { .. }
+        | Constant::MethodRef { .. }
+        | Constant::InterfaceMethodRef { .. } => (member(pool, index)?, true),
+        Constant::String { string_index } => (format!("\"{}\"", pool.utf8(*string_index)?), false),
+        Constant::Integer(value) => (value.to_string(), false),
+        Constant::Float(value) => (value.to_string(), false),
+        Constant::Long(value) => (value.to_string(), false),
+        Constant::Double(value) => (value.to_string(), false),
+        Constant::MethodType { descriptor_index } => (pool.utf8(*descriptor_index)?, false),
+        Constant::MethodHandle {
+            reference_index, ..
+        } => (member(pool, *reference_index)?, true),
+        Constant::Dynamic {
+            name_and_type_index,
+            ..
+        }
+        | Constant::InvokeDynamic {
+            name_and_type_index,
+            ..
+        } => {
+            let (name, descriptor) = pool.name_and_type(*name_and_type_index)?;
+            (format!("{}:{}", name, descriptor), true)
+        }
+        _ => return Err(Error::InvalidConstant(index)),
+    })
+}
+
+fn member(pool: Pool, index: u16) -> Result<String, Error> {
+    let (class, name, descriptor) = pool.member(index)?;
+    Ok(format!("{}.{}:{}", class, name, descriptor))
+}
+
+/// Line of the last `LineNumberTable` entry starting at or before `pc`
+fn line(code: &CodeAttribute, pc: u32) -> Option<u16> {
+    code.attributes
+        .iter()
+        .filter_map(|attribute| match &attribute.attr_type {
+            AttributeType::LineNumberTable { line_number_table } => Some(line_number_table),
+            _ => None,
+        })
+        .flatten()
+        .filter(|line_number| line_number.start_pc as u32 <= pc)
+        .max_by_key(|line_number| line_number.start_pc)
+        .map(|line_number| line_number.line_number)
+}
+
+/// Whether `text` matches `pattern` as a whole, `*` standing for any run of
+/// characters and `?` for any one
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it was tried against
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, start)) => {
+                    p = star;
+                    t = start + 1;
+                    backtrack = Some((star, start + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jasmin::parse;
+
+    const THREADS: &str = r#"
+.class public Threads
+.super java/lang/Object
+
+.method public static halt(Ljava/lang/Thread;)V
+    .limit stack 2
+    .limit locals 1
+    .line 10
+    aload_0
+    invokevirtual java/lang/Thread/stop()V
+    .line 11
+    new java/io/File
+    dup
+    ldc "tmp"
+    invokespecial java/io/File/<init>(Ljava/lang/String;)V
+    pop
+    .line 12
+    aload_0
+    invokevirtual java/lang/Thread/interrupt()V
+    return
+.end method
+"#;
+
+    fn search(query: &str) -> Vec<String> {
+        let class_file = parse(THREADS).unwrap();
+        Query::parse(query)
+            .unwrap()
+            .search(&class_file)
+            .unwrap()
+            .iter()
+            .map(|m| m.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(super::glob("invoke*", "invokevirtual"));
+        assert!(super::glob("*load_?", "aload_0"));
+        assert!(super::glob("a*b*c", "aXbYbZc"));
+        assert!(!super::glob("a*b*c", "aXbYcZ"));
+        assert!(!super::glob("?", ""));
+        assert!(super::glob("*", ""));
+    }
+
+    #[test]
+    fn invocations() {
+        assert_eq!(
+            search("invokevirtual java/lang/Thread.stop"),
+            ["Threads.halt(Ljava/lang/Thread;)V pc 1 line 10"]
+        );
+        assert_eq!(search("invokevirtual java/lang/Thread.*:()V").len(), 2);
+        assert!(search("invokevirtual java/lang/Thread.stop:(J)V").is_empty());
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(
+            search("new java/io/File; ...; invokespecial *.<init>"),
+            ["Threads.halt(Ljava/lang/Thread;)V pc 4 line 11"]
+        );
+        assert_eq!(
+            search("aload_0\n*"),
+            [
+                "Threads.halt(Ljava/lang/Thread;)V pc 0 line 10",
+                "Threads.halt(Ljava/lang/Thread;)V pc 14 line 12"
+            ]
+        );
+        assert_eq!(search("dup; ldc \"t*\"").len(), 1);
+        assert!(search("new; invokespecial").is_empty());
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            Query::parse(" ; "),
+            Err(Error::InvalidPattern(";".to_string()))
+        );
+        assert_eq!(
+            Query::parse("...; ..."),
+            Err(Error::InvalidPattern("...; ...".to_string()))
+        );
+        assert_eq!(
+            Query::parse("new; ... x"),
+            Err(Error::InvalidPattern("... x".to_string()))
+        );
+    }
+}
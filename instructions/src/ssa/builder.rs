@@ -0,0 +1,1158 @@
+use crate::decoder::decode;
+use crate::error::Error;
+use crate::instruction::{Instruction, Operand};
+use crate::metadata::{member_descriptor, Flow};
+use crate::opcode::OpCode;
+use crate::ssa::{
+    BasicBlock, BinaryOp, Block, Condition, Definition, ElementType, Function, Handler, InvokeKind,
+    Op, Terminator, Type, Value,
+};
+use crate::subroutine::inline_subroutines;
+use classfile::constant::Constant;
+use classfile::descriptor::{FieldType, MethodDescriptor};
+use classfile::method::MethodInfo;
+use classfile::ConstantPoolRef;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+
+const CONDITIONS: [Condition; 6] = [
+    Condition::Eq,
+    Condition::Ne,
+    Condition::Lt,
+    Condition::Ge,
+    Condition::Gt,
+    Condition::Le,
+];
+
+/// Lifts the code of `method` into SSA form.
+///
+/// Subroutines are inlined first. Blocks start where basic blocks of the
+/// control flow graph do and after each instruction that can throw within the
+/// range of an exception handler, so exceptions only leave a block at its end.
+/// The entry block defines the parameters, a handler branched to as well as
+/// thrown to gets a block of its own for the exception caught.
+///
+/// A phi is placed for every local variable and stack slot at the start of
+/// every block, those merging a single value or only used by phis are then
+/// removed. A local variable holding values of different types at a merge is
+/// dropped there, it can't be loaded until stored again.
+pub fn build(method: &MethodInfo, constant_pool: &ConstantPoolRef) -> Result<Function, Error> {
+    let code = method.get_code_attr().ok_or(Error::MissingCode)?;
+    let has_subroutines = decode(&code.code)?.iter().any(|(_, instruction)| {
+        matches!(
+            instruction.opcode,
+            OpCode::jsr | OpCode::jsr_w | OpCode::ret
+        )
+    });
+    let code = if has_subroutines {
+        Cow::Owned(inline_subroutines(code)?)
+    } else {
+        Cow::Borrowed(code)
+    };
+
+    let index = method.descriptor_index;
+    let descriptor = match (index as usize)
+        .checked_sub(1)
+        .and_then(|i| constant_pool.get(i))
+    {
+        Some(Constant::Utf8(bytes)) => {
+            MethodDescriptor::parse(bytes).map_err(|_| Error::InvalidConstant(index))?
+        }
+        _ => return Err(Error::InvalidConstant(index)),
+    };
+    let mut parameters = vec![];
+    let mut slot = 0;
+    if !method.flags().is_static() {
+        parameters.push((0, Type::Reference));
+        slot = 1;
+    }
+    for parameter in &descriptor.parameters {
+        let ty = Type::from_field_type(parameter);
+        parameters.push((slot, ty));
+        slot += ty.size();
+    }
+    if slot > code.max_locals {
+        return Err(Error::InvalidLocal(0));
+    }
+
+    let mut builder = Builder {
+        constant_pool,
+        instructions: decode(&code.code)?,
+        sources: vec![],
+        blocks: HashMap::new(),
+        parameters,
+        max_locals: code.max_locals as usize,
+        function: Function {
+            blocks: vec![],
+            values: vec![],
+        },
+        current: Block(0),
+    };
+    builder.split(&code.exception_table)?;
+    builder.construct()?;
+    let mut function = builder.function;
+    remove_phis(&mut function);
+    Ok(compact(function))
+}
+
+/// Where a block comes from
+#[derive(Debug, Clone)]
+struct Source {
+    /// Offset of the first instruction, or of the handler
+    start: u32,
+    /// Indices of the instructions, empty for the entry block and the blocks
+    /// of handlers also reached without an exception
+    instructions: Range<usize>,
+    /// Whether the block starts with the exception caught
+    catches: bool,
+    /// Block an empty block continues in
+    next: Option<Block>,
+    /// Handlers of the last instruction
+    handlers: Vec<Handler>,
+}
+
+/// Values of the variables at a point of the code
+#[derive(Debug, Clone)]
+struct State {
+    locals: Vec<Option<Value>>,
+    stack: Vec<Value>,
+}
+
+/// Types of the variables at the start of a block
+#[derive(Debug, Clone, PartialEq)]
+struct Shape {
+    locals: Vec<Option<Type>>,
+    stack: Vec<Type>,
+}
+
+/// Variable a phi stands for
+#[derive(Debug, Clone, Copy)]
+enum Variable {
+    Local(usize),
+    Stack(usize),
+}
+
+/// Values leaving a block
+struct Exit {
+    end: State,
+    /// Local variables before the last instruction, for the handlers
+    thrown: Vec<Option<Value>>,
+}
+
+struct Builder<'a> {
+    constant_pool: &'a ConstantPoolRef,
+    instructions: Vec<(u32, Instruction)>,
+    sources: Vec<Source>,
+    /// Block of the instruction at each offset starting one
+    blocks: HashMap<u32, Block>,
+    parameters: Vec<(u16, Type)>,
+    max_locals: usize,
+    function: Function,
+    /// Block operations are added to
+    current: Block,
+}
+
+impl<'a> Builder<'a> {
+    /// Splits the instructions into blocks
+    fn split(&mut self, exception_table: &[classfile::attribute::Exception]) -> Result<(), Error> {
+        let offsets: BTreeSet<u32> = self.instructions.iter().map(|(pc, _)| *pc).collect();
+        let covered = |pc: u32| {
+            exception_table
+                .iter()
+                .any(|entry| entry.start_pc as u32 <= pc && pc < entry.end_pc as u32)
+        };
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for (index, (pc, instruction)) in self.instructions.iter().enumerate() {
+            for target in instruction.targets() {
+                if !offsets.contains(target) {
+                    return Err(Error::InvalidBranchTarget(*pc, *target as i64));
+                }
+                leaders.insert(*target);
+            }
+            let info = instruction.opcode.info();
+            if info.flow != Flow::Next || (info.can_throw && covered(*pc)) {
+                if let Some((next, _)) = self.instructions.get(index + 1) {
+                    leaders.insert(*next);
+                }
+            }
+        }
+        for (index, entry) in exception_table.iter().enumerate() {
+            if !offsets.contains(&(entry.handler_pc as u32)) {
+                return Err(Error::InvalidExceptionTable(index as u16));
+            }
+            leaders.insert(entry.handler_pc as u32);
+        }
+
+        // the entry block, then one per group of instructions
+        self.sources.push(Source {
+            start: 0,
+            instructions: 0..0,
+            catches: false,
+            next: Some(Block(1)),
+            handlers: vec![],
+        });
+        let mut reached = BTreeSet::new();
+        reached.insert(0);
+        for (index, (pc, instruction)) in self.instructions.iter().enumerate() {
+            if leaders.contains(pc) {
+                self.blocks.insert(*pc, Block(self.sources.len()));
+                self.sources.push(Source {
+                    start: *pc,
+                    instructions: index..index,
+                    catches: false,
+                    next: None,
+                    handlers: vec![],
+                });
+            }
+            self.sources.last_mut().unwrap().instructions.end = index + 1;
+            let last = index + 1 == self.instructions.len()
+                || leaders.contains(&self.instructions[index + 1].0);
+            if last {
+                reached.extend(instruction.targets().into_iter().copied());
+                if instruction.opcode.info().falls_through() {
+                    reached.extend(self.instructions.get(index + 1).map(|(next, _)| *next));
+                }
+            }
+        }
+
+        // handlers reached without an exception get a block for the exception
+        let mut landings = HashMap::new();
+        for entry in exception_table {
+            let handler = entry.handler_pc as u32;
+            if landings.contains_key(&handler) {
+                continue;
+            }
+            let block = self.blocks[&handler];
+            if reached.contains(&handler) {
+                landings.insert(handler, Block(self.sources.len()));
+                self.sources.push(Source {
+                    start: handler,
+                    instructions: 0..0,
+                    catches: true,
+                    next: Some(block),
+                    handlers: vec![],
+                });
+            } else {
+                landings.insert(handler, block);
+                self.sources[block.0].catches = true;
+            }
+        }
+
+        for source in &mut self.sources {
+            let last = match source.instructions.clone().last() {
+                Some(last) => last,
+                None => continue,
+            };
+            let (pc, instruction) = &self.instructions[last];
+            if !instruction.opcode.info().can_throw {
+                continue;
+            }
+            for entry in exception_table {
+                if entry.start_pc as u32 <= *pc && *pc < entry.end_pc as u32 {
+                    let handler = Handler {
+                        catch_type: entry.catch_type,
+                        block: landings[&(entry.handler_pc as u32)],
+                    };
+                    if !source.handlers.iter().any(|h| h.block == handler.block) {
+                        source.handlers.push(handler);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the types of the variables entering each block, then defines the
+    /// values of each block and connects the phis to them
+    fn construct(&mut self) -> Result<(), Error> {
+        let count = self.sources.len();
+        self.function.blocks = (0..count)
+            .map(|_| BasicBlock {
+                phis: vec![],
+                instructions: vec![],
+                terminator: Terminator::Return(None),
+                handlers: vec![],
+            })
+            .collect();
+
+        let mut shapes: Vec<Option<Shape>> = vec![None; count];
+        shapes[0] = Some(Shape {
+            locals: vec![None; self.max_locals],
+            stack: vec![],
+        });
+        let mut pending = vec![Block(0)];
+        while let Some(block) = pending.pop() {
+            let (state, _) = self.enter(block, shapes[block.0].as_ref().unwrap());
+            let exit = self.execute(block, state)?;
+            let (successors, handlers) = self.successors(block);
+            for successor in successors {
+                let shape = self.shape(&exit.end.locals, &exit.end.stack);
+                if self.merge(&mut shapes, successor, shape)? {
+                    pending.push(successor);
+                }
+            }
+            for handler in handlers {
+                let mut shape = self.shape(&exit.thrown, &[]);
+                shape.stack.push(Type::Reference);
+                if self.merge(&mut shapes, handler, shape)? {
+                    pending.push(handler);
+                }
+            }
+        }
+
+        // values defined while finding the types are dropped
+        self.function.values.clear();
+        let mut variables = vec![vec![]; count];
+        let mut exits = vec![];
+        for (index, shape) in shapes.iter().enumerate() {
+            let block = Block(index);
+            exits.push(match shape {
+                Some(shape) => {
+                    let (state, phis) = self.enter(block, shape);
+                    variables[index] = phis;
+                    Some(self.execute(block, state)?)
+                }
+                None => None,
+            });
+        }
+        for (index, exit) in exits.iter().enumerate() {
+            let exit = match exit {
+                Some(exit) => exit,
+                None => continue,
+            };
+            let (successors, handlers) = self.successors(Block(index));
+            let edges = successors
+                .into_iter()
+                .map(|successor| (successor, &exit.end.locals, &exit.end.stack[..]))
+                .chain(
+                    handlers
+                        .into_iter()
+                        .map(|handler| (handler, &exit.thrown, &[][..])),
+                );
+            for (successor, locals, stack) in edges {
+                for (phi, variable) in &variables[successor.0] {
+                    let value = match variable {
+                        // a variable the successor has is defined in every
+                        // predecessor
+                        Variable::Local(local) => locals[*local].unwrap(),
+                        Variable::Stack(slot) => stack[*slot],
+                    };
+                    if let Op::Phi(operands) = &mut self.function.values[phi.0].op {
+                        operands.push((Block(index), value));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Distinct normal successors and handlers of a block
+    fn successors(&self, block: Block) -> (Vec<Block>, Vec<Block>) {
+        let mut successors = vec![];
+        for successor in self.function.blocks[block.0].terminator.successors() {
+            if !successors.contains(&successor) {
+                successors.push(successor);
+            }
+        }
+        let handlers = self.sources[block.0]
+            .handlers
+            .iter()
+            .map(|handler| handler.block)
+            .collect();
+        (successors, handlers)
+    }
+
+    fn shape(&self, locals: &[Option<Value>], stack: &[Value]) -> Shape {
+        Shape {
+            locals: locals
+                .iter()
+                .map(|local| local.and_then(|value| self.function.ty(value)))
+                .collect(),
+            stack: stack
+                .iter()
+                .filter_map(|value| self.function.ty(*value))
+                .collect(),
+        }
+    }
+
+    /// Merges the variables reaching `block` from one more predecessor,
+    /// returns whether that changed them
+    fn merge(
+        &self,
+        shapes: &mut [Option<Shape>],
+        block: Block,
+        shape: Shape,
+    ) -> Result<bool, Error> {
+        let current = match &mut shapes[block.0] {
+            Some(current) => current,
+            None => {
+                shapes[block.0] = Some(shape);
+                return Ok(true);
+            }
+        };
+        let pc = self.sources[block.0].start;
+        if current.stack.len() != shape.stack.len() {
+            return Err(Error::InconsistentStackHeight(pc));
+        }
+        if current.stack != shape.stack {
+            return Err(Error::StackTypeMismatch(pc));
+        }
+        let mut changed = false;
+        for (current, merged) in current.locals.iter_mut().zip(shape.locals) {
+            if current.is_some() && *current != merged {
+                *current = None;
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Values of the variables entering a block: the parameters in the entry
+    /// block, phis elsewhere. Returns the variable of each phi.
+    fn enter(&mut self, block: Block, shape: &Shape) -> (State, Vec<(Value, Variable)>) {
+        self.current = block;
+        self.function.blocks[block.0].phis.clear();
+        self.function.blocks[block.0].instructions.clear();
+        let mut state = State {
+            locals: vec![None; self.max_locals],
+            stack: vec![],
+        };
+        let mut phis = vec![];
+        if block == Block(0) {
+            for (slot, ty) in self.parameters.clone() {
+                state.locals[slot as usize] = Some(self.emit(Op::Parameter(slot), Some(ty), None));
+            }
+            return (state, phis);
+        }
+        let mut phi = |builder: &mut Builder, ty: Type, variable: Variable| {
+            let value = builder.define(Op::Phi(vec![]), Some(ty), None);
+            builder.function.blocks[block.0].phis.push(value);
+            phis.push((value, variable));
+            value
+        };
+        for (local, ty) in shape.locals.iter().enumerate() {
+            if let Some(ty) = ty {
+                state.locals[local] = Some(phi(self, *ty, Variable::Local(local)));
+            }
+        }
+        // the exception caught isn't merged
+        if !self.sources[block.0].catches {
+            for (slot, ty) in shape.stack.iter().enumerate() {
+                state.stack.push(phi(self, *ty, Variable::Stack(slot)));
+            }
+        }
+        (state, phis)
+    }
+
+    /// Defines the values of the instructions of a block and its terminator
+    fn execute(&mut self, block: Block, mut state: State) -> Result<Exit, Error> {
+        self.current = block;
+        let source = self.sources[block.0].clone();
+        if source.catches {
+            let caught = self.emit(
+                Op::CaughtException,
+                Some(Type::Reference),
+                Some(source.start),
+            );
+            state.stack.push(caught);
+        }
+        let mut thrown = state.locals.clone();
+        let mut terminator = source.next.map(Terminator::Goto);
+        for index in source.instructions.clone() {
+            let (pc, instruction) = self.instructions[index].clone();
+            let next = self.instructions.get(index + 1).map(|(next, _)| *next);
+            thrown = state.locals.clone();
+            terminator = self.step(&mut state, pc, &instruction, next)?;
+            if terminator.is_none() && index + 1 == source.instructions.end {
+                let next = next.ok_or(Error::UnexpectedEnd(pc))?;
+                terminator = Some(Terminator::Goto(self.block_at(pc, next)?));
+            }
+        }
+        let data = &mut self.function.blocks[block.0];
+        data.terminator = terminator.unwrap();
+        data.handlers = source.handlers;
+        Ok(Exit { end: state, thrown })
+    }
+
+    /// Defines the values of an instruction, returns the terminator of the
+    /// block if it ends one
+    fn step(
+        &mut self,
+        state: &mut State,
+        pc: u32,
+        instruction: &Instruction,
+        next: Option<u32>,
+    ) -> Result<Option<Terminator>, Error> {
+        use OpCode::*;
+        let opcode = instruction.opcode;
+        match opcode {
+            nop => {}
+            aconst_null => self.push(state, pc, Op::Null, Type::Reference),
+            iconst_m1 | iconst_0 | iconst_1 | iconst_2 | iconst_3 | iconst_4 | iconst_5 => {
+                let value = opcode as i32 - iconst_0 as i32;
+                self.push(state, pc, Op::Int(value), Type::Int)
+            }
+            bipush | sipush => {
+                let value = match instruction.operand {
+                    Operand::Byte(value) => value as i32,
+                    Operand::Short(value) => value as i32,
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                self.push(state, pc, Op::Int(value), Type::Int)
+            }
+            lconst_0 | lconst_1 => {
+                let value = (opcode as u8 - lconst_0 as u8) as i64;
+                self.push(state, pc, Op::Long(value), Type::Long)
+            }
+            fconst_0 | fconst_1 | fconst_2 => {
+                let value = (opcode as u8 - fconst_0 as u8) as f32;
+                self.push(state, pc, Op::Float(value), Type::Float)
+            }
+            dconst_0 | dconst_1 => {
+                let value = (opcode as u8 - dconst_0 as u8) as f64;
+                self.push(state, pc, Op::Double(value), Type::Double)
+            }
+            ldc | ldc_w | ldc2_w => {
+                let (op, ty) = self.constant(constant_index(instruction)?)?;
+                self.push(state, pc, op, ty)
+            }
+
+            // Loads
+            iload | iload_0 | iload_1 | iload_2 | iload_3 => {
+                self.load(state, pc, instruction, Type::Int)?
+            }
+            lload | lload_0 | lload_1 | lload_2 | lload_3 => {
+                self.load(state, pc, instruction, Type::Long)?
+            }
+            fload | fload_0 | fload_1 | fload_2 | fload_3 => {
+                self.load(state, pc, instruction, Type::Float)?
+            }
+            dload | dload_0 | dload_1 | dload_2 | dload_3 => {
+                self.load(state, pc, instruction, Type::Double)?
+            }
+            aload | aload_0 | aload_1 | aload_2 | aload_3 => {
+                self.load(state, pc, instruction, Type::Reference)?
+            }
+            iaload => self.array_load(state, pc, ElementType::Int)?,
+            laload => self.array_load(state, pc, ElementType::Long)?,
+            faload => self.array_load(state, pc, ElementType::Float)?,
+            daload => self.array_load(state, pc, ElementType::Double)?,
+            aaload => self.array_load(state, pc, ElementType::Reference)?,
+            baload => self.array_load(state, pc, ElementType::Byte)?,
+            caload => self.array_load(state, pc, ElementType::Char)?,
+            saload => self.array_load(state, pc, ElementType::Short)?,
+
+            // Stores
+            istore | istore_0 | istore_1 | istore_2 | istore_3 | lstore | lstore_0 | lstore_1
+            | lstore_2 | lstore_3 | fstore | fstore_0 | fstore_1 | fstore_2 | fstore_3 | dstore
+            | dstore_0 | dstore_1 | dstore_2 | dstore_3 | astore | astore_0 | astore_1
+            | astore_2 | astore_3 => {
+                let value = self.pop(state, pc)?;
+                let index = instruction.local_index().unwrap();
+                self.set_local(state, pc, index, value)?;
+            }
+            iastore => self.array_store(state, pc, ElementType::Int)?,
+            lastore => self.array_store(state, pc, ElementType::Long)?,
+            fastore => self.array_store(state, pc, ElementType::Float)?,
+            dastore => self.array_store(state, pc, ElementType::Double)?,
+            aastore => self.array_store(state, pc, ElementType::Reference)?,
+            bastore => self.array_store(state, pc, ElementType::Byte)?,
+            castore => self.array_store(state, pc, ElementType::Char)?,
+            sastore => self.array_store(state, pc, ElementType::Short)?,
+
+            // Stack
+            pop => {
+                self.pop_slots(state, pc, 1)?;
+            }
+            pop2 => {
+                self.pop_slots(state, pc, 2)?;
+            }
+            dup => self.dup(state, pc, 1, 0)?,
+            dup_x1 => self.dup(state, pc, 1, 1)?,
+            dup_x2 => self.dup(state, pc, 1, 2)?,
+            dup2 => self.dup(state, pc, 2, 0)?,
+            dup2_x1 => self.dup(state, pc, 2, 1)?,
+            dup2_x2 => self.dup(state, pc, 2, 2)?,
+            swap => {
+                let v1 = self.pop_slots(state, pc, 1)?;
+                let v2 = self.pop_slots(state, pc, 1)?;
+                state.stack.extend(v1);
+                state.stack.extend(v2);
+            }
+
+            // Math
+            iadd | ladd | fadd | dadd => self.binary(state, pc, BinaryOp::Add)?,
+            isub | lsub | fsub | dsub => self.binary(state, pc, BinaryOp::Sub)?,
+            imul | lmul | fmul | dmul => self.binary(state, pc, BinaryOp::Mul)?,
+            idiv | ldiv | fdiv | ddiv => self.binary(state, pc, BinaryOp::Div)?,
+            irem | lrem | frem | drem => self.binary(state, pc, BinaryOp::Rem)?,
+            ishl | lshl => self.binary(state, pc, BinaryOp::Shl)?,
+            ishr | lshr => self.binary(state, pc, BinaryOp::Shr)?,
+            iushr | lushr => self.binary(state, pc, BinaryOp::Ushr)?,
+            iand | land => self.binary(state, pc, BinaryOp::And)?,
+            ior | lor => self.binary(state, pc, BinaryOp::Or)?,
+            ixor | lxor => self.binary(state, pc, BinaryOp::Xor)?,
+            ineg | lneg | fneg | dneg => {
+                let value = self.pop(state, pc)?;
+                let ty = self.ty(pc, value)?;
+                self.push(state, pc, Op::Negate(value), ty)
+            }
+            iinc => {
+                let (index, constant) = match instruction.operand {
+                    Operand::Iinc {
+                        index, constant, ..
+                    } => (index, constant),
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                let value = self.local(state, pc, index, Type::Int)?;
+                let constant = self.emit(Op::Int(constant as i32), Some(Type::Int), Some(pc));
+                let sum = self.emit(
+                    Op::Binary(BinaryOp::Add, value, constant),
+                    Some(Type::Int),
+                    Some(pc),
+                );
+                self.set_local(state, pc, index, sum)?;
+            }
+
+            // Conversions
+            i2l | f2l | d2l => self.convert(state, pc, opcode, Type::Long)?,
+            i2f | l2f | d2f => self.convert(state, pc, opcode, Type::Float)?,
+            i2d | l2d | f2d => self.convert(state, pc, opcode, Type::Double)?,
+            l2i | f2i | d2i | i2b | i2c | i2s => self.convert(state, pc, opcode, Type::Int)?,
+
+            // Comparisons
+            lcmp | fcmpl | fcmpg | dcmpl | dcmpg => {
+                let right = self.pop(state, pc)?;
+                let left = self.pop(state, pc)?;
+                self.push(state, pc, Op::Compare(opcode, left, right), Type::Int)
+            }
+            ifeq | ifne | iflt | ifge | ifgt | ifle | ifnull | ifnonnull => {
+                let condition = match opcode {
+                    ifnull => Condition::Eq,
+                    ifnonnull => Condition::Ne,
+                    _ => CONDITIONS[(opcode as u8 - ifeq as u8) as usize],
+                };
+                let left = self.pop(state, pc)?;
+                return self.branch(pc, instruction, next, condition, left, None);
+            }
+            if_icmpeq | if_icmpne | if_icmplt | if_icmpge | if_icmpgt | if_icmple | if_acmpeq
+            | if_acmpne => {
+                let condition = match opcode {
+                    if_acmpeq => Condition::Eq,
+                    if_acmpne => Condition::Ne,
+                    _ => CONDITIONS[(opcode as u8 - if_icmpeq as u8) as usize],
+                };
+                let right = self.pop(state, pc)?;
+                let left = self.pop(state, pc)?;
+                return self.branch(pc, instruction, next, condition, left, Some(right));
+            }
+
+            // Control
+            goto | goto_w => {
+                let target = self.block_at(pc, *instruction.targets()[0])?;
+                return Ok(Some(Terminator::Goto(target)));
+            }
+            tableswitch | lookupswitch => {
+                let value = self.pop(state, pc)?;
+                let (default, keyed) = match &instruction.operand {
+                    Operand::TableSwitch {
+                        default,
+                        low,
+                        targets,
+                        ..
+                    } => (
+                        *default,
+                        targets
+                            .iter()
+                            .enumerate()
+                            .map(|(i, target)| (low.wrapping_add(i as i32), *target))
+                            .collect(),
+                    ),
+                    Operand::LookupSwitch { default, pairs } => (*default, pairs.clone()),
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                let mut cases = vec![];
+                for (key, target) in keyed {
+                    cases.push((key, self.block_at(pc, target)?));
+                }
+                return Ok(Some(Terminator::Switch {
+                    value,
+                    cases,
+                    default: self.block_at(pc, default)?,
+                }));
+            }
+            ireturn | lreturn | freturn | dreturn | areturn => {
+                let value = self.pop(state, pc)?;
+                return Ok(Some(Terminator::Return(Some(value))));
+            }
+            vreturn => return Ok(Some(Terminator::Return(None))),
+
+            // References
+            getstatic => {
+                let index = constant_index(instruction)?;
+                let ty = self.field_type(index)?;
+                self.push(state, pc, Op::GetStatic(index), ty)
+            }
+            putstatic => {
+                let value = self.pop(state, pc)?;
+                let index = constant_index(instruction)?;
+                self.emit(Op::PutStatic(index, value), None, Some(pc));
+            }
+            getfield => {
+                let object = self.pop(state, pc)?;
+                let index = constant_index(instruction)?;
+                let ty = self.field_type(index)?;
+                self.push(state, pc, Op::GetField(index, object), ty)
+            }
+            putfield => {
+                let value = self.pop(state, pc)?;
+                let object = self.pop(state, pc)?;
+                let index = constant_index(instruction)?;
+                self.emit(Op::PutField(index, object, value), None, Some(pc));
+            }
+            invokevirtual | invokespecial | invokestatic | invokeinterface | invokedynamic => {
+                self.invoke(state, pc, instruction)?
+            }
+            new => {
+                let index = constant_index(instruction)?;
+                self.push(state, pc, Op::New(index), Type::Reference)
+            }
+            newarray => {
+                let array_type = match instruction.operand {
+                    Operand::NewArray(array_type) => array_type,
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                let length = self.pop(state, pc)?;
+                self.push(state, pc, Op::NewArray(array_type, length), Type::Reference)
+            }
+            anewarray => {
+                let length = self.pop(state, pc)?;
+                let index = constant_index(instruction)?;
+                self.push(
+                    state,
+                    pc,
+                    Op::NewObjectArray(index, length),
+                    Type::Reference,
+                )
+            }
+            arraylength => {
+                let array = self.pop(state, pc)?;
+                self.push(state, pc, Op::ArrayLength(array), Type::Int)
+            }
+            athrow => {
+                let exception = self.pop(state, pc)?;
+                return Ok(Some(Terminator::Throw(exception)));
+            }
+            checkcast => {
+                let value = self.pop(state, pc)?;
+                let index = constant_index(instruction)?;
+                self.push(state, pc, Op::CheckCast(index, value), Type::Reference)
+            }
+            instanceof => {
+                let value = self.pop(state, pc)?;
+                let index = constant_index(instruction)?;
+                self.push(state, pc, Op::InstanceOf(index, value), Type::Int)
+            }
+            monitorenter | monitorexit => {
+                let value = self.pop(state, pc)?;
+                let op = if opcode == monitorenter {
+                    Op::MonitorEnter(value)
+                } else {
+                    Op::MonitorExit(value)
+                };
+                self.emit(op, None, Some(pc));
+            }
+
+            // Extended
+            multianewarray => {
+                let (index, dimensions) = match instruction.operand {
+                    Operand::MultiANewArray { index, dimensions } => (index, dimensions),
+                    _ => return Err(Error::InvalidOperand(opcode)),
+                };
+                let mut lengths = vec![];
+                for _ in 0..dimensions {
+                    lengths.push(self.pop(state, pc)?);
+                }
+                lengths.reverse();
+                self.push(
+                    state,
+                    pc,
+                    Op::NewMultiArray(index, lengths),
+                    Type::Reference,
+                )
+            }
+            _ => return Err(Error::InvalidOpCode(pc, opcode as u8)),
+        }
+        Ok(None)
+    }
+
+    fn branch(
+        &self,
+        pc: u32,
+        instruction: &Instruction,
+        next: Option<u32>,
+        condition: Condition,
+        left: Value,
+        right: Option<Value>,
+    ) -> Result<Option<Terminator>, Error> {
+        let next = next.ok_or(Error::UnexpectedEnd(pc))?;
+        Ok(Some(Terminator::If {
+            condition,
+            left,
+            right,
+            then: self.block_at(pc, *instruction.targets()[0])?,
+            otherwise: self.block_at(pc, next)?,
+        }))
+    }
+
+    fn invoke(
+        &mut self,
+        state: &mut State,
+        pc: u32,
+        instruction: &Instruction,
+    ) -> Result<(), Error> {
+        let index = match instruction.operand {
+            Operand::Constant(index) | Operand::InvokeInterface { index, .. } => index,
+            _ => return Err(Error::InvalidOperand(instruction.opcode)),
+        };
+        let descriptor = MethodDescriptor::parse(member_descriptor(self.constant_pool, index)?)
+            .map_err(|_| Error::InvalidConstant(index))?;
+        let kind = match instruction.opcode {
+            OpCode::invokevirtual => InvokeKind::Virtual,
+            OpCode::invokespecial => InvokeKind::Special,
+            OpCode::invokestatic => InvokeKind::Static,
+            OpCode::invokeinterface => InvokeKind::Interface,
+            _ => InvokeKind::Dynamic,
+        };
+        let mut arguments = vec![];
+        for _ in &descriptor.parameters {
+            arguments.push(self.pop(state, pc)?);
+        }
+        if !matches!(kind, InvokeKind::Static | InvokeKind::Dynamic) {
+            arguments.push(self.pop(state, pc)?);
+        }
+        arguments.reverse();
+        let ty = descriptor.return_type.as_ref().map(Type::from_field_type);
+        let value = self.emit(Op::Invoke(kind, index, arguments), ty, Some(pc));
+        if ty.is_some() {
+            state.stack.push(value);
+        }
+        Ok(())
+    }
+
+    /// Operation and type of the constant `ldc` loads
+    fn constant(&self, index: u16) -> Result<(Op, Type), Error> {
+        match (index as usize)
+            .checked_sub(1)
+            .and_then(|i| self.constant_pool.get(i))
+        {
+            Some(Constant::Integer(value)) => Ok((Op::Int(*value), Type::Int)),
+            Some(Constant::Float(value)) => Ok((Op::Float(*value), Type::Float)),
+            Some(Constant::Long(value)) => Ok((Op::Long(*value), Type::Long)),
+            Some(Constant::Double(value)) => Ok((Op::Double(*value), Type::Double)),
+            Some(Constant::String { .. })
+            | Some(Constant::Class { .. })
+            | Some(Constant::MethodType { .. })
+            | Some(Constant::MethodHandle { .. }) => Ok((Op::Constant(index), Type::Reference)),
+            Some(Constant::Dynamic { .. }) => Ok((Op::Constant(index), self.field_type(index)?)),
+            _ => Err(Error::InvalidConstant(index)),
+        }
+    }
+
+    /// Type of a field or dynamic constant
+    fn field_type(&self, index: u16) -> Result<Type, Error> {
+        let descriptor = member_descriptor(self.constant_pool, index)?;
+        let field_type = FieldType::parse(descriptor).map_err(|_| Error::InvalidConstant(index))?;
+        Ok(Type::from_field_type(&field_type))
+    }
+
+    fn load(
+        &mut self,
+        state: &mut State,
+        pc: u32,
+        instruction: &Instruction,
+        ty: Type,
+    ) -> Result<(), Error> {
+        let value = self.local(state, pc, instruction.local_index().unwrap(), ty)?;
+        state.stack.push(value);
+        Ok(())
+    }
+
+    fn local(&self, state: &State, pc: u32, index: u16, ty: Type) -> Result<Value, Error> {
+        state
+            .locals
+            .get(index as usize)
+            .copied()
+            .flatten()
+            .filter(|value| self.function.ty(*value) == Some(ty))
+            .ok_or(Error::InvalidLocal(pc))
+    }
+
+    fn set_local(&self, state: &mut State, pc: u32, index: u16, value: Value) -> Result<(), Error> {
+        let index = index as usize;
+        let size = self.ty(pc, value)?.size() as usize;
+        if index + size > self.max_locals {
+            return Err(Error::InvalidLocal(pc));
+        }
+        state.locals[index] = Some(value);
+        if size == 2 {
+            state.locals[index + 1] = None;
+        }
+        // the first half of a long or double is gone with the second
+        if index > 0 {
+            if let Some(previous) = state.locals[index - 1] {
+                if self.ty(pc, previous)?.size() == 2 {
+                    state.locals[index - 1] = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn array_load(
+        &mut self,
+        state: &mut State,
+        pc: u32,
+        element: ElementType,
+    ) -> Result<(), Error> {
+        let index = self.pop(state, pc)?;
+        let array = self.pop(state, pc)?;
+        let op = Op::ArrayLoad(element, array, index);
+        self.push(state, pc, op, element.value_type());
+        Ok(())
+    }
+
+    fn array_store(
+        &mut self,
+        state: &mut State,
+        pc: u32,
+        element: ElementType,
+    ) -> Result<(), Error> {
+        let value = self.pop(state, pc)?;
+        let index = self.pop(state, pc)?;
+        let array = self.pop(state, pc)?;
+        self.emit(Op::ArrayStore(element, array, index, value), None, Some(pc));
+        Ok(())
+    }
+
+    fn binary(&mut self, state: &mut State, pc: u32, op: BinaryOp) -> Result<(), Error> {
+        let right = self.pop(state, pc)?;
+        let left = self.pop(state, pc)?;
+        // shifts take an int distance
+        let ty = self.ty(pc, left)?;
+        self.push(state, pc, Op::Binary(op, left, right), ty);
+        Ok(())
+    }
+
+    fn convert(
+        &mut self,
+        state: &mut State,
+        pc: u32,
+        opcode: OpCode,
+        ty: Type,
+    ) -> Result<(), Error> {
+        let value = self.pop(state, pc)?;
+        self.push(state, pc, Op::Convert(opcode, value), ty);
+        Ok(())
+    }
+
+    /// Duplicates the values in the top `slots` slots of the stack and inserts
+    /// them below the `under` slots below them
+    fn dup(&self, state: &mut State, pc: u32, slots: u16, under: u16) -> Result<(), Error> {
+        let top = self.pop_slots(state, pc, slots)?;
+        let below = self.pop_slots(state, pc, under)?;
+        state.stack.extend(top.iter().copied());
+        state.stack.extend(below);
+        state.stack.extend(top);
+        Ok(())
+    }
+
+    /// Pops values taking `slots` slots, returns them bottom first
+    fn pop_slots(&self, state: &mut State, pc: u32, slots: u16) -> Result<Vec<Value>, Error> {
+        let mut popped = vec![];
+        let mut size = 0;
+        while size < slots {
+            let value = self.pop(state, pc)?;
+            size += self.ty(pc, value)?.size();
+            popped.push(value);
+        }
+        if size != slots {
+            return Err(Error::StackTypeMismatch(pc));
+        }
+        popped.reverse();
+        Ok(popped)
+    }
+
+    fn pop(&self, state: &mut State, pc: u32) -> Result<Value, Error> {
+        state.stack.pop().ok_or(Error::StackUnderflow(pc))
+    }
+
+    fn ty(&self, pc: u32, value: Value) -> Result<Type, Error> {
+        self.function.ty(value).ok_or(Error::StackTypeMismatch(pc))
+    }
+
+    fn push(&mut self, state: &mut State, pc: u32, op: Op, ty: Type) {
+        let value = self.emit(op, Some(ty), Some(pc));
+        state.stack.push(value);
+    }
+
+    /// Defines a value and adds it to the current block
+    fn emit(&mut self, op: Op, ty: Option<Type>, pc: Option<u32>) -> Value {
+        let value = self.define(op, ty, pc);
+        self.function.blocks[self.current.0]
+            .instructions
+            .push(value);
+        value
+    }
+
+    fn define(&mut self, op: Op, ty: Option<Type>, pc: Option<u32>) -> Value {
+        self.function.values.push(Definition { op, ty, pc });
+        Value(self.function.values.len() - 1)
+    }
+
+    fn block_at(&self, pc: u32, target: u32) -> Result<Block, Error> {
+        self.blocks
+            .get(&target)
+            .copied()
+            .ok_or(Error::InvalidBranchTarget(pc, target as i64))
+    }
+}
+
+fn constant_index(instruction: &Instruction) -> Result<u16, Error> {
+    match instruction.operand {
+        Operand::Constant(index) => Ok(index),
+        _ => Err(Error::InvalidOperand(instruction.opcode)),
+    }
+}
+
+/// Replaces phis merging a single value by it, then removes phis only used by
+/// phis
+fn remove_phis(function: &mut Function) {
+    let mut replaced: HashMap<Value, Value> = HashMap::new();
+    let resolve = |replaced: &HashMap<Value, Value>, mut value: Value| {
+        while let Some(replacement) = replaced.get(&value) {
+            value = *replacement;
+        }
+        value
+    };
+    loop {
+        let mut changed = false;
+        for block in 0..function.blocks.len() {
+            for phi in function.blocks[block].phis.clone() {
+                let mut merged = vec![];
+                if let Op::Phi(operands) = &function.values[phi.0].op {
+                    for (_, operand) in operands {
+                        let operand = resolve(&replaced, *operand);
+                        if operand != phi && !merged.contains(&operand) {
+                            merged.push(operand);
+                        }
+                    }
+                }
+                if let [value] = merged[..] {
+                    replaced.insert(phi, value);
+                    function.blocks[block].phis.retain(|p| *p != phi);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    for definition in &mut function.values {
+        for operand in definition.op.operands_mut() {
+            *operand = resolve(&replaced, *operand);
+        }
+    }
+    for block in &mut function.blocks {
+        for operand in block.terminator.operands_mut() {
+            *operand = resolve(&replaced, *operand);
+        }
+    }
+
+    let mut used: HashSet<Value> = HashSet::new();
+    let mut pending = vec![];
+    for block in &function.blocks {
+        for value in &block.instructions {
+            pending.extend(function.values[value.0].op.operands());
+        }
+        pending.extend(block.terminator.operands());
+    }
+    while let Some(value) = pending.pop() {
+        if used.insert(value) {
+            if let Op::Phi(operands) = &function.values[value.0].op {
+                pending.extend(operands.iter().map(|(_, operand)| *operand));
+            }
+        }
+    }
+    for block in &mut function.blocks {
+        block.phis.retain(|phi| used.contains(phi));
+    }
+}
+
+/// Drops the blocks no shape reached and the values no block holds, numbering
+/// the rest in order
+fn compact(function: Function) -> Function {
+    // every block but the entry is reached by a terminator or handler
+    let mut reached = vec![false; function.blocks.len()];
+    let mut pending = vec![Block(0)];
+    while let Some(block) = pending.pop() {
+        if !reached[block.0] {
+            reached[block.0] = true;
+            pending.extend(function.successors(block));
+        }
+    }
+    let mut blocks = HashMap::new();
+    for (index, _) in reached.iter().enumerate().filter(|(_, reached)| **reached) {
+        blocks.insert(Block(index), Block(blocks.len()));
+    }
+    let mut values = HashMap::new();
+    for (index, block) in function.blocks.iter().enumerate() {
+        if reached[index] {
+            for value in block.phis.iter().chain(&block.instructions) {
+                values.insert(*value, Value(values.len()));
+            }
+        }
+    }
+
+    let mut compacted = Function {
+        blocks: vec![],
+        values: vec![],
+    };
+    let mut order: Vec<(&Value, &Value)> = values.iter().collect();
+    order.sort_by_key(|(_, new)| **new);
+    for (old, _) in order {
+        let mut definition = function.values[old.0].clone();
+        if let Op::Phi(operands) = &mut definition.op {
+            for (block, _) in operands.iter_mut() {
+                *block = blocks[block];
+            }
+        }
+        for operand in definition.op.operands_mut() {
+            *operand = values[operand];
+        }
+        compacted.values.push(definition);
+    }
+    for (index, block) in function.blocks.into_iter().enumerate() {
+        if !reached[index] {
+            continue;
+        }
+        let mut block = block;
+        for value in block.phis.iter_mut().chain(block.instructions.iter_mut()) {
+            *value = values[value];
+        }
+        for operand in block.terminator.operands_mut() {
+            *operand = values[operand];
+        }
+        for successor in block.terminator.successors_mut() {
+            *successor = blocks[successor];
+        }
+        for handler in &mut block.handlers {
+            handler.block = blocks[&handler.block];
+        }
+        compacted.blocks.push(block);
+    }
+    compacted
+}
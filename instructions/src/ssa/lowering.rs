@@ -0,0 +1,489 @@
+use crate::assembler::{assemble, Item, Label};
+use crate::error::Error;
+use crate::instruction::{Instruction, Operand};
+use crate::limits::compute_max_stack;
+use crate::opcode::OpCode;
+use crate::ssa::{BinaryOp, Block, Condition, Function, InvokeKind, Op, Terminator, Type, Value};
+use classfile::attribute::{CodeAttribute, Exception};
+use classfile::constant::Constant;
+use classfile::constant_pool::ConstantPoolBuilder;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// Translates `function` back to bytecode.
+///
+/// Parameters stay in their local variables, every other value used gets one
+/// of its own, stored after its operation and loaded for each use. The values
+/// of a phi are copied to its variable when leaving each predecessor: at the
+/// end of the block, on the edge of a branch or, for a handler, before the
+/// instruction that throws. Blocks are laid out in order.
+///
+/// Constants too large for an instruction of their own are added to `pool`,
+/// which starts from the constant pool of the class the function comes from.
+/// The code has no attributes, `max_stack` and `max_locals` are computed.
+pub fn lower(function: &Function, pool: &mut ConstantPoolBuilder) -> Result<CodeAttribute, Error> {
+    let mut lowering = Lowering {
+        function,
+        pool,
+        slots: HashMap::new(),
+        max_locals: 0,
+        items: vec![],
+        stubs: vec![],
+        labels: function.blocks.len() as u32,
+        ranges: vec![],
+    };
+    lowering.allocate()?;
+    for block in 0..function.blocks.len() {
+        lowering.block(Block(block))?;
+    }
+    let mut items = lowering.items;
+    items.append(&mut lowering.stubs);
+
+    let assembled = assemble(&items)?;
+    let offset = |label: &Label| assembled.labels[label] as u16;
+    let mut code = CodeAttribute {
+        max_stack: 0,
+        max_locals: lowering.max_locals,
+        code: Arc::new(assembled.code.clone()),
+        exception_table: lowering
+            .ranges
+            .iter()
+            .map(|(start, end, handler, catch_type)| Exception {
+                start_pc: offset(start),
+                end_pc: offset(end),
+                handler_pc: offset(handler),
+                catch_type: *catch_type,
+            })
+            .collect(),
+        attributes: vec![],
+    };
+    code.max_stack = compute_max_stack(&code, &lowering.pool.clone().build())?;
+    Ok(code)
+}
+
+struct Lowering<'a> {
+    function: &'a Function,
+    pool: &'a mut ConstantPoolBuilder,
+    /// Local variable of each value used
+    slots: HashMap<Value, u16>,
+    max_locals: u16,
+    items: Vec<Item>,
+    /// Copies on the edges of branches, after the blocks
+    stubs: Vec<Item>,
+    /// Labels so far, those of the blocks first
+    labels: u32,
+    /// Start, end and handler of the instructions covered by each handler, and
+    /// the exceptions caught
+    ranges: Vec<(Label, Label, Label, u16)>,
+}
+
+impl<'a> Lowering<'a> {
+    fn allocate(&mut self) -> Result<(), Error> {
+        let function = self.function;
+        let mut used = HashSet::new();
+        for block in &function.blocks {
+            for value in block.phis.iter().chain(&block.instructions) {
+                used.extend(function.definition(*value).op.operands());
+            }
+            used.extend(block.terminator.operands());
+        }
+        let mut next: u32 = 0;
+        for definition in &function.values {
+            if let (Op::Parameter(slot), Some(ty)) = (&definition.op, definition.ty) {
+                next = next.max(*slot as u32 + ty.size() as u32);
+            }
+        }
+        for (index, definition) in function.values.iter().enumerate() {
+            let value = Value(index);
+            match (&definition.op, definition.ty) {
+                (Op::Parameter(slot), _) => {
+                    self.slots.insert(value, *slot);
+                }
+                (_, Some(ty)) if used.contains(&value) => {
+                    if next + ty.size() as u32 > u16::MAX as u32 {
+                        return Err(Error::InvalidLocal(definition.pc.unwrap_or(0)));
+                    }
+                    self.slots.insert(value, next as u16);
+                    next += ty.size() as u32;
+                }
+                _ => {}
+            }
+        }
+        self.max_locals = next as u16;
+        Ok(())
+    }
+
+    fn block(&mut self, block: Block) -> Result<(), Error> {
+        self.items.push(Item::Label(Label(block.0 as u32)));
+        let data = &self.function.blocks[block.0];
+        let throws = !data.handlers.is_empty();
+        for value in &data.instructions {
+            let last = Some(value) == data.instructions.last();
+            self.operation(block, *value, throws && last && !data.terminator.exits())?;
+        }
+        self.terminator(block, throws && data.terminator.exits())
+    }
+
+    /// Loads the operands of a value, applies its operation and stores it.
+    /// `throws` when the block's exceptions are thrown there.
+    fn operation(&mut self, block: Block, value: Value, throws: bool) -> Result<(), Error> {
+        let definition = self.function.definition(value);
+        let instruction = match &definition.op {
+            // in its variable from the start
+            Op::Parameter(_) => return Ok(()),
+            // on the stack entering the handler
+            Op::CaughtException => None,
+            op => {
+                for operand in op.operands() {
+                    self.load(operand)?;
+                }
+                Some(self.instruction(&definition.op, definition.ty)?)
+            }
+        };
+        if let Some(instruction) = instruction {
+            self.throwing(block, throws, instruction)?;
+        }
+        if let Some(ty) = definition.ty {
+            match self.slots.get(&value) {
+                Some(slot) => self.push(typed(0x36, ty), local(*slot)),
+                None => self.pop(ty),
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the instruction where the exceptions of `block` are thrown if
+    /// `throws`, after copying to the phis of the handlers
+    fn throwing(
+        &mut self,
+        block: Block,
+        throws: bool,
+        instruction: Instruction<Label>,
+    ) -> Result<(), Error> {
+        if !throws {
+            self.items.push(Item::Instruction(instruction));
+            return Ok(());
+        }
+        let handlers = self.function.blocks[block.0].handlers.clone();
+        let mut copies = vec![];
+        for handler in &handlers {
+            copies.extend(self.copies(block, handler.block));
+        }
+        self.copy(&copies)?;
+        let (start, end) = (self.label(), self.label());
+        self.items.push(Item::Label(start));
+        self.items.push(Item::Instruction(instruction));
+        self.items.push(Item::Label(end));
+        for handler in handlers {
+            let label = Label(handler.block.0 as u32);
+            self.ranges.push((start, end, label, handler.catch_type));
+        }
+        Ok(())
+    }
+
+    fn terminator(&mut self, block: Block, throws: bool) -> Result<(), Error> {
+        let next = Block(block.0 + 1);
+        match &self.function.blocks[block.0].terminator {
+            Terminator::Goto(target) => {
+                let copies = self.copies(block, *target);
+                self.copy(&copies)?;
+                if *target != next {
+                    self.jump(OpCode::goto, Label(target.0 as u32));
+                }
+            }
+            Terminator::If {
+                condition,
+                left,
+                right,
+                then,
+                otherwise,
+            } => {
+                self.load(*left)?;
+                if let Some(right) = right {
+                    self.load(*right)?;
+                }
+                let index = *condition as u8;
+                let opcode = match (self.ty(*left)?, right, condition) {
+                    (Type::Reference, None, Condition::Eq) => OpCode::ifnull,
+                    (Type::Reference, None, _) => OpCode::ifnonnull,
+                    (Type::Reference, Some(_), Condition::Eq) => OpCode::if_acmpeq,
+                    (Type::Reference, Some(_), _) => OpCode::if_acmpne,
+                    (_, None, _) => opcode(OpCode::ifeq as u8 + index),
+                    (_, Some(_), _) => opcode(OpCode::if_icmpeq as u8 + index),
+                };
+                let target = self.edge(block, *then)?;
+                self.jump(opcode, target);
+                let copies = self.copies(block, *otherwise);
+                self.copy(&copies)?;
+                if *otherwise != next {
+                    self.jump(OpCode::goto, Label(otherwise.0 as u32));
+                }
+            }
+            Terminator::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                self.load(*value)?;
+                let mut edges = HashMap::new();
+                for target in std::iter::once(default).chain(cases.iter().map(|(_, t)| t)) {
+                    if !edges.contains_key(target) {
+                        let label = self.edge(block, *target)?;
+                        edges.insert(*target, label);
+                    }
+                }
+                let mut pairs: Vec<(i32, Label)> = cases
+                    .iter()
+                    .map(|(key, target)| (*key, edges[target]))
+                    .collect();
+                pairs.sort_by_key(|(key, _)| *key);
+                let default = edges[default];
+                let instruction = match (pairs.first(), pairs.last()) {
+                    (Some((low, _)), Some((high, _)))
+                        if (*high as i64 - *low as i64) < 2 * pairs.len() as i64 + 4 =>
+                    {
+                        let (low, high) = (*low, *high);
+                        let mut targets = vec![default; (high as i64 - low as i64 + 1) as usize];
+                        for (key, label) in &pairs {
+                            targets[(*key as i64 - low as i64) as usize] = *label;
+                        }
+                        Instruction::new(
+                            OpCode::tableswitch,
+                            Operand::TableSwitch {
+                                default,
+                                low,
+                                high,
+                                targets,
+                            },
+                        )
+                    }
+                    _ => Instruction::new(
+                        OpCode::lookupswitch,
+                        Operand::LookupSwitch { default, pairs },
+                    ),
+                };
+                self.items.push(Item::Instruction(instruction));
+            }
+            Terminator::Return(Some(value)) => {
+                self.load(*value)?;
+                let instruction = Instruction::new(typed(0xac, self.ty(*value)?), Operand::None);
+                self.throwing(block, throws, instruction)?;
+            }
+            Terminator::Return(None) => {
+                let instruction = Instruction::new(OpCode::vreturn, Operand::None);
+                self.throwing(block, throws, instruction)?;
+            }
+            Terminator::Throw(value) => {
+                self.load(*value)?;
+                let instruction = Instruction::new(OpCode::athrow, Operand::None);
+                self.throwing(block, throws, instruction)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Label a branch from `block` to `target` goes to, a stub copying to the
+    /// phis of `target` if it has any
+    fn edge(&mut self, block: Block, target: Block) -> Result<Label, Error> {
+        let copies = self.copies(block, target);
+        if copies.is_empty() {
+            return Ok(Label(target.0 as u32));
+        }
+        let label = self.label();
+        let items = std::mem::take(&mut self.items);
+        self.items.push(Item::Label(label));
+        self.copy(&copies)?;
+        self.jump(OpCode::goto, Label(target.0 as u32));
+        let stub = std::mem::replace(&mut self.items, items);
+        self.stubs.extend(stub);
+        Ok(label)
+    }
+
+    /// Phis of `target` and their values leaving `block`, but for those
+    /// already in the variable
+    fn copies(&self, block: Block, target: Block) -> Vec<(Value, Value)> {
+        let mut copies = vec![];
+        for phi in &self.function.blocks[target.0].phis {
+            if let Op::Phi(operands) = &self.function.definition(*phi).op {
+                for (predecessor, value) in operands {
+                    if *predecessor == block && self.slots.get(value) != self.slots.get(phi) {
+                        copies.push((*phi, *value));
+                    }
+                }
+            }
+        }
+        copies
+    }
+
+    /// Copies values to phis all at once, through the stack
+    fn copy(&mut self, copies: &[(Value, Value)]) -> Result<(), Error> {
+        for (_, value) in copies {
+            self.load(*value)?;
+        }
+        for (phi, _) in copies.iter().rev() {
+            let ty = self.ty(*phi)?;
+            self.push(typed(0x36, ty), local(self.slots[phi]));
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, op: &Op, ty: Option<Type>) -> Result<Instruction<Label>, Error> {
+        let none = |opcode: OpCode| Ok(Instruction::new(opcode, Operand::None));
+        let constant =
+            |opcode: OpCode, index: u16| Ok(Instruction::new(opcode, Operand::Constant(index)));
+        match op {
+            Op::Int(value) => {
+                if (-1..=5).contains(value) {
+                    none(opcode((OpCode::iconst_0 as i32 + value) as u8))
+                } else if let Ok(value) = i8::try_from(*value) {
+                    Ok(Instruction::new(OpCode::bipush, Operand::Byte(value)))
+                } else if let Ok(value) = i16::try_from(*value) {
+                    Ok(Instruction::new(OpCode::sipush, Operand::Short(value)))
+                } else {
+                    constant(OpCode::ldc, self.pool.add(Constant::Integer(*value))?)
+                }
+            }
+            Op::Long(value) => match value {
+                0 | 1 => none(opcode(OpCode::lconst_0 as u8 + *value as u8)),
+                _ => constant(OpCode::ldc2_w, self.pool.add(Constant::Long(*value))?),
+            },
+            Op::Float(value) => {
+                // not -0.0
+                match [0.0, 1.0, 2.0f32]
+                    .iter()
+                    .position(|c| c.to_bits() == value.to_bits())
+                {
+                    Some(index) => none(opcode(OpCode::fconst_0 as u8 + index as u8)),
+                    None => constant(OpCode::ldc, self.pool.add(Constant::Float(*value))?),
+                }
+            }
+            Op::Double(value) => match [0.0, 1.0f64]
+                .iter()
+                .position(|c| c.to_bits() == value.to_bits())
+            {
+                Some(index) => none(opcode(OpCode::dconst_0 as u8 + index as u8)),
+                None => constant(OpCode::ldc2_w, self.pool.add(Constant::Double(*value))?),
+            },
+            Op::Null => none(OpCode::aconst_null),
+            Op::Constant(index) => match ty {
+                Some(Type::Long) | Some(Type::Double) => constant(OpCode::ldc2_w, *index),
+                _ => constant(OpCode::ldc, *index),
+            },
+            Op::Binary(op, _, _) => {
+                let ty = ty.unwrap_or(Type::Int).index();
+                let base = match op {
+                    BinaryOp::Add => 0x60,
+                    BinaryOp::Sub => 0x64,
+                    BinaryOp::Mul => 0x68,
+                    BinaryOp::Div => 0x6c,
+                    BinaryOp::Rem => 0x70,
+                    BinaryOp::Shl => 0x78,
+                    BinaryOp::Shr => 0x7a,
+                    BinaryOp::Ushr => 0x7c,
+                    BinaryOp::And => 0x7e,
+                    BinaryOp::Or => 0x80,
+                    BinaryOp::Xor => 0x82,
+                };
+                none(opcode(base + ty))
+            }
+            Op::Negate(_) => none(typed(0x74, ty.unwrap_or(Type::Int))),
+            Op::Convert(opcode, _) | Op::Compare(opcode, _, _) => none(*opcode),
+            Op::ArrayLoad(element, _, _) => none(opcode(0x2e + *element as u8)),
+            Op::ArrayStore(element, _, _, _) => none(opcode(0x4f + *element as u8)),
+            Op::ArrayLength(_) => none(OpCode::arraylength),
+            Op::NewArray(array_type, _) => Ok(Instruction::new(
+                OpCode::newarray,
+                Operand::NewArray(*array_type),
+            )),
+            Op::NewObjectArray(index, _) => constant(OpCode::anewarray, *index),
+            Op::NewMultiArray(index, lengths) => Ok(Instruction::new(
+                OpCode::multianewarray,
+                Operand::MultiANewArray {
+                    index: *index,
+                    dimensions: lengths.len() as u8,
+                },
+            )),
+            Op::New(index) => constant(OpCode::new, *index),
+            Op::CheckCast(index, _) => constant(OpCode::checkcast, *index),
+            Op::InstanceOf(index, _) => constant(OpCode::instanceof, *index),
+            Op::GetField(index, _) => constant(OpCode::getfield, *index),
+            Op::PutField(index, _, _) => constant(OpCode::putfield, *index),
+            Op::GetStatic(index) => constant(OpCode::getstatic, *index),
+            Op::PutStatic(index, _) => constant(OpCode::putstatic, *index),
+            Op::Invoke(kind, index, arguments) => match kind {
+                InvokeKind::Virtual => constant(OpCode::invokevirtual, *index),
+                InvokeKind::Special => constant(OpCode::invokespecial, *index),
+                InvokeKind::Static => constant(OpCode::invokestatic, *index),
+                InvokeKind::Dynamic => constant(OpCode::invokedynamic, *index),
+                InvokeKind::Interface => {
+                    let mut count = 0;
+                    for argument in arguments {
+                        count += self.ty(*argument)?.size();
+                    }
+                    Ok(Instruction::new(
+                        OpCode::invokeinterface,
+                        Operand::InvokeInterface {
+                            index: *index,
+                            count: count as u8,
+                        },
+                    ))
+                }
+            },
+            Op::MonitorEnter(_) => none(OpCode::monitorenter),
+            Op::MonitorExit(_) => none(OpCode::monitorexit),
+            Op::Parameter(_) | Op::CaughtException | Op::Phi(_) => {
+                unreachable!("{} has no instruction", op)
+            }
+        }
+    }
+
+    fn load(&mut self, value: Value) -> Result<(), Error> {
+        let ty = self.ty(value)?;
+        self.push(typed(0x15, ty), local(self.slots[&value]));
+        Ok(())
+    }
+
+    fn pop(&mut self, ty: Type) {
+        let opcode = if ty.size() == 2 {
+            OpCode::pop2
+        } else {
+            OpCode::pop
+        };
+        self.push(opcode, Operand::None);
+    }
+
+    fn jump(&mut self, opcode: OpCode, target: Label) {
+        self.push(opcode, Operand::Branch(target));
+    }
+
+    fn push(&mut self, opcode: OpCode, operand: Operand<Label>) {
+        self.items
+            .push(Item::Instruction(Instruction::new(opcode, operand)));
+    }
+
+    fn label(&mut self) -> Label {
+        self.labels += 1;
+        Label(self.labels - 1)
+    }
+
+    fn ty(&self, value: Value) -> Result<Type, Error> {
+        self.function.ty(value).ok_or(Error::StackTypeMismatch(
+            self.function.definition(value).pc.unwrap_or(0),
+        ))
+    }
+}
+
+fn local(index: u16) -> Operand<Label> {
+    Operand::Local { index, wide: false }
+}
+
+/// The form of a typed instruction for `ty`, from the `int` form `base`
+fn typed(base: u8, ty: Type) -> OpCode {
+    opcode(base + ty.index())
+}
+
+fn opcode(opcode: u8) -> OpCode {
+    // only computed from the opcodes of typed instruction families
+    OpCode::try_from(opcode).unwrap()
+}
@@ -0,0 +1,719 @@
+//! Static single assignment form of method bodies.
+//!
+//! Every local variable and operand stack slot of the bytecode becomes a
+//! `Value` defined once, by a typed operation or by a phi where control flow
+//! merges. Blocks end in a `Terminator`, exceptions leave a block through its
+//! `handlers`.
+
+use crate::instruction::ArrayType;
+use crate::opcode::OpCode;
+use classfile::descriptor::{BaseType, FieldType};
+use std::fmt::{self, Display, Formatter};
+
+mod builder;
+mod lowering;
+
+pub use builder::build;
+pub use lowering::lower;
+
+/// Index into `Function::values`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Value(pub usize);
+
+/// Index into `Function::blocks`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Block(pub usize);
+
+/// Computational type of a value, `boolean`, `byte`, `char` and `short` are
+/// `Int`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int,
+    Long,
+    Float,
+    Double,
+    Reference,
+}
+
+impl Type {
+    pub fn from_field_type(field_type: &FieldType) -> Type {
+        match field_type {
+            FieldType::Base(BaseType::Long) => Type::Long,
+            FieldType::Base(BaseType::Float) => Type::Float,
+            FieldType::Base(BaseType::Double) => Type::Double,
+            FieldType::Base(_) => Type::Int,
+            FieldType::Object(_) | FieldType::Array(_) => Type::Reference,
+        }
+    }
+
+    /// Local variable or operand stack slots taken by a value of the type
+    pub fn size(&self) -> u16 {
+        match self {
+            Type::Long | Type::Double => 2,
+            _ => 1,
+        }
+    }
+
+    /// 0 to 4 in the order of the typed forms of instructions, e.g. `iload`
+    /// to `aload`
+    pub(crate) fn index(&self) -> u8 {
+        *self as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    Ushr,
+    And,
+    Or,
+    Xor,
+}
+
+/// Comparison of a conditional branch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Condition {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Gt,
+    Le,
+}
+
+/// Component type of the array accessed by an `ArrayLoad` or `ArrayStore`,
+/// `Byte` for `boolean` arrays too
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementType {
+    Int,
+    Long,
+    Float,
+    Double,
+    Reference,
+    Byte,
+    Char,
+    Short,
+}
+
+impl ElementType {
+    /// Type of the value loaded from an array or stored into it
+    pub fn value_type(&self) -> Type {
+        match self {
+            ElementType::Long => Type::Long,
+            ElementType::Float => Type::Float,
+            ElementType::Double => Type::Double,
+            ElementType::Reference => Type::Reference,
+            _ => Type::Int,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InvokeKind {
+    Virtual,
+    Special,
+    Static,
+    Interface,
+    Dynamic,
+}
+
+/// The operation defining a value. Constant pool indices are those of the
+/// class the code comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    /// `this` or an argument, by its local variable slot
+    Parameter(u16),
+    /// The exception caught, first in a handler
+    CaughtException,
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Null,
+    /// `ldc` of a string, class, method type, method handle or dynamic
+    /// constant
+    Constant(u16),
+    /// The value of the variable leaving each predecessor
+    Phi(Vec<(Block, Value)>),
+    Binary(BinaryOp, Value, Value),
+    Negate(Value),
+    /// One of `i2l` to `i2s`
+    Convert(OpCode, Value),
+    /// `lcmp`, `fcmpl`, `fcmpg`, `dcmpl` or `dcmpg`
+    Compare(OpCode, Value, Value),
+    /// Array and index
+    ArrayLoad(ElementType, Value, Value),
+    /// Array, index and the value stored
+    ArrayStore(ElementType, Value, Value, Value),
+    ArrayLength(Value),
+    NewArray(ArrayType, Value),
+    /// Component class and length
+    NewObjectArray(u16, Value),
+    /// Array class and one length per dimension created
+    NewMultiArray(u16, Vec<Value>),
+    New(u16),
+    CheckCast(u16, Value),
+    InstanceOf(u16, Value),
+    GetField(u16, Value),
+    /// Field, object and the value stored
+    PutField(u16, Value, Value),
+    GetStatic(u16),
+    PutStatic(u16, Value),
+    /// Method or call site and the arguments, the receiver first
+    Invoke(InvokeKind, u16, Vec<Value>),
+    MonitorEnter(Value),
+    MonitorExit(Value),
+}
+
+impl Op {
+    pub fn operands(&self) -> Vec<Value> {
+        let mut op = self.clone();
+        op.operands_mut().into_iter().map(|value| *value).collect()
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Op::Parameter(_)
+            | Op::CaughtException
+            | Op::Int(_)
+            | Op::Long(_)
+            | Op::Float(_)
+            | Op::Double(_)
+            | Op::Null
+            | Op::Constant(_)
+            | Op::New(_)
+            | Op::GetStatic(_) => vec![],
+            Op::Phi(operands) => operands.iter_mut().map(|(_, value)| value).collect(),
+            Op::Negate(value)
+            | Op::Convert(_, value)
+            | Op::ArrayLength(value)
+            | Op::NewArray(_, value)
+            | Op::NewObjectArray(_, value)
+            | Op::CheckCast(_, value)
+            | Op::InstanceOf(_, value)
+            | Op::GetField(_, value)
+            | Op::PutStatic(_, value)
+            | Op::MonitorEnter(value)
+            | Op::MonitorExit(value) => vec![value],
+            Op::Binary(_, left, right)
+            | Op::Compare(_, left, right)
+            | Op::ArrayLoad(_, left, right)
+            | Op::PutField(_, left, right) => vec![left, right],
+            Op::ArrayStore(_, array, index, value) => vec![array, index, value],
+            Op::NewMultiArray(_, values) | Op::Invoke(_, _, values) => values.iter_mut().collect(),
+        }
+    }
+}
+
+/// A value and how it is defined
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub op: Op,
+    /// None for operations without a result, like `PutField`
+    pub ty: Option<Type>,
+    /// Offset of the instruction the operation comes from, None for phis and
+    /// parameters
+    pub pc: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Goto(Block),
+    /// Compares `left` with `right`, or zero or null without one
+    If {
+        condition: Condition,
+        left: Value,
+        right: Option<Value>,
+        then: Block,
+        otherwise: Block,
+    },
+    Switch {
+        value: Value,
+        cases: Vec<(i32, Block)>,
+        default: Block,
+    },
+    Return(Option<Value>),
+    Throw(Value),
+}
+
+impl Terminator {
+    /// Blocks control continues in when no exception is thrown
+    pub fn successors(&self) -> Vec<Block> {
+        match self {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::If {
+                then, otherwise, ..
+            } => vec![*then, *otherwise],
+            Terminator::Switch { cases, default, .. } => {
+                let mut successors = vec![*default];
+                successors.extend(cases.iter().map(|(_, target)| *target));
+                successors
+            }
+            Terminator::Return(_) | Terminator::Throw(_) => vec![],
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut Block> {
+        match self {
+            Terminator::Goto(target) => vec![target],
+            Terminator::If {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+            Terminator::Switch { cases, default, .. } => {
+                let mut successors = vec![default];
+                successors.extend(cases.iter_mut().map(|(_, target)| target));
+                successors
+            }
+            Terminator::Return(_) | Terminator::Throw(_) => vec![],
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Value> {
+        let mut terminator = self.clone();
+        terminator
+            .operands_mut()
+            .into_iter()
+            .map(|value| *value)
+            .collect()
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Terminator::Goto(_) | Terminator::Return(None) => vec![],
+            Terminator::If { left, right, .. } => {
+                let mut operands = vec![left];
+                operands.extend(right);
+                operands
+            }
+            Terminator::Switch { value, .. }
+            | Terminator::Return(Some(value))
+            | Terminator::Throw(value) => vec![value],
+        }
+    }
+
+    /// Whether the terminator leaves the method, so it is where the exceptions
+    /// of its block are thrown
+    pub fn exits(&self) -> bool {
+        matches!(self, Terminator::Return(_) | Terminator::Throw(_))
+    }
+}
+
+/// An exception handler of a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handler {
+    /// Class constant of the exceptions caught, 0 for any
+    pub catch_type: u16,
+    pub block: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub phis: Vec<Value>,
+    /// Operations in execution order
+    pub instructions: Vec<Value>,
+    pub terminator: Terminator,
+    /// Handlers searched in order for an exception thrown by the block, which
+    /// only throws from its terminator if that exits the method and from its
+    /// last instruction otherwise. The phis of a handler take the values
+    /// variables hold just before.
+    pub handlers: Vec<Handler>,
+}
+
+/// A method body in SSA form, the entry block first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub blocks: Vec<BasicBlock>,
+    pub values: Vec<Definition>,
+}
+
+impl Function {
+    pub fn definition(&self, value: Value) -> &Definition {
+        &self.values[value.0]
+    }
+
+    pub fn ty(&self, value: Value) -> Option<Type> {
+        self.values[value.0].ty
+    }
+
+    /// Normal successors followed by handlers
+    pub fn successors(&self, block: Block) -> Vec<Block> {
+        let block = &self.blocks[block.0];
+        let mut successors = block.terminator.successors();
+        successors.extend(block.handlers.iter().map(|handler| handler.block));
+        successors.dedup();
+        successors
+    }
+
+    pub fn predecessors(&self, block: Block) -> Vec<Block> {
+        (0..self.blocks.len())
+            .map(Block)
+            .filter(|predecessor| self.successors(*predecessor).contains(&block))
+            .collect()
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl Display for Block {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "b{}", self.0)
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Int => "int",
+            Type::Long => "long",
+            Type::Float => "float",
+            Type::Double => "double",
+            Type::Reference => "reference",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Comma separated values
+fn list(values: &[Value]) -> String {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    values.join(", ")
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Parameter(slot) => write!(f, "parameter {}", slot),
+            Op::CaughtException => write!(f, "caught"),
+            Op::Int(value) => write!(f, "const {}", value),
+            Op::Long(value) => write!(f, "const {}", value),
+            Op::Float(value) => write!(f, "const {:?}", value),
+            Op::Double(value) => write!(f, "const {:?}", value),
+            Op::Null => write!(f, "const null"),
+            Op::Constant(index) => write!(f, "ldc #{}", index),
+            Op::Phi(operands) => {
+                let operands: Vec<String> = operands
+                    .iter()
+                    .map(|(block, value)| format!("{} {}", block, value))
+                    .collect();
+                write!(f, "phi {}", operands.join(", "))
+            }
+            Op::Binary(op, left, right) => {
+                let name = format!("{:?}", op).to_lowercase();
+                write!(f, "{} {}, {}", name, left, right)
+            }
+            Op::Negate(value) => write!(f, "neg {}", value),
+            Op::Convert(opcode, value) => write!(f, "{} {}", opcode.name(), value),
+            Op::Compare(opcode, left, right) => {
+                write!(f, "{} {}, {}", opcode.name(), left, right)
+            }
+            Op::ArrayLoad(_, array, index) => write!(f, "load {}[{}]", array, index),
+            Op::ArrayStore(_, array, index, value) => {
+                write!(f, "store {}[{}], {}", array, index, value)
+            }
+            Op::ArrayLength(array) => write!(f, "arraylength {}", array),
+            Op::NewArray(array_type, length) => {
+                write!(f, "newarray {} {}", array_type.name(), length)
+            }
+            Op::NewObjectArray(index, length) => write!(f, "anewarray #{} {}", index, length),
+            Op::NewMultiArray(index, lengths) => {
+                write!(f, "multianewarray #{} {}", index, list(lengths))
+            }
+            Op::New(index) => write!(f, "new #{}", index),
+            Op::CheckCast(index, value) => write!(f, "checkcast #{} {}", index, value),
+            Op::InstanceOf(index, value) => write!(f, "instanceof #{} {}", index, value),
+            Op::GetField(index, object) => write!(f, "getfield #{} {}", index, object),
+            Op::PutField(index, object, value) => {
+                write!(f, "putfield #{} {}, {}", index, object, value)
+            }
+            Op::GetStatic(index) => write!(f, "getstatic #{}", index),
+            Op::PutStatic(index, value) => write!(f, "putstatic #{} {}", index, value),
+            Op::Invoke(kind, index, arguments) => {
+                let name = format!("invoke{:?}", kind).to_lowercase();
+                if arguments.is_empty() {
+                    write!(f, "{} #{}", name, index)
+                } else {
+                    write!(f, "{} #{} {}", name, index, list(arguments))
+                }
+            }
+            Op::MonitorEnter(value) => write!(f, "monitorenter {}", value),
+            Op::MonitorExit(value) => write!(f, "monitorexit {}", value),
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Goto(target) => write!(f, "goto {}", target),
+            Terminator::If {
+                condition,
+                left,
+                right,
+                then,
+                otherwise,
+            } => {
+                let condition = format!("{:?}", condition).to_lowercase();
+                match right {
+                    Some(right) => write!(f, "if {} {}, {}", condition, left, right)?,
+                    None => write!(f, "if {} {}", condition, left)?,
+                }
+                write!(f, " then {} else {}", then, otherwise)
+            }
+            Terminator::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                write!(f, "switch {}", value)?;
+                for (key, target) in cases {
+                    write!(f, ", {}: {}", key, target)?;
+                }
+                write!(f, ", default: {}", default)
+            }
+            Terminator::Return(Some(value)) => write!(f, "return {}", value),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Throw(value) => write!(f, "throw {}", value),
+        }
+    }
+}
+
+impl Display for Function {
+    /// One block after the other, e.g.
+    ///
+    /// ```text
+    /// b1: catch #7 b2
+    ///     v3: int = phi b0 v1, b1 v5
+    ///     v4: int = invokestatic #12 v3
+    ///     goto b1
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (index, block) in self.blocks.iter().enumerate() {
+            write!(f, "{}:", Block(index))?;
+            for handler in &block.handlers {
+                match handler.catch_type {
+                    0 => write!(f, " catch any {}", handler.block)?,
+                    catch_type => write!(f, " catch #{} {}", catch_type, handler.block)?,
+                }
+            }
+            writeln!(f)?;
+            for value in block.phis.iter().chain(&block.instructions) {
+                let definition = self.definition(*value);
+                match definition.ty {
+                    Some(ty) => writeln!(f, "    {}: {} = {}", value, ty, definition.op)?,
+                    None => writeln!(f, "    {}", definition.op)?,
+                }
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::jasmin::parse;
+    use crate::ssa::{build, lower};
+    use crate::verifier::test::Hierarchy;
+    use crate::verifier::type_inference;
+    use classfile::attribute::AttributeType;
+    use classfile::constant_pool::ConstantPoolBuilder;
+
+    const LOOP: &str = ".class A
+.super java/lang/Object
+.method static m(I)I
+    .limit stack 2
+    .limit locals 3
+    .catch java/lang/RuntimeException from Start to End using Handler
+    iconst_0
+    istore_1
+    iconst_0
+    istore_2
+Loop:
+    iload_2
+    iload_0
+    if_icmpge Done
+    iload_1
+    iload_2
+    iadd
+    istore_1
+    iinc 2 1
+    goto Loop
+Done:
+    iload_1
+Start:
+    iload_0
+    idiv
+End:
+    ireturn
+Handler:
+    pop
+    iload_0
+    ifne Positive
+    iconst_0
+    goto Join
+Positive:
+    iconst_1
+Join:
+    ireturn
+.end method
+";
+
+    const SSA: &str = "b0:
+    v0: int = parameter 0
+    goto b1
+b1:
+    v1: int = const 0
+    v2: int = const 0
+    goto b2
+b2:
+    v3: int = phi b1 v1, b3 v5
+    v4: int = phi b1 v2, b3 v7
+    if ge v4, v0 then b4 else b3
+b3:
+    v5: int = add v3, v4
+    v6: int = const 1
+    v7: int = add v4, v6
+    goto b2
+b4: catch #2 b6
+    v8: int = div v3, v0
+    goto b5
+b5:
+    return v8
+b6:
+    v9: reference = caught
+    if ne v0 then b8 else b7
+b7:
+    v10: int = const 0
+    goto b9
+b8:
+    v11: int = const 1
+    goto b9
+b9:
+    v12: int = phi b7 v10, b8 v11
+    return v12
+";
+
+    #[test]
+    fn build_and_lower() {
+        let mut class_file = parse(LOOP).unwrap();
+        let function = build(&class_file.methods[0], &class_file.constant_pool).unwrap();
+        // the loop counters and the ternary become phis, the division is
+        // covered by the handler
+        assert_eq!(function.to_string(), SSA);
+
+        let mut pool = ConstantPoolBuilder::from_constant_pool(&class_file.constant_pool).unwrap();
+        let code = lower(&function, &mut pool).unwrap();
+        class_file.constant_pool = pool.build();
+        class_file.methods[0].attributes[0].attr_type = AttributeType::Code { code };
+        type_inference::verify_class(&class_file, &Hierarchy).unwrap();
+    }
+
+    /// SSA form of a static method `m(II)I` with `body`
+    fn ssa(body: &str) -> String {
+        let source = format!(
+            ".class A\n.super java/lang/Object\n.method static m(II)I\n    .limit stack 4\n    .limit locals 4\n{}\n.end method\n",
+            body
+        );
+        let class_file = parse(&source).unwrap();
+        build(&class_file.methods[0], &class_file.constant_pool)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn places_phis_at_joins() {
+        // each branch assigns a different parameter, both get a phi
+        let ssa = ssa(
+            "    iload_0\n    ifeq Else\n    iconst_2\n    istore_1\n    goto Join\nElse:\n    iconst_3\n    istore_0\nJoin:\n    iload_0\n    iload_1\n    iadd\n    ireturn",
+        );
+        assert_eq!(
+            ssa,
+            "b0:
+    v0: int = parameter 0
+    v1: int = parameter 1
+    goto b1
+b1:
+    if eq v0 then b3 else b2
+b2:
+    v2: int = const 2
+    goto b4
+b3:
+    v3: int = const 3
+    goto b4
+b4:
+    v4: int = phi b2 v0, b3 v3
+    v5: int = phi b2 v2, b3 v1
+    v6: int = add v4, v5
+    return v6
+"
+        );
+    }
+
+    #[test]
+    fn places_phis_at_loop_headers() {
+        // the counter merges the entry and both back edges, the parameter
+        // the loop doesn't assign has no phi
+        let ssa = ssa(
+            "Loop:\n    iload_0\n    ifeq Done\n    iinc 0 -1\n    iload_1\n    ifeq Loop\n    goto Loop\nDone:\n    iload_1\n    ireturn",
+        );
+        assert_eq!(
+            ssa,
+            "b0:
+    v0: int = parameter 0
+    v1: int = parameter 1
+    goto b1
+b1:
+    v2: int = phi b0 v0, b2 v4, b3 v4
+    if eq v2 then b4 else b2
+b2:
+    v3: int = const -1
+    v4: int = add v2, v3
+    if eq v1 then b1 else b3
+b3:
+    goto b1
+b4:
+    return v1
+"
+        );
+    }
+
+    #[test]
+    fn follows_exception_edges() {
+        // every instruction that can throw ends a block with an edge to the
+        // handler, which merges the local as it was at each of them
+        let ssa = ssa(
+            "    .catch java/lang/ArithmeticException from Start to End using Handler\n    iconst_0\n    istore_2\nStart:\n    iload_0\n    iload_1\n    idiv\n    istore_2\n    iload_2\n    iload_1\n    irem\n    istore_2\nEnd:\n    iload_2\n    ireturn\nHandler:\n    pop\n    iload_2\n    ireturn",
+        );
+        assert_eq!(
+            ssa,
+            "b0:
+    v0: int = parameter 0
+    v1: int = parameter 1
+    goto b1
+b1: catch #2 b4
+    v2: int = const 0
+    v3: int = div v0, v1
+    goto b2
+b2: catch #2 b4
+    v4: int = rem v3, v1
+    goto b3
+b3:
+    return v4
+b4:
+    v5: int = phi b1 v2, b2 v3
+    v6: reference = caught
+    return v5
+"
+        );
+    }
+}
@@ -0,0 +1,502 @@
+use crate::assembler::{Assembled, Assembler, Label};
+use crate::decoder::decode;
+use crate::error::Error;
+use crate::instruction::{Instruction, Operand};
+use crate::opcode::OpCode;
+use crate::verifier::stack_map::add_stack_maps;
+use crate::verifier::{ClassHierarchy, TYPE_CHECKING_VERSION};
+use classfile::attribute::{
+    Attribute, AttributeType, CodeAttribute, Exception, LineNumber, LocalVariable,
+    LocalVariableType,
+};
+use classfile::class_file::ClassFile;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// Rewrites `class_file` for verification by type checking: the subroutines
+/// of every method are inlined, a `StackMapTable` is computed for each and
+/// the version is raised to 50 if it is older.
+pub fn upgrade(class_file: &ClassFile, hierarchy: &dyn ClassHierarchy) -> Result<ClassFile, Error> {
+    let mut class_file = class_file.clone();
+    for method in &mut class_file.methods {
+        let index = match method.get_code_attr() {
+            Some(_) => method.code_attr_index.unwrap(),
+            None => continue,
+        };
+        let name_index = method.attributes[index].attribute_name_index;
+        let code = inline_subroutines(method.get_code_attr().unwrap())?;
+        method.attributes[index] = Attribute::new(name_index, AttributeType::Code { code })?;
+    }
+    if class_file.major_version < TYPE_CHECKING_VERSION {
+        class_file.major_version = TYPE_CHECKING_VERSION;
+        class_file.minor_version = 0;
+    }
+    add_stack_maps(&class_file, hierarchy)
+}
+
+/// Replaces every `jsr` by a copy of the subroutine it calls, so the code has
+/// no `jsr` or `ret` left.
+///
+/// The code reached from a `jsr` up to the `ret`s is copied for each call,
+/// including the copies for the calls it makes itself, and the `ret` becomes a
+/// `goto` back to the instruction after the call. The `jsr` pushes `null`
+/// instead of a return address and jumps to its copy, so the subroutine may
+/// still store it. An exception handler protecting code of a subroutine is
+/// copied with it, unless it is also reached by the caller, whose copy is then
+/// shared. The exception table, `LineNumberTable`, `LocalVariableTable` and
+/// `LocalVariableTypeTable` are rewritten to cover every copy, other
+/// attributes of the code refer to offsets that no longer exist and are
+/// dropped. Code without subroutines is returned unchanged.
+///
+/// The code should pass verification by type inference, a subroutine calling
+/// itself can't be inlined.
+pub fn inline_subroutines(code: &CodeAttribute) -> Result<CodeAttribute, Error> {
+    let instructions = decode(&code.code)?;
+    let has_subroutines = instructions.iter().any(|(_, instruction)| {
+        matches!(
+            instruction.opcode,
+            OpCode::jsr | OpCode::jsr_w | OpCode::ret
+        )
+    });
+    if !has_subroutines {
+        return Ok(code.clone());
+    }
+
+    let mut inliner = Inliner {
+        code,
+        indices: instructions
+            .iter()
+            .enumerate()
+            .map(|(index, (pc, _))| (*pc, index))
+            .collect(),
+        instructions,
+        bodies: BTreeMap::new(),
+        instances: vec![],
+        assembler: Assembler::new(),
+    };
+    inliner.bodies.insert(None, inliner.body(0)?);
+    for (pc, instruction) in &inliner.instructions {
+        if matches!(instruction.opcode, OpCode::jsr | OpCode::jsr_w) {
+            let entry = inliner.index_of(*pc, *instruction.targets()[0])?;
+            if !inliner.bodies.contains_key(&Some(entry)) {
+                let body = inliner.body(entry)?;
+                inliner.bodies.insert(Some(entry), body);
+            }
+        }
+    }
+    inliner.instantiate(None, None, None);
+    // every call adds an instance, emitted after the ones before it
+    let mut instance = 0;
+    while instance < inliner.instances.len() {
+        inliner.emit(instance)?;
+        instance += 1;
+    }
+    inliner.code_attribute()
+}
+
+/// Copy of the main code or of a subroutine called from a `jsr` of its
+/// parent
+struct Instance {
+    /// Index of the first instruction of the subroutine, None for the main
+    /// code
+    subroutine: Option<usize>,
+    parent: Option<usize>,
+    /// Where `ret` returns to
+    return_to: Option<Label>,
+    /// Labels of the instructions this copy owns
+    labels: BTreeMap<usize, Label>,
+    /// Instructions in the order they were emitted, with their labels
+    emitted: Vec<(usize, Label)>,
+    /// Bound after the last instruction
+    end: Label,
+}
+
+struct Inliner<'a> {
+    code: &'a CodeAttribute,
+    instructions: Vec<(u32, Instruction)>,
+    /// Index of the instruction at each offset
+    indices: BTreeMap<u32, usize>,
+    /// Instructions of the main code and of each subroutine
+    bodies: BTreeMap<Option<usize>, BTreeSet<usize>>,
+    instances: Vec<Instance>,
+    assembler: Assembler,
+}
+
+impl<'a> Inliner<'a> {
+    /// Instructions reached from `entry` without following a `jsr` into its
+    /// subroutine or a `ret` out of it, and the handlers of exceptions they
+    /// throw
+    fn body(&self, entry: usize) -> Result<BTreeSet<usize>, Error> {
+        let mut body = BTreeSet::new();
+        let mut pending = vec![entry];
+        while !pending.is_empty() {
+            while let Some(index) = pending.pop() {
+                if !body.insert(index) {
+                    continue;
+                }
+                let (pc, instruction) = &self.instructions[index];
+                match instruction.opcode {
+                    OpCode::ret => {}
+                    OpCode::jsr | OpCode::jsr_w => pending.push(self.next(index)?),
+                    opcode => {
+                        for target in instruction.targets() {
+                            pending.push(self.index_of(*pc, *target)?);
+                        }
+                        if opcode.info().falls_through() {
+                            pending.push(self.next(index)?);
+                        }
+                    }
+                }
+            }
+            for (number, entry) in self.code.exception_table.iter().enumerate() {
+                let handler = self
+                    .indices
+                    .get(&(entry.handler_pc as u32))
+                    .ok_or(Error::InvalidExceptionTable(number as u16))?;
+                let covered = body.iter().any(|index| {
+                    let pc = self.instructions[*index].0;
+                    pc >= entry.start_pc as u32 && pc < entry.end_pc as u32
+                });
+                if covered && !body.contains(handler) {
+                    pending.push(*handler);
+                }
+            }
+        }
+        Ok(body)
+    }
+
+    fn instantiate(
+        &mut self,
+        subroutine: Option<usize>,
+        parent: Option<usize>,
+        return_to: Option<Label>,
+    ) -> usize {
+        let instance = self.instances.len();
+        let end = self.assembler.new_label();
+        self.instances.push(Instance {
+            subroutine,
+            parent,
+            return_to,
+            labels: BTreeMap::new(),
+            emitted: vec![],
+            end,
+        });
+        for &index in &self.bodies[&subroutine] {
+            if self.owner(instance, index) == instance {
+                let label = self.assembler.new_label();
+                self.instances[instance].labels.insert(index, label);
+            }
+        }
+        instance
+    }
+
+    /// Instance whose copy of the instruction at `index` `instance` uses: the
+    /// outermost of `instance` and its ancestors that has it in its body
+    fn owner(&self, instance: usize, index: usize) -> usize {
+        let mut owner = instance;
+        let mut ancestor = self.instances[instance].parent;
+        while let Some(current) = ancestor {
+            if self.bodies[&self.instances[current].subroutine].contains(&index) {
+                owner = current;
+            }
+            ancestor = self.instances[current].parent;
+        }
+        owner
+    }
+
+    fn label(&self, instance: usize, index: usize) -> Label {
+        self.instances[self.owner(instance, index)].labels[&index]
+    }
+
+    fn emit(&mut self, instance: usize) -> Result<(), Error> {
+        let body: Vec<usize> = self.bodies[&self.instances[instance].subroutine]
+            .iter()
+            .cloned()
+            .collect();
+        for index in body {
+            if self.owner(instance, index) != instance {
+                continue;
+            }
+            let label = self.instances[instance].labels[&index];
+            self.assembler.bind(label);
+            self.instances[instance].emitted.push((index, label));
+            let (pc, instruction) = self.instructions[index].clone();
+            match instruction.opcode {
+                OpCode::jsr | OpCode::jsr_w => {
+                    let entry = self.index_of(pc, *instruction.targets()[0])?;
+                    let mut ancestor = Some(instance);
+                    while let Some(current) = ancestor {
+                        if self.instances[current].subroutine == Some(entry) {
+                            return Err(Error::RecursiveSubroutine(pc));
+                        }
+                        ancestor = self.instances[current].parent;
+                    }
+                    let return_to = self.label(instance, self.next(index)?);
+                    let called = self.instantiate(Some(entry), Some(instance), Some(return_to));
+                    let start = self.label(called, entry);
+                    self.assembler.emit(OpCode::aconst_null, Operand::None);
+                    self.assembler.emit(OpCode::goto, Operand::Branch(start));
+                }
+                OpCode::ret => {
+                    let return_to = self.instances[instance]
+                        .return_to
+                        .ok_or(Error::UnmatchedRet(pc))?;
+                    self.assembler
+                        .emit(OpCode::goto, Operand::Branch(return_to));
+                }
+                opcode => {
+                    let instruction = instruction.map_targets(|target| {
+                        self.index_of(pc, target)
+                            .map(|target| self.label(instance, target))
+                    })?;
+                    self.assembler.push(instruction);
+                    // the next instruction may be the copy of an ancestor
+                    if opcode.info().falls_through() {
+                        let next = self.next(index)?;
+                        if self.owner(instance, next) != instance {
+                            let next = self.label(instance, next);
+                            self.assembler.emit(OpCode::goto, Operand::Branch(next));
+                        }
+                    }
+                }
+            }
+        }
+        self.assembler.bind(self.instances[instance].end);
+        Ok(())
+    }
+
+    fn code_attribute(&self) -> Result<CodeAttribute, Error> {
+        let assembled = self.assembler.assemble()?;
+        let offset = |label: &Label| assembled.labels[label] as u16;
+
+        let mut exception_table = vec![];
+        for entry in &self.code.exception_table {
+            let handler = self.indices[&(entry.handler_pc as u32)];
+            for (instance, start, end) in self.ranges(entry.start_pc, entry.end_pc) {
+                exception_table.push(Exception {
+                    start_pc: offset(&start),
+                    end_pc: offset(&end),
+                    handler_pc: offset(&self.label(instance, handler)),
+                    catch_type: entry.catch_type,
+                });
+            }
+        }
+
+        let mut attributes = vec![];
+        for attribute in &self.code.attributes {
+            let attr_type = match &attribute.attr_type {
+                AttributeType::LineNumberTable { line_number_table } => {
+                    let mut lines = vec![];
+                    for instance in &self.instances {
+                        for (index, label) in &instance.emitted {
+                            let pc = self.instructions[*index].0;
+                            lines.extend(
+                                line_number_table
+                                    .iter()
+                                    .filter(|line| line.start_pc as u32 == pc)
+                                    .map(|line| LineNumber {
+                                        start_pc: offset(label),
+                                        line_number: line.line_number,
+                                    }),
+                            );
+                        }
+                    }
+                    lines.sort_by_key(|line| line.start_pc);
+                    AttributeType::LineNumberTable {
+                        line_number_table: lines,
+                    }
+                }
+                AttributeType::LocalVariableTable {
+                    local_variable_table,
+                } => AttributeType::LocalVariableTable {
+                    local_variable_table: local_variable_table
+                        .iter()
+                        .flat_map(|variable| {
+                            self.ranges(variable.start_pc, variable.start_pc + variable.length)
+                                .into_iter()
+                                .map(move |(_, start, end)| LocalVariable {
+                                    start_pc: offset(&start),
+                                    length: offset(&end) - offset(&start),
+                                    ..variable.clone()
+                                })
+                        })
+                        .collect(),
+                },
+                AttributeType::LocalVariableTypeTable {
+                    local_variable_type_table,
+                } => AttributeType::LocalVariableTypeTable {
+                    local_variable_type_table: local_variable_type_table
+                        .iter()
+                        .flat_map(|variable| {
+                            self.ranges(variable.start_pc, variable.start_pc + variable.length)
+                                .into_iter()
+                                .map(move |(_, start, end)| LocalVariableType {
+                                    start_pc: offset(&start),
+                                    length: offset(&end) - offset(&start),
+                                    ..variable.clone()
+                                })
+                        })
+                        .collect(),
+                },
+                // StackMapTable and type annotations
+                _ => continue,
+            };
+            attributes.push(Attribute::new(attribute.attribute_name_index, attr_type)?);
+        }
+
+        let Assembled { code, .. } = assembled;
+        Ok(CodeAttribute {
+            max_stack: self.code.max_stack,
+            max_locals: self.code.max_locals,
+            code: Arc::new(code),
+            exception_table,
+            attributes,
+        })
+    }
+
+    /// Runs of copied instructions whose original offset is in `start..end`,
+    /// with the instance they belong to
+    fn ranges(&self, start: u16, end: u16) -> Vec<(usize, Label, Label)> {
+        let mut ranges = vec![];
+        for (number, instance) in self.instances.iter().enumerate() {
+            let mut run: Option<Label> = None;
+            for (index, label) in &instance.emitted {
+                let pc = self.instructions[*index].0;
+                let covered = pc >= start as u32 && pc < end as u32;
+                match run {
+                    None if covered => run = Some(*label),
+                    Some(first) if !covered => {
+                        ranges.push((number, first, *label));
+                        run = None;
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(first) = run {
+                ranges.push((number, first, instance.end));
+            }
+        }
+        ranges
+    }
+
+    fn index_of(&self, pc: u32, target: u32) -> Result<usize, Error> {
+        self.indices
+            .get(&target)
+            .cloned()
+            .ok_or(Error::InvalidBranchTarget(pc, target as i64))
+    }
+
+    /// Index of the instruction after the one at `index`, which must not be
+    /// the last
+    fn next(&self, index: usize) -> Result<usize, Error> {
+        if index + 1 < self.instructions.len() {
+            Ok(index + 1)
+        } else {
+            Err(Error::UnexpectedEnd(self.instructions[index].0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::decoder::decode;
+    use crate::error::Error;
+    use crate::jasmin::parse;
+    use crate::opcode::OpCode;
+    use crate::subroutine::{inline_subroutines, upgrade};
+    use crate::verifier::test::Hierarchy;
+    use crate::verifier::{type_checker, type_inference};
+    use classfile::attribute::AttributeType;
+
+    /// try { x = 1 } finally { if (s != null) s = null }, within a try block
+    /// catching RuntimeException
+    const FINALLY: &str = ".bytecode 49.0
+.class A
+.super java/lang/Object
+.method static m(Ljava/lang/String;)I
+    .limit stack 2
+    .limit locals 4
+    .catch all from Start to End using Handler
+    .catch java/lang/RuntimeException from Start to Outer using Caught
+Start:
+    .line 1
+    iconst_1
+    istore_1
+End:
+    jsr Finally
+    .line 2
+    iload_1
+    ireturn
+Handler:
+    astore_1
+    jsr Finally
+    aload_1
+    athrow
+Finally:
+    .line 3
+    astore_2
+    aload_0
+    ifnull Done
+    aconst_null
+    astore_0
+Done:
+    ret 2
+Outer:
+Caught:
+    pop
+    iconst_0
+    ireturn
+.end method
+";
+
+    #[test]
+    fn inline_subroutines_into_callers() {
+        let class_file = parse(FINALLY).unwrap();
+        type_inference::verify_class(&class_file, &Hierarchy).unwrap();
+        let code = class_file.methods[0].get_code_attr().unwrap();
+        let inlined = inline_subroutines(code).unwrap();
+        let instructions = decode(&inlined.code).unwrap();
+        assert!(!instructions.iter().any(|(_, instruction)| matches!(
+            instruction.opcode,
+            OpCode::jsr | OpCode::jsr_w | OpCode::ret
+        )));
+        // two copies of the subroutine body
+        let nulls = instructions
+            .iter()
+            .filter(|(_, instruction)| instruction.opcode == OpCode::ifnull)
+            .count();
+        assert_eq!(nulls, 2);
+        // the catch all covers the main code once, RuntimeException covers
+        // it and each copy
+        let catch_types: Vec<u16> = inlined
+            .exception_table
+            .iter()
+            .map(|entry| entry.catch_type)
+            .collect();
+        assert_eq!(catch_types.len(), 4);
+        assert_eq!(catch_types.iter().filter(|t| **t == 0).count(), 1);
+        assert_eq!(
+            inlined.line_number(0),
+            code.line_number(0),
+            "first line kept"
+        );
+        let lines: Vec<u16> = match &inlined.attributes[0].attr_type {
+            AttributeType::LineNumberTable { line_number_table } => line_number_table
+                .iter()
+                .map(|line| line.line_number)
+                .collect(),
+            _ => panic!("no LineNumberTable"),
+        };
+        // the copies follow the main code
+        assert_eq!(lines, vec![1, 2, 3, 3]);
+
+        let upgraded = upgrade(&class_file, &Hierarchy).unwrap();
+        assert_eq!(upgraded.major_version, 50);
+        type_checker::verify_class(&upgraded, &Hierarchy).unwrap();
+
+        let recursive = FINALLY.replace("    ret 2", "    jsr Finally\n    ret 2");
+        let code = parse(&recursive).unwrap();
+        let error = inline_subroutines(code.methods[0].get_code_attr().unwrap()).unwrap_err();
+        assert!(matches!(error, Error::RecursiveSubroutine(_)));
+    }
+}
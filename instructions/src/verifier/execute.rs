@@ -0,0 +1,779 @@
+use crate::decoder::decode_at;
+use crate::instruction::{ArrayType, Instruction, Operand};
+use crate::opcode::OpCode;
+use crate::verifier::VerificationType::{self, *};
+use crate::verifier::{is_assignable, Frame, MethodContext};
+use classfile::constant::Constant;
+use classfile::descriptor::{FieldType, MethodDescriptor};
+
+/// Applies `instruction` to `frame`, checking the types it takes from the
+/// operand stack and the local variables. The resulting frame is the one
+/// reaching the next instruction and the branch targets, if any.
+///
+/// `jsr`, `jsr_w` and `ret` are rejected, verifiers allowing them handle them
+/// before calling this.
+pub(crate) fn execute(
+    method: &MethodContext,
+    frame: &mut Frame,
+    pc: u32,
+    instruction: &Instruction,
+) -> Result<(), String> {
+    let mut state = State { method, frame };
+    state.execute(pc, instruction)
+}
+
+struct State<'a, 'b> {
+    method: &'a MethodContext<'a>,
+    frame: &'b mut Frame,
+}
+
+impl<'a, 'b> State<'a, 'b> {
+    fn execute(&mut self, pc: u32, instruction: &Instruction) -> Result<(), String> {
+        use OpCode::*;
+        let opcode = instruction.opcode;
+        match opcode {
+            nop => {}
+            aconst_null => self.push(Null)?,
+            iconst_m1 | iconst_0 | iconst_1 | iconst_2 | iconst_3 | iconst_4 | iconst_5
+            | bipush | sipush => self.push(Integer)?,
+            lconst_0 | lconst_1 => self.push(Long)?,
+            fconst_0 | fconst_1 | fconst_2 => self.push(Float)?,
+            dconst_0 | dconst_1 => self.push(Double)?,
+            ldc | ldc_w | ldc2_w => {
+                let index = constant_index(instruction)?;
+                let t = self.constant_type(index)?;
+                if (opcode == ldc2_w) != (t.size() == 2) {
+                    return Err(format!(
+                        "Constant pool entry {} of type {} can't be loaded by {}",
+                        index,
+                        t,
+                        opcode.name()
+                    ));
+                }
+                self.push(t)?;
+            }
+
+            // Loads
+            iload | iload_0 | iload_1 | iload_2 | iload_3 => self.load(instruction, Integer)?,
+            lload | lload_0 | lload_1 | lload_2 | lload_3 => self.load(instruction, Long)?,
+            fload | fload_0 | fload_1 | fload_2 | fload_3 => self.load(instruction, Float)?,
+            dload | dload_0 | dload_1 | dload_2 | dload_3 => self.load(instruction, Double)?,
+            aload | aload_0 | aload_1 | aload_2 | aload_3 => {
+                let index = local_index(instruction)?;
+                let t = self.local(index)?;
+                if !t.is_reference() {
+                    return Err(format!(
+                        "Type {} (current frame, locals[{}]) is not a reference",
+                        t, index
+                    ));
+                }
+                self.push(t)?;
+            }
+            iaload => self.array_load(&["[I"], Integer)?,
+            laload => self.array_load(&["[J"], Long)?,
+            faload => self.array_load(&["[F"], Float)?,
+            daload => self.array_load(&["[D"], Double)?,
+            baload => self.array_load(&["[B", "[Z"], Integer)?,
+            caload => self.array_load(&["[C"], Integer)?,
+            saload => self.array_load(&["[S"], Integer)?,
+            aaload => {
+                self.pop_expect(&Integer)?;
+                let array = self.pop_initialized()?;
+                match array.component() {
+                    Some(component) if component.is_reference() => self.push(component)?,
+                    _ => return Err(format!("Type {} is not an array of references", array)),
+                }
+            }
+
+            // Stores
+            istore | istore_0 | istore_1 | istore_2 | istore_3 => {
+                self.store(instruction, Integer)?
+            }
+            lstore | lstore_0 | lstore_1 | lstore_2 | lstore_3 => self.store(instruction, Long)?,
+            fstore | fstore_0 | fstore_1 | fstore_2 | fstore_3 => self.store(instruction, Float)?,
+            dstore | dstore_0 | dstore_1 | dstore_2 | dstore_3 => {
+                self.store(instruction, Double)?
+            }
+            astore | astore_0 | astore_1 | astore_2 | astore_3 => {
+                let index = local_index(instruction)?;
+                let t = self.pop()?;
+                // a subroutine stores its return address
+                if !t.is_reference() && !matches!(t, ReturnAddress(_)) {
+                    return Err(self.not_assignable(&t, "reference"));
+                }
+                self.set_local(index, t)?;
+            }
+            iastore => self.array_store(&["[I"], Integer)?,
+            lastore => self.array_store(&["[J"], Long)?,
+            fastore => self.array_store(&["[F"], Float)?,
+            dastore => self.array_store(&["[D"], Double)?,
+            bastore => self.array_store(&["[B", "[Z"], Integer)?,
+            castore => self.array_store(&["[C"], Integer)?,
+            sastore => self.array_store(&["[S"], Integer)?,
+            aastore => {
+                // the component type is checked at run time
+                self.pop_initialized()?;
+                self.pop_expect(&Integer)?;
+                let array = self.pop_initialized()?;
+                if !matches!(array.component(), Some(component) if component.is_reference()) {
+                    return Err(format!("Type {} is not an array of references", array));
+                }
+            }
+
+            // Stack
+            pop => {
+                self.pop_category(1)?;
+            }
+            pop2 => {
+                if self.peek_category()? == 1 {
+                    self.pop_category(1)?;
+                    self.pop_category(1)?;
+                } else {
+                    self.pop()?;
+                }
+            }
+            dup => {
+                let v1 = self.pop_category(1)?;
+                self.push_all(&[v1.clone(), v1])?;
+            }
+            dup_x1 => {
+                let v1 = self.pop_category(1)?;
+                let v2 = self.pop_category(1)?;
+                self.push_all(&[v1.clone(), v2, v1])?;
+            }
+            dup_x2 => {
+                let v1 = self.pop_category(1)?;
+                let v2 = self.pop()?;
+                if v2.size() == 2 {
+                    self.push_all(&[v1.clone(), v2, v1])?;
+                } else {
+                    let v3 = self.pop_category(1)?;
+                    self.push_all(&[v1.clone(), v3, v2, v1])?;
+                }
+            }
+            dup2 => {
+                let v1 = self.pop()?;
+                if v1.size() == 2 {
+                    self.push_all(&[v1.clone(), v1])?;
+                } else {
+                    let v2 = self.pop_category(1)?;
+                    self.push_all(&[v2.clone(), v1.clone(), v2, v1])?;
+                }
+            }
+            dup2_x1 => {
+                let v1 = self.pop()?;
+                if v1.size() == 2 {
+                    let v2 = self.pop_category(1)?;
+                    self.push_all(&[v1.clone(), v2, v1])?;
+                } else {
+                    let v2 = self.pop_category(1)?;
+                    let v3 = self.pop_category(1)?;
+                    self.push_all(&[v2.clone(), v1.clone(), v3, v2, v1])?;
+                }
+            }
+            dup2_x2 => {
+                let v1 = self.pop()?;
+                if v1.size() == 2 {
+                    let v2 = self.pop()?;
+                    if v2.size() == 2 {
+                        self.push_all(&[v1.clone(), v2, v1])?;
+                    } else {
+                        let v3 = self.pop_category(1)?;
+                        self.push_all(&[v1.clone(), v3, v2, v1])?;
+                    }
+                } else {
+                    let v2 = self.pop_category(1)?;
+                    let v3 = self.pop()?;
+                    if v3.size() == 2 {
+                        self.push_all(&[v2.clone(), v1.clone(), v3, v2, v1])?;
+                    } else {
+                        let v4 = self.pop_category(1)?;
+                        self.push_all(&[v2.clone(), v1.clone(), v4, v3, v2, v1])?;
+                    }
+                }
+            }
+            swap => {
+                let v1 = self.pop_category(1)?;
+                let v2 = self.pop_category(1)?;
+                self.push_all(&[v1, v2])?;
+            }
+
+            // Math
+            iadd | isub | imul | idiv | irem | iand | ior | ixor | ishl | ishr | iushr => {
+                self.binary(Integer, Integer, Integer)?
+            }
+            ladd | lsub | lmul | ldiv | lrem | land | lor | lxor => {
+                self.binary(Long, Long, Long)?
+            }
+            lshl | lshr | lushr => self.binary(Long, Integer, Long)?,
+            fadd | fsub | fmul | fdiv | frem => self.binary(Float, Float, Float)?,
+            dadd | dsub | dmul | ddiv | drem => self.binary(Double, Double, Double)?,
+            ineg => self.unary(Integer, Integer)?,
+            lneg => self.unary(Long, Long)?,
+            fneg => self.unary(Float, Float)?,
+            dneg => self.unary(Double, Double)?,
+            iinc => {
+                let index = local_index(instruction)?;
+                let t = self.local(index)?;
+                if t != Integer {
+                    return Err(format!(
+                        "Type {} (current frame, locals[{}]) is not assignable to integer",
+                        t, index
+                    ));
+                }
+            }
+
+            // Conversions
+            i2l => self.unary(Integer, Long)?,
+            i2f => self.unary(Integer, Float)?,
+            i2d => self.unary(Integer, Double)?,
+            l2i => self.unary(Long, Integer)?,
+            l2f => self.unary(Long, Float)?,
+            l2d => self.unary(Long, Double)?,
+            f2i => self.unary(Float, Integer)?,
+            f2l => self.unary(Float, Long)?,
+            f2d => self.unary(Float, Double)?,
+            d2i => self.unary(Double, Integer)?,
+            d2l => self.unary(Double, Long)?,
+            d2f => self.unary(Double, Float)?,
+            i2b | i2c | i2s => self.unary(Integer, Integer)?,
+
+            // Comparisons
+            lcmp => self.binary(Long, Long, Integer)?,
+            fcmpl | fcmpg => self.binary(Float, Float, Integer)?,
+            dcmpl | dcmpg => self.binary(Double, Double, Integer)?,
+            ifeq | ifne | iflt | ifge | ifgt | ifle => {
+                self.pop_expect(&Integer)?;
+            }
+            if_icmpeq | if_icmpne | if_icmplt | if_icmpge | if_icmpgt | if_icmple => {
+                self.pop_expect(&Integer)?;
+                self.pop_expect(&Integer)?;
+            }
+            if_acmpeq | if_acmpne => {
+                self.pop_reference()?;
+                self.pop_reference()?;
+            }
+
+            // Control
+            goto | goto_w => {}
+            tableswitch | lookupswitch => {
+                self.pop_expect(&Integer)?;
+            }
+            ireturn => self.return_value(Integer)?,
+            lreturn => self.return_value(Long)?,
+            freturn => self.return_value(Float)?,
+            dreturn => self.return_value(Double)?,
+            areturn => {
+                let expected = match &self.method.descriptor.return_type {
+                    Some(return_type @ FieldType::Object(_))
+                    | Some(return_type @ FieldType::Array(_)) => {
+                        VerificationType::from_field_type(return_type)
+                    }
+                    _ => return Err("areturn in a method not returning a reference".to_string()),
+                };
+                self.pop_expect(&expected)?;
+            }
+            vreturn => {
+                if self.method.descriptor.return_type.is_some() {
+                    return Err("return in a method returning a value".to_string());
+                }
+                if self.method.name == "<init>" && self.frame.this_uninitialized() {
+                    return Err("Constructor must call super() or this() before return".to_string());
+                }
+            }
+
+            // References
+            getstatic => {
+                let (_, _, descriptor) = self.member(instruction)?;
+                self.push(field_type(&descriptor)?)?;
+            }
+            putstatic => {
+                let (_, _, descriptor) = self.member(instruction)?;
+                self.pop_expect(&field_type(&descriptor)?)?;
+            }
+            getfield => {
+                let (class, _, descriptor) = self.member(instruction)?;
+                self.pop_expect(&Reference(class))?;
+                self.push(field_type(&descriptor)?)?;
+            }
+            putfield => {
+                let (class, _, descriptor) = self.member(instruction)?;
+                self.pop_expect(&field_type(&descriptor)?)?;
+                // a constructor may set fields of its own class before calling super()
+                let own_field = self.method.name == "<init>" && class == self.method.class_name;
+                if !(own_field && self.peek()? == UninitializedThis) {
+                    self.pop_expect(&Reference(class))?;
+                } else {
+                    self.pop()?;
+                }
+            }
+            invokevirtual | invokespecial | invokestatic | invokeinterface | invokedynamic => {
+                self.invoke(instruction)?
+            }
+            new => {
+                let index = constant_index(instruction)?;
+                let class = self.method.class_name(index)?;
+                if class.starts_with('[') {
+                    return Err(format!("new of array type {}", class));
+                }
+                // an object left uninitialized by an earlier pass through this `new`
+                // can't be told apart from the new one
+                for local in self.frame.locals.iter_mut() {
+                    if *local == Uninitialized(pc) {
+                        *local = Top;
+                    }
+                }
+                if self.frame.stack.contains(&Uninitialized(pc)) {
+                    return Err(format!(
+                        "Uninitialized object created at {} is still on the operand stack",
+                        pc
+                    ));
+                }
+                self.push(Uninitialized(pc))?;
+            }
+            newarray => {
+                let array_type = match instruction.operand {
+                    Operand::NewArray(array_type) => array_type,
+                    _ => return Err("Invalid operand of newarray".to_string()),
+                };
+                self.pop_expect(&Integer)?;
+                self.push(Reference(primitive_array(array_type).to_string()))?;
+            }
+            anewarray => {
+                let index = constant_index(instruction)?;
+                let class = self.method.class_name(index)?;
+                self.pop_expect(&Integer)?;
+                self.push(Reference(array_of(&class)))?;
+            }
+            arraylength => {
+                let array = self.pop_initialized()?;
+                if array != Null && !array.is_array() {
+                    return Err(format!("Type {} is not an array", array));
+                }
+                self.push(Integer)?;
+            }
+            athrow => {
+                self.pop_expect(&Reference("java/lang/Throwable".to_string()))?;
+            }
+            checkcast => {
+                let index = constant_index(instruction)?;
+                let class = self.method.class_name(index)?;
+                self.pop_initialized()?;
+                self.push(Reference(class))?;
+            }
+            instanceof => {
+                constant_index(instruction)?;
+                self.pop_initialized()?;
+                self.push(Integer)?;
+            }
+            monitorenter | monitorexit => {
+                self.pop_initialized()?;
+            }
+
+            // Extended
+            multianewarray => {
+                let (index, dimensions) = match instruction.operand {
+                    Operand::MultiANewArray { index, dimensions } => (index, dimensions),
+                    _ => return Err("Invalid operand of multianewarray".to_string()),
+                };
+                let class = self.method.class_name(index)?;
+                let depth = class.bytes().take_while(|b| *b == b'[').count();
+                if dimensions == 0 || depth < dimensions as usize {
+                    return Err(format!(
+                        "multianewarray of {} dimensions of type {}",
+                        dimensions, class
+                    ));
+                }
+                for _ in 0..dimensions {
+                    self.pop_expect(&Integer)?;
+                }
+                self.push(Reference(class))?;
+            }
+            ifnull | ifnonnull => {
+                self.pop_reference()?;
+            }
+            _ => return Err(format!("Illegal instruction {}", opcode.name())),
+        }
+        Ok(())
+    }
+
+    fn invoke(&mut self, instruction: &Instruction) -> Result<(), String> {
+        let opcode = instruction.opcode;
+        let (class, name, descriptor) = if opcode == OpCode::invokedynamic {
+            let index = constant_index(instruction)?;
+            match self.method.constant(index)? {
+                Constant::InvokeDynamic {
+                    name_and_type_index,
+                    ..
+                } => {
+                    let (name, descriptor) = self.method.name_and_type(*name_and_type_index)?;
+                    (String::new(), name, descriptor)
+                }
+                _ => {
+                    return Err(format!(
+                        "Constant pool entry {} is not an InvokeDynamic",
+                        index
+                    ))
+                }
+            }
+        } else {
+            self.member(instruction)?
+        };
+        if name.starts_with('<') && !(opcode == OpCode::invokespecial && name == "<init>") {
+            return Err(format!("Illegal call to internal method {}", name));
+        }
+        let parsed = MethodDescriptor::parse(descriptor.as_bytes())
+            .map_err(|_| format!("Invalid method descriptor {}", descriptor))?;
+        if let Operand::InvokeInterface { count, .. } = instruction.operand {
+            if count as usize != parsed.parameter_slots() + 1 {
+                return Err(format!(
+                    "Inconsistent args count operand {} for {}",
+                    count, descriptor
+                ));
+            }
+        }
+        for parameter in parsed.parameters.iter().rev() {
+            self.pop_expect(&VerificationType::from_field_type(parameter))?;
+        }
+        match opcode {
+            OpCode::invokestatic | OpCode::invokedynamic => {}
+            OpCode::invokespecial if name == "<init>" => {
+                let receiver = self.pop()?;
+                let initialized = match &receiver {
+                    UninitializedThis => {
+                        let is_super = self.method.super_name.as_deref() == Some(class.as_str());
+                        if class != self.method.class_name && !is_super {
+                            return Err(format!(
+                                "Bad <init> method call, {} is neither the current class nor its superclass",
+                                class
+                            ));
+                        }
+                        Reference(self.method.class_name.clone())
+                    }
+                    Uninitialized(at) => {
+                        let created = self.class_of_new(*at)?;
+                        if created != class {
+                            return Err(format!(
+                                "Bad <init> method call, object of type {} initialized by a constructor of {}",
+                                created, class
+                            ));
+                        }
+                        Reference(created)
+                    }
+                    other => return Err(self.not_assignable(other, "an uninitialized object")),
+                };
+                for t in self
+                    .frame
+                    .locals
+                    .iter_mut()
+                    .chain(self.frame.stack.iter_mut())
+                {
+                    if *t == receiver {
+                        *t = initialized.clone();
+                    }
+                }
+            }
+            // interface types are only checked at run time
+            OpCode::invokeinterface => {
+                self.pop_initialized()?;
+            }
+            OpCode::invokespecial => {
+                self.pop_expect(&Reference(self.method.class_name.clone()))?;
+            }
+            _ => {
+                self.pop_expect(&Reference(class))?;
+            }
+        }
+        if let Some(return_type) = &parsed.return_type {
+            self.push(VerificationType::from_field_type(return_type))?;
+        }
+        Ok(())
+    }
+
+    fn constant_type(&self, index: u16) -> Result<VerificationType, String> {
+        let reference = |name: &str| Ok(Reference(name.to_string()));
+        match self.method.constant(index)? {
+            Constant::Integer(_) => Ok(Integer),
+            Constant::Float(_) => Ok(Float),
+            Constant::Long(_) => Ok(Long),
+            Constant::Double(_) => Ok(Double),
+            Constant::String { .. } => reference("java/lang/String"),
+            Constant::Class { .. } => reference("java/lang/Class"),
+            Constant::MethodType { .. } => reference("java/lang/invoke/MethodType"),
+            Constant::MethodHandle { .. } => reference("java/lang/invoke/MethodHandle"),
+            Constant::Dynamic {
+                name_and_type_index,
+                ..
+            } => {
+                let (_, descriptor) = self.method.name_and_type(*name_and_type_index)?;
+                field_type(&descriptor)
+            }
+            _ => Err(format!("Constant pool entry {} can't be loaded", index)),
+        }
+    }
+
+    fn member(&self, instruction: &Instruction) -> Result<(String, String, String), String> {
+        let index = match instruction.operand {
+            Operand::Constant(index) | Operand::InvokeInterface { index, .. } => index,
+            _ => return Err(format!("Invalid operand of {}", instruction.opcode.name())),
+        };
+        self.method.member(index)
+    }
+
+    /// Class instantiated by the `new` at `pc`
+    fn class_of_new(&self, pc: u32) -> Result<String, String> {
+        match decode_at(&self.method.code.code, pc) {
+            Ok((
+                Instruction {
+                    opcode: OpCode::new,
+                    operand: Operand::Constant(index),
+                },
+                _,
+            )) => self.method.class_name(index),
+            _ => Err(format!(
+                "Uninitialized({}) doesn't refer to a new instruction",
+                pc
+            )),
+        }
+    }
+
+    fn load(
+        &mut self,
+        instruction: &Instruction,
+        expected: VerificationType,
+    ) -> Result<(), String> {
+        let index = local_index(instruction)?;
+        let t = self.local(index)?;
+        if t != expected {
+            return Err(format!(
+                "Type {} (current frame, locals[{}]) is not assignable to {}",
+                t, index, expected
+            ));
+        }
+        if expected.size() == 2 {
+            // the second slot of a long or double
+            self.local(index + 1)?;
+        }
+        self.push(expected)
+    }
+
+    fn store(&mut self, instruction: &Instruction, t: VerificationType) -> Result<(), String> {
+        let index = local_index(instruction)?;
+        self.pop_expect(&t)?;
+        self.set_local(index, t)
+    }
+
+    fn local(&self, index: usize) -> Result<VerificationType, String> {
+        self.frame.locals.get(index).cloned().ok_or_else(|| {
+            format!(
+                "Local variable index {} out of range, max_locals is {}",
+                index, self.method.code.max_locals
+            )
+        })
+    }
+
+    fn set_local(&mut self, index: usize, t: VerificationType) -> Result<(), String> {
+        let size = t.size();
+        if index + size > self.frame.locals.len() {
+            return Err(format!(
+                "Local variable index {} out of range, max_locals is {}",
+                index + size - 1,
+                self.method.code.max_locals
+            ));
+        }
+        // storing into the second slot of a long or double breaks it
+        if index > 0 && self.frame.locals[index - 1].size() == 2 {
+            self.frame.locals[index - 1] = Top;
+        }
+        self.frame.locals[index] = t;
+        if size == 2 {
+            self.frame.locals[index + 1] = Top;
+        }
+        Ok(())
+    }
+
+    fn array_load(&mut self, arrays: &[&str], t: VerificationType) -> Result<(), String> {
+        self.pop_expect(&Integer)?;
+        self.pop_array(arrays)?;
+        self.push(t)
+    }
+
+    fn array_store(&mut self, arrays: &[&str], t: VerificationType) -> Result<(), String> {
+        self.pop_expect(&t)?;
+        self.pop_expect(&Integer)?;
+        self.pop_array(arrays)
+    }
+
+    /// Pops an array of one of the given primitive array types, or null
+    fn pop_array(&mut self, arrays: &[&str]) -> Result<(), String> {
+        let index = self.frame.stack.len().saturating_sub(1);
+        let array = self.pop()?;
+        match &array {
+            Null => Ok(()),
+            Reference(name) if arrays.contains(&name.as_str()) => Ok(()),
+            _ => Err(format!(
+                "Type {} (current frame, stack[{}]) is not assignable to '{}'",
+                array,
+                index,
+                arrays.join("' or '")
+            )),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        left: VerificationType,
+        right: VerificationType,
+        result: VerificationType,
+    ) -> Result<(), String> {
+        self.pop_expect(&right)?;
+        self.pop_expect(&left)?;
+        self.push(result)
+    }
+
+    fn unary(&mut self, operand: VerificationType, result: VerificationType) -> Result<(), String> {
+        self.pop_expect(&operand)?;
+        self.push(result)
+    }
+
+    fn return_value(&mut self, t: VerificationType) -> Result<(), String> {
+        let declared = self
+            .method
+            .descriptor
+            .return_type
+            .as_ref()
+            .map(VerificationType::from_field_type);
+        if declared.as_ref() != Some(&t) {
+            return Err(format!(
+                "Method returning {} can't return {}",
+                declared.map_or("void".to_string(), |d| d.to_string()),
+                t
+            ));
+        }
+        self.pop_expect(&t)?;
+        Ok(())
+    }
+
+    fn push(&mut self, t: VerificationType) -> Result<(), String> {
+        self.frame.stack.push(t);
+        if self.frame.stack_size() > self.method.code.max_stack as usize {
+            return Err(format!(
+                "Operand stack overflow, max_stack is {}",
+                self.method.code.max_stack
+            ));
+        }
+        Ok(())
+    }
+
+    fn push_all(&mut self, types: &[VerificationType]) -> Result<(), String> {
+        for t in types {
+            self.push(t.clone())?;
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Result<VerificationType, String> {
+        self.frame
+            .stack
+            .last()
+            .cloned()
+            .ok_or_else(|| "Operand stack underflow".to_string())
+    }
+
+    fn peek_category(&self) -> Result<usize, String> {
+        self.peek().map(|t| t.size())
+    }
+
+    fn pop(&mut self) -> Result<VerificationType, String> {
+        self.frame
+            .stack
+            .pop()
+            .ok_or_else(|| "Operand stack underflow".to_string())
+    }
+
+    fn pop_category(&mut self, category: usize) -> Result<VerificationType, String> {
+        let t = self.pop()?;
+        if t.size() != category {
+            return Err(self.not_assignable(&t, &format!("a category {} type", category)));
+        }
+        Ok(t)
+    }
+
+    fn pop_expect(&mut self, expected: &VerificationType) -> Result<VerificationType, String> {
+        let t = self.pop()?;
+        if !is_assignable(&t, expected, self.method.hierarchy) {
+            return Err(self.not_assignable(&t, &expected.to_string()));
+        }
+        Ok(t)
+    }
+
+    /// Pops a reference, uninitialized objects included
+    fn pop_reference(&mut self) -> Result<VerificationType, String> {
+        let t = self.pop()?;
+        if !t.is_reference() {
+            return Err(self.not_assignable(&t, "reference"));
+        }
+        Ok(t)
+    }
+
+    /// Pops null or a class or array type
+    fn pop_initialized(&mut self) -> Result<VerificationType, String> {
+        let t = self.pop()?;
+        if !matches!(t, Null | Reference(_)) {
+            return Err(self.not_assignable(&t, "an initialized reference"));
+        }
+        Ok(t)
+    }
+
+    /// Error for the value just popped from `stack[len]`
+    fn not_assignable(&self, t: &VerificationType, expected: &str) -> String {
+        format!(
+            "Type {} (current frame, stack[{}]) is not assignable to {}",
+            t,
+            self.frame.stack.len(),
+            expected
+        )
+    }
+}
+
+fn constant_index(instruction: &Instruction) -> Result<u16, String> {
+    match instruction.operand {
+        Operand::Constant(index) => Ok(index),
+        _ => Err(format!("Invalid operand of {}", instruction.opcode.name())),
+    }
+}
+
+fn local_index(instruction: &Instruction) -> Result<usize, String> {
+    instruction
+        .local_index()
+        .map(|index| index as usize)
+        .ok_or_else(|| format!("Invalid operand of {}", instruction.opcode.name()))
+}
+
+fn field_type(descriptor: &str) -> Result<VerificationType, String> {
+    FieldType::parse(descriptor.as_bytes())
+        .map(|t| VerificationType::from_field_type(&t))
+        .map_err(|_| format!("Invalid field descriptor {}", descriptor))
+}
+
+fn primitive_array(array_type: ArrayType) -> &'static str {
+    match array_type {
+        ArrayType::Boolean => "[Z",
+        ArrayType::Char => "[C",
+        ArrayType::Float => "[F",
+        ArrayType::Double => "[D",
+        ArrayType::Byte => "[B",
+        ArrayType::Short => "[S",
+        ArrayType::Int => "[I",
+        ArrayType::Long => "[J",
+    }
+}
+
+/// Array type with components of the class or array type `class`
+pub(crate) fn array_of(class: &str) -> String {
+    if class.starts_with('[') {
+        format!("[{}", class)
+    } else {
+        format!("[L{};", class)
+    }
+}
@@ -0,0 +1,562 @@
+//! Bytecode verification of JVMS 4.10.
+//!
+//! Class files of version 50 and above are verified by type checking against
+//! their `StackMapTable` attributes, older ones by type inference. Assignability
+//! between class types is decided by a `ClassHierarchy` supplied by the caller,
+//! which may load classes to answer.
+
+use classfile::attribute::CodeAttribute;
+use classfile::class_file::ClassFile;
+use classfile::constant::Constant;
+use classfile::descriptor::{BaseType, FieldType, MethodDescriptor};
+use classfile::ConstantPoolRef;
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+
+mod execute;
+pub mod stack_map;
+pub mod type_checker;
+pub mod type_inference;
+
+pub const JAVA_LANG_OBJECT: &str = "java/lang/Object";
+
+pub const JAVA_LANG_THROWABLE: &str = "java/lang/Throwable";
+
+/// Class files from this major version on carry stack maps
+pub const TYPE_CHECKING_VERSION: u16 = 50;
+
+/// Types of local variables and operand stack entries, JVMS 4.10.1.2
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum VerificationType {
+    Top,
+    /// boolean, byte, char, short and int
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    /// `this` in a constructor before the superclass constructor is called
+    UninitializedThis,
+    /// Object created by the `new` at this offset, not yet initialized
+    Uninitialized(u32),
+    /// Class name in internal form, or the descriptor of an array type
+    Reference(String),
+    /// Pushed by the `jsr` calling the subroutine at this offset
+    ReturnAddress(u32),
+}
+
+impl VerificationType {
+    pub fn object() -> VerificationType {
+        VerificationType::Reference(JAVA_LANG_OBJECT.to_string())
+    }
+
+    pub fn from_field_type(field_type: &FieldType) -> VerificationType {
+        match field_type {
+            FieldType::Base(base_type) => match base_type {
+                BaseType::Long => VerificationType::Long,
+                BaseType::Double => VerificationType::Double,
+                BaseType::Float => VerificationType::Float,
+                _ => VerificationType::Integer,
+            },
+            FieldType::Object(name) => VerificationType::Reference(name.clone()),
+            FieldType::Array(_) => VerificationType::Reference(field_type.descriptor()),
+        }
+    }
+
+    /// Operand stack and local variable slots taken by a value of this type
+    pub fn size(&self) -> usize {
+        match self {
+            VerificationType::Long | VerificationType::Double => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn is_reference(&self) -> bool {
+        matches!(
+            self,
+            VerificationType::Null
+                | VerificationType::Reference(_)
+                | VerificationType::UninitializedThis
+                | VerificationType::Uninitialized(_)
+        )
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, VerificationType::Reference(name) if name.starts_with('['))
+    }
+
+    /// Component type of an array type, Null for Null
+    pub fn component(&self) -> Option<VerificationType> {
+        match self {
+            VerificationType::Null => Some(VerificationType::Null),
+            VerificationType::Reference(name) if name.starts_with('[') => {
+                FieldType::parse(&name.as_bytes()[1..])
+                    .ok()
+                    .map(|component| VerificationType::from_field_type(&component))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for VerificationType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationType::Top => write!(f, "top"),
+            VerificationType::Integer => write!(f, "integer"),
+            VerificationType::Float => write!(f, "float"),
+            VerificationType::Long => write!(f, "long"),
+            VerificationType::Double => write!(f, "double"),
+            VerificationType::Null => write!(f, "null"),
+            VerificationType::UninitializedThis => write!(f, "uninitializedThis"),
+            VerificationType::Uninitialized(pc) => write!(f, "uninitialized({})", pc),
+            VerificationType::Reference(name) => write!(f, "'{}'", name),
+            VerificationType::ReturnAddress(pc) => write!(f, "returnAddress({})", pc),
+        }
+    }
+}
+
+/// Types of the local variables and the operand stack at an instruction
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Frame {
+    /// One entry per slot, `long` and `double` are followed by `Top`
+    pub locals: Vec<VerificationType>,
+    /// One entry per value, whatever its size
+    pub stack: Vec<VerificationType>,
+}
+
+impl Frame {
+    /// Operand stack depth counted in slots
+    pub fn stack_size(&self) -> usize {
+        self.stack.iter().map(|t| t.size()).sum()
+    }
+
+    /// Whether `this` is still uninitialized, flagThisUninit of JVMS 4.10.1.4
+    pub fn this_uninitialized(&self) -> bool {
+        self.locals.contains(&VerificationType::UninitializedThis)
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let list = |types: &[VerificationType]| {
+            types
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(
+            f,
+            "locals: {{ {} }} stack: {{ {} }}",
+            list(&self.locals),
+            list(&self.stack)
+        )
+    }
+}
+
+/// Answers subtyping questions about classes the verifier doesn't see
+pub trait ClassHierarchy {
+    /// Direct superclass of `class`, None for `java/lang/Object` and for a
+    /// class that can't be found
+    fn super_class(&self, class: &str) -> Option<String>;
+
+    fn is_interface(&self, class: &str) -> bool;
+
+    /// Whether `class` is `ancestor` or one of its subclasses
+    fn is_subclass_of(&self, class: &str, ancestor: &str) -> bool {
+        let mut current = Some(class.to_string());
+        while let Some(name) = current {
+            if name == ancestor {
+                return true;
+            }
+            current = self.super_class(&name);
+        }
+        false
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub class: String,
+    /// Name followed by the descriptor, e.g. `main([Ljava/lang/String;)V`
+    pub method: String,
+    /// Offset of the offending instruction, None when the method as a whole is
+    /// rejected
+    pub pc: Option<u32>,
+    pub message: String,
+    /// Frame the instruction was checked in
+    pub current_frame: Option<Box<Frame>>,
+    /// Frame recorded in the stack map, or inferred so far, that
+    /// `current_frame` failed to match
+    pub stackmap_frame: Option<Box<Frame>>,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.class, self.method)?;
+        if let Some(pc) = self.pc {
+            write!(f, " @{}", pc)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(frame) = &self.current_frame {
+            write!(f, "\n  Current frame: {}", frame)?;
+        }
+        if let Some(frame) = &self.stackmap_frame {
+            write!(f, "\n  Stackmap frame: {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Verifies every method of `class_file`.
+///
+/// Class files older than version 50 have no stack maps and are verified by
+/// type inference, version 50 falls back to it when type checking fails as
+/// JVMS 4.10 allows.
+pub fn verify(class_file: &ClassFile, hierarchy: &dyn ClassHierarchy) -> Result<(), VerifyError> {
+    match class_file.major_version {
+        major if major < TYPE_CHECKING_VERSION => {
+            type_inference::verify_class(class_file, hierarchy)
+        }
+        TYPE_CHECKING_VERSION => type_checker::verify_class(class_file, hierarchy)
+            .or_else(|_| type_inference::verify_class(class_file, hierarchy)),
+        _ => type_checker::verify_class(class_file, hierarchy),
+    }
+}
+
+/// isAssignable of JVMS 4.10.1.2, whether a value of type `from` can be used
+/// where `to` is expected
+pub fn is_assignable(
+    from: &VerificationType,
+    to: &VerificationType,
+    hierarchy: &dyn ClassHierarchy,
+) -> bool {
+    use VerificationType::*;
+    if from == to {
+        return true;
+    }
+    match (from, to) {
+        (_, Top) => true,
+        (Null, Reference(_)) => true,
+        (Reference(from), Reference(to)) => is_java_assignable(from, to, hierarchy),
+        _ => false,
+    }
+}
+
+/// isJavaAssignable of JVMS 4.10.1.2 for class and array types. Interfaces
+/// are treated like `java/lang/Object`, an interface type is only checked
+/// when the method is invoked.
+fn is_java_assignable(from: &str, to: &str, hierarchy: &dyn ClassHierarchy) -> bool {
+    if from == to || to == JAVA_LANG_OBJECT {
+        return true;
+    }
+    match (from.strip_prefix('['), to.strip_prefix('[')) {
+        (Some(from), Some(to)) => {
+            let from = FieldType::parse(from.as_bytes());
+            let to = FieldType::parse(to.as_bytes());
+            match (from, to) {
+                (Ok(FieldType::Base(from)), Ok(FieldType::Base(to))) => from == to,
+                (Ok(FieldType::Base(_)), _) | (_, Ok(FieldType::Base(_))) => false,
+                (Ok(from), Ok(to)) => is_assignable(
+                    &VerificationType::from_field_type(&from),
+                    &VerificationType::from_field_type(&to),
+                    hierarchy,
+                ),
+                _ => false,
+            }
+        }
+        (Some(_), None) => to == "java/lang/Cloneable" || to == "java/io/Serializable",
+        (None, Some(_)) => false,
+        (None, None) => hierarchy.is_interface(to) || hierarchy.is_subclass_of(from, to),
+    }
+}
+
+/// Checks that every exception handler covers a range of whole instructions,
+/// starts at an instruction and catches a `Throwable`
+pub(crate) fn check_exception_table(
+    method: &MethodContext,
+    boundaries: &BTreeSet<u32>,
+) -> Result<(), VerifyError> {
+    let length = method.code.code.len() as u32;
+    let throwable = VerificationType::Reference(JAVA_LANG_THROWABLE.to_string());
+    for entry in &method.code.exception_table {
+        let (start, end, handler) = (
+            entry.start_pc as u32,
+            entry.end_pc as u32,
+            entry.handler_pc as u32,
+        );
+        let valid = start < end
+            && boundaries.contains(&start)
+            && (end == length || boundaries.contains(&end))
+            && boundaries.contains(&handler);
+        if !valid {
+            return Err(method.error(
+                None,
+                format!(
+                    "Illegal exception table range from {} to {} handled at {}",
+                    start, end, handler
+                ),
+            ));
+        }
+        let caught = method
+            .caught(entry.catch_type)
+            .map_err(|message| method.error(None, message))?;
+        if !is_assignable(&caught, &throwable, method.hierarchy) {
+            return Err(method.error(
+                Some(handler),
+                format!("Catch type {} is not a subclass of Throwable", caught),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Error at `pc` with the frame it was detected in and the frame that frame
+/// was checked against
+pub(crate) fn frame_error(
+    method: &MethodContext,
+    pc: u32,
+    message: String,
+    current: &Frame,
+    stack_map: Option<&Frame>,
+) -> VerifyError {
+    VerifyError {
+        current_frame: Some(Box::new(current.clone())),
+        stackmap_frame: stack_map.map(|frame| Box::new(frame.clone())),
+        ..method.error(Some(pc), message)
+    }
+}
+
+/// Locals in the form of the `StackMapTable`, without the `Top` following
+/// a long or double or the unused slots at the end
+pub(crate) fn compact(locals: &[VerificationType]) -> Vec<VerificationType> {
+    let mut compact = vec![];
+    let mut slots = locals.iter();
+    while let Some(local) = slots.next() {
+        if local.size() == 2 {
+            slots.next();
+        }
+        compact.push(local.clone());
+    }
+    while compact.last() == Some(&VerificationType::Top) {
+        compact.pop();
+    }
+    compact
+}
+
+/// A method being verified and what the verifier needs to know about it
+pub(crate) struct MethodContext<'a> {
+    pub class_name: String,
+    pub super_name: Option<String>,
+    pub constant_pool: &'a ConstantPoolRef,
+    pub name: String,
+    pub descriptor: MethodDescriptor,
+    pub is_static: bool,
+    pub code: &'a CodeAttribute,
+    pub hierarchy: &'a dyn ClassHierarchy,
+}
+
+impl<'a> MethodContext<'a> {
+    /// Frame at the method entry, JVMS 4.10.1.6 methodInitialStackFrame
+    pub fn initial_frame(&self) -> Result<Frame, String> {
+        let mut locals = vec![];
+        if !self.is_static {
+            if self.name == "<init>" && self.class_name != JAVA_LANG_OBJECT {
+                locals.push(VerificationType::UninitializedThis);
+            } else {
+                locals.push(VerificationType::Reference(self.class_name.clone()));
+            }
+        }
+        for parameter in &self.descriptor.parameters {
+            let t = VerificationType::from_field_type(parameter);
+            let size = t.size();
+            locals.push(t);
+            if size == 2 {
+                locals.push(VerificationType::Top);
+            }
+        }
+        let max_locals = self.code.max_locals as usize;
+        if locals.len() > max_locals {
+            return Err(format!(
+                "Arguments need {} local variable slots, max_locals is {}",
+                locals.len(),
+                max_locals
+            ));
+        }
+        locals.resize(max_locals, VerificationType::Top);
+        Ok(Frame {
+            locals,
+            stack: vec![],
+        })
+    }
+
+    pub fn error(&self, pc: Option<u32>, message: String) -> VerifyError {
+        VerifyError {
+            class: self.class_name.clone(),
+            method: format!("{}{}", self.name, self.descriptor.descriptor()),
+            pc,
+            message,
+            current_frame: None,
+            stackmap_frame: None,
+        }
+    }
+
+    pub fn constant(&self, index: u16) -> Result<&'a Constant, String> {
+        (index as usize)
+            .checked_sub(1)
+            .and_then(|i| self.constant_pool.get(i))
+            .ok_or_else(|| format!("Invalid constant pool index {}", index))
+    }
+
+    pub fn utf8(&self, index: u16) -> Result<String, String> {
+        match self.constant(index)? {
+            Constant::Utf8(bytes) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            _ => Err(format!("Constant pool entry {} is not a Utf8", index)),
+        }
+    }
+
+    pub fn class_name(&self, index: u16) -> Result<String, String> {
+        match self.constant(index)? {
+            Constant::Class { name_index } => self.utf8(*name_index),
+            _ => Err(format!("Constant pool entry {} is not a Class", index)),
+        }
+    }
+
+    /// Type of the exception caught by a handler, `catch_type` is 0 for any
+    pub fn caught(&self, catch_type: u16) -> Result<VerificationType, String> {
+        match catch_type {
+            0 => Ok(VerificationType::Reference(JAVA_LANG_THROWABLE.to_string())),
+            index => Ok(VerificationType::Reference(self.class_name(index)?)),
+        }
+    }
+
+    /// Name and descriptor of a NameAndType entry
+    pub fn name_and_type(&self, index: u16) -> Result<(String, String), String> {
+        match self.constant(index)? {
+            Constant::NameAndType {
+                name_index,
+                descriptor_index,
+            } => Ok((self.utf8(*name_index)?, self.utf8(*descriptor_index)?)),
+            _ => Err(format!(
+                "Constant pool entry {} is not a NameAndType",
+                index
+            )),
+        }
+    }
+
+    /// Class, name and descriptor of a field or method reference
+    pub fn member(&self, index: u16) -> Result<(String, String, String), String> {
+        match self.constant(index)? {
+            Constant::FieldRef {
+                class_index,
+                name_and_type_index,
+            }
+            | Constant::MethodRef {
+                class_index,
+                name_and_type_index,
+            }
+            | Constant::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => {
+                let (name, descriptor) = self.name_and_type(*name_and_type_index)?;
+                Ok((self.class_name(*class_index)?, name, descriptor))
+            }
+            _ => Err(format!(
+                "Constant pool entry {} is not a member reference",
+                index
+            )),
+        }
+    }
+}
+
+/// Context of each method with code, in class file order
+pub(crate) fn methods<'a>(
+    class_file: &'a ClassFile,
+    hierarchy: &'a dyn ClassHierarchy,
+) -> Result<Vec<MethodContext<'a>>, VerifyError> {
+    let constant_pool = &class_file.constant_pool;
+    let class_error = |message: String| VerifyError {
+        class: String::new(),
+        method: String::new(),
+        pc: None,
+        message,
+        current_frame: None,
+        stackmap_frame: None,
+    };
+    let utf8 = |index: u16| match (index as usize)
+        .checked_sub(1)
+        .and_then(|i| constant_pool.get(i))
+    {
+        Some(Constant::Utf8(bytes)) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+        _ => Err(class_error(format!("Invalid Utf8 constant {}", index))),
+    };
+    let class_name = |index: u16| match (index as usize)
+        .checked_sub(1)
+        .and_then(|i| constant_pool.get(i))
+    {
+        Some(Constant::Class { name_index }) => utf8(*name_index),
+        _ => Err(class_error(format!("Invalid Class constant {}", index))),
+    };
+    let this_class = class_name(class_file.this_class)?;
+    let super_class = match class_file.super_class {
+        0 => None,
+        index => Some(class_name(index)?),
+    };
+
+    let mut methods = vec![];
+    for method in &class_file.methods {
+        let code = match method.get_code_attr() {
+            Some(code) => code,
+            None => continue,
+        };
+        let name = utf8(method.name_index)?;
+        let descriptor = utf8(method.descriptor_index)?;
+        let descriptor =
+            MethodDescriptor::parse(descriptor.as_bytes()).map_err(|_| VerifyError {
+                class: this_class.clone(),
+                method: format!("{}{}", name, descriptor),
+                ..class_error(format!("Invalid method descriptor {}", descriptor))
+            })?;
+        methods.push(MethodContext {
+            class_name: this_class.clone(),
+            super_name: super_class.clone(),
+            constant_pool,
+            name,
+            descriptor,
+            is_static: method.flags().is_static(),
+            code,
+            hierarchy,
+        });
+    }
+    Ok(methods)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::verifier::ClassHierarchy;
+
+    /// Superclasses of the classes `Verified` uses
+    pub(crate) struct Hierarchy;
+
+    impl ClassHierarchy for Hierarchy {
+        fn super_class(&self, class: &str) -> Option<String> {
+            let super_class = match class {
+                "java/lang/NumberFormatException" => "java/lang/IllegalArgumentException",
+                "java/lang/IllegalArgumentException" | "java/lang/IllegalStateException" => {
+                    "java/lang/RuntimeException"
+                }
+                "java/lang/RuntimeException" | "java/io/IOException" => "java/lang/Exception",
+                "java/lang/Exception" => "java/lang/Throwable",
+                "java/lang/Object" => return None,
+                _ => "java/lang/Object",
+            };
+            Some(super_class.to_string())
+        }
+
+        fn is_interface(&self, class: &str) -> bool {
+            matches!(class, "java/util/List" | "java/lang/Runnable")
+        }
+    }
+}
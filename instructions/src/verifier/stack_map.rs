@@ -0,0 +1,243 @@
+use crate::decoder::decode;
+use crate::error::Error;
+use crate::verifier::type_inference::infer_frames;
+use crate::verifier::{
+    compact, frame_error, methods, ClassHierarchy, Frame, MethodContext, VerificationType,
+};
+use classfile::attribute::{
+    Attribute, AttributeType, StackMap, StackMapFrame, VerificationTypeInfo,
+};
+use classfile::class_file::ClassFile;
+use classfile::constant_pool::ConstantPoolBuilder;
+use std::collections::BTreeSet;
+
+/// Replaces the `StackMapTable` of every method of `class_file` with one
+/// computed by type inference, JVMS 4.10.2.
+///
+/// A frame is recorded at each branch target, exception handler and
+/// instruction following an unconditional branch, where type checking needs
+/// one. Each is the merge of all frames reaching the instruction, so code that
+/// passes type inference passes type checking against them as long as
+/// `hierarchy` gives the same answers. The class file version is left as is.
+pub fn add_stack_maps(
+    class_file: &ClassFile,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<ClassFile, Error> {
+    let mut pool = ConstantPoolBuilder::from_constant_pool(&class_file.constant_pool)?;
+    let mut tables = vec![];
+    for method in methods(class_file, hierarchy)? {
+        tables.push(stack_map_table(&method, &mut pool)?);
+    }
+
+    let mut class_file = class_file.clone();
+    let mut tables = tables.into_iter();
+    for method in &mut class_file.methods {
+        // methods lists the methods with code in order
+        let index = match method.get_code_attr() {
+            Some(_) => method.code_attr_index.unwrap(),
+            None => continue,
+        };
+        let entries = tables.next().unwrap();
+        let name_index = method.attributes[index].attribute_name_index;
+        let mut code = method.get_code_attr().unwrap().clone();
+        code.attributes.retain(|attribute| {
+            !matches!(attribute.attr_type, AttributeType::StackMapTable { .. })
+        });
+        if !entries.is_empty() {
+            code.attributes.push(Attribute::new(
+                pool.utf8("StackMapTable")?,
+                AttributeType::StackMapTable { entries },
+            )?);
+        }
+        method.attributes[index] = Attribute::new(name_index, AttributeType::Code { code })?;
+    }
+    class_file.constant_pool = pool.build();
+    Ok(class_file)
+}
+
+/// Stack map frames of a method, in the most compact form of each
+fn stack_map_table(
+    method: &MethodContext,
+    pool: &mut ConstantPoolBuilder,
+) -> Result<Vec<StackMap>, Error> {
+    let frames = infer_frames(method)?;
+    let instructions = decode(&method.code.code)?;
+    let mut offsets = BTreeSet::new();
+    for (index, (_, instruction)) in instructions.iter().enumerate() {
+        offsets.extend(instruction.targets().into_iter().cloned());
+        if !instruction.opcode.info().falls_through() {
+            if let Some((next, _)) = instructions.get(index + 1) {
+                offsets.insert(*next);
+            }
+        }
+    }
+    for entry in &method.code.exception_table {
+        offsets.insert(entry.handler_pc as u32);
+    }
+
+    let initial = method
+        .initial_frame()
+        .map_err(|message| method.error(None, message))?;
+    let mut previous_locals = compact(&initial.locals);
+    let mut previous: Option<u32> = None;
+    let mut entries = vec![];
+    for pc in offsets {
+        let frame = frames.get(&pc).ok_or_else(|| {
+            method.error(
+                Some(pc),
+                "Unreachable code has no stack map frame".to_string(),
+            )
+        })?;
+        let locals = compact(&frame.locals);
+        let delta = previous.map_or(pc, |previous| pc - previous - 1) as u16;
+        previous = Some(pc);
+        let info = |t: &VerificationType, pool: &mut ConstantPoolBuilder| {
+            verification_type_info(method, pc, frame, t, pool)
+        };
+
+        let same_locals = locals == previous_locals;
+        let (frame_type, stack_map_frame) = match frame.stack.as_slice() {
+            [] if same_locals && delta < 64 => (delta as u8, StackMapFrame::SameFrame),
+            [] if same_locals => (
+                251,
+                StackMapFrame::SameFrameExtended {
+                    offset_delta: delta,
+                },
+            ),
+            [stack] if same_locals && delta < 64 => (
+                64 + delta as u8,
+                StackMapFrame::SameLocals1StackItemFrame {
+                    stack: info(stack, pool)?,
+                },
+            ),
+            [stack] if same_locals => (
+                247,
+                StackMapFrame::SameLocals1StackItemFrameExtended {
+                    offset_delta: delta,
+                    stack: info(stack, pool)?,
+                },
+            ),
+            [] if locals.len() < previous_locals.len()
+                && previous_locals.len() - locals.len() <= 3
+                && previous_locals.starts_with(&locals) =>
+            {
+                (
+                    (251 - (previous_locals.len() - locals.len())) as u8,
+                    StackMapFrame::ChopFrame {
+                        offset_delta: delta,
+                    },
+                )
+            }
+            [] if locals.len() > previous_locals.len()
+                && locals.len() - previous_locals.len() <= 3
+                && locals.starts_with(&previous_locals) =>
+            {
+                let appended = locals[previous_locals.len()..]
+                    .iter()
+                    .map(|t| info(t, pool))
+                    .collect::<Result<_, _>>()?;
+                (
+                    (251 + locals.len() - previous_locals.len()) as u8,
+                    StackMapFrame::AppendFrame {
+                        offset_delta: delta,
+                        locals: appended,
+                    },
+                )
+            }
+            stack => (
+                255,
+                StackMapFrame::FullFrame {
+                    offset_delta: delta,
+                    locals: locals
+                        .iter()
+                        .map(|t| info(t, pool))
+                        .collect::<Result<_, _>>()?,
+                    stack: stack
+                        .iter()
+                        .map(|t| info(t, pool))
+                        .collect::<Result<_, _>>()?,
+                },
+            ),
+        };
+        entries.push(StackMap::new(frame_type, stack_map_frame));
+        previous_locals = locals;
+    }
+    Ok(entries)
+}
+
+fn verification_type_info(
+    method: &MethodContext,
+    pc: u32,
+    frame: &Frame,
+    t: &VerificationType,
+    pool: &mut ConstantPoolBuilder,
+) -> Result<VerificationTypeInfo, Error> {
+    Ok(match t {
+        VerificationType::Top => VerificationTypeInfo::Top,
+        VerificationType::Integer => VerificationTypeInfo::Integer,
+        VerificationType::Float => VerificationTypeInfo::Float,
+        VerificationType::Long => VerificationTypeInfo::Long,
+        VerificationType::Double => VerificationTypeInfo::Double,
+        VerificationType::Null => VerificationTypeInfo::Null,
+        VerificationType::UninitializedThis => VerificationTypeInfo::UninitializedThis,
+        VerificationType::Uninitialized(offset) => VerificationTypeInfo::Uninitialized {
+            offset: *offset as u16,
+        },
+        VerificationType::Reference(name) => VerificationTypeInfo::Object {
+            cpool_index: pool.class(name)?,
+        },
+        VerificationType::ReturnAddress(_) => {
+            return Err(frame_error(
+                method,
+                pc,
+                format!("Type {} has no stack map representation", t),
+                frame,
+                None,
+            )
+            .into())
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::verifier::stack_map::add_stack_maps;
+    use crate::verifier::test::Hierarchy;
+    use crate::verifier::type_checker::verify_class;
+    use classfile::attribute::AttributeType;
+    use classfile::class_file::ClassFile;
+    use std::fs::File;
+
+    #[test]
+    fn computed_stack_maps() {
+        let class_file =
+            ClassFile::read_from(File::open("../classfile/tests/Verified.class").unwrap()).unwrap();
+        let mut stripped = class_file.clone();
+        for method in &mut stripped.methods {
+            if let Some(index) = method.code_attr_index {
+                if let AttributeType::Code { code } = &mut method.attributes[index].attr_type {
+                    code.attributes.retain(|attribute| {
+                        !matches!(attribute.attr_type, AttributeType::StackMapTable { .. })
+                    });
+                }
+            }
+        }
+        assert!(verify_class(&stripped, &Hierarchy).is_err());
+
+        let computed = add_stack_maps(&stripped, &Hierarchy).unwrap();
+        verify_class(&computed, &Hierarchy).unwrap();
+        let frame_count = |class_file: &ClassFile| -> usize {
+            class_file
+                .methods
+                .iter()
+                .filter_map(|method| method.get_code_attr())
+                .flat_map(|code| &code.attributes)
+                .map(|attribute| match &attribute.attr_type {
+                    AttributeType::StackMapTable { entries } => entries.len(),
+                    _ => 0,
+                })
+                .sum()
+        };
+        assert_eq!(frame_count(&computed), frame_count(&class_file));
+    }
+}
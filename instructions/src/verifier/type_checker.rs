@@ -0,0 +1,389 @@
+use crate::decoder::decode;
+use crate::opcode::OpCode;
+use crate::verifier::execute::execute;
+use crate::verifier::{
+    check_exception_table, compact, frame_error, is_assignable, methods, ClassHierarchy, Frame,
+    MethodContext, VerificationType, VerifyError,
+};
+use classfile::attribute::{AttributeType, StackMapFrame, VerificationTypeInfo};
+use classfile::class_file::ClassFile;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Verifies each method of `class_file` by type checking, JVMS 4.10.1.
+///
+/// Every instruction is checked once, in code order, against the frame the
+/// `StackMapTable` records for it or the frame left by the previous one. A
+/// branch target, an exception handler and an instruction following an
+/// unconditional branch must have a stack map frame.
+pub fn verify_class(
+    class_file: &ClassFile,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<(), VerifyError> {
+    for method in methods(class_file, hierarchy)? {
+        verify_method(&method)?;
+    }
+    Ok(())
+}
+
+fn verify_method(method: &MethodContext) -> Result<(), VerifyError> {
+    let code = method.code;
+    let instructions = decode(&code.code)
+        .map_err(|error| method.error(None, format!("Malformed code: {:?}", error)))?;
+    if instructions.is_empty() {
+        return Err(method.error(None, "Code is empty".to_string()));
+    }
+    let boundaries: BTreeSet<u32> = instructions.iter().map(|(pc, _)| *pc).collect();
+    let initial = method
+        .initial_frame()
+        .map_err(|message| method.error(None, message))?;
+    let stack_maps = stack_map_frames(method, &initial, &boundaries)?;
+    check_exception_table(method, &boundaries)?;
+    for entry in &method.code.exception_table {
+        if !stack_maps.contains_key(&(entry.handler_pc as u32)) {
+            return Err(method.error(
+                Some(entry.handler_pc as u32),
+                "Expecting a stackmap frame at an exception handler".to_string(),
+            ));
+        }
+    }
+
+    let mut current = Some(initial);
+    for (pc, instruction) in &instructions {
+        let pc = *pc;
+        if let Some(stack_map) = stack_maps.get(&pc) {
+            if let Some(frame) = &current {
+                frame_is_assignable(frame, stack_map, method.hierarchy)
+                    .map_err(|message| frame_error(method, pc, message, frame, Some(stack_map)))?;
+            }
+            current = Some(stack_map.clone());
+        }
+        let incoming = current.take().ok_or_else(|| {
+            method.error(
+                Some(pc),
+                "Expecting a stackmap frame after an unconditional branch".to_string(),
+            )
+        })?;
+        let opcode = instruction.opcode;
+        if matches!(opcode, OpCode::jsr | OpCode::jsr_w | OpCode::ret) {
+            return Err(frame_error(
+                method,
+                pc,
+                format!("{} is not allowed with stack maps", opcode.name()),
+                &incoming,
+                None,
+            ));
+        }
+
+        check_handlers(method, pc, &incoming, &stack_maps)?;
+        let mut frame = incoming.clone();
+        execute(method, &mut frame, pc, instruction)
+            .map_err(|message| frame_error(method, pc, message, &incoming, None))?;
+        for target in instruction.targets() {
+            let stack_map = stack_maps.get(target).ok_or_else(|| {
+                frame_error(
+                    method,
+                    pc,
+                    format!("Expecting a stackmap frame at branch target {}", target),
+                    &frame,
+                    None,
+                )
+            })?;
+            frame_is_assignable(&frame, stack_map, method.hierarchy).map_err(|message| {
+                frame_error(
+                    method,
+                    pc,
+                    format!("Bad branch target {}: {}", target, message),
+                    &frame,
+                    Some(stack_map),
+                )
+            })?;
+        }
+        if opcode.info().falls_through() {
+            current = Some(frame);
+        }
+    }
+    match current {
+        Some(frame) => {
+            let (pc, _) = instructions.last().unwrap();
+            Err(frame_error(
+                method,
+                *pc,
+                "Falling off the end of the code".to_string(),
+                &frame,
+                None,
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Checks that the exception stack frame of an instruction, its incoming
+/// locals and the caught exception, matches every handler covering it
+fn check_handlers(
+    method: &MethodContext,
+    pc: u32,
+    incoming: &Frame,
+    stack_maps: &BTreeMap<u32, Frame>,
+) -> Result<(), VerifyError> {
+    for entry in &method.code.exception_table {
+        if pc < entry.start_pc as u32 || pc >= entry.end_pc as u32 {
+            continue;
+        }
+        let caught = method
+            .caught(entry.catch_type)
+            .map_err(|message| method.error(Some(pc), message))?;
+        let exception_frame = Frame {
+            locals: incoming.locals.clone(),
+            stack: vec![caught],
+        };
+        // handlers without a stack map frame are rejected up front
+        let stack_map = &stack_maps[&(entry.handler_pc as u32)];
+        frame_is_assignable(&exception_frame, stack_map, method.hierarchy).map_err(|message| {
+            frame_error(
+                method,
+                pc,
+                format!("Bad exception handler {}: {}", entry.handler_pc, message),
+                &exception_frame,
+                Some(stack_map),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// frameIsAssignable of JVMS 4.10.1.4
+fn frame_is_assignable(
+    from: &Frame,
+    to: &Frame,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<(), String> {
+    if from.stack.len() != to.stack.len() {
+        return Err(format!(
+            "Operand stack of {} entries doesn't match a stack map of {}",
+            from.stack.len(),
+            to.stack.len()
+        ));
+    }
+    if from.this_uninitialized() && !to.this_uninitialized() {
+        return Err("Uninitialized this doesn't match the stack map".to_string());
+    }
+    let kinds = [
+        ("locals", &from.locals, &to.locals),
+        ("stack", &from.stack, &to.stack),
+    ];
+    for (kind, from, to) in kinds.iter() {
+        for (index, (from, to)) in from.iter().zip(to.iter()).enumerate() {
+            if !is_assignable(from, to, hierarchy) {
+                return Err(format!(
+                    "Type {} (current frame, {}[{}]) is not assignable to {} (stack map, {}[{}])",
+                    from, kind, index, to, kind, index
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Frames recorded by the `StackMapTable` of the method, by offset
+fn stack_map_frames(
+    method: &MethodContext,
+    initial: &Frame,
+    boundaries: &BTreeSet<u32>,
+) -> Result<BTreeMap<u32, Frame>, VerifyError> {
+    let mut frames = BTreeMap::new();
+    let entries = method
+        .code
+        .attributes
+        .iter()
+        .find_map(|attribute| match &attribute.attr_type {
+            AttributeType::StackMapTable { entries } => Some(entries),
+            _ => None,
+        });
+    let entries = match entries {
+        Some(entries) => entries,
+        None => return Ok(frames),
+    };
+
+    // locals as listed in the table, a long or double is a single entry
+    let mut locals = compact(&initial.locals);
+    let mut previous: Option<u32> = None;
+    for entry in entries {
+        let delta = entry.offset_delta() as u32;
+        let pc = previous.map_or(delta, |previous| previous + delta + 1);
+        previous = Some(pc);
+        let error = |message: String| method.error(Some(pc), message);
+        let stack = match entry.frame() {
+            StackMapFrame::SameFrame | StackMapFrame::SameFrameExtended { .. } => vec![],
+            StackMapFrame::SameLocals1StackItemFrame { stack }
+            | StackMapFrame::SameLocals1StackItemFrameExtended { stack, .. } => {
+                vec![verification_type(method, stack).map_err(error)?]
+            }
+            StackMapFrame::ChopFrame { .. } => {
+                let chopped = 251 - entry.frame_type() as usize;
+                if chopped > locals.len() {
+                    return Err(error(format!(
+                        "Stack map frame chops {} of {} locals",
+                        chopped,
+                        locals.len()
+                    )));
+                }
+                locals.truncate(locals.len() - chopped);
+                vec![]
+            }
+            StackMapFrame::AppendFrame {
+                locals: appended, ..
+            } => {
+                for local in appended {
+                    locals.push(verification_type(method, local).map_err(error)?);
+                }
+                vec![]
+            }
+            StackMapFrame::FullFrame {
+                locals: full,
+                stack,
+                ..
+            } => {
+                locals = full
+                    .iter()
+                    .map(|local| verification_type(method, local))
+                    .collect::<Result<_, _>>()
+                    .map_err(error)?;
+                stack
+                    .iter()
+                    .map(|item| verification_type(method, item))
+                    .collect::<Result<_, _>>()
+                    .map_err(error)?
+            }
+        };
+        if !boundaries.contains(&pc) {
+            return Err(error(format!(
+                "Stack map frame at {} is not at an instruction",
+                pc
+            )));
+        }
+
+        let mut frame = Frame {
+            locals: vec![],
+            stack,
+        };
+        for local in &locals {
+            frame.locals.push(local.clone());
+            if local.size() == 2 {
+                frame.locals.push(VerificationType::Top);
+            }
+        }
+        let max_locals = method.code.max_locals as usize;
+        if frame.locals.len() > max_locals {
+            return Err(error(format!(
+                "Stack map frame has {} locals, max_locals is {}",
+                frame.locals.len(),
+                max_locals
+            )));
+        }
+        frame.locals.resize(max_locals, VerificationType::Top);
+        if frame.stack_size() > method.code.max_stack as usize {
+            return Err(error(format!(
+                "Stack map frame has {} stack entries, max_stack is {}",
+                frame.stack_size(),
+                method.code.max_stack
+            )));
+        }
+        frames.insert(pc, frame);
+    }
+    Ok(frames)
+}
+
+fn verification_type(
+    method: &MethodContext,
+    info: &VerificationTypeInfo,
+) -> Result<VerificationType, String> {
+    Ok(match info {
+        VerificationTypeInfo::Top => VerificationType::Top,
+        VerificationTypeInfo::Integer => VerificationType::Integer,
+        VerificationTypeInfo::Float => VerificationType::Float,
+        VerificationTypeInfo::Long => VerificationType::Long,
+        VerificationTypeInfo::Double => VerificationType::Double,
+        VerificationTypeInfo::Null => VerificationType::Null,
+        VerificationTypeInfo::UninitializedThis => VerificationType::UninitializedThis,
+        VerificationTypeInfo::Object { cpool_index } => {
+            VerificationType::Reference(method.class_name(*cpool_index)?)
+        }
+        VerificationTypeInfo::Uninitialized { offset } => {
+            VerificationType::Uninitialized(*offset as u32)
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::jasmin::parse;
+    use crate::verifier::test::Hierarchy;
+    use crate::verifier::type_checker::verify_class;
+    use crate::verifier::{VerificationType, VerifyError};
+    use classfile::class_file::ClassFile;
+    use std::fs::File;
+
+    fn verify_jasmin(body: &str) -> Result<(), VerifyError> {
+        let source = format!(
+            ".bytecode 50.0\n.class A\n.super java/lang/Object\n.method static m(IF)V\n{}\n.end method\n",
+            body
+        );
+        verify_class(&parse(&source).unwrap(), &Hierarchy)
+    }
+
+    #[test]
+    fn type_checking_verifier() {
+        let class_file =
+            ClassFile::read_from(File::open("../classfile/tests/Verified.class").unwrap()).unwrap();
+        verify_class(&class_file, &Hierarchy).unwrap();
+
+        let error = verify_jasmin(
+            "    .limit stack 2\n    .limit locals 2\n    iload_0\n    fload_1\n    iadd\n    pop\n    return",
+        )
+        .unwrap_err();
+        assert_eq!(error.class, "A");
+        assert_eq!(error.method, "m(IF)V");
+        assert_eq!(error.pc, Some(2));
+        assert_eq!(
+            error.message,
+            "Type float (current frame, stack[1]) is not assignable to integer"
+        );
+        let frame = error.current_frame.unwrap();
+        assert_eq!(
+            frame.stack,
+            vec![VerificationType::Integer, VerificationType::Float]
+        );
+
+        // no stack map frames, so a branch target can't be checked
+        let error = verify_jasmin(
+            "    .limit stack 1\n    .limit locals 2\n    iload_0\n    ifeq Done\nDone:\n    return",
+        )
+        .unwrap_err();
+        assert_eq!(error.pc, Some(1));
+        assert_eq!(
+            error.message,
+            "Expecting a stackmap frame at branch target 4"
+        );
+
+        let error = verify_jasmin("    .limit stack 1\n    .limit locals 2\n    iload_0\n    pop")
+            .unwrap_err();
+        assert_eq!(error.message, "Falling off the end of the code");
+        assert!(error
+            .to_string()
+            .starts_with("A.m(IF)V @1: Falling off the end of the code\n  Current frame: locals: { integer, float }"));
+    }
+
+    #[test]
+    fn pop2_does_not_split_category_2_values() {
+        let error = verify_jasmin(
+            "    .limit stack 3\n    .limit locals 2\n    lconst_0\n    iconst_0\n    pop2\n    return",
+        )
+        .unwrap_err();
+        assert_eq!(error.pc, Some(2));
+        assert!(error.message.starts_with("Type long"));
+        verify_jasmin(
+            "    .limit stack 4\n    .limit locals 2\n    iconst_0\n    iconst_0\n    pop2\n    lconst_0\n    pop2\n    return",
+        )
+        .unwrap();
+    }
+}
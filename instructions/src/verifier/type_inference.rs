@@ -0,0 +1,545 @@
+use crate::dataflow::local_access;
+use crate::decoder::decode;
+use crate::instruction::Instruction;
+use crate::opcode::OpCode;
+use crate::verifier::execute::execute;
+use crate::verifier::{
+    check_exception_table, frame_error, methods, ClassHierarchy, Frame, MethodContext,
+    VerificationType, VerifyError, JAVA_LANG_OBJECT,
+};
+use classfile::class_file::ClassFile;
+use classfile::descriptor::FieldType;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Verifies each method of `class_file` by type inference, JVMS 4.10.2.
+///
+/// The frame at each instruction is inferred by merging the frames reaching
+/// it until nothing changes. `jsr` pushes a `returnAddress` of the subroutine
+/// it calls, the instruction after it is reached when the subroutine returns,
+/// with the locals the subroutine accesses as they are at its `ret` and the
+/// others as they were at the call.
+pub fn verify_class(
+    class_file: &ClassFile,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<(), VerifyError> {
+    for method in methods(class_file, hierarchy)? {
+        verify_method(&method)?;
+    }
+    Ok(())
+}
+
+/// A subroutine, by the offset of its first instruction
+#[derive(Default)]
+struct Subroutine {
+    /// Indices of the `jsr` instructions calling it
+    callers: BTreeSet<usize>,
+    /// Local variable slots it, or a subroutine it calls, may access
+    locals: BTreeSet<usize>,
+    /// Merged frame at its `ret` instructions
+    returned: Option<Frame>,
+}
+
+struct Inference<'a> {
+    method: &'a MethodContext<'a>,
+    instructions: Vec<(u32, Instruction)>,
+    /// Index of the instruction at each offset
+    indices: BTreeMap<u32, usize>,
+    /// Frame before each instruction, None until it is reached
+    frames: Vec<Option<Frame>>,
+    subroutines: BTreeMap<u32, Subroutine>,
+    /// Instructions whose frame changed since they were last checked
+    worklist: BTreeSet<usize>,
+}
+
+fn verify_method(method: &MethodContext) -> Result<(), VerifyError> {
+    infer_frames(method).map(|_| ())
+}
+
+/// Frame before each instruction reached, by offset
+pub(crate) fn infer_frames(method: &MethodContext) -> Result<BTreeMap<u32, Frame>, VerifyError> {
+    let instructions = decode(&method.code.code)
+        .map_err(|error| method.error(None, format!("Malformed code: {:?}", error)))?;
+    if instructions.is_empty() {
+        return Err(method.error(None, "Code is empty".to_string()));
+    }
+    let boundaries: BTreeSet<u32> = instructions.iter().map(|(pc, _)| *pc).collect();
+    check_exception_table(method, &boundaries)?;
+    let initial = method
+        .initial_frame()
+        .map_err(|message| method.error(None, message))?;
+
+    let mut inference = Inference {
+        method,
+        indices: instructions
+            .iter()
+            .enumerate()
+            .map(|(index, (pc, _))| (*pc, index))
+            .collect(),
+        frames: vec![None; instructions.len()],
+        instructions,
+        subroutines: BTreeMap::new(),
+        worklist: BTreeSet::new(),
+    };
+    inference.frames[0] = Some(initial);
+    inference.worklist.insert(0);
+    while let Some(index) = inference.worklist.pop_first() {
+        inference.step(index)?;
+    }
+    let Inference {
+        instructions,
+        frames,
+        ..
+    } = inference;
+    Ok(instructions
+        .into_iter()
+        .zip(frames)
+        .filter_map(|((pc, _), frame)| frame.map(|frame| (pc, frame)))
+        .collect())
+}
+
+impl<'a> Inference<'a> {
+    /// Checks the instruction at `index` against its frame and merges the
+    /// frames it produces into its successors
+    fn step(&mut self, index: usize) -> Result<(), VerifyError> {
+        let method = self.method;
+        let (pc, instruction) = self.instructions[index].clone();
+        let incoming = self.frames[index].clone().unwrap();
+        self.merge_handlers(pc, &incoming)?;
+        match instruction.opcode {
+            OpCode::jsr | OpCode::jsr_w => self.call(index, &incoming),
+            OpCode::ret => self.ret(index, &incoming),
+            opcode => {
+                let mut frame = incoming.clone();
+                execute(method, &mut frame, pc, &instruction)
+                    .map_err(|message| frame_error(method, pc, message, &incoming, None))?;
+                self.merge_handlers(pc, &frame)?;
+                for target in instruction.targets() {
+                    let target = self.index_of(pc, *target, &frame)?;
+                    self.merge(pc, target, &frame)?;
+                }
+                if opcode.info().falls_through() {
+                    let next = self.next(index, &frame)?;
+                    self.merge(pc, next, &frame)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// `jsr` and `jsr_w`, enters the subroutine and returns from it if its
+    /// `ret` has been reached already
+    fn call(&mut self, index: usize, incoming: &Frame) -> Result<(), VerifyError> {
+        let (pc, instruction) = &self.instructions[index];
+        let (pc, entry) = (*pc, *instruction.targets()[0]);
+        let target = self.index_of(pc, entry, incoming)?;
+        let mut frame = incoming.clone();
+        frame.stack.push(VerificationType::ReturnAddress(entry));
+        if frame.stack_size() > self.method.code.max_stack as usize {
+            return Err(frame_error(
+                self.method,
+                pc,
+                format!(
+                    "Operand stack overflow, max_stack is {}",
+                    self.method.code.max_stack
+                ),
+                incoming,
+                None,
+            ));
+        }
+        if !self.subroutines.contains_key(&entry) {
+            let locals = self
+                .subroutine_locals(target)
+                .map_err(|message| frame_error(self.method, pc, message, incoming, None))?;
+            self.subroutines.insert(
+                entry,
+                Subroutine {
+                    locals,
+                    ..Subroutine::default()
+                },
+            );
+        }
+        let subroutine = self.subroutines.get_mut(&entry).unwrap();
+        subroutine.callers.insert(index);
+        let returned = subroutine
+            .returned
+            .as_ref()
+            .map(|returned| combine(incoming, returned, &subroutine.locals));
+        self.merge(pc, target, &frame)?;
+        if let Some(returned) = returned {
+            let next = self.next(index, &returned)?;
+            self.merge(pc, next, &returned)?;
+        }
+        Ok(())
+    }
+
+    /// `ret`, returns to the instruction after every call of the subroutine
+    /// whose return address the local variable holds
+    fn ret(&mut self, index: usize, incoming: &Frame) -> Result<(), VerifyError> {
+        let method = self.method;
+        let pc = self.instructions[index].0;
+        let slot = self.instructions[index].1.local_index().unwrap_or(0) as usize;
+        let entry = match incoming.locals.get(slot) {
+            Some(VerificationType::ReturnAddress(entry)) => *entry,
+            local => {
+                let message = match local {
+                    Some(t) => format!(
+                        "Type {} (current frame, locals[{}]) is not a return address",
+                        t, slot
+                    ),
+                    None => format!(
+                        "Local variable index {} out of range, max_locals is {}",
+                        slot, method.code.max_locals
+                    ),
+                };
+                return Err(frame_error(method, pc, message, incoming, None));
+            }
+        };
+        // a return address is only stored by the subroutine it was pushed for
+        let subroutine = self.subroutines.get_mut(&entry).unwrap();
+        let returned = match &subroutine.returned {
+            None => incoming.clone(),
+            Some(returned) => merge_frames(returned, incoming, method.hierarchy)
+                .map_err(|message| frame_error(method, pc, message, incoming, Some(returned)))?,
+        };
+        if subroutine.returned.as_ref() == Some(&returned) {
+            return Ok(());
+        }
+        subroutine.returned = Some(returned.clone());
+        let locals = subroutine.locals.clone();
+        let callers: Vec<usize> = subroutine.callers.iter().cloned().collect();
+        for caller in callers {
+            let call = match &self.frames[caller] {
+                Some(call) => combine(call, &returned, &locals),
+                None => continue,
+            };
+            let next = self.next(caller, &call)?;
+            self.merge(pc, next, &call)?;
+        }
+        Ok(())
+    }
+
+    /// Merges the frame of each handler catching exceptions thrown at `pc`
+    /// with the locals of `frame` and the exception caught
+    fn merge_handlers(&mut self, pc: u32, frame: &Frame) -> Result<(), VerifyError> {
+        let method = self.method;
+        for entry in &method.code.exception_table {
+            if pc < entry.start_pc as u32 || pc >= entry.end_pc as u32 {
+                continue;
+            }
+            let caught = method
+                .caught(entry.catch_type)
+                .map_err(|message| method.error(Some(pc), message))?;
+            let exception_frame = Frame {
+                locals: frame.locals.clone(),
+                stack: vec![caught],
+            };
+            // the exception table was checked, handlers are at instructions
+            let handler = self.indices[&(entry.handler_pc as u32)];
+            self.merge(pc, handler, &exception_frame)?;
+        }
+        Ok(())
+    }
+
+    /// Merges `frame`, produced by the instruction at `pc`, into the frame of
+    /// the instruction at `index`
+    fn merge(&mut self, pc: u32, index: usize, frame: &Frame) -> Result<(), VerifyError> {
+        let merged = match &self.frames[index] {
+            None => frame.clone(),
+            Some(current) => {
+                let merged =
+                    merge_frames(current, frame, self.method.hierarchy).map_err(|message| {
+                        frame_error(
+                            self.method,
+                            pc,
+                            format!(
+                                "Bad control flow to {}: {}",
+                                self.instructions[index].0, message
+                            ),
+                            frame,
+                            Some(current),
+                        )
+                    })?;
+                if &merged == current {
+                    return Ok(());
+                }
+                merged
+            }
+        };
+        self.frames[index] = Some(merged);
+        self.worklist.insert(index);
+        Ok(())
+    }
+
+    /// Index of the instruction at the branch target `target` of `pc`
+    fn index_of(&self, pc: u32, target: u32, frame: &Frame) -> Result<usize, VerifyError> {
+        self.indices.get(&target).cloned().ok_or_else(|| {
+            frame_error(
+                self.method,
+                pc,
+                format!("Branch target {} is not an instruction", target),
+                frame,
+                None,
+            )
+        })
+    }
+
+    /// Index of the instruction following the one at `index`
+    fn next(&self, index: usize, frame: &Frame) -> Result<usize, VerifyError> {
+        if index + 1 < self.instructions.len() {
+            return Ok(index + 1);
+        }
+        Err(frame_error(
+            self.method,
+            self.instructions[index].0,
+            "Falling off the end of the code".to_string(),
+            frame,
+            None,
+        ))
+    }
+
+    /// Local variable slots accessed by the subroutine starting at `entry`
+    /// and the subroutines it calls, following its control flow up to `ret`
+    fn subroutine_locals(&self, entry: usize) -> Result<BTreeSet<usize>, String> {
+        let entry_pc = self.instructions[entry].0;
+        let mut locals = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![entry];
+        while let Some(index) = pending.pop() {
+            if !visited.insert(index) {
+                continue;
+            }
+            let (pc, instruction) = &self.instructions[index];
+            if let Some((slot, width, _)) = local_access(instruction) {
+                let slot = slot as usize;
+                locals.extend(slot..slot + width as usize);
+            }
+            let opcode = instruction.opcode;
+            if opcode == OpCode::ret {
+                continue;
+            }
+            for target in instruction.targets() {
+                if *target == entry_pc && matches!(opcode, OpCode::jsr | OpCode::jsr_w) {
+                    return Err(format!("Recursive call to subroutine {}", entry_pc));
+                }
+                let target = self
+                    .indices
+                    .get(target)
+                    .ok_or_else(|| format!("Branch target {} is not an instruction", target))?;
+                pending.push(*target);
+            }
+            let returns = matches!(opcode, OpCode::jsr | OpCode::jsr_w);
+            if (opcode.info().falls_through() || returns) && index + 1 < self.instructions.len() {
+                pending.push(index + 1);
+            } else if opcode.info().falls_through() {
+                return Err(format!("Falling off the end of the code at {}", pc));
+            }
+        }
+        Ok(locals)
+    }
+}
+
+/// Frame after a `jsr` once the subroutine returns: the locals the
+/// subroutine accesses come from its `ret`, the others from the call
+fn combine(call: &Frame, returned: &Frame, locals: &BTreeSet<usize>) -> Frame {
+    let mut combined: Vec<VerificationType> = call
+        .locals
+        .iter()
+        .zip(&returned.locals)
+        .enumerate()
+        .map(|(slot, (call, returned))| {
+            if locals.contains(&slot) {
+                returned.clone()
+            } else {
+                call.clone()
+            }
+        })
+        .collect();
+    // a long or double of the call broken by a store into its second slot
+    for slot in 1..combined.len() {
+        if combined[slot - 1].size() == 2 && combined[slot] != VerificationType::Top {
+            combined[slot - 1] = VerificationType::Top;
+        }
+    }
+    Frame {
+        locals: combined,
+        stack: returned.stack.clone(),
+    }
+}
+
+/// Merges two frames reaching the same instruction. Locals of different
+/// types become unusable, operand stack entries must merge to a usable type.
+fn merge_frames(
+    current: &Frame,
+    incoming: &Frame,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<Frame, String> {
+    if current.stack.len() != incoming.stack.len() {
+        return Err(format!(
+            "Inconsistent stack height {} != {}",
+            incoming.stack_size(),
+            current.stack_size()
+        ));
+    }
+    let mut stack = vec![];
+    for (index, (a, b)) in current.stack.iter().zip(&incoming.stack).enumerate() {
+        let merged = merge_types(a, b, hierarchy);
+        if merged == VerificationType::Top {
+            return Err(format!(
+                "Mismatched stack types {} and {} at stack[{}]",
+                b, a, index
+            ));
+        }
+        stack.push(merged);
+    }
+    let locals = current
+        .locals
+        .iter()
+        .zip(&incoming.locals)
+        .map(|(a, b)| merge_types(a, b, hierarchy))
+        .collect();
+    Ok(Frame { locals, stack })
+}
+
+/// Least upper bound of two types, Top if they have none
+fn merge_types(
+    a: &VerificationType,
+    b: &VerificationType,
+    hierarchy: &dyn ClassHierarchy,
+) -> VerificationType {
+    use VerificationType::*;
+    match (a, b) {
+        _ if a == b => a.clone(),
+        (Null, Reference(_)) => b.clone(),
+        (Reference(_), Null) => a.clone(),
+        (Reference(a), Reference(b)) => Reference(merge_classes(a, b, hierarchy)),
+        _ => Top,
+    }
+}
+
+/// Closest common superclass of two class or array types. Interfaces merge
+/// to `java/lang/Object`, as they are only checked when a method is invoked.
+fn merge_classes(a: &str, b: &str, hierarchy: &dyn ClassHierarchy) -> String {
+    if a == b {
+        return a.to_string();
+    }
+    match (a.strip_prefix('['), b.strip_prefix('[')) {
+        (Some(a), Some(b)) => {
+            let a = FieldType::parse(a.as_bytes());
+            let b = FieldType::parse(b.as_bytes());
+            let component = match (a, b) {
+                (Ok(FieldType::Base(_)), _) | (_, Ok(FieldType::Base(_))) => None,
+                (Ok(a), Ok(b)) => match merge_types(
+                    &VerificationType::from_field_type(&a),
+                    &VerificationType::from_field_type(&b),
+                    hierarchy,
+                ) {
+                    VerificationType::Reference(name) if name.starts_with('[') => Some(name),
+                    VerificationType::Reference(name) => Some(format!("L{};", name)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            component.map_or(JAVA_LANG_OBJECT.to_string(), |component| {
+                format!("[{}", component)
+            })
+        }
+        (None, None) if !hierarchy.is_interface(a) && !hierarchy.is_interface(b) => {
+            let mut ancestors = vec![a.to_string()];
+            while let Some(super_class) = hierarchy.super_class(ancestors.last().unwrap()) {
+                ancestors.push(super_class);
+            }
+            let mut class = Some(b.to_string());
+            while let Some(current) = class {
+                if ancestors.contains(&current) {
+                    return current;
+                }
+                class = hierarchy.super_class(&current);
+            }
+            JAVA_LANG_OBJECT.to_string()
+        }
+        _ => JAVA_LANG_OBJECT.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::jasmin::parse;
+    use crate::verifier::test::Hierarchy;
+    use crate::verifier::type_inference::verify_class;
+    use crate::verifier::VerifyError;
+    use classfile::class_file::ClassFile;
+    use std::fs::File;
+
+    fn verify_jasmin(body: &str) -> Result<(), VerifyError> {
+        let source = format!(
+            ".bytecode 49.0\n.class A\n.super java/lang/Object\n.method static m(I)V\n{}\n.end method\n",
+            body
+        );
+        verify_class(&parse(&source).unwrap(), &Hierarchy)
+    }
+
+    /// try { x = 1 } finally { use(s) }, the finally block a subroutine that
+    /// only reads local 2
+    const FINALLY: &str = "    .limit stack 2
+    .limit locals 4
+    .catch all from Start to End using Handler
+    ldc \"s\"
+    astore_2
+Start:
+    iconst_1
+    istore_1
+End:
+    jsr Finally
+    iload_1
+    pop
+    return
+Handler:
+    astore_1
+    jsr Finally
+    aload_1
+    athrow
+Finally:
+    astore_3
+    aload_2
+    pop
+    ret 3";
+
+    #[test]
+    fn type_inference_verifier() {
+        let class_file =
+            ClassFile::read_from(File::open("../classfile/tests/Verified.class").unwrap()).unwrap();
+        verify_class(&class_file, &Hierarchy).unwrap();
+
+        // local 1 is an int after one call and a Throwable after the other
+        verify_jasmin(FINALLY).unwrap();
+
+        // local 1 is as the subroutine left it after the call
+        let error =
+            verify_jasmin(&FINALLY.replace("    aload_2\n    pop", "    iconst_0\n    istore_1"))
+                .unwrap_err();
+        assert_eq!(error.pc, Some(15));
+        assert_eq!(
+            error.message,
+            "Type integer (current frame, locals[1]) is not a reference"
+        );
+
+        let error = verify_jasmin(
+            "    .limit stack 1\n    .limit locals 1\n    iload_0\n    ifeq Done\n    iconst_0\nDone:\n    return",
+        )
+        .unwrap_err();
+        assert_eq!(error.pc, Some(4));
+        assert_eq!(
+            error.message,
+            "Bad control flow to 5: Inconsistent stack height 1 != 0"
+        );
+
+        let error = verify_jasmin(
+            "    .limit stack 1\n    .limit locals 2\n    iload_0\n    istore_1\n    ret 1",
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.message,
+            "Type integer (current frame, locals[1]) is not a return address"
+        );
+    }
+}
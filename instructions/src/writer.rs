@@ -0,0 +1,55 @@
+use crate::error::Error;
+use crate::limits::compute_maxs;
+use classfile::class_file::ClassFile;
+use std::borrow::Cow;
+use std::io::Write;
+
+/// How `write_class` prepares a class file before writing it
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Recompute `max_stack` and `max_locals` of every method from its code,
+    /// for generated or rewritten code
+    pub compute_maxs: bool,
+}
+
+/// Writes `class_file` as `ClassFile::write_to` does once `options` are
+/// applied, returns the number of bytes written
+pub fn write_class(
+    class_file: &ClassFile,
+    writer: impl Write,
+    options: &WriteOptions,
+) -> Result<usize, Error> {
+    let mut class_file = Cow::Borrowed(class_file);
+    if options.compute_maxs {
+        class_file = Cow::Owned(compute_maxs(&class_file)?);
+    }
+    Ok(class_file.write_to(writer)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::jasmin::parse;
+    use crate::writer::{write_class, WriteOptions};
+    use classfile::class_file::ClassFile;
+
+    #[test]
+    fn write_with_computed_maxs() {
+        let class_file = parse(
+            ".class A\n.super java/lang/Object\n.method static m(D)D\n    .limit stack 0\n    .limit locals 0\n    dload_0\n    dload_0\n    dadd\n    dreturn\n.end method\n",
+        )
+        .unwrap();
+        let mut written = vec![];
+        let options = WriteOptions { compute_maxs: true };
+        let len = write_class(&class_file, &mut written, &options).unwrap();
+        assert_eq!(len, written.len());
+        let read = ClassFile::read_from(&written[..]).unwrap();
+        let code = read.methods[0].get_code_attr().unwrap();
+        assert_eq!((code.max_stack, code.max_locals), (4, 2));
+
+        let mut written = vec![];
+        write_class(&class_file, &mut written, &WriteOptions::default()).unwrap();
+        let read = ClassFile::read_from(&written[..]).unwrap();
+        let code = read.methods[0].get_code_attr().unwrap();
+        assert_eq!((code.max_stack, code.max_locals), (0, 0));
+    }
+}